@@ -0,0 +1,184 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::Parser;
+
+use crate::build_cleaner::{BUILD_MANIFEST_FILE, BUILD_MARKER_FILE};
+use crate::checksums::{collect_files, ChecksumScope};
+use crate::config;
+use crate::error::{Result, BuildError, FileSystemError};
+use crate::paths::ResolvedPaths;
+
+/// Pack the outputs of `build_dir` into a gzip-compressed tar at `archive_path`, returning the number of files.
+/// Entries are sorted with a zero mtime and no owner, so the same outputs always give the same archive.
+/// Preserved .env files, a backup_dir inside the build directory and the build bookkeeping files are left out.
+pub fn write_archive(build_dir: &Path, archive_path: &Path, scope: &ChecksumScope) -> Result<usize> {
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    if absolute(archive_path).starts_with(absolute(build_dir)) {
+        return Err(BuildError::ArchiveInBuildDir { path: archive_path.to_path_buf() }.into());
+    }
+
+    let mut files = Vec::new();
+    collect_files(build_dir, build_dir, &mut files)?;
+    let mut names: Vec<(String, PathBuf)> = files.into_iter()
+        .filter(|relative_path| scope.is_output(relative_path))
+        .filter(|relative_path| {
+            let name = relative_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            name != BUILD_MARKER_FILE && name != BUILD_MANIFEST_FILE
+        })
+        .map(|relative_path| (relative_path.to_string_lossy().replace('\\', "/"), relative_path))
+        .collect();
+    names.sort();
+
+    if let Some(parent) = archive_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                path: parent.to_path_buf(),
+                source: e,
+            })?;
+    }
+
+    // Packed next to the target and renamed, so a failed run never leaves a truncated archive behind
+    let mut temp_name = archive_path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = archive_path.with_file_name(temp_name);
+    let write_failed = |e: std::io::Error| FileSystemError::FileWriteFailed {
+        path: archive_path.to_path_buf(),
+        source: e,
+    };
+
+    let result = (|| -> Result<()> {
+        let file = fs::File::create(&temp_path).map_err(write_failed)?;
+        // GzBuilder leaves the gzip mtime at zero
+        let encoder = flate2::GzBuilder::new().write(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, relative_path) in &names {
+            let path = build_dir.join(relative_path);
+            let content = fs::read(&path)
+                .map_err(|e| FileSystemError::FileReadFailed {
+                    path: path.clone(),
+                    source: e,
+                })?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(if is_executable(&path) { 0o755 } else { 0o644 });
+            header.set_mtime(0);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_slice()).map_err(write_failed)?;
+        }
+        builder.into_inner().and_then(|encoder| encoder.finish()).map_err(write_failed)?;
+        Ok(())
+    })();
+
+    if let Err(e) = result.and_then(|()| fs::rename(&temp_path, archive_path).map_err(|e| write_failed(e).into())) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    Ok(names.len())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+/// Runs the archive command logic
+pub fn run_archive(args: &ArchiveArgs) -> Result<()> {
+    let mut config = config::load_config()?;
+    let project_dir = std::env::current_dir()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to determine current directory: {}", e),
+        })?;
+    if let Some(ref build_dir) = args.build_dir {
+        config.paths.build_dir = build_dir.to_string_lossy().to_string();
+    }
+    ResolvedPaths::new(&config, &project_dir).apply_to(&mut config);
+
+    let archive_path = match (&args.output, &config.build.archive_path) {
+        (Some(output), _) => output.clone(),
+        (None, Some(configured)) => project_dir.join(configured),
+        (None, None) => return Err(BuildError::ArchivePathNotSet.into()),
+    };
+    let build_dir = PathBuf::from(&config.paths.build_dir);
+    if !build_dir.is_dir() {
+        return Err(FileSystemError::DirectoryReadFailed {
+            path: build_dir,
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        }.into());
+    }
+
+    let count = write_archive(&build_dir, &archive_path, &ChecksumScope::from_config(&config))?;
+    status!("✓ Archived {} files to {}", count, archive_path.display());
+    Ok(())
+}
+
+#[derive(Parser, Debug, Default)]
+pub struct ArchiveArgs {
+    /// Archive to write (defaults to archive_path from the [build] section)
+    #[arg(value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Build directory to archive instead of the configured build_dir
+    #[arg(long, value_name = "DIR")]
+    pub build_dir: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sorted entry names and modes of a tar.gz archive
+    fn entries(archive: &Path) -> Vec<(String, u32, u64)> {
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(archive).unwrap()));
+        archive.entries().unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let header = entry.header();
+                (entry.path().unwrap().to_string_lossy().to_string(), header.mode().unwrap(), header.mtime().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_archive_is_deterministic_and_skips_env_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path().join("build");
+        fs::create_dir_all(build.join("dev/config")).unwrap();
+        fs::write(build.join("dev/docker-compose.yml"), "services: {}\n").unwrap();
+        fs::write(build.join("dev/config/app.conf"), "x").unwrap();
+        fs::write(build.join("dev/.env"), "SECRET=1\n").unwrap();
+        fs::write(build.join(BUILD_MARKER_FILE), "").unwrap();
+        let scope = ChecksumScope { env_file_patterns: vec![".env".to_string()], excluded_dirs: Vec::new() };
+
+        let first = temp.path().join("out/first.tar.gz");
+        assert_eq!(write_archive(&build, &first, &scope).unwrap(), 2);
+        let names: Vec<_> = entries(&first).into_iter().map(|(name, mode, mtime)| {
+            assert_eq!((mode, mtime), (0o644, 0));
+            name
+        }).collect();
+        assert_eq!(names, ["dev/config/app.conf", "dev/docker-compose.yml"]);
+
+        // Newer mtimes on disk do not change the archive
+        fs::write(build.join("dev/config/app.conf"), "x").unwrap();
+        let second = temp.path().join("out/second.tar.gz");
+        write_archive(&build, &second, &scope).unwrap();
+        assert_eq!(fs::read(&first).unwrap(), fs::read(&second).unwrap());
+    }
+
+    #[test]
+    fn test_archive_inside_build_dir_is_rejected() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path().join("build");
+        fs::create_dir_all(&build).unwrap();
+
+        let error = write_archive(&build, &build.join("stacks.tar.gz"), &ChecksumScope::default()).unwrap_err();
+        assert_eq!(error.code(), "build.archive_in_build_dir");
+    }
+}
@@ -0,0 +1,557 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use clap::{Parser, Subcommand};
+use crate::config;
+use crate::diagnostics::{self, WarningCategory};
+use crate::build_cleaner::{self, BackupEntry, BackupMetadata, BACKUP_ARCHIVE_EXTENSION, BACKUP_DIR_PREFIX};
+use crate::error::{Result, BackupError, FileSystemError};
+
+/// Information about a single backup directory or archive
+#[derive(Debug)]
+pub struct BackupInfo {
+    /// Backup identifier (directory name, or archive name without extension)
+    pub id: String,
+    /// Parsed backup metadata
+    pub metadata: BackupMetadata,
+}
+
+/// Runs the backups command logic
+pub fn run_backups(args: &BackupsArgs) -> Result<()> {
+    let config = config::load_config()?;
+    let backup_dir = Path::new(&config.build.backup_dir);
+
+    match &args.command {
+        BackupsCommand::List => {
+            let backups = list_backups(backup_dir)?;
+            if backups.is_empty() {
+                println!("No backups found in: {}", backup_dir.display());
+                return Ok(());
+            }
+
+            println!("Backups in {}:", backup_dir.display());
+            for backup in &backups {
+                println!("  {} (created {}, {} files)",
+                        backup.id, format_timestamp(backup.metadata.created_at), backup.metadata.files.len());
+                for entry in &backup.metadata.files {
+                    println!("    - {}", entry.original_path.display());
+                }
+            }
+        }
+        BackupsCommand::Restore { backup_id, path, force } => {
+            let build_dir = Path::new(&config.paths.build_dir);
+            let report = restore(backup_dir, build_dir, backup_id, path.as_deref(), *force, false)?;
+            print_restore_report(&report);
+        }
+    }
+
+    Ok(())
+}
+
+/// List all backups in the backup directory, oldest first
+pub fn list_backups(backup_dir: &Path) -> Result<Vec<BackupInfo>> {
+    let mut backups = Vec::new();
+
+    if !backup_dir.exists() {
+        return Ok(backups);
+    }
+
+    for entry in fs::read_dir(backup_dir)
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: backup_dir.to_path_buf(),
+            source: e,
+        })? {
+        let entry = entry.map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: backup_dir.to_path_buf(),
+            source: e,
+        })?;
+
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let id = if path.is_dir() {
+            name
+        } else if let Some(id) = name.strip_suffix(BACKUP_ARCHIVE_EXTENSION).filter(|_| path.is_file()) {
+            id.to_string()
+        } else {
+            continue;
+        };
+        if !id.starts_with(BACKUP_DIR_PREFIX) {
+            continue;
+        }
+
+        match build_cleaner::read_backup_metadata(&path) {
+            Ok(metadata) => backups.push(BackupInfo { id, metadata }),
+            Err(e) => diagnostics::warn_at(WarningCategory::Cleanup, &path, format!("Skipping backup '{}': {:#}", id, e)),
+        }
+    }
+
+    backups.sort_by(|a, b| a.metadata.created_at.cmp(&b.metadata.created_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(backups)
+}
+
+/// Restore files from a backup to their recorded locations under the build directory
+#[cfg(test)]
+pub fn restore_backup(
+    backup_dir: &Path,
+    build_dir: &Path,
+    backup_id: &str,
+    only_path: Option<&Path>,
+    force: bool,
+) -> Result<Vec<PathBuf>> {
+    Ok(restore(backup_dir, build_dir, backup_id, only_path, force, false)?.restored)
+}
+
+/// Outcome of restoring a backup
+#[derive(Debug)]
+pub struct RepairReport {
+    /// Backup the files were restored from
+    pub backup_id: String,
+    pub restored: Vec<PathBuf>,
+    /// Existing files with different content, left untouched
+    pub conflicts: Vec<PathBuf>,
+}
+
+/// Restore every file of the newest backup to its original path under the build directory.
+/// Unlike `backups restore`, conflicting files are skipped and reported while the others are restored.
+pub fn repair_from_latest_backup(backup_dir: &Path, build_dir: &Path, force: bool) -> Result<RepairReport> {
+    let latest = list_backups(backup_dir)?.pop()
+        .ok_or_else(|| BackupError::NoBackups { backup_dir: backup_dir.to_path_buf() })?;
+    restore(backup_dir, build_dir, &latest.id, None, force, true)
+}
+
+/// Restore the files of a backup, or only `only_path`, under the build directory. Existing files with
+/// different content fail the restore before anything is written, or with `skip_conflicts` are left
+/// untouched and reported while the others are restored; `force` overwrites them.
+fn restore(
+    backup_dir: &Path,
+    build_dir: &Path,
+    backup_id: &str,
+    only_path: Option<&Path>,
+    force: bool,
+    skip_conflicts: bool,
+) -> Result<RepairReport> {
+    let (dir_name, backup_path) = locate_backup(backup_dir, backup_id);
+
+    if !backup_path.is_dir() && !backup_path.is_file() {
+        return Err(BackupError::BackupNotFound {
+            backup_id: backup_id.to_string(),
+            backup_dir: backup_dir.to_path_buf(),
+            available: list_backups(backup_dir)?.into_iter().map(|b| b.id).collect(),
+        }.into());
+    }
+
+    let metadata = build_cleaner::read_backup_metadata(&backup_path)
+        .map_err(|e| BackupError::InvalidBackup {
+            backup_id: dir_name.clone(),
+            details: format!("{:#}", e),
+        })?;
+
+    let entries: Vec<_> = metadata.files.iter()
+        .filter(|entry| only_path.is_none_or(|p| entry.original_path == p))
+        .collect();
+
+    if let Some(path) = only_path {
+        if entries.is_empty() {
+            return Err(BackupError::PathNotInBackup {
+                backup_id: dir_name,
+                path: path.to_path_buf(),
+            }.into());
+        }
+    }
+
+    // Load contents and check for conflicts before touching anything
+    let (pending, conflicts) = load_restores(&backup_path, &dir_name, entries, build_dir, force)?;
+    if !conflicts.is_empty() && !skip_conflicts {
+        return Err(BackupError::RestoreConflict { paths: conflicts }.into());
+    }
+
+    Ok(RepairReport {
+        restored: write_restores(pending)?,
+        backup_id: dir_name,
+        conflicts,
+    })
+}
+
+/// Print the restored files and the conflicts left untouched
+fn print_restore_report(report: &RepairReport) {
+    for path in &report.restored {
+        status!("✓ Restored: {}", path.display());
+    }
+    for path in &report.conflicts {
+        status!("✗ Conflict: {} exists with different content", path.display());
+    }
+    status!("Restored {} files from backup '{}'", report.restored.len(), report.backup_id);
+}
+
+/// Directory name and path of a backup. Accepts "backup_<timestamp>", the bare timestamp and the archive file name
+fn locate_backup(backup_dir: &Path, backup_id: &str) -> (String, PathBuf) {
+    let id = backup_id.strip_suffix(BACKUP_ARCHIVE_EXTENSION).unwrap_or(backup_id);
+    let dir_name = if id.starts_with(BACKUP_DIR_PREFIX) {
+        id.to_string()
+    } else {
+        format!("{}{}", BACKUP_DIR_PREFIX, id)
+    };
+
+    // Backups are either directories or single tar.gz archives
+    let archive_path = backup_dir.join(format!("{}{}", dir_name, BACKUP_ARCHIVE_EXTENSION));
+    let backup_path = if archive_path.is_file() {
+        archive_path
+    } else {
+        backup_dir.join(&dir_name)
+    };
+    (dir_name, backup_path)
+}
+
+/// Target path and backed up content of a file to restore
+type PendingRestore = (PathBuf, String);
+
+/// Read the backed up contents of `entries` with their targets under `build_dir`.
+/// Targets that exist with different content are returned as conflicts unless `force` is set.
+fn load_restores<'a>(
+    backup_path: &Path,
+    backup_id: &str,
+    entries: impl IntoIterator<Item = &'a BackupEntry>,
+    build_dir: &Path,
+    force: bool,
+) -> Result<(Vec<PendingRestore>, Vec<PathBuf>)> {
+    let mut pending = Vec::new();
+    let mut conflicts = Vec::new();
+    for entry in entries {
+        // The metadata is a plain file next to the backup, so its paths must not lead out of the build directory
+        for path in [&entry.original_path, Path::new(&entry.backup_file)] {
+            if !is_plain_relative(path) {
+                return Err(BackupError::UnsafePath {
+                    backup_id: backup_id.to_string(),
+                    path: path.to_path_buf(),
+                }.into());
+            }
+        }
+
+        let content = build_cleaner::read_backup_file(backup_path, entry)
+            .map_err(|e| BackupError::InvalidBackup {
+                backup_id: backup_id.to_string(),
+                details: format!("{:#}", e),
+            })?;
+
+        let target = build_dir.join(&entry.original_path);
+        if target.exists() && !force {
+            let existing = fs::read_to_string(&target)
+                .map_err(|e| FileSystemError::FileReadFailed {
+                    path: target.clone(),
+                    source: e,
+                })?;
+            if existing != content {
+                conflicts.push(target);
+                continue;
+            }
+        }
+
+        pending.push((target, content));
+    }
+    Ok((pending, conflicts))
+}
+
+/// Whether `path` is relative and names only plain directories and files: no root, prefix, `.` or `..`
+fn is_plain_relative(path: &Path) -> bool {
+    path.components().next().is_some() && path.components().all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Write restored contents, creating parent directories
+fn write_restores(pending: Vec<PendingRestore>) -> Result<Vec<PathBuf>> {
+    let mut restored = Vec::new();
+    for (target, content) in pending {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                    path: parent.to_path_buf(),
+                    source: e,
+                })?;
+        }
+        fs::write(&target, content)
+            .map_err(|e| FileSystemError::FileWriteFailed {
+                path: target.clone(),
+                source: e,
+            })?;
+        restored.push(target);
+    }
+
+    Ok(restored)
+}
+
+/// Runs the repair command logic
+pub fn run_repair(args: &RepairArgs) -> Result<()> {
+    let config = config::load_config()?;
+    let backup_dir = Path::new(&config.build.backup_dir);
+    let build_dir = Path::new(&config.paths.build_dir);
+
+    let _quiet = args.quiet.then(diagnostics::quiet);
+    let report = repair_from_latest_backup(backup_dir, build_dir, args.force)?;
+    print_restore_report(&report);
+
+    if !report.conflicts.is_empty() {
+        return Err(BackupError::RestoreConflict { paths: report.conflicts }.into());
+    }
+    Ok(())
+}
+
+/// Format a Unix timestamp as a UTC date-time string
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let secs = timestamp % 86_400;
+
+    // Civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            year, month, day, secs / 3_600, (secs % 3_600) / 60, secs % 60)
+}
+
+#[derive(Parser)]
+pub struct BackupsArgs {
+    #[command(subcommand)]
+    pub command: BackupsCommand,
+}
+
+#[derive(Subcommand)]
+pub enum BackupsCommand {
+    /// List backups with their timestamps and contained files
+    List,
+    /// Restore files from a backup to their original locations in the build directory
+    Restore {
+        /// Backup identifier as shown by 'backups list' (e.g. backup_1694268450)
+        backup_id: String,
+
+        /// Restore only the file with this original path (relative to build directory)
+        #[arg(long)]
+        path: Option<PathBuf>,
+
+        /// Overwrite existing files with different content
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Parser, Debug, Default)]
+pub struct RepairArgs {
+    /// Overwrite existing files with different content
+    #[arg(long)]
+    pub force: bool,
+
+    /// Print nothing but errors
+    #[arg(long, short = 'q')]
+    pub quiet: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_cleaner::BACKUP_METADATA_FILE;
+
+    fn write_backup(backup_dir: &Path, timestamp: u64, files: &[(&str, &str)]) {
+        let backup_path = backup_dir.join(format!("{}{}", BACKUP_DIR_PREFIX, timestamp));
+        fs::create_dir_all(&backup_path).unwrap();
+
+        let mut entries = Vec::new();
+        for (original, content) in files {
+            let backup_file = original.replace('/', "_");
+            fs::write(backup_path.join(&backup_file), content).unwrap();
+            entries.push(BackupEntry {
+                original_path: PathBuf::from(original),
+                backup_file,
+                environment: None,
+                extensions: vec![],
+            });
+        }
+
+        let metadata = BackupMetadata { created_at: timestamp, files: entries };
+        fs::write(backup_path.join(BACKUP_METADATA_FILE), serde_json::to_string(&metadata).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_list_backups_sorted() {
+        let temp = tempfile::tempdir().unwrap();
+        write_backup(temp.path(), 200, &[("dev/auth/.env", "A=1")]);
+        write_backup(temp.path(), 100, &[(".env", "B=2"), ("prod/.env", "C=3")]);
+
+        let backups = list_backups(temp.path()).unwrap();
+        assert_eq!(backups.len(), 2);
+        assert_eq!(backups[0].id, "backup_100");
+        assert_eq!(backups[0].metadata.files.len(), 2);
+        assert_eq!(backups[1].metadata.files[0].original_path, PathBuf::from("dev/auth/.env"));
+    }
+
+    #[test]
+    fn test_list_backups_legacy_metadata() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_path = temp.path().join("backup_42");
+        fs::create_dir_all(&backup_path).unwrap();
+        fs::write(backup_path.join(BACKUP_METADATA_FILE), r#"[{"original_path":"dev/auth/.env","content":"A=1","environment":"dev","extensions":["auth"]}]"#).unwrap();
+
+        let backups = list_backups(temp.path()).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].metadata.created_at, 42);
+        assert_eq!(backups[0].metadata.files[0].backup_file, "dev_auth_.env");
+    }
+
+    #[test]
+    fn test_restore_backup() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_dir = temp.path().join("backup");
+        let build_dir = temp.path().join("build");
+        write_backup(&backup_dir, 100, &[("dev/auth/.env", "A=1"), ("prod/.env", "B=2")]);
+
+        let restored = restore_backup(&backup_dir, &build_dir, "100", None, false).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(fs::read_to_string(build_dir.join("dev/auth/.env")).unwrap(), "A=1");
+        assert_eq!(fs::read_to_string(build_dir.join("prod/.env")).unwrap(), "B=2");
+    }
+
+    #[test]
+    fn test_restore_rejects_paths_outside_the_build_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_dir = temp.path().join("backup");
+        let build_dir = temp.path().join("build");
+        let outside = temp.path().join("outside/.env");
+        write_backup(&backup_dir, 100, &[("dev/.env", "A=1"), ("../outside/.env", "B=2")]);
+        write_backup(&backup_dir, 200, &[(outside.to_str().unwrap(), "C=3")]);
+
+        for backup_id in ["100", "200"] {
+            let error = restore_backup(&backup_dir, &build_dir, backup_id, None, true).unwrap_err();
+            assert_eq!(error.code(), "backup.unsafe_path", "{}", error);
+        }
+        let error = repair_from_latest_backup(&backup_dir, &build_dir, true).unwrap_err();
+        assert_eq!(error.code(), "backup.unsafe_path", "{}", error);
+        assert!(!outside.exists());
+        assert!(!build_dir.join("dev/.env").exists(), "Nothing is restored from a backup with an unsafe path");
+    }
+
+    #[test]
+    fn test_restore_backup_single_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_dir = temp.path().join("backup");
+        let build_dir = temp.path().join("build");
+        write_backup(&backup_dir, 100, &[("dev/auth/.env", "A=1"), ("prod/.env", "B=2")]);
+
+        let restored = restore_backup(&backup_dir, &build_dir, "backup_100", Some(Path::new("prod/.env")), false).unwrap();
+        assert_eq!(restored, vec![build_dir.join("prod/.env")]);
+        assert!(!build_dir.join("dev/auth/.env").exists());
+
+        let result = restore_backup(&backup_dir, &build_dir, "backup_100", Some(Path::new("missing/.env")), false);
+        assert!(matches!(result, Err(crate::error::StackBuilderError::Backup(BackupError::PathNotInBackup { .. }))));
+    }
+
+    #[test]
+    fn test_restore_backup_conflict_requires_force() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_dir = temp.path().join("backup");
+        let build_dir = temp.path().join("build");
+        write_backup(&backup_dir, 100, &[(".env", "A=1")]);
+        fs::create_dir_all(&build_dir).unwrap();
+        fs::write(build_dir.join(".env"), "A=changed").unwrap();
+
+        let result = restore_backup(&backup_dir, &build_dir, "100", None, false);
+        assert!(matches!(result, Err(crate::error::StackBuilderError::Backup(BackupError::RestoreConflict { .. }))));
+        assert_eq!(fs::read_to_string(build_dir.join(".env")).unwrap(), "A=changed");
+
+        restore_backup(&backup_dir, &build_dir, "100", None, true).unwrap();
+        assert_eq!(fs::read_to_string(build_dir.join(".env")).unwrap(), "A=1");
+    }
+
+    #[test]
+    fn test_restore_unknown_backup() {
+        let temp = tempfile::tempdir().unwrap();
+        write_backup(temp.path(), 100, &[(".env", "A=1")]);
+
+        let result = restore_backup(temp.path(), temp.path(), "999", None, false);
+        match result {
+            Err(crate::error::StackBuilderError::Backup(BackupError::BackupNotFound { available, .. })) => {
+                assert_eq!(available, vec!["backup_100".to_string()]);
+            }
+            other => panic!("Expected BackupNotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_list_and_restore_archive_backup() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_dir = temp.path().join("backup");
+        let build_dir = temp.path().join("build");
+        fs::create_dir_all(build_dir.join("dev/auth")).unwrap();
+        fs::write(build_dir.join("dev/auth/.env"), "A=1").unwrap();
+        fs::write(build_dir.join(".env"), "B=2").unwrap();
+        fs::write(build_dir.join(build_cleaner::BUILD_MARKER_FILE), "").unwrap();
+
+        // Cleaning without a new structure backs up every preserved file
+        let cleaner = build_cleaner::BuildCleaner::new(
+            &build_dir, true, vec![".env".to_string()], backup_dir.to_string_lossy().to_string(),
+        ).with_backup_format(crate::config::BackupFormat::TarGz);
+        cleaner.clean_build_directory().unwrap();
+        cleaner.restore_env_files(&[]).unwrap();
+
+        let archives: Vec<_> = fs::read_dir(&backup_dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(archives.len(), 1);
+        assert!(archives[0].is_file());
+        assert!(archives[0].to_string_lossy().ends_with(BACKUP_ARCHIVE_EXTENSION));
+
+        let backups = list_backups(&backup_dir).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].metadata.files.len(), 2);
+
+        let archive_name = archives[0].file_name().unwrap().to_string_lossy().to_string();
+        let restored = restore_backup(&backup_dir, &build_dir, &archive_name, None, false).unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(fs::read_to_string(build_dir.join("dev/auth/.env")).unwrap(), "A=1");
+        assert_eq!(fs::read_to_string(build_dir.join(".env")).unwrap(), "B=2");
+    }
+
+    #[test]
+    fn test_repair_restores_newest_backup() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup_dir = temp.path().join("backup");
+        let build_dir = temp.path().join("build");
+        let result = repair_from_latest_backup(&backup_dir, &build_dir, false);
+        assert!(matches!(result, Err(crate::error::StackBuilderError::Backup(BackupError::NoBackups { .. }))));
+
+        write_backup(&backup_dir, 100, &[("dev/.env", "OLD=1")]);
+        fs::create_dir_all(build_dir.join("dev/auth")).unwrap();
+        fs::write(build_dir.join("dev/auth/.env"), "A=1").unwrap();
+        fs::write(build_dir.join(".env"), "B=2").unwrap();
+        fs::write(build_dir.join(build_cleaner::BUILD_MARKER_FILE), "").unwrap();
+
+        // Cleaning without a new structure backs up every preserved file
+        let cleaner = build_cleaner::BuildCleaner::new(
+            &build_dir, true, vec![".env".to_string()], backup_dir.to_string_lossy().to_string(),
+        );
+        cleaner.clean_build_directory().unwrap();
+        cleaner.restore_env_files(&[]).unwrap();
+        assert!(!build_dir.join("dev/auth/.env").exists());
+
+        // A file recreated with other content is a conflict; the rest is restored
+        fs::write(build_dir.join(".env"), "B=changed").unwrap();
+        let report = repair_from_latest_backup(&backup_dir, &build_dir, false).unwrap();
+        assert_ne!(report.backup_id, "backup_100");
+        assert_eq!(report.restored, vec![build_dir.join("dev/auth/.env")]);
+        assert_eq!(report.conflicts, vec![build_dir.join(".env")]);
+        assert_eq!(fs::read_to_string(build_dir.join("dev/auth/.env")).unwrap(), "A=1");
+        assert_eq!(fs::read_to_string(build_dir.join(".env")).unwrap(), "B=changed");
+        assert!(!build_dir.join("dev/.env").exists(), "Older backups are not restored");
+
+        let report = repair_from_latest_backup(&backup_dir, &build_dir, true).unwrap();
+        assert_eq!(report.restored.len(), 2);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(fs::read_to_string(build_dir.join(".env")).unwrap(), "B=2");
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_694_268_450), "2023-09-09 14:07:30 UTC");
+    }
+}
@@ -1,13 +1,35 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use clap::Parser;
+use sha2::{Digest, Sha256};
 
-use crate::config::{self, YamlMergerType};
-use crate::merger::{ComposeMerger, merge_compose_files};
-use crate::yq_merger::{YqMerger, yq_merge_compose_files, check_yq_availability};
+pub use crate::config::ConfigFormat;
+use crate::config::{self, LabelStyle, OutputFormat, OutputMode, PortStyle, YamlMergerType, EXTENSION_COMBINATION_SEPARATOR};
+use crate::merger::{ComposeMerger, extension_compose_file, find_service_conflicts, load_compose_file, merge_compose_files, rebase_relative_paths, relative_build_contexts};
+use crate::yq_merger::{YqMerger, YqRetry, yq_merge_compose_files, check_yq_availability};
 use crate::env_merger::{EnvMerger, merge_env_files, write_merged_env};
-use crate::file_copier::FileCopier;
+use crate::file_copier::{self, FileCopier, OBJECT_STORE_DIR};
+use crate::helper_scripts::{self, HELPER_SCRIPTS};
+use crate::image_check::{self, DEFAULT_IMAGE_CHECK_COMMAND};
+use crate::kompose::{self, KUBERNETES_OUTPUT_DIR};
+use crate::variables::{self, VARIABLES_ENV_FILE};
+use crate::output_writer::OutputWriter;
+use crate::paths::ResolvedPaths;
+use crate::archive;
 use crate::build_cleaner::BuildCleaner;
-use crate::error::{Result, BuildError, FileSystemError, YamlError, ValidationError};
+use crate::build_cache::{self, BuildCache, CachedCombination, BUILD_CACHE_FILE};
+use crate::build_lock::{BuildLock, BUILD_LOCK_FILE};
+use crate::build_report::{self, BuildReport, CombinationReport, CombinationsManifest, ComposeCounts, FailedCombination, ManifestCombination, PrunedCombination, ServiceInventory};
+use crate::checksums::{self, ChecksumScope};
+use crate::diagnostics::{self, Diagnostics, WarningCategory};
+use crate::observer::{BuildObserver, BuildPhase, ConsoleObserver, GithubObserver, ProgressObserver, TimingObserver};
+use crate::yaml_emitter;
+use crate::error::{combination_label, combination_layers, Result, StackBuilderError, BuildError, ConfigError, FileSystemError, ValidationError, YamlError};
 
 /// Structure for managing build process execution
 #[derive(Debug)]
@@ -19,82 +41,571 @@ pub struct BuildExecutor {
     pub num_envs: usize,
     pub num_extensions: usize,
     pub num_combos: usize,
+    /// Absolute paths of the configuration, resolved against the project directory
+    pub paths: ResolvedPaths,
+    /// Commit checked out in the components directory, recorded in provenance headers
+    pub components_commit: Option<String>,
+    /// Template of the helper scripts, loaded when `generate_helper_scripts` is enabled
+    pub helper_script_template: Option<String>,
 }
 
 impl BuildExecutor {
-    /// Create new BuildExecutor with loaded configuration
+    /// Create new BuildExecutor with the configuration of the current directory
     pub fn new() -> Result<Self> {
-        let mut config = config::load_config()?;
-        config::resolve_paths(&mut config)?;
-        config::validate_config(&config)?;
+        let config = config::load_config()?;
+        Self::from_config(config, &current_project_dir()?)
+    }
+
+    /// Create a BuildExecutor for `config`, resolving its relative paths against `project_dir`.
+    /// Whether yq can be run is only checked when a build starts.
+    pub fn from_config(mut config: config::Config, project_dir: &Path) -> Result<Self> {
+        let paths = config::resolve_paths_in(&mut config, project_dir);
+        config::validate_config(&config, config.build.validation_mode)?;
+
+        let rust_merger = ComposeMerger::from_paths(&paths)
+        .with_merge_strategy(config.build.merge_strategy.clone())
+        .with_inline_env_files(config.build.inline_env_files)
+        .with_rewrite_build_context(config.build.rewrite_build_context)
+        .with_prune_empty_collections(config.build.prune_empty_collections)
+        .with_label_style(config.build.label_style)
+        .with_port_style(config.build.port_style)
+        .with_require_base(config.build.require_base);
 
-        // Check yq availability only if yq merger is configured
         if config.build.yaml_merger == YamlMergerType::Yq {
-            check_yq_availability()
-                .map_err(|_| BuildError::BuildProcessFailed {
-                    details: "yq is required but not available. Please either:\n\
-                        1. Install yq v4+ from https://github.com/mikefarah/yq\n\
-                        2. Or set yaml_merger = \"rust\" in your stackbuilder.toml config file\n\n\
-                        Installation options:\n\
-                        - Ubuntu/Debian: sudo apt install yq\n\
-                        - macOS: brew install yq\n\
-                        - Binary: wget https://github.com/mikefarah/yq/releases/latest/download/yq_linux_amd64 -O /usr/bin/yq && chmod +x /usr/bin/yq".to_string(),
-                })?;
+            if !config.build.merge_strategy.is_empty() {
+                diagnostics::warn(WarningCategory::Config, "merge_strategy is only applied by the Rust merger and is ignored with yaml_merger = \"yq\"");
+            }
+            if config.build.inline_env_files {
+                diagnostics::warn(WarningCategory::Config, "inline_env_files is only applied by the Rust merger and is ignored with yaml_merger = \"yq\"");
+            }
+            if config.build.rewrite_build_context {
+                diagnostics::warn(WarningCategory::Config, "rewrite_build_context is only applied by the Rust merger and is ignored with yaml_merger = \"yq\"");
+            }
+            if config.build.prune_empty_collections {
+                diagnostics::warn(WarningCategory::Config, "prune_empty_collections is only applied by the Rust merger and is ignored with yaml_merger = \"yq\"");
+            }
+            if config.build.label_style != LabelStyle::default() {
+                diagnostics::warn(WarningCategory::Config, "label_style is only applied by the Rust merger and is ignored with yaml_merger = \"yq\"");
+            }
+            if config.build.port_style != PortStyle::default() {
+                diagnostics::warn(WarningCategory::Config, "port_style is only applied by the Rust merger and is ignored with yaml_merger = \"yq\"");
+            }
         }
 
-        let rust_merger = ComposeMerger::new(
-            config.paths.base_dir.clone(),
-            config.paths.environments_dir.clone(),
-            config.paths.extensions_dirs.clone(),
-        );
+        if config.build.output_mode == OutputMode::Layers && config.build.output_formats.iter().any(|&format| format != OutputFormat::Yaml) {
+            diagnostics::warn(WarningCategory::Config, "output_formats other than yaml are ignored with output_mode = \"layers\", which copies the compose files as they are");
+        }
+        let image_tags = config.build.environments_config.iter()
+            .flat_map(|environments| environments.environment_configs.values())
+            .any(|environment| environment.image_tags.as_ref().is_some_and(|tags| !tags.is_empty()));
+        if image_tags && (config.build.output_mode == OutputMode::Layers || config.build.multi_document) {
+            diagnostics::warn(WarningCategory::Config, "image_tags are applied to the merged compose file and are ignored with output_mode = \"layers\" and multi_document = true");
+        }
+        if config.build.kubernetes_output && (config.build.output_mode == OutputMode::Layers || config.build.multi_document) {
+            diagnostics::warn(WarningCategory::Config, "kubernetes_output needs a merged compose file and is ignored with output_mode = \"layers\" and multi_document = true");
+        }
+        if config.build.multi_document {
+            match config.build.output_mode {
+                OutputMode::Layers => diagnostics::warn(WarningCategory::Config, "multi_document is ignored with output_mode = \"layers\""),
+                OutputMode::Merged if config.build.output_formats.iter().any(|&format| format != OutputFormat::Yaml) => {
+                    diagnostics::warn(WarningCategory::Config, "output_formats other than yaml are ignored with multi_document = true, which writes a YAML stream");
+                }
+                OutputMode::Merged => {}
+            }
+        }
+        if config.build.resolve_env && config.build.output_mode == OutputMode::Layers {
+            diagnostics::warn(WarningCategory::Config, "resolve_env is ignored with output_mode = \"layers\", which copies the compose files as they are");
+        }
 
-        let yq_merger = YqMerger::new(
-            config.paths.base_dir.clone(),
-            config.paths.environments_dir.clone(),
-            config.paths.extensions_dirs.clone(),
-        );
+        let yq_merger = YqMerger::from_paths(&paths)
+        .with_retry(YqRetry::new(config.build.yq_retries, config.build.yq_retry_backoff_ms))
+        .with_require_base(config.build.require_base);
 
-        let env_merger = EnvMerger::new(
-            config.paths.base_dir.clone(),
-            config.paths.environments_dir.clone(),
-            config.paths.extensions_dirs.clone(),
-        );
+        let env_merger = EnvMerger::from_paths(&paths)
+            .with_require_base(config.build.require_base)
+            .with_source_filename(&config.build.env_source_filename);
 
         let num_envs = config::get_environments_list(&config).len();
-        let num_extensions = config.build.extensions.as_ref().map_or(0, |e| e.len());
+        let num_extensions = config.build.extensions.as_ref().map_or(0, |e| e.len()) + config::matrix_size(&config);
         let num_combos = config.build.combos.len();
 
-        Ok(Self { config, rust_merger, yq_merger, env_merger, num_envs, num_extensions, num_combos })
+        let components_commit = match config.build.provenance_header && config.build.include_git_info {
+            true => git_commit(paths.components_dir()),
+            false => None,
+        };
+
+        let helper_script_template = match config.build.generate_helper_scripts {
+            true => Some(helper_scripts::load_template(config.build.helper_script_template.as_deref(), &paths.project_root)?),
+            false => {
+                if config.build.helper_script_template.is_some() {
+                    diagnostics::warn(WarningCategory::Config, "helper_script_template has no effect without generate_helper_scripts = true");
+                }
+                None
+            }
+        };
+
+        Ok(Self {
+            config,
+            rust_merger,
+            yq_merger,
+            env_merger,
+            num_envs,
+            num_extensions,
+            num_combos,
+            paths,
+            components_commit,
+            helper_script_template,
+        })
+    }
+}
+
+/// What to build and where, for [`build`]
+#[derive(Clone)]
+pub struct BuildOptions {
+    /// Project directory holding stackbuilder.toml; relative configured paths are resolved against it
+    pub working_dir: PathBuf,
+    /// Configuration to build instead of `working_dir/stackbuilder.toml`
+    pub config: Option<config::Config>,
+    /// Build flags as accepted by `stackbuilder build`; `list_files` is only handled by the CLI
+    pub args: BuildArgs,
+    /// Print progress and warnings like the CLI does
+    pub print_output: bool,
+    /// Receives progress events; `ConsoleObserver` when not set
+    pub observer: Option<Arc<dyn BuildObserver>>,
+    /// User configuration whose `[build]` preferences apply below the project's; none unless set,
+    /// so a library build only depends on the project
+    pub user_config: Option<PathBuf>,
+}
+
+// The observer is a trait object without Debug
+impl std::fmt::Debug for BuildOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BuildOptions")
+            .field("working_dir", &self.working_dir)
+            .field("config", &self.config)
+            .field("args", &self.args)
+            .field("print_output", &self.print_output)
+            .field("user_config", &self.user_config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BuildOptions {
+    /// Build the project in `working_dir` with default flags and no output
+    pub fn new(working_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            working_dir: working_dir.into(),
+            config: None,
+            args: BuildArgs::default(),
+            print_output: false,
+            observer: None,
+            user_config: None,
+        }
+    }
+
+    /// Build this configuration instead of loading `stackbuilder.toml`
+    pub fn with_config(mut self, config: config::Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Apply the `[build]` preferences of this user configuration, e.g. [`config::user_config_path`], like the CLI does
+    pub fn with_user_config(mut self, user_config: Option<PathBuf>) -> Self {
+        self.user_config = user_config;
+        self
+    }
+
+    /// Use these build flags
+    pub fn with_args(mut self, args: BuildArgs) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Print progress and warnings to stdout
+    pub fn with_print_output(mut self, print_output: bool) -> Self {
+        self.print_output = print_output;
+        self
+    }
+
+    /// Send combination, file and warning events to `observer` instead of printing them
+    pub fn with_observer(mut self, observer: Arc<dyn BuildObserver>) -> Self {
+        self.observer = Some(observer);
+        self
     }
 }
 
 /// Main build execution function
-pub fn execute_build() -> Result<()> {
-    println!("Starting build process...");
+pub fn execute_build(args: &BuildArgs) -> Result<BuildReport> {
+    // The JSON report is the only thing written to stdout, so it can be piped
+    let json = args.report == Some(ReportFormat::Json);
+    let mut options = BuildOptions::new(current_project_dir()?)
+        .with_args(args.clone())
+        .with_user_config(config::user_config_path())
+        .with_print_output(!json && !args.quiet);
+    // CI logs keep the line-based output, which reads better than redrawn bars
+    if options.print_output && args.log_format() == LogFormat::Github {
+        options = options.with_observer(Arc::new(GithubObserver::new()));
+    } else if options.print_output && std::io::stdout().is_terminal() {
+        options = options.with_observer(Arc::new(ProgressObserver::new()));
+    }
+    let report = build(options)?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| BuildError::BuildProcessFailed {
+                details: format!("Failed to serialize build report: {}", e),
+            })?;
+        println!("{}", json);
+    } else if !report.combinations.is_empty() && !args.quiet {
+        status!("\n{}", report.format_table());
+        if let Some(pruned) = report.format_pruned() {
+            status!("\n{}", pruned);
+        }
+        if let Some(changes) = report.format_service_changes() {
+            status!("\n{}", changes);
+        }
+        if let Some(timings) = report.timings.as_ref().filter(|_| args.timings) {
+            status!("\n{}", timings.format_table());
+        }
+    }
+    Ok(report)
+}
+
+/// Build every combination of the project in `options.working_dir` and return what was written.
+/// A dry run writes nothing and returns a report without combinations.
+pub fn build(mut options: BuildOptions) -> Result<BuildReport> {
+    let _quiet = (!options.print_output).then(diagnostics::quiet);
+    let observer = options.observer.take().unwrap_or_else(|| Arc::new(ConsoleObserver));
+    let timings = (options.args.timings || options.args.timings_file.is_some())
+        .then(|| Arc::new(TimingObserver::new(Arc::clone(&observer))));
+    let observer: Arc<dyn BuildObserver> = match timings {
+        Some(ref timings) => Arc::clone(timings) as Arc<dyn BuildObserver>,
+        None => observer,
+    };
+    let timings_file = options.args.timings_file.as_ref().map(|path| options.working_dir.join(path));
+
+    // Warnings scroll away in long builds, so repeat them at the end whether or not the build succeeded
+    let diagnostics = Diagnostics::collect();
+    let result = {
+        let _observer = diagnostics::observe(Arc::clone(&observer));
+        let result = run_build(options, Arc::clone(&observer));
+        observer.on_build_done();
+        result
+    };
+    let result = result.and_then(|mut report| {
+        report.timings = timings.map(|timings| timings.timings());
+        if let (Some(path), Some(timings)) = (timings_file, report.timings.as_ref()) {
+            build_report::write_json_file(timings, &path, "build timings")?;
+            status!("✓ Wrote build timings: {}", path.display());
+        }
+        Ok(report)
+    });
+    diagnostics::print_summary(&diagnostics.finish());
+    result
+}
+
+fn run_build(options: BuildOptions, observer: Arc<dyn BuildObserver>) -> Result<BuildReport> {
+    status!("Starting build process...");
+    observer.on_build_phase(BuildPhase::Config);
+    let started = Instant::now();
 
-    let executor = BuildExecutor::new()
+    let project_dir = std::path::absolute(&options.working_dir)
         .map_err(|e| BuildError::BuildProcessFailed {
-            details: format!("Failed to initialize build executor: {}", e),
+            details: format!("Failed to resolve working directory '{}': {}", options.working_dir.display(), e),
         })?;
-    println!("Configuration loaded and validated");
+    let args = &options.args;
+    let mut config = match options.config {
+        Some(config) => config,
+        None => load_build_config(args, &project_dir, options.user_config.as_deref())?,
+    };
+    apply_build_args(&mut config, args);
+
+    // Held until the build finishes, fails or panics, from before the pre-build command writes anything.
+    // A dry run writes nothing and needs no lock.
+    let _lock = match args.dry_run {
+        true => None,
+        false => acquire_build_lock(&config, &project_dir, args.no_lock)?,
+    };
+
+    // Generated components have to exist before the configuration is validated against them
+    match config.build.pre_build_command {
+        Some(_) if args.dry_run => status!("ℹ Skipping pre-build command (--dry-run)"),
+        Some(ref command) if !args.skip_pre_build => {
+            observer.on_build_phase(BuildPhase::PreBuild);
+            run_pre_build_command(command, &project_dir)?
+        }
+        Some(_) => status!("ℹ Skipping pre-build command (--skip-pre-build)"),
+        None => {}
+    }
 
-    let combinations = determine_build_combinations(&executor.config)?;
-    println!("Determined {} build combinations", combinations.len());
+    // Keep the original error, so a broken configuration exits with its own code rather than as a build failure
+    observer.on_build_phase(BuildPhase::Validation);
+    let executor = BuildExecutor::from_config(config, &project_dir)?;
+    status!("Configuration loaded and validated");
+    check_merger_available(&executor.config)?;
+
+    let combinations = match args.components_only {
+        Some(ref extensions) => resolve_components_only_combination(&executor.config, extensions, args.env.as_deref())?,
+        None if args.use_default_env => resolve_default_environment_combinations(&executor.config)?,
+        None => determine_build_combinations(&executor.config)?,
+    };
+    status!("Determined {} build combinations", combinations.len());
 
     if combinations.is_empty() {
-        return Err(BuildError::BuildProcessFailed {
-            details: "No valid build combinations found".to_string(),
-        }.into());
+        return Err(BuildError::NoCombinations.into());
+    }
+
+    if args.dry_run {
+        let new_structure: Vec<String> = combinations.iter().map(|combo| combo.output_dir.clone()).collect();
+        let cleaner = create_cleaner(&executor.config, &executor.paths, args.force_clean, args.force)?;
+        let cleanup = cleaner.plan_cleanup()
+            .map_err(|e| BuildError::BuildProcessFailed {
+                details: format!("Failed to plan build directory cleanup: {}", e),
+            })?;
+        let restore = cleaner.plan_restore(&cleanup.preserved_files, &new_structure);
+        cleaner.print_plan(&cleanup, &restore);
+        let stale = stale_combinations(Path::new(&executor.config.paths.build_dir), &combinations);
+        if !stale.is_empty() {
+            status!("{}:", if args.keep_stale { "Stale combinations that would be kept" } else { "Combinations that would be pruned" });
+            for combination in &stale {
+                status!("  {}", combination.output_dir);
+            }
+        }
+        status!("Dry run completed, nothing was written");
+        let mut report = BuildReport::new(executor.config.build.yaml_merger.clone(), Path::new(&executor.config.paths.build_dir));
+        report.set_warnings(diagnostics::collected());
+        return Ok(report);
+    }
+
+    let (mut report, failures) = create_build_structure(&executor, &combinations, args, Arc::clone(&observer))?;
+    observer.on_build_phase(BuildPhase::Finish);
+    report.set_warnings(diagnostics::collected());
+    report.duration_ms = started.elapsed().as_millis() as u64;
+
+    if let Some(ref summary_file) = args.summary_file {
+        let summary_path = executor.paths.project_root.join(summary_file);
+        build_report::write_summary_file(&report, &summary_path)?;
+        status!("✓ Wrote build summary: {}", summary_file.display());
+    }
+
+    // The successful combinations are in place; fail with the others only now
+    if !failures.is_empty() {
+        return Err(BuildError::CombinationsFailed { total: combinations.len(), errors: failures }.into());
+    }
+
+    let archive_path = args.archive.clone().or_else(|| executor.config.build.archive_path.as_ref().map(PathBuf::from));
+    if let Some(archive_path) = archive_path {
+        let build_dir = Path::new(&executor.config.paths.build_dir);
+        let count = archive::write_archive(build_dir, &executor.paths.project_root.join(&archive_path), &ChecksumScope::from_config(&executor.config))?;
+        status!("✓ Archived {} files to {}", count, archive_path.display());
+    }
+
+    status!("Build process completed successfully");
+    Ok(report)
+}
+
+/// Run the image check command for every image referenced by the compose files of `report`, which are still in
+/// `staging_dir`, and fail with the images it did not find. Without the command's tool a warning is emitted instead.
+fn check_images(executor: &BuildExecutor, report: &BuildReport, staging_dir: &Path) -> Result<()> {
+    let mut documents = Vec::new();
+    for combination in &report.combinations {
+        let compose_files = combination.files_written.iter()
+            .filter(|file| file.extension().is_some_and(|extension| extension == "yml" || extension == "yaml" || extension == "json"));
+        for file in compose_files {
+            let path = staging_dir.join(file.strip_prefix(&report.build_dir).unwrap_or(file));
+            let Ok(content) = fs::read_to_string(&path) else { continue };
+            documents.extend(image_check::parse_documents(&content));
+        }
+    }
+
+    let images = image_check::compose_images(&documents);
+    let template = executor.config.build.image_check_command.as_deref().unwrap_or(DEFAULT_IMAGE_CHECK_COMMAND);
+    status!("Checking {} images with: {}", images.len(), template);
+    let mut missing = Vec::new();
+    for image in &images {
+        if !image_check::is_checkable_image(image) {
+            diagnostics::warn(WarningCategory::ImageCheck, format!("Image '{}' was not checked: it is interpolated by docker compose or contains characters the check command cannot take", image));
+            continue;
+        }
+        let command = image_check::render_command(template, image);
+        let output = shell_command(&command)
+            .current_dir(&executor.paths.project_root)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .map_err(|e| BuildError::BuildProcessFailed {
+                details: format!("Failed to run image check '{}': {}", command, e),
+            })?;
+        if output.status.success() {
+            status!("  ✓ {}", image);
+        } else if image_check::is_command_not_found(output.status.code()) {
+            diagnostics::warn(WarningCategory::ImageCheck, format!("Image check command not found, no images were checked: {}", String::from_utf8_lossy(&output.stderr).trim()));
+            return Ok(());
+        } else {
+            status!("  ✗ {}", image);
+            missing.push(image.clone());
+        }
+    }
+
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(BuildError::MissingImages { images: missing }.into()),
+    }
+}
+
+/// Convert the merged compose file of every combination in `report` with kompose into `k8s/<combination>` of the
+/// staging directory. A missing kompose or a failed conversion is a warning, so `--strict` decides whether it fails the build.
+fn convert_to_kubernetes(report: &BuildReport, build_dir: &Path, staging_dir: &Path) {
+    if let Err(e) = kompose::check_kompose_availability() {
+        diagnostics::warn(WarningCategory::Kubernetes, format!("kubernetes_output is enabled but kompose is not available, no Kubernetes manifests were generated: {}", e));
+        return;
+    }
+    for combination in &report.combinations {
+        let _scope = diagnostics::in_combination(&combination.output_dir);
+        let compose_file = staging_dir.join(combination.compose_file.strip_prefix(build_dir).unwrap_or(&combination.compose_file));
+        let output_dir = staging_dir.join(KUBERNETES_OUTPUT_DIR).join(&combination.output_dir);
+        match kompose::convert(&compose_file, &output_dir) {
+            Ok(()) => status!("✓ Generated Kubernetes manifests for {}: {}", combination_label(&combination.output_dir),
+                              build_dir.join(KUBERNETES_OUTPUT_DIR).join(&combination.output_dir).display()),
+            Err(e) => diagnostics::warn(WarningCategory::Kubernetes, format!("No Kubernetes manifests for {}: {}", combination_label(&combination.output_dir), e)),
+        }
     }
+}
+
+/// Combinations of the build in `build_dir`, as recorded in its manifest, whose output directory still exists
+/// but belongs to none of `combinations`. A build written to the build root is never stale.
+fn stale_combinations(build_dir: &Path, combinations: &[BuildCombination]) -> Vec<ManifestCombination> {
+    let Some(previous) = CombinationsManifest::load(build_dir) else { return Vec::new() };
+    previous.combinations.into_iter()
+        .chain(previous.stale)
+        .filter(|stale| {
+            // The manifest may be hand-edited; never prune or carry over anything outside the build directory
+            let inside = Path::new(&stale.output_dir).components().all(|component| matches!(component, std::path::Component::Normal(_)));
+            if !inside {
+                diagnostics::warn(WarningCategory::Cleanup, format!(
+                    "{} lists output directory '{}', which is not inside the build directory; it is ignored",
+                    build_report::COMBINATIONS_MANIFEST_FILE, stale.output_dir,
+                ));
+            }
+            inside
+        })
+        .filter(|stale| !stale.output_dir.is_empty() && build_dir.join(&stale.output_dir).is_dir())
+        .filter(|stale| combinations.iter().all(|combo| combo.output_dir != stale.output_dir))
+        .collect()
+}
+
+/// Whether a stale output directory can be carried over: it must not contain or lie in a directory of this build
+fn stale_output_keepable(stale: &str, output_dirs: &[PathBuf]) -> bool {
+    let stale = Path::new(stale);
+    output_dirs.iter().all(|output_dir| !(output_dir.starts_with(stale) || stale.starts_with(output_dir)))
+}
+
+/// `command` run by the shell: `sh -c`, or `cmd /C` on Windows
+fn shell_command(command: &str) -> std::process::Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Run `command` through the shell in `project_dir`. Its output is captured and printed as progress,
+/// so it stays out of `--report json`, and becomes part of the error when the command fails.
+fn run_pre_build_command(command: &str, project_dir: &Path) -> Result<()> {
+    status!("Running pre-build command: {}", command);
+    let output = shell_command(command)
+        .current_dir(project_dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| BuildError::PreBuildCommandFailed {
+            command: command.to_string(),
+            status: format!("could not be started: {}", e),
+            output: String::new(),
+        })?;
 
-    create_build_structure(&executor, &combinations)?;
+    let text = [&output.stdout, &output.stderr]
+        .iter()
+        .map(|stream| String::from_utf8_lossy(stream).trim_end().to_string())
+        .filter(|stream| !stream.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !output.status.success() {
+        return Err(BuildError::PreBuildCommandFailed {
+            command: command.to_string(),
+            status: match output.status.code() {
+                Some(code) => format!("exited with code {}", code),
+                None => "was terminated by a signal".to_string(),
+            },
+            output: text,
+        }.into());
+    }
+    for line in text.lines() {
+        status!("  {}", line);
+    }
+    status!("✓ Pre-build command completed");
+    Ok(())
+}
 
-    println!("Build process completed successfully");
+/// Fail early when the configured merger needs a yq binary that cannot be run
+fn check_merger_available(config: &config::Config) -> Result<()> {
+    // Check yq availability only if yq merger is configured and something is merged
+    let merges = config.build.output_mode == OutputMode::Merged && !config.build.multi_document;
+    if config.build.yaml_merger == YamlMergerType::Yq && merges {
+        check_yq_availability()
+            .map_err(|_| BuildError::BuildProcessFailed {
+                details: "yq is required but not available. Please either:\n\
+                    1. Install yq v4+ from https://github.com/mikefarah/yq\n\
+                    2. Or set yaml_merger = \"rust\" in your stackbuilder.toml config file\n\n\
+                    Installation options:\n\
+                    - Ubuntu/Debian: sudo apt install yq\n\
+                    - macOS: brew install yq\n\
+                    - Binary: wget https://github.com/mikefarah/yq/releases/latest/download/yq_linux_amd64 -O /usr/bin/yq && chmod +x /usr/bin/yq".to_string(),
+            })?;
+    }
     Ok(())
 }
 
+/// Apply the `build` flags that override configuration options
+fn apply_build_args(config: &mut config::Config, args: &BuildArgs) {
+    config.build.validation_mode = config::validation_mode(config, args.fail_fast);
+    add_component_roots(config, args);
+    if let Some(ref template) = args.compose_project_name {
+        config.build.project_name_template = Some(template.clone());
+    }
+    if args.keep_going {
+        config.build.keep_going = true;
+    }
+    if args.resolve_env {
+        config.build.resolve_env = true;
+    }
+}
+
+/// Append the roots given with --components-dir to those of the configuration
+fn add_component_roots(config: &mut config::Config, args: &BuildArgs) {
+    config.paths.components_dirs.extend(args.components_dirs.iter().map(|dir| dir.to_string_lossy().to_string()));
+}
+
 /// Determine all build combinations based on configuration
-fn determine_build_combinations(config: &config::Config) -> Result<Vec<BuildCombination>> {
+pub fn determine_build_combinations(config: &config::Config) -> Result<Vec<BuildCombination>> {
+    // The matrix is expanded into inline extension combinations of every environment
+    let expanded;
+    let config = match config::matrix_size(config) {
+        0 => config,
+        size => {
+            let count = size.saturating_mul(config::get_environments_list(config).len().max(1));
+            if count > config.build.max_combinations {
+                return Err(ValidationError::TooManyCombinations { count, max: config.build.max_combinations }.into());
+            }
+            expanded = config::with_matrix_extensions(config);
+            &expanded
+        }
+    };
+
     let combinations = if config::is_using_new_environments_api(config) {
         // New API mode: use new environments structure
         resolve_new_api_combinations(config)?
@@ -106,20 +617,168 @@ fn determine_build_combinations(config: &config::Config) -> Result<Vec<BuildComb
     };
 
     if combinations.is_empty() {
-        return Err(BuildError::BuildProcessFailed {
-            details: "No valid build combinations found".to_string(),
-        }.into());
+        return Err(BuildError::NoCombinations.into());
+    }
+    check_exclusive_groups(config, &combinations)?;
+    let combinations = apply_aliases(config, combinations);
+    // The build directory is cleaned before anything is written, so a collision must fail here
+    check_output_dir_collisions(&combinations)?;
+    if config.build.kubernetes_output {
+        if let Some(combo) = combinations.iter().find(|combo| Path::new(&combo.output_dir).starts_with(KUBERNETES_OUTPUT_DIR)) {
+            return Err(ValidationError::OutputDirCollision {
+                output_dir: combo.output_dir.clone(),
+                first: combo.definition(),
+                second: "the Kubernetes manifests of kubernetes_output".to_string(),
+            }.into());
+        }
     }
 
-    println!("Generated {} build combinations:", combinations.len());
+    status!("Generated {} build combinations:", combinations.len());
     for combo in &combinations {
-        println!("  → {}: env={:?}, extensions={:?}, combos={:?}",
+        status!("  → {}: env={:?}, extensions={:?}, combos={:?}",
                 combo.output_dir, combo.environment, combo.extensions, combo.combo_names);
     }
 
     Ok(combinations)
 }
 
+/// The first group of `[build.exclusive_groups]` with more than one member in `extensions`, and those members
+fn exclusive_group_conflict<'a>(config: &'a config::Config, extensions: &[String]) -> Option<(&'a str, Vec<&'a String>)> {
+    config.build.exclusive_groups.iter()
+        .map(|(group, members)| (group.as_str(), members.iter().filter(|member| extensions.contains(member)).collect::<Vec<_>>()))
+        .find(|(_, present)| present.len() > 1)
+}
+
+/// Fail when a combination has more than one extension of an exclusive group, naming where each of them comes from
+fn check_exclusive_groups(config: &config::Config, combinations: &[BuildCombination]) -> Result<()> {
+    for combo in combinations {
+        let Some((group, present)) = exclusive_group_conflict(config, &combo.extensions) else { continue };
+        let members = present.into_iter()
+            .map(|member| Ok(format!("{} ({})", member, extension_source(config, combo, member)?)))
+            .collect::<Result<Vec<_>>>()?;
+        return Err(ValidationError::ExclusiveGroupConflict {
+            group: group.to_string(),
+            combination: combination_label(&combo.output_dir).to_string(),
+            members,
+        }.into());
+    }
+    Ok(())
+}
+
+/// Where `extension` of `combo` comes from: one of its combos, a `[build.matrix]` entry or an extensions list entry
+fn extension_source(config: &config::Config, combo: &BuildCombination, extension: &String) -> Result<String> {
+    for combo_name in &combo.combo_names {
+        if config::resolve_combo_extensions(config, std::slice::from_ref(combo_name))?.contains(extension) {
+            return Ok(format!("combo '{}'", combo_name));
+        }
+    }
+    let entry = combo.extensions.join(&EXTENSION_COMBINATION_SEPARATOR.to_string());
+    if config::matrix_extension_entries(config).contains(&entry) {
+        return Ok(format!("[build.matrix] entry '{}'", entry));
+    }
+    // Environments with their own configuration do not use the global extensions
+    let list = match combo.environment.as_deref().filter(|env| config::get_environment_config(config, env).is_some()) {
+        Some(env) => format!("the extensions of environment '{}'", env),
+        None => "the [build] extensions".to_string(),
+    };
+    Ok(format!("entry '{}' in {}", entry, list))
+}
+
+/// Write the combinations named in `[build.aliases]` to their alias instead of their planned output directory
+fn apply_aliases(config: &config::Config, mut combinations: Vec<BuildCombination>) -> Vec<BuildCombination> {
+    for (name, alias) in &config.build.aliases {
+        let name = name.trim_matches('/');
+        match combinations.iter_mut().find(|combo| combo.output_dir == name) {
+            Some(combo) => combo.output_dir = alias.trim_matches('/').to_string(),
+            None => diagnostics::warn(WarningCategory::Config, format!("[build.aliases] names '{}', which is not the output directory of any combination", name)),
+        }
+    }
+    combinations
+}
+
+/// The output directory of the combination called `name`, either its planned output directory or its alias
+pub fn aliased_output_dir<'a>(config: &'a config::Config, name: &'a str) -> &'a str {
+    let name = name.trim_matches('/');
+    config.build.aliases.iter()
+        .find(|(planned, _)| planned.trim_matches('/') == name)
+        .map_or(name, |(_, alias)| alias.trim_matches('/'))
+}
+
+/// Fail when two combinations share an output directory, where the second would silently overwrite the first
+fn check_output_dir_collisions(combinations: &[BuildCombination]) -> Result<()> {
+    let mut seen: HashMap<&str, &BuildCombination> = HashMap::new();
+    for combo in combinations {
+        match seen.entry(combo.output_dir.as_str()) {
+            Entry::Occupied(first) => {
+                return Err(ValidationError::OutputDirCollision {
+                    output_dir: combination_label(&combo.output_dir).to_string(),
+                    first: first.get().definition(),
+                    second: combo.definition(),
+                }.into());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(combo);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Build a single combination from an explicit extension list, ignoring configured environments and combos
+fn resolve_components_only_combination(config: &config::Config, extensions: &[String], environment: Option<&str>) -> Result<Vec<BuildCombination>> {
+    let available_extensions = config::discover_extensions(config)?;
+
+    for ext in extensions {
+        if !available_extensions.contains(ext) {
+            return Err(ValidationError::extension_not_found(ext.clone(), config.paths.extensions_dirs.clone(), available_extensions.clone()).into());
+        }
+    }
+    if let Some((group, present)) = exclusive_group_conflict(config, extensions) {
+        return Err(ValidationError::ExclusiveGroupConflict {
+            group: group.to_string(),
+            combination: combination_label("").to_string(),
+            members: present.into_iter().map(|member| format!("{} (--components-only)", member)).collect(),
+        }.into());
+    }
+
+    // The environment filter must name a configured environment or an environment folder
+    if let Some(env) = environment {
+        let envs_path = Path::new(&config.paths.components_dir).join(&config.paths.environments_dir);
+        let mut available = config::get_environments_list(config);
+        for name in config::discover_component_dirs(&envs_path)? {
+            if !available.contains(&name) {
+                available.push(name);
+            }
+        }
+        if !available.iter().any(|name| name == env) {
+            return Err(ValidationError::environment_not_found(env, envs_path, available).into());
+        }
+    }
+
+    status!("Building explicit component set: env={:?}, extensions={:?}", environment, extensions);
+
+    Ok(vec![BuildCombination {
+        environment: environment.map(|e| e.to_string()),
+        extensions: extensions.to_vec(),
+        combo_names: vec![],
+        output_dir: "".to_string(), // Put directly in build root
+    }])
+}
+
+/// Resolve the combinations of `default_environment` only (`--use-default-env`), keeping their output directories
+fn resolve_default_environment_combinations(config: &config::Config) -> Result<Vec<BuildCombination>> {
+    let default_env = config.build.default_environment.as_deref()
+        .ok_or(BuildError::DefaultEnvironmentNotSet)?;
+
+    let combinations: Vec<BuildCombination> = determine_build_combinations(config)?
+        .into_iter()
+        .filter(|combo| combo.environment.as_deref() == Some(default_env))
+        .collect();
+
+    status!("Building default environment '{}' only", default_env);
+    Ok(combinations)
+}
+
 /// Resolve build combinations using new environments API
 fn resolve_new_api_combinations(config: &config::Config) -> Result<Vec<BuildCombination>> {
     if let Some(ref env_config) = config.build.environments_config {
@@ -130,6 +789,7 @@ fn resolve_new_api_combinations(config: &config::Config) -> Result<Vec<BuildComb
                     extensions: cfg.extensions.clone(),
                     combos: cfg.combos.clone(),
                     skip_base_generation: cfg.skip_base_generation,
+                    exclude_patterns: cfg.exclude_patterns.clone(),
                 }))
                 .collect(),
         };
@@ -239,23 +899,19 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
                 for ext in &env_extensions {
                     combinations.push(BuildCombination {
                         environment: Some(env.clone()),
-                        extensions: vec![ext.clone()],
+                        extensions: config::parse_extension_combination(ext),
                         combo_names: vec![],
-                        output_dir: ext.clone(),
+                        output_dir: config::extension_combination_name(config, ext),
                     });
                 }
                 
                 // Add combo combinations for environment (NO env prefix for single environment)
                 for combo_name in &env_combo_names {
-                    let combo_extensions = config.build.combos.get(combo_name)
-                        .ok_or_else(|| ValidationError::ComboNotFound {
-                            combo_name: combo_name.clone(),
-                            available_combos: config.build.combos.keys().cloned().collect(),
-                        })?;
+                    let combo_extensions = config::resolve_combo_extensions(config, std::slice::from_ref(combo_name))?;
                     
                     combinations.push(BuildCombination {
                         environment: Some(env.clone()),
-                        extensions: combo_extensions.clone(),
+                        extensions: combo_extensions,
                         combo_names: vec![combo_name.clone()],
                         output_dir: combo_name.clone(),
                     });
@@ -265,22 +921,18 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
                 if env_extensions.len() == 1 {
                     combinations.push(BuildCombination {
                         environment: Some(env.clone()),
-                        extensions: env_extensions.clone(),
+                        extensions: config::parse_extension_combination(&env_extensions[0]),
                         combo_names: vec![],
                         output_dir: "".to_string(), // Empty = build root
                     });
                 } else if env_combo_names.len() == 1 {
                     // Single combo case
                     let combo_name = &env_combo_names[0];
-                    let combo_extensions = config.build.combos.get(combo_name)
-                        .ok_or_else(|| ValidationError::ComboNotFound {
-                            combo_name: combo_name.clone(),
-                            available_combos: config.build.combos.keys().cloned().collect(),
-                        })?;
+                    let combo_extensions = config::resolve_combo_extensions(config, std::slice::from_ref(combo_name))?;
                     
                     combinations.push(BuildCombination {
                         environment: Some(env.clone()),
-                        extensions: combo_extensions.clone(),
+                        extensions: combo_extensions,
                         combo_names: vec![combo_name.clone()],
                         output_dir: "".to_string(), // Empty = build root
                     });
@@ -332,23 +984,19 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
                         for ext in &env_extensions {
                             combinations.push(BuildCombination {
                                 environment: Some(env.clone()),
-                                extensions: vec![ext.clone()],
+                                extensions: config::parse_extension_combination(ext),
                                 combo_names: vec![],
-                                output_dir: format!("{}/{}", env, ext),
+                                output_dir: format!("{}/{}", env, config::extension_combination_name(config, ext)),
                             });
                         }
                         
                         // Environment with combos - with subfolders
                         for combo_name in &env_combo_names {
-                            let combo_extensions = config.build.combos.get(combo_name)
-                                .ok_or_else(|| ValidationError::ComboNotFound {
-                                    combo_name: combo_name.clone(),
-                                    available_combos: config.build.combos.keys().cloned().collect(),
-                                })?;
+                            let combo_extensions = config::resolve_combo_extensions(config, std::slice::from_ref(combo_name))?;
                             
                             combinations.push(BuildCombination {
                                 environment: Some(env.clone()),
-                                extensions: combo_extensions.clone(),
+                                extensions: combo_extensions,
                                 combo_names: vec![combo_name.clone()],
                                 output_dir: format!("{}/{}", env, combo_name),
                             });
@@ -358,21 +1006,17 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
                         if env_extensions.len() == 1 {
                             combinations.push(BuildCombination {
                                 environment: Some(env.clone()),
-                                extensions: env_extensions.clone(),
+                                extensions: config::parse_extension_combination(&env_extensions[0]),
                                 combo_names: vec![],
                                 output_dir: env.clone(),
                             });
                         } else if env_combo_names.len() == 1 {
                             let combo_name = &env_combo_names[0];
-                            let combo_extensions = config.build.combos.get(combo_name)
-                                .ok_or_else(|| ValidationError::ComboNotFound {
-                                    combo_name: combo_name.clone(),
-                                    available_combos: config.build.combos.keys().cloned().collect(),
-                                })?;
+                            let combo_extensions = config::resolve_combo_extensions(config, std::slice::from_ref(combo_name))?;
                             
                             combinations.push(BuildCombination {
                                 environment: Some(env.clone()),
-                                extensions: combo_extensions.clone(),
+                                extensions: combo_extensions,
                                 combo_names: vec![combo_name.clone()],
                                 output_dir: env.clone(),
                             });
@@ -399,21 +1043,24 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
         // Use global extensions if no environments
         for ext in &global_extensions {
             let output_dir = if should_create_subfolders {
-                ext.clone()
+                config::extension_combination_name(config, ext)
             } else {
                 "".to_string() // Single variant - put directly in build root
             };
             
             combinations.push(BuildCombination {
                 environment: None,
-                extensions: vec![ext.clone()],
+                extensions: config::parse_extension_combination(ext),
                 combo_names: vec![],
                 output_dir,
             });
         }
         
-        // Add combo combinations for 0 environments case
-        for (combo_name, combo_extensions) in &config.build.combos {
+        // Add combo combinations for 0 environments case, in name order since combos is a HashMap
+        let mut combo_names: Vec<&String> = config.build.combos.keys().collect();
+        combo_names.sort();
+        for combo_name in combo_names {
+            let combo_extensions = config::resolve_combo_extensions(config, std::slice::from_ref(combo_name))?;
             let output_dir = if should_create_subfolders {
                 combo_name.clone()
             } else {
@@ -422,7 +1069,7 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
             
             combinations.push(BuildCombination {
                 environment: None,
-                extensions: combo_extensions.clone(),
+                extensions: combo_extensions,
                 combo_names: vec![combo_name.clone()],
                 output_dir,
             });
@@ -434,7 +1081,7 @@ fn resolve_legacy_combinations_with_targets(config: &config::Config, targets: Op
 
 
 /// Resolve all extensions from direct extensions and combo names
-fn resolve_all_extensions(config: &config::Config, direct_extensions: &[String], combo_names: &[String]) -> Result<Vec<String>> {
+pub fn resolve_all_extensions(config: &config::Config, direct_extensions: &[String], combo_names: &[String]) -> Result<Vec<String>> {
     let mut all_extensions = Vec::new();
     
     // Add direct extensions
@@ -454,171 +1101,1426 @@ fn resolve_all_extensions(config: &config::Config, direct_extensions: &[String],
         }
     }
     
-    Ok(all_extensions)
+    // Higher-weight extensions are applied later; the sort is stable so equal weights keep their order
+    let mut weighted = Vec::with_capacity(all_extensions.len());
+    for ext in all_extensions {
+        let weight = config::load_extension_meta(config, &ext)?.weight;
+        weighted.push((weight, ext));
+    }
+    weighted.sort_by_key(|(weight, _)| *weight);
+    
+    Ok(weighted.into_iter().map(|(_, ext)| ext).collect())
 }
 
-/// Create build directory structure and merge files
-fn create_build_structure(executor: &BuildExecutor, combinations: &[BuildCombination]) -> Result<()> {
+/// Create build directory structure and merge files.
+/// Everything is generated into a staging directory that replaces the build directory only on success.
+/// With `keep_going`, failed combinations are left out and their errors returned next to the report.
+fn create_build_structure(
+    executor: &BuildExecutor,
+    combinations: &[BuildCombination],
+    args: &BuildArgs,
+    observer: Arc<dyn BuildObserver>,
+) -> Result<(BuildReport, Vec<StackBuilderError>)> {
     let build_dir = Path::new(&executor.config.paths.build_dir);
+    observer.on_build_phase(BuildPhase::Cleanup);
 
     // Smart cleanup with .env preservation
-    let cleaner = BuildCleaner::new(
-        build_dir,
-        executor.config.build.preserve_env_files,
-        executor.config.build.env_file_patterns.clone(),
-        executor.config.build.backup_dir.clone(),
-    );
+    let cleaner = create_cleaner(&executor.config, &executor.paths, args.force_clean, args.force)?
+        .with_keep_stale(args.keep_stale);
 
-    cleaner.clean_build_directory()
+    // Refuse to wipe directories that look like user data before touching anything
+    if let Some(reason) = cleaner.unsafe_cleanup_reason() {
+        return Err(BuildError::UnsafeBuildDirectory {
+            path: build_dir.to_path_buf(),
+            reason,
+        }.into());
+    }
+
+    let staging_dir = cleaner.prepare_staging()
         .map_err(|e| BuildError::BuildProcessFailed {
-            details: format!("Failed to clean build directory: {}", e),
+            details: format!("Failed to prepare staging directory: {}", e),
+        })?;
+
+    // --force regenerates everything, but still records a fresh cache for the next build
+    let cache_path = executor.paths.project_root.join(BUILD_CACHE_FILE);
+    let merger = executor.config.build.yaml_merger.clone();
+    let mut cache = match args.force {
+        true => BuildCache::new(merger),
+        false => BuildCache::load(&cache_path, merger),
+    };
+
+    let writer = OutputWriter::new(args.parallel_io, Some(Arc::clone(&observer)))
+        .with_previous_build(&staging_dir, build_dir);
+    let result = generate_build_outputs(executor, combinations, &cleaner, &staging_dir, writer, observer.as_ref(), &mut cache)
+        .and_then(|(report, failures)| match failures.len() == combinations.len() {
+            // Nothing usable was generated, keep the previous build instead
+            true => Err(BuildError::CombinationsFailed { total: combinations.len(), errors: failures }.into()),
+            false => Ok((report, failures)),
+        })
+        .and_then(|generated| match args.check_images {
+            true => check_images(executor, &generated.0, &staging_dir).map(|()| generated),
+            false => Ok(generated),
+        })
+        .and_then(|generated| match args.strict {
+            true => check_strict_warnings(&executor.config).map(|()| generated),
+            false => Ok(generated),
+        });
+    let (report, failures) = match result {
+        Ok(generated) => generated,
+        Err(e) => {
+            // The previous build output is still intact, only drop the partial staging tree
+            cleaner.discard_staging(&staging_dir);
+            return Err(e);
+        }
+    };
+
+    observer.on_build_phase(BuildPhase::Cleanup);
+    cleaner.commit_staging(&staging_dir)
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to replace build directory: {}", e),
         })?;
 
-    // Collect new structure paths for .env restoration
-    let new_structure: Vec<String> = combinations
+    // Objects only the previous build linked to are unreferenced now that it is gone
+    let object_store = executor.paths.project_root.join(OBJECT_STORE_DIR);
+    match file_copier::prune_object_store(&object_store) {
+        Ok(0) => {}
+        Ok(removed) => status!("✓ Pruned {} unreferenced objects from {}", removed, object_store.display()),
+        Err(e) => diagnostics::warn_at(WarningCategory::Cleanup, &object_store, format!("Failed to prune the object store: {:#}", e)),
+    }
+
+    observer.on_build_phase(BuildPhase::Finish);
+    if let Err(e) = cache.save(&cache_path) {
+        diagnostics::warn_at(WarningCategory::Cache, &cache_path, format!("Failed to write build cache: {}", e));
+    }
+
+    Ok((report, failures))
+}
+
+/// Generate all combinations, restored .env files and the .gitignore into the staging directory.
+/// `cache` holds the previous build's cache on entry and describes this build's outputs on return.
+/// With `keep_going`, the errors of combinations that failed are returned next to the report.
+fn generate_build_outputs(
+    executor: &BuildExecutor,
+    combinations: &[BuildCombination],
+    cleaner: &BuildCleaner,
+    staging_dir: &Path,
+    writer: OutputWriter,
+    observer: &dyn BuildObserver,
+    cache: &mut BuildCache,
+) -> Result<(BuildReport, Vec<StackBuilderError>)> {
+    let build_dir = Path::new(&executor.config.paths.build_dir);
+    let previous_cache = std::mem::replace(cache, BuildCache::new(executor.config.build.yaml_merger.clone()));
+    let mut report = BuildReport::new(executor.config.build.yaml_merger.clone(), build_dir);
+    // One timestamp for the whole build, so every header of a build agrees
+    let build_timestamp = crate::backups::format_timestamp(current_unix_time());
+    // Reused outputs would keep the timestamp of the build that generated them
+    let reuse_outputs = !executor.config.build.generated_header.as_deref().is_some_and(|template| template.contains("{timestamp}"));
+
+    // Create every output directory up front instead of once per combination
+    let total_variants = executor.num_extensions + executor.num_combos;
+    let output_paths: Vec<PathBuf> = combinations
         .iter()
-        .map(|combo| combo.output_dir.clone())
+        .map(|combo| combination_output_path(executor.num_envs, total_variants, combo, staging_dir))
         .collect();
+    for output_path in output_paths.iter().filter(|path| path.as_path() != staging_dir) {
+        fs::create_dir_all(output_path)
+            .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                path: output_path.clone(),
+                source: e,
+            })?;
+    }
 
-    for combo in combinations {
-        println!("Processing combination: {:?}", combo.output_dir);
-
-        // Special cases for putting file directly in build directory without subfolders:
-        // 1. 1 env + 0 ext + 0 combos
-        // 2. 0 env + 1 total variant (when output_dir is empty)
-        let total_variants = executor.num_extensions + executor.num_combos;
-        let (output_path, file_name) = if (executor.num_envs == 1 && total_variants == 0) || combo.output_dir.is_empty() {
-            (build_dir.to_path_buf(), "docker-compose.yml".to_string())
-        } else {
-            let path = build_dir.join(&combo.output_dir);
-            fs::create_dir_all(&path)
-                .map_err(|e| FileSystemError::DirectoryCreationFailed {
-                    path: path.clone(),
-                    source: e,
+    // Outputs of combinations the previous build had and this one does not; only --keep-stale carries them over
+    let stale = stale_combinations(build_dir, combinations);
+    let output_dirs: Vec<PathBuf> = output_paths.iter()
+        .map(|path| path.strip_prefix(staging_dir).unwrap_or(path).to_path_buf())
+        .collect();
+    let kept: Vec<bool> = stale.iter()
+        .map(|combination| cleaner.keeps_stale() && stale_output_keepable(&combination.output_dir, &output_dirs))
+        .collect();
+    if cleaner.keeps_stale() {
+        for (combination, _) in stale.iter().zip(&kept).filter(|(_, kept)| !**kept) {
+            diagnostics::warn(WarningCategory::Cleanup, format!(
+                "Stale combination {} overlaps an output directory of this build and is pruned despite --keep-stale",
+                combination.output_dir,
+            ));
+        }
+        for (combination, _) in stale.iter().zip(&kept).filter(|(_, kept)| **kept) {
+            cleaner.carry_over(staging_dir, &combination.output_dir)
+                .map_err(|e| BuildError::BuildProcessFailed {
+                    details: format!("Failed to keep stale combination {}: {}", combination.output_dir, e),
                 })?;
-            (path, "docker-compose.yml".to_string())
-        };
+            status!("ℹ Kept stale combination: {}", combination.output_dir);
+        }
+    }
 
-        // Merge compose files
-        let environment_opt = combo.environment.as_deref();
-        
-        // Resolve all extensions (direct + from combos)
-        let all_extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
-        
-        // Choose merger based on configuration
-        let final_content = match executor.config.build.yaml_merger {
-            YamlMergerType::Yq => {
-                // Use yq merger
-                let content = yq_merge_compose_files(&executor.yq_merger, environment_opt, &all_extensions)
-                    .map_err(|e| BuildError::BuildProcessFailed {
-                        details: format!("Failed to merge compose files with yq for combination {:?}: {}", combo.output_dir, e),
-                    })?;
-                println!("✓ Used yq merger for: {}", combo.output_dir);
-                content
-            }
-            YamlMergerType::Rust => {
-                // Use Rust merger directly
-                let merged = merge_compose_files(&executor.rust_merger, environment_opt, &all_extensions)
-                    .map_err(|e| BuildError::BuildProcessFailed {
-                        details: format!("Failed to merge compose files with Rust for combination {:?}: {}", combo.output_dir, e),
-                    })?;
-                
-                println!("✓ Used Rust merger for: {}", combo.output_dir);
-                serialize_yaml_with_proper_indentation(&merged)?
+    // Combinations generated in this build, by position in the report, with the hash of their inputs
+    let mut input_hashes = Vec::new();
+    // With keep_going a failed combination is recorded and the build goes on with the next one
+    let mut failures = Vec::new();
+    let mut failed_paths = Vec::new();
+    let mut failed_files = Vec::new();
+    observer.on_build_start(combinations);
+    for (combo, output_path) in combinations.iter().zip(&output_paths) {
+        observer.on_combination_start(combo);
+        // Progress bars take the place of the combination's line-based output
+        let _progress = observer.draws_progress().then(diagnostics::quiet);
+        writer.take_written();
+        let started = Instant::now();
+        let _scope = diagnostics::in_combination(&combo.output_dir);
+        let header = executor.config.build.generated_header.as_deref()
+            .map(|template| generated_header_lines(template, &combo.output_dir, &build_timestamp));
+
+        let outcome = (|| {
+            let extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
+            let input_hash = build_cache::input_hash(&executor.config, &executor.paths, combo, &extensions, executor.components_commit.as_deref())?;
+
+            // Unchanged inputs and untouched outputs: carry the previous outputs over instead of merging again
+            let reusable = previous_cache.lookup(&combo.output_dir, &input_hash)
+                .filter(|cached| reuse_outputs && cached.outputs_match(build_dir));
+            let combination_report = match reusable {
+                Some(cached) => {
+                    cached.restore_into(build_dir, staging_dir, &writer)?;
+                    status!("✓ Reused unchanged outputs for: {}", combo.output_dir);
+                    if let Some(counts) = cached.counts {
+                        status!("{}: {}", combination_label(&combo.output_dir), counts);
+                    }
+                    // Reused outputs still carry their problems, so --strict and the report see them again
+                    cached.warnings.iter().for_each(diagnostics::replay);
+                    cached.report(combo, extensions, executor.config.build.yaml_merger.clone(), build_dir)
+                }
+                None => generate_combination(executor, combo, output_path, staging_dir, header.as_deref(), &writer, observer)?,
+            };
+            Ok((combination_report, input_hash))
+        })().map_err(|e| combination_failed(&executor.config, combo, e));
+
+        let (mut combination_report, input_hash) = match outcome {
+            Ok(generated) => generated,
+            Err(e) if executor.config.build.keep_going => {
+                status!("✗ {}", e);
+                observer.on_combination_failed(combo, &e, started.elapsed());
+                let (output_dir, environment, extensions) = e.combination().unwrap_or((&combo.output_dir, None, &[]));
+                // The combination has its own fields, the code and message describe what went wrong in it
+                let cause = match &e {
+                    StackBuilderError::Build(BuildError::CombinationFailed { source, .. }) => source.as_ref(),
+                    other => other,
+                };
+                report.failed.push(FailedCombination {
+                    output_dir: output_dir.to_string(),
+                    environment: environment.map(str::to_string),
+                    extensions: extensions.to_vec(),
+                    code: cause.code().to_string(),
+                    message: cause.to_string(),
+                });
+                failures.push(e);
+                failed_paths.push(output_path);
+                failed_files.extend(writer.take_written());
+                continue;
             }
+            Err(e) => return Err(e),
         };
+        match previous_cache.lookup(&combo.output_dir, &input_hash).filter(|_| combination_report.cached) {
+            Some(cached) => cache.insert(&combo.output_dir, cached.clone()),
+            None => input_hashes.push((report.combinations.len(), input_hash)),
+        }
+        let duration = started.elapsed();
+        combination_report.duration_ms = duration.as_millis() as u64;
+        report.combinations.push(combination_report);
+        observer.on_combination_done(combo, duration);
+    }
 
-        // Write merged file
-        let compose_path = output_path.join(&file_name);
+    warn_unmatched_image_tags(&executor.config, &report);
 
-        fs::write(&compose_path, final_content)
-            .map_err(|e| BuildError::OutputFileWriteError {
-                path: compose_path.clone(),
-                source: e,
-            })?;
-        println!("✓ Created {}", compose_path.display());
+    // Queued writes must land before .env files are restored next to them
+    writer.finish()?;
 
-        // Process .env.example files if enabled
-        if executor.config.build.copy_env_example {
-            let env_file_path = output_path.join(".env.example");
-            let environment_opt = combo.environment.as_deref();
-            
-            // Resolve all extensions for .env merging
-            let all_extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
-            
-            match merge_env_files(&executor.env_merger, environment_opt, &all_extensions) {
-                Ok(merged_env) => {
-                    if !merged_env.variables.is_empty() || !merged_env.header_comments.is_empty() {
-                        if let Err(e) = write_merged_env(&merged_env, &env_file_path.to_string_lossy()) {
-                            println!("Warning: Failed to write .env.example file for {}: {}", combo.output_dir, e);
-                        }
-                    } else {
-                        println!("No .env.example variables found for combination: {}", combo.output_dir);
-                    }
-                }
-                Err(e) => {
-                    println!("Warning: Failed to merge .env.example files for {}: {}", combo.output_dir, e);
-                }
+    if executor.config.build.kubernetes_output && executor.config.build.output_mode == OutputMode::Merged && !executor.config.build.multi_document {
+        convert_to_kubernetes(&report, build_dir, staging_dir);
+    }
+
+    // Drop what failed combinations wrote before failing, and the directories that leaves empty
+    for file in &failed_files {
+        let _ = fs::remove_file(file);
+    }
+    let directories = failed_files.iter().filter_map(|file| file.parent()).chain(failed_paths.iter().map(|path| path.as_path()));
+    for directory in directories {
+        let mut directory = Some(directory);
+        while let Some(dir) = directory.filter(|dir| *dir != staging_dir && dir.starts_with(staging_dir)) {
+            if fs::remove_dir(dir).is_err() {
+                break;
             }
+            directory = dir.parent();
         }
+    }
 
-        // Copy additional files if enabled
-        let file_copier = FileCopier::new(executor.config.clone())
-            .map_err(|e| BuildError::BuildProcessFailed {
-                details: format!("Failed to initialize file copier: {}", e),
-            })?;
+    // .env files of failed combinations have nowhere to go and are backed up
+    let new_structure: Vec<String> = report.combinations
+        .iter()
+        .map(|combination| combination.output_dir.clone())
+        .collect();
+    // Preserved files of kept stale combinations go back where they were
+    let restore_structure: Vec<String> = new_structure.iter()
+        .cloned()
+        .chain(stale.iter().zip(&kept).filter(|(_, kept)| **kept).map(|(combination, _)| combination.output_dir.clone()))
+        .collect();
 
-        // Resolve all extensions for file copying
-        let all_extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
-        
-        if let Err(e) = file_copier.copy_additional_files(
-            combo.environment.as_deref(),
-            &all_extensions,
-            &output_path,
-        ) {
-            println!("Warning: Failed to copy additional files for {}: {}", combo.output_dir, e);
+    // Hash the outputs before preserved files are restored over them, so edited files never match the cache
+    for (index, input_hash) in input_hashes {
+        let combination = &report.combinations[index];
+        match CachedCombination::record(input_hash, combination, build_dir, staging_dir) {
+            Ok(cached) => cache.insert(&combination.output_dir, cached),
+            Err(e) => diagnostics::warn(WarningCategory::Cache, format!("Failed to record {} in the build cache: {}", combination.output_dir, e)),
         }
     }
 
     // Restore preserved .env files after creating new structure
-    cleaner.restore_env_files(&new_structure)
+    observer.on_build_phase(BuildPhase::Restore);
+    let restored = cleaner.restore_env_files_into(staging_dir, &restore_structure)
         .map_err(|e| BuildError::BuildProcessFailed {
             details: format!("Failed to restore .env files: {}", e),
         })?;
+    report.pruned = stale.into_iter()
+        .zip(kept)
+        .map(|(combination, kept)| {
+            let dir = Path::new(&combination.output_dir);
+            PrunedCombination {
+                env_files_backed_up: restored.backed_up.iter().filter(|path| path.starts_with(dir)).cloned().collect(),
+                env_files_relocated: restored.restored.iter()
+                    .filter(|(old_path, new_path)| old_path.starts_with(dir) && !new_path.starts_with(dir))
+                    .map(|(_, new_path)| build_dir.join(new_path))
+                    .collect(),
+                kept,
+                output_dir: combination.output_dir,
+                environment: combination.environment,
+                extensions: combination.extensions,
+                combos: combination.combos,
+                files: combination.files,
+            }
+        })
+        .collect();
+    report.pruned.sort_by(|a, b| a.output_dir.cmp(&b.output_dir));
+    for (_, new_path) in restored.restored {
+        let restored_dir = new_path.parent().unwrap_or(Path::new(""));
+        let combination = report.combinations.iter_mut()
+            .find(|combination| combination.compose_file.parent() == Some(build_dir.join(restored_dir).as_path()));
+        if let Some(combination) = combination {
+            combination.env_files_restored.push(build_dir.join(&new_path));
+        }
+    }
+    report.env_files_backed_up = restored.backed_up;
 
-    Ok(())
-}
-
-/// Serialize YAML with proper formatting and clean null values
-fn serialize_yaml_with_proper_indentation(value: &serde_yaml_ng::Value) -> Result<String> {
-    // Use yaml-rust2 for better formatting control
-    let mut out_str = String::new();
-    {
-        let mut emitter = yaml_rust2::YamlEmitter::new(&mut out_str);
+    observer.on_build_phase(BuildPhase::Finish);
+    if executor.config.build.write_gitignore {
+        write_build_gitignore(&executor.config, staging_dir)?;
+    }
+
+    let manifest = CombinationsManifest::from_report(&report, build_cache::config_hash(&executor.config, &executor.paths.project_root)?, &executor.config.build.aliases);
+    match CombinationsManifest::load(build_dir) {
+        Some(previous) if previous.schema < 2 => status!("ℹ The previous build recorded no service inventory; service changes are reported from the next build on"),
+        Some(previous) => report.service_changes = manifest.service_changes(&previous),
+        None => {}
+    }
+    manifest.write(staging_dir)?;
+
+    // Record what we generated so operator edits can be detected by the next build
+    cleaner.write_manifest(staging_dir)
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to write build manifest: {}", e),
+        })?;
+
+    if executor.config.build.write_checksums {
+        let combination_dirs: Vec<&str> = new_structure.iter().map(String::as_str).collect();
+        checksums::write_checksums(staging_dir, &combination_dirs, &ChecksumScope::from_config(&executor.config))?;
+    }
+
+    Ok((report, failures))
+}
+
+/// Warn about services that two of `extensions` define differently, or fail with `fail_on_service_conflicts`.
+/// docker compose layers the files the same way, so every output mode is checked.
+fn check_service_conflicts(executor: &BuildExecutor, extensions: &[String]) -> Result<()> {
+    let files: Vec<String> = extensions.iter()
+        .filter_map(|extension| extension_compose_file(&executor.rust_merger, extension))
+        .map(|file| file.to_string_lossy().to_string())
+        .collect();
+    for conflict in find_service_conflicts(&files) {
+        let error = BuildError::ServiceConflict {
+            service: conflict.service,
+            first: conflict.first,
+            second: conflict.second.clone(),
+            paths: conflict.paths,
+        };
+        if executor.config.build.fail_on_service_conflicts {
+            return Err(error.into());
+        }
+        diagnostics::warn_at(WarningCategory::ServiceConflict, &conflict.second, error.to_string());
+    }
+    Ok(())
+}
+
+/// Name the combination an error occurred in, so the message points at the environment and extensions involved
+fn combination_failed(config: &config::Config, combo: &BuildCombination, source: StackBuilderError) -> StackBuilderError {
+    BuildError::CombinationFailed {
+        output_dir: combo.output_dir.clone(),
+        environment: combo.environment.clone(),
+        extensions: resolve_all_extensions(config, &combo.extensions, &combo.combo_names)
+            .unwrap_or_else(|_| combo.extensions.clone()),
+        source: Box::new(source),
+    }.into()
+}
+
+/// Merge one combination and write its compose files, .env.example and additional files below `output_path`
+fn generate_combination(
+    executor: &BuildExecutor,
+    combo: &BuildCombination,
+    output_path: &Path,
+    staging_dir: &Path,
+    header: Option<&[String]>,
+    writer: &OutputWriter,
+    observer: &dyn BuildObserver,
+) -> Result<CombinationReport> {
+    let build_dir = Path::new(&executor.config.paths.build_dir);
+
+    // Merge compose files
+    observer.on_phase(combo, BuildPhase::Merging);
+    let environment_opt = combo.environment.as_deref();
+    
+    // Resolve all extensions (direct + from combos)
+    let all_extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
+    
+    let project_name = executor.config.build.project_name_template.as_deref()
+        .and_then(|template| combination_project_name(template, executor.config.project.name.as_deref(), combo));
+
+    check_service_conflicts(executor, &all_extensions)?;
+
+    let variables = config::combination_variables(&executor.config, environment_opt, &combo.combo_names);
+    for (name, variable) in &variables {
+        for source in &variable.overridden {
+            status!("ℹ Variable '{}' from {} overrides the value from {}", name, variable.source, source);
+        }
+    }
+    let resolve_env = executor.config.build.resolve_env && !variables.is_empty();
+    let image_tags = config::environment_image_tags(&executor.config, environment_opt);
+
+    let mut overridden_keys = None;
+    let mut counts = None;
+    let mut services = None;
+    let mut required_variables = None;
+    let compose_files = match executor.config.build.output_mode {
+        OutputMode::Layers => {
+            let final_dir = final_output_dir(build_dir, staging_dir, output_path)?;
+            write_compose_layers(executor, combo, &all_extensions, output_path, &final_dir, header, writer)?
+        }
+        OutputMode::Merged if executor.config.build.multi_document => {
+            let mut content = multi_document_compose(executor, combo, &all_extensions, project_name.as_deref())?;
+            status!("✓ Joined compose files into one multi-document file for: {}", combo.output_dir);
+            if resolve_env {
+                content = variables::substitute(&content, &variables);
+            }
+            let content = match combination_header(executor, header, combo, &all_extensions, OutputFormat::Yaml.file_name()) {
+                Some(lines) => format!("{}\n{}", lines.join("\n"), content),
+                None => content,
+            };
+            // A stream of documents has no single JSON equivalent
+            write_compose_outputs(&[OutputFormat::Yaml], output_path, &content, None, writer)?
+        }
+        OutputMode::Merged => {
+            // Choose merger based on configuration. Each combination is merged once, then serialized per output format
+            let (yaml_content, merged_value) = match executor.config.build.yaml_merger {
+                YamlMergerType::Yq => {
+                    // Use yq merger
+                    let mut content = yq_merge_compose_files(&executor.yq_merger, environment_opt, &all_extensions)?;
+                    status!("✓ Used yq merger for: {}", combo.output_dir);
+                    // Parsed once here for the build context check and reused for non-YAML formats
+                    let mut merged: Option<serde_yaml_ng::Value> = serde_yaml_ng::from_str(&content).ok();
+                    if let Some(ref merged) = merged {
+                        warn_relative_build_contexts(combo, merged);
+                    }
+                    if let Some(ref name) = project_name {
+                        match merged {
+                            Some(ref mut merged) => {
+                                set_compose_project_name(merged, name);
+                                content = serialize_yaml_with_proper_indentation(merged);
+                            }
+                            None => diagnostics::warn(WarningCategory::Config, format!("project_name_template was not applied to {}: the yq output could not be parsed", combination_label(&combo.output_dir))),
+                        }
+                    }
+                    if !image_tags.is_empty() {
+                        match merged {
+                            Some(ref mut merged) => {
+                                apply_image_tags(merged, &image_tags);
+                                content = serialize_yaml_with_proper_indentation(merged);
+                            }
+                            None => diagnostics::warn(WarningCategory::Config, format!("image_tags were not applied to {}: the yq output could not be parsed", combination_label(&combo.output_dir))),
+                        }
+                    }
+                    if resolve_env {
+                        // Substituted values may need quoting, so a parsed document is re-emitted rather than edited as text
+                        match merged {
+                            Some(ref mut merged) => {
+                                variables::substitute_value(merged, &variables);
+                                content = serialize_yaml_with_proper_indentation(merged);
+                            }
+                            None => content = variables::substitute(&content, &variables),
+                        }
+                    }
+                    (content, merged)
+                }
+                YamlMergerType::Rust => {
+                    // Use Rust merger directly
+                    // Contexts point into the components; make them relative to where the output will live
+                    let rebased;
+                    let merger = match executor.config.build.rewrite_build_context {
+                        true => {
+                            rebased = executor.rust_merger.clone().with_output_dir(final_output_dir(build_dir, staging_dir, output_path)?);
+                            &rebased
+                        }
+                        false => &executor.rust_merger,
+                    };
+                    let (mut merged, stats) = merge_compose_files(merger, environment_opt, &all_extensions)?;
+
+                    status!("✓ Used Rust merger for: {} ({} keys overridden across {} files)",
+                            combo.output_dir, stats.overridden_keys, stats.files);
+                    overridden_keys = Some(stats.overridden_keys);
+
+                    if !executor.config.build.rewrite_build_context {
+                        warn_relative_build_contexts(combo, &merged);
+                    }
+                    if let Some(ref name) = project_name {
+                        set_compose_project_name(&mut merged, name);
+                    }
+                    apply_image_tags(&mut merged, &image_tags);
+                    if resolve_env {
+                        variables::substitute_value(&mut merged, &variables);
+                    }
+                    (serialize_yaml_with_proper_indentation(&merged), Some(merged))
+                }
+            };
+            counts = merged_value.as_ref().map(ComposeCounts::of);
+            services = merged_value.as_ref().map(ServiceInventory::of);
+            required_variables = merged_value.as_ref().map(|merged| helper_scripts::required_variables([merged]));
+
+            let compose_header = combination_header(executor, header, combo, &all_extensions, OutputFormat::Yaml.file_name());
+            let yaml_content = match compose_header {
+                Some(lines) => format!("{}\n{}", lines.join("\n"), yaml_content),
+                None => yaml_content,
+            };
+
+            write_compose_outputs(
+                &executor.config.build.output_formats,
+                output_path,
+                &yaml_content,
+                merged_value,
+                writer,
+            )?
+        }
+    };
+    let compose_path = compose_files.first().cloned().unwrap_or_else(|| output_path.join(OutputFormat::Yaml.file_name()));
+    if let Some(counts) = counts {
+        status!("{}: {}", combination_label(&combo.output_dir), counts);
+    }
+
+    // Files are staged first; report where they end up
+    let in_build_dir = |path: &Path| build_dir.join(path.strip_prefix(staging_dir).unwrap_or(path));
+    let mut combination_report = CombinationReport {
+        output_dir: combo.output_dir.clone(),
+        environment: combo.environment.clone(),
+        extensions: all_extensions.clone(),
+        combos: combo.combo_names.clone(),
+        compose_file: in_build_dir(&compose_path),
+        merger: executor.config.build.yaml_merger.clone(),
+        overridden_keys,
+        counts,
+        services,
+        files_written: compose_files.iter().map(|path| in_build_dir(path)).collect(),
+        files_copied: Vec::new(),
+        env_files_restored: Vec::new(),
+        warnings: Vec::new(),
+        duration_ms: 0,
+        cached: false,
+    };
+
+    if let Some(ref template) = executor.helper_script_template {
+        // Nothing merged to scan with layers, multi_document or unparsable yq output; the component files are read instead
+        let required_variables = match required_variables {
+            Some(required_variables) => required_variables,
+            None => {
+                let layers = compose_layers(&executor.paths, environment_opt, &all_extensions, executor.config.build.require_base);
+                let documents = layers.iter()
+                    .map(|layer| load_compose_file(&layer.source.to_string_lossy()))
+                    .collect::<Result<Vec<_>>>()?;
+                helper_scripts::required_variables(&documents)
+            }
+        };
+        let compose_file_names: Vec<String> = match executor.config.build.output_mode {
+            OutputMode::Layers => compose_files.iter().skip(1).collect::<Vec<_>>(),
+            OutputMode::Merged => vec![&compose_path],
+        }
+        .into_iter()
+        .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().to_string()))
+        .collect();
+        let project_name = project_name.clone()
+            .or_else(|| combination_project_name(HELPER_SCRIPT_PROJECT_NAME, executor.config.project.name.as_deref(), combo))
+            .unwrap_or_else(|| "stack".to_string());
+
+        for (file_name, command) in HELPER_SCRIPTS {
+            let script = helper_scripts::render_helper_script(
+                template,
+                combination_label(&combo.output_dir),
+                command,
+                &compose_file_names,
+                &project_name,
+                &required_variables,
+            );
+            let script_path = output_path.join(file_name);
+            writer.write_executable(&script_path, script)?;
+            combination_report.files_written.push(in_build_dir(&script_path));
+        }
+        status!("✓ Created helper scripts for: {}", combo.output_dir);
+    }
+
+    // Process .env.example files if enabled
+    observer.on_phase(combo, BuildPhase::Env);
+    let mut variables_written = false;
+    if executor.config.build.copy_env_example {
+        let env_file_path = output_path.join(&executor.config.build.env_output_filename);
+        let environment_opt = combo.environment.as_deref();
         
-        // Convert serde_yaml::Value to yaml_rust2::Yaml
-        let yaml_str = serde_yaml_ng::to_string(value)
-            .map_err(|e| YamlError::SerializationError {
-                details: e.to_string(),
-            })?;
-            
-        let docs = yaml_rust2::YamlLoader::load_from_str(&yaml_str)
-            .map_err(|e| YamlError::SerializationError {
-                details: format!("Failed to parse YAML for formatting: {}", e),
+        // Resolve all extensions for .env merging
+        let all_extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
+        
+        match merge_env_files(&executor.env_merger, environment_opt, &all_extensions) {
+            Ok(mut merged_env) => {
+                // Written to .env itself, the merged file takes the variables; later lines win in docker compose
+                if executor.config.build.env_output_filename == VARIABLES_ENV_FILE {
+                    merged_env.variables.extend(variables.iter().map(|(name, variable)| (name.clone(), variables::env_line(name, &variable.value))));
+                }
+                match combination_header(executor, header, combo, &all_extensions, &executor.config.build.env_source_filename) {
+                    // The provenance block lists the source files itself
+                    Some(lines) if executor.config.build.provenance_header => merged_env.header_comments = lines,
+                    Some(lines) => merged_env.set_generated_header(&lines),
+                    None => {}
+                }
+                // A file with nothing but our header and the sources' comments is only clutter
+                if merged_env.has_variables() {
+                    match write_merged_env(&merged_env, &env_file_path, writer) {
+                        Ok(()) => {
+                            combination_report.files_written.push(in_build_dir(&env_file_path));
+                            variables_written |= executor.config.build.env_output_filename == VARIABLES_ENV_FILE;
+                        }
+                        Err(e) => diagnostics::warn_at(WarningCategory::EnvExample, &env_file_path, format!("Failed to write {} file for {}: {}", executor.config.build.env_output_filename, combo.output_dir, e)),
+                    }
+                } else {
+                    status!("No {} variables found for combination: {}", executor.config.build.env_source_filename, combo.output_dir);
+                }
+            }
+            Err(e) => {
+                diagnostics::warn(WarningCategory::EnvExample, format!("Failed to merge {} files for {}: {}", executor.config.build.env_source_filename, combo.output_dir, e));
+            }
+        }
+    }
+
+    if !variables.is_empty() && !variables_written {
+        let env_path = output_path.join(VARIABLES_ENV_FILE);
+        let mut lines = header.map(<[String]>::to_vec).unwrap_or_default();
+        lines.extend(variables::variable_header_lines(&variables));
+        writer.write(&env_path, variables::variables_env_content(&variables, &lines))?;
+        combination_report.files_written.push(in_build_dir(&env_path));
+        status!("✓ Created {} with {} variables for: {}", VARIABLES_ENV_FILE, variables.len(), combo.output_dir);
+    }
+
+    // Copy additional files if enabled
+    observer.on_phase(combo, BuildPhase::Copying);
+    let file_copier = FileCopier::new(executor.config.clone(), executor.paths.clone())
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to initialize file copier: {}", e),
+        })?;
+
+    // Resolve all extensions for file copying
+    let all_extensions = resolve_all_extensions(&executor.config, &combo.extensions, &combo.combo_names)?;
+    
+    match file_copier.copy_additional_files(
+        combo.environment.as_deref(),
+        &all_extensions,
+        output_path,
+        writer,
+    ) {
+        Ok(copied) => combination_report.files_copied = copied.iter().map(|path| in_build_dir(path)).collect(),
+        Err(e) => diagnostics::warn(WarningCategory::FileCopy, format!("Failed to copy additional files for {}: {}", combo.output_dir, e)),
+    }
+
+    Ok(combination_report)
+}
+
+/// `generated_header` as comment lines with `{combination}` and `{timestamp}` filled in.
+/// Lines that are not comments yet get a `# ` prefix.
+pub fn generated_header_lines(template: &str, combination: &str, timestamp: &str) -> Vec<String> {
+    let combination = if combination.is_empty() { "." } else { combination };
+    template.lines()
+        .map(|line| line.replace("{combination}", combination).replace("{timestamp}", timestamp))
+        .map(|line| match line.trim() {
+            "" => "#".to_string(),
+            trimmed if trimmed.starts_with('#') => line,
+            _ => format!("# {}", line),
+        })
+        .collect()
+}
+
+/// `generated_header` lines followed, with `provenance_header`, by the provenance of the component files named `file_name`
+/// and the combination variables with their levels
+fn combination_header(
+    executor: &BuildExecutor,
+    generated_header: Option<&[String]>,
+    combo: &BuildCombination,
+    extensions: &[String],
+    file_name: &str,
+) -> Option<Vec<String>> {
+    let mut lines = generated_header.map(<[String]>::to_vec).unwrap_or_default();
+    if executor.config.build.provenance_header {
+        let sources = component_sources(&executor.paths, combo.environment.as_deref(), extensions, file_name);
+        lines.extend(provenance_header_lines(combo, extensions, &sources, executor.components_commit.as_deref()));
+        let variables = config::combination_variables(&executor.config, combo.environment.as_deref(), &combo.combo_names);
+        if !variables.is_empty() {
+            lines.extend(variables::variable_header_lines(&variables));
+        }
+    }
+    (!lines.is_empty()).then_some(lines)
+}
+
+/// Comment block naming the stackbuilder version, environment, extensions, combos, the components commit
+/// and `sources` in merge order. It has no timestamp, so unchanged inputs give identical output.
+pub fn provenance_header_lines(combo: &BuildCombination, extensions: &[String], sources: &[String], commit: Option<&str>) -> Vec<String> {
+    let mut lines = vec![format!("# Generated by stackbuilder {}", env!("CARGO_PKG_VERSION"))];
+    if let Some(ref environment) = combo.environment {
+        lines.push(format!("# Environment: {}", environment));
+    }
+    if !extensions.is_empty() {
+        lines.push(format!("# Extensions: {}", extensions.join(", ")));
+    }
+    if !combo.combo_names.is_empty() {
+        lines.push(format!("# Combos: {}", combo.combo_names.join(", ")));
+    }
+    if let Some(commit) = commit {
+        lines.push(format!("# Components commit: {}", commit));
+    }
+    lines.push("# Sources in merge order:".to_string());
+    lines.extend(sources.iter().map(|source| format!("#   {}", source)));
+    lines
+}
+
+/// Existing component files named `file_name` in merge order: base, environment, then each extension
+/// from the first extensions directory that has it. Paths are relative to the project root when inside it.
+fn component_sources(paths: &ResolvedPaths, environment: Option<&str>, extensions: &[String], file_name: &str) -> Vec<String> {
+    let mut files = vec![paths.base_dir.join(file_name)];
+    if let Some(env) = environment {
+        files.push(paths.environments_dir.join(env).join(file_name));
+    }
+    for extension in extensions {
+        if let Some(file) = paths.extensions_dirs.iter().map(|dir| dir.join(extension).join(file_name)).find(|file| file.is_file()) {
+            files.push(file);
+        }
+    }
+
+    files.into_iter()
+        .filter(|file| file.is_file())
+        .map(|file| file.strip_prefix(&paths.project_root).map(Path::to_path_buf).unwrap_or(file))
+        .map(|file| file.to_string_lossy().replace('\\', "/"))
+        .collect()
+}
+
+/// Commit checked out in the git work tree containing `dir`, when git is installed and `dir` is in one
+fn git_commit(dir: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!commit.is_empty()).then_some(commit)
+}
+
+/// Compose project name of a combination from `project_name_template`.
+/// `{env}` is the environment, `{combo}` the extensions or combo part of the output directory (`base` without one)
+/// and `{project}` the project name. The result is lowercased and limited to `[a-z0-9_-]`, starting with a letter or digit.
+pub fn combination_project_name(template: &str, project: Option<&str>, combo: &BuildCombination) -> Option<String> {
+    let env = combo.environment.as_deref().unwrap_or_default();
+    let variant = match combo.output_dir.strip_prefix(env) {
+        Some("") if !env.is_empty() => "",
+        Some(rest) if !env.is_empty() => rest.strip_prefix('/').unwrap_or(&combo.output_dir),
+        _ => &combo.output_dir,
+    };
+    let variant = if variant.is_empty() { "base" } else { variant };
+
+    let name = template
+        .replace("{env}", env)
+        .replace("{combo}", variant)
+        .replace("{project}", project.unwrap_or_default());
+    let name: String = name.to_lowercase().chars()
+        .map(|c| if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-' { c } else { '-' })
+        .collect();
+    let name = name.trim_matches(|c| c == '-' || c == '_');
+
+    if name.is_empty() {
+        diagnostics::warn(WarningCategory::Config, format!("project_name_template '{}' gives an empty project name for {:?}; no name is set", template, combo.output_dir));
+        return None;
+    }
+    Some(name.to_string())
+}
+
+/// Set the top-level `name` of a compose document, as its first key
+fn set_compose_project_name(document: &mut serde_yaml_ng::Value, name: &str) {
+    if let serde_yaml_ng::Value::Mapping(map) = document {
+        map.remove("name");
+        let rest = std::mem::take(map);
+        map.insert("name".into(), name.into());
+        map.extend(rest);
+    }
+}
+
+/// Replace the tag of every service image named in `image_tags` by its service name or its image repository,
+/// keeping the repository. The service name wins over the repository; a digest is replaced as well.
+fn apply_image_tags(document: &mut serde_yaml_ng::Value, image_tags: &BTreeMap<String, String>) {
+    if image_tags.is_empty() {
+        return;
+    }
+    let Some(services) = document.get_mut("services").and_then(serde_yaml_ng::Value::as_mapping_mut) else { return };
+    for (name, service) in services.iter_mut() {
+        let Some(image) = service.get_mut("image") else { continue };
+        let Some(current) = image.as_str() else { continue };
+        let repository = image_repository(current);
+        let tag = name.as_str().and_then(|name| image_tags.get(name)).or_else(|| image_tags.get(repository));
+        if let Some(tag) = tag {
+            *image = format!("{}:{}", repository, tag).into();
+        }
+    }
+}
+
+/// Repository part of an image reference, without its tag and digest, e.g. `registry:5000/app` of `registry:5000/app:1.2`
+fn image_repository(image: &str) -> &str {
+    let image = image.split_once('@').map_or(image, |(repository, _)| repository);
+    let name_start = image.rfind('/').map_or(0, |slash| slash + 1);
+    match image[name_start..].rfind(':') {
+        Some(colon) => &image[..name_start + colon],
+        None => image,
+    }
+}
+
+/// Warn about `image_tags` keys that match no service name or image repository in any combination of their environment.
+/// Combinations without a merged compose file are not inspected.
+fn warn_unmatched_image_tags(config: &config::Config, report: &BuildReport) {
+    let mut environments: Vec<&String> = report.combinations.iter().filter_map(|combination| combination.environment.as_ref()).collect();
+    environments.sort();
+    environments.dedup();
+    for environment in environments {
+        let image_tags = config::environment_image_tags(config, Some(environment));
+        let inventories: Vec<_> = report.combinations.iter()
+            .filter(|combination| combination.environment.as_ref() == Some(environment))
+            .filter_map(|combination| combination.services.as_ref())
+            .collect();
+        if image_tags.is_empty() || inventories.is_empty() {
+            continue;
+        }
+        for key in image_tags.keys() {
+            let matched = inventories.iter().any(|services| {
+                services.contains_key(key) || services.values().any(|service| service.image.as_deref().map(image_repository) == Some(key.as_str()))
+            });
+            if !matched {
+                diagnostics::warn(WarningCategory::Config, format!("image_tags of environment '{}' names '{}', which is neither a service nor an image repository of its combinations", environment, key));
+            }
+        }
+    }
+}
+
+fn current_unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Absolute path `output_path` in the staging directory will have once it replaces the build directory
+fn final_output_dir(build_dir: &Path, staging_dir: &Path, output_path: &Path) -> Result<PathBuf> {
+    let final_dir = build_dir.join(output_path.strip_prefix(staging_dir).unwrap_or(output_path));
+    std::path::absolute(&final_dir)
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: final_dir.clone(),
+            source: e,
+        }.into())
+}
+
+/// Warn about services whose relative build context was written for a component directory
+fn warn_relative_build_contexts(combo: &BuildCombination, merged: &serde_yaml_ng::Value) {
+    for (service, context) in relative_build_contexts(merged) {
+        diagnostics::warn(WarningCategory::BuildContext, format!(
+            "Service '{}' in {:?} uses the relative build context '{}', which may not resolve relative to the output directory; set rewrite_build_context = true to rewrite it against its component",
+            service, combo.output_dir, context
+        ));
+    }
+}
+
+/// Write the merged document of a combination in every configured format, returning the written paths.
+/// yq output is parsed at most once, and only when a non-YAML format needs the document.
+pub fn write_compose_outputs(
+    formats: &[OutputFormat],
+    output_path: &Path,
+    yaml_content: &str,
+    mut merged_value: Option<serde_yaml_ng::Value>,
+    writer: &OutputWriter,
+) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for &format in formats {
+        let content = match format {
+            OutputFormat::Yaml => yaml_content.to_string(),
+            OutputFormat::Json => {
+                if merged_value.is_none() {
+                    merged_value = Some(serde_yaml_ng::from_str(yaml_content)
+                        .map_err(|e| YamlError::serde_error(output_path.display().to_string(), yaml_content, e))?);
+                }
+                let json = serde_json::to_string_pretty(&merged_value)
+                    .map_err(|e| YamlError::SerializationError {
+                        details: format!("Failed to serialize merged compose file to JSON: {}", e),
+                    })?;
+                json + "\n"
+            }
+        };
+
+        let path = output_path.join(format.file_name());
+        writer.write(&path, content)?;
+        status!("✓ Created {}", path.display());
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// Project name baked into the helper scripts when `project_name_template` is not set
+const HELPER_SCRIPT_PROJECT_NAME: &str = "{project}-{env}-{combo}";
+
+/// Script written next to the compose layers with `output_mode = "layers"`
+pub const COMPOSE_SCRIPT_FILE: &str = "compose.sh";
+
+/// A component compose file copied verbatim with `output_mode = "layers"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeLayer {
+    /// Name of the copy in the combination directory
+    pub file_name: String,
+    /// Component the file belongs to, e.g. `base` or `extension:monitoring`
+    pub component: String,
+    pub source: PathBuf,
+}
+
+/// Compose files of a combination in merge order, named `00-base.yml`, `10-env-<environment>.yml`,
+/// then `20-ext-<extension>.yml`, `21-ext-...` in extension order.
+/// A missing base is listed when it is required, so copying it fails like merging would.
+pub fn compose_layers(paths: &ResolvedPaths, environment: Option<&str>, extensions: &[String], require_base: bool) -> Vec<ComposeLayer> {
+    let compose_file = OutputFormat::Yaml.file_name();
+    let mut layers = Vec::new();
+    let base = paths.base_dir.join(compose_file);
+    if require_base || base.is_file() {
+        layers.push(ComposeLayer { file_name: "00-base.yml".to_string(), component: "base".to_string(), source: base });
+    }
+    if let Some(env) = environment {
+        let file = paths.environments_dir.join(env).join(compose_file);
+        if file.is_file() {
+            layers.push(ComposeLayer { file_name: format!("10-env-{}.yml", env), component: format!("environment:{}", env), source: file });
+        }
+    }
+    for (index, extension) in extensions.iter().enumerate() {
+        if let Some(file) = paths.extensions_dirs.iter().map(|dir| dir.join(extension).join(compose_file)).find(|file| file.is_file()) {
+            layers.push(ComposeLayer {
+                file_name: format!("{}-ext-{}.yml", 20 + index, extension),
+                component: format!("extension:{}", extension),
+                source: file,
+            });
+        }
+    }
+    layers
+}
+
+/// Copy the compose files of a combination as layers and write `compose.sh`, which runs
+/// `docker compose` with `COMPOSE_FILE` listing them in merge order. Every layer must parse.
+/// docker compose resolves the paths of every layer against the combination directory, so a layer
+/// whose relative paths would miss their component (see `rebase_relative_paths`) is written with the
+/// paths rewritten for `final_dir` instead of being copied. Returns the script followed by the layers.
+fn write_compose_layers(
+    executor: &BuildExecutor,
+    combo: &BuildCombination,
+    extensions: &[String],
+    output_path: &Path,
+    final_dir: &Path,
+    header: Option<&[String]>,
+    writer: &OutputWriter,
+) -> Result<Vec<PathBuf>> {
+    let layers = compose_layers(&executor.paths, combo.environment.as_deref(), extensions, executor.config.build.require_base);
+    let mut written = Vec::new();
+    for layer in &layers {
+        // Nothing is merged, so a broken layer has to be caught here rather than by docker compose
+        let mut document = load_compose_file(&layer.source.to_string_lossy())?;
+        let rewrite_build_context = executor.config.build.rewrite_build_context;
+        if !rewrite_build_context {
+            warn_relative_build_contexts(combo, &document);
+        }
+        let component_dir = layer.source.parent().unwrap_or(Path::new("."));
+        let component_dir = std::path::absolute(component_dir)
+            .map_err(|e| FileSystemError::DirectoryReadFailed {
+                path: component_dir.to_path_buf(),
+                source: e,
             })?;
-            
-        if let Some(doc) = docs.first() {
-            emitter.dump(doc)
-                .map_err(|e| YamlError::SerializationError {
-                    details: format!("Failed to emit YAML: {}", e),
+        let copied = executor.config.build.copy_additional_files;
+        let dest = output_path.join(&layer.file_name);
+        match rebase_relative_paths(&mut document, &component_dir, final_dir, copied, rewrite_build_context) {
+            0 => writer.copy(&layer.source, &dest)?,
+            rewritten => {
+                writer.write(&dest, serialize_yaml_with_proper_indentation(&document))?;
+                status!("✓ Rewrote {} relative paths of {} for: {}", rewritten, layer.file_name, combo.output_dir);
+            }
+        }
+        written.push(dest);
+    }
+    status!("✓ Copied {} compose layers for: {}", layers.len(), combo.output_dir);
+
+    let mut script = vec!["#!/bin/sh".to_string()];
+    script.extend(combination_header(executor, header, combo, extensions, OutputFormat::Yaml.file_name()).unwrap_or_default());
+    script.push("# Runs docker compose with the layers of this combination in merge order".to_string());
+    script.push("cd \"$(dirname \"$0\")\" || exit 1".to_string());
+    let files: Vec<&str> = layers.iter().map(|layer| layer.file_name.as_str()).collect();
+    script.push(format!("export COMPOSE_FILE=\"{}\"", files.join(":")));
+    script.push("export COMPOSE_PATH_SEPARATOR=\":\"".to_string());
+    let project_name = executor.config.build.project_name_template.as_deref()
+        .and_then(|template| combination_project_name(template, executor.config.project.name.as_deref(), combo));
+    if let Some(name) = project_name {
+        script.push(format!("export COMPOSE_PROJECT_NAME=\"{}\"", name));
+    }
+    script.push(helper_scripts::EXEC_COMPOSE.to_string());
+
+    let script_path = output_path.join(COMPOSE_SCRIPT_FILE);
+    writer.write_executable(&script_path, script.join("\n") + "\n")?;
+    status!("✓ Created {}", script_path.display());
+    written.insert(0, script_path);
+    Ok(written)
+}
+
+/// The compose files of a combination as one YAML stream with a document per file, base first.
+/// Each document starts with `---` and a comment naming its component; every document must parse.
+fn multi_document_compose(executor: &BuildExecutor, combo: &BuildCombination, extensions: &[String], project_name: Option<&str>) -> Result<String> {
+    let layers = compose_layers(&executor.paths, combo.environment.as_deref(), extensions, executor.config.build.require_base);
+    let mut documents = Vec::new();
+    for layer in &layers {
+        let source = layer.source.to_string_lossy();
+        let mut document = load_compose_file(&source)?;
+        warn_relative_build_contexts(combo, &document);
+        // The project name goes into the first document, which is then re-emitted rather than kept as written
+        let content = match project_name {
+            Some(name) if documents.is_empty() => {
+                set_compose_project_name(&mut document, name);
+                serialize_yaml_with_proper_indentation(&document)
+            }
+            _ => fs::read_to_string(&layer.source)
+                .map_err(|e| FileSystemError::FileReadFailed {
+                    path: layer.source.clone(),
+                    source: e,
+                })?,
+        };
+        // The separator is ours; one the content starts with would add an empty document
+        let content = content.trim_start().strip_prefix("---").unwrap_or(&content).trim_matches('\n').to_string();
+        documents.push(format!("---\n# {}\n{}\n", layer.component, content.trim_end()));
+    }
+    Ok(documents.concat())
+}
+
+/// Directory a combination's files are generated into, below `root`
+fn combination_output_path(num_envs: usize, total_variants: usize, combo: &BuildCombination, root: &Path) -> PathBuf {
+    // Special cases for putting file directly in build directory without subfolders:
+    // 1. 1 env + 0 ext + 0 combos
+    // 2. 0 env + 1 total variant (when output_dir is empty)
+    if (num_envs == 1 && total_variants == 0) || combo.output_dir.is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(&combo.output_dir)
+    }
+}
+
+/// File a build would produce for a combination
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedFile {
+    /// Output path including the build directory
+    pub path: PathBuf,
+    /// What the file is generated or copied from
+    pub source: String,
+}
+
+/// Resolve a combination by its output directory, planned or aliased, and list every file the build would write for it
+pub fn list_combination_files(config: &config::Config, paths: &ResolvedPaths, combinations: &[BuildCombination], name: &str) -> Result<Vec<PlannedFile>> {
+    let name = name.trim_matches('/');
+    let output_dir = aliased_output_dir(config, name);
+    let combo = combinations.iter()
+        .find(|combo| combo.output_dir == output_dir)
+        .ok_or_else(|| BuildError::UnknownCombination {
+            name: name.to_string(),
+            available: combinations.iter().map(|combo| combo.output_dir.clone()).collect(),
+        })?;
+
+    let num_envs = config::get_environments_list(config).len();
+    let total_variants = config.build.extensions.as_ref().map_or(0, |e| e.len()) + config::matrix_size(config) + config.build.combos.len();
+    let output_path = combination_output_path(num_envs, total_variants, combo, Path::new(&config.paths.build_dir));
+    let all_extensions = resolve_all_extensions(config, &combo.extensions, &combo.combo_names)?;
+    let mut files: Vec<PlannedFile> = match config.build.output_mode {
+        OutputMode::Merged if config.build.multi_document => vec![PlannedFile {
+            path: output_path.join(OutputFormat::Yaml.file_name()),
+            source: "compose files as documents".to_string(),
+        }],
+        OutputMode::Merged => config.build.output_formats.iter()
+            .map(|format| PlannedFile {
+                path: output_path.join(format.file_name()),
+                source: "merged compose files".to_string(),
+            })
+            .collect(),
+        OutputMode::Layers => {
+            let layers = compose_layers(paths, combo.environment.as_deref(), &all_extensions, config.build.require_base);
+            std::iter::once(PlannedFile {
+                path: output_path.join(COMPOSE_SCRIPT_FILE),
+                source: "compose layer list".to_string(),
+            })
+            .chain(layers.into_iter().map(|layer| PlannedFile {
+                path: output_path.join(layer.file_name),
+                source: layer.component,
+            }))
+            .collect()
+        }
+    };
+
+    if config.build.generate_helper_scripts {
+        files.extend(HELPER_SCRIPTS.iter().map(|(file_name, command)| PlannedFile {
+            path: output_path.join(file_name),
+            source: format!("helper script running docker compose {}", command),
+        }));
+    }
+
+    let variables = config::combination_variables(config, combo.environment.as_deref(), &combo.combo_names);
+    let mut variables_planned = variables.is_empty();
+    if config.build.copy_env_example {
+        let env_merger = EnvMerger::from_paths(paths)
+            .with_require_base(config.build.require_base)
+            .with_source_filename(&config.build.env_source_filename);
+        // Mirrors the build: the file is only written when concatenation yields content
+        if let Ok(merged_env) = merge_env_files(&env_merger, combo.environment.as_deref(), &all_extensions) {
+            let with_variables = config.build.env_output_filename == VARIABLES_ENV_FILE && !variables.is_empty();
+            if merged_env.has_variables() || with_variables {
+                files.push(PlannedFile {
+                    path: output_path.join(&config.build.env_output_filename),
+                    source: format!("concatenated {} files", config.build.env_source_filename),
+                });
+                variables_planned |= with_variables;
+            }
+        }
+    }
+
+    if !variables_planned {
+        files.push(PlannedFile {
+            path: output_path.join(VARIABLES_ENV_FILE),
+            source: "combination variables".to_string(),
+        });
+    }
+
+    let file_copier = FileCopier::new(config.clone(), paths.clone())
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to initialize file copier: {}", e),
+        })?
+        .quiet();
+    let copied = file_copier.plan_additional_files(combo.environment.as_deref(), &all_extensions)
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to plan additional files for {}: {}", combo.output_dir, e),
+        })?;
+    files.extend(copied.into_iter().map(|(relative_path, file_info)| PlannedFile {
+        path: output_path.join(relative_path),
+        source: file_info.source_component,
+    }));
+
+    Ok(files)
+}
+
+/// Print the files one combination would produce without writing anything
+pub fn execute_list_files(args: &BuildArgs, name: &str) -> Result<()> {
+    let project_dir = current_project_dir()?;
+    let mut config = load_build_config(args, &project_dir, config::user_config_path().as_deref())?;
+    add_component_roots(&mut config, args);
+    let paths = config::resolve_paths_in(&mut config, &project_dir);
+    config::validate_config(&config, config::validation_mode(&config, args.fail_fast))?;
+
+    let combinations = match args.components_only {
+        Some(ref extensions) => resolve_components_only_combination(&config, extensions, args.env.as_deref())?,
+        None => determine_build_combinations(&config)?,
+    };
+
+    let files = list_combination_files(&config, &paths, &combinations, name)?;
+    status!("Files for combination '{}':", name.trim_matches('/'));
+    for file in &files {
+        status!("  {} (from {})", file.path.display(), file.source);
+    }
+    status!("{} files, nothing was written", files.len());
+
+    Ok(())
+}
+
+/// What `watch` compares between polls of the project
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputFingerprint {
+    /// Output directory of the combination that was asked for by name
+    pub combination: Option<String>,
+    /// Hash over the build cache input hashes of the combinations
+    pub hash: String,
+}
+
+/// Fingerprint the inputs of the combinations a build with `args` would generate in `project_dir`, or only of
+/// the combination `only` names (planned or aliased output directory). It changes whenever the outputs would.
+pub fn input_fingerprint(args: &BuildArgs, project_dir: &Path, only: Option<&str>) -> Result<InputFingerprint> {
+    let mut config = load_build_config(args, project_dir, config::user_config_path().as_deref())?;
+    apply_build_args(&mut config, args);
+    let paths = config::resolve_paths_in(&mut config, project_dir);
+
+    let combinations = match args.components_only {
+        Some(ref extensions) => resolve_components_only_combination(&config, extensions, args.env.as_deref())?,
+        None if args.use_default_env => resolve_default_environment_combinations(&config)?,
+        None => determine_build_combinations(&config)?,
+    };
+    let selected: Vec<&BuildCombination> = match only {
+        Some(name) => {
+            let output_dir = aliased_output_dir(&config, name.trim_matches('/'));
+            let combo = combinations.iter()
+                .find(|combo| combo.output_dir == output_dir)
+                .ok_or_else(|| BuildError::UnknownCombination {
+                    name: name.to_string(),
+                    available: combinations.iter().map(|combo| combo.output_dir.clone()).collect(),
                 })?;
+            vec![combo]
         }
+        None => combinations.iter().collect(),
+    };
+
+    let mut hasher = Sha256::new();
+    for combo in &selected {
+        let extensions = resolve_all_extensions(&config, &combo.extensions, &combo.combo_names)?;
+        hasher.update(build_cache::input_hash(&config, &paths, combo, &extensions, None)?);
+    }
+    Ok(InputFingerprint {
+        combination: only.map(|_| selected[0].output_dir.clone()),
+        hash: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// The configuration a build with `args` would use, after included files, command line overrides
+/// and path resolution, with variable values redacted
+pub fn effective_config(mut config: config::Config, args: &BuildArgs, project_dir: &Path) -> config::Config {
+    apply_build_args(&mut config, args);
+    ResolvedPaths::new(&config, project_dir).apply_to(&mut config);
+    config.redacted()
+}
+
+/// Print the configuration a build would use to stdout without building anything
+pub fn execute_print_config(args: &BuildArgs, format: ConfigFormat) -> Result<()> {
+    let project_dir = current_project_dir()?;
+    let config = effective_config(load_build_config(args, &project_dir, config::user_config_path().as_deref())?, args, &project_dir);
+    let roots = config::component_roots(&config);
+    let text = match format {
+        ConfigFormat::Toml => {
+            let text = toml::to_string_pretty(&config).map_err(ConfigError::toml_serialize_error)?;
+            let text = match config.user_defaults {
+                Some(ref defaults) => mark_user_defaults(&text, defaults),
+                None => text,
+            };
+            let roots: Vec<String> = roots.iter().map(|root| root.display().to_string()).collect();
+            format!("# Components roots in search order: {}\n{}", roots.join(", "), text)
+        }
+        ConfigFormat::Json => {
+            let mut value = serde_json::to_value(&config)
+                .map_err(|e| BuildError::BuildProcessFailed {
+                    details: format!("Failed to serialize configuration: {}", e),
+                })?;
+            value["components_roots"] = serde_json::json!(roots);
+            if let Some(ref defaults) = config.user_defaults {
+                value["user_config"] = serde_json::json!({ "path": defaults.path, "keys": defaults.keys });
+            }
+            serde_json::to_string_pretty(&value).unwrap_or_default() + "\n"
+        }
+    };
+    print!("{}", text);
+    Ok(())
+}
+
+/// `text`, the TOML of a configuration, with a comment on every `[build]` value taken from the user configuration
+pub fn mark_user_defaults(text: &str, defaults: &config::UserDefaults) -> String {
+    let mut lines = vec![format!(
+        "# Values marked \"user config\" come from {}; all others from the project configuration or the defaults",
+        defaults.path.display()
+    )];
+    let mut in_build = false;
+    for line in text.lines() {
+        if line.starts_with('[') {
+            in_build = line == "[build]";
+        }
+        match line.split_once(" = ") {
+            Some((key, _)) if in_build && defaults.keys.iter().any(|user_key| user_key == key) => lines.push(format!("{}  # user config", line)),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// The configuration `--config` names, stackbuilder.toml of `project_dir` without it.
+/// Relative component paths resolve against `project_dir` wherever the configuration was read from.
+fn load_build_config(args: &BuildArgs, project_dir: &Path, user_config: Option<&Path>) -> Result<config::Config> {
+    let config_path = args.config.as_deref().unwrap_or(Path::new(config::CONFIG_FILE));
+    config::load_config_arg(config_path, args.format, project_dir, user_config)
+}
+
+/// The current directory, which CLI commands treat as the project directory
+fn current_project_dir() -> Result<PathBuf> {
+    std::env::current_dir()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to determine current directory: {}", e),
+        }.into())
+}
+
+/// Create the build directory cleaner for the configured build directory
+fn create_cleaner(config: &config::Config, paths: &ResolvedPaths, force_clean: bool, force: bool) -> Result<BuildCleaner> {
+    Ok(BuildCleaner::new(
+        &paths.build_dir,
+        config.build.preserve_env_files,
+        config::preserved_env_file_patterns(config),
+        paths.backup_dir.to_string_lossy().to_string(),
+    )
+    .with_project_dir(&paths.project_root)
+    .with_force_clean(force_clean)
+    .with_backup_format(config.build.backup_format)
+    .with_keep_modified_files(!force)
+    .with_read_only_dirs(paths.read_only_dirs())
+    .with_aliases(&config.build.aliases))
+}
+
+/// Header of the .gitignore generated in the build directory
+const GITIGNORE_HEADER: &str = "# Generated by stackbuilder from env_file_patterns and backup_dir - do not edit, changes are overwritten";
+
+/// Compute the content of the build directory .gitignore, keeping secrets out of version control
+pub fn build_gitignore_content(config: &config::Config) -> String {
+    let mut lines = vec![GITIGNORE_HEADER.to_string()];
+
+    lines.push("# Preserved environment files".to_string());
+    lines.extend(config.build.env_file_patterns.iter().cloned());
+
+    // Backups contain the same secrets when they are kept inside the build directory
+    let absolute = |path: &str| fs::canonicalize(path).or_else(|_| std::path::absolute(path)).ok();
+    let build_dir = absolute(&config.paths.build_dir);
+    let backup_dir = absolute(&config.build.backup_dir);
+    if let (Some(build_dir), Some(backup_dir)) = (build_dir, backup_dir) {
+        if let Ok(relative) = backup_dir.strip_prefix(&build_dir) {
+            if !relative.as_os_str().is_empty() {
+                lines.push("# Backup directory".to_string());
+                lines.push(format!("/{}/", relative.to_string_lossy().replace('\\', "/")));
+            }
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Write the build directory .gitignore into the output directory, rewriting it only when its content changes
+fn write_build_gitignore(config: &config::Config, output_dir: &Path) -> Result<()> {
+    let gitignore_path = output_dir.join(".gitignore");
+    let content = build_gitignore_content(config);
+
+    if fs::read_to_string(&gitignore_path).is_ok_and(|existing| existing == content) {
+        return Ok(());
+    }
+
+    fs::write(&gitignore_path, content)
+        .map_err(|e| FileSystemError::FileWriteFailed {
+            path: gitignore_path.clone(),
+            source: e,
+        })?;
+    status!("✓ Created {}", gitignore_path.display());
+
+    Ok(())
+}
+
+/// Fail when a warning of a category listed in `strict_categories` was emitted (`--strict`)
+fn check_strict_warnings(config: &config::Config) -> Result<()> {
+    let mut categories: Vec<WarningCategory> = diagnostics::collected().into_iter()
+        .map(|warning| warning.category)
+        .filter(|category| config.build.strict_categories.contains(category))
+        .collect();
+    if categories.is_empty() {
+        return Ok(());
+    }
+
+    let count = categories.len();
+    categories.sort();
+    categories.dedup();
+    Err(BuildError::StrictWarnings {
+        count,
+        categories: categories.iter().map(|category| category.to_string()).collect(),
+    }.into())
+}
+
+/// Acquire the build lock unless disabled
+fn acquire_build_lock(config: &config::Config, project_dir: &Path, no_lock: bool) -> Result<Option<BuildLock>> {
+    if no_lock {
+        diagnostics::warn(WarningCategory::Lock, "Build lock disabled, concurrent builds may corrupt the build directory");
+        return Ok(None);
     }
+
+    BuildLock::acquire(project_dir.join(BUILD_LOCK_FILE), Duration::from_secs(config.build.lock_timeout)).map(Some)
+}
+
+/// Clean the build directory without building, backing up preserved .env files
+pub fn execute_clean(args: &CleanArgs) -> Result<()> {
+    let config = config::load_config()?;
+    let paths = ResolvedPaths::new(&config, &current_project_dir()?);
+    let build_dir = paths.build_dir.as_path();
+    let cleaner = create_cleaner(&config, &paths, args.force_clean, args.force)?;
+
+    if args.dry_run {
+        let cleanup = cleaner.plan_cleanup()
+            .map_err(|e| BuildError::BuildProcessFailed {
+                details: format!("Failed to plan build directory cleanup: {}", e),
+            })?;
+        // No new structure is generated, so every preserved file falls through to backup
+        let restore = cleaner.plan_restore(&cleanup.preserved_files, &[]);
+        cleaner.print_plan(&cleanup, &restore);
+        status!("Dry run completed, nothing was deleted");
+        return Ok(());
+    }
+
+    if let Some(reason) = cleaner.unsafe_cleanup_reason() {
+        return Err(BuildError::UnsafeBuildDirectory {
+            path: build_dir.to_path_buf(),
+            reason,
+        }.into());
+    }
+
+    let _lock = acquire_build_lock(&config, &paths.project_root, args.no_lock)?;
+
+    cleaner.clean_build_directory()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to clean build directory: {}", e),
+        })?;
+
+    cleaner.restore_env_files(&[])
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to back up .env files: {}", e),
+        })?;
+
+    status!("Build directory cleaned: {}", build_dir.display());
+    Ok(())
+}
+
+/// Serialize YAML with proper formatting and clean null values
+fn serialize_yaml_with_proper_indentation(value: &serde_yaml_ng::Value) -> String {
+    // Emit straight from the merged value instead of re-parsing serialized output
+    let out_str = yaml_emitter::emit_yaml(value);
     
     // Clean up null values (~ symbols)
-    let yaml_string = clean_yaml_null_values(out_str);
-    
-    Ok(yaml_string)
+    clean_yaml_null_values(out_str)
 }
 
 /// Clean YAML string from null values (~ symbols) in volumes sections
@@ -636,11 +2538,258 @@ fn clean_yaml_null_values(yaml_content: String) -> String {
     cleaned2.to_string()
 }
 
+#[derive(Parser, Debug, Default, Clone)]
+pub struct BuildArgs {
+    /// Clean the build directory even if it looks like a project, repository or home directory
+    #[arg(long)]
+    pub force_clean: bool,
+
+    /// Build base plus exactly these extensions (comma-separated) into the build root, ignoring configured environments and combos
+    #[arg(long, value_name = "EXTENSIONS", value_delimiter = ',')]
+    pub components_only: Option<Vec<String>>,
+
+    /// Environment to apply together with --components-only
+    #[arg(long, value_name = "ENV", requires = "components_only")]
+    pub env: Option<String>,
+
+    /// Show what the build directory cleanup would delete, preserve, restore and back up without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Do not acquire the build lock (allows concurrent builds to interleave)
+    #[arg(long)]
+    pub no_lock: bool,
+
+    /// Overwrite generated files that were modified since the last build instead of preserving them,
+    /// and regenerate every combination instead of reusing unchanged ones from the build cache
+    #[arg(long)]
+    pub force: bool,
+
+    /// Write a JSON build report (combinations, outputs, warnings, merger) to this path
+    #[arg(long, value_name = "PATH")]
+    pub summary_file: Option<PathBuf>,
+
+    /// Pack the build directory into this tar.gz archive after a successful build
+    #[arg(long, value_name = "FILE")]
+    pub archive: Option<PathBuf>,
+
+    /// List every file the combination (e.g. dev/monitoring) would produce without writing anything
+    #[arg(long, value_name = "COMBINATION", conflicts_with_all = ["dry_run", "summary_file"])]
+    pub list_files: Option<String>,
+
+    /// Write generated files on a small pool of IO threads; speeds up builds producing many small files
+    #[arg(long)]
+    pub parallel_io: bool,
+
+    /// Check that every image referenced by the generated compose files exists, with image_check_command
+    #[arg(long)]
+    pub check_images: bool,
+
+    /// Fail without replacing the build directory when warnings of the categories in strict_categories were emitted
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Stop validating the configuration at the first problem instead of reporting all of them
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Keep building the other combinations when one fails and report the failures at the end
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Substitute the combination variables into the generated compose files; overrides resolve_env
+    #[arg(long)]
+    pub resolve_env: bool,
+
+    /// Do not run pre_build_command from the [build] section
+    #[arg(long)]
+    pub skip_pre_build: bool,
+
+    /// Build only the combinations of default_environment from the [build] section
+    #[arg(long, visible_alias = "single", conflicts_with = "components_only")]
+    pub use_default_env: bool,
+
+    /// Set the top-level name: of every generated compose file from this template, e.g. "{env}-{combo}"; overrides project_name_template
+    #[arg(long, value_name = "TEMPLATE")]
+    pub compose_project_name: Option<String>,
+
+    /// Further components root searched after components_dir, e.g. a vendored read-only tree; repeatable
+    #[arg(long = "components-dir", value_name = "DIR", value_delimiter = ',')]
+    pub components_dirs: Vec<PathBuf>,
+
+    /// Keep the output directories of combinations that are no longer configured instead of pruning them
+    #[arg(long)]
+    pub keep_stale: bool,
+
+    /// Print nothing but errors; warnings still count for --strict and are written to --summary-file
+    #[arg(long, short = 'q', conflicts_with = "report")]
+    pub quiet: bool,
+
+    /// Print the build report as JSON to stdout instead of progress and the summary table
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "list_files")]
+    pub report: Option<ReportFormat>,
+
+    /// Print how long each phase and combination took after the build
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Write the timings of the build as JSON to this path, relative to the project directory
+    #[arg(long, value_name = "PATH")]
+    pub timings_file: Option<PathBuf>,
+
+    /// How warnings, errors and combinations are marked in the output; `github` when GITHUB_ACTIONS=true
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub output_format: Option<LogFormat>,
+
+    /// Print the effective configuration, including included files and the flags above, to stdout and exit without building
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "toml",
+          conflicts_with_all = ["list_files", "report", "dry_run", "summary_file"])]
+    pub print_config: Option<ConfigFormat>,
+
+    /// Configuration file to build instead of stackbuilder.toml; '-' reads it from stdin, e.g. `generate-config | stackbuilder build --config -`
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Syntax of the configuration given with --config
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ConfigFormat::Toml, requires = "config")]
+    pub format: ConfigFormat,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Print the configuration a build with the same flags would use, like `build --print-config`
+    Show(ConfigShowArgs),
+}
+
+/// The `build` flags that change the effective configuration
+#[derive(Parser, Debug, Default)]
+pub struct ConfigShowArgs {
+    /// Print JSON instead of TOML
+    #[arg(long)]
+    pub json: bool,
+
+    /// Configuration file to show instead of stackbuilder.toml; '-' reads it from stdin
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Syntax of the configuration given with --config
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ConfigFormat::Toml, requires = "config")]
+    pub format: ConfigFormat,
+
+    /// Further components root searched after components_dir; repeatable
+    #[arg(long = "components-dir", value_name = "DIR", value_delimiter = ',')]
+    pub components_dirs: Vec<PathBuf>,
+
+    /// Top-level name template of the generated compose files; overrides project_name_template
+    #[arg(long, value_name = "TEMPLATE")]
+    pub compose_project_name: Option<String>,
+
+    /// Keep building the other combinations when one fails
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Substitute the combination variables into the generated compose files
+    #[arg(long)]
+    pub resolve_env: bool,
+
+    /// Stop validating the configuration at the first problem
+    #[arg(long)]
+    pub fail_fast: bool,
+}
+
+impl ConfigShowArgs {
+    /// The `build` arguments with the same effect on the configuration
+    pub fn build_args(&self) -> BuildArgs {
+        BuildArgs {
+            config: self.config.clone(),
+            format: self.format,
+            components_dirs: self.components_dirs.clone(),
+            compose_project_name: self.compose_project_name.clone(),
+            keep_going: self.keep_going,
+            resolve_env: self.resolve_env,
+            fail_fast: self.fail_fast,
+            ..Default::default()
+        }
+    }
+}
+
+/// Run a `config` subcommand
+pub fn execute_config(args: &ConfigArgs) -> Result<()> {
+    match args.command {
+        ConfigCommand::Show(ref show) => {
+            let format = if show.json { ConfigFormat::Json } else { ConfigFormat::Toml };
+            execute_print_config(&show.build_args(), format)
+        }
+    }
+}
+
+/// Output format of `build --report`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Json,
+}
+
+/// Output format of `build --output-format`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Progress lines, or bars on a terminal
+    Text,
+    /// GitHub Actions workflow commands: `::warning`/`::error` annotations and a `::group::` per combination
+    Github,
+}
+
+impl BuildArgs {
+    /// The `--output-format` given, otherwise `github` inside GitHub Actions and `text` elsewhere
+    pub fn log_format(&self) -> LogFormat {
+        self.output_format.unwrap_or_else(|| match std::env::var("GITHUB_ACTIONS").as_deref() {
+            Ok("true") => LogFormat::Github,
+            _ => LogFormat::Text,
+        })
+    }
+}
+
+#[derive(Parser, Debug, Default, Clone)]
+pub struct CleanArgs {
+    /// Show what would be deleted and backed up without deleting anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Clean the build directory even if it looks like a project, repository or home directory
+    #[arg(long)]
+    pub force_clean: bool,
+
+    /// Do not acquire the build lock
+    #[arg(long)]
+    pub no_lock: bool,
+
+    /// Delete generated files that were modified since the last build instead of backing them up
+    #[arg(long)]
+    pub force: bool,
+}
+
 /// Structure representing a build combination
 #[derive(Debug)]
-struct BuildCombination {
+pub struct BuildCombination {
     pub environment: Option<String>,
     pub extensions: Vec<String>,
     pub combo_names: Vec<String>,
     pub output_dir: String,
+}
+
+impl BuildCombination {
+    /// What the combination is defined by and made of, e.g. `combo 'full' (environment: dev, extensions: a, b)`
+    pub fn definition(&self) -> String {
+        let kind = match (self.combo_names.is_empty(), self.extensions.is_empty()) {
+            (false, _) => format!("combo '{}'", self.combo_names.join(", ")),
+            (true, false) => format!("extension '{}'", self.extensions.join(&EXTENSION_COMBINATION_SEPARATOR.to_string())),
+            (true, true) => "base".to_string(),
+        };
+        format!("{} ({})", kind, combination_layers(&self.environment, &self.extensions))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::build::BuildCombination;
+use crate::build_report::{CombinationReport, ComposeCounts, ServiceInventory};
+use crate::checksums::{collect_files, hash_file};
+use crate::config::{Config, YamlMergerType};
+use crate::diagnostics::{self, Warning, WarningCategory};
+use crate::error::{Result, BuildError, FileSystemError};
+use crate::output_writer::{self, OutputWriter};
+use crate::paths::ResolvedPaths;
+
+/// Default location of the build cache, relative to the project directory
+pub const BUILD_CACHE_FILE: &str = ".stackbuilder/cache.json";
+
+/// Input and output hashes of the combinations of the last successful build.
+/// A combination whose inputs hash the same and whose outputs are untouched is copied instead of regenerated.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BuildCache {
+    /// stackbuilder version that wrote the cache; any other version starts from scratch
+    version: String,
+    /// Merger that produced the cached outputs; switching mergers starts from scratch
+    merger: YamlMergerType,
+    /// Cached combinations keyed by output directory
+    combinations: BTreeMap<String, CachedCombination>,
+}
+
+/// Inputs hash and outputs of one cached combination
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedCombination {
+    pub input_hash: String,
+    /// Generated compose file, relative to the build directory
+    pub compose_file: String,
+    pub overridden_keys: Option<usize>,
+    #[serde(default)]
+    pub counts: Option<ComposeCounts>,
+    #[serde(default)]
+    pub services: Option<BTreeMap<String, ServiceInventory>>,
+    pub files_written: Vec<CachedFile>,
+    pub files_copied: Vec<CachedFile>,
+    /// Warnings emitted while the combination was generated, replayed whenever it is reused
+    pub warnings: Vec<Warning>,
+}
+
+/// An output file relative to the build directory with its SHA-256 hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedFile {
+    pub path: String,
+    pub sha256: String,
+}
+
+impl BuildCache {
+    /// An empty cache for the current version and `merger`
+    pub fn new(merger: YamlMergerType) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            merger,
+            combinations: BTreeMap::new(),
+        }
+    }
+
+    /// Load the cache at `path`. A missing cache, or one written by another version or for another merger,
+    /// yields an empty cache; an unreadable one is reported and ignored.
+    pub fn load(path: &Path, merger: YamlMergerType) -> Self {
+        let empty = Self::new(merger);
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return empty,
+            Err(e) => {
+                diagnostics::warn_at(WarningCategory::Cache, path, format!("Failed to read build cache, rebuilding everything: {}", e));
+                return empty;
+            }
+        };
+
+        match serde_json::from_str::<Self>(&content) {
+            Ok(cache) if cache.version == empty.version && cache.merger == empty.merger => cache,
+            Ok(_) => {
+                status!("Build cache was written by another version or merger, rebuilding everything");
+                empty
+            }
+            Err(e) => {
+                diagnostics::warn_at(WarningCategory::Cache, path, format!("Build cache is corrupt, rebuilding everything: {}", e));
+                empty
+            }
+        }
+    }
+
+    /// Write the cache to `path`, creating its directory
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                    path: parent.to_path_buf(),
+                    source: e,
+                })?;
+        }
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| BuildError::BuildProcessFailed {
+                details: format!("Failed to serialize build cache: {}", e),
+            })?;
+        fs::write(path, content)
+            .map_err(|e| FileSystemError::FileWriteFailed {
+                path: path.to_path_buf(),
+                source: e,
+            }.into())
+    }
+
+    /// The cached combination at `output_dir`, if it was built from inputs hashing to `input_hash`
+    pub fn lookup(&self, output_dir: &str, input_hash: &str) -> Option<&CachedCombination> {
+        self.combinations.get(output_dir).filter(|cached| cached.input_hash == input_hash)
+    }
+
+    pub fn insert(&mut self, output_dir: &str, cached: CachedCombination) {
+        self.combinations.insert(output_dir.to_string(), cached);
+    }
+}
+
+impl CachedCombination {
+    /// Record the outputs and warnings of a combination just generated into `staging_dir`.
+    /// The report names the outputs by where they end up in `build_dir`.
+    pub fn record(input_hash: String, report: &CombinationReport, build_dir: &Path, staging_dir: &Path) -> Result<Self> {
+        let relative = |path: &Path| path.strip_prefix(build_dir).unwrap_or(path).to_path_buf();
+        let hash_all = |paths: &[PathBuf]| -> Result<Vec<CachedFile>> {
+            paths.iter()
+                .map(|path| {
+                    let relative = relative(path);
+                    Ok(CachedFile {
+                        sha256: hash_file(&staging_dir.join(&relative))?,
+                        path: relative.to_string_lossy().to_string(),
+                    })
+                })
+                .collect()
+        };
+
+        Ok(Self {
+            input_hash,
+            compose_file: relative(&report.compose_file).to_string_lossy().to_string(),
+            overridden_keys: report.overridden_keys,
+            counts: report.counts,
+            services: report.services.clone(),
+            files_written: hash_all(&report.files_written)?,
+            files_copied: hash_all(&report.files_copied)?,
+            warnings: diagnostics::collected().into_iter()
+                .filter(|warning| warning.combination.as_deref() == Some(report.output_dir.as_str()))
+                .collect(),
+        })
+    }
+
+    /// Whether every recorded output still exists in `build_dir` with its recorded content
+    pub fn outputs_match(&self, build_dir: &Path) -> bool {
+        self.files().all(|file| {
+            hash_file(&build_dir.join(&file.path)).is_ok_and(|hash| hash == file.sha256)
+        })
+    }
+
+    /// Carry the recorded outputs over from `build_dir` into `staging_dir`, reporting each to `writer`.
+    /// Copied additional files are hardlinked, so those linked from the object store (`dedup_copies = "hardlink"`)
+    /// stay linked to it; generated files, and files that cannot be linked, are copied with their modification time.
+    pub fn restore_into(&self, build_dir: &Path, staging_dir: &Path, writer: &OutputWriter) -> Result<()> {
+        let files = self.files_written.iter().map(|file| (file, false))
+            .chain(self.files_copied.iter().map(|file| (file, true)));
+        for (file, link) in files {
+            let target = staging_dir.join(&file.path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                        path: parent.to_path_buf(),
+                        source: e,
+                    })?;
+            }
+            let source = build_dir.join(&file.path);
+            if !(link && fs::hard_link(&source, &target).is_ok()) {
+                fs::copy(&source, &target)
+                    .and_then(|_| output_writer::keep_modified_time(&source, &target))
+                    .map_err(|e| FileSystemError::FileWriteFailed {
+                        path: target.clone(),
+                        source: e,
+                    })?;
+            }
+            writer.written(&target);
+        }
+        Ok(())
+    }
+
+    /// Report of the combination as if it had just been generated into `build_dir`
+    pub fn report(&self, combo: &BuildCombination, extensions: Vec<String>, merger: YamlMergerType, build_dir: &Path) -> CombinationReport {
+        let in_build_dir = |files: &[CachedFile]| files.iter().map(|file| build_dir.join(&file.path)).collect();
+        CombinationReport {
+            output_dir: combo.output_dir.clone(),
+            environment: combo.environment.clone(),
+            extensions,
+            combos: combo.combo_names.clone(),
+            compose_file: build_dir.join(&self.compose_file),
+            merger,
+            overridden_keys: self.overridden_keys,
+            counts: self.counts,
+            services: self.services.clone(),
+            files_written: in_build_dir(&self.files_written),
+            files_copied: in_build_dir(&self.files_copied),
+            env_files_restored: Vec::new(),
+            warnings: Vec::new(),
+            duration_ms: 0,
+            cached: true,
+        }
+    }
+
+    fn files(&self) -> impl Iterator<Item = &CachedFile> {
+        self.files_written.iter().chain(&self.files_copied)
+    }
+}
+
+/// SHA-256 of the configuration with its resolved paths relative to `project_root` again, so a project hashes
+/// the same in every checkout. serde_json objects are sorted by key, so map fields hash the same on every run.
+pub fn config_hash(config: &Config, project_root: &Path) -> Result<String> {
+    let relative = |path: &mut String| {
+        if let Ok(relative) = Path::new(path.as_str()).strip_prefix(project_root) {
+            *path = relative.to_string_lossy().to_string();
+        }
+    };
+    let mut config = config.clone();
+    relative(&mut config.paths.components_dir);
+    config.paths.components_dirs.iter_mut().for_each(relative);
+    relative(&mut config.paths.base_dir);
+    relative(&mut config.paths.environments_dir);
+    config.paths.extensions_dirs.iter_mut().for_each(relative);
+    relative(&mut config.paths.build_dir);
+    relative(&mut config.build.backup_dir);
+
+    let config_value = serde_json::to_value(&config)
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to hash configuration: {}", e),
+        })?;
+    Ok(format!("{:x}", Sha256::digest(config_value.to_string().as_bytes())))
+}
+
+/// Hash over everything that determines a combination's outputs: the stackbuilder version, the configuration,
+/// the combination itself, the components commit shown in provenance headers, a custom helper script template
+/// and the content of every file in its base, environment and extension directories
+pub fn input_hash(
+    config: &Config,
+    paths: &ResolvedPaths,
+    combo: &BuildCombination,
+    extensions: &[String],
+    components_commit: Option<&str>,
+) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let mut field = |label: &str, value: &str| {
+        hasher.update(label.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.as_bytes());
+        hasher.update([0]);
+    };
+
+    field("version", env!("CARGO_PKG_VERSION"));
+    field("config", &config_hash(config, &paths.project_root)?);
+    field("output_dir", &combo.output_dir);
+    field("environment", combo.environment.as_deref().unwrap_or_default());
+    field("extensions", &extensions.join(","));
+    field("components_commit", components_commit.unwrap_or_default());
+    // A custom helper script template lives outside the components
+    if let Some(template) = config.build.helper_script_template.as_deref().filter(|_| config.build.generate_helper_scripts) {
+        let path = paths.project_root.join(template);
+        field("helper_script_template", &hash_file(&path)?);
+    }
+
+    // The same directories the mergers and the file copier read from; the first extensions dir that has it wins
+    let mut component_dirs = vec![paths.base_dir.clone()];
+    if let Some(env) = &combo.environment {
+        component_dirs.push(paths.environments_dir.join(env));
+    }
+    for extension in extensions {
+        if let Some(dir) = paths.extensions_dirs.iter().map(|dir| dir.join(extension)).find(|dir| dir.is_dir()) {
+            component_dirs.push(dir);
+        }
+    }
+
+    for dir in component_dirs {
+        field("component", &dir.to_string_lossy());
+        if !dir.is_dir() {
+            continue;
+        }
+        let mut files = Vec::new();
+        collect_files(&dir, &dir, &mut files)?;
+        files.sort();
+        for file in files {
+            field(&file.to_string_lossy(), &hash_file(&dir.join(&file))?);
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_discards_other_version_and_merger() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(BUILD_CACHE_FILE);
+        let mut cache = BuildCache::new(YamlMergerType::Rust);
+        cache.insert("dev", CachedCombination {
+            input_hash: "abc".to_string(),
+            compose_file: "dev/docker-compose.yml".to_string(),
+            overridden_keys: None,
+            counts: None,
+            services: None,
+            files_written: Vec::new(),
+            files_copied: Vec::new(),
+            warnings: Vec::new(),
+        });
+        cache.save(&path).unwrap();
+
+        assert!(BuildCache::load(&path, YamlMergerType::Rust).lookup("dev", "abc").is_some());
+        assert!(BuildCache::load(&path, YamlMergerType::Rust).lookup("dev", "def").is_none());
+        assert!(BuildCache::load(&path, YamlMergerType::Yq).lookup("dev", "abc").is_none());
+
+        cache.version = "0.0.0-other".to_string();
+        cache.save(&path).unwrap();
+        assert_eq!(BuildCache::load(&path, YamlMergerType::Rust), BuildCache::new(YamlMergerType::Rust));
+    }
+
+    #[test]
+    fn test_load_ignores_corrupt_cache() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("cache.json");
+        fs::write(&path, "{not json").unwrap();
+
+        let diagnostics = crate::diagnostics::Diagnostics::collect();
+        assert_eq!(BuildCache::load(&path, YamlMergerType::Rust), BuildCache::new(YamlMergerType::Rust));
+        let warnings = diagnostics.finish();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].category, WarningCategory::Cache);
+    }
+}
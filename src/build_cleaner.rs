@@ -1,8 +1,15 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::build_report::COMBINATIONS_MANIFEST_FILE;
+use crate::config::BackupFormat;
+use crate::diagnostics::{self, WarningCategory};
+use crate::output_writer::keep_modified_time;
+use crate::variables::{self, VARIABLES_ENV_MARKER};
 
 /// Structure for managing build directory cleaning with .env file preservation
 pub struct BuildCleaner {
@@ -16,6 +23,20 @@ pub struct BuildCleaner {
     backup_dir: PathBuf,
     /// In-memory storage for .env files during build process
     preserved_files: std::cell::RefCell<Option<Vec<PreservedEnvFile>>>,
+    /// Project directory (containing stackbuilder.toml) that must never be wiped
+    project_dir: Option<PathBuf>,
+    /// Skip safety checks before wiping the build directory
+    force_clean: bool,
+    /// Storage format for new backups
+    backup_format: BackupFormat,
+    /// Preserve generated files that were modified since the last build
+    keep_modified_files: bool,
+    /// Components directories that are never written to, even with `force_clean`
+    read_only_dirs: Vec<PathBuf>,
+    /// Carry output directories of combinations that are no longer configured over into the new build
+    keep_stale: bool,
+    /// Planned output directories mapped to their `[build.aliases]` alias
+    aliases: BTreeMap<String, String>,
 }
 
 /// Represents a preserved .env file with its original location
@@ -31,13 +52,89 @@ pub struct PreservedEnvFile {
     pub extensions: Vec<String>,
 }
 
+/// Marker file written into build directories created by stackbuilder
+pub const BUILD_MARKER_FILE: &str = ".stackbuilder-build";
+
+/// Confidence of a mapping that relocates a .env file between the build root and a base subfolder
+const RELOCATED_MAPPING_CONFIDENCE: f32 = 0.8;
+
+/// Minimum mapping confidence required to restore a preserved file instead of backing it up
+const MIN_RESTORE_CONFIDENCE: f32 = 0.8;
+
+/// Manifest recording hashes of every file stackbuilder generated into the build directory
+pub const BUILD_MANIFEST_FILE: &str = ".stackbuilder-manifest.json";
+
+/// Hashes of generated files, keyed by path relative to the build directory
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BuildManifest {
+    /// SHA-256 hex digest per generated file
+    pub files: BTreeMap<String, String>,
+}
+
+/// Name of the metadata file stored in every backup directory
+pub const BACKUP_METADATA_FILE: &str = "metadata.json";
+
+/// Prefix of timestamped backup directory names
+pub const BACKUP_DIR_PREFIX: &str = "backup_";
+
+/// Extension of single-file compressed backups
+pub const BACKUP_ARCHIVE_EXTENSION: &str = ".tar.gz";
+
+/// Metadata describing the contents of a single backup directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupMetadata {
+    /// Unix timestamp (seconds) when the backup was created
+    pub created_at: u64,
+    /// Files stored in the backup
+    pub files: Vec<BackupEntry>,
+}
+
+/// A single file stored in a backup directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+    /// Relative path from build directory where file was found
+    pub original_path: PathBuf,
+    /// Name of the stored copy inside the backup directory
+    pub backup_file: String,
+    /// Environment name if detected from path
+    pub environment: Option<String>,
+    /// Extension names if detected from path
+    pub extensions: Vec<String>,
+}
+
+/// On-disk metadata formats (older backups stored a bare list of files)
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredBackupMetadata {
+    Current(BackupMetadata),
+    Legacy(Vec<PreservedEnvFile>),
+}
+
 /// Result of scanning for .env files
 #[derive(Debug)]
 pub struct EnvFileScanResult {
     /// All found .env files with their metadata
     pub files: Vec<PreservedEnvFile>,
-    /// Total number of files found
-    pub count: usize,
+}
+
+/// Planned result of cleaning the build directory
+#[derive(Debug)]
+pub struct CleanupPlan {
+    /// Reason the cleanup would be refused by the safety checks, if any
+    pub unsafe_reason: Option<String>,
+    /// Files that would be deleted, relative to the build directory
+    pub files_to_delete: Vec<PathBuf>,
+    /// .env files that would be preserved in memory
+    pub preserved_files: Vec<PreservedEnvFile>,
+}
+
+/// Planned or performed restoration of preserved .env files
+#[derive(Debug, Default)]
+pub struct RestorePlan {
+    /// Files that would be restored as (original path, new path), relative to the build directory
+    pub restored: Vec<(PathBuf, PathBuf)>,
+    /// Files that would fall through to the backup directory
+    pub backed_up: Vec<PathBuf>,
 }
 
 /// Mapping between old and new paths for .env file restoration
@@ -65,63 +162,261 @@ impl BuildCleaner {
             env_file_patterns,
             backup_dir: PathBuf::from(backup_dir),
             preserved_files: std::cell::RefCell::new(None),
+            project_dir: None,
+            force_clean: false,
+            backup_format: BackupFormat::default(),
+            keep_modified_files: true,
+            read_only_dirs: Vec::new(),
+            keep_stale: false,
+            aliases: BTreeMap::new(),
+        }
+    }
+
+    /// Set the project directory that the build directory must never equal or contain
+    pub fn with_project_dir<P: AsRef<Path>>(mut self, project_dir: P) -> Self {
+        self.project_dir = Some(project_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Allow wiping the build directory even if safety checks fail
+    pub fn with_force_clean(mut self, force_clean: bool) -> Self {
+        self.force_clean = force_clean;
+        self
+    }
+
+    /// Set the storage format used for new backups
+    pub fn with_backup_format(mut self, backup_format: BackupFormat) -> Self {
+        self.backup_format = backup_format;
+        self
+    }
+
+    /// Preserve (restore or back up) generated files modified since the last build instead of deleting them
+    pub fn with_keep_modified_files(mut self, keep_modified_files: bool) -> Self {
+        self.keep_modified_files = keep_modified_files;
+        self
+    }
+
+    /// Set the components directories the build and backup directories must stay out of
+    pub fn with_read_only_dirs(mut self, read_only_dirs: Vec<PathBuf>) -> Self {
+        self.read_only_dirs = read_only_dirs;
+        self
+    }
+
+    /// Restore files of a planned output directory into its alias once the alias is configured
+    pub fn with_aliases(mut self, aliases: &BTreeMap<String, String>) -> Self {
+        self.aliases = aliases.iter()
+            .map(|(name, alias)| (name.trim_matches('/').to_string(), alias.trim_matches('/').to_string()))
+            .collect();
+        self
+    }
+
+    /// Keep the output directories of combinations that are no longer configured instead of pruning them
+    pub fn with_keep_stale(mut self, keep_stale: bool) -> Self {
+        self.keep_stale = keep_stale;
+        self
+    }
+
+    /// Whether stale output directories are kept
+    pub fn keeps_stale(&self) -> bool {
+        self.keep_stale
+    }
+
+    /// Copy the directory `relative_dir` of the current build directory into the staging directory as it is
+    pub fn carry_over(&self, staging_path: &Path, relative_dir: &str) -> Result<()> {
+        let source = self.build_path.join(relative_dir);
+        let mut files = Vec::new();
+        collect_files_recursive(&source, &source, &mut files)?;
+        for file in files {
+            let target = staging_path.join(relative_dir).join(&file);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            }
+            let source = source.join(&file);
+            fs::copy(&source, &target)
+                .and_then(|_| keep_modified_time(&source, &target))
+                .with_context(|| format!("Failed to copy {} into the new build", source.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Check whether wiping the build directory could destroy user data.
+    /// Returns the reason when the cleanup must be refused.
+    pub fn unsafe_cleanup_reason(&self) -> Option<String> {
+        // Components may be shared or read-only, so --force-clean does not override this
+        let read_only_dirs: Vec<_> = self.read_only_dirs.iter().map(|dir| crate::config::absolute_path(dir)).collect();
+        if let Some(dir) = crate::config::read_only_overlap(&read_only_dirs, &crate::config::absolute_path(&self.build_path)) {
+            return Some(format!("it overlaps the read-only components directory '{}'", dir.display()));
+        }
+        if let Some(dir) = crate::config::read_only_overlap(&read_only_dirs, &crate::config::absolute_path(&self.backup_dir)) {
+            return Some(format!("its backup directory overlaps the read-only components directory '{}'", dir.display()));
+        }
+
+        if self.force_clean || !self.build_path.exists() {
+            return None;
+        }
+
+        let build_path = self.build_path.canonicalize().unwrap_or_else(|_| self.build_path.clone());
+
+        if build_path.parent().is_none() {
+            return Some("it is the filesystem root".to_string());
+        }
+
+        let home_dir = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+        if let Some(home) = home_dir.map(PathBuf::from) {
+            if home.canonicalize().unwrap_or(home) == build_path {
+                return Some("it is the home directory".to_string());
+            }
+        }
+
+        if let Some(ref project_dir) = self.project_dir {
+            let project_dir = project_dir.canonicalize().unwrap_or_else(|_| project_dir.clone());
+            if project_dir.starts_with(&build_path) {
+                return Some(format!("it contains the project directory '{}'", project_dir.display()));
+            }
+        }
+
+        if build_path.join(".git").is_dir() {
+            return Some("it contains a .git directory".to_string());
+        }
+
+        if build_path.join("stackbuilder.toml").exists() {
+            return Some("it contains a stackbuilder.toml file".to_string());
+        }
+
+        if !build_path.join(BUILD_MARKER_FILE).exists() && !is_empty_dir(&build_path) {
+            return Some(format!("it was not created by stackbuilder (no {} marker)", BUILD_MARKER_FILE));
         }
+
+        None
     }
 
     /// Main function to clean build directory with .env preservation
     pub fn clean_build_directory(&self) -> Result<()> {
-        if !self.preserve_env_files {
-            println!("Env file preservation disabled, performing standard cleanup");
+        if !self.preserve_env_files && !self.keep_modified_files {
+            status!("Env file preservation disabled, performing standard cleanup");
             return self.standard_cleanup();
         }
 
-        println!("Starting intelligent build directory cleanup with .env preservation");
+        status!("Starting intelligent build directory cleanup with .env preservation");
 
-        // Step 1: Scan for .env files before cleanup
-        let scan_result = self.scan_env_files()
-            .context("Failed to scan for .env files")?;
+        // Step 1: Scan for .env files and modified generated files before cleanup
+        let preserved_files = self.collect_preserved_files()?;
 
-        if scan_result.count == 0 {
-            println!("No .env files found, performing standard cleanup");
+        if preserved_files.is_empty() {
+            status!("No .env files found, performing standard cleanup");
             return self.standard_cleanup();
         }
 
-        println!("Found {} .env files to preserve", scan_result.count);
+        status!("Found {} files to preserve", preserved_files.len());
 
         // Step 2: Store .env files in memory only (no backup to disk yet)
-        self.store_env_files_in_memory(&scan_result.files);
+        self.store_env_files_in_memory(&preserved_files);
 
         // Step 3: Clean build directory
         self.standard_cleanup()
             .context("Failed to clean build directory")?;
 
-        println!("✓ Build directory cleaned, .env files preserved in memory for restoration");
+        status!("✓ Build directory cleaned, .env files preserved in memory for restoration");
         
         Ok(())
     }
 
-    /// Restore preserved .env files to new build structure
-    pub fn restore_env_files(&self, new_structure: &[String]) -> Result<()> {
-        if !self.preserve_env_files {
+    /// Prepare a fresh staging directory next to the build directory and preserve .env files in memory.
+    /// The real build directory is left untouched until `commit_staging` swaps it in.
+    pub fn prepare_staging(&self) -> Result<PathBuf> {
+        let preserved_files = self.collect_preserved_files()?;
+        if !preserved_files.is_empty() {
+            status!("Found {} files to preserve", preserved_files.len());
+            self.store_env_files_in_memory(&preserved_files);
+        }
+
+        let staging_path = self.sibling_path(&format!(".tmp-{}", std::process::id()));
+        if staging_path.exists() {
+            fs::remove_dir_all(&staging_path)
+                .with_context(|| format!("Failed to remove stale staging directory: {}", staging_path.display()))?;
+        }
+
+        fs::create_dir_all(&staging_path)
+            .with_context(|| format!("Failed to create staging directory: {}", staging_path.display()))?;
+        write_build_marker(&staging_path)?;
+        status!("✓ Created staging directory: {}", staging_path.display());
+
+        Ok(staging_path)
+    }
+
+    /// Replace the build directory with a fully generated staging directory
+    pub fn commit_staging(&self, staging_path: &Path) -> Result<()> {
+        if let Some(reason) = self.unsafe_cleanup_reason() {
+            anyhow::bail!("Refusing to replace build directory '{}': {}", self.build_path.display(), reason);
+        }
+
+        if !self.build_path.exists() {
+            fs::rename(staging_path, &self.build_path)
+                .with_context(|| format!("Failed to move staging directory to: {}", self.build_path.display()))?;
+            status!("✓ Build directory ready: {}", self.build_path.display());
             return Ok(());
         }
 
+        // Swap via rename so the previous output stays complete until the new one is in place
+        let old_path = self.sibling_path(&format!(".old-{}", std::process::id()));
+        if fs::rename(&self.build_path, &old_path).is_ok() {
+            if let Err(e) = fs::rename(staging_path, &self.build_path) {
+                let _ = fs::rename(&old_path, &self.build_path);
+                return Err(e).with_context(|| format!("Failed to move staging directory to: {}", self.build_path.display()));
+            }
+            if let Err(e) = fs::remove_dir_all(&old_path) {
+                diagnostics::warn_at(WarningCategory::Cleanup, &old_path, format!("Failed to remove previous build output {}: {}", old_path.display(), e));
+            }
+        } else {
+            // Renaming can fail e.g. for mount points - fall back to remove and rename
+            fs::remove_dir_all(&self.build_path)
+                .with_context(|| format!("Failed to remove build directory: {}", self.build_path.display()))?;
+            fs::rename(staging_path, &self.build_path)
+                .with_context(|| format!("Failed to move staging directory to: {}", self.build_path.display()))?;
+        }
+
+        status!("✓ Replaced build directory: {}", self.build_path.display());
+        Ok(())
+    }
+
+    /// Remove a staging directory after a failed build, leaving the build directory untouched
+    pub fn discard_staging(&self, staging_path: &Path) {
+        if let Err(e) = fs::remove_dir_all(staging_path) {
+            diagnostics::warn_at(WarningCategory::Cleanup, staging_path, format!("Failed to remove staging directory {}: {}", staging_path.display(), e));
+        }
+        *self.preserved_files.borrow_mut() = None;
+    }
+
+    /// Restore preserved .env files to new build structure
+    pub fn restore_env_files(&self, new_structure: &[String]) -> Result<RestorePlan> {
+        self.restore_env_files_into(&self.build_path, new_structure)
+    }
+
+    /// Restore preserved .env files to new build structure under the given output root
+    /// and return which files were restored and which were backed up
+    pub fn restore_env_files_into(&self, output_root: &Path, new_structure: &[String]) -> Result<RestorePlan> {
+        let mut outcome = RestorePlan::default();
+        if !self.preserve_env_files && !self.keep_modified_files {
+            return Ok(outcome);
+        }
+
         // Get preserved files from memory
         let preserved_files_opt = self.preserved_files.borrow().clone();
         let preserved_files = match preserved_files_opt {
             Some(files) => files,
             None => {
-                println!("No .env files were preserved, skipping restoration");
-                return Ok(());
+                status!("No .env files were preserved, skipping restoration");
+                return Ok(outcome);
             }
         };
 
         if preserved_files.is_empty() {
-            println!("No preserved .env files to restore");
-            return Ok(());
+            status!("No preserved .env files to restore");
+            return Ok(outcome);
         }
 
-        println!("Restoring preserved .env files to new build structure");
+        status!("Restoring preserved .env files to new build structure");
 
         // Generate path mappings
         let mappings = self.generate_path_mappings(&preserved_files, new_structure)
@@ -132,23 +427,25 @@ impl BuildCleaner {
         let mut failed_files = Vec::new();
 
         for preserved_file in &preserved_files {
-            let restore_result = self.restore_single_file(preserved_file, &mappings);
+            let restore_result = self.restore_single_file(output_root, preserved_file, &mappings);
             
             match restore_result {
                 Ok(RestoreResult::Restored(path)) => {
-                    println!("✓ Restored .env file to: {}", path.display());
+                    status!("✓ Restored .env file to: {}", path.display());
                     restored_count += 1;
+                    let new_path = path.strip_prefix(output_root).unwrap_or(&path).to_path_buf();
+                    outcome.restored.push((preserved_file.original_path.clone(), new_path));
                 }
                 Ok(RestoreResult::SkippedNoMatch) => {
-                    println!("ℹ Skipped .env file (no matching structure): {}", preserved_file.original_path.display());
+                    status!("ℹ Skipped .env file (no matching structure): {}", preserved_file.original_path.display());
                     failed_files.push(preserved_file.clone());
                 }
                 Ok(RestoreResult::SkippedConflict) => {
-                    println!("⚠ Skipped .env file (content conflict): {}", preserved_file.original_path.display());
+                    diagnostics::warn_at(WarningCategory::Cleanup, &preserved_file.original_path, format!("Skipped .env file (content conflict): {}", preserved_file.original_path.display()));
                     failed_files.push(preserved_file.clone());
                 }
                 Err(e) => {
-                    println!("✗ Failed to restore .env file from {}: {}",
+                    status!("✗ Failed to restore .env file from {}: {}",
                             preserved_file.original_path.display(), e);
                     failed_files.push(preserved_file.clone());
                 }
@@ -157,20 +454,176 @@ impl BuildCleaner {
 
         // Only create backup if some files couldn't be restored
         if !failed_files.is_empty() {
-            println!("Creating backup for {} files that couldn't be restored", failed_files.len());
+            status!("Creating backup for {} files that couldn't be restored", failed_files.len());
             self.create_backup_for_failed_files(&failed_files)
                 .context("Failed to create backup for failed files")?;
+            outcome.backed_up = failed_files.iter().map(|file| file.original_path.clone()).collect();
         }
 
-        println!("Restoration completed: {} restored successfully", restored_count);
+        status!("Restoration completed: {} restored successfully", restored_count);
         if !failed_files.is_empty() {
-            println!("ℹ {} files backed up to: {}", failed_files.len(), self.backup_dir.display());
+            status!("ℹ {} files backed up to: {}", failed_files.len(), self.backup_dir.display());
         }
 
         // Clear memory storage
         *self.preserved_files.borrow_mut() = None;
 
-        Ok(())
+        Ok(outcome)
+    }
+
+    /// Plan-only variant of `clean_build_directory`: report what would be deleted and preserved without touching anything
+    pub fn plan_cleanup(&self) -> Result<CleanupPlan> {
+        let mut files_to_delete = Vec::new();
+        if self.build_path.exists() {
+            collect_files_recursive(&self.build_path, &self.build_path, &mut files_to_delete)
+                .context("Failed to list build directory contents")?;
+            files_to_delete.sort();
+        }
+
+        let preserved_files = self.collect_preserved_files()?;
+
+        Ok(CleanupPlan {
+            unsafe_reason: self.unsafe_cleanup_reason(),
+            files_to_delete,
+            preserved_files,
+        })
+    }
+
+    /// Plan-only variant of `restore_env_files`: report where each preserved file would go for the given structure
+    pub fn plan_restore(&self, preserved_files: &[PreservedEnvFile], new_structure: &[String]) -> RestorePlan {
+        let mut plan = RestorePlan::default();
+
+        for file in preserved_files {
+            let mapping = self.find_best_path_mapping(file, new_structure);
+            if mapping.confidence >= MIN_RESTORE_CONFIDENCE {
+                plan.restored.push((mapping.old_path, mapping.new_path));
+            } else {
+                plan.backed_up.push(mapping.old_path);
+            }
+        }
+
+        plan
+    }
+
+    /// Print a cleanup and restoration plan in human-readable form
+    pub fn print_plan(&self, cleanup: &CleanupPlan, restore: &RestorePlan) {
+        status!("Dry run for build directory: {}", self.build_path.display());
+
+        if let Some(ref reason) = cleanup.unsafe_reason {
+            status!("⚠ Cleanup would be refused: {}", reason);
+        }
+
+        if cleanup.files_to_delete.is_empty() {
+            status!("Nothing to delete");
+        } else {
+            status!("Would delete {} files:", cleanup.files_to_delete.len());
+            for path in &cleanup.files_to_delete {
+                status!("  - {}", path.display());
+            }
+        }
+
+        if !cleanup.preserved_files.is_empty() {
+            status!("Would preserve {} .env files in memory:", cleanup.preserved_files.len());
+            for file in &cleanup.preserved_files {
+                status!("  - {}", file.original_path.display());
+            }
+        }
+
+        for (old_path, new_path) in &restore.restored {
+            status!("Would restore: {} -> {}", old_path.display(), new_path.display());
+        }
+
+        if !restore.backed_up.is_empty() {
+            status!("Would back up {} files to {}:", restore.backed_up.len(), self.backup_dir.display());
+            for path in &restore.backed_up {
+                status!("  - {}", path.display());
+            }
+        }
+    }
+
+    /// Collect .env files and operator-modified generated files that must survive cleanup
+    fn collect_preserved_files(&self) -> Result<Vec<PreservedEnvFile>> {
+        let mut files = if self.preserve_env_files {
+            self.scan_env_files()
+                .context("Failed to scan for .env files")?
+                .files
+        } else {
+            Vec::new()
+        };
+
+        if self.keep_modified_files {
+            for file in self.scan_modified_files().context("Failed to compare build directory with manifest")? {
+                if !files.iter().any(|f| f.original_path == file.original_path) {
+                    files.push(file);
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Find generated files whose content no longer matches the manifest of the last build
+    pub fn scan_modified_files(&self) -> Result<Vec<PreservedEnvFile>> {
+        let manifest_path = self.build_path.join(BUILD_MANIFEST_FILE);
+        if !manifest_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let content = fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read manifest: {}", manifest_path.display()))?;
+        let manifest: BuildManifest = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse manifest: {}", manifest_path.display()))?;
+
+        let mut modified = Vec::new();
+        for (relative_path, expected_hash) in &manifest.files {
+            let path = self.build_path.join(relative_path);
+            if !path.is_file() || hash_file(&path)? == *expected_hash {
+                continue;
+            }
+
+            match fs::read_to_string(&path) {
+                Ok(content) => {
+                    diagnostics::warn_at(WarningCategory::Cleanup, relative_path, format!("Generated file was modified since the last build, preserving it: {}", relative_path));
+                    let (environment, extensions) = self.analyze_env_file_path(Path::new(relative_path));
+                    modified.push(PreservedEnvFile {
+                        original_path: PathBuf::from(relative_path),
+                        content,
+                        environment,
+                        extensions,
+                    });
+                }
+                Err(_) => {
+                    diagnostics::warn_at(WarningCategory::Cleanup, relative_path, format!("Generated file was modified since the last build but is not text, it will be replaced: {}", relative_path));
+                }
+            }
+        }
+
+        Ok(modified)
+    }
+
+    /// Record hashes of all generated files in the output directory, skipping preserved .env files
+    pub fn write_manifest(&self, output_dir: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        collect_files_recursive(output_dir, output_dir, &mut files)
+            .context("Failed to list generated files")?;
+
+        let mut manifest = BuildManifest::default();
+        for relative_path in files {
+            let name = relative_path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            // The combinations manifest is rewritten on every build, edits to it are not worth preserving
+            let combinations_manifest = relative_path == Path::new(COMBINATIONS_MANIFEST_FILE);
+            if name == BUILD_MARKER_FILE || name == BUILD_MANIFEST_FILE || combinations_manifest || self.is_env_file(&relative_path) {
+                continue;
+            }
+            let hash = hash_file(&output_dir.join(&relative_path))?;
+            manifest.files.insert(relative_path.to_string_lossy().replace('\\', "/"), hash);
+        }
+
+        let manifest_path = output_dir.join(BUILD_MANIFEST_FILE);
+        let json = serde_json::to_string_pretty(&manifest)
+            .context("Failed to serialize build manifest")?;
+        fs::write(&manifest_path, json)
+            .with_context(|| format!("Failed to write manifest: {}", manifest_path.display()))
     }
 
     /// Scan build directory for .env files
@@ -178,16 +631,15 @@ impl BuildCleaner {
         let mut files = Vec::new();
 
         if !self.build_path.exists() {
-            return Ok(EnvFileScanResult { files, count: 0 });
+            return Ok(EnvFileScanResult { files });
         }
 
         self.scan_directory_recursive(&self.build_path, &self.build_path, &mut files)
             .context("Failed to scan build directory recursively")?;
 
-        let count = files.len();
-        println!("Scanned build directory, found {} .env files", count);
+        status!("Scanned build directory, found {} .env files", files.len());
 
-        Ok(EnvFileScanResult { files, count })
+        Ok(EnvFileScanResult { files })
     }
 
     /// Recursively scan directory for .env files
@@ -215,6 +667,11 @@ impl BuildCleaner {
                 
                 let content = fs::read_to_string(&path)
                     .with_context(|| format!("Failed to read .env file: {}", path.display()))?;
+                // Written from the combination variables; the build generates it again
+                if content.starts_with(VARIABLES_ENV_MARKER) {
+                    continue;
+                }
+                let content = variables::hand_written_part(&content).to_string();
 
                 let (environment, extensions) = self.analyze_env_file_path(relative_path);
 
@@ -225,7 +682,7 @@ impl BuildCleaner {
                     extensions: extensions.clone(),
                 });
 
-                println!("Found .env file: {} (env: {:?}, ext: {:?})",
+                status!("Found .env file: {} (env: {:?}, ext: {:?})",
                         relative_path.display(), environment, extensions);
             }
         }
@@ -281,41 +738,110 @@ impl BuildCleaner {
     /// Store .env files in memory for temporary preservation during build
     fn store_env_files_in_memory(&self, files: &[PreservedEnvFile]) {
         *self.preserved_files.borrow_mut() = Some(files.to_vec());
-        println!("✓ Stored {} .env files in memory for restoration", files.len());
+        status!("✓ Stored {} .env files in memory for restoration", files.len());
     }
 
     /// Create backup only for files that couldn't be restored
     fn create_backup_for_failed_files(&self, files: &[PreservedEnvFile]) -> Result<()> {
-        let backup_path = self.get_backup_path();
+        let created_at = current_timestamp();
+
+        match self.backup_format {
+            BackupFormat::Dir => self.create_backup_dir(files, created_at),
+            BackupFormat::TarGz => self.create_backup_archive(files, created_at),
+        }
+    }
+
+    /// Write a backup directory with mangled file names and metadata
+    fn create_backup_dir(&self, files: &[PreservedEnvFile], created_at: u64) -> Result<()> {
+        let backup_path = self.backup_path_for(created_at);
         
         // Create backup directory
         fs::create_dir_all(&backup_path)
             .with_context(|| format!("Failed to create backup directory: {}", backup_path.display()))?;
 
-        // Save metadata
-        let metadata_path = backup_path.join("metadata.json");
-        let metadata_json = serde_json::to_string_pretty(files)
-            .context("Failed to serialize .env files metadata")?;
-        
-        fs::write(&metadata_path, metadata_json)
-            .with_context(|| format!("Failed to write metadata file: {}", metadata_path.display()))?;
-
         // Save files with full path as filename (replacing / and \ with _)
+        let mut entries: Vec<BackupEntry> = Vec::new();
         for file in files.iter() {
-            let safe_filename = file.original_path.to_string_lossy()
+            let mut safe_filename = file.original_path.to_string_lossy()
                 .replace(['/', '\\'], "_");
+            // Mangled names can collide (e.g. "a_b/.env" and "a/b_.env"), keep them unique
+            if entries.iter().any(|e| e.backup_file == safe_filename) {
+                safe_filename = format!("{}.{}", safe_filename, entries.len());
+            }
             let backup_file_path = backup_path.join(&safe_filename);
             
             fs::write(&backup_file_path, &file.content)
                 .with_context(|| format!("Failed to backup .env file: {}", backup_file_path.display()))?;
             
-            println!("  Backed up: {} -> {}", file.original_path.display(), safe_filename);
+            status!("  Backed up: {} -> {}", file.original_path.display(), safe_filename);
+
+            entries.push(BackupEntry {
+                original_path: file.original_path.clone(),
+                backup_file: safe_filename,
+                environment: file.environment.clone(),
+                extensions: file.extensions.clone(),
+            });
         }
 
-        println!("✓ Created backup for {} .env files: {}", files.len(), backup_path.display());
+        // Save metadata - the recorded original paths are the source of truth for restoration
+        let metadata = BackupMetadata {
+            created_at,
+            files: entries,
+        };
+        let metadata_path = backup_path.join(BACKUP_METADATA_FILE);
+        let metadata_json = serde_json::to_string_pretty(&metadata)
+            .context("Failed to serialize .env files metadata")?;
+        
+        fs::write(&metadata_path, metadata_json)
+            .with_context(|| format!("Failed to write metadata file: {}", metadata_path.display()))?;
+
+        status!("✓ Created backup for {} .env files: {}", files.len(), backup_path.display());
         Ok(())
     }
 
+    /// Write a single tar.gz backup keeping original relative paths, plus metadata
+    fn create_backup_archive(&self, files: &[PreservedEnvFile], created_at: u64) -> Result<()> {
+        let archive_path = self.backup_archive_path_for(created_at);
+
+        fs::create_dir_all(&self.backup_dir)
+            .with_context(|| format!("Failed to create backup directory: {}", self.backup_dir.display()))?;
+
+        let archive_file = fs::File::create(&archive_path)
+            .with_context(|| format!("Failed to create backup archive: {}", archive_path.display()))?;
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(archive_file, flate2::Compression::default()));
+
+        let mut entries: Vec<BackupEntry> = Vec::new();
+        for file in files {
+            let archive_name = file.original_path.to_string_lossy().replace('\\', "/");
+            append_archive_entry(&mut builder, &archive_name, file.content.as_bytes(), created_at)
+                .with_context(|| format!("Failed to backup .env file: {}", file.original_path.display()))?;
+
+            status!("  Backed up: {}", file.original_path.display());
+
+            entries.push(BackupEntry {
+                original_path: file.original_path.clone(),
+                backup_file: archive_name,
+                environment: file.environment.clone(),
+                extensions: file.extensions.clone(),
+            });
+        }
+
+        let metadata = BackupMetadata {
+            created_at,
+            files: entries,
+        };
+        let metadata_json = serde_json::to_string_pretty(&metadata)
+            .context("Failed to serialize .env files metadata")?;
+        append_archive_entry(&mut builder, BACKUP_METADATA_FILE, metadata_json.as_bytes(), created_at)
+            .context("Failed to write metadata to backup archive")?;
+
+        builder.into_inner()
+            .and_then(|encoder| encoder.finish())
+            .with_context(|| format!("Failed to finish backup archive: {}", archive_path.display()))?;
+
+        status!("✓ Created backup for {} .env files: {}", files.len(), archive_path.display());
+        Ok(())
+    }
 
     /// Generate path mappings from old to new structure
     fn generate_path_mappings(
@@ -330,36 +856,40 @@ impl BuildCleaner {
             mappings.push(best_mapping);
         }
 
-        println!("Generated {} path mappings for .env restoration", mappings.len());
+        status!("Generated {} path mappings for .env restoration", mappings.len());
         Ok(mappings)
     }
 
-    /// Find best path mapping for a preserved .env file - only restore to exact original structure
+    /// Find best path mapping for a preserved .env file. Files are restored to their exact original
+    /// directory; a .env file whose combination moved between the build root and a base subfolder is relocated.
     fn find_best_path_mapping(&self, file: &PreservedEnvFile, new_structure: &[String]) -> PathMapping {
+        let expected_dir = file.original_path.parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default();
+
         // Try to find exact match in new structure
-        for new_path_str in new_structure {
-            let expected_dir = if let Some(parent) = file.original_path.parent() {
-                parent.to_string_lossy().to_string()
-            } else {
-                String::new()
+        if new_structure.contains(&expected_dir) {
+            return PathMapping {
+                old_path: file.original_path.clone(),
+                new_path: self.path_in_output_dir(file, &expected_dir),
+                confidence: 1.0, // Exact match
+            };
+        }
+
+        // The combination got an alias since the file was written
+        if let Some(alias) = self.aliases.get(&expected_dir).filter(|alias| new_structure.contains(alias)) {
+            let new_path = self.path_in_output_dir(file, alias);
+            status!("ℹ Combination {} is now written to its alias {}, restoring {} to {}",
+                    expected_dir, alias, file.original_path.display(), new_path.display());
+            return PathMapping {
+                old_path: file.original_path.clone(),
+                new_path,
+                confidence: 1.0,
             };
-            
-            // Check if this new path matches the expected directory structure
-            if new_path_str == &expected_dir || (expected_dir.is_empty() && new_path_str.is_empty()) {
-                let filename = file.original_path.file_name()
-                    .unwrap_or_default().to_string_lossy();
-                let new_path = if new_path_str.is_empty() {
-                    PathBuf::from(filename.as_ref())
-                } else {
-                    PathBuf::from(new_path_str).join(filename.as_ref())
-                };
-                
-                return PathMapping {
-                    old_path: file.original_path.clone(),
-                    new_path,
-                    confidence: 1.0, // Exact match
-                };
-            }
+        }
+
+        if let Some(mapping) = self.find_relocated_path_mapping(file, &expected_dir, new_structure) {
+            return mapping;
         }
         
         // No exact match found - file will remain in backup
@@ -370,10 +900,52 @@ impl BuildCleaner {
         }
     }
 
+    /// Map a .env file across the single-combination <-> multi-combination transition:
+    /// root -> the only base-equivalent output directory, and base-equivalent directory -> root
+    fn find_relocated_path_mapping(&self, file: &PreservedEnvFile, expected_dir: &str, new_structure: &[String]) -> Option<PathMapping> {
+        if !self.is_env_file(&file.original_path) {
+            return None;
+        }
+
+        let target_dir = if expected_dir.is_empty() {
+            // Root-level file without environment/extension hints moves to the base-equivalent combination
+            if file.environment.is_some() || !file.extensions.is_empty() {
+                return None;
+            }
+            let mut candidates = new_structure.iter().filter(|dir| is_base_output_dir(dir));
+            match (candidates.next(), candidates.next()) {
+                (Some(dir), None) => dir.clone(),
+                _ => return None,
+            }
+        } else if is_base_output_dir(expected_dir) && new_structure.iter().any(|dir| dir.is_empty()) {
+            // Base-equivalent file moves back to the build root
+            String::new()
+        } else {
+            return None;
+        };
+
+        Some(PathMapping {
+            old_path: file.original_path.clone(),
+            new_path: self.path_in_output_dir(file, &target_dir),
+            confidence: RELOCATED_MAPPING_CONFIDENCE,
+        })
+    }
+
+    /// Path of a preserved file's name inside an output directory of the new structure
+    fn path_in_output_dir(&self, file: &PreservedEnvFile, output_dir: &str) -> PathBuf {
+        let filename = file.original_path.file_name().unwrap_or_default();
+        if output_dir.is_empty() {
+            PathBuf::from(filename)
+        } else {
+            PathBuf::from(output_dir).join(filename)
+        }
+    }
+
 
     /// Restore a single .env file - only to exact original location, no fallbacks in build
     fn restore_single_file(
         &self,
+        output_root: &Path,
         file: &PreservedEnvFile,
         mappings: &[PathMapping]
     ) -> Result<RestoreResult> {
@@ -382,12 +954,12 @@ impl BuildCleaner {
             .find(|m| m.old_path == file.original_path)
             .ok_or_else(|| anyhow::anyhow!("No mapping found for file: {}", file.original_path.display()))?;
 
-        // Only restore if we have high confidence (exact match)
-        if mapping.confidence < 1.0 {
+        // Only restore exact matches and unambiguous relocations
+        if mapping.confidence < MIN_RESTORE_CONFIDENCE {
             return Ok(RestoreResult::SkippedNoMatch);
         }
 
-        let target_path = self.build_path.join(&mapping.new_path);
+        let target_path = output_root.join(&mapping.new_path);
 
         // Create parent directories
         if let Some(parent) = target_path.parent() {
@@ -396,47 +968,207 @@ impl BuildCleaner {
         }
 
         // Check for conflicts - if file exists and differs, keep existing and skip restoration
+        let mut content = file.content.clone();
         if target_path.exists() {
             let existing_content = fs::read_to_string(&target_path)
                 .context("Failed to read existing .env file")?;
             
-            if existing_content != file.content {
+            // The build wrote the combination variables there; they join the operator's file, whose values win
+            if existing_content.starts_with(VARIABLES_ENV_MARKER) {
+                content = variables::merge_into_hand_written(&file.content, &existing_content);
+            } else if existing_content != file.content {
                 return Ok(RestoreResult::SkippedConflict);
             }
         }
 
         // Write file to target location
-        fs::write(&target_path, &file.content)
+        fs::write(&target_path, &content)
             .with_context(|| format!("Failed to write .env file: {}", target_path.display()))?;
 
+        if mapping.confidence == RELOCATED_MAPPING_CONFIDENCE {
+            status!("ℹ Combination for {} moved, restored it to {} ({})",
+                    file.original_path.display(), mapping.new_path.display(),
+                    if mapping.new_path.components().count() == 1 { "build now has a single combination at the root" } else { "base combination of the new structure" });
+        }
+
         Ok(RestoreResult::Restored(target_path))
     }
 
 
     /// Perform standard cleanup (remove all build directory contents)
     fn standard_cleanup(&self) -> Result<()> {
+        if let Some(reason) = self.unsafe_cleanup_reason() {
+            anyhow::bail!("Refusing to remove build directory '{}': {}", self.build_path.display(), reason);
+        }
+
         if self.build_path.exists() {
             fs::remove_dir_all(&self.build_path)
                 .with_context(|| format!("Failed to remove build directory: {}", self.build_path.display()))?;
-            println!("✓ Removed existing build directory");
+            status!("✓ Removed existing build directory");
         }
 
         fs::create_dir_all(&self.build_path)
             .with_context(|| format!("Failed to create build directory: {}", self.build_path.display()))?;
-        println!("✓ Created clean build directory");
+
+        write_build_marker(&self.build_path)?;
+        status!("✓ Created clean build directory");
 
         Ok(())
     }
 
+    /// Path next to the build directory with a suffix appended to its name
+    fn sibling_path(&self, suffix: &str) -> PathBuf {
+        let build_path = std::path::absolute(&self.build_path).unwrap_or_else(|_| self.build_path.clone());
+        let name = build_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        build_path.with_file_name(format!("{}{}", name, suffix))
+    }
+
     /// Get path to backup directory with timestamp
-    fn get_backup_path(&self) -> PathBuf {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        self.backup_dir.join(format!("backup_{}", timestamp))
+    fn backup_path_for(&self, timestamp: u64) -> PathBuf {
+        self.backup_dir.join(format!("{}{}", BACKUP_DIR_PREFIX, timestamp))
+    }
+
+    /// Get the archive path for a tar.gz backup with a specific timestamp
+    fn backup_archive_path_for(&self, timestamp: u64) -> PathBuf {
+        self.backup_dir.join(format!("{}{}{}", BACKUP_DIR_PREFIX, timestamp, BACKUP_ARCHIVE_EXTENSION))
+    }
+
+}
+
+/// Read the content of a single file stored in a backup directory or tar.gz archive
+pub fn read_backup_file(backup_path: &Path, entry: &BackupEntry) -> Result<String> {
+    let content = if backup_path.is_file() {
+        read_archive_entry(backup_path, &entry.backup_file)?
+    } else {
+        let file_path = backup_path.join(&entry.backup_file);
+        fs::read(&file_path)
+            .with_context(|| format!("Failed to read backup file: {}", file_path.display()))?
+    };
+
+    String::from_utf8(content)
+        .with_context(|| format!("Backup file is not valid UTF-8: {}", entry.backup_file))
+}
+
+/// Read a single entry from a tar.gz backup archive
+fn read_archive_entry(archive_path: &Path, name: &str) -> Result<Vec<u8>> {
+    let archive_file = fs::File::open(archive_path)
+        .with_context(|| format!("Failed to open backup archive: {}", archive_path.display()))?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive_file));
+
+    for entry in archive.entries()
+        .with_context(|| format!("Failed to read backup archive: {}", archive_path.display()))? {
+        let mut entry = entry
+            .with_context(|| format!("Failed to read backup archive: {}", archive_path.display()))?;
+        if entry.path().is_ok_and(|path| path == Path::new(name)) {
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content)
+                .with_context(|| format!("Failed to read '{}' from backup archive: {}", name, archive_path.display()))?;
+            return Ok(content);
+        }
+    }
+
+    anyhow::bail!("'{}' not found in backup archive: {}", name, archive_path.display())
+}
+
+/// Append an in-memory file to a tar archive
+fn append_archive_entry<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    content: &[u8],
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(&mut header, name, content)
+}
+
+/// Compute the SHA-256 hex digest of a file
+fn hash_file(path: &Path) -> Result<String> {
+    let content = fs::read(path)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok(format!("{:x}", Sha256::digest(&content)))
+}
+
+/// Check whether an output directory holds a base combination ("base" or "<env>/base")
+fn is_base_output_dir(dir: &str) -> bool {
+    Path::new(dir).file_name().is_some_and(|name| name == "base") && Path::new(dir).components().count() <= 2
+}
+
+/// Write the marker identifying a directory as generated by stackbuilder
+fn write_build_marker(dir: &Path) -> Result<()> {
+    let marker_path = dir.join(BUILD_MARKER_FILE);
+    fs::write(&marker_path, "This directory is generated by stackbuilder and is wiped on every build.\n")
+        .with_context(|| format!("Failed to write build marker: {}", marker_path.display()))
+}
+
+/// Recursively collect all files below a directory as paths relative to the base directory
+fn collect_files_recursive(current_dir: &Path, base_dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(current_dir)
+        .with_context(|| format!("Failed to read directory: {}", current_dir.display()))? {
+        let path = entry.context("Failed to read directory entry")?.path();
+        if path.is_dir() {
+            collect_files_recursive(&path, base_dir, files)?;
+        } else if let Ok(relative_path) = path.strip_prefix(base_dir) {
+            files.push(relative_path.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Check whether a directory has no entries
+fn is_empty_dir(path: &Path) -> bool {
+    fs::read_dir(path).map(|mut entries| entries.next().is_none()).unwrap_or(false)
+}
+
+/// Current Unix timestamp in seconds
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Read backup metadata from a backup directory or tar.gz archive, accepting both the current and the legacy format
+pub fn read_backup_metadata(backup_path: &Path) -> Result<BackupMetadata> {
+    if backup_path.is_file() {
+        let content = read_archive_entry(backup_path, BACKUP_METADATA_FILE)?;
+        return serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse metadata in backup archive: {}", backup_path.display()));
     }
 
+    let metadata_path = backup_path.join(BACKUP_METADATA_FILE);
+    let content = fs::read_to_string(&metadata_path)
+        .with_context(|| format!("Failed to read metadata file: {}", metadata_path.display()))?;
+
+    let metadata: StoredBackupMetadata = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse metadata file: {}", metadata_path.display()))?;
+
+    Ok(match metadata {
+        StoredBackupMetadata::Current(metadata) => metadata,
+        StoredBackupMetadata::Legacy(files) => {
+            // Legacy backups stored a plain list of files, named after the mangled original path
+            let created_at = backup_path.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(BACKUP_DIR_PREFIX))
+                .and_then(|ts| ts.parse().ok())
+                .unwrap_or(0);
+            BackupMetadata {
+                created_at,
+                files: files.into_iter()
+                    .map(|file| BackupEntry {
+                        backup_file: file.original_path.to_string_lossy().replace(['/', '\\'], "_"),
+                        original_path: file.original_path,
+                        environment: file.environment,
+                        extensions: file.extensions,
+                    })
+                    .collect(),
+            }
+        }
+    })
 }
 
 /// Result of restoring a single .env file
@@ -542,13 +1274,164 @@ mod tests {
     #[test]
     fn test_backup_path_generation() {
         let cleaner = BuildCleaner::new("/tmp/test", true, vec![".env".to_string()], "/tmp/backup".to_string());
-        let backup_path = cleaner.get_backup_path();
+        let backup_path = cleaner.backup_path_for(current_timestamp());
         
         // Should be in the format /tmp/backup/backup_TIMESTAMP
         assert!(backup_path.to_string_lossy().starts_with("/tmp/backup/backup_"));
     }
 
     #[test]
+    fn test_backup_metadata_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let cleaner = BuildCleaner::new(temp.path().join("build"), true, vec![".env".to_string()],
+                                        temp.path().join("backup").to_string_lossy().to_string());
+
+        let files = vec![
+            PreservedEnvFile {
+                original_path: PathBuf::from("dev/auth_x/.env"),
+                content: "A=1".to_string(),
+                environment: Some("dev".to_string()),
+                extensions: vec!["auth_x".to_string()],
+            },
+            PreservedEnvFile {
+                original_path: PathBuf::from("dev_auth/x/.env"),
+                content: "B=2".to_string(),
+                environment: Some("dev_auth".to_string()),
+                extensions: vec!["x".to_string()],
+            },
+        ];
+        cleaner.create_backup_for_failed_files(&files).unwrap();
+
+        let backup_path = fs::read_dir(temp.path().join("backup")).unwrap().next().unwrap().unwrap().path();
+        let metadata = read_backup_metadata(&backup_path).unwrap();
+        assert_eq!(metadata.files.len(), 2);
+        assert_eq!(metadata.files[0].original_path, PathBuf::from("dev/auth_x/.env"));
+        assert_eq!(metadata.files[1].original_path, PathBuf::from("dev_auth/x/.env"));
+        // Colliding mangled names must not overwrite each other
+        assert_ne!(metadata.files[0].backup_file, metadata.files[1].backup_file);
+        assert_eq!(fs::read_to_string(backup_path.join(&metadata.files[1].backup_file)).unwrap(), "B=2");
+    }
+
+    #[test]
+    fn test_unsafe_cleanup_detection() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = temp.path().join("project");
+        fs::create_dir_all(project.join("build")).unwrap();
+
+        // Build dir equal to the project directory
+        let cleaner = BuildCleaner::new(&project, false, vec![], "/tmp/backup".to_string())
+            .with_project_dir(&project);
+        assert!(cleaner.unsafe_cleanup_reason().is_some());
+
+        // Build dir containing the project directory
+        let cleaner = BuildCleaner::new(temp.path(), false, vec![], "/tmp/backup".to_string())
+            .with_project_dir(&project);
+        assert!(cleaner.unsafe_cleanup_reason().is_some());
+
+        // Regular build dir inside the project
+        let cleaner = BuildCleaner::new(project.join("build"), false, vec![], "/tmp/backup".to_string())
+            .with_project_dir(&project);
+        assert!(cleaner.unsafe_cleanup_reason().is_none());
+
+        // A non-empty build dir without the marker was not created by stackbuilder
+        fs::write(project.join("build/notes.txt"), "").unwrap();
+        assert!(cleaner.unsafe_cleanup_reason().unwrap().contains("not created by stackbuilder"));
+        fs::write(project.join("build").join(BUILD_MARKER_FILE), "").unwrap();
+        assert!(cleaner.unsafe_cleanup_reason().is_none());
+
+        // Build dir that looks like a repository or another project
+        fs::create_dir_all(project.join("build/.git")).unwrap();
+        assert!(cleaner.unsafe_cleanup_reason().is_some());
+        fs::remove_dir_all(project.join("build/.git")).unwrap();
+        fs::write(project.join("build/stackbuilder.toml"), "").unwrap();
+        assert!(cleaner.unsafe_cleanup_reason().is_some());
+
+        // Forced cleanup skips the checks
+        let cleaner = cleaner.with_force_clean(true);
+        assert!(cleaner.unsafe_cleanup_reason().is_none());
+
+        // ...except the one protecting components
+        let cleaner = cleaner.with_read_only_dirs(vec![project.clone()]);
+        assert!(cleaner.unsafe_cleanup_reason().unwrap().contains("read-only components directory"));
+
+        let root = BuildCleaner::new("/", false, vec![], "/tmp/backup".to_string());
+        assert!(root.unsafe_cleanup_reason().is_some());
+    }
+
+    #[test]
+    fn test_cleanup_refuses_unsafe_dir_and_writes_marker() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("stackbuilder.toml"), "").unwrap();
+
+        let cleaner = BuildCleaner::new(temp.path(), false, vec![], "/tmp/backup".to_string())
+            .with_project_dir(temp.path());
+        assert!(cleaner.clean_build_directory().is_err());
+        assert!(temp.path().join("stackbuilder.toml").exists());
+
+        let build = temp.path().join("build");
+        let cleaner = BuildCleaner::new(&build, false, vec![], "/tmp/backup".to_string())
+            .with_project_dir(temp.path());
+        cleaner.clean_build_directory().unwrap();
+        assert!(build.join(BUILD_MARKER_FILE).exists());
+    }
+
+    #[test]
+    fn test_cleanup_and_restore_plan() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path().join("build");
+        fs::create_dir_all(build.join("dev/auth")).unwrap();
+        fs::create_dir_all(build.join("staging")).unwrap();
+        fs::write(build.join(BUILD_MARKER_FILE), "").unwrap();
+        fs::write(build.join("dev/auth/docker-compose.yml"), "services: {}").unwrap();
+        fs::write(build.join("dev/auth/.env"), "A=1").unwrap();
+        fs::write(build.join("staging/.env"), "B=2").unwrap();
+
+        let cleaner = BuildCleaner::new(&build, true, vec![".env".to_string()], "/tmp/backup".to_string());
+        let cleanup = cleaner.plan_cleanup().unwrap();
+        assert!(cleanup.unsafe_reason.is_none());
+        // The marker is removed with the rest
+        assert_eq!(cleanup.files_to_delete.len(), 4);
+        assert_eq!(cleanup.preserved_files.len(), 2);
+
+        let restore = cleaner.plan_restore(&cleanup.preserved_files, &["dev/auth".to_string()]);
+        assert_eq!(restore.restored, vec![(PathBuf::from("dev/auth/.env"), PathBuf::from("dev/auth/.env"))]);
+        assert_eq!(restore.backed_up, vec![PathBuf::from("staging/.env")]);
+
+        // Planning must not touch the build directory
+        assert!(build.join("dev/auth/docker-compose.yml").exists());
+        assert!(build.join("staging/.env").exists());
+    }
+
+    #[test]
+    fn test_staging_commit_replaces_build_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path().join("build");
+        fs::create_dir_all(build.join("dev")).unwrap();
+        fs::write(build.join(BUILD_MARKER_FILE), "").unwrap();
+        fs::write(build.join("dev/docker-compose.yml"), "old").unwrap();
+        fs::write(build.join("dev/.env"), "A=1").unwrap();
+
+        let cleaner = BuildCleaner::new(&build, true, vec![".env".to_string()], temp.path().join("backup").to_string_lossy().to_string());
+        let staging = cleaner.prepare_staging().unwrap();
+        assert_ne!(staging, build);
+        assert!(staging.join(BUILD_MARKER_FILE).exists());
+
+        // Nothing in the real build directory changes until commit
+        fs::create_dir_all(staging.join("dev")).unwrap();
+        fs::write(staging.join("dev/docker-compose.yml"), "new").unwrap();
+        assert_eq!(fs::read_to_string(build.join("dev/docker-compose.yml")).unwrap(), "old");
+
+        cleaner.restore_env_files_into(&staging, &["dev".to_string()]).unwrap();
+        cleaner.commit_staging(&staging).unwrap();
+
+        assert!(!staging.exists());
+        assert_eq!(fs::read_to_string(build.join("dev/docker-compose.yml")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(build.join("dev/.env")).unwrap(), "A=1");
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 1, "Only the build directory should remain");
+    }
+
+    #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_restore_result_enum() {
         // Test that the enum variants work correctly
         let restored = RestoreResult::Restored(PathBuf::from("/test/path"));
@@ -588,4 +1471,51 @@ mod tests {
         assert_eq!(mapping.confidence, 1.0);
         assert_eq!(mapping.new_path, PathBuf::from(".env"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_root_env_relocated_to_base_combination() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path().join("build");
+        fs::create_dir_all(&build).unwrap();
+        fs::write(build.join("docker-compose.yml"), "services: {}").unwrap();
+        fs::write(build.join(BUILD_MARKER_FILE), "").unwrap();
+        fs::write(build.join(".env"), "SECRET=1").unwrap();
+
+        let cleaner = BuildCleaner::new(&build, true, vec![".env".to_string()], temp.path().join("backup").to_string_lossy().to_string());
+        let cleanup = cleaner.plan_cleanup().unwrap();
+
+        // Single environment with extensions: root output moved to base/
+        let restore = cleaner.plan_restore(&cleanup.preserved_files, &["base".to_string(), "auth".to_string()]);
+        assert_eq!(restore.restored, vec![(PathBuf::from(".env"), PathBuf::from("base/.env"))]);
+
+        // Several base directories are ambiguous, the file goes to backup
+        let restore = cleaner.plan_restore(&cleanup.preserved_files, &["dev/base".to_string(), "prod/base".to_string()]);
+        assert!(restore.restored.is_empty());
+        assert_eq!(restore.backed_up, vec![PathBuf::from(".env")]);
+
+        cleaner.clean_build_directory().unwrap();
+        cleaner.restore_env_files(&["base".to_string(), "auth".to_string()]).unwrap();
+        assert_eq!(fs::read_to_string(build.join("base/.env")).unwrap(), "SECRET=1");
+        assert!(!temp.path().join("backup").exists());
+    }
+
+    #[test]
+    fn test_base_env_relocated_to_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path().join("build");
+        fs::create_dir_all(build.join("base")).unwrap();
+        fs::create_dir_all(build.join("auth")).unwrap();
+        fs::write(build.join(BUILD_MARKER_FILE), "").unwrap();
+        fs::write(build.join("base/.env"), "SECRET=1").unwrap();
+        fs::write(build.join("auth/.env"), "TOKEN=2").unwrap();
+
+        let cleaner = BuildCleaner::new(&build, true, vec![".env".to_string()], temp.path().join("backup").to_string_lossy().to_string());
+        cleaner.clean_build_directory().unwrap();
+        cleaner.restore_env_files(&["".to_string()]).unwrap();
+
+        // Only the base combination follows the move back to the root
+        assert_eq!(fs::read_to_string(build.join(".env")).unwrap(), "SECRET=1");
+        assert!(!build.join("auth/.env").exists());
+        assert!(temp.path().join("backup").exists());
+    }
+}
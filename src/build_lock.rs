@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::error::{Result, BuildError, FileSystemError};
+
+/// Default location of the build lock file, relative to the project directory
+pub const BUILD_LOCK_FILE: &str = ".stackbuilder/build.lock";
+
+/// Interval between attempts to acquire a held lock
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Advisory lock preventing concurrent builds from interleaving cleanup and restoration.
+/// The lock is an OS file lock on the lock file, so the operating system releases it when its process dies
+/// and no stale lock is ever left behind. The file itself only records the owner for messages, and is
+/// removed when the guard is dropped, including during panic unwinding.
+#[derive(Debug)]
+pub struct BuildLock {
+    path: PathBuf,
+    /// Open for as long as the lock is held; closing it releases the lock
+    _file: fs::File,
+}
+
+/// Owner information stored in the lock file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockOwner {
+    /// Process ID of the build holding the lock
+    pub pid: u32,
+    /// Unix timestamp (seconds) when the lock was acquired
+    pub acquired_at: u64,
+}
+
+impl BuildLock {
+    /// Acquire the lock, waiting up to `timeout` for another build to release it.
+    /// A lock file left by a process that died is taken over, whatever it contains.
+    pub fn acquire<P: AsRef<Path>>(path: P, timeout: Duration) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                    path: parent.to_path_buf(),
+                    source: e,
+                })?;
+        }
+
+        let started = Instant::now();
+        let mut reported_wait = false;
+
+        loop {
+            let file_error = |e| FileSystemError::FileWriteFailed { path: path.clone(), source: e };
+            let mut file = fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)
+                .map_err(file_error)?;
+            match file.try_lock() {
+                Ok(()) if is_file_at(&file, &path) => {
+                    let mut previous = String::new();
+                    let _ = file.read_to_string(&mut previous);
+                    if let Some(owner) = parse_lock_owner(&previous).filter(|owner| owner.pid != std::process::id()) {
+                        status!("Taking over build lock left by process {}", owner.pid);
+                    }
+
+                    let acquired_at = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    file.set_len(0)
+                        .and_then(|()| file.seek(SeekFrom::Start(0)))
+                        .and_then(|_| write!(file, "{}\n{}\n", std::process::id(), acquired_at))
+                        .map_err(file_error)?;
+                    return Ok(BuildLock { path, _file: file });
+                }
+                // The previous holder removed the file while releasing it; lock the one at the path now
+                Ok(()) => continue,
+                Err(fs::TryLockError::WouldBlock) => {
+                    drop(file);
+                    let owner = read_lock_owner(&path);
+
+                    if started.elapsed() >= timeout {
+                        return Err(BuildError::BuildLocked {
+                            lock_path: path,
+                            pid: owner.map(|o| o.pid),
+                        }.into());
+                    }
+
+                    if !reported_wait {
+                        match owner {
+                            Some(ref owner) => status!("Waiting for build lock held by process {}...", owner.pid),
+                            None => status!("Waiting for build lock: {}...", path.display()),
+                        }
+                        reported_wait = true;
+                    }
+                    thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(fs::TryLockError::Error(e)) => return Err(file_error(e).into()),
+            }
+        }
+    }
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so a waiting build notices and locks a new file instead of this one
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Read the owner recorded in a lock file, if it can be parsed
+pub fn read_lock_owner(path: &Path) -> Option<LockOwner> {
+    parse_lock_owner(&fs::read_to_string(path).ok()?)
+}
+
+fn parse_lock_owner(content: &str) -> Option<LockOwner> {
+    let mut lines = content.lines();
+    let pid = lines.next()?.trim().parse().ok()?;
+    let acquired_at = lines.next().and_then(|l| l.trim().parse().ok()).unwrap_or(0);
+    Some(LockOwner { pid, acquired_at })
+}
+
+/// Whether `file` is still the file at `path`, rather than one its previous holder removed
+#[cfg(unix)]
+fn is_file_at(file: &fs::File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(held), Ok(current)) => held.dev() == current.dev() && held.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Files that are open elsewhere cannot be removed on Windows, so the file at `path` is always the open one
+#[cfg(not(unix))]
+fn is_file_at(_file: &fs::File, _path: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_released_on_drop() {
+        let temp = tempfile::tempdir().unwrap();
+        let lock_path = temp.path().join(".stackbuilder/build.lock");
+
+        {
+            let _lock = BuildLock::acquire(&lock_path, Duration::ZERO).unwrap();
+            let owner = read_lock_owner(&lock_path).unwrap();
+            assert_eq!(owner.pid, std::process::id());
+        }
+
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_held_lock_times_out() {
+        let temp = tempfile::tempdir().unwrap();
+        let lock_path = temp.path().join("build.lock");
+
+        let _lock = BuildLock::acquire(&lock_path, Duration::ZERO).unwrap();
+        let result = BuildLock::acquire(&lock_path, Duration::from_millis(300));
+        assert!(matches!(result, Err(crate::error::StackBuilderError::Build(BuildError::BuildLocked { pid: Some(_), .. }))));
+        assert!(lock_path.exists());
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let temp = tempfile::tempdir().unwrap();
+        let lock_path = temp.path().join("build.lock");
+
+        // A lock file of a build that died; nothing holds the lock on it any more
+        fs::write(&lock_path, "999999999\n0\n").unwrap();
+
+        let _lock = BuildLock::acquire(&lock_path, Duration::ZERO).unwrap();
+        assert_eq!(read_lock_owner(&lock_path).unwrap().pid, std::process::id());
+    }
+
+    #[test]
+    fn test_unreadable_lock_file_is_taken_over() {
+        let temp = tempfile::tempdir().unwrap();
+        let lock_path = temp.path().join("build.lock");
+
+        // What a build that died between creating and writing the file leaves behind
+        for content in ["", "not a pid\n"] {
+            fs::write(&lock_path, content).unwrap();
+            let lock = BuildLock::acquire(&lock_path, Duration::ZERO).unwrap();
+            assert_eq!(read_lock_owner(&lock_path).unwrap().pid, std::process::id());
+            drop(lock);
+        }
+    }
+
+    #[test]
+    fn test_released_lock_is_acquired_by_waiting_build() {
+        let temp = tempfile::tempdir().unwrap();
+        let lock_path = temp.path().join("build.lock");
+
+        let lock = BuildLock::acquire(&lock_path, Duration::ZERO).unwrap();
+        let waiting = {
+            let lock_path = lock_path.clone();
+            thread::spawn(move || BuildLock::acquire(&lock_path, Duration::from_secs(10)).map(|_| ()))
+        };
+        thread::sleep(LOCK_POLL_INTERVAL);
+        drop(lock);
+        assert!(waiting.join().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,562 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::config::YamlMergerType;
+use crate::diagnostics::Warning;
+use crate::error::{Result, BuildError, FileSystemError};
+use crate::observer::BuildPhase;
+use crate::ports::PortMapping;
+
+/// Machine-readable list of the combinations a build produced, written to the build directory
+pub const COMBINATIONS_MANIFEST_FILE: &str = "stackbuilder-manifest.json";
+
+/// Layout version of the combinations manifest; 2 added the service inventory
+pub const COMBINATIONS_MANIFEST_SCHEMA: u32 = 2;
+
+/// Structured summary of a completed build
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildReport {
+    /// Merger used to produce the compose files
+    pub merger: YamlMergerType,
+    /// Build directory the outputs were written to
+    pub build_dir: PathBuf,
+    /// Generated combinations in build order
+    pub combinations: Vec<CombinationReport>,
+    /// Preserved .env files that matched no combination and were moved to the backup directory
+    pub env_files_backed_up: Vec<PathBuf>,
+    /// Wall-clock time of the whole build in milliseconds
+    pub duration_ms: u64,
+    /// Combinations that failed in a build that kept going, in build order
+    pub failed: Vec<FailedCombination>,
+    /// Combinations of the previous build that are no longer configured, sorted by output directory
+    pub pruned: Vec<PrunedCombination>,
+    /// Services that changed since the previous build, per combination present in both, sorted by output directory
+    pub service_changes: Vec<ServiceChanges>,
+    /// Number of warnings emitted during the build
+    pub warning_count: usize,
+    /// Non-fatal problems encountered during the build, in the order they occurred
+    pub warnings: Vec<Warning>,
+    /// Time spent per phase and combination, with `--timings` or `--timings-file`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<BuildTimings>,
+}
+
+/// Where the time of a build went, see `build --timings`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BuildTimings {
+    /// Wall-clock time of the whole build in milliseconds
+    pub total_ms: u64,
+    /// Milliseconds per phase, keyed by phase name; the phases of the combinations are summed over all of them
+    pub phases: BTreeMap<String, u64>,
+    /// Generated and reused combinations in build order
+    pub combinations: Vec<CombinationTimings>,
+}
+
+/// Time spent on one combination
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CombinationTimings {
+    /// Output directory relative to the build directory (empty for the build root)
+    pub output_dir: String,
+    /// Time from starting the combination until its outputs were written, in milliseconds
+    pub duration_ms: u64,
+    /// Milliseconds per phase; empty for outputs reused from the build cache
+    pub phases: BTreeMap<String, u64>,
+    /// The combination failed with `keep_going`; the times are those until the failure
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub failed: bool,
+}
+
+impl BuildTimings {
+    /// The phases, slowest first, with the total, followed by the combinations, slowest first, with their phases
+    pub fn format_table(&self) -> String {
+        let ms = |ms: u64| format!("{}ms", ms);
+        let mut phases: Vec<(&String, &u64)> = self.phases.iter().collect();
+        phases.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let phase_rows: Vec<[String; 2]> = std::iter::once(["PHASE".to_string(), "TIME".to_string()])
+            .chain(phases.into_iter().map(|(phase, duration)| [phase.clone(), ms(*duration)]))
+            .chain(std::iter::once(["total".to_string(), ms(self.total_ms)]))
+            .collect();
+
+        let mut combinations: Vec<&CombinationTimings> = self.combinations.iter().collect();
+        combinations.sort_by(|a, b| b.duration_ms.cmp(&a.duration_ms).then(a.output_dir.cmp(&b.output_dir)));
+        let header = std::iter::once("COMBINATION".to_string())
+            .chain(BuildPhase::COMBINATION.map(|phase| phase.name().to_uppercase()))
+            .chain(std::iter::once("TOTAL".to_string()))
+            .collect();
+        let combination_rows: Vec<Vec<String>> = std::iter::once(header)
+            .chain(combinations.into_iter().map(|combination| {
+                let name = if combination.output_dir.is_empty() { "." } else { &combination.output_dir };
+                std::iter::once(if combination.failed { format!("{} (failed)", name) } else { name.to_string() })
+                    .chain(BuildPhase::COMBINATION.map(|phase| match combination.phases.get(phase.name()) {
+                        Some(duration) => ms(*duration),
+                        None => "-".to_string(),
+                    }))
+                    .chain(std::iter::once(ms(combination.duration_ms)))
+                    .collect()
+            }))
+            .collect();
+
+        format!("{}\n\n{}", format_columns(&phase_rows), format_columns(&combination_rows))
+    }
+}
+
+/// Rows of cells as left-aligned columns separated by two spaces
+fn format_columns<R: AsRef<[String]>>(rows: impl IntoIterator<Item = R> + Clone) -> String {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows.clone() {
+        for (i, cell) in row.as_ref().iter().enumerate() {
+            match widths.get_mut(i) {
+                Some(width) => *width = (*width).max(cell.chars().count()),
+                None => widths.push(cell.chars().count()),
+            }
+        }
+    }
+
+    rows.into_iter()
+        .map(|row| {
+            let line: Vec<String> = row.as_ref().iter().zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            line.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Outputs of a single build combination
+#[derive(Debug, Clone, Serialize)]
+pub struct CombinationReport {
+    /// Output directory relative to the build directory (empty for the build root)
+    pub output_dir: String,
+    /// Environment applied, if any
+    pub environment: Option<String>,
+    /// Extensions applied, including those from combos
+    pub extensions: Vec<String>,
+    /// Combos applied
+    pub combos: Vec<String>,
+    /// Path of the generated docker-compose file
+    pub compose_file: PathBuf,
+    /// Merger that produced the compose files
+    pub merger: YamlMergerType,
+    /// Number of leaf values overridden during the merge (Rust merger only)
+    pub overridden_keys: Option<usize>,
+    /// Top-level entries of the merged compose file; absent when nothing was merged
+    pub counts: Option<ComposeCounts>,
+    /// Image and ports of every service of the merged compose file; absent when nothing was merged
+    pub services: Option<BTreeMap<String, ServiceInventory>>,
+    /// Generated files: compose files and .env.example
+    pub files_written: Vec<PathBuf>,
+    /// Additional files copied or hardlinked from the components
+    pub files_copied: Vec<PathBuf>,
+    /// Preserved .env files from the previous build restored into this combination
+    pub env_files_restored: Vec<PathBuf>,
+    /// Warnings emitted while this combination was generated
+    pub warnings: Vec<Warning>,
+    /// Time spent generating this combination in milliseconds
+    pub duration_ms: u64,
+    /// Outputs were copied from the previous build because none of the combination's inputs changed
+    pub cached: bool,
+}
+
+/// Number of services, volumes and networks defined in a merged compose file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComposeCounts {
+    pub services: usize,
+    pub volumes: usize,
+    pub networks: usize,
+}
+
+impl ComposeCounts {
+    /// Count the entries of the top-level `services`, `volumes` and `networks` mappings
+    pub fn of(compose: &serde_yaml_ng::Value) -> Self {
+        let count = |section: &str| compose.get(section).and_then(serde_yaml_ng::Value::as_mapping).map_or(0, |entries| entries.len());
+        Self {
+            services: count("services"),
+            volumes: count("volumes"),
+            networks: count("networks"),
+        }
+    }
+}
+
+impl std::fmt::Display for ComposeCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let plural = |count: usize, noun: &str| format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" });
+        write!(f, "{}, {}, {}", plural(self.services, "service"), plural(self.volumes, "volume"), plural(self.networks, "network"))
+    }
+}
+
+/// Image and published ports of one service of a merged compose file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceInventory {
+    /// Image reference with its tag; absent for services that are only built
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Port mappings as in the short syntax, e.g. `127.0.0.1:8080:80/udp`, sorted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<String>,
+}
+
+impl ServiceInventory {
+    /// Inventory of every service of the top-level `services` mapping, keyed by service name
+    pub fn of(compose: &serde_yaml_ng::Value) -> BTreeMap<String, Self> {
+        let Some(services) = compose.get("services").and_then(serde_yaml_ng::Value::as_mapping) else { return BTreeMap::new() };
+        services.iter()
+            .filter_map(|(name, service)| {
+                let mut ports: Vec<String> = service.get("ports")
+                    .and_then(serde_yaml_ng::Value::as_sequence)
+                    .map_or_else(Vec::new, |ports| ports.iter()
+                        // In the short syntax; interpolated entries are left as written
+                        .filter_map(|port| PortMapping::parse(port).and_then(|mapping| mapping.to_short()).or_else(|| port.as_str().map(str::to_string)))
+                        .collect());
+                ports.sort();
+                Some((name.as_str()?.to_string(), Self {
+                    image: service.get("image").and_then(serde_yaml_ng::Value::as_str).map(str::to_string),
+                    ports,
+                }))
+            })
+            .collect()
+    }
+}
+
+/// How the services of a combination changed since the previous build
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ServiceChanges {
+    /// Output directory relative to the build directory (empty for the build root)
+    pub output_dir: String,
+    /// Services that were not in the previous build
+    pub added: Vec<String>,
+    /// Services of the previous build that are gone
+    pub removed: Vec<String>,
+    /// Services whose image changed, e.g. a bumped tag
+    pub images: Vec<ServiceChange>,
+    /// Services whose port mappings changed
+    pub ports: Vec<ServiceChange>,
+}
+
+/// A value of one service before and after a build
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServiceChange {
+    pub service: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl ServiceChanges {
+    /// Changes from the `previous` to the `current` services of the combination in `output_dir`, or `None` without any
+    pub fn between(output_dir: &str, previous: &BTreeMap<String, ServiceInventory>, current: &BTreeMap<String, ServiceInventory>) -> Option<Self> {
+        let describe_image = |image: &Option<String>| image.clone().unwrap_or_else(|| "no image".to_string());
+        let describe_ports = |ports: &[String]| if ports.is_empty() { "no ports".to_string() } else { ports.join(", ") };
+        let mut changes = Self { output_dir: output_dir.to_string(), ..Self::default() };
+        for (service, inventory) in current {
+            let Some(before) = previous.get(service) else {
+                changes.added.push(service.clone());
+                continue;
+            };
+            if before.image != inventory.image {
+                changes.images.push(ServiceChange { service: service.clone(), from: describe_image(&before.image), to: describe_image(&inventory.image) });
+            }
+            if before.ports != inventory.ports {
+                changes.ports.push(ServiceChange { service: service.clone(), from: describe_ports(&before.ports), to: describe_ports(&inventory.ports) });
+            }
+        }
+        changes.removed = previous.keys().filter(|service| !current.contains_key(*service)).cloned().collect();
+        let unchanged = changes.added.is_empty() && changes.removed.is_empty() && changes.images.is_empty() && changes.ports.is_empty();
+        (!unchanged).then_some(changes)
+    }
+}
+
+/// A combination that failed while the build went on with the others
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedCombination {
+    /// Output directory relative to the build directory (empty for the build root)
+    pub output_dir: String,
+    /// Environment applied, if any
+    pub environment: Option<String>,
+    /// Extensions applied, including those from combos
+    pub extensions: Vec<String>,
+    /// Error code of the failure, e.g. `yaml.parse_error`
+    pub code: String,
+    /// Error message, without the combination it happened in
+    pub message: String,
+}
+
+/// Output directory of an earlier build whose combination is no longer configured
+#[derive(Debug, Clone, Serialize)]
+pub struct PrunedCombination {
+    /// Output directory relative to the build directory
+    pub output_dir: String,
+    pub environment: Option<String>,
+    pub extensions: Vec<String>,
+    pub combos: Vec<String>,
+    /// Generated and copied files the directory held, relative to it
+    pub files: Vec<String>,
+    /// The directory was kept by `--keep-stale` instead of being removed
+    pub kept: bool,
+    /// Preserved files from the directory that were moved to the backup directory
+    pub env_files_backed_up: Vec<PathBuf>,
+    /// Preserved files from the directory restored into another combination, at their new path
+    pub env_files_relocated: Vec<PathBuf>,
+}
+
+impl BuildReport {
+    /// Create an empty report for the given merger and build directory
+    pub fn new(merger: YamlMergerType, build_dir: &Path) -> Self {
+        Self {
+            merger,
+            build_dir: build_dir.to_path_buf(),
+            combinations: Vec::new(),
+            env_files_backed_up: Vec::new(),
+            failed: Vec::new(),
+            pruned: Vec::new(),
+            service_changes: Vec::new(),
+            duration_ms: 0,
+            warning_count: 0,
+            warnings: Vec::new(),
+            timings: None,
+        }
+    }
+
+    /// Record the warnings collected during the build, and each combination's share of them
+    pub fn set_warnings(&mut self, warnings: Vec<Warning>) {
+        for combination in &mut self.combinations {
+            combination.warnings = warnings.iter()
+                .filter(|warning| warning.combination.as_deref() == Some(combination.output_dir.as_str()))
+                .cloned()
+                .collect();
+        }
+        self.warning_count = warnings.len();
+        self.warnings = warnings;
+    }
+
+    /// One row per combination with its environment, extensions, file counts, warnings and time; failed combinations come last
+    pub fn format_table(&self) -> String {
+        let header = ["COMBINATION", "ENVIRONMENT", "EXTENSIONS", "FILES", "WARNINGS", "TIME"].map(String::from);
+        let rows: Vec<[String; 6]> = self.combinations.iter()
+            .map(|combination| [
+                if combination.output_dir.is_empty() { ".".to_string() } else { combination.output_dir.clone() },
+                combination.environment.clone().unwrap_or_else(|| "-".to_string()),
+                if combination.extensions.is_empty() { "-".to_string() } else { combination.extensions.join(",") },
+                (combination.files_written.len() + combination.files_copied.len()).to_string(),
+                combination.warnings.len().to_string(),
+                if combination.cached { "cached".to_string() } else { format!("{}ms", combination.duration_ms) },
+            ])
+            .chain(self.failed.iter().map(|failed| [
+                if failed.output_dir.is_empty() { ".".to_string() } else { failed.output_dir.clone() },
+                failed.environment.clone().unwrap_or_else(|| "-".to_string()),
+                if failed.extensions.is_empty() { "-".to_string() } else { failed.extensions.join(",") },
+                "-".to_string(),
+                "-".to_string(),
+                "failed".to_string(),
+            ]))
+            .collect();
+
+        format_columns(std::iter::once(&header).chain(&rows))
+    }
+
+    /// The pruned combinations section of the build output, one line per directory, or `None` without any
+    pub fn format_pruned(&self) -> Option<String> {
+        if self.pruned.is_empty() {
+            return None;
+        }
+        let mut lines = vec![match self.pruned.iter().all(|pruned| pruned.kept) {
+            true => "Stale combinations (no longer configured, kept by --keep-stale):".to_string(),
+            false => "Pruned combinations (no longer configured, removed from the build directory):".to_string(),
+        }];
+        for pruned in &self.pruned {
+            let mut notes = Vec::new();
+            if pruned.kept {
+                notes.push("kept".to_string());
+            }
+            if !pruned.env_files_backed_up.is_empty() {
+                notes.push(format!("{} preserved files backed up", pruned.env_files_backed_up.len()));
+            }
+            if !pruned.env_files_relocated.is_empty() {
+                notes.push(format!("{} preserved files restored elsewhere", pruned.env_files_relocated.len()));
+            }
+            if notes.is_empty() {
+                notes.push("no preserved files".to_string());
+            }
+            lines.push(format!("  {} ({})", pruned.output_dir, notes.join(", ")));
+        }
+        Some(lines.join("\n"))
+    }
+
+    /// The service changes section of the build output, one line per change, or `None` without any
+    pub fn format_service_changes(&self) -> Option<String> {
+        if self.service_changes.is_empty() {
+            return None;
+        }
+        let mut lines = vec!["Service changes since the previous build:".to_string()];
+        for changes in &self.service_changes {
+            lines.push(format!("  {}:", if changes.output_dir.is_empty() { "." } else { &changes.output_dir }));
+            lines.extend(changes.added.iter().map(|service| format!("    + {}", service)));
+            lines.extend(changes.removed.iter().map(|service| format!("    - {}", service)));
+            lines.extend(changes.images.iter().map(|change| format!("    ~ {}: image {} → {}", change.service, change.from, change.to)));
+            lines.extend(changes.ports.iter().map(|change| format!("    ~ {}: ports {} → {}", change.service, change.from, change.to)));
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// What a build produced, for tooling that deploys the combinations without knowing the configuration.
+/// Sorted and free of timestamps, so an unchanged build writes an identical file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CombinationsManifest {
+    /// Layout version, see [`COMBINATIONS_MANIFEST_SCHEMA`]; manifests written before it was introduced have 1
+    #[serde(default = "legacy_manifest_schema")]
+    pub schema: u32,
+    /// stackbuilder version that wrote the manifest
+    pub version: String,
+    /// SHA-256 of the effective configuration
+    pub config_hash: String,
+    /// Combinations sorted by output directory
+    pub combinations: Vec<ManifestCombination>,
+    /// Directories of combinations no longer configured that `--keep-stale` kept, sorted by output directory
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stale: Vec<ManifestCombination>,
+}
+
+/// One combination of the combinations manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestCombination {
+    pub environment: Option<String>,
+    /// Extensions applied, including those from combos
+    pub extensions: Vec<String>,
+    pub combos: Vec<String>,
+    /// Directory holding the outputs, relative to the build directory (empty for the build root)
+    pub output_dir: String,
+    /// Generated and copied files, relative to `output_dir` and sorted
+    pub files: Vec<String>,
+    /// Planned output directory of a combination written to its `[build.aliases]` alias
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+    /// Image and ports of every service; absent when nothing was merged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub services: Option<BTreeMap<String, ServiceInventory>>,
+}
+
+fn legacy_manifest_schema() -> u32 {
+    1
+}
+
+impl CombinationsManifest {
+    /// Manifest of the combinations in `report`, built with the output directory `aliases` of `[build.aliases]`
+    pub fn from_report(report: &BuildReport, config_hash: String, aliases: &BTreeMap<String, String>) -> Self {
+        let relative = |path: &Path, base: &Path| path.strip_prefix(base).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        let alias_of = |output_dir: &str| aliases.iter()
+            .find(|(_, alias)| alias.trim_matches('/') == output_dir)
+            .map(|(name, _)| name.trim_matches('/').to_string());
+        let mut combinations: Vec<ManifestCombination> = report.combinations.iter()
+            .map(|combination| {
+                let dir = combination.compose_file.parent().unwrap_or(&report.build_dir);
+                let mut files: Vec<String> = combination.files_written.iter().chain(&combination.files_copied)
+                    .map(|file| relative(file, dir))
+                    .collect();
+                files.sort();
+                let output_dir = relative(dir, &report.build_dir);
+                ManifestCombination {
+                    environment: combination.environment.clone(),
+                    extensions: combination.extensions.clone(),
+                    combos: combination.combos.clone(),
+                    alias_of: alias_of(&output_dir),
+                    output_dir,
+                    files,
+                    services: combination.services.clone(),
+                }
+            })
+            .collect();
+        combinations.sort_by(|a, b| a.output_dir.cmp(&b.output_dir));
+        let stale = report.pruned.iter()
+            .filter(|pruned| pruned.kept)
+            .map(|pruned| ManifestCombination {
+                environment: pruned.environment.clone(),
+                extensions: pruned.extensions.clone(),
+                combos: pruned.combos.clone(),
+                output_dir: pruned.output_dir.clone(),
+                files: pruned.files.clone(),
+                alias_of: None,
+                services: None,
+            })
+            .collect();
+
+        Self {
+            schema: COMBINATIONS_MANIFEST_SCHEMA,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            config_hash,
+            combinations,
+            stale,
+        }
+    }
+
+    /// How the services of the combinations in both manifests changed since `previous`, sorted by output directory.
+    /// Combinations without an inventory on either side, e.g. from a manifest older than schema 2, are skipped.
+    pub fn service_changes(&self, previous: &CombinationsManifest) -> Vec<ServiceChanges> {
+        self.combinations.iter()
+            .filter_map(|combination| {
+                let before = previous.combinations.iter().find(|before| before.output_dir == combination.output_dir)?;
+                ServiceChanges::between(&combination.output_dir, before.services.as_ref()?, combination.services.as_ref()?)
+            })
+            .collect()
+    }
+
+    /// The manifest a previous build wrote to `dir`, if there is a readable one
+    pub fn load(dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(dir.join(COMBINATIONS_MANIFEST_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Write the manifest as JSON to `dir`
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(COMBINATIONS_MANIFEST_FILE);
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| BuildError::BuildProcessFailed {
+                details: format!("Failed to serialize combinations manifest: {}", e),
+            })?;
+        fs::write(&path, json + "\n")
+            .map_err(|e| FileSystemError::FileWriteFailed { path, source: e }.into())
+    }
+}
+
+/// Write the report as JSON, creating parent directories and replacing the file atomically
+pub fn write_summary_file(report: &BuildReport, path: &Path) -> Result<()> {
+    write_json_file(report, path, "build report")
+}
+
+/// Write `value` as pretty-printed JSON to `path`, creating parent directories and replacing the file atomically.
+/// `what` names the content in errors.
+pub fn write_json_file(value: &impl Serialize, path: &Path, what: &str) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .map_err(|e| FileSystemError::DirectoryCreationFailed {
+                path: parent.to_path_buf(),
+                source: e,
+            })?;
+    }
+
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to serialize {}: {}", what, e),
+        })?;
+
+    // Write next to the target and rename so readers never see a partial file
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    fs::write(&temp_path, json + "\n")
+        .map_err(|e| FileSystemError::FileWriteFailed {
+            path: temp_path.clone(),
+            source: e,
+        })?;
+    fs::rename(&temp_path, path)
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            FileSystemError::FileWriteFailed {
+                path: path.to_path_buf(),
+                source: e,
+            }
+        })?;
+
+    Ok(())
+}
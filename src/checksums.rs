@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::Parser;
+use sha2::{Digest, Sha256};
+
+use crate::build_cleaner::{BUILD_MANIFEST_FILE, BUILD_MARKER_FILE};
+use crate::config::{self, Config};
+use crate::error::{Result, BuildError, FileSystemError};
+use crate::paths::ResolvedPaths;
+
+/// Checksums manifest written into the build directory and every combination directory,
+/// in the format of `sha256sum`, so `sha256sum -c CHECKSUMS.sha256` works as well
+pub const CHECKSUMS_FILE: &str = "CHECKSUMS.sha256";
+
+/// Which files of a build directory the checksums manifests list
+#[derive(Debug, Clone, Default)]
+pub struct ChecksumScope {
+    /// Names of preserved .env files; they hold host-specific secrets and are never listed
+    pub env_file_patterns: Vec<String>,
+    /// Directories skipped entirely, relative to the build directory (a `backup_dir` kept inside it)
+    pub excluded_dirs: Vec<PathBuf>,
+}
+
+impl ChecksumScope {
+    /// Scope for a configuration whose paths are resolved
+    pub fn from_config(config: &Config) -> Self {
+        let build_dir = Path::new(&config.paths.build_dir);
+        let excluded_dirs = Path::new(&config.build.backup_dir).strip_prefix(build_dir)
+            .ok()
+            .filter(|relative| !relative.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .into_iter()
+            .collect();
+
+        Self {
+            env_file_patterns: config::preserved_env_file_patterns(config),
+            excluded_dirs,
+        }
+    }
+
+    /// Whether the file at `relative_path` (relative to the build directory) is listed
+    fn includes(&self, relative_path: &Path) -> bool {
+        let name = relative_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let bookkeeping = [CHECKSUMS_FILE, BUILD_MARKER_FILE, BUILD_MANIFEST_FILE].contains(&name);
+        !bookkeeping && self.is_output(relative_path)
+    }
+
+    /// Whether the file at `relative_path` was written by the build rather than preserved or backed up
+    pub(crate) fn is_output(&self, relative_path: &Path) -> bool {
+        let name = relative_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        !self.env_file_patterns.iter().any(|pattern| pattern == name)
+            && !self.excluded_dirs.iter().any(|dir| relative_path.starts_with(dir))
+    }
+
+    /// Listed files below `dir` with their hashes, keyed by `/`-separated path relative to `dir`.
+    /// `prefix` is the path of `dir` relative to the build directory.
+    fn hash_files(&self, dir: &Path, prefix: &Path) -> Result<BTreeMap<String, String>> {
+        let mut files = Vec::new();
+        collect_files(dir, dir, &mut files)?;
+
+        let mut hashes = BTreeMap::new();
+        for relative_path in files {
+            if self.includes(&prefix.join(&relative_path)) {
+                let hash = hash_file(&dir.join(&relative_path))?;
+                hashes.insert(relative_path.to_string_lossy().replace('\\', "/"), hash);
+            }
+        }
+        Ok(hashes)
+    }
+}
+
+/// Result of checking a build directory against its checksums manifest
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Number of files listed in the manifest
+    pub checked: usize,
+    /// Listed files whose content changed
+    pub modified: Vec<String>,
+    /// Listed files that no longer exist
+    pub missing: Vec<String>,
+    /// Files in scope that the manifest does not list
+    pub extra: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Whether the build directory matches the manifest exactly
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+/// Write `CHECKSUMS.sha256` into every combination directory and the build directory.
+/// `combination_dirs` are relative to `build_dir`; the build root itself is only covered by the top-level manifest,
+/// and combinations that produced no directory get none.
+pub fn write_checksums(build_dir: &Path, combination_dirs: &[&str], scope: &ChecksumScope) -> Result<()> {
+    for combination_dir in combination_dirs.iter().filter(|dir| !dir.is_empty()) {
+        let dir = build_dir.join(combination_dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        write_manifest(&dir, &scope.hash_files(&dir, Path::new(combination_dir))?)?;
+    }
+    write_manifest(build_dir, &scope.hash_files(build_dir, Path::new(""))?)
+}
+
+/// Recompute the hashes of `build_dir` and compare them with its `CHECKSUMS.sha256`
+pub fn verify_checksums(build_dir: &Path, scope: &ChecksumScope) -> Result<VerifyReport> {
+    let manifest_path = build_dir.join(CHECKSUMS_FILE);
+    if !manifest_path.is_file() {
+        return Err(BuildError::ChecksumsNotFound { path: manifest_path }.into());
+    }
+    let content = fs::read_to_string(&manifest_path)
+        .map_err(|e| FileSystemError::FileReadFailed {
+            path: manifest_path.clone(),
+            source: e,
+        })?;
+    let expected = parse_checksums(&content);
+    let actual = scope.hash_files(build_dir, Path::new(""))?;
+
+    let mut report = VerifyReport { checked: expected.len(), ..VerifyReport::default() };
+    for (path, hash) in &expected {
+        match actual.get(path) {
+            Some(actual_hash) if actual_hash == hash => {}
+            Some(_) => report.modified.push(path.clone()),
+            // Listed files are looked up directly, so an excluded name that was listed is still checked
+            None if build_dir.join(path).is_file() => {
+                if hash_file(&build_dir.join(path))? != *hash {
+                    report.modified.push(path.clone());
+                }
+            }
+            None => report.missing.push(path.clone()),
+        }
+    }
+    report.extra = actual.into_keys().filter(|path| !expected.contains_key(path)).collect();
+
+    Ok(report)
+}
+
+/// Parse `<sha256>  <path>` lines; `*` before the path (binary mode of `sha256sum`) is accepted
+pub fn parse_checksums(content: &str) -> BTreeMap<String, String> {
+    content.lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, path)| {
+            let path = path.strip_prefix(' ').or_else(|| path.strip_prefix('*')).unwrap_or(path);
+            (path.to_string(), hash.to_string())
+        })
+        .collect()
+}
+
+/// Runs the verify command logic
+pub fn run_verify(args: &VerifyArgs) -> Result<()> {
+    let mut config = config::load_config()?;
+    let project_dir = std::env::current_dir()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to determine current directory: {}", e),
+        })?;
+    if let Some(ref build_dir) = args.build_dir {
+        config.paths.build_dir = build_dir.to_string_lossy().to_string();
+    }
+    ResolvedPaths::new(&config, &project_dir).apply_to(&mut config);
+
+    let build_dir = PathBuf::from(&config.paths.build_dir);
+    let report = verify_checksums(&build_dir, &ChecksumScope::from_config(&config))?;
+
+    for (label, paths) in [("Modified", &report.modified), ("Missing", &report.missing), ("Extra", &report.extra)] {
+        if !paths.is_empty() {
+            println!("{} files:", label);
+            for path in paths {
+                println!("  - {}", path);
+            }
+        }
+    }
+
+    if !report.is_clean() {
+        return Err(BuildError::ChecksumMismatch {
+            modified: report.modified.len(),
+            missing: report.missing.len(),
+            extra: report.extra.len(),
+        }.into());
+    }
+
+    println!("✓ {} files match {}", report.checked, build_dir.join(CHECKSUMS_FILE).display());
+    Ok(())
+}
+
+fn write_manifest(dir: &Path, hashes: &BTreeMap<String, String>) -> Result<()> {
+    let content: String = hashes.iter()
+        .map(|(path, hash)| format!("{}  {}\n", hash, path))
+        .collect();
+    let path = dir.join(CHECKSUMS_FILE);
+    fs::write(&path, content)
+        .map_err(|e| FileSystemError::FileWriteFailed {
+            path,
+            source: e,
+        }.into())
+}
+
+pub(crate) fn hash_file(path: &Path) -> Result<String> {
+    let content = fs::read(path)
+        .map_err(|e| FileSystemError::FileReadFailed {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+    Ok(format!("{:x}", Sha256::digest(&content)))
+}
+
+/// Collect the files below `dir`, relative to `root`; symlinks to files count as files, symlinks to directories are skipped
+pub(crate) fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: dir.to_path_buf(),
+            source: e,
+        })?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: dir.to_path_buf(),
+            source: e,
+        })?;
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: path.clone(),
+            source: e,
+        })?;
+        // Symlinked directories are not followed, so a link to a parent cannot recurse forever
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if !(file_type.is_symlink() && path.is_dir()) {
+            files.push(path.strip_prefix(root).map(Path::to_path_buf).unwrap_or(path));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Parser, Debug, Default)]
+pub struct VerifyArgs {
+    /// Build directory to verify instead of the configured build_dir
+    #[arg(long, value_name = "DIR")]
+    pub build_dir: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_verify_checksums() {
+        let temp = tempfile::tempdir().unwrap();
+        let build = temp.path();
+        fs::create_dir_all(build.join("dev/config")).unwrap();
+        fs::write(build.join("dev/docker-compose.yml"), "services: {}\n").unwrap();
+        fs::write(build.join("dev/config/app.conf"), "port=80\n").unwrap();
+        fs::write(build.join("dev/.env"), "SECRET=1\n").unwrap();
+        fs::write(build.join(BUILD_MARKER_FILE), "").unwrap();
+        let scope = ChecksumScope { env_file_patterns: vec![".env".to_string()], excluded_dirs: vec![] };
+
+        write_checksums(build, &["dev"], &scope).unwrap();
+
+        let root = parse_checksums(&fs::read_to_string(build.join(CHECKSUMS_FILE)).unwrap());
+        assert_eq!(root.keys().collect::<Vec<_>>(), vec!["dev/config/app.conf", "dev/docker-compose.yml"]);
+        assert_eq!(root["dev/docker-compose.yml"], format!("{:x}", Sha256::digest(b"services: {}\n")));
+        let combination = fs::read_to_string(build.join("dev").join(CHECKSUMS_FILE)).unwrap();
+        assert!(combination.ends_with("  docker-compose.yml\n"), "{}", combination);
+        assert!(verify_checksums(build, &scope).unwrap().is_clean());
+
+        // Secrets may change freely; generated files may not
+        fs::write(build.join("dev/.env"), "SECRET=2\n").unwrap();
+        fs::write(build.join("dev/docker-compose.yml"), "services: {web: {}}\n").unwrap();
+        fs::remove_file(build.join("dev/config/app.conf")).unwrap();
+        fs::write(build.join("dev/notes.txt"), "").unwrap();
+
+        let report = verify_checksums(build, &scope).unwrap();
+        assert_eq!(report.modified, vec!["dev/docker-compose.yml"]);
+        assert_eq!(report.missing, vec!["dev/config/app.conf"]);
+        assert_eq!(report.extra, vec!["dev/notes.txt"]);
+        assert_eq!(report.checked, 2);
+    }
+
+    #[test]
+    fn test_verify_without_manifest() {
+        let temp = tempfile::tempdir().unwrap();
+        let error = verify_checksums(temp.path(), &ChecksumScope::default()).unwrap_err();
+        assert_eq!(error.code(), "build.checksums_not_found");
+    }
+
+    #[test]
+    fn test_parse_checksums_binary_marker() {
+        let parsed = parse_checksums("abc  a b.txt\ndef *bin/tool\n");
+        assert_eq!(parsed["a b.txt"], "abc");
+        assert_eq!(parsed["bin/tool"], "def");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_does_not_follow_symlinked_directories() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("dev")).unwrap();
+        fs::write(dir.join("dev/docker-compose.yml"), "services: {}\n").unwrap();
+        std::os::unix::fs::symlink("docker-compose.yml", dir.join("dev/compose.yml")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("dev/parent")).unwrap();
+
+        let mut files = Vec::new();
+        collect_files(dir, dir, &mut files).unwrap();
+        files.sort();
+        assert_eq!(files, [PathBuf::from("dev/compose.yml"), PathBuf::from("dev/docker-compose.yml")]);
+    }
+}
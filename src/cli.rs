@@ -0,0 +1,173 @@
+//! The command line of the `stackbuilder` binary
+
+use clap::{Parser, Subcommand, ValueEnum};
+use crate::{archive, backups, build, checksums, diagnostics, init, manifest, upgrade_config, validate, watch};
+use crate::error::{StackBuilderError, Result};
+
+#[derive(Parser)]
+#[command(name = "stackbuilder")]
+#[command(version)]
+#[command(about = "A tool for building docker-compose files from modular components")]
+#[command(long_about = "Stackbuilder is a CLI tool designed to build docker-compose files from modular components.\n\nExamples:\n  stackbuilder init --name my-project\n  stackbuilder build --config ./config.yml")]
+struct Cli {
+    /// How errors are reported on stderr
+    #[arg(long, global = true, value_enum, default_value_t = ErrorFormat::Text)]
+    error_format: ErrorFormat,
+
+    #[command(subcommand)]
+    command: Commands,
+}
+
+/// Error output format
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ErrorFormat {
+    /// Message, suggestion and hints for humans
+    Text,
+    /// A single JSON object with code, message, suggestion, path and exit code
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Initialize a new stackbuilder project with default configuration and folder structure
+    Init(init::InitArgs),
+    /// Build docker-compose files by merging base, environment and extension components
+    Build(build::BuildArgs),
+    /// Build, then rebuild whenever the components or the configuration change
+    Watch(watch::WatchArgs),
+    /// Clean the build directory, backing up preserved .env files
+    Clean(build::CleanArgs),
+    /// List and restore .env backups created during build directory cleanup
+    Backups(backups::BackupsArgs),
+    /// Restore the .env files of the newest backup into the build directory
+    Repair(backups::RepairArgs),
+    /// Validate the configuration and project structure without building
+    Validate(validate::ValidateArgs),
+    /// Check the build directory against its CHECKSUMS.sha256 manifest
+    Verify(checksums::VerifyArgs),
+    /// Pack the build directory into a reproducible tar.gz archive
+    Archive(archive::ArchiveArgs),
+    /// Render the build combinations for other deployment tools
+    #[cfg(feature = "argocd")]
+    Export(crate::export::ExportArgs),
+    /// Print a JSON inventory of the base, environment and extension components and their files
+    Manifest(manifest::ManifestArgs),
+    /// Show the effective configuration
+    Config(build::ConfigArgs),
+    /// Migrate a legacy configuration (targets, environments array) to the new environments API
+    UpgradeConfig(upgrade_config::UpgradeConfigArgs),
+}
+
+fn run_build(args: &build::BuildArgs) -> Result<()> {
+    if let Some(format) = args.print_config {
+        return build::execute_print_config(args, format);
+    }
+    match args.list_files {
+        Some(ref combination) => build::execute_list_files(args, combination),
+        None => build::execute_build(args).map(|_| ()),
+    }
+}
+
+fn run_watch(args: &watch::WatchArgs) -> Result<()> {
+    watch::run_watch(args)
+}
+
+fn run_clean(args: &build::CleanArgs) -> Result<()> {
+    build::execute_clean(args)
+}
+
+fn run_init(args: &init::InitArgs) -> Result<()> {
+    init::run_init(args)
+}
+
+fn run_backups(args: &backups::BackupsArgs) -> Result<()> {
+    backups::run_backups(args)
+}
+
+fn run_repair(args: &backups::RepairArgs) -> Result<()> {
+    backups::run_repair(args)
+}
+
+fn run_validate(args: &validate::ValidateArgs) -> Result<()> {
+    validate::run_validate(args)
+}
+
+fn run_verify(args: &checksums::VerifyArgs) -> Result<()> {
+    checksums::run_verify(args)
+}
+
+fn run_archive(args: &archive::ArchiveArgs) -> Result<()> {
+    archive::run_archive(args)
+}
+
+#[cfg(feature = "argocd")]
+fn run_export(args: &crate::export::ExportArgs) -> Result<()> {
+    crate::export::run_export(args)
+}
+
+fn run_manifest(args: &manifest::ManifestArgs) -> Result<()> {
+    manifest::run_manifest(args)
+}
+
+fn run_config(args: &build::ConfigArgs) -> Result<()> {
+    build::execute_config(args)
+}
+
+fn run_upgrade_config(args: &upgrade_config::UpgradeConfigArgs) -> Result<()> {
+    upgrade_config::run_upgrade_config(args)
+}
+
+fn print_error(error: &StackBuilderError, format: ErrorFormat, github: bool) {
+    if format == ErrorFormat::Json {
+        eprintln!("{}", error.to_json());
+        return;
+    }
+
+    if github {
+        diagnostics::print_github_annotations(error);
+    }
+
+    eprintln!("Error: {}", error);
+    
+    // Print suggestion if available
+    if let Some(suggestion) = error.suggestion() {
+        eprintln!("\nSuggestion: {}", suggestion);
+    }
+    
+    // Add context for common error patterns
+    if error.suggests_init() {
+        eprintln!("\nTo create a new project, run:");
+        eprintln!("  stackbuilder init");
+    }
+}
+
+/// Run the command given on the command line and exit with its exit code when it fails
+pub fn run() {
+    let cli = Cli::parse();
+    let github = match cli.command {
+        Commands::Build(ref args) | Commands::Watch(watch::WatchArgs { build: ref args, .. }) => args.log_format() == build::LogFormat::Github,
+        _ => false,
+    };
+
+    let result = match cli.command {
+        Commands::Init(args) => run_init(&args),
+        Commands::Build(args) => run_build(&args),
+        Commands::Watch(args) => run_watch(&args),
+        Commands::Clean(args) => run_clean(&args),
+        Commands::Backups(args) => run_backups(&args),
+        Commands::Repair(args) => run_repair(&args),
+        Commands::Validate(args) => run_validate(&args),
+        Commands::Verify(args) => run_verify(&args),
+        Commands::Archive(args) => run_archive(&args),
+        #[cfg(feature = "argocd")]
+        Commands::Export(args) => run_export(&args),
+        Commands::Manifest(args) => run_manifest(&args),
+        Commands::Config(args) => run_config(&args),
+        Commands::UpgradeConfig(args) => run_upgrade_config(&args),
+    };
+
+    if let Err(error) = result {
+        print_error(&error, cli.error_format, github);
+        std::process::exit(error.exit_code());
+    }
+}
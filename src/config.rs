@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use crate::error::{Result, ConfigError, ValidationError, FileSystemError};
+use std::collections::{BTreeMap, HashMap};
+use crate::error::{Result, StackBuilderError, ConfigError, ValidationError, FileSystemError};
+use crate::diagnostics::{self, WarningCategory};
+use crate::paths::ResolvedPaths;
 
 /// YAML merger type configuration
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
@@ -13,33 +15,387 @@ pub enum YamlMergerType {
     Rust,
 }
 
+/// Storage format for .env file backups
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackupFormat {
+    /// Loose files with mangled names plus metadata.json in a backup directory (default)
+    #[default]
+    Dir,
+    /// Single compressed archive keeping original relative paths plus metadata.json
+    TarGz,
+}
+
+/// How FileCopier writes identical files shared by several combinations
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupCopies {
+    /// Copy file contents into every combination (default)
+    #[default]
+    Copy,
+    /// Hardlink files to a content-addressed store under .stackbuilder/objects
+    Hardlink,
+}
+
+/// Merge strategy for a top-level compose section in the Rust merger
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Recursively merge mappings and append sequences
+    #[default]
+    Deep,
+    /// Replace the whole section with the later file's value
+    Replace,
+    /// Replace entries by name and append sequences
+    Append,
+    /// Add only entries whose name or value is not present yet
+    UniqueAppend,
+}
+
+/// How configuration validation handles problems
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValidationMode {
+    /// Check everything and report all problems together (default)
+    #[default]
+    Collect,
+    /// Stop at the first problem
+    FailFast,
+}
+
+/// Serialization of the merged compose document written for each combination
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// docker-compose.yml (default)
+    #[default]
+    Yaml,
+    /// docker-compose.json
+    Json,
+}
+
+impl OutputFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            OutputFormat::Yaml => "docker-compose.yml",
+            OutputFormat::Json => "docker-compose.json",
+        }
+    }
+}
+
+/// What is written for the compose files of each combination
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputMode {
+    /// One merged compose file per output format (default)
+    #[default]
+    Merged,
+    /// The source compose files copied verbatim as numbered layers, plus compose.sh chaining them
+    Layers,
+}
+
+/// How the Rust merger writes `labels`, which are merged by key whatever form each file uses
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelStyle {
+    /// `key: value` entries (default)
+    #[default]
+    Map,
+    /// `- key=value` entries
+    List,
+}
+
+/// How the Rust merger writes `ports`, which are de-duplicated across the short and long syntax
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PortStyle {
+    /// Each entry in the syntax of its last declaration (default)
+    #[default]
+    Preserve,
+    /// `"HOST_IP:PUBLISHED:TARGET/PROTOCOL"` strings, except entries with options only the long syntax has
+    Short,
+    /// `target:`/`published:` mappings
+    Long,
+}
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 pub struct Config {
+    #[serde(default, skip_serializing_if = "ProjectConfig::is_empty")]
+    pub project: ProjectConfig,
     #[serde(default)]
     pub paths: Paths,
     #[serde(default)]
     pub build: BuildConfig,
+    /// The user configuration the `[build]` defaults were loaded from, if any
+    #[serde(skip)]
+    pub user_defaults: Option<UserDefaults>,
+}
+
+/// `[build]` values taken from the user configuration because the project does not set them
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserDefaults {
+    pub path: std::path::PathBuf,
+    /// Keys of the `[build]` table, sorted
+    pub keys: Vec<String>,
+}
+
+/// Project metadata
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct ProjectConfig {
+    /// Project name, used as the default Compose project name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl ProjectConfig {
+    fn is_empty(&self) -> bool {
+        self.name.is_none()
+    }
+}
+
+impl Config {
+    /// Parse a configuration from TOML text, without touching the filesystem.
+    /// `include_files` can only be resolved when loading from a file and are ignored here.
+    pub fn from_toml_str(content: &str) -> Result<Config> {
+        let config: Config = toml::from_str(content)
+            .map_err(|e| ConfigError::toml_parse_error("<string>", content, e))?;
+        if !config.build.include_files.is_empty() {
+            diagnostics::warn(WarningCategory::Config, "include_files are only resolved when the configuration is loaded from a file and are ignored");
+        }
+        Ok(config)
+    }
+
+    /// Start building a configuration in code; unset options keep their defaults
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// A copy with the values of `[build.variables]`, `[build.combo_variables]` and the environments' `variables`
+    /// replaced by [`REDACTED_VALUE`], since they may hold secrets
+    pub fn redacted(&self) -> Config {
+        let mut config = self.clone();
+        let redact = |variables: &mut BTreeMap<String, String>| variables.values_mut().for_each(|value| *value = REDACTED_VALUE.to_string());
+        redact(&mut config.build.variables);
+        config.build.combo_variables.values_mut().for_each(redact);
+        if let Some(ref mut environments) = config.build.environments_config {
+            environments.environment_configs.values_mut().filter_map(|env| env.variables.as_mut()).for_each(redact);
+        }
+        config
+    }
+
+    /// Run the checks of `validate_config` with relative paths resolved against `root` instead of the current directory
+    pub fn validate_against(&self, root: &std::path::Path) -> Result<()> {
+        let mut rooted = self.clone();
+        ResolvedPaths::new(self, root).apply_to(&mut rooted);
+        validate_config(&rooted, self.build.validation_mode)
+    }
+}
+
+/// Shown instead of variable values by [`Config::redacted`]
+pub const REDACTED_VALUE: &str = "<redacted>";
+
+/// Builds a [`Config`] in code, for tests and library users
+#[derive(Debug, Default, Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Project name, used as the default Compose project name
+    pub fn with_project_name(mut self, name: impl Into<String>) -> Self {
+        self.config.project.name = Some(name.into());
+        self
+    }
+
+    /// Directory holding all components
+    pub fn with_components_dir(mut self, components_dir: impl Into<String>) -> Self {
+        self.config.paths.components_dir = components_dir.into();
+        self
+    }
+
+    /// Further components roots searched after the components directory
+    pub fn with_components_dirs<S: Into<String>>(mut self, components_dirs: impl IntoIterator<Item = S>) -> Self {
+        self.config.paths.components_dirs = components_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Base component directory, relative to the components directory
+    pub fn with_base_dir(mut self, base_dir: impl Into<String>) -> Self {
+        self.config.paths.base_dir = base_dir.into();
+        self
+    }
+
+    /// Environments directory, relative to the components directory
+    pub fn with_environments_dir(mut self, environments_dir: impl Into<String>) -> Self {
+        self.config.paths.environments_dir = environments_dir.into();
+        self
+    }
+
+    /// Extensions directories, relative to the components directory
+    pub fn with_extensions_dirs<S: Into<String>>(mut self, extensions_dirs: impl IntoIterator<Item = S>) -> Self {
+        self.config.paths.extensions_dirs = extensions_dirs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Output directory of the build
+    pub fn with_build_dir(mut self, build_dir: impl Into<String>) -> Self {
+        self.config.paths.build_dir = build_dir.into();
+        self
+    }
+
+    /// Environments to build
+    pub fn with_environments<S: Into<String>>(mut self, environments: impl IntoIterator<Item = S>) -> Self {
+        self.config.build.environments = Some(environments.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Extensions to build, each on its own
+    pub fn with_extensions<S: Into<String>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.config.build.extensions = Some(extensions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Define a named combo of extensions
+    pub fn with_combo<S: Into<String>>(mut self, name: impl Into<String>, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.config.build.combos.insert(name.into(), extensions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Merger used for compose files
+    pub fn with_yaml_merger(mut self, yaml_merger: YamlMergerType) -> Self {
+        self.config.build.yaml_merger = yaml_merger;
+        self
+    }
+
+    /// Whether the base component must exist
+    pub fn with_require_base(mut self, require_base: bool) -> Self {
+        self.config.build.require_base = require_base;
+        self
+    }
+
+    /// Replace all build options at once
+    pub fn with_build_config(mut self, build: BuildConfig) -> Self {
+        self.config.build = build;
+        self
+    }
+
+    /// Finish the configuration
+    pub fn build(self) -> Config {
+        self.config
+    }
 }
 
 // Use custom deserializer to handle both APIs
 #[derive(Serialize, Debug, Clone)]
 pub struct BuildConfig {
     // All fields unified
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub environments: Option<Vec<String>>,
     pub extensions: Option<Vec<String>>,
     pub combos: HashMap<String, Vec<String>>,
     pub targets: Option<BuildTargets>,
+    /// The `environments` table of the environments API; only one of the two forms is set
+    #[serde(rename = "environments", skip_serializing_if = "Option::is_none")]
     pub environments_config: Option<BuildEnvironments>,
     pub yaml_merger: YamlMergerType,
     pub copy_env_example: bool,
+    /// File each component provides for the env merger to read
+    pub env_source_filename: String,
+    /// File the merged env content is written to in every output directory
+    pub env_output_filename: String,
     pub copy_additional_files: bool,
     pub exclude_patterns: Vec<String>,
     pub preserve_env_files: bool,
     pub env_file_patterns: Vec<String>,
     pub backup_dir: String,
+    pub backup_format: BackupFormat,
+    pub lock_timeout: u64,
+    pub write_gitignore: bool,
+    pub write_checksums: bool,
+    pub dedup_copies: DedupCopies,
+    pub merge_strategy: BTreeMap<String, MergeStrategy>,
+    pub inline_env_files: bool,
+    pub rewrite_build_context: bool,
+    pub prune_empty_collections: bool,
+    /// Form of the merged `labels` of services and top-level resources
+    pub label_style: LabelStyle,
+    /// Syntax of the merged `ports` of services
+    pub port_style: PortStyle,
+    pub combo_name_separator: String,
+    pub yq_retries: u32,
+    pub yq_retry_backoff_ms: u64,
+    pub output_formats: Vec<OutputFormat>,
+    pub output_mode: OutputMode,
+    /// Write the compose files as separate documents of one YAML stream instead of merging them
+    pub multi_document: bool,
+    pub require_base: bool,
     pub skip_base_generation: bool,
+    pub strict_categories: Vec<WarningCategory>,
+    pub generated_header: Option<String>,
+    pub provenance_header: bool,
+    pub include_git_info: bool,
+    pub project_name_template: Option<String>,
+    pub include_files: Vec<String>,
+    pub validation_mode: ValidationMode,
+    pub default_environment: Option<String>,
+    /// tar.gz archive of the build directory written after every successful build, relative to the project directory
+    pub archive_path: Option<String>,
+    /// Write up.sh and down.sh running docker compose into every combination directory
+    pub generate_helper_scripts: bool,
+    /// Template the helper scripts are rendered from instead of the built-in one, relative to the project directory
+    pub helper_script_template: Option<String>,
+    /// Shell command run in the project directory before the combinations are determined, e.g. to generate components
+    pub pre_build_command: Option<String>,
+    /// Command `build --check-images` runs per image, with `{image}` replaced by the reference
+    pub image_check_command: Option<String>,
+    /// Convert every merged compose file to Kubernetes manifests with kompose, below `k8s/` in the build directory
+    pub kubernetes_output: bool,
+    /// Fail instead of warning when two extensions of a combination define the same service differently
+    pub fail_on_service_conflicts: bool,
+    /// Groups of alternative extensions; every environment gets one combination per element of their cartesian product
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub matrix: BTreeMap<String, Vec<String>>,
+    /// Largest number of combinations `matrix` may expand to
+    pub max_combinations: usize,
+    /// Groups of extensions of which at most one may be part of any single combination, e.g. alternative databases
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub exclusive_groups: BTreeMap<String, Vec<String>>,
+    /// Output directory of a combination, e.g. `prod/security`, mapped to the directory it is written to instead
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
+    /// Variables of every combination, overridden by environment and combo variables
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Variables of the combinations applying a named combo, keyed by combo name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub combo_variables: BTreeMap<String, BTreeMap<String, String>>,
+    /// Substitute the combination variables into the compose files instead of leaving `${NAME}` to docker compose
+    pub resolve_env: bool,
+    /// Build the other combinations when one fails and report the failures at the end
+    pub keep_going: bool,
+    /// Keys of the `[build]` table that are not options
+    #[serde(skip)]
+    pub unknown_keys: Vec<String>,
 }
 
+/// Options of the `[build]` table, for typo hints on unknown keys
+pub const BUILD_KEYS: &[&str] = &[
+    "environments", "extensions", "combos", "targets", "yaml_merger", "copy_env_example",
+    "env_source_filename", "env_output_filename",
+    "copy_additional_files", "exclude_patterns", "preserve_env_files", "env_file_patterns", "backup_dir",
+    "backup_format", "lock_timeout", "write_gitignore", "write_checksums", "dedup_copies", "merge_strategy",
+    "inline_env_files", "rewrite_build_context", "prune_empty_collections", "label_style", "port_style", "combo_name_separator",
+    "yq_retries", "yq_retry_backoff_ms", "output_formats", "output_format", "output_mode", "multi_document", "require_base",
+    "skip_base_generation", "strict_categories", "generated_header", "provenance_header", "include_git_info",
+    "project_name_template", "include_files", "validation_mode", "default_environment", "fail_fast", "keep_going", "archive_path",
+    "generate_helper_scripts", "helper_script_template", "pre_build_command", "image_check_command", "kubernetes_output", "fail_on_service_conflicts", "matrix", "max_combinations", "exclusive_groups", "aliases", "variables", "combo_variables", "resolve_env",
+];
+
+/// Options of the `[paths]` table, for typo hints on unknown keys
+pub const PATHS_KEYS: &[&str] = &[
+    "components_dir", "components_dirs", "base_dir", "environments_dir", "extensions_dirs", "build_dir",
+];
+
 impl<'de> Deserialize<'de> for BuildConfig {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
@@ -67,54 +423,236 @@ impl<'de> Deserialize<'de> for BuildConfig {
                 let mut targets: Option<BuildTargets> = None;
                 let mut yaml_merger: Option<YamlMergerType> = None;
                 let mut copy_env_example: Option<bool> = None;
+                let mut env_source_filename: Option<String> = None;
+                let mut env_output_filename: Option<String> = None;
                 let mut copy_additional_files: Option<bool> = None;
                 let mut exclude_patterns: Option<Vec<String>> = None;
                 let mut preserve_env_files: Option<bool> = None;
                 let mut env_file_patterns: Option<Vec<String>> = None;
                 let mut backup_dir: Option<String> = None;
+                let mut backup_format: Option<BackupFormat> = None;
+                let mut lock_timeout: Option<u64> = None;
+                let mut write_gitignore: Option<bool> = None;
+                let mut write_checksums: Option<bool> = None;
+                let mut unknown_keys: Vec<String> = Vec::new();
+                let mut dedup_copies: Option<DedupCopies> = None;
+                let mut merge_strategy: BTreeMap<String, MergeStrategy> = BTreeMap::new();
+                let mut inline_env_files: Option<bool> = None;
+                let mut rewrite_build_context: Option<bool> = None;
+                let mut prune_empty_collections: Option<bool> = None;
+                let mut label_style: Option<LabelStyle> = None;
+                let mut port_style: Option<PortStyle> = None;
+                let mut combo_name_separator: Option<String> = None;
+                let mut yq_retries: Option<u32> = None;
+                let mut yq_retry_backoff_ms: Option<u64> = None;
+                let mut output_format: Option<OutputFormat> = None;
+                let mut output_formats: Option<Vec<OutputFormat>> = None;
+                let mut output_mode: Option<OutputMode> = None;
+                let mut multi_document: Option<bool> = None;
+                let mut require_base: Option<bool> = None;
                 let mut skip_base_generation: Option<bool> = None;
-
-                while let Some(key) = map.next_key::<String>().map_err(serde::de::Error::custom)? {
+                let mut strict_categories: Option<Vec<WarningCategory>> = None;
+                let mut generated_header: Option<String> = None;
+                let mut provenance_header: Option<bool> = None;
+                let mut include_git_info: Option<bool> = None;
+                let mut project_name_template: Option<String> = None;
+                let mut include_files: Option<Vec<String>> = None;
+                let mut validation_mode: Option<ValidationMode> = None;
+                let mut default_environment: Option<String> = None;
+                let mut archive_path: Option<String> = None;
+                let mut generate_helper_scripts: Option<bool> = None;
+                let mut helper_script_template: Option<String> = None;
+                let mut pre_build_command: Option<String> = None;
+                let mut image_check_command: Option<String> = None;
+                let mut kubernetes_output: Option<bool> = None;
+                let mut fail_on_service_conflicts: Option<bool> = None;
+                let mut matrix: BTreeMap<String, Vec<String>> = BTreeMap::new();
+                let mut max_combinations: Option<usize> = None;
+                let mut exclusive_groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+                let mut aliases: BTreeMap<String, String> = BTreeMap::new();
+                let mut variables: BTreeMap<String, String> = BTreeMap::new();
+                let mut combo_variables: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+                let mut resolve_env: Option<bool> = None;
+                let mut fail_fast: Option<bool> = None;
+                let mut keep_going: Option<bool> = None;
+
+                while let Some(key) = map.next_key::<String>()? {
                     match key.as_str() {
                         "environments" => {
-                            environments = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            environments = Some(map.next_value()?);
                         }
                         "extensions" => {
-                            extensions = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            extensions = Some(map.next_value()?);
                         }
                         "combos" => {
-                            combos = map.next_value().map_err(serde::de::Error::custom)?;
+                            combos = map.next_value()?;
                         }
                         "targets" => {
-                            targets = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            targets = Some(map.next_value()?);
                         }
                         "yaml_merger" => {
-                            yaml_merger = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            yaml_merger = Some(map.next_value()?);
                         }
                         "copy_env_example" => {
-                            copy_env_example = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            copy_env_example = Some(map.next_value()?);
+                        }
+                        "env_source_filename" => {
+                            env_source_filename = Some(map.next_value()?);
+                        }
+                        "env_output_filename" => {
+                            env_output_filename = Some(map.next_value()?);
                         }
                         "copy_additional_files" => {
-                            copy_additional_files = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            copy_additional_files = Some(map.next_value()?);
                         }
                         "exclude_patterns" => {
-                            exclude_patterns = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            exclude_patterns = Some(map.next_value()?);
                         }
                         "preserve_env_files" => {
-                            preserve_env_files = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            preserve_env_files = Some(map.next_value()?);
                         }
                         "env_file_patterns" => {
-                            env_file_patterns = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            env_file_patterns = Some(map.next_value()?);
                         }
                         "backup_dir" => {
-                            backup_dir = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            backup_dir = Some(map.next_value()?);
+                        }
+                        "backup_format" => {
+                            backup_format = Some(map.next_value()?);
+                        }
+                        "lock_timeout" => {
+                            lock_timeout = Some(map.next_value()?);
+                        }
+                        "write_gitignore" => {
+                            write_gitignore = Some(map.next_value()?);
+                        }
+                        "write_checksums" => {
+                            write_checksums = Some(map.next_value()?);
+                        }
+                        "dedup_copies" => {
+                            dedup_copies = Some(map.next_value()?);
+                        }
+                        "merge_strategy" => {
+                            merge_strategy = map.next_value()?;
+                        }
+                        "inline_env_files" => {
+                            inline_env_files = Some(map.next_value()?);
+                        }
+                        "rewrite_build_context" => {
+                            rewrite_build_context = Some(map.next_value()?);
+                        }
+                        "prune_empty_collections" => {
+                            prune_empty_collections = Some(map.next_value()?);
+                        }
+                        "label_style" => {
+                            label_style = Some(map.next_value()?);
+                        }
+                        "port_style" => {
+                            port_style = Some(map.next_value()?);
+                        }
+                        "combo_name_separator" => {
+                            combo_name_separator = Some(map.next_value()?);
+                        }
+                        "yq_retries" => {
+                            yq_retries = Some(map.next_value()?);
+                        }
+                        "yq_retry_backoff_ms" => {
+                            yq_retry_backoff_ms = Some(map.next_value()?);
+                        }
+                        "output_format" => {
+                            output_format = Some(map.next_value()?);
+                        }
+                        "output_formats" => {
+                            output_formats = Some(map.next_value()?);
+                        }
+                        "output_mode" => {
+                            output_mode = Some(map.next_value()?);
+                        }
+                        "multi_document" => {
+                            multi_document = Some(map.next_value()?);
+                        }
+                        "require_base" => {
+                            require_base = Some(map.next_value()?);
                         }
                         "skip_base_generation" => {
-                            skip_base_generation = Some(map.next_value().map_err(serde::de::Error::custom)?);
+                            skip_base_generation = Some(map.next_value()?);
+                        }
+                        "strict_categories" => {
+                            strict_categories = Some(map.next_value()?);
+                        }
+                        "generated_header" => {
+                            generated_header = Some(map.next_value()?);
+                        }
+                        "fail_fast" => {
+                            fail_fast = Some(map.next_value()?);
+                        }
+                        "keep_going" => {
+                            keep_going = Some(map.next_value()?);
+                        }
+                        "provenance_header" => {
+                            provenance_header = Some(map.next_value()?);
+                        }
+                        "include_git_info" => {
+                            include_git_info = Some(map.next_value()?);
+                        }
+                        "project_name_template" => {
+                            project_name_template = Some(map.next_value()?);
+                        }
+                        "include_files" => {
+                            include_files = Some(map.next_value()?);
+                        }
+                        "validation_mode" => {
+                            validation_mode = Some(map.next_value()?);
+                        }
+                        "default_environment" => {
+                            default_environment = Some(map.next_value()?);
+                        }
+                        "archive_path" => {
+                            archive_path = Some(map.next_value()?);
+                        }
+                        "generate_helper_scripts" => {
+                            generate_helper_scripts = Some(map.next_value()?);
+                        }
+                        "helper_script_template" => {
+                            helper_script_template = Some(map.next_value()?);
+                        }
+                        "pre_build_command" => {
+                            pre_build_command = Some(map.next_value()?);
+                        }
+                        "image_check_command" => {
+                            image_check_command = Some(map.next_value()?);
+                        }
+                        "kubernetes_output" => {
+                            kubernetes_output = Some(map.next_value()?);
+                        }
+                        "fail_on_service_conflicts" => {
+                            fail_on_service_conflicts = Some(map.next_value()?);
+                        }
+                        "matrix" => {
+                            matrix = map.next_value()?;
+                        }
+                        "max_combinations" => {
+                            max_combinations = Some(map.next_value()?);
+                        }
+                        "exclusive_groups" => {
+                            exclusive_groups = map.next_value()?;
+                        }
+                        "aliases" => {
+                            aliases = map.next_value()?;
+                        }
+                        "variables" => {
+                            variables = map.next_value()?;
+                        }
+                        "combo_variables" => {
+                            combo_variables = map.next_value()?;
+                        }
+                        "resolve_env" => {
+                            resolve_env = Some(map.next_value()?);
                         }
                         _ => {
-                            // Skip unknown fields
-                            let _: serde_json::Value = map.next_value().map_err(serde::de::Error::custom)?;
+                            // Skip unknown fields, remembering them for the typo check of validation
+                            let _: serde_json::Value = map.next_value()?;
+                            unknown_keys.push(key);
                         }
                     }
                 }
@@ -138,6 +676,15 @@ impl<'de> Deserialize<'de> for BuildConfig {
                     (None, None)
                 };
 
+                // keep_going is the inverse of fail_fast, so only one of them may be set
+                let keep_going = match (fail_fast, keep_going) {
+                    (Some(_), Some(_)) => {
+                        return Err(serde::de::Error::custom("fail_fast and keep_going are opposites, set only one of them"));
+                    }
+                    (Some(fail_fast), None) => !fail_fast,
+                    (None, keep_going) => keep_going.unwrap_or_default(),
+                };
+
                 Ok(BuildConfig {
                     environments: final_environments,
                     extensions,
@@ -146,12 +693,57 @@ impl<'de> Deserialize<'de> for BuildConfig {
                     environments_config,
                     yaml_merger: yaml_merger.unwrap_or_default(),
                     copy_env_example: copy_env_example.unwrap_or_else(default_copy_env_example),
+                    env_source_filename: env_source_filename.unwrap_or_else(default_env_filename),
+                    env_output_filename: env_output_filename.unwrap_or_else(default_env_filename),
                     copy_additional_files: copy_additional_files.unwrap_or_else(default_copy_additional_files),
                     exclude_patterns: exclude_patterns.unwrap_or_else(default_exclude_patterns),
                     preserve_env_files: preserve_env_files.unwrap_or_else(default_preserve_env_files),
                     env_file_patterns: env_file_patterns.unwrap_or_else(default_env_file_patterns),
                     backup_dir: backup_dir.unwrap_or_else(default_backup_dir),
+                    backup_format: backup_format.unwrap_or_default(),
+                    lock_timeout: lock_timeout.unwrap_or_else(default_lock_timeout),
+                    write_gitignore: write_gitignore.unwrap_or_else(default_write_gitignore),
+                    write_checksums: write_checksums.unwrap_or_else(default_write_checksums),
+                    dedup_copies: dedup_copies.unwrap_or_default(),
+                    merge_strategy,
+                    inline_env_files: inline_env_files.unwrap_or_default(),
+                    rewrite_build_context: rewrite_build_context.unwrap_or_default(),
+                    prune_empty_collections: prune_empty_collections.unwrap_or_default(),
+                    label_style: label_style.unwrap_or_default(),
+                    port_style: port_style.unwrap_or_default(),
+                    combo_name_separator: combo_name_separator.unwrap_or_else(default_combo_name_separator),
+                    yq_retries: yq_retries.unwrap_or_default(),
+                    yq_retry_backoff_ms: yq_retry_backoff_ms.unwrap_or_else(default_yq_retry_backoff_ms),
+                    // output_format is shorthand for a single-entry output_formats
+                    output_formats: resolve_output_formats(output_formats, output_format),
+                    output_mode: output_mode.unwrap_or_default(),
+                    multi_document: multi_document.unwrap_or(false),
+                    require_base: require_base.unwrap_or_else(default_require_base),
                     skip_base_generation: skip_base_generation.unwrap_or_else(default_skip_base_generation),
+                    strict_categories: strict_categories.unwrap_or_else(default_strict_categories),
+                    generated_header,
+                    provenance_header: provenance_header.unwrap_or_default(),
+                    include_git_info: include_git_info.unwrap_or_else(default_include_git_info),
+                    project_name_template,
+                    include_files: include_files.unwrap_or_default(),
+                    validation_mode: validation_mode.unwrap_or_default(),
+                    default_environment,
+                    archive_path,
+                    generate_helper_scripts: generate_helper_scripts.unwrap_or(false),
+                    helper_script_template,
+                    pre_build_command,
+                    image_check_command,
+                    kubernetes_output: kubernetes_output.unwrap_or(false),
+                    fail_on_service_conflicts: fail_on_service_conflicts.unwrap_or(false),
+                    matrix,
+                    max_combinations: max_combinations.unwrap_or(DEFAULT_MAX_COMBINATIONS),
+                    exclusive_groups,
+                    aliases,
+                    variables,
+                    combo_variables,
+                    resolve_env: resolve_env.unwrap_or(false),
+                    keep_going,
+                    unknown_keys,
                 })
             }
         }
@@ -170,12 +762,56 @@ impl Default for BuildConfig {
             environments_config: None,
             yaml_merger: YamlMergerType::default(),
             copy_env_example: default_copy_env_example(),
+            env_source_filename: default_env_filename(),
+            env_output_filename: default_env_filename(),
             copy_additional_files: default_copy_additional_files(),
             exclude_patterns: default_exclude_patterns(),
             preserve_env_files: default_preserve_env_files(),
             env_file_patterns: default_env_file_patterns(),
             backup_dir: default_backup_dir(),
+            backup_format: BackupFormat::default(),
+            lock_timeout: default_lock_timeout(),
+            write_gitignore: default_write_gitignore(),
+            write_checksums: default_write_checksums(),
+            dedup_copies: DedupCopies::default(),
+            merge_strategy: BTreeMap::new(),
+            inline_env_files: false,
+            rewrite_build_context: false,
+            prune_empty_collections: false,
+            label_style: LabelStyle::default(),
+            port_style: PortStyle::default(),
+            combo_name_separator: default_combo_name_separator(),
+            yq_retries: 0,
+            yq_retry_backoff_ms: default_yq_retry_backoff_ms(),
+            output_formats: vec![OutputFormat::default()],
+            output_mode: OutputMode::default(),
+            multi_document: false,
+            require_base: default_require_base(),
             skip_base_generation: default_skip_base_generation(),
+            strict_categories: default_strict_categories(),
+            generated_header: None,
+            provenance_header: false,
+            include_git_info: default_include_git_info(),
+            project_name_template: None,
+            include_files: Vec::new(),
+            validation_mode: ValidationMode::default(),
+            default_environment: None,
+            archive_path: None,
+            generate_helper_scripts: false,
+            helper_script_template: None,
+            pre_build_command: None,
+            image_check_command: None,
+            kubernetes_output: false,
+            fail_on_service_conflicts: false,
+            matrix: BTreeMap::new(),
+            max_combinations: DEFAULT_MAX_COMBINATIONS,
+            exclusive_groups: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            variables: BTreeMap::new(),
+            combo_variables: BTreeMap::new(),
+            resolve_env: false,
+            keep_going: false,
+            unknown_keys: Vec::new(),
         }
     }
 }
@@ -184,6 +820,9 @@ impl Default for BuildConfig {
 pub struct Paths {
     #[serde(default = "default_components_dir")]
     pub components_dir: String,
+    /// Further components roots, e.g. a vendored read-only tree, searched after `components_dir`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components_dirs: Vec<String>,
     #[serde(default = "default_base_dir")]
     pub base_dir: String,
     #[serde(default = "default_environments_dir")]
@@ -192,49 +831,25 @@ pub struct Paths {
     pub extensions_dirs: Vec<String>,
     #[serde(default = "default_build_dir")]
     pub build_dir: String,
+    /// Keys of the `[paths]` table that are not options
+    #[serde(flatten, skip_serializing)]
+    pub unknown_keys: std::collections::BTreeMap<String, toml::Value>,
 }
 
 impl Default for Paths {
     fn default() -> Self {
         Paths {
             components_dir: default_components_dir(),
+            components_dirs: Vec::new(),
             base_dir: default_base_dir(),
             environments_dir: default_environments_dir(),
             extensions_dirs: default_extensions_dirs(),
             build_dir: default_build_dir(),
+            unknown_keys: Default::default(),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct Build {
-    // Legacy field for backwards compatibility
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub environments: Option<Vec<String>>,
-    pub extensions: Option<Vec<String>>,
-    #[serde(default)]
-    pub combos: HashMap<String, Vec<String>>,
-    // Legacy field for backwards compatibility
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub targets: Option<BuildTargets>,
-    #[serde(default)]
-    pub yaml_merger: YamlMergerType,
-    #[serde(default = "default_copy_env_example")]
-    pub copy_env_example: bool,
-    #[serde(default = "default_copy_additional_files")]
-    pub copy_additional_files: bool,
-    #[serde(default = "default_exclude_patterns")]
-    pub exclude_patterns: Vec<String>,
-    #[serde(default = "default_preserve_env_files")]
-    pub preserve_env_files: bool,
-    #[serde(default = "default_env_file_patterns")]
-    pub env_file_patterns: Vec<String>,
-    #[serde(default = "default_backup_dir")]
-    pub backup_dir: String,
-    #[serde(default = "default_skip_base_generation")]
-    pub skip_base_generation: bool,
-}
-
 // New environments structure
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct BuildEnvironments {
@@ -248,6 +863,11 @@ pub struct EnvironmentConfig {
     pub extensions: Option<Vec<String>>,
     pub combos: Option<Vec<String>>,
     pub skip_base_generation: Option<bool>,
+    pub exclude_patterns: Option<Vec<String>>,
+    /// Variables of the environment's combinations, overriding `[build.variables]`
+    pub variables: Option<BTreeMap<String, String>>,
+    /// Tags that replace the image tags of the environment's services, keyed by service name or image repository
+    pub image_tags: Option<BTreeMap<String, String>>,
 }
 
 // Legacy structure for backwards compatibility
@@ -263,28 +883,9 @@ pub struct EnvironmentTarget {
     pub extensions: Option<Vec<String>>,
     pub combos: Option<Vec<String>>,
     pub skip_base_generation: Option<bool>,
+    pub exclude_patterns: Option<Vec<String>>,
 }
 
-impl Default for Build {
-    fn default() -> Self {
-        Build {
-            environments: None,
-            extensions: None,
-            combos: HashMap::new(),
-            targets: None,
-            yaml_merger: YamlMergerType::default(),
-            copy_env_example: default_copy_env_example(),
-            copy_additional_files: default_copy_additional_files(),
-            exclude_patterns: default_exclude_patterns(),
-            preserve_env_files: default_preserve_env_files(),
-            env_file_patterns: default_env_file_patterns(),
-            backup_dir: default_backup_dir(),
-            skip_base_generation: default_skip_base_generation(),
-        }
-    }
-}
-
-
 // Default functions
 fn default_components_dir() -> String {
     "./components".to_string()
@@ -310,6 +911,10 @@ fn default_copy_env_example() -> bool {
     true
 }
 
+fn default_env_filename() -> String {
+    ENV_EXAMPLE_FILE.to_string()
+}
+
 fn default_copy_additional_files() -> bool {
     true
 }
@@ -341,34 +946,322 @@ fn default_backup_dir() -> String {
     "./.stackbuilder/backup".to_string()
 }
 
+fn default_lock_timeout() -> u64 {
+    60
+}
+
+fn resolve_output_formats(output_formats: Option<Vec<OutputFormat>>, output_format: Option<OutputFormat>) -> Vec<OutputFormat> {
+    let mut formats = output_formats
+        .or_else(|| output_format.map(|format| vec![format]))
+        .filter(|formats| !formats.is_empty())
+        .unwrap_or_else(|| vec![OutputFormat::default()]);
+    let mut seen = Vec::new();
+    formats.retain(|format| {
+        let first = !seen.contains(format);
+        seen.push(*format);
+        first
+    });
+    formats
+}
+
+fn default_yq_retry_backoff_ms() -> u64 {
+    100
+}
+
+fn default_write_gitignore() -> bool {
+    true
+}
+
+fn default_write_checksums() -> bool {
+    true
+}
+
+fn default_include_git_info() -> bool {
+    true
+}
+
+fn default_combo_name_separator() -> String {
+    EXTENSION_COMBINATION_SEPARATOR.to_string()
+}
+
+fn default_require_base() -> bool {
+    true
+}
+
 fn default_skip_base_generation() -> bool {
     false
 }
 
-// Load and parse stackbuilder.toml configuration file
+fn default_strict_categories() -> Vec<WarningCategory> {
+    WarningCategory::ALL.to_vec()
+}
+
+/// Configuration file of a project, in its directory
+pub const CONFIG_FILE: &str = "stackbuilder.toml";
+
+/// Value of `--config` that reads the configuration from stdin
+pub const STDIN_CONFIG: &str = "-";
+
+/// Name error messages use for a configuration read from stdin
+const STDIN_CONFIG_NAME: &str = "<stdin>";
+
+/// User configuration file in the platform configuration directory, e.g. `$XDG_CONFIG_HOME/stackbuilder/config.toml`
+pub const USER_CONFIG_FILE: &str = "config.toml";
+
+/// Environment variable naming the user configuration file instead; an empty value disables the user configuration
+pub const USER_CONFIG_ENV: &str = "STACKBUILDER_USER_CONFIG";
+
+/// `[build]` keys the user configuration may set: personal preferences that neither name paths
+/// nor change which combinations a project defines
+pub const USER_CONFIG_KEYS: &[&str] = &[
+    "yaml_merger", "yq_retries", "yq_retry_backoff_ms", "label_style", "port_style", "output_format", "output_formats",
+    "generated_header", "provenance_header", "include_git_info", "exclude_patterns", "backup_format",
+    "lock_timeout", "validation_mode", "fail_fast", "keep_going", "strict_categories",
+];
+
+/// Syntax of a configuration: read with `build --config --format`, printed with `build --print-config`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+// Load and parse stackbuilder.toml configuration file, over the defaults of the user configuration like every CLI command
 pub fn load_config() -> Result<Config> {
-    let config_path = "stackbuilder.toml";
-    
+    load_config_file(std::path::Path::new(CONFIG_FILE), ConfigFormat::Toml, user_config_path().as_deref())
+}
+
+/// Load a configuration file and merge its `[build] include_files` into it.
+/// Included files use the same layout; later includes override earlier ones and the main file overrides all of them.
+/// The user configuration is not read, so the result only depends on the project.
+pub fn load_config_from(config_path: &std::path::Path) -> Result<Config> {
+    load_config_file(config_path, ConfigFormat::Toml, None)
+}
+
+/// Load a configuration file like [`load_config_from`], over the `[build]` defaults of the user configuration `user_config`
+#[cfg(test)]
+pub fn load_config_with_user_config(config_path: &std::path::Path, user_config: Option<&std::path::Path>) -> Result<Config> {
+    load_config_file(config_path, ConfigFormat::Toml, user_config)
+}
+
+/// The user configuration: [`USER_CONFIG_ENV`] when set, otherwise [`USER_CONFIG_FILE`] in the platform
+/// configuration directory (`$XDG_CONFIG_HOME/stackbuilder` on Linux, `~/Library/Application Support/stackbuilder`
+/// on macOS, `%APPDATA%\stackbuilder\config` on Windows)
+pub fn user_config_path() -> Option<std::path::PathBuf> {
+    if let Some(path) = std::env::var_os(USER_CONFIG_ENV) {
+        return (!path.is_empty()).then(|| path.into());
+    }
+    directories::ProjectDirs::from("", "", "stackbuilder").map(|dirs| dirs.config_dir().join(USER_CONFIG_FILE))
+}
+
+/// The `[build]` table of the user configuration at `path`, limited to [`USER_CONFIG_KEYS`];
+/// `None` when the file does not exist. Other keys and tables are ignored with a `config` warning.
+fn load_user_defaults(path: &std::path::Path) -> Result<Option<toml::Table>> {
+    let file = path.display().to_string();
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(ConfigError::ConfigFileReadError { file, source: e }.into()),
+    };
+
+    // Parse on its own first, so type errors point into the user configuration
+    let _: Config = toml::from_str(&content)
+        .map_err(|e| ConfigError::toml_parse_error(file.clone(), &content, e))?;
+    let table: toml::Table = toml::from_str(&content)
+        .map_err(|e| ConfigError::toml_parse_error(file.clone(), &content, e))?;
+
+    let mut ignored = Vec::new();
+    let mut defaults = toml::Table::new();
+    for (section, value) in table {
+        match value {
+            toml::Value::Table(build) if section == "build" => {
+                for (key, value) in build {
+                    if USER_CONFIG_KEYS.contains(&key.as_str()) {
+                        defaults.insert(key, value);
+                    } else {
+                        ignored.push(format!("build.{}", key));
+                    }
+                }
+            }
+            _ => ignored.push(section),
+        }
+    }
+    if !ignored.is_empty() {
+        diagnostics::warn_at(WarningCategory::Config, path, format!(
+            "{} in user configuration '{}' ignored; it may only set these [build] keys: {}",
+            ignored.join(", "), file, USER_CONFIG_KEYS.join(", ")
+        ));
+    }
+
+    Ok(Some(toml::Table::from_iter([("build".to_string(), toml::Value::Table(defaults))])))
+}
+
+/// Load the configuration `--config` names in `format` over the user configuration `user_config`: a file relative to `project_dir`,
+/// or stdin for `-`, whose included files are then relative to `project_dir`
+pub fn load_config_arg(config_path: &std::path::Path, format: ConfigFormat, project_dir: &std::path::Path, user_config: Option<&std::path::Path>) -> Result<Config> {
+    if config_path == std::path::Path::new(STDIN_CONFIG) {
+        return load_config_reader(std::io::stdin().lock(), format, project_dir, user_config);
+    }
+    load_config_file(&project_dir.join(config_path), format, user_config)
+}
+
+/// Load a configuration from `reader`, e.g. stdin, resolving its included files against `project_dir`
+pub fn load_config_reader(mut reader: impl std::io::Read, format: ConfigFormat, project_dir: &std::path::Path, user_config: Option<&std::path::Path>) -> Result<Config> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)
+        .map_err(|e| ConfigError::ConfigFileReadError {
+            file: STDIN_CONFIG_NAME.to_string(),
+            source: e,
+        })?;
+    parse_config(&content, STDIN_CONFIG_NAME, format, project_dir, user_config)
+}
+
+fn load_config_file(config_path: &std::path::Path, format: ConfigFormat, user_config: Option<&std::path::Path>) -> Result<Config> {
+    let file = config_path.display().to_string();
+
     let content = std::fs::read_to_string(config_path)
         .map_err(|e| match e.kind() {
-            std::io::ErrorKind::NotFound => ConfigError::config_not_found(config_path),
+            std::io::ErrorKind::NotFound => ConfigError::config_not_found(file.clone()),
             _ => ConfigError::ConfigFileReadError {
-                file: config_path.to_string(),
+                file: file.clone(),
                 source: e,
             }
         })?;
 
-    let config: Config = toml::from_str(&content)
-        .map_err(|e| ConfigError::toml_parse_error(config_path, e))?;
+    // Include paths are relative to the directory of the including file
+    let config_dir = config_path.parent().unwrap_or_else(|| std::path::Path::new(""));
+    parse_config(&content, &file, format, config_dir, user_config)
+}
+
+/// Parse the configuration `content` of `file` and merge its included files, which are TOML whatever `format` is,
+/// over the defaults of the user configuration `user_config`
+fn parse_config(content: &str, file: &str, format: ConfigFormat, config_dir: &std::path::Path, user_config: Option<&std::path::Path>) -> Result<Config> {
+    let file = file.to_string();
+    let config: Config = parse_config_as(content, &file, format)?;
+    let user_defaults = match user_config {
+        Some(path) => load_user_defaults(path)?,
+        None => None,
+    };
+
+    if config.build.include_files.is_empty() && user_defaults.is_none() {
+        return Ok(config);
+    }
+    // Named in type errors, which only show up once everything is merged
+    let mut merged_sources = Vec::new();
+    if !config.build.include_files.is_empty() {
+        merged_sources.push("include_files".to_string());
+    }
+    if let (Some(_), Some(path)) = (&user_defaults, user_config) {
+        merged_sources.push(format!("the user configuration {}", path.display()));
+    }
+
+    let mut merged = toml::Table::new();
+    for include in &config.build.include_files {
+        let include_path = config_dir.join(include);
+        let include_file = include_path.display().to_string();
+
+        let include_content = std::fs::read_to_string(&include_path)
+            .map_err(|e| ConfigError::ConfigFileReadError {
+                file: include_file.clone(),
+                source: e,
+            })?;
+
+        // Parse on its own first, so type errors point into the included file
+        let included: Config = toml::from_str(&include_content)
+            .map_err(|e| ConfigError::toml_parse_error(include_file.clone(), &include_content, e))?;
+        if !included.build.include_files.is_empty() {
+            diagnostics::warn_at(WarningCategory::Config, &include_path, format!(
+                "include_files in included file '{}' are ignored; list every file in {}", include_file, file
+            ));
+        }
+
+        let table: toml::Table = toml::from_str(&include_content)
+            .map_err(|e| ConfigError::toml_parse_error(include_file.clone(), &include_content, e))?;
+        merge_config_tables(&mut merged, table);
+    }
+
+    let main: toml::Table = parse_config_as(content, &file, format)?;
+    merge_config_tables(&mut merged, main);
+
+    // The project wins over the user configuration
+    let mut user_keys = Vec::new();
+    if let Some(mut defaults) = user_defaults {
+        let project_build = merged.get("build").and_then(toml::Value::as_table);
+        if let Some(toml::Value::Table(build)) = defaults.get("build") {
+            user_keys = build.keys()
+                .filter(|key| !project_build.is_some_and(|project| {
+                    project.contains_key(*key) || inverse_build_key(key).is_some_and(|inverse| project.contains_key(inverse))
+                }))
+                .cloned()
+                .collect();
+        }
+        merge_config_tables(&mut defaults, merged);
+        merged = defaults;
+    }
+
+    let mut config: Config = toml::Value::Table(merged).try_into()
+        .map_err(|e: toml::de::Error| ConfigError::InvalidTomlSyntax {
+            file,
+            details: format!("{} (after merging {})", e.message().trim_end(), merged_sources.join(" and ")),
+            location: None,
+        })?;
+    if let (false, Some(path)) = (user_keys.is_empty(), user_config) {
+        config.user_defaults = Some(UserDefaults { path: path.to_path_buf(), keys: user_keys });
+    }
 
     Ok(config)
 }
 
+fn parse_config_as<T: serde::de::DeserializeOwned>(content: &str, file: &str, format: ConfigFormat) -> Result<T> {
+    let value = match format {
+        ConfigFormat::Toml => toml::from_str(content)
+            .map_err(|e| ConfigError::toml_parse_error(file, content, e))?,
+        ConfigFormat::Json => serde_json::from_str(content)
+            .map_err(|e| ConfigError::json_parse_error(file, content, e))?,
+    };
+    Ok(value)
+}
+
+/// `[build]` key that sets the opposite of `key`, so only one of the two may be set
+fn inverse_build_key(key: &str) -> Option<&'static str> {
+    match key {
+        "fail_fast" => Some("keep_going"),
+        "keep_going" => Some("fail_fast"),
+        _ => None,
+    }
+}
+
+/// Merge the configuration `overlay` into `base`, where a `[build]` key of `overlay` also replaces its inverse in `base`
+fn merge_config_tables(base: &mut toml::Table, overlay: toml::Table) {
+    if let (Some(toml::Value::Table(base_build)), Some(toml::Value::Table(overlay_build))) = (base.get_mut("build"), overlay.get("build")) {
+        for inverse in overlay_build.keys().filter_map(|key| inverse_build_key(key)) {
+            base_build.remove(inverse);
+        }
+    }
+    merge_toml_tables(base, overlay);
+}
+
+/// Merge `overlay` into `base`: tables are merged key by key, any other value in `overlay` replaces the one in `base`
+fn merge_toml_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => {
+                merge_toml_tables(base_table, overlay_table);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
 // Validate configuration: check paths existence and requirements
-pub fn validate_config(config: &Config) -> Result<()> {
-    println!("Validating configuration...");
+pub fn validate_config(config: &Config, mode: ValidationMode) -> Result<()> {
+    status!("Validating configuration...");
+    let mut problems = ValidationProblems::new(mode);
 
-    // Check required directories
+    // Check required directories; nothing else can be checked without the components directory
     let components_path = std::path::Path::new(&config.paths.components_dir);
     if !components_path.exists() {
         return Err(ValidationError::ComponentsDirectoryNotFound {
@@ -377,10 +1270,24 @@ pub fn validate_config(config: &Config) -> Result<()> {
     }
 
     let base_path = components_path.join(&config.paths.base_dir);
-    if !base_path.exists() {
-        return Err(ValidationError::BaseDirectoryNotFound {
+    if config.build.require_base && !base_path.exists() {
+        problems.report(ValidationError::BaseDirectoryNotFound {
             path: base_path,
-        }.into());
+        })?;
+    }
+
+    // Components may be shared or read-only; nothing stackbuilder writes may live in or around them
+    let paths = ResolvedPaths::new(config, std::path::Path::new("."));
+    for output in [&paths.build_dir, &paths.backup_dir] {
+        if let Some(root) = read_only_overlap(&paths.read_only_dirs(), output) {
+            problems.report(ValidationError::OutputInsideComponentsRoot { path: output.clone(), root })?;
+        }
+    }
+
+    // Misspelled options are skipped by deserialization and would otherwise change the build silently
+    for unknown in find_unknown_keys(config) {
+        let hint = unknown.suggestion.map(|key| format!(", did you mean '{}'?", key)).unwrap_or_default();
+        diagnostics::warn(WarningCategory::Config, format!("Unknown key '{}' in [{}] is ignored{}", unknown.key, unknown.table, hint));
     }
 
     // Check if build configuration has valid targets
@@ -391,11 +1298,91 @@ pub fn validate_config(config: &Config) -> Result<()> {
     let has_targets = config.build.targets.is_some() || config.build.environments_config.is_some();
 
     if !has_environments && !has_legacy_extensions && !has_combos && !has_targets {
-        println!("ℹ No specific targets configured - will build base configuration only");
+        status!("ℹ No specific targets configured - will build base configuration only");
+    }
+
+    // Combination names become output directory names; without a separator distinct combinations can share one
+    let separator = &config.build.combo_name_separator;
+    if separator.is_empty() || separator.contains('/') || separator.contains('\\') {
+        problems.report(ValidationError::InvalidComboNameSeparator { separator: separator.clone() })?;
+    }
+
+    for (key, name) in [("env_source_filename", &config.build.env_source_filename), ("env_output_filename", &config.build.env_output_filename)] {
+        if name.is_empty() || name.contains('/') || name.contains('\\') {
+            problems.report(ValidationError::InvalidEnvFilename { key: key.to_string(), name: name.clone() })?;
+        }
+    }
+
+    // Aliases become output directories; they must stay below the build directory and not share one
+    let mut aliased: BTreeMap<&str, &str> = BTreeMap::new();
+    for (name, alias) in &config.build.aliases {
+        let path = std::path::Path::new(alias.trim_matches('/'));
+        let reason = if path.as_os_str().is_empty() {
+            Some("is empty")
+        } else if alias.starts_with('/') || alias.contains('\\') || path.is_absolute() {
+            Some("must be a relative path with / as the separator")
+        } else if alias.trim_matches('/').split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..") {
+            Some("must not contain '.', '..' or empty segments")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            problems.report(ValidationError::InvalidAlias { name: name.clone(), alias: alias.clone(), reason: reason.to_string() })?;
+        } else if let Some(first) = aliased.insert(alias.trim_matches('/'), name) {
+            problems.report(ValidationError::DuplicateAlias { alias: alias.clone(), first: first.to_string(), second: name.clone() })?;
+        }
+    }
+    // An alias inside another one would be written into, and replaced with, the outer combination's directory
+    for (outer, first) in &aliased {
+        for (inner, second) in aliased.iter().filter(|(inner, _)| inner != &outer && std::path::Path::new(inner).starts_with(outer)) {
+            problems.report(ValidationError::NestedAlias { outer: outer.to_string(), inner: inner.to_string(), first: first.to_string(), second: second.to_string() })?;
+        }
+    }
+
+    if config.build.preserve_env_files && preserved_env_file_patterns(config).len() != config.build.env_file_patterns.len() {
+        diagnostics::warn(WarningCategory::Config, format!("env_file_patterns lists '{}', which copy_env_example generates; the generated file always replaces a preserved copy", config.build.env_output_filename));
+    }
+
+    for combo_name in config.build.combo_variables.keys().filter(|name| !config.build.combos.contains_key(*name)) {
+        diagnostics::warn(WarningCategory::Config, format!("[build.combo_variables] sets variables of '{}', which is not defined in [build.combos]; they are never applied", combo_name));
     }
 
     // Validate combo definitions
-    validate_combo_definitions(config)?;
+    validate_combo_definitions(config, &mut problems)?;
+
+    if !config.build.matrix.is_empty() {
+        let available_extensions = discover_extensions(config)?;
+        for (group, values) in &config.build.matrix {
+            if values.is_empty() {
+                problems.report(ValidationError::InvalidMatrixGroup { group: group.clone(), details: "has no values".to_string() })?;
+            }
+            for value in values.iter().filter(|value| *value != MATRIX_NONE) {
+                if value.contains(EXTENSION_COMBINATION_SEPARATOR) || !available_extensions.contains(value) {
+                    problems.report(ValidationError::extension_not_found(value.clone(), config.paths.extensions_dirs.clone(), available_extensions.clone()))?;
+                }
+            }
+        }
+    }
+
+    if let Some(ref environments) = config.build.environments_config {
+        let mut names: Vec<&String> = environments.environment_configs.keys().collect();
+        names.sort();
+        for name in names {
+            let image_tags = environments.environment_configs[name].image_tags.iter().flatten();
+            for (key, tag) in image_tags.filter(|(_, tag)| !is_valid_image_tag(tag)) {
+                problems.report(ValidationError::InvalidImageTag { environment: name.clone(), key: key.clone(), tag: tag.clone() })?;
+            }
+        }
+    }
+
+    if !config.build.exclusive_groups.is_empty() {
+        let available_extensions = discover_extensions(config)?;
+        for member in config.build.exclusive_groups.values().flatten() {
+            if member.contains(EXTENSION_COMBINATION_SEPARATOR) || !available_extensions.contains(member) {
+                problems.report(ValidationError::extension_not_found(member.clone(), config.paths.extensions_dirs.clone(), available_extensions.clone()))?;
+            }
+        }
+    }
 
     // Check environments_dir if specified and not empty (optional - environments can exist without specific folders)
     let environments_list = get_environments_list(config);
@@ -407,24 +1394,34 @@ pub fn validate_config(config: &Config) -> Result<()> {
                 let env_path = envs_path.join(env);
                 // Individual environment directories are also optional
                 if env_path.exists() {
-                    println!("✓ Found environment directory: {}", env);
+                    status!("✓ Found environment directory: {}", env);
                 } else {
-                    println!("ℹ Environment '{}' has no specific directory (using base only)", env);
+                    status!("ℹ Environment '{}' has no specific directory (using base only)", env);
                 }
             }
         } else {
-            println!("ℹ No environments directory found - environments will use base configuration only");
+            status!("ℹ No environments directory found - environments will use base configuration only");
+        }
+    }
+
+    // The default environment must be one that is built
+    if let Some(ref default_env) = config.build.default_environment {
+        if !environments_list.contains(default_env) {
+            problems.report(ValidationError::DefaultEnvironmentNotFound {
+                name: default_env.clone(),
+                available: environments_list.clone(),
+            })?;
         }
     }
 
     // Validate targets section if present (legacy API)
     if let Some(ref targets) = config.build.targets {
-        validate_build_targets(config, targets)?;
+        validate_build_targets(config, targets, &mut problems)?;
     }
     
     // Validate new environments configuration if present
     if let Some(ref env_config) = config.build.environments_config {
-        validate_build_environments(config, env_config)?;
+        validate_build_environments(config, env_config, &mut problems)?;
     }
 
     // Check extensions_dirs if extensions are specified (optional - extensions directories may not exist)
@@ -432,46 +1429,97 @@ pub fn validate_config(config: &Config) -> Result<()> {
         for ext_dir in &config.paths.extensions_dirs {
             let ext_path = components_path.join(ext_dir);
             if ext_path.exists() {
-                println!("✓ Found extensions directory: {}", ext_dir);
+                status!("✓ Found extensions directory: {}", ext_dir);
             } else {
-                println!("ℹ Extensions directory '{}' not found - no extensions will be available", ext_dir);
+                status!("ℹ Extensions directory '{}' not found - no extensions will be available", ext_dir);
             }
         }
     }
 
-    println!("Configuration validation passed");
+    problems.finish()?;
+    status!("Configuration validation passed");
     Ok(())
 }
 
+/// Validation mode for a command: `--fail-fast` overrides `validation_mode`
+pub fn validation_mode(config: &Config, fail_fast: bool) -> ValidationMode {
+    if fail_fast {
+        ValidationMode::FailFast
+    } else {
+        config.build.validation_mode
+    }
+}
+
+/// Validation problems found so far; in `ValidationMode::FailFast` the first one is returned right away
+struct ValidationProblems {
+    mode: ValidationMode,
+    errors: Vec<StackBuilderError>,
+}
+
+impl ValidationProblems {
+    fn new(mode: ValidationMode) -> Self {
+        Self { mode, errors: Vec::new() }
+    }
+
+    /// Record a problem, failing immediately in fail-fast mode
+    fn report(&mut self, error: impl Into<StackBuilderError>) -> Result<()> {
+        let error = error.into();
+        match self.mode {
+            ValidationMode::FailFast => Err(error),
+            ValidationMode::Collect => {
+                self.errors.push(error);
+                Ok(())
+            }
+        }
+    }
+
+    /// A single problem is returned as is, several are combined into `ValidationError::Multiple`
+    fn finish(mut self) -> Result<()> {
+        match self.errors.len() {
+            0 => Ok(()),
+            1 => Err(self.errors.remove(0)),
+            _ => Err(ValidationError::Multiple { errors: self.errors }.into()),
+        }
+    }
+}
+
 // Validate combo definitions
-fn validate_combo_definitions(config: &Config) -> Result<()> {
+fn validate_combo_definitions(config: &Config, problems: &mut ValidationProblems) -> Result<()> {
     let available_extensions = discover_extensions(config)?;
     
-    for (combo_name, extensions) in &config.build.combos {
+    let mut combo_names: Vec<&String> = config.build.combos.keys().collect();
+    combo_names.sort();
+    for combo_name in combo_names {
+        let extensions = &config.build.combos[combo_name];
         if extensions.is_empty() {
-            return Err(ValidationError::InvalidComboDefinition {
+            problems.report(ValidationError::InvalidComboDefinition {
                 combo_name: combo_name.clone(),
                 details: "Combo must contain at least one extension".to_string(),
-            }.into());
+            })?;
+            continue;
         }
         
         for ext in extensions {
-            if !available_extensions.contains(ext) {
-                return Err(ValidationError::ExtensionNotFound {
-                    name: ext.clone(),
-                    available_dirs: config.paths.extensions_dirs.clone(),
-                }.into());
+            if is_extension_pattern(ext) {
+                if let Err(details) = match_extension_pattern(ext, &available_extensions) {
+                    problems.report(ValidationError::InvalidComboDefinition {
+                        combo_name: combo_name.clone(),
+                        details,
+                    })?;
+                }
+            } else if !available_extensions.contains(ext) {
+                problems.report(ValidationError::extension_not_found(ext.clone(), config.paths.extensions_dirs.clone(), available_extensions.clone()))?;
             }
         }
         
-        println!("✓ Validated combo '{}': {:?}", combo_name, extensions);
+        status!("✓ Validated combo '{}': {:?}", combo_name, extensions);
     }
     
     Ok(())
 }
 
 // Validate build targets section (legacy)
-fn validate_build_targets(config: &Config, targets: &BuildTargets) -> Result<()> {
+fn validate_build_targets(config: &Config, targets: &BuildTargets, problems: &mut ValidationProblems) -> Result<()> {
     let available_extensions = discover_extensions(config)?;
     
     // Validate target environments from global config (targets no longer have environments field)
@@ -485,13 +1533,13 @@ fn validate_build_targets(config: &Config, targets: &BuildTargets) -> Result<()>
             for env in &environments_list {
                 let env_path = envs_path.join(env);
                 if env_path.exists() {
-                    println!("✓ Found target environment directory: {}", env);
+                    status!("✓ Found target environment directory: {}", env);
                 } else {
-                    println!("ℹ Target environment '{}' has no specific directory (using base only)", env);
+                    status!("ℹ Target environment '{}' has no specific directory (using base only)", env);
                 }
             }
         } else {
-            println!("ℹ No environments directory found for targets - environments will use base configuration only");
+            status!("ℹ No environments directory found for targets - environments will use base configuration only");
         }
     }
     
@@ -499,12 +1547,9 @@ fn validate_build_targets(config: &Config, targets: &BuildTargets) -> Result<()>
     for (env_name, env_target) in &targets.environment_configs {
         // Validate extensions
         if let Some(ref extensions) = env_target.extensions {
-            for ext in extensions {
-                if !available_extensions.contains(ext) {
-                    return Err(ValidationError::ExtensionNotFound {
-                        name: ext.clone(),
-                        available_dirs: config.paths.extensions_dirs.clone(),
-                    }.into());
+            for ext in extensions.iter().flat_map(|entry| parse_extension_combination(entry)) {
+                if !available_extensions.contains(&ext) {
+                    problems.report(ValidationError::extension_not_found(ext, config.paths.extensions_dirs.clone(), available_extensions.clone()))?;
                 }
             }
         }
@@ -513,22 +1558,22 @@ fn validate_build_targets(config: &Config, targets: &BuildTargets) -> Result<()>
         if let Some(ref combos) = env_target.combos {
             for combo_name in combos {
                 if !config.build.combos.contains_key(combo_name) {
-                    return Err(ValidationError::ComboNotFound {
+                    problems.report(ValidationError::ComboNotFound {
                         combo_name: combo_name.clone(),
                         available_combos: config.build.combos.keys().cloned().collect(),
-                    }.into());
+                    })?;
                 }
             }
         }
         
-        println!("✓ Validated target environment '{}' configuration", env_name);
+        status!("✓ Validated target environment '{}' configuration", env_name);
     }
     
     Ok(())
 }
 
 // Validate new build environments section
-fn validate_build_environments(config: &Config, env_config: &BuildEnvironments) -> Result<()> {
+fn validate_build_environments(config: &Config, env_config: &BuildEnvironments, problems: &mut ValidationProblems) -> Result<()> {
     let available_extensions = discover_extensions(config)?;
     
     // Validate environments from available list
@@ -541,13 +1586,13 @@ fn validate_build_environments(config: &Config, env_config: &BuildEnvironments)
             for env in available_envs {
                 let env_path = envs_path.join(env);
                 if env_path.exists() {
-                    println!("✓ Found environment directory: {}", env);
+                    status!("✓ Found environment directory: {}", env);
                 } else {
-                    println!("ℹ Environment '{}' has no specific directory (using base only)", env);
+                    status!("ℹ Environment '{}' has no specific directory (using base only)", env);
                 }
             }
         } else {
-            println!("ℹ No environments directory found - environments will use base configuration only");
+            status!("ℹ No environments directory found - environments will use base configuration only");
         }
     }
     
@@ -555,12 +1600,9 @@ fn validate_build_environments(config: &Config, env_config: &BuildEnvironments)
     for (env_name, env_cfg) in &env_config.environment_configs {
         // Validate extensions
         if let Some(ref extensions) = env_cfg.extensions {
-            for ext in extensions {
-                if !available_extensions.contains(ext) {
-                    return Err(ValidationError::ExtensionNotFound {
-                        name: ext.clone(),
-                        available_dirs: config.paths.extensions_dirs.clone(),
-                    }.into());
+            for ext in extensions.iter().flat_map(|entry| parse_extension_combination(entry)) {
+                if !available_extensions.contains(&ext) {
+                    problems.report(ValidationError::extension_not_found(ext, config.paths.extensions_dirs.clone(), available_extensions.clone()))?;
                 }
             }
         }
@@ -569,122 +1611,186 @@ fn validate_build_environments(config: &Config, env_config: &BuildEnvironments)
         if let Some(ref combos) = env_cfg.combos {
             for combo_name in combos {
                 if !config.build.combos.contains_key(combo_name) {
-                    return Err(ValidationError::ComboNotFound {
+                    problems.report(ValidationError::ComboNotFound {
                         combo_name: combo_name.clone(),
                         available_combos: config.build.combos.keys().cloned().collect(),
-                    }.into());
+                    })?;
                 }
             }
         }
         
-        println!("✓ Validated environment '{}' configuration", env_name);
+        status!("✓ Validated environment '{}' configuration", env_name);
     }
     
     Ok(())
 }
 
-// Resolve relative paths to absolute paths
-pub fn resolve_paths(config: &mut Config) -> Result<()> {
-    let components_path = std::path::Path::new(&config.paths.components_dir).canonicalize()
-        .map_err(|e| ValidationError::PathResolutionError {
-            path: config.paths.components_dir.clone(),
-            details: e.to_string(),
-        })?;
+/// A key of `[build]` or `[paths]` that is not an option and is ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownKey {
+    /// Table the key appears in, `build` or `paths`
+    pub table: &'static str,
+    pub key: String,
+    /// Closest known key, when the key looks like a typo of it
+    pub suggestion: Option<&'static str>,
+}
+
+impl std::fmt::Display for UnknownKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.table, self.key)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean '{}'?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
 
-    config.paths.components_dir = components_path.to_string_lossy().to_string();
+/// Keys of `[build]` and `[paths]` that deserialization skipped, sorted per table, with the closest known key of each
+pub fn find_unknown_keys(config: &Config) -> Vec<UnknownKey> {
+    let mut build_keys: Vec<&String> = config.build.unknown_keys.iter().collect();
+    build_keys.sort();
+    let build = build_keys.into_iter().map(|key| ("build", key, BUILD_KEYS));
+    let paths = config.paths.unknown_keys.keys().map(|key| ("paths", key, PATHS_KEYS));
+    build.chain(paths)
+        .map(|(table, key, known)| UnknownKey {
+            table,
+            key: key.clone(),
+            suggestion: crate::error::closest_match(key, known),
+        })
+        .collect()
+}
 
-    // Resolve other paths relative to components_dir
-    let base_path = components_path.join(&config.paths.base_dir).canonicalize()
+// Resolve relative paths to absolute paths
+pub fn resolve_paths(config: &mut Config) -> Result<ResolvedPaths> {
+    let project_dir = std::env::current_dir()
         .map_err(|e| ValidationError::PathResolutionError {
-            path: config.paths.base_dir.clone(),
+            path: ".".to_string(),
             details: e.to_string(),
         })?;
-    config.paths.base_dir = base_path.to_string_lossy().to_string();
+    Ok(resolve_paths_in(config, &project_dir))
+}
 
-    // Only resolve environments_dir if environments are specified and directory exists
-    let environments_list = get_environments_list(config);
-    if !environments_list.is_empty() {
-        let env_path = components_path.join(&config.paths.environments_dir);
-        if env_path.exists() {
-            let resolved_env_path = env_path.canonicalize()
-                .map_err(|e| ValidationError::PathResolutionError {
-                    path: config.paths.environments_dir.clone(),
-                    details: e.to_string(),
-                })?;
-            config.paths.environments_dir = resolved_env_path.to_string_lossy().to_string();
-        } else {
-            // Keep relative path if directory doesn't exist - it's optional
-            config.paths.environments_dir = env_path.to_string_lossy().to_string();
-        }
-    }
+/// Resolve the configured paths to absolute paths, relative ones against `project_dir`.
+/// Directories are not required to exist; validation reports the missing ones.
+pub fn resolve_paths_in(config: &mut Config, project_dir: &std::path::Path) -> ResolvedPaths {
+    let paths = ResolvedPaths::new(config, project_dir);
+    paths.apply_to(config);
+    status!("Paths resolved successfully");
+    paths
+}
 
-    // Only resolve extensions_dirs if extensions are specified in build configuration
-    if config.build.extensions.is_some() || !config.build.combos.is_empty() ||
-       config.build.targets.is_some() || config.build.environments_config.is_some() {
-        let mut resolved_ext_dirs = Vec::new();
-        for ext_dir in &config.paths.extensions_dirs {
-            let ext_path = components_path.join(ext_dir).canonicalize()
-                .map_err(|e| ValidationError::PathResolutionError {
-                    path: ext_dir.clone(),
-                    details: e.to_string(),
-                })?;
-            resolved_ext_dirs.push(ext_path.to_string_lossy().to_string());
+// Discover available extensions from extensions_dirs
+pub fn discover_extensions(config: &Config) -> Result<Vec<String>> {
+    let mut extensions = Vec::new();
+    for component in discover_extension_sources(config)? {
+        if !extensions.contains(&component.name) {
+            extensions.push(component.name);
         }
-        config.paths.extensions_dirs = resolved_ext_dirs;
     }
 
-    // Build dir will be created during build process, resolve to absolute path without requiring existence
-    let build_path = std::path::Path::new(&config.paths.build_dir);
-    config.paths.build_dir = build_path.canonicalize().unwrap_or_else(|_| build_path.to_path_buf()).to_string_lossy().to_string();
+    status!("Discovered extensions: {:?}", extensions);
+    Ok(extensions)
+}
 
-    println!("Paths resolved successfully");
-    Ok(())
+/// A component found during discovery and the components root it comes from
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredComponent {
+    pub name: String,
+    /// Directory of the component
+    pub path: std::path::PathBuf,
+    /// Components root (`components_dir` or one of `components_dirs`) the component was found in
+    pub root: std::path::PathBuf,
 }
 
-// Discover available extensions from extensions_dirs
-pub fn discover_extensions(config: &Config) -> Result<Vec<String>> {
-    let mut extensions = Vec::new();
+/// Discover extensions in search order with the components root of each.
+/// An extension present in several roots is listed once per root; the first one is used.
+pub fn discover_extension_sources(config: &Config) -> Result<Vec<DiscoveredComponent>> {
+    let roots = component_roots(config);
+    let mut components = Vec::new();
 
     for ext_dir in &config.paths.extensions_dirs {
         // Build full path: components_dir + ext_dir
         let ext_path = std::path::Path::new(&config.paths.components_dir).join(ext_dir);
-        
-        if ext_path.exists() {
-            for entry in std::fs::read_dir(&ext_path)
-                .map_err(|e| FileSystemError::DirectoryReadFailed {
-                    path: ext_path.to_path_buf(),
-                    source: e,
-                })? {
-                let entry = entry.map_err(|e| FileSystemError::DirectoryReadFailed {
-                    path: ext_path.to_path_buf(),
-                    source: e,
-                })?;
-                
-                if entry.path().is_dir() {
-                    if let Some(name) = entry.file_name().to_str() {
-                        extensions.push(name.to_string());
-                    }
-                }
-            }
+        let root = component_root_of(&roots, &ext_path).unwrap_or_else(|| std::path::PathBuf::from(&config.paths.components_dir));
+        for name in discover_component_dirs(&ext_path)? {
+            components.push(DiscoveredComponent {
+                path: ext_path.join(&name),
+                name,
+                root: root.clone(),
+            });
         }
     }
 
-    println!("Discovered extensions: {:?}", extensions);
-    Ok(extensions)
+    Ok(components)
+}
+
+/// Components roots in search order: `components_dir`, then `components_dirs`
+pub fn component_roots(config: &Config) -> Vec<std::path::PathBuf> {
+    std::iter::once(&config.paths.components_dir)
+        .chain(&config.paths.components_dirs)
+        .map(std::path::PathBuf::from)
+        .collect()
+}
+
+/// The directory of `read_only_dirs` that `output` lies in or contains, if any
+pub fn read_only_overlap(read_only_dirs: &[std::path::PathBuf], output: &std::path::Path) -> Option<std::path::PathBuf> {
+    read_only_dirs.iter().find(|dir| output.starts_with(dir) || dir.starts_with(output)).cloned()
+}
+
+/// The components root `path` lies in, if any
+pub fn component_root_of(roots: &[std::path::PathBuf], path: &std::path::Path) -> Option<std::path::PathBuf> {
+    roots.iter().find(|root| path.starts_with(root)).cloned()
+}
+
+/// `path` made absolute, with symlinks resolved in the part of it that exists
+pub fn absolute_path(path: &std::path::Path) -> std::path::PathBuf {
+    let absolute: std::path::PathBuf = std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .components()
+        .collect();
+    let mut existing = absolute.as_path();
+    let mut missing = Vec::new();
+    while let Some(parent) = existing.parent() {
+        if existing.exists() {
+            break;
+        }
+        missing.push(existing.file_name().unwrap_or_default().to_os_string());
+        existing = parent;
+    }
+    let mut resolved = existing.canonicalize().unwrap_or_else(|_| existing.to_path_buf());
+    resolved.extend(missing.iter().rev());
+    resolved
 }
 
-// Resolve combo extensions into a flat list of extension names
+// Resolve combo extensions into a flat list of extension names.
+// Glob members such as `auth-*` expand to the matching discovered extensions in sorted order.
 pub fn resolve_combo_extensions(config: &Config, combo_names: &[String]) -> Result<Vec<String>> {
     let mut resolved_extensions = Vec::new();
+    // Extensions are only discovered once a combo uses a pattern
+    let mut available_extensions: Option<Vec<String>> = None;
     
     for combo_name in combo_names {
         if let Some(extensions) = config.build.combos.get(combo_name) {
-            for ext in extensions {
-                if !resolved_extensions.contains(ext) {
-                    resolved_extensions.push(ext.clone());
+            for member in extensions {
+                let expanded = if is_extension_pattern(member) {
+                    if available_extensions.is_none() {
+                        available_extensions = Some(discover_extensions(config)?);
+                    }
+                    match_extension_pattern(member, available_extensions.as_deref().unwrap_or_default())
+                        .map_err(|details| ValidationError::InvalidComboDefinition {
+                            combo_name: combo_name.clone(),
+                            details,
+                        })?
+                } else {
+                    vec![member.clone()]
+                };
+                for ext in expanded {
+                    if !resolved_extensions.contains(&ext) {
+                        resolved_extensions.push(ext);
+                    }
                 }
             }
-            println!("✓ Resolved combo '{}' to extensions: {:?}", combo_name, extensions);
+            status!("✓ Resolved combo '{}' to extensions: {:?}", combo_name, extensions);
         } else {
             return Err(ValidationError::ComboNotFound {
                 combo_name: combo_name.clone(),
@@ -696,6 +1802,65 @@ pub fn resolve_combo_extensions(config: &Config, combo_names: &[String]) -> Resu
     Ok(resolved_extensions)
 }
 
+/// Whether a combo member is a glob pattern rather than an extension name
+pub fn is_extension_pattern(member: &str) -> bool {
+    member.contains(['*', '?', '['])
+}
+
+/// Extensions among `available` matching the glob `pattern`, sorted by name.
+/// Fails with a description when the pattern is invalid or matches nothing.
+pub fn match_extension_pattern(pattern: &str, available: &[String]) -> std::result::Result<Vec<String>, String> {
+    let compiled = glob::Pattern::new(pattern)
+        .map_err(|e| format!("Invalid extension pattern '{}': {}", pattern, e))?;
+    let mut matches: Vec<String> = available.iter()
+        .filter(|name| compiled.matches(name))
+        .cloned()
+        .collect();
+    if matches.is_empty() {
+        return Err(format!("Pattern '{}' matches no extension (available: {})", pattern, available.join(", ")));
+    }
+    matches.sort();
+    matches.dedup();
+    Ok(matches)
+}
+
+/// Optional metadata file in an extension directory
+pub const EXTENSION_META_FILE: &str = "stackbuilder.meta.toml";
+
+/// Per-extension metadata read from `stackbuilder.meta.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct ExtensionMeta {
+    /// Merge precedence: extensions with a higher weight are applied later and win conflicts
+    #[serde(default)]
+    pub weight: i64,
+}
+
+/// Load metadata for an extension from the first extensions directory that contains it
+pub fn load_extension_meta(config: &Config, extension: &str) -> Result<ExtensionMeta> {
+    let components_path = std::path::Path::new(&config.paths.components_dir);
+    let ext_path = config.paths.extensions_dirs.iter()
+        .map(|ext_dir| components_path.join(ext_dir).join(extension))
+        .find(|path| path.is_dir());
+
+    let meta_path = match ext_path {
+        Some(path) => path.join(EXTENSION_META_FILE),
+        None => return Ok(ExtensionMeta::default()),
+    };
+    if !meta_path.exists() {
+        return Ok(ExtensionMeta::default());
+    }
+
+    let content = std::fs::read_to_string(&meta_path)
+        .map_err(|e| FileSystemError::FileReadFailed {
+            path: meta_path.clone(),
+            source: e,
+        })?;
+    let meta = toml::from_str(&content)
+        .map_err(|e| ConfigError::toml_parse_error(meta_path.display().to_string(), &content, e))?;
+
+    Ok(meta)
+}
+
 /// Get environments list from configuration (new API first, then legacy fallback)
 pub fn get_environments_list(config: &Config) -> Vec<String> {
     // Try new API first
@@ -709,6 +1874,149 @@ pub fn get_environments_list(config: &Config) -> Vec<String> {
     config.build.environments.as_ref().map_or_else(Vec::new, |v| v.clone())
 }
 
+/// Default of `env_source_filename` and `env_output_filename`: the file the components provide and the merged file
+pub const ENV_EXAMPLE_FILE: &str = ".env.example";
+
+/// `env_file_patterns` the build cleaner preserves. With `copy_env_example` the generated `env_output_filename`
+/// takes precedence, so it is never preserved and restored over the fresh file
+pub fn preserved_env_file_patterns(config: &Config) -> Vec<String> {
+    config.build.env_file_patterns.iter()
+        .filter(|pattern| !(config.build.copy_env_example && *pattern == &config.build.env_output_filename))
+        .cloned()
+        .collect()
+}
+
+/// Separator of an inline extension combination in an extensions list, e.g. `monitoring+auth`
+pub const EXTENSION_COMBINATION_SEPARATOR: char = '+';
+
+/// Value of a `[build.matrix]` group for the combinations without any of its extensions
+pub const MATRIX_NONE: &str = "none";
+
+/// Default of `max_combinations`
+pub const DEFAULT_MAX_COMBINATIONS: usize = 100;
+
+/// Number of combinations `[build.matrix]` adds to every environment: the product of the group sizes,
+/// less the element made only of `"none"`, which is the base combination
+pub fn matrix_size(config: &Config) -> usize {
+    if config.build.matrix.is_empty() {
+        return 0;
+    }
+    let product = config.build.matrix.values().fold(1usize, |product, values| product.saturating_mul(values.len()));
+    let all_none = config.build.matrix.values().all(|values| values.iter().any(|value| value == MATRIX_NONE));
+    product.saturating_sub(usize::from(all_none))
+}
+
+/// Extensions list entries of `[build.matrix]`, e.g. `postgres+redis`: one per element of the cartesian product
+/// of its groups, taken in group name order, without `"none"` values. The first group varies slowest.
+pub fn matrix_extension_entries(config: &Config) -> Vec<String> {
+    if config.build.matrix.is_empty() {
+        return Vec::new();
+    }
+    let mut elements: Vec<Vec<&str>> = vec![Vec::new()];
+    for values in config.build.matrix.values() {
+        elements = elements.iter()
+            .flat_map(|element| values.iter().map(move |value| {
+                let mut element = element.clone();
+                if value != MATRIX_NONE {
+                    element.push(value.as_str());
+                }
+                element
+            }))
+            .collect();
+    }
+    let mut entries: Vec<String> = Vec::new();
+    for element in elements.into_iter().filter(|element| !element.is_empty()) {
+        let entry = element.join(&EXTENSION_COMBINATION_SEPARATOR.to_string());
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// `config` with the `[build.matrix]` entries appended to the global extensions and to those of every environment
+pub fn with_matrix_extensions(config: &Config) -> Config {
+    let entries = matrix_extension_entries(config);
+    let mut config = config.clone();
+    let append = |extensions: &mut Option<Vec<String>>| extensions.get_or_insert_with(Vec::new).extend(entries.iter().cloned());
+    append(&mut config.build.extensions);
+    if let Some(ref mut environments) = config.build.environments_config {
+        environments.environment_configs.values_mut().for_each(|environment| append(&mut environment.extensions));
+    }
+    if let Some(ref mut targets) = config.build.targets {
+        targets.environment_configs.values_mut().for_each(|target| append(&mut target.extensions));
+    }
+    config
+}
+
+/// Extensions of an extensions list entry; `monitoring+auth` is an inline combination of both
+pub fn parse_extension_combination(entry: &str) -> Vec<String> {
+    entry.split(EXTENSION_COMBINATION_SEPARATOR)
+        .map(str::trim)
+        .filter(|ext| !ext.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Output directory name of an extensions list entry: its extensions joined by `combo_name_separator`
+pub fn extension_combination_name(config: &Config, entry: &str) -> String {
+    parse_extension_combination(entry).join(&config.build.combo_name_separator)
+}
+
+/// Sorted names of the directories directly inside `parent`; a missing parent has none
+pub fn discover_component_dirs(parent: &std::path::Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    if !parent.exists() {
+        return Ok(names);
+    }
+
+    for entry in std::fs::read_dir(parent)
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: parent.to_path_buf(),
+            source: e,
+        })? {
+        let entry = entry.map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: parent.to_path_buf(),
+            source: e,
+        })?;
+
+        if entry.path().is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
+
+/// Extensions named anywhere in the configuration: the legacy list, per-environment lists of both APIs and combos.
+/// Inline combinations are split, each name is listed once in order of first appearance; combos are visited by name.
+/// Glob members of combos name no particular extension and are skipped.
+pub fn get_referenced_extensions(config: &Config) -> Vec<String> {
+    let mut extensions: Vec<String> = config.build.extensions.clone().unwrap_or_default();
+
+    for env in get_environments_list(config) {
+        if let Some(env_config) = get_environment_config(config, &env) {
+            extensions.extend(env_config.extensions.unwrap_or_default());
+        }
+    }
+
+    let mut combo_names: Vec<&String> = config.build.combos.keys().collect();
+    combo_names.sort();
+    for combo in combo_names {
+        extensions.extend(config.build.combos[combo].iter().cloned());
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    extensions.into_iter()
+        .filter(|entry| !is_extension_pattern(entry))
+        .flat_map(|entry| parse_extension_combination(&entry))
+        .filter(|ext| seen.insert(ext.clone()))
+        .collect()
+}
+
 /// Get environment-specific configuration (new API first, then legacy fallback)
 pub fn get_environment_config(config: &Config, env_name: &str) -> Option<EnvironmentConfig> {
     // Try new API first
@@ -725,6 +2033,9 @@ pub fn get_environment_config(config: &Config, env_name: &str) -> Option<Environ
                 extensions: legacy_target.extensions.clone(),
                 combos: legacy_target.combos.clone(),
                 skip_base_generation: legacy_target.skip_base_generation,
+                exclude_patterns: legacy_target.exclude_patterns.clone(),
+                variables: None,
+                image_tags: None,
             });
         }
     }
@@ -732,6 +2043,61 @@ pub fn get_environment_config(config: &Config, env_name: &str) -> Option<Environ
     None
 }
 
+/// A combination variable with the level that set it, e.g. `environment 'prod'`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombinationVariable {
+    pub value: String,
+    pub source: String,
+    /// Earlier levels that set a different value, in override order
+    pub overridden: Vec<String>,
+}
+
+/// Tags that replace image tags in the combinations of `environment`, from its `image_tags`
+pub fn environment_image_tags(config: &Config, environment: Option<&str>) -> BTreeMap<String, String> {
+    environment.and_then(|env| get_environment_config(config, env))
+        .and_then(|env_config| env_config.image_tags)
+        .unwrap_or_default()
+}
+
+/// Whether `tag` is a valid image tag: up to 128 letters, digits, `_`, `.` and `-`, not starting with `.` or `-`
+pub fn is_valid_image_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 128
+        && !tag.starts_with(['.', '-'])
+        && tag.chars().all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+}
+
+/// Variables of a combination assembled from `[build.variables]`, the environment's `variables` and
+/// `[build.combo_variables]` of each applied combo, in that order; a later level overrides an earlier one
+pub fn combination_variables(config: &Config, environment: Option<&str>, combo_names: &[String]) -> BTreeMap<String, CombinationVariable> {
+    let mut levels: Vec<(String, BTreeMap<String, String>)> = vec![("[build.variables]".to_string(), config.build.variables.clone())];
+    if let Some(env) = environment {
+        if let Some(variables) = get_environment_config(config, env).and_then(|env_config| env_config.variables) {
+            levels.push((format!("environment '{}'", env), variables));
+        }
+    }
+    for combo_name in combo_names {
+        if let Some(variables) = config.build.combo_variables.get(combo_name) {
+            levels.push((format!("combo '{}'", combo_name), variables.clone()));
+        }
+    }
+
+    let mut resolved: BTreeMap<String, CombinationVariable> = BTreeMap::new();
+    for (source, variables) in levels {
+        for (name, value) in variables {
+            let mut overridden = Vec::new();
+            if let Some(previous) = resolved.remove(&name) {
+                overridden = previous.overridden;
+                if previous.value != value {
+                    overridden.push(previous.source);
+                }
+            }
+            resolved.insert(name, CombinationVariable { value, source: source.clone(), overridden });
+        }
+    }
+    resolved
+}
+
 /// Check if new environments API is being used
 pub fn is_using_new_environments_api(config: &Config) -> bool {
     config.build.environments_config.is_some()
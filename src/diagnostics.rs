@@ -0,0 +1,325 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::error::{BuildError, StackBuilderError};
+use crate::observer::BuildObserver;
+
+/// Kind of a non-fatal problem, used to group the build summary and to select what `--strict` rejects
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningCategory {
+    /// Configuration options that have no effect
+    Config,
+    /// Component files that are missing or invalid and were skipped
+    MissingFile,
+    /// .env.example files that could not be merged or written
+    EnvExample,
+    /// Additional files that could not be copied
+    FileCopy,
+    /// Relative build contexts that may not resolve from the output directory
+    BuildContext,
+    /// Build directory cleanup and preserved file restoration
+    Cleanup,
+    /// Build lock disabled
+    Lock,
+    /// Failed yq invocations that were retried
+    Yq,
+    /// Build cache that could not be read or written
+    Cache,
+    /// Kubernetes manifests of `kubernetes_output` that could not be generated
+    Kubernetes,
+    /// Images `--check-images` could not check
+    ImageCheck,
+    /// Services that two extensions of a combination define with different values
+    ServiceConflict,
+}
+
+impl WarningCategory {
+    pub const ALL: [WarningCategory; 12] = [
+        WarningCategory::Config,
+        WarningCategory::MissingFile,
+        WarningCategory::EnvExample,
+        WarningCategory::FileCopy,
+        WarningCategory::BuildContext,
+        WarningCategory::Cleanup,
+        WarningCategory::Lock,
+        WarningCategory::Yq,
+        WarningCategory::Cache,
+        WarningCategory::Kubernetes,
+        WarningCategory::ImageCheck,
+        WarningCategory::ServiceConflict,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCategory::Config => "config",
+            WarningCategory::MissingFile => "missing_file",
+            WarningCategory::EnvExample => "env_example",
+            WarningCategory::FileCopy => "file_copy",
+            WarningCategory::BuildContext => "build_context",
+            WarningCategory::Cleanup => "cleanup",
+            WarningCategory::Lock => "lock",
+            WarningCategory::Yq => "yq",
+            WarningCategory::Cache => "cache",
+            WarningCategory::Kubernetes => "kubernetes",
+            WarningCategory::ImageCheck => "image_check",
+            WarningCategory::ServiceConflict => "service_conflict",
+        }
+    }
+}
+
+impl fmt::Display for WarningCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A warning emitted during a build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    pub category: WarningCategory,
+    pub message: String,
+    /// Output directory of the combination being generated, if any
+    pub combination: Option<String>,
+    /// File the warning is about, if any
+    pub path: Option<PathBuf>,
+}
+
+thread_local! {
+    static COLLECTED: RefCell<Option<Vec<Warning>>> = const { RefCell::new(None) };
+    static COMBINATION: RefCell<Option<String>> = const { RefCell::new(None) };
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+    static OBSERVER: RefCell<Option<Arc<dyn BuildObserver>>> = const { RefCell::new(None) };
+}
+
+/// Whether build progress and warnings are currently silenced on this thread
+pub fn is_quiet() -> bool {
+    QUIET.with(Cell::get)
+}
+
+/// Silences build progress and warnings on this thread until dropped
+pub struct QuietScope(bool);
+
+/// Silence build progress and warnings; warnings are still collected
+pub fn quiet() -> QuietScope {
+    QuietScope(QUIET.with(|quiet| quiet.replace(true)))
+}
+
+impl Drop for QuietScope {
+    fn drop(&mut self) {
+        QUIET.with(|quiet| quiet.set(self.0));
+    }
+}
+
+/// Print a line of build progress, through the observer of this thread when it draws progress itself
+pub fn print_status(line: fmt::Arguments) {
+    match OBSERVER.with(|observer| observer.borrow().clone()).filter(|observer| observer.draws_progress()) {
+        Some(observer) => observer.print_line(&line.to_string()),
+        None => println!("{}", line),
+    }
+}
+
+/// Print a warning and record it while a build is collecting diagnostics on this thread
+pub fn warn(category: WarningCategory, message: impl Into<String>) {
+    emit(category, message.into(), None);
+}
+
+/// Like `warn`, for a warning about a specific file
+pub fn warn_at(category: WarningCategory, path: impl AsRef<Path>, message: impl Into<String>) {
+    emit(category, message.into(), Some(path.as_ref().to_path_buf()));
+}
+
+/// Emit a warning recorded by an earlier build again, attributed to the current combination
+pub fn replay(warning: &Warning) {
+    emit(warning.category, warning.message.clone(), warning.path.clone());
+}
+
+fn emit(category: WarningCategory, message: String, path: Option<PathBuf>) {
+    let warning = Warning {
+        category,
+        message,
+        combination: COMBINATION.with(|combination| combination.borrow().clone()),
+        path,
+    };
+
+    match OBSERVER.with(|observer| observer.borrow().clone()) {
+        Some(observer) => observer.on_warning(&warning),
+        None => status!("Warning: {}", warning.message),
+    }
+
+    COLLECTED.with(|collected| {
+        if let Some(warnings) = collected.borrow_mut().as_mut() {
+            warnings.push(warning);
+        }
+    });
+}
+
+/// Warnings recorded so far by the active collector
+pub fn collected() -> Vec<Warning> {
+    COLLECTED.with(|collected| collected.borrow().clone().unwrap_or_default())
+}
+
+/// Collects the warnings emitted on the current thread until finished or dropped
+pub struct Diagnostics(());
+
+impl Diagnostics {
+    pub fn collect() -> Self {
+        COLLECTED.with(|collected| *collected.borrow_mut() = Some(Vec::new()));
+        Self(())
+    }
+
+    /// Stop collecting and return the recorded warnings
+    pub fn finish(self) -> Vec<Warning> {
+        COLLECTED.with(|collected| collected.borrow_mut().take().unwrap_or_default())
+    }
+}
+
+impl Drop for Diagnostics {
+    fn drop(&mut self) {
+        COLLECTED.with(|collected| *collected.borrow_mut() = None);
+        COMBINATION.with(|combination| *combination.borrow_mut() = None);
+    }
+}
+
+/// Attributes warnings to a combination until dropped
+pub struct CombinationScope(());
+
+/// Attribute the following warnings to the combination with this output directory
+pub fn in_combination(output_dir: &str) -> CombinationScope {
+    COMBINATION.with(|combination| *combination.borrow_mut() = Some(output_dir.to_string()));
+    CombinationScope(())
+}
+
+impl Drop for CombinationScope {
+    fn drop(&mut self) {
+        COMBINATION.with(|combination| *combination.borrow_mut() = None);
+    }
+}
+
+/// Sends warnings to an observer instead of printing them until dropped
+pub struct ObserverScope(Option<Arc<dyn BuildObserver>>);
+
+/// Report the following warnings on this thread to `observer`
+pub fn observe(observer: Arc<dyn BuildObserver>) -> ObserverScope {
+    ObserverScope(OBSERVER.with(|current| current.borrow_mut().replace(observer)))
+}
+
+impl Drop for ObserverScope {
+    fn drop(&mut self) {
+        OBSERVER.with(|current| *current.borrow_mut() = self.0.take());
+    }
+}
+
+/// Re-print the warnings grouped by category, so they are not lost in a long build log
+pub fn print_summary(warnings: &[Warning]) {
+    if warnings.is_empty() {
+        return;
+    }
+
+    let mut by_category: BTreeMap<WarningCategory, Vec<&Warning>> = BTreeMap::new();
+    for warning in warnings {
+        by_category.entry(warning.category).or_default().push(warning);
+    }
+
+    status!("\n{} warning(s):", warnings.len());
+    for (category, warnings) in by_category {
+        status!("  {} ({}):", category, warnings.len());
+        for warning in warnings {
+            match warning.combination.as_deref() {
+                Some(combination) if !combination.is_empty() => status!("    - [{}] {}", combination, warning.message),
+                _ => status!("    - {}", warning.message),
+            }
+        }
+    }
+}
+
+/// A GitHub Actions workflow command such as `::warning file=app.yml,line=3::message`, with the
+/// property values and the message escaped so commas, colons and line breaks survive
+pub fn github_command(command: &str, properties: &[(&str, String)], message: &str) -> String {
+    let escape_data = |data: &str| data.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A");
+    let escape_property = |value: &str| escape_data(value).replace(':', "%3A").replace(',', "%2C");
+    let properties: Vec<String> = properties.iter()
+        .map(|(name, value)| format!("{}={}", name, escape_property(value)))
+        .collect();
+    match properties.is_empty() {
+        true => format!("::{}::{}", command, escape_data(message)),
+        false => format!("::{} {}::{}", command, properties.join(","), escape_data(message)),
+    }
+}
+
+/// Print an `::error` workflow command for `error`, or for each combination that failed, on the file and line it points at
+pub fn print_github_annotations(error: &StackBuilderError) {
+    let errors = match error {
+        StackBuilderError::Build(BuildError::CombinationsFailed { errors, .. }) => errors.iter().collect(),
+        error => vec![error],
+    };
+    for error in errors {
+        let mut properties = Vec::new();
+        if let Some(path) = error.path() {
+            properties.push(("file", annotation_path(&path)));
+        }
+        if let Some(location) = error.location() {
+            properties.push(("line", location.line.to_string()));
+            properties.push(("col", location.column.to_string()));
+        }
+        properties.push(("title", format!("stackbuilder {}", error.code())));
+        // Workflow commands are read from stdout like the rest of the build output
+        println!("{}", github_command("error", &properties, &error.to_string()));
+    }
+}
+
+/// `path` as GitHub annotations expect it: relative to the working directory, which is the checkout in Actions
+pub fn annotation_path(path: &Path) -> String {
+    let relative = std::env::current_dir().ok()
+        .and_then(|current_dir| path.strip_prefix(current_dir).ok().map(Path::to_path_buf));
+    relative.as_deref().unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warnings_are_collected_with_combination() {
+        warn(WarningCategory::Lock, "before collecting");
+
+        let diagnostics = Diagnostics::collect();
+        warn(WarningCategory::Config, "global");
+        {
+            let _scope = in_combination("dev/monitoring");
+            warn_at(WarningCategory::MissingFile, "components/x/.env.example", "missing");
+        }
+        warn(WarningCategory::Config, "after combination");
+        let warnings = diagnostics.finish();
+
+        assert_eq!(warnings.len(), 3);
+        assert_eq!(warnings[0].combination, None);
+        assert_eq!(warnings[1].category, WarningCategory::MissingFile);
+        assert_eq!(warnings[1].combination.as_deref(), Some("dev/monitoring"));
+        assert_eq!(warnings[1].path, Some(PathBuf::from("components/x/.env.example")));
+        assert_eq!(warnings[2].combination, None);
+
+        warn(WarningCategory::Lock, "after finishing");
+        assert!(collected().is_empty());
+    }
+
+    #[test]
+    fn test_github_command_escaping() {
+        assert_eq!(github_command("endgroup", &[], ""), "::endgroup::");
+        assert_eq!(
+            github_command("warning", &[("file", "components/a,b.yml".to_string()), ("title", "missing_file".to_string())], "100% missing\nsee: docs"),
+            "::warning file=components/a%2Cb.yml,title=missing_file::100%25 missing%0Asee: docs",
+        );
+        assert_eq!(github_command("error", &[("file", "C:/stack/app.yml".to_string()), ("line", "3".to_string())], "bad"), "::error file=C%3A/stack/app.yml,line=3::bad");
+    }
+
+    #[test]
+    fn test_category_names_match_serde() {
+        for category in WarningCategory::ALL {
+            assert_eq!(serde_json::to_value(category).unwrap(), category.name());
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use std::fs;
 use std::path::Path;
 use crate::error::{Result, FileSystemError};
+use crate::diagnostics::{self, WarningCategory};
+use crate::output_writer::OutputWriter;
+use crate::paths::ResolvedPaths;
 
 /// Structure for managing .env.example file merging process
 #[derive(Debug)]
@@ -8,6 +11,10 @@ pub struct EnvMerger {
     pub base_path: String,
     pub environments_path: String,
     pub extensions_paths: Vec<String>,
+    /// Read the base component's env file even when its directory is missing, failing the merge
+    pub require_base: bool,
+    /// File read from every component directory
+    pub source_filename: String,
 }
 
 impl EnvMerger {
@@ -17,12 +24,35 @@ impl EnvMerger {
             base_path,
             environments_path,
             extensions_paths,
+            require_base: true,
+            source_filename: crate::config::ENV_EXAMPLE_FILE.to_string(),
         }
     }
+
+    /// Create an EnvMerger reading the components of `paths`
+    pub fn from_paths(paths: &ResolvedPaths) -> Self {
+        let (base_path, environments_path, extensions_paths) = paths.merger_paths();
+        Self::new(base_path, environments_path, extensions_paths)
+    }
+
+    /// Skip the base component's env file when its directory is missing, so the environment's comes first
+    pub fn with_require_base(mut self, require_base: bool) -> Self {
+        self.require_base = require_base;
+        self
+    }
+
+    /// Read `source_filename` from the components instead of .env.example
+    pub fn with_source_filename(mut self, source_filename: impl Into<String>) -> Self {
+        self.source_filename = source_filename.into();
+        self
+    }
 }
 
+/// First header line of every merged .env.example unless `generated_header` replaces it
+const GENERATED_ENV_HEADER: &str = "# Generated by stackbuilder from concatenated .env.example files";
+
 /// Structure representing concatenated .env file content
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct EnvFile {
     pub variables: Vec<(String, String)>, // Store lines as key-value pairs
     pub header_comments: Vec<String>,    // General file comments
@@ -35,9 +65,24 @@ impl EnvFile {
             header_comments: Vec::new(),
         }
     }
+
+    /// Whether any source line assigns a variable; blank and comment lines alone do not count
+    pub fn has_variables(&self) -> bool {
+        self.variables.iter().any(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+    }
+
+    /// Replace the default "Generated by stackbuilder" line with custom comment lines, keeping the source file list
+    pub fn set_generated_header(&mut self, lines: &[String]) {
+        if let Some(index) = self.header_comments.iter().position(|comment| comment == GENERATED_ENV_HEADER) {
+            self.header_comments.splice(index..=index, lines.iter().cloned());
+        }
+    }
 }
 
-/// Concatenate .env.example files in specified order: base -> environment -> extensions
+/// Concatenate the components' env files (.env.example unless configured otherwise) in order: base -> environment -> extensions
 pub fn merge_env_files(
     merger: &EnvMerger,
     environment: Option<&str>,
@@ -52,9 +97,9 @@ pub fn merge_env_files(
     for file_path in file_paths {
         match fs::read_to_string(&file_path) {
             Ok(content) => {
-                println!("Loaded and concatenating .env file: {}", file_path);
+                status!("Loaded and concatenating .env file: {}", file_path);
                 processed_files += 1;
-                source_files.push(get_source_name(&file_path));
+                source_files.push(get_source_name(&file_path, &merger.source_filename));
                 
                 // Add file content with separator
                 if !all_content.is_empty() && !all_content.ends_with('\n') {
@@ -71,20 +116,20 @@ pub fn merge_env_files(
                     }.into());
                 }
                 // For other files, skip with warning
-                println!("Warning: Skipping missing .env.example file '{}': {}", file_path, e);
+                diagnostics::warn_at(WarningCategory::MissingFile, &file_path, format!("Skipping missing {} file '{}': {}", merger.source_filename, file_path, e));
                 continue;
             }
         }
     }
 
     if processed_files == 0 {
-        println!("Warning: No .env.example files found to concatenate");
+        diagnostics::warn(WarningCategory::EnvExample, format!("No {} files found to concatenate", merger.source_filename));
         return Ok(EnvFile::new());
     }
 
     // Create simple structure with all content
     let mut env_file = EnvFile::new();
-    env_file.header_comments.push("# Generated by stackbuilder from concatenated .env.example files".to_string());
+    env_file.header_comments.push(GENERATED_ENV_HEADER.to_string());
     if !source_files.is_empty() {
         env_file.header_comments.push(format!("# Source files: {}", source_files.join(", ")));
     }
@@ -94,14 +139,33 @@ pub fn merge_env_files(
         env_file.variables.push((format!("line_{}", i), line.to_string()));
     }
 
-    println!("Successfully concatenated {} .env.example files with {} total lines",
-             processed_files, env_file.variables.len());
+    status!("Successfully concatenated {} {} files with {} total lines",
+             processed_files, merger.source_filename, env_file.variables.len());
     
     Ok(env_file)
 }
 
+/// Parse dotenv content into key-value pairs in file order.
+/// Blank lines, comments and keys without a value are skipped; `export` prefixes and surrounding quotes are removed.
+pub fn parse_env_file(content: &str) -> Vec<(String, String)> {
+    content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = match (value.chars().next(), value.chars().last()) {
+                (Some(q @ ('"' | '\'')), Some(last)) if value.len() >= 2 && last == q => &value[1..value.len() - 1],
+                _ => value,
+            };
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
 /// Write concatenated .env.example file to specified path
-pub fn write_merged_env(env_file: &EnvFile, output_path: &str) -> Result<()> {
+pub fn write_merged_env(env_file: &EnvFile, output_path: &Path, writer: &OutputWriter) -> Result<()> {
     let mut content = String::new();
 
     // Write header comments first
@@ -126,17 +190,13 @@ pub fn write_merged_env(env_file: &EnvFile, output_path: &str) -> Result<()> {
         content.pop();
     }
 
-    fs::write(output_path, content)
-        .map_err(|e| FileSystemError::FileWriteFailed {
-            path: output_path.into(),
-            source: e,
-        })?;
+    writer.write(output_path, content)?;
 
-    println!("✓ Created concatenated .env.example file: {}", output_path);
+    status!("✓ Created concatenated env file: {}", output_path.display());
     Ok(())
 }
 
-/// Resolve the order of .env.example files to merge
+/// Resolve the order of the components' `source_filename` files to merge
 fn resolve_env_merge_order(
     merger: &EnvMerger,
     environment: Option<&str>,
@@ -144,15 +204,17 @@ fn resolve_env_merge_order(
 ) -> Result<Vec<String>> {
     let mut file_paths = Vec::new();
 
-    // Always start with base
-    let base_file = Path::new(&merger.base_path).join(".env.example");
-    file_paths.push(base_file.to_string_lossy().to_string());
+    // Start with base unless it is optional and missing
+    let base_file = Path::new(&merger.base_path).join(&merger.source_filename);
+    if merger.require_base || Path::new(&merger.base_path).exists() {
+        file_paths.push(base_file.to_string_lossy().to_string());
+    }
 
     // Add environment file if specified
     if let Some(env) = environment {
         let env_file = Path::new(&merger.environments_path)
             .join(env)
-            .join(".env.example");
+            .join(&merger.source_filename);
         file_paths.push(env_file.to_string_lossy().to_string());
     }
 
@@ -160,7 +222,7 @@ fn resolve_env_merge_order(
     for ext in extensions {
         let mut found = false;
         for ext_dir in &merger.extensions_paths {
-            let ext_file = Path::new(ext_dir).join(ext).join(".env.example");
+            let ext_file = Path::new(ext_dir).join(ext).join(&merger.source_filename);
             if ext_file.exists() {
                 file_paths.push(ext_file.to_string_lossy().to_string());
                 found = true;
@@ -169,7 +231,7 @@ fn resolve_env_merge_order(
         }
         
         if !found {
-            println!("Warning: .env.example for extension '{}' not found in any extensions directory", ext);
+            diagnostics::warn(WarningCategory::MissingFile, format!("{} for extension '{}' not found in any extensions directory", merger.source_filename, ext));
         }
     }
 
@@ -177,17 +239,14 @@ fn resolve_env_merge_order(
 }
 
 /// Extract readable source name from file path
-fn get_source_name(file_path: &str) -> String {
+fn get_source_name(file_path: &str, source_filename: &str) -> String {
     let path = Path::new(file_path);
     
     // Get parent directory name
     if let Some(parent) = path.parent() {
         if let Some(dir_name) = parent.file_name() {
             if let Some(name) = dir_name.to_str() {
-                return match name {
-                    "base" => "base/.env.example".to_string(),
-                    other => format!("{}/.env.example", other),
-                };
+                return format!("{}/{}", name, source_filename);
             }
         }
     }
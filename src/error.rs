@@ -1,3 +1,4 @@
+use std::ops::Range;
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -21,6 +22,9 @@ pub enum StackBuilderError {
     
     #[error(transparent)]
     Init(#[from] InitError),
+    
+    #[error(transparent)]
+    Backup(#[from] BackupError),
 }
 
 /// Configuration-related errors
@@ -32,11 +36,17 @@ pub enum ConfigError {
     #[error("Failed to read configuration file '{file}': {source}")]
     ConfigFileReadError { file: String, source: std::io::Error },
     
-    #[error("Invalid TOML syntax in configuration file '{file}': {details}")]
-    InvalidTomlSyntax { file: String, details: String },
+    #[error("Invalid TOML syntax in configuration file '{file}{}': {details}{}", position(location), snippet(location))]
+    InvalidTomlSyntax { file: String, details: String, location: Option<SourceLocation> },
+
+    #[error("Invalid JSON syntax in configuration file '{file}{}': {details}{}", position(location), snippet(location))]
+    InvalidJsonSyntax { file: String, details: String, location: Option<SourceLocation> },
     
     #[error("Failed to serialize configuration to TOML: {details}")]
     TomlSerializationError { details: String },
+
+    #[error("Unknown configuration keys: {}", keys.join(", "))]
+    UnknownKeys { keys: Vec<String> },
 }
 
 /// Validation-related errors
@@ -48,12 +58,11 @@ pub enum ValidationError {
     #[error("Base directory '{path}' does not exist in components directory. Create base/docker-compose.yml file")]
     BaseDirectoryNotFound { path: PathBuf },
     
-    #[cfg(test)]
     #[error("Environment '{name}' does not exist in environments directory '{path}'")]
-    EnvironmentNotFound { name: String, path: PathBuf },
+    EnvironmentNotFound { name: String, path: PathBuf, available: Vec<String> },
     
     #[error("Extension '{name}' not found in any extensions directory. Available directories: {available_dirs:?}")]
-    ExtensionNotFound { name: String, available_dirs: Vec<String> },
+    ExtensionNotFound { name: String, available_dirs: Vec<String>, available: Vec<String> },
     
     #[error("Combo '{combo_name}' not found in combo definitions. Available combos: {available_combos:?}")]
     ComboNotFound { combo_name: String, available_combos: Vec<String> },
@@ -63,6 +72,48 @@ pub enum ValidationError {
     
     #[error("Invalid path resolution for '{path}': {details}")]
     PathResolutionError { path: String, details: String },
+    
+    #[error("Extensions not used by any build combination: {names:?}")]
+    OrphanExtensions { names: Vec<String> },
+
+    #[error("combo_name_separator '{separator}' must not be empty or contain a path separator")]
+    InvalidComboNameSeparator { separator: String },
+
+    #[error("{key} '{name}' must be a plain file name without a path separator")]
+    InvalidEnvFilename { key: String, name: String },
+
+    #[error("Combinations write to the same output directory '{output_dir}': {first} and {second}")]
+    OutputDirCollision { output_dir: String, first: String, second: String },
+
+    #[error("'{tag}' for '{key}' in the image_tags of environment '{environment}' is not a valid image tag")]
+    InvalidImageTag { environment: String, key: String, tag: String },
+
+    #[error("Alias '{alias}' of '{name}' in [build.aliases] {reason}")]
+    InvalidAlias { name: String, alias: String, reason: String },
+
+    #[error("'{first}' and '{second}' in [build.aliases] have the same alias '{alias}'")]
+    DuplicateAlias { alias: String, first: String, second: String },
+
+    #[error("Alias '{inner}' of '{second}' in [build.aliases] is inside the alias '{outer}' of '{first}'")]
+    NestedAlias { outer: String, inner: String, first: String, second: String },
+
+    #[error("Matrix group '{group}' in [build.matrix] {details}")]
+    InvalidMatrixGroup { group: String, details: String },
+
+    #[error("[build.matrix] expands to {count} combinations, more than max_combinations = {max}")]
+    TooManyCombinations { count: usize, max: usize },
+
+    #[error("Combination '{combination}' has more than one extension of exclusive group '{group}': {}", members.join(", "))]
+    ExclusiveGroupConflict { group: String, combination: String, members: Vec<String> },
+
+    #[error("default_environment '{name}' is not a configured environment. Configured environments: {available:?}")]
+    DefaultEnvironmentNotFound { name: String, available: Vec<String> },
+
+    #[error("'{path}' overlaps the read-only components directory '{root}'; stackbuilder never writes into components")]
+    OutputInsideComponentsRoot { path: PathBuf, root: PathBuf },
+
+    #[error("Configuration has {} problems:{}", errors.len(), numbered_list(errors))]
+    Multiple { errors: Vec<StackBuilderError> },
 }
 
 /// Build process errors
@@ -73,6 +124,54 @@ pub enum BuildError {
     
     #[error("Build process failed: {details}")]
     BuildProcessFailed { details: String },
+    
+    #[error("Refusing to clean build directory '{path}': {reason}")]
+    UnsafeBuildDirectory { path: PathBuf, reason: String },
+    
+    #[error("Another build holds the lock file '{lock_path}' (owner PID: {pid:?})")]
+    BuildLocked { lock_path: PathBuf, pid: Option<u32> },
+
+    #[error("Unknown build combination '{name}'. Available combinations: {}", available.join(", "))]
+    UnknownCombination { name: String, available: Vec<String> },
+
+    #[error("--strict: {count} warning(s) in categories {}, the build directory was left unchanged", categories.join(", "))]
+    StrictWarnings { count: usize, categories: Vec<String> },
+
+    #[error("Failed to build combination '{}' ({}): {source}", combination_label(output_dir), combination_layers(environment, extensions))]
+    CombinationFailed { output_dir: String, environment: Option<String>, extensions: Vec<String>, source: Box<StackBuilderError> },
+
+    #[error("{} of {total} combinations failed:{}", errors.len(), numbered_list(errors))]
+    CombinationsFailed { total: usize, errors: Vec<StackBuilderError> },
+
+    #[error("No valid build combinations found")]
+    NoCombinations,
+
+    #[error("--use-default-env requires default_environment in the [build] section")]
+    DefaultEnvironmentNotSet,
+
+    #[error("Checksums manifest '{path}' not found")]
+    ChecksumsNotFound { path: PathBuf },
+
+    #[error("Build output does not match its checksums: {modified} modified, {missing} missing, {extra} extra file(s)")]
+    ChecksumMismatch { modified: usize, missing: usize, extra: usize },
+
+    #[error("Archive '{path}' would be written into the build directory it packs")]
+    ArchiveInBuildDir { path: PathBuf },
+
+    #[error("No archive path given and archive_path is not set in the [build] section")]
+    ArchivePathNotSet,
+
+    #[error("Pre-build command '{command}' {status}{}", command_output(output))]
+    PreBuildCommandFailed { command: String, status: String, output: String },
+
+    #[error("{} image(s) not found by the image check: {}", images.len(), images.join(", "))]
+    MissingImages { images: Vec<String> },
+
+    #[error("Service '{service}' is defined differently by {first} and {second}: {}", paths.join(", "))]
+    ServiceConflict { service: String, first: String, second: String, paths: Vec<String> },
+
+    #[error("'--config -' reads the configuration from stdin once, but watch reloads it on every change")]
+    WatchConfigFromStdin,
 }
 
 /// File system operation errors
@@ -95,8 +194,8 @@ pub enum FileSystemError {
 /// YAML processing errors
 #[derive(Error, Debug)]
 pub enum YamlError {
-    #[error("Failed to parse YAML file '{file}': {details}")]
-    ParseError { file: String, details: String },
+    #[error("Failed to parse YAML file '{file}{}': {details}{}", position(location), snippet(location))]
+    ParseError { file: String, details: String, location: Option<SourceLocation> },
     
     #[error("YAML serialization failed: {details}")]
     SerializationError { details: String },
@@ -116,21 +215,278 @@ pub enum InitError {
     
     #[error("Failed to create example files: {details}")]
     ExampleFileCreationFailed { details: String },
+
+    #[error("Components directory '{path}' already exists, refusing to adopt compose files into it")]
+    ComponentsDirectoryExists { path: PathBuf },
+
+    #[error("Unknown template '{name}'. Available templates: {}", available.join(", "))]
+    UnknownTemplate { name: String, available: Vec<String> },
+
+    #[error("Overwriting '{path}' was not confirmed")]
+    OverwriteNotConfirmed { path: PathBuf },
+
+    #[error("No component tree found in '{path}': expected folders containing a docker-compose.yml")]
+    ComponentTreeNotFound { path: PathBuf },
+
+    #[error("Found several plausible {kind} directories: {}", candidates.join(", "))]
+    AmbiguousLayout { kind: &'static str, candidates: Vec<String>, flag: &'static str },
+
+    #[error("'--config -' reads the configuration from stdin, but init writes a configuration file")]
+    ConfigToStdin,
+}
+
+/// Backup management errors
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("Backup '{backup_id}' not found in '{backup_dir}'. Available backups: {available:?}")]
+    BackupNotFound { backup_id: String, backup_dir: PathBuf, available: Vec<String> },
+    
+    #[error("Failed to read backup '{backup_id}': {details}")]
+    InvalidBackup { backup_id: String, details: String },
+    
+    #[error("Path '{path}' is not recorded in backup '{backup_id}'")]
+    PathNotInBackup { backup_id: String, path: PathBuf },
+    
+    #[error("Refusing to overwrite existing files with different content: {paths:?}")]
+    RestoreConflict { paths: Vec<PathBuf> },
+
+    #[error("No backups found in '{backup_dir}'")]
+    NoBackups { backup_dir: PathBuf },
+
+    #[error("Backup '{backup_id}' records '{path}', which is not a relative path inside the build directory")]
+    UnsafePath { backup_id: String, path: PathBuf },
+}
+
+/// Position of a parse error in its source file, with an annotated excerpt of the surrounding lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, counted in characters
+    pub column: usize,
+    pub snippet: String,
+}
+
+impl SourceLocation {
+    /// Locate a byte range of `content`, as reported by toml. The caret underlines the range up to the end of its first line
+    pub fn from_span(content: &str, span: Range<usize>) -> Self {
+        let mut start = span.start.min(content.len());
+        while !content.is_char_boundary(start) {
+            start -= 1;
+        }
+        let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = content[start..].find('\n').map_or(content.len(), |i| start + i);
+        let mut end = span.end.clamp(start, line_end);
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        let line = content[..start].matches('\n').count() + 1;
+        let column = content[line_start..start].chars().count() + 1;
+        let width = content[start..end].chars().count();
+        Self::new(content, line, column, width)
+    }
+
+    /// Locate a 1-based line and column of `content`, as reported by serde_yaml
+    pub fn from_line_column(content: &str, line: usize, column: usize) -> Self {
+        Self::new(content, line.max(1), column.max(1), 1)
+    }
+
+    /// Render the offending line and the one before it, cargo style, with a caret under the error
+    fn new(content: &str, line: usize, column: usize, width: usize) -> Self {
+        let lines: Vec<&str> = content.lines().collect();
+        let source_line = |number: usize| lines.get(number - 1).copied().unwrap_or("");
+        let gutter = line.to_string().len();
+
+        let mut snippet = format!("{:gutter$} |\n", "");
+        for number in line.saturating_sub(1).max(1)..=line {
+            snippet.push_str(&format!("{:>gutter$} | {}\n", number, source_line(number)));
+        }
+        // Keep tabs so the caret lines up with the source text
+        let indent: String = source_line(line).chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        snippet.push_str(&format!("{:gutter$} | {}{}", "", indent, "^".repeat(width.max(1))));
+
+        Self { line, column, snippet }
+    }
+}
+
+/// Output directory of a combination, `.` for the build root
+pub(crate) fn combination_label(output_dir: &str) -> &str {
+    if output_dir.is_empty() { "." } else { output_dir }
+}
+
+/// Environment and extensions a combination is made of, for error messages
+pub(crate) fn combination_layers(environment: &Option<String>, extensions: &[String]) -> String {
+    let environment = match environment {
+        Some(environment) => format!("environment: {}", environment),
+        None => "no environment".to_string(),
+    };
+    match extensions.is_empty() {
+        true => format!("{}, no extensions", environment),
+        false => format!("{}, extensions: {}", environment, extensions.join(", ")),
+    }
+}
+
+/// `:line:column` suffix for the file name in parse errors
+fn position(location: &Option<SourceLocation>) -> String {
+    location.as_ref()
+        .map(|location| format!(":{}:{}", location.line, location.column))
+        .unwrap_or_default()
+}
+
+/// Annotated excerpt appended below parse errors
+fn snippet(location: &Option<SourceLocation>) -> String {
+    location.as_ref()
+        .map(|location| format!("\n{}", location.snippet))
+        .unwrap_or_default()
+}
+
+/// Output of a failed command, indented below the message
+fn command_output(output: &str) -> String {
+    output.lines()
+        .map(|line| format!("\n  {}", line))
+        .collect()
+}
+
+/// One numbered line per collected error
+fn numbered_list(errors: &[StackBuilderError]) -> String {
+    errors.iter()
+        .enumerate()
+        .map(|(i, error)| format!("\n  {}. {}", i + 1, error))
+        .collect()
 }
 
 impl StackBuilderError {
-    /// Get the exit code for this error type
+    /// Get the exit code for this error type.
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 0 | Success, including builds that only emitted warnings without `--strict` |
+    /// | 1 | Configuration could not be read or parsed |
+    /// | 2 | Configuration is invalid |
+    /// | 3 | Build failed |
+    /// | 4 | Filesystem operation failed |
+    /// | 5 | YAML could not be parsed, merged or written |
+    /// | 6 | `init` failed |
+    /// | 7 | Backup operation failed |
+    /// | 8 | Nothing to build: the configuration produced no combinations |
+    /// | 9 | `--strict` turned warnings into a failure |
+    /// | 10 | `verify` found modified, missing or extra files |
+    ///
+    /// A failed combination exits with the code of the error that caused it.
     pub fn exit_code(&self) -> i32 {
         match self {
+            StackBuilderError::Build(BuildError::NoCombinations) => 8,
+            StackBuilderError::Build(BuildError::ChecksumMismatch { .. }) => 10,
+            StackBuilderError::Build(BuildError::StrictWarnings { .. }) => 9,
+            StackBuilderError::Build(BuildError::CombinationFailed { source, .. }) => source.exit_code(),
             StackBuilderError::Config(_) => 1,
             StackBuilderError::Validation(_) => 2,
             StackBuilderError::Build(_) => 3,
             StackBuilderError::FileSystem(_) => 4,
             StackBuilderError::Yaml(_) => 5,
             StackBuilderError::Init(_) => 6,
+            StackBuilderError::Backup(_) => 7,
         }
     }
     
+    /// Stable machine-readable code, `<category>.<variant>`. Codes must never change once released
+    pub fn code(&self) -> &'static str {
+        match self {
+            StackBuilderError::Config(e) => e.code(),
+            StackBuilderError::Validation(e) => e.code(),
+            StackBuilderError::Build(e) => e.code(),
+            StackBuilderError::FileSystem(e) => e.code(),
+            StackBuilderError::Yaml(e) => e.code(),
+            StackBuilderError::Init(e) => e.code(),
+            StackBuilderError::Backup(e) => e.code(),
+        }
+    }
+
+    /// File or directory the error is about, when there is one
+    pub fn path(&self) -> Option<PathBuf> {
+        match self {
+            StackBuilderError::Build(BuildError::CombinationFailed { source, .. }) => source.path(),
+            StackBuilderError::Config(ConfigError::ConfigFileNotFound { file })
+            | StackBuilderError::Config(ConfigError::ConfigFileReadError { file, .. })
+            | StackBuilderError::Config(ConfigError::InvalidTomlSyntax { file, .. })
+            | StackBuilderError::Config(ConfigError::InvalidJsonSyntax { file, .. }) => Some(PathBuf::from(file)),
+            StackBuilderError::Validation(ValidationError::ComponentsDirectoryNotFound { path })
+            | StackBuilderError::Validation(ValidationError::BaseDirectoryNotFound { path }) => Some(path.clone()),
+            StackBuilderError::Validation(ValidationError::EnvironmentNotFound { path, .. })
+            | StackBuilderError::Validation(ValidationError::OutputInsideComponentsRoot { path, .. }) => Some(path.clone()),
+            StackBuilderError::Validation(ValidationError::PathResolutionError { path, .. }) => Some(PathBuf::from(path)),
+            StackBuilderError::Build(BuildError::OutputFileWriteError { path, .. })
+            | StackBuilderError::Build(BuildError::UnsafeBuildDirectory { path, .. }) => Some(path.clone()),
+            StackBuilderError::Build(BuildError::BuildLocked { lock_path, .. }) => Some(lock_path.clone()),
+            StackBuilderError::Build(BuildError::ChecksumsNotFound { path })
+            | StackBuilderError::Build(BuildError::ArchiveInBuildDir { path }) => Some(path.clone()),
+            StackBuilderError::FileSystem(FileSystemError::DirectoryCreationFailed { path, .. })
+            | StackBuilderError::FileSystem(FileSystemError::DirectoryReadFailed { path, .. })
+            | StackBuilderError::FileSystem(FileSystemError::FileReadFailed { path, .. })
+            | StackBuilderError::FileSystem(FileSystemError::FileWriteFailed { path, .. }) => Some(path.clone()),
+            StackBuilderError::Yaml(YamlError::ParseError { file, .. })
+            | StackBuilderError::Yaml(YamlError::InvalidComposeFormat { file, .. }) => Some(PathBuf::from(file)),
+            StackBuilderError::Init(InitError::ComponentsDirectoryExists { path })
+            | StackBuilderError::Init(InitError::OverwriteNotConfirmed { path })
+            | StackBuilderError::Init(InitError::ComponentTreeNotFound { path }) => Some(path.clone()),
+            StackBuilderError::Backup(BackupError::BackupNotFound { backup_dir, .. }) => Some(backup_dir.clone()),
+            StackBuilderError::Backup(BackupError::PathNotInBackup { path, .. }) => Some(path.clone()),
+            StackBuilderError::Backup(BackupError::NoBackups { backup_dir }) => Some(backup_dir.clone()),
+            StackBuilderError::Backup(BackupError::UnsafePath { path, .. }) => Some(path.clone()),
+            _ => None,
+        }
+    }
+
+    /// Single JSON object describing the error, for `--error-format json`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "code": self.code(),
+            "message": self.to_string(),
+            "suggestion": self.suggestion(),
+            "path": self.path(),
+            "line": self.location().map(|location| location.line),
+            "column": self.location().map(|location| location.column),
+            "combination": self.combination().map(|(output_dir, environment, extensions)| serde_json::json!({
+                "output_dir": output_dir,
+                "environment": environment,
+                "extensions": extensions,
+            })),
+            "errors": match self {
+                StackBuilderError::Validation(ValidationError::Multiple { errors })
+                | StackBuilderError::Build(BuildError::CombinationsFailed { errors, .. }) => {
+                    Some(errors.iter().map(StackBuilderError::to_json).collect::<Vec<_>>())
+                }
+                _ => None,
+            },
+            "exit_code": self.exit_code(),
+        })
+    }
+
+    /// Output directory, environment and extensions of the combination the error occurred in
+    pub fn combination(&self) -> Option<(&str, Option<&str>, &[String])> {
+        match self {
+            StackBuilderError::Build(BuildError::CombinationFailed { output_dir, environment, extensions, .. }) => {
+                Some((output_dir, environment.as_deref(), extensions))
+            }
+            _ => None,
+        }
+    }
+
+    /// Position inside `path` for parse errors that could be located
+    pub fn location(&self) -> Option<&SourceLocation> {
+        match self {
+            StackBuilderError::Config(ConfigError::InvalidTomlSyntax { location, .. })
+            | StackBuilderError::Config(ConfigError::InvalidJsonSyntax { location, .. })
+            | StackBuilderError::Yaml(YamlError::ParseError { location, .. }) => location.as_ref(),
+            StackBuilderError::Build(BuildError::CombinationFailed { source, .. }) => source.location(),
+            _ => None,
+        }
+    }
+
     /// Check if this error suggests running init command
     pub fn suggests_init(&self) -> bool {
         matches!(
@@ -144,6 +500,7 @@ impl StackBuilderError {
     /// Get helpful suggestion for fixing this error
     pub fn suggestion(&self) -> Option<String> {
         match self {
+            StackBuilderError::Build(BuildError::CombinationFailed { source, .. }) => source.suggestion(),
             StackBuilderError::Config(ConfigError::ConfigFileNotFound { .. }) => {
                 Some("Run 'stackbuilder init' to create a new project with default configuration".to_string())
             }
@@ -153,8 +510,128 @@ impl StackBuilderError {
             StackBuilderError::Validation(ValidationError::BaseDirectoryNotFound { .. }) => {
                 Some("Create a base/docker-compose.yml file in your components directory".to_string())
             }
-            StackBuilderError::Validation(ValidationError::ExtensionNotFound { name, .. }) => {
-                Some(format!("Create an extension directory and docker-compose.yml file for '{}'", name))
+            StackBuilderError::Validation(ValidationError::ExtensionNotFound { name, available, .. }) => {
+                Some(match did_you_mean(name, available) {
+                    Some(hint) => hint,
+                    None => format!("Create an extension directory and docker-compose.yml file for '{}'", name),
+                })
+            }
+            StackBuilderError::Validation(ValidationError::EnvironmentNotFound { name, available, .. }) => {
+                did_you_mean(name, available)
+            }
+            StackBuilderError::Validation(ValidationError::DefaultEnvironmentNotFound { name, available }) => {
+                did_you_mean(name, available)
+            }
+            StackBuilderError::Validation(ValidationError::ComboNotFound { combo_name, available_combos }) => {
+                did_you_mean(combo_name, available_combos)
+            }
+            StackBuilderError::Build(BuildError::UnknownCombination { name, available }) => {
+                did_you_mean(name, available)
+            }
+            StackBuilderError::Validation(ValidationError::OutputInsideComponentsRoot { .. }) => {
+                Some("Move build_dir and backup_dir out of components_dirs and the base, environment and extension directories".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::OutputDirCollision { .. }) => {
+                Some("Rename the combo or extension, or remove the duplicate entry, so every combination gets its own output directory".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::InvalidImageTag { .. }) => {
+                Some("Use only the tag, e.g. \"sha-3f9a2c1\" or \"1.4.2\": up to 128 letters, digits, '_', '.' and '-', not starting with '.' or '-'".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::InvalidAlias { .. }) => {
+                Some("Use a relative directory below the build directory, e.g. \"prod-hardened\" or \"prod/hardened\"".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::DuplicateAlias { .. }) => {
+                Some("Give every aliased combination its own output directory".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::NestedAlias { .. }) => {
+                Some("Use aliases that are not parent directories of one another; a rebuild of the outer one would replace the inner one".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::InvalidMatrixGroup { .. }) => {
+                Some(format!("List at least one extension per group, or \"{}\" for combinations without the group", crate::config::MATRIX_NONE))
+            }
+            StackBuilderError::Validation(ValidationError::TooManyCombinations { .. }) => {
+                Some("Remove values from [build.matrix], or raise max_combinations in [build] if the size is intended".to_string())
+            }
+            StackBuilderError::Validation(ValidationError::ExclusiveGroupConflict { group, .. }) => {
+                Some(format!("Keep one extension of '{}' per combination, or remove the group from [build.exclusive_groups] if they can be combined", group))
+            }
+            StackBuilderError::Validation(ValidationError::OrphanExtensions { .. }) => {
+                Some("Reference the extensions from an environment, combo or the global extensions list, or remove their directories".to_string())
+            }
+            StackBuilderError::Build(BuildError::UnsafeBuildDirectory { .. }) => {
+                Some("Point build_dir at a dedicated output directory, or pass --force-clean if you are sure it can be wiped".to_string())
+            }
+            StackBuilderError::Build(BuildError::NoCombinations) => {
+                Some("Check the environments, extensions and combos in [build] and their skip_base_generation settings".to_string())
+            }
+            StackBuilderError::Build(BuildError::DefaultEnvironmentNotSet) => {
+                Some("Add default_environment = \"<environment>\" to the [build] section of stackbuilder.toml".to_string())
+            }
+            StackBuilderError::Build(BuildError::ChecksumsNotFound { .. }) => {
+                Some("Run 'stackbuilder build' with write_checksums enabled, or pass --build-dir to verify another build directory".to_string())
+            }
+            StackBuilderError::Build(BuildError::ChecksumMismatch { .. }) => {
+                Some("Rebuild with 'stackbuilder build' to regenerate the outputs, or find out who changed the listed files".to_string())
+            }
+            StackBuilderError::Build(BuildError::ArchiveInBuildDir { .. }) => {
+                Some("Write the archive outside build_dir, e.g. --archive dist/stacks.tar.gz".to_string())
+            }
+            StackBuilderError::Build(BuildError::ArchivePathNotSet) => {
+                Some("Pass the archive file, e.g. 'stackbuilder archive dist/stacks.tar.gz', or set archive_path in [build]".to_string())
+            }
+            StackBuilderError::Build(BuildError::ServiceConflict { .. }) => {
+                Some("Rename the service in one of the extensions, keep them apart with [build.exclusive_groups], or mark an intended replacement with !override".to_string())
+            }
+            StackBuilderError::Build(BuildError::WatchConfigFromStdin) => {
+                Some("Pass a file path to --config so watch can pick up its changes".to_string())
+            }
+            StackBuilderError::Build(BuildError::MissingImages { .. }) => {
+                Some("Push the images or fix their references; the previous build directory was kept".to_string())
+            }
+            StackBuilderError::Build(BuildError::PreBuildCommandFailed { .. }) => {
+                Some("Fix pre_build_command in [build], or pass --skip-pre-build to build from the components as they are".to_string())
+            }
+            StackBuilderError::Build(BuildError::CombinationsFailed { total, errors }) if errors.len() < *total => {
+                Some("The other combinations were written to the build directory; fix the listed combinations and build again".to_string())
+            }
+            StackBuilderError::Build(BuildError::CombinationsFailed { .. }) => {
+                Some("The previous build directory was kept; fix the listed combinations and build again".to_string())
+            }
+            StackBuilderError::Build(BuildError::StrictWarnings { .. }) => {
+                Some("Fix the warnings listed in the summary, or narrow strict_categories in stackbuilder.toml".to_string())
+            }
+            StackBuilderError::Build(BuildError::BuildLocked { .. }) => {
+                Some("Wait for the other build to finish, increase lock_timeout, or pass --no-lock if you are sure no other build is running".to_string())
+            }
+            StackBuilderError::Init(InitError::ComponentsDirectoryExists { .. }) => {
+                Some("Pass --force to adopt the compose files into the existing components directory".to_string())
+            }
+            StackBuilderError::Init(InitError::OverwriteNotConfirmed { .. }) => {
+                Some("Pass --yes to overwrite the configuration without confirmation".to_string())
+            }
+            StackBuilderError::Init(InitError::AmbiguousLayout { flag, .. }) => {
+                Some(format!("Pass --{} to choose one of them", flag))
+            }
+            StackBuilderError::Init(InitError::ComponentTreeNotFound { .. }) => {
+                Some("Pass --components-dir, or run 'stackbuilder init' without --adopt to scaffold a new tree".to_string())
+            }
+            StackBuilderError::Init(InitError::ConfigToStdin) => {
+                Some("Pass a file path to --config, or leave it out to write stackbuilder.toml".to_string())
+            }
+            StackBuilderError::Init(InitError::UnknownTemplate { .. }) => {
+                Some("Run 'stackbuilder init --list-templates' to see template descriptions".to_string())
+            }
+            StackBuilderError::Config(ConfigError::UnknownKeys { .. }) => {
+                Some("Fix the spelling of the listed keys or remove them; unknown keys are ignored by builds".to_string())
+            }
+            StackBuilderError::Backup(BackupError::BackupNotFound { .. }) => {
+                Some("Run 'stackbuilder backups list' to see available backups".to_string())
+            }
+            StackBuilderError::Backup(BackupError::RestoreConflict { .. }) => {
+                Some("Use --force to overwrite the existing files".to_string())
+            }
+            StackBuilderError::Backup(BackupError::UnsafePath { .. }) => {
+                Some("The backup metadata was edited or is corrupted; copy the files you need out of the backup by hand".to_string())
             }
             StackBuilderError::Yaml(YamlError::InvalidComposeFormat { .. }) => {
                 Some("Verify your docker-compose.yml files have valid YAML syntax and Docker Compose structure".to_string())
@@ -164,22 +641,172 @@ impl StackBuilderError {
     }
 }
 
+/// Maximum edit distance for a "did you mean" hint
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// "Did you mean" hint naming the candidate closest to `name`, if one is within a couple of typos
+fn did_you_mean(name: &str, candidates: &[String]) -> Option<String> {
+    closest_match(name, candidates).map(|candidate| format!("Did you mean '{}'?", candidate))
+}
+
+/// The candidate closest to `name` within a couple of typos
+pub(crate) fn closest_match<'a, S: AsRef<str>>(name: &str, candidates: &'a [S]) -> Option<&'a str> {
+    candidates.iter()
+        .map(AsRef::as_ref)
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (strsim::levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
 // Convenience type alias for Results
 pub type Result<T> = std::result::Result<T, StackBuilderError>;
 
+impl ConfigError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConfigError::ConfigFileNotFound { .. } => "config.file_not_found",
+            ConfigError::ConfigFileReadError { .. } => "config.file_read_error",
+            ConfigError::InvalidTomlSyntax { .. } => "config.invalid_toml_syntax",
+            ConfigError::InvalidJsonSyntax { .. } => "config.invalid_json_syntax",
+            ConfigError::TomlSerializationError { .. } => "config.toml_serialization_error",
+            ConfigError::UnknownKeys { .. } => "config.unknown_keys",
+        }
+    }
+}
+
+impl ValidationError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ValidationError::ComponentsDirectoryNotFound { .. } => "validation.components_directory_not_found",
+            ValidationError::BaseDirectoryNotFound { .. } => "validation.base_directory_not_found",
+            ValidationError::EnvironmentNotFound { .. } => "validation.environment_not_found",
+            ValidationError::ExtensionNotFound { .. } => "validation.extension_not_found",
+            ValidationError::ComboNotFound { .. } => "validation.combo_not_found",
+            ValidationError::InvalidComboDefinition { .. } => "validation.invalid_combo_definition",
+            ValidationError::PathResolutionError { .. } => "validation.path_resolution_error",
+            ValidationError::OrphanExtensions { .. } => "validation.orphan_extensions",
+            ValidationError::InvalidComboNameSeparator { .. } => "validation.invalid_combo_name_separator",
+            ValidationError::InvalidEnvFilename { .. } => "validation.invalid_env_filename",
+            ValidationError::OutputDirCollision { .. } => "validation.output_dir_collision",
+            ValidationError::InvalidImageTag { .. } => "validation.invalid_image_tag",
+            ValidationError::InvalidAlias { .. } => "validation.invalid_alias",
+            ValidationError::DuplicateAlias { .. } => "validation.duplicate_alias",
+            ValidationError::NestedAlias { .. } => "validation.nested_alias",
+            ValidationError::InvalidMatrixGroup { .. } => "validation.invalid_matrix_group",
+            ValidationError::TooManyCombinations { .. } => "validation.too_many_combinations",
+            ValidationError::ExclusiveGroupConflict { .. } => "validation.exclusive_group_conflict",
+            ValidationError::DefaultEnvironmentNotFound { .. } => "validation.default_environment_not_found",
+            ValidationError::OutputInsideComponentsRoot { .. } => "validation.output_inside_components_root",
+            ValidationError::Multiple { .. } => "validation.multiple",
+        }
+    }
+}
+
+impl BuildError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            BuildError::OutputFileWriteError { .. } => "build.output_file_write_error",
+            BuildError::BuildProcessFailed { .. } => "build.process_failed",
+            BuildError::UnsafeBuildDirectory { .. } => "build.unsafe_build_directory",
+            BuildError::BuildLocked { .. } => "build.locked",
+            BuildError::UnknownCombination { .. } => "build.unknown_combination",
+            BuildError::StrictWarnings { .. } => "build.strict_warnings",
+            BuildError::CombinationFailed { .. } => "build.combination_failed",
+            BuildError::CombinationsFailed { .. } => "build.combinations_failed",
+            BuildError::NoCombinations => "build.no_combinations",
+            BuildError::DefaultEnvironmentNotSet => "build.default_environment_not_set",
+            BuildError::ChecksumsNotFound { .. } => "build.checksums_not_found",
+            BuildError::ChecksumMismatch { .. } => "build.checksum_mismatch",
+            BuildError::ArchiveInBuildDir { .. } => "build.archive_in_build_dir",
+            BuildError::ArchivePathNotSet => "build.archive_path_not_set",
+            BuildError::PreBuildCommandFailed { .. } => "build.pre_build_command_failed",
+            BuildError::MissingImages { .. } => "build.missing_images",
+            BuildError::ServiceConflict { .. } => "build.service_conflict",
+            BuildError::WatchConfigFromStdin => "build.watch_config_from_stdin",
+        }
+    }
+}
+
+impl FileSystemError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FileSystemError::DirectoryCreationFailed { .. } => "filesystem.directory_creation_failed",
+            FileSystemError::DirectoryReadFailed { .. } => "filesystem.directory_read_failed",
+            FileSystemError::FileReadFailed { .. } => "filesystem.file_read_failed",
+            FileSystemError::FileWriteFailed { .. } => "filesystem.file_write_failed",
+        }
+    }
+}
+
+impl YamlError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            YamlError::ParseError { .. } => "yaml.parse_error",
+            YamlError::SerializationError { .. } => "yaml.serialization_error",
+            YamlError::MergeError { .. } => "yaml.merge_error",
+            YamlError::InvalidComposeFormat { .. } => "yaml.invalid_compose_format",
+        }
+    }
+}
+
+impl InitError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            InitError::ProjectStructureCreationFailed { .. } => "init.project_structure_creation_failed",
+            InitError::ExampleFileCreationFailed { .. } => "init.example_file_creation_failed",
+            InitError::ComponentsDirectoryExists { .. } => "init.components_directory_exists",
+            InitError::UnknownTemplate { .. } => "init.unknown_template",
+            InitError::OverwriteNotConfirmed { .. } => "init.overwrite_not_confirmed",
+            InitError::ComponentTreeNotFound { .. } => "init.component_tree_not_found",
+            InitError::AmbiguousLayout { .. } => "init.ambiguous_layout",
+            InitError::ConfigToStdin => "init.config_to_stdin",
+        }
+    }
+}
+
+impl BackupError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            BackupError::BackupNotFound { .. } => "backup.not_found",
+            BackupError::InvalidBackup { .. } => "backup.invalid",
+            BackupError::PathNotInBackup { .. } => "backup.path_not_in_backup",
+            BackupError::RestoreConflict { .. } => "backup.restore_conflict",
+            BackupError::NoBackups { .. } => "backup.no_backups",
+            BackupError::UnsafePath { .. } => "backup.unsafe_path",
+        }
+    }
+}
+
 // Helper functions for creating common errors
 impl ConfigError {
     pub fn config_not_found(file: impl Into<String>) -> Self {
         Self::ConfigFileNotFound { file: file.into() }
     }
     
-    pub fn toml_parse_error(file: impl Into<String>, error: toml::de::Error) -> Self {
+    /// Parse error of `content`, located by the span toml reports
+    pub fn toml_parse_error(file: impl Into<String>, content: &str, error: toml::de::Error) -> Self {
         Self::InvalidTomlSyntax {
             file: file.into(),
-            details: error.to_string(),
+            details: error.message().trim_end().to_string(),
+            location: error.span().map(|span| SourceLocation::from_span(content, span)),
         }
     }
     
+    /// Parse error of a configuration read with `--format json`, located by the line and column serde_json reports
+    pub fn json_parse_error(file: impl Into<String>, content: &str, error: serde_json::Error) -> Self {
+        let details = error.to_string();
+        let details = details.strip_suffix(&format!(" at line {} column {}", error.line(), error.column()))
+            .unwrap_or(&details)
+            .to_string();
+        Self::InvalidJsonSyntax {
+            file: file.into(),
+            details,
+            location: (error.line() > 0).then(|| SourceLocation::from_line_column(content, error.line(), error.column())),
+        }
+    }
+
     pub fn toml_serialize_error(error: toml::ser::Error) -> Self {
         Self::TomlSerializationError {
             details: error.to_string(),
@@ -188,27 +815,29 @@ impl ConfigError {
 }
 
 impl ValidationError {
-    #[cfg(test)]
-    pub fn environment_not_found(name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+    pub fn environment_not_found(name: impl Into<String>, path: impl Into<PathBuf>, available: Vec<String>) -> Self {
         Self::EnvironmentNotFound {
             name: name.into(),
             path: path.into(),
+            available,
         }
     }
     
-    #[cfg(test)]
-    pub fn extension_not_found(name: impl Into<String>, available_dirs: Vec<String>) -> Self {
+    pub fn extension_not_found(name: impl Into<String>, available_dirs: Vec<String>, available: Vec<String>) -> Self {
         Self::ExtensionNotFound {
             name: name.into(),
             available_dirs,
+            available,
         }
     }
 }
 
 impl YamlError {
-    pub fn serde_error(file: impl Into<String>, error: serde_yaml_ng::Error) -> Self {
+    /// Parse error of `content`, located by the line and column serde_yaml reports
+    pub fn serde_error(file: impl Into<String>, content: &str, error: serde_yaml_ng::Error) -> Self {
         Self::ParseError {
             file: file.into(),
+            location: error.location().map(|location| SourceLocation::from_line_column(content, location.line(), location.column())),
             details: error.to_string(),
         }
     }
@@ -218,6 +847,7 @@ impl YamlError {
         Self::ParseError {
             file: file.into(),
             details: details.into(),
+            location: None,
         }
     }
 }
\ No newline at end of file
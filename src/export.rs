@@ -0,0 +1,262 @@
+use std::fs;
+use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+
+use crate::build::{combination_project_name, determine_build_combinations, BuildCombination};
+use crate::config::{self, Config};
+use crate::diagnostics;
+use crate::error::{Result, FileSystemError, YamlError};
+
+/// Name of an element and its Application, from the project name and the combination
+const APPLICATION_NAME_TEMPLATE: &str = "{project}-{env}-{combo}";
+
+/// Argo CD ApplicationSet with a list generator holding one element per build combination
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationSet {
+    pub api_version: &'static str,
+    pub kind: &'static str,
+    pub metadata: ObjectMeta,
+    pub spec: ApplicationSetSpec,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ObjectMeta {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApplicationSetSpec {
+    pub generators: Vec<Generator>,
+    pub template: ApplicationTemplate,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Generator {
+    pub list: ListGenerator,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListGenerator {
+    pub elements: Vec<ListElement>,
+}
+
+/// Values of one combination. The list generator only substitutes strings, so lists are comma-joined
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ListElement {
+    pub name: String,
+    /// Output directory below the build directory, `.` for the build root
+    pub output_dir: String,
+    pub path: String,
+    pub environment: String,
+    pub extensions: String,
+    pub combos: String,
+    /// The environment, or `default` for combinations without one
+    pub namespace: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApplicationTemplate {
+    pub metadata: ObjectMeta,
+    pub spec: ApplicationSpec,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApplicationSpec {
+    pub project: String,
+    pub source: ApplicationSource,
+    pub destination: ApplicationDestination,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationSource {
+    #[serde(rename = "repoURL")]
+    pub repo_url: String,
+    pub target_revision: String,
+    pub path: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ApplicationDestination {
+    pub server: String,
+    pub namespace: String,
+}
+
+/// Render `combinations` as an ApplicationSet whose Applications point at their output directories
+/// below `args.path_prefix` in `args.repo`
+pub fn application_set(config: &Config, combinations: &[BuildCombination], args: &ArgocdArgs) -> ApplicationSet {
+    let project = config.project.name.as_deref();
+    let prefix = args.path_prefix.trim_end_matches('/');
+    let elements = combinations.iter()
+        .map(|combo| {
+            let path = [prefix, combo.output_dir.as_str()].into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join("/");
+            let environment = combo.environment.clone().unwrap_or_default();
+            ListElement {
+                name: combination_project_name(APPLICATION_NAME_TEMPLATE, project, combo).unwrap_or_else(|| "stack".to_string()),
+                output_dir: crate::error::combination_label(&combo.output_dir).to_string(),
+                path: if path.is_empty() { ".".to_string() } else { path },
+                namespace: if environment.is_empty() { "default".to_string() } else { environment.clone() },
+                environment,
+                extensions: combo.extensions.join(","),
+                combos: combo.combo_names.join(","),
+            }
+        })
+        .collect();
+
+    ApplicationSet {
+        api_version: "argoproj.io/v1alpha1",
+        kind: "ApplicationSet",
+        metadata: ObjectMeta {
+            name: args.name.clone()
+                .or_else(|| project.map(|name| name.to_lowercase().replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "-")))
+                .unwrap_or_else(|| "stackbuilder".to_string()),
+        },
+        spec: ApplicationSetSpec {
+            generators: vec![Generator { list: ListGenerator { elements } }],
+            template: ApplicationTemplate {
+                metadata: ObjectMeta { name: "{{name}}".to_string() },
+                spec: ApplicationSpec {
+                    project: args.project.clone(),
+                    source: ApplicationSource {
+                        repo_url: args.repo.clone(),
+                        target_revision: args.revision.clone(),
+                        path: "{{path}}".to_string(),
+                    },
+                    destination: ApplicationDestination {
+                        server: args.server.clone(),
+                        namespace: "{{namespace}}".to_string(),
+                    },
+                },
+            },
+        },
+    }
+}
+
+/// Runs the export command logic
+pub fn run_export(args: &ExportArgs) -> Result<()> {
+    match &args.target {
+        ExportTarget::Argocd(args) => run_export_argocd(args),
+    }
+}
+
+fn run_export_argocd(args: &ArgocdArgs) -> Result<()> {
+    let mut config = config::load_config()?;
+    // The manifest is written to stdout, so progress and warnings are kept out of it
+    let combinations = {
+        let _quiet = diagnostics::quiet();
+        config::resolve_paths(&mut config)?;
+        config::validate_config(&config, config.build.validation_mode)?;
+        determine_build_combinations(&config)?
+    };
+
+    let yaml = serde_yaml_ng::to_string(&application_set(&config, &combinations, args))
+        .map_err(|e| YamlError::SerializationError {
+            details: format!("Failed to serialize ApplicationSet: {}", e),
+        })?;
+
+    match args.output {
+        Some(ref path) => {
+            fs::write(path, yaml)
+                .map_err(|e| FileSystemError::FileWriteFailed {
+                    path: path.clone(),
+                    source: e,
+                })?;
+            eprintln!("✓ Wrote ApplicationSet with {} combinations: {}", combinations.len(), path.display());
+        }
+        None => print!("{}", yaml),
+    }
+    Ok(())
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    #[command(subcommand)]
+    pub target: ExportTarget,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportTarget {
+    /// Argo CD ApplicationSet with a list generator element per build combination
+    Argocd(ArgocdArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct ArgocdArgs {
+    /// Git repository holding the build output
+    #[arg(long, value_name = "URL")]
+    pub repo: String,
+
+    /// Directory of the build output inside the repository
+    #[arg(long, value_name = "DIR", default_value = "build")]
+    pub path_prefix: String,
+
+    /// Revision the Applications track
+    #[arg(long, default_value = "HEAD")]
+    pub revision: String,
+
+    /// Name of the ApplicationSet (defaults to the lowercased project name)
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Argo CD project of the Applications
+    #[arg(long, default_value = "default")]
+    pub project: String,
+
+    /// Cluster the Applications deploy to
+    #[arg(long, default_value = "https://kubernetes.default.svc")]
+    pub server: String,
+
+    /// Write the manifest to this file instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args() -> ArgocdArgs {
+        ArgocdArgs::parse_from(["argocd", "--repo", "https://example.com/stacks.git", "--path-prefix", "build/"])
+    }
+
+    #[test]
+    fn test_application_set_lists_every_combination() {
+        let config: Config = toml::from_str("[project]\nname = \"Shop\"\n\n[build]\nenvironments = [\"dev\", \"prod\"]\nextensions = [\"monitoring\"]\n").unwrap();
+        let combinations = determine_build_combinations(&config).unwrap();
+
+        let set = application_set(&config, &combinations, &args());
+        let elements = &set.spec.generators[0].list.elements;
+        assert_eq!(elements.len(), combinations.len());
+        assert_eq!(elements[1], ListElement {
+            name: "shop-dev-monitoring".to_string(),
+            output_dir: "dev/monitoring".to_string(),
+            path: "build/dev/monitoring".to_string(),
+            environment: "dev".to_string(),
+            extensions: "monitoring".to_string(),
+            combos: String::new(),
+            namespace: "dev".to_string(),
+        });
+
+        let yaml = serde_yaml_ng::to_string(&set).unwrap();
+        assert!(yaml.starts_with("apiVersion: argoproj.io/v1alpha1\nkind: ApplicationSet\nmetadata:\n  name: shop\n"), "{}", yaml);
+        assert!(yaml.contains("repoURL: https://example.com/stacks.git"), "{}", yaml);
+        assert!(yaml.contains("path: '{{path}}'"), "{}", yaml);
+    }
+
+    #[test]
+    fn test_build_root_combination_uses_the_prefix() {
+        let config = Config::default();
+        let combinations = determine_build_combinations(&config).unwrap();
+
+        let set = application_set(&config, &combinations, &args());
+        let element = &set.spec.generators[0].list.elements[0];
+        assert_eq!((element.output_dir.as_str(), element.path.as_str(), element.namespace.as_str()), (".", "build", "default"));
+        assert_eq!(set.metadata.name, "stackbuilder");
+    }
+}
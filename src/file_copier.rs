@@ -1,10 +1,16 @@
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use anyhow::{Context, Result};
 use glob::Pattern;
+use sha2::{Digest, Sha256};
 
-use crate::config::Config;
+use crate::config::{self, Config, DedupCopies};
+use crate::output_writer::OutputWriter;
+use crate::paths::ResolvedPaths;
+
+/// Content-addressed store for hardlinked copies, relative to the project directory
+pub const OBJECT_STORE_DIR: &str = ".stackbuilder/objects";
 
 /// File copy priority - higher number = higher priority
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -25,85 +31,148 @@ pub struct FileInfo {
 /// Manages file copying operations with priority-based overriding
 pub struct FileCopier {
     config: Config,
+    paths: ResolvedPaths,
     exclude_patterns: Vec<Pattern>,
+    verbose: bool,
+    object_store: PathBuf,
 }
 
 impl FileCopier {
     /// Create a new FileCopier instance
-    pub fn new(config: Config) -> Result<Self> {
-        let exclude_patterns = config.build.exclude_patterns
-            .iter()
-            .map(|pattern| Pattern::new(pattern))
-            .collect::<Result<Vec<_>, _>>()
-            .context("Failed to compile exclude patterns")?;
+    pub fn new(config: Config, paths: ResolvedPaths) -> Result<Self> {
+        let mut exclude_patterns = config.build.exclude_patterns.clone();
+        // The env merger consumes the source file, like the default .env.example exclusion
+        if config.build.copy_env_example && !exclude_patterns.contains(&config.build.env_source_filename) {
+            exclude_patterns.push(config.build.env_source_filename.clone());
+        }
+        let exclude_patterns = compile_patterns(&exclude_patterns)?;
 
         Ok(FileCopier {
             config,
             exclude_patterns,
+            verbose: true,
+            object_store: paths.project_root.join(OBJECT_STORE_DIR),
+            paths,
         })
     }
 
-    /// Copy all additional files for the specified environment and extensions
+    /// Do not log discovered, excluded and overridden files
+    pub fn quiet(mut self) -> Self {
+        self.verbose = false;
+        self
+    }
+
+    /// Copy all additional files for the specified environment and extensions and return their destinations
     pub fn copy_additional_files(
         &self,
         environment: Option<&str>,
         extensions: &[String],
         output_dir: &Path,
-    ) -> Result<()> {
+        writer: &OutputWriter,
+    ) -> Result<Vec<PathBuf>> {
         if !self.config.build.copy_additional_files {
-            println!("Skipping additional file copying (disabled in config)");
-            return Ok(());
+            status!("Skipping additional file copying (disabled in config)");
+            return Ok(Vec::new());
+        }
+
+        status!("Copying additional files...");
+
+        let plan = self.plan_additional_files(environment, extensions)?;
+
+        // Create every destination directory once instead of per file
+        let dirs: BTreeSet<PathBuf> = plan.iter()
+            .filter_map(|(relative_path, _)| output_dir.join(relative_path).parent().map(Path::to_path_buf))
+            .collect();
+        for dir in &dirs {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+        }
+
+        // Copy files with priority resolution
+        let mut copied = Vec::with_capacity(plan.len());
+        for (relative_path, file_info) in plan {
+            self.copy_file_with_priority(&file_info, &relative_path, output_dir, writer)?;
+            copied.push(output_dir.join(relative_path));
+        }
+
+        status!("Additional file copying completed");
+        Ok(copied)
+    }
+
+    /// Resolve which component file ends up at each relative output path, without copying anything.
+    /// Returns an empty plan when additional file copying is disabled.
+    pub fn plan_additional_files(
+        &self,
+        environment: Option<&str>,
+        extensions: &[String],
+    ) -> Result<Vec<(PathBuf, FileInfo)>> {
+        if !self.config.build.copy_additional_files {
+            return Ok(Vec::new());
         }
 
-        println!("Copying additional files...");
+        // Environment-specific patterns extend the global ones for this call only
+        let exclude_patterns = self.exclude_patterns_for(environment)?;
 
         // Discover all files from components
         let mut file_map = HashMap::new();
         
         // 1. Discover base files (lowest priority)
         self.discover_files(
-            Path::new(&self.config.paths.base_dir),
+            &self.paths.base_dir,
             FilePriority::Base,
             "base",
             &mut file_map,
+            &exclude_patterns,
         )?;
 
         // 2. Discover environment files (medium priority)
         if let Some(env) = environment {
-            let env_path = Path::new(&self.config.paths.environments_dir).join(env);
+            let env_path = self.paths.environments_dir.join(env);
             if env_path.exists() {
                 self.discover_files(
                     &env_path,
                     FilePriority::Environment,
                     &format!("environment:{}", env),
                     &mut file_map,
+                    &exclude_patterns,
                 )?;
             }
         }
 
         // 3. Discover extension files (highest priority)
         for extension in extensions {
-            for ext_dir in &self.config.paths.extensions_dirs {
-                let ext_path = Path::new(ext_dir).join(extension);
+            for ext_dir in &self.paths.extensions_dirs {
+                let ext_path = ext_dir.join(extension);
                 if ext_path.exists() {
                     self.discover_files(
                         &ext_path,
                         FilePriority::Extension,
                         &format!("extension:{}", extension),
                         &mut file_map,
+                        &exclude_patterns,
                     )?;
                     break; // Use first found extension directory
                 }
             }
         }
 
-        // Copy files with priority resolution
-        for (relative_path, file_info) in file_map {
-            self.copy_file_with_priority(&file_info, &relative_path, output_dir)?;
+        let mut plan: Vec<(PathBuf, FileInfo)> = file_map.into_iter().collect();
+        plan.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(plan)
+    }
+
+    /// Global exclude patterns extended with the patterns configured for the environment
+    fn exclude_patterns_for(&self, environment: Option<&str>) -> Result<Vec<Pattern>> {
+        let mut patterns = self.exclude_patterns.clone();
+
+        if let Some(env_patterns) = environment
+            .and_then(|env| config::get_environment_config(&self.config, env))
+            .and_then(|env_config| env_config.exclude_patterns)
+        {
+            patterns.extend(compile_patterns(&env_patterns)?);
         }
 
-        println!("Additional file copying completed");
-        Ok(())
+        Ok(patterns)
     }
 
     /// Discover all files in a component directory
@@ -113,6 +182,7 @@ impl FileCopier {
         priority: FilePriority,
         component_name: &str,
         file_map: &mut HashMap<PathBuf, FileInfo>,
+        exclude_patterns: &[Pattern],
     ) -> Result<()> {
         if !component_dir.exists() {
             return Ok(());
@@ -124,6 +194,7 @@ impl FileCopier {
             priority,
             component_name,
             file_map,
+            exclude_patterns,
         )
     }
 
@@ -135,6 +206,7 @@ impl FileCopier {
         priority: FilePriority,
         component_name: &str,
         file_map: &mut HashMap<PathBuf, FileInfo>,
+        exclude_patterns: &[Pattern],
     ) -> Result<()> {
         for entry in fs::read_dir(current_dir)
             .with_context(|| format!("Failed to read directory: {}", current_dir.display()))?
@@ -150,6 +222,7 @@ impl FileCopier {
                     priority,
                     component_name,
                     file_map,
+                    exclude_patterns,
                 )?;
             } else if path.is_file() {
                 // Process file
@@ -157,9 +230,14 @@ impl FileCopier {
                     .with_context(|| format!("Failed to get relative path for: {}", path.display()))?
                     .to_path_buf();
 
+                // Extension metadata only steers the build
+                if relative_path == Path::new(config::EXTENSION_META_FILE) {
+                    continue;
+                }
+
                 // Check if file should be excluded
-                if self.should_exclude_file(&relative_path) {
-                    println!("  Excluding file: {} (matches exclude pattern)", relative_path.display());
+                if self.should_exclude_file(&relative_path, exclude_patterns) {
+                    self.log(format!("  Excluding file: {} (matches exclude pattern)", relative_path.display()));
                     continue;
                 }
 
@@ -178,10 +256,10 @@ impl FileCopier {
     }
 
     /// Determine if a file should be excluded based on patterns
-    fn should_exclude_file(&self, relative_path: &Path) -> bool {
+    fn should_exclude_file(&self, relative_path: &Path, exclude_patterns: &[Pattern]) -> bool {
         let path_str = relative_path.to_string_lossy();
         
-        for pattern in &self.exclude_patterns {
+        for pattern in exclude_patterns {
             if pattern.matches(&path_str) {
                 return true;
             }
@@ -208,80 +286,88 @@ impl FileCopier {
         match file_map.get(relative_path) {
             Some(existing_file) => {
                 if new_file.priority > existing_file.priority {
-                    println!(
+                    self.log(format!(
                         "  File {}: {} overrides {} (priority: {:?} > {:?})",
                         relative_path.display(),
                         new_file.source_component,
                         existing_file.source_component,
                         new_file.priority,
                         existing_file.priority
-                    );
+                    ));
                     file_map.insert(relative_path.clone(), new_file);
                 } else if new_file.priority == existing_file.priority {
                     // Same priority - last one wins (order matters)
-                    println!(
+                    self.log(format!(
                         "  File {}: {} replaces {} (same priority: {:?})",
                         relative_path.display(),
                         new_file.source_component,
                         existing_file.source_component,
                         new_file.priority
-                    );
+                    ));
                     file_map.insert(relative_path.clone(), new_file);
                 } else {
-                    println!(
+                    self.log(format!(
                         "  File {}: keeping {} (priority: {:?} > {:?})",
                         relative_path.display(),
                         existing_file.source_component,
                         existing_file.priority,
                         new_file.priority
-                    );
+                    ));
                 }
             }
             None => {
-                println!(
+                self.log(format!(
                     "  File {} found from {} (priority: {:?})",
                     relative_path.display(),
                     new_file.source_component,
                     new_file.priority
-                );
+                ));
                 file_map.insert(relative_path.clone(), new_file);
             }
         }
     }
 
-    /// Copy a file with priority information
+    fn log(&self, message: String) {
+        if self.verbose {
+            status!("{}", message);
+        }
+    }
+
+    /// Copy a file with priority information. The destination directory must already exist
     fn copy_file_with_priority(
         &self,
         file_info: &FileInfo,
         relative_path: &PathBuf,
         output_dir: &Path,
+        writer: &OutputWriter,
     ) -> Result<()> {
         let dest_path = output_dir.join(relative_path);
-        
-        // Create parent directories if they don't exist
-        if let Some(parent) = dest_path.parent() {
-            fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
-        }
 
-        // Copy the file
-        fs::copy(&file_info.source_path, &dest_path)
-            .with_context(|| format!(
-                "Failed to copy file from {} to {}",
-                file_info.source_path.display(),
-                dest_path.display()
-            ))?;
+        let linked = match self.config.build.dedup_copies {
+            DedupCopies::Hardlink => match link_from_object_store(&self.object_store, &file_info.source_path, &dest_path) {
+                Ok(()) => true,
+                Err(e) => {
+                    // e.g. the store is on another device - fall back to a normal copy
+                    status!("  Hardlinking {} failed, copying instead: {:#}", relative_path.display(), e);
+                    false
+                }
+            },
+            DedupCopies::Copy => false,
+        };
 
-        // Preserve permissions on Unix systems
-        #[cfg(unix)]
-        {
-            if let Ok(metadata) = fs::metadata(&file_info.source_path) {
-                let permissions = metadata.permissions();
-                let _ = fs::set_permissions(&dest_path, permissions);
-            }
+        if linked {
+            writer.written(&dest_path);
+        } else {
+            // Copy the file, keeping its permissions
+            writer.copy(&file_info.source_path, &dest_path)
+                .with_context(|| format!(
+                    "Failed to copy file from {} to {}",
+                    file_info.source_path.display(),
+                    dest_path.display()
+                ))?;
         }
 
-        println!(
+        status!(
             "  Copied: {} -> {} (from {})",
             relative_path.display(),
             dest_path.display(),
@@ -290,4 +376,102 @@ impl FileCopier {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Hardlink the destination to a content-addressed copy of the source in the object store.
+/// Objects are read-only, since every output linked to one shares its inode: editing a generated file in place
+/// would change the object and every other combination linking it. An object whose content or mode no longer
+/// matches its name is replaced before it is linked again.
+fn link_from_object_store(object_store: &Path, source_path: &Path, dest_path: &Path) -> Result<()> {
+    let content = fs::read(source_path)
+        .with_context(|| format!("Failed to read file: {}", source_path.display()))?;
+    let mut permissions = fs::metadata(source_path)
+        .with_context(|| format!("Failed to read metadata: {}", source_path.display()))?
+        .permissions();
+
+    // Hardlinks share permissions, so files differing only in mode get separate objects
+    let mut hasher = Sha256::new();
+    hasher.update(&content);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        hasher.update(permissions.mode().to_le_bytes());
+        permissions.set_mode(permissions.mode() & !0o222);
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(true);
+    let hash = format!("{:x}", hasher.finalize());
+
+    let object_path = object_store.join(&hash[..2]).join(&hash);
+    let intact = fs::metadata(&object_path).is_ok_and(|metadata| metadata.permissions() == permissions)
+        && fs::read(&object_path).is_ok_and(|stored| stored == content);
+    if !intact {
+        if object_path.exists() {
+            status!("  Replacing modified object {}", object_path.display());
+        }
+        let object_dir = object_path.parent().unwrap_or(object_store);
+        fs::create_dir_all(object_dir)
+            .with_context(|| format!("Failed to create directory: {}", object_dir.display()))?;
+
+        // Write under a temporary name so a partially written object is never linked
+        let temp_path = object_path.with_extension("tmp");
+        fs::write(&temp_path, &content)
+            .with_context(|| format!("Failed to write object: {}", temp_path.display()))?;
+        fs::set_permissions(&temp_path, permissions)
+            .with_context(|| format!("Failed to set permissions: {}", temp_path.display()))?;
+        fs::rename(&temp_path, &object_path)
+            .with_context(|| format!("Failed to store object: {}", object_path.display()))?;
+    }
+
+    if dest_path.exists() {
+        fs::remove_file(dest_path)
+            .with_context(|| format!("Failed to replace file: {}", dest_path.display()))?;
+    }
+
+    fs::hard_link(&object_path, dest_path)
+        .with_context(|| format!(
+            "Failed to hardlink {} to {}",
+            object_path.display(),
+            dest_path.display()
+        ))
+}
+
+/// Remove the objects of `object_store` that no build output links to any more, and leftover temporary files.
+/// Returns the number of files removed. Link counts are only available on Unix, elsewhere only temporary files go.
+pub fn prune_object_store(object_store: &Path) -> Result<usize> {
+    let mut removed = 0;
+    let Ok(prefixes) = fs::read_dir(object_store) else { return Ok(0) };
+    for prefix in prefixes {
+        let prefix = prefix.with_context(|| format!("Failed to read directory: {}", object_store.display()))?.path();
+        if !prefix.is_dir() {
+            continue;
+        }
+        for object in fs::read_dir(&prefix).with_context(|| format!("Failed to read directory: {}", prefix.display()))? {
+            let object = object.with_context(|| format!("Failed to read directory: {}", prefix.display()))?.path();
+            let metadata = fs::symlink_metadata(&object)
+                .with_context(|| format!("Failed to read metadata: {}", object.display()))?;
+            #[cfg(unix)]
+            let unreferenced = std::os::unix::fs::MetadataExt::nlink(&metadata) <= 1;
+            #[cfg(not(unix))]
+            let unreferenced = false;
+            let temporary = object.extension().is_some_and(|extension| extension == "tmp");
+            if metadata.is_file() && (unreferenced || temporary) {
+                fs::remove_file(&object)
+                    .with_context(|| format!("Failed to remove object: {}", object.display()))?;
+                removed += 1;
+            }
+        }
+        // Only succeeds once the prefix directory is empty
+        let _ = fs::remove_dir(&prefix);
+    }
+    Ok(removed)
+}
+
+/// Compile glob exclude patterns
+fn compile_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| Pattern::new(pattern))
+        .collect::<Result<Vec<_>, _>>()
+        .context("Failed to compile exclude patterns")
+}
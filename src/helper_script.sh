@@ -0,0 +1,23 @@
+#!/bin/sh
+# Runs docker compose {{command}} for the {{combination}} combination
+cd "$(dirname "$0")" || exit 1
+
+# Variables the compose file requires with ${VAR:?} or ${VAR?} must be set here or in .env
+missing=""
+for var in {{required_variables}}; do
+    if ! printenv "$var" >/dev/null && ! grep -Eq "^(export +)?$var=" .env 2>/dev/null; then
+        missing="$missing $var"
+    fi
+done
+if [ -n "$missing" ]; then
+    echo "Refusing to run docker compose {{command}}, required variables are not set:$missing" >&2
+    echo "Set them in the environment or in $(pwd)/.env" >&2
+    exit 1
+fi
+
+set -- {{command}} "$@"
+if [ -f .env ]; then
+    set -- --env-file .env "$@"
+fi
+set -- {{compose_files}} --project-name "{{project_name}}" "$@"
+{{exec_compose}}
@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use regex::Regex;
+use serde_yaml_ng::Value;
+
+use crate::error::{Result, FileSystemError};
+
+/// Template the helper scripts are rendered from unless `helper_script_template` names another one
+pub const DEFAULT_HELPER_SCRIPT_TEMPLATE: &str = include_str!("helper_script.sh");
+
+/// Scripts written with `generate_helper_scripts` and the docker compose command each one runs
+pub const HELPER_SCRIPTS: &[(&str, &str)] = &[("up.sh", "up -d"), ("down.sh", "down")];
+
+/// Shell lines that run compose with the script's arguments: the compose plugin of docker when it is installed,
+/// the standalone `docker-compose` otherwise
+pub const EXEC_COMPOSE: &str = "\
+if docker compose version >/dev/null 2>&1; then
+    exec docker compose \"$@\"
+fi
+exec docker-compose \"$@\"";
+
+/// The compose command as program and leading arguments, detected like `EXEC_COMPOSE` does: the compose plugin
+/// of docker when `docker compose version` succeeds, the standalone `docker-compose` otherwise, `None` without either
+pub fn compose_command() -> Option<Vec<&'static str>> {
+    let works = |command: &[&str]| Command::new(command[0])
+        .args(&command[1..])
+        .arg("version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+    [&["docker", "compose"][..], &["docker-compose"][..]].into_iter()
+        .find(|command| works(command))
+        .map(<[&str]>::to_vec)
+}
+
+/// The configured template read relative to `project_root`, or the built-in one
+pub fn load_template(template: Option<&str>, project_root: &Path) -> Result<String> {
+    let Some(template) = template else {
+        return Ok(DEFAULT_HELPER_SCRIPT_TEMPLATE.to_string());
+    };
+    let path = project_root.join(template);
+    fs::read_to_string(&path)
+        .map_err(|e| FileSystemError::FileReadFailed {
+            path,
+            source: e,
+        }.into())
+}
+
+/// Variables compose refuses to run without: those interpolated as `${VAR:?message}` or `${VAR?message}`
+/// anywhere in `documents`, sorted. `$${VAR:?}` is an escaped literal and does not count.
+pub fn required_variables<'a>(documents: impl IntoIterator<Item = &'a Value>) -> Vec<String> {
+    let re = Regex::new(r"(\$+)\{([A-Za-z_][A-Za-z0-9_]*):?\?").unwrap();
+    let mut variables = BTreeSet::new();
+    let mut pending: Vec<&Value> = documents.into_iter().collect();
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::String(text) => {
+                for captures in re.captures_iter(text) {
+                    if captures[1].len() % 2 == 1 {
+                        variables.insert(captures[2].to_string());
+                    }
+                }
+            }
+            Value::Sequence(items) => pending.extend(items),
+            Value::Mapping(map) => pending.extend(map.values()),
+            Value::Tagged(tagged) => pending.push(&tagged.value),
+            _ => {}
+        }
+    }
+    variables.into_iter().collect()
+}
+
+/// Fill in the placeholders of a helper script template: `{{combination}}`, `{{command}}`,
+/// `{{compose_files}}` (`-f` per compose file), `{{project_name}}`, `{{required_variables}}` (space-separated)
+/// and `{{exec_compose}}` ([`EXEC_COMPOSE`])
+pub fn render_helper_script(
+    template: &str,
+    combination: &str,
+    command: &str,
+    compose_files: &[String],
+    project_name: &str,
+    required_variables: &[String],
+) -> String {
+    let compose_files: Vec<String> = compose_files.iter().map(|file| format!("-f {}", file)).collect();
+    template
+        .replace("{{combination}}", combination)
+        .replace("{{command}}", command)
+        .replace("{{compose_files}}", &compose_files.join(" "))
+        .replace("{{project_name}}", project_name)
+        .replace("{{required_variables}}", &required_variables.join(" "))
+        .replace("{{exec_compose}}", EXEC_COMPOSE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_variables() {
+        let document: Value = serde_yaml_ng::from_str(r#"
+services:
+  app:
+    image: "app:${TAG:?set TAG}"
+    environment:
+      - DB_PASSWORD=${DB_PASSWORD?}
+      - OPTIONAL=${OPTIONAL:-x}
+      - LITERAL=$${ESCAPED:?}
+    command: ["run", "${TAG:?}"]
+"#).unwrap();
+
+        assert_eq!(required_variables([&document]), ["DB_PASSWORD", "TAG"]);
+    }
+
+    #[test]
+    fn test_render_default_template() {
+        let script = render_helper_script(
+            DEFAULT_HELPER_SCRIPT_TEMPLATE,
+            "dev/monitoring",
+            "up -d",
+            &["docker-compose.yml".to_string()],
+            "shop-dev-monitoring",
+            &["DB_PASSWORD".to_string(), "TAG".to_string()],
+        );
+
+        assert!(script.starts_with("#!/bin/sh\n"), "{}", script);
+        assert!(script.contains("for var in DB_PASSWORD TAG; do"), "{}", script);
+        assert!(script.contains("set -- up -d \"$@\""), "{}", script);
+        assert!(script.contains("set -- -f docker-compose.yml --project-name \"shop-dev-monitoring\" \"$@\""), "{}", script);
+        assert!(script.contains("exec docker compose \"$@\""), "{}", script);
+        assert!(script.contains("exec docker-compose \"$@\""), "{}", script);
+        assert!(!script.contains("{{"), "{}", script);
+    }
+}
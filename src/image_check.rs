@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+use serde::Deserialize;
+use serde_yaml_ng::Value;
+
+/// Command `--check-images` runs per image unless `image_check_command` names another one
+pub const DEFAULT_IMAGE_CHECK_COMMAND: &str = "docker manifest inspect {image}";
+
+/// Exit codes of `sh` and `cmd` for a command that does not exist
+const COMMAND_NOT_FOUND_CODES: &[i32] = &[127, 9009];
+
+/// Every document of a compose file, skipping the ones that do not parse
+pub fn parse_documents(content: &str) -> Vec<Value> {
+    serde_yaml_ng::Deserializer::from_str(content)
+        .filter_map(|document| Value::deserialize(document).ok())
+        .collect()
+}
+
+/// Unique `services.*.image` references of `documents`, sorted
+pub fn compose_images<'a>(documents: impl IntoIterator<Item = &'a Value>) -> BTreeSet<String> {
+    documents.into_iter()
+        .filter_map(|document| document.get("services").and_then(Value::as_mapping))
+        .flat_map(|services| services.values())
+        .filter_map(|service| service.get("image").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether `image` can be passed to a shell command as it is: a plain reference without interpolation
+/// such as `${TAG}` left for docker compose, nor characters the shell would interpret
+pub fn is_checkable_image(image: &str) -> bool {
+    !image.is_empty() && image.chars().all(|c| c.is_ascii_alphanumeric() || "._-/:@".contains(c))
+}
+
+/// `template` with `{image}` replaced by `image`
+pub fn render_command(template: &str, image: &str) -> String {
+    template.replace("{image}", image)
+}
+
+/// Whether a check command exited because the shell could not find it
+pub fn is_command_not_found(code: Option<i32>) -> bool {
+    code.is_some_and(|code| COMMAND_NOT_FOUND_CODES.contains(&code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_images() {
+        let documents = parse_documents("services:\n  web:\n    image: nginx:1.27\n  app:\n    build: .\n  db:\n    image: postgres:16\n---\nservices:\n  proxy:\n    image: nginx:1.27\n");
+
+        assert_eq!(compose_images(&documents).into_iter().collect::<Vec<_>>(), ["nginx:1.27", "postgres:16"]);
+    }
+
+    #[test]
+    fn test_checkable_images() {
+        assert!(is_checkable_image("ghcr.io/org/app@sha256:0123abcd"));
+        assert!(is_checkable_image("registry:5000/app:1.2-rc.1"));
+        assert!(!is_checkable_image("app:${TAG:-latest}"));
+        assert!(!is_checkable_image("app; rm -rf /"));
+        assert_eq!(render_command(DEFAULT_IMAGE_CHECK_COMMAND, "nginx"), "docker manifest inspect nginx");
+    }
+}
@@ -1,116 +1,773 @@
 use std::fs;
-use std::path::Path;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use clap::Parser;
-use crate::config;
+use crate::config::{self, CONFIG_FILE, STDIN_CONFIG};
+use crate::diagnostics::{self, WarningCategory};
 use crate::error::{Result, InitError, ConfigError, FileSystemError};
+use crate::templates::{ProjectTemplate, TEMPLATES};
+
+/// Directory holding the build lock, object store and default backups
+const STATE_DIR: &str = ".stackbuilder";
+/// Environment that receives an adopted docker-compose.override.yml
+const ADOPTED_OVERRIDE_ENV: &str = "dev";
+/// Environment and extension added to the configuration by --with-examples
+const EXAMPLE_ENVIRONMENT: &str = "dev";
+const EXAMPLE_EXTENSION: &str = "monitoring";
+/// Directory names recognized as the environments directory by --adopt
+const ENVIRONMENT_DIR_NAMES: &[&str] = &["environments", "envs", "env"];
 
 /// Runs the init command logic
 pub fn run_init(args: &InitArgs) -> Result<()> {
-    const CONFIG_FILE: &str = "stackbuilder.toml";
+    init_project(args, Path::new(""))
+}
+
+/// Initialize a project in the given directory (empty path for the current directory).
+/// The changes are planned first; with `dry_run` the plan is only reported.
+pub fn init_project(args: &InitArgs, working_dir: &Path) -> Result<()> {
+    if args.config.as_deref() == Some(Path::new(STDIN_CONFIG)) {
+        return Err(InitError::ConfigToStdin.into());
+    }
+    if args.list_templates {
+        list_templates();
+        return Ok(());
+    }
+
+    let plan = match args.template {
+        Some(ref template) => plan_template(args, template, working_dir)?,
+        None if args.adopt => plan_adopted(args, working_dir)?,
+        None => plan_project(args, working_dir)?,
+    };
+
+    // Show what --force would lose before anything is written
+    for action in &plan.actions {
+        if let InitAction::WriteConfig { path, content, previous: Some(previous) } = action {
+            println!("Changes to {}:", path.display());
+            for line in line_diff(previous, content) {
+                println!("  {}", line);
+            }
+            if !args.dry_run && !args.yes {
+                confirm_overwrite(path)?;
+            }
+        }
+    }
+
+    if args.dry_run {
+        plan.print();
+        println!("{}", plan.summary(true));
+        println!("Dry run completed, nothing was written");
+    } else {
+        let summary = plan.summary(false);
+        plan.execute()?;
+        println!("{}", summary);
+    }
+
+    Ok(())
+}
+
+/// A single filesystem change made by init
+#[derive(Debug)]
+enum InitAction {
+    /// Write the configuration file, replacing `previous` content if any
+    WriteConfig { path: PathBuf, content: String, previous: Option<String> },
+    CreateFolder(PathBuf),
+    /// Copy or move an existing compose file into a component
+    AdoptFile { source: PathBuf, target: PathBuf, move_file: bool },
+    CreateFile { path: PathBuf, content: String },
+    /// Append lines to an existing file
+    AppendLines { path: PathBuf, lines: Vec<String> },
+    /// Existing file or folder that is left untouched
+    Keep { path: PathBuf, kind: &'static str },
+}
+
+/// Ordered changes init makes, computed before anything is written
+#[derive(Debug, Default)]
+struct InitPlan {
+    actions: Vec<InitAction>,
+}
+
+impl InitPlan {
+    fn push(&mut self, action: InitAction) {
+        self.actions.push(action);
+    }
+
+    fn print(&self) {
+        for action in &self.actions {
+            match action {
+                InitAction::WriteConfig { path, previous: None, .. } => {
+                    println!("Would create configuration file: {}", path.display());
+                }
+                InitAction::WriteConfig { path, previous: Some(_), .. } => {
+                    println!("Would overwrite existing configuration file: {}", path.display());
+                }
+                InitAction::CreateFolder(path) => println!("Would create folder: {}", path.display()),
+                InitAction::AdoptFile { source, target, move_file } => {
+                    let verb = if *move_file { "move" } else { "copy" };
+                    println!("Would {} {} to {}", verb, source.display(), target.display());
+                }
+                InitAction::CreateFile { path, .. } => println!("Would create example file: {}", path.display()),
+                InitAction::AppendLines { path, lines } => {
+                    println!("Would append to {}: {}", path.display(), lines.join(", "));
+                }
+                InitAction::Keep { path, kind } => println!("{} already exists: {}", kind, path.display()),
+            }
+        }
+    }
+
+    /// One-line count of created, overwritten and kept paths
+    fn summary(&self, dry_run: bool) -> String {
+        let (mut create, mut overwrite, mut keep) = (0, 0, 0);
+        for action in &self.actions {
+            match action {
+                InitAction::WriteConfig { previous: Some(_), .. } | InitAction::AppendLines { .. } => overwrite += 1,
+                InitAction::Keep { .. } => keep += 1,
+                _ => create += 1,
+            }
+        }
+        if dry_run {
+            format!("Summary: {} to create, {} to overwrite, {} already exist", create, overwrite, keep)
+        } else {
+            format!("Summary: {} created, {} overwritten, {} kept", create, overwrite, keep)
+        }
+    }
+
+    fn execute(self) -> Result<()> {
+        for action in self.actions {
+            match action {
+                InitAction::WriteConfig { path, content, previous } => {
+                    write_file(&path, &content)?;
+                    match previous {
+                        Some(_) => println!("Overwrote configuration file: {}", path.display()),
+                        None => println!("Created configuration file: {}", path.display()),
+                    }
+                }
+                InitAction::CreateFolder(path) => {
+                    fs::create_dir_all(&path)
+                        .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
+                    println!("Created folder: {}", path.display());
+                }
+                InitAction::AdoptFile { source, target, move_file } => adopt_file(&source, &target, move_file)?,
+                InitAction::CreateFile { path, content } => {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)
+                            .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
+                    }
+                    fs::write(&path, content)
+                        .map_err(|e| InitError::ExampleFileCreationFailed {
+                            details: format!("Failed to write {}: {}", path.display(), e),
+                        })?;
+                    println!("Created example file: {}", path.display());
+                }
+                InitAction::AppendLines { path, lines } => {
+                    let mut content = fs::read_to_string(&path)
+                        .map_err(|e| FileSystemError::FileReadFailed {
+                            path: path.clone(),
+                            source: e,
+                        })?;
+                    if !content.is_empty() && !content.ends_with('\n') {
+                        content.push('\n');
+                    }
+                    content.push_str(&(lines.join("\n") + "\n"));
+                    write_file(&path, &content)?;
+                    println!("Appended to {}: {}", path.display(), lines.join(", "));
+                }
+                InitAction::Keep { path, kind } => println!("{} already exists: {}", kind, path.display()),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path of the configuration init writes, relative to the project directory
+fn config_file(args: &InitArgs) -> &Path {
+    args.config.as_deref().unwrap_or(Path::new(CONFIG_FILE))
+}
+
+/// Plan the configuration, folders, adopted compose files and example components
+fn plan_project(args: &InitArgs, working_dir: &Path) -> Result<InitPlan> {
+    let mut plan = InitPlan::default();
 
-    // Step 1: Check if config exists
-    let config_path = Path::new(CONFIG_FILE);
+    let config_path = working_dir.join(config_file(args));
     let config_exists = config_path.exists();
 
-    if !config_exists {
-        // Create default config
-        let default_config = config::Config::default();
-        let toml_content = toml::to_string(&default_config)
-            .map_err(ConfigError::toml_serialize_error)?;
-        fs::write(CONFIG_FILE, toml_content)
-            .map_err(|e| FileSystemError::FileWriteFailed {
-                path: config_path.to_path_buf(),
-                source: e,
-            })?;
-        println!("Created default configuration file: {}", CONFIG_FILE);
-    } else if !args.force {
-        println!("Configuration file already exists: {}", CONFIG_FILE);
+    let config = if config_exists && !args.force {
+        if args.with_examples {
+            diagnostics::warn(WarningCategory::Config, format!(
+                "--with-examples only adds the '{}' environment and '{}' extension to a new {}; add them to [build] or pass --force",
+                EXAMPLE_ENVIRONMENT, EXAMPLE_EXTENSION, config_path.display()
+            ));
+        }
+        config::load_config_from(&config_path)?
     } else {
-        println!("Overwriting existing configuration file: {}", CONFIG_FILE);
-        let default_config = config::Config::default();
-        let toml_content = toml::to_string(&default_config)
-            .map_err(ConfigError::toml_serialize_error)?;
-        fs::write(CONFIG_FILE, toml_content)
-            .map_err(|e| FileSystemError::FileWriteFailed {
-                path: config_path.to_path_buf(),
-                source: e,
-            })?;
-        println!("Overwrote configuration file: {}", CONFIG_FILE);
+        default_config(args, working_dir)?
+    };
+
+    // Adopting compose files must not mix them into an existing component tree
+    let components_dir_path = working_dir.join(&config.paths.components_dir);
+    if args.from_compose.is_some() && components_dir_path.exists() && !args.force {
+        return Err(InitError::ComponentsDirectoryExists { path: components_dir_path }.into());
+    }
+
+    let content = toml::to_string(&config)
+        .map_err(ConfigError::toml_serialize_error)?;
+    plan_config(&mut plan, config_path, content, args.force)?;
+    if !args.no_gitignore {
+        plan_gitignore(&mut plan, &config, working_dir)?;
+    }
+
+    if args.skip_folders {
+        println!("Skipping folder creation due to --skip-folders flag");
+        // Adopted compose files are not scaffolding, so they still take their place in the components tree
+        plan.actions.extend(plan_adoption(args, &config, working_dir)?);
+        return Ok(plan);
+    }
+
+    for folder in component_folders(&config, working_dir) {
+        if folder.exists() {
+            plan.push(InitAction::Keep { path: folder, kind: "Folder" });
+        } else {
+            plan.push(InitAction::CreateFolder(folder));
+        }
+    }
+
+    // Adopted compose files take the place of the example compose files
+    let adopted = plan_adoption(args, &config, working_dir)?;
+    let adopted_targets: Vec<PathBuf> = adopted.iter()
+        .filter_map(|action| match action {
+            InitAction::AdoptFile { target, .. } => Some(target.clone()),
+            _ => None,
+        })
+        .collect();
+    plan.actions.extend(adopted);
+
+    for scaffold in component_scaffolds(&config, working_dir, args.with_examples) {
+        let compose_path = scaffold.dir.join("docker-compose.yml");
+        if !adopted_targets.contains(&compose_path) {
+            plan_example_file(&mut plan, compose_path, scaffold.compose);
+        }
+        if let Some(env_example) = scaffold.env_example {
+            plan_example_file(&mut plan, scaffold.dir.join(".env.example"), env_example);
+        }
+    }
+
+    if !args.no_readme {
+        plan_example_file(&mut plan, components_dir_path.join("README.md"), components_readme(&config));
+    }
+
+    Ok(plan)
+}
+
+/// Plan an embedded template. Existing files are kept; the configuration is only replaced with --force.
+fn plan_template(args: &InitArgs, name: &str, working_dir: &Path) -> Result<InitPlan> {
+    let template = ProjectTemplate::find(name).ok_or_else(|| InitError::UnknownTemplate {
+        name: name.to_string(),
+        available: ProjectTemplate::names(),
+    })?;
+
+    let project_name = match args.name {
+        Some(ref name) => name.clone(),
+        None => default_project_name(working_dir)?,
+    };
+    println!("Initializing project '{}' from template '{}'", project_name, template.name);
+
+    let mut plan = InitPlan::default();
+    let files = template.files(&project_name);
+
+    let mut folders: Vec<PathBuf> = files.iter()
+        .flat_map(|(path, _)| path.ancestors().skip(1).map(|dir| working_dir.join(dir)).collect::<Vec<_>>())
+        .filter(|dir| dir != working_dir && !dir.exists())
+        .collect();
+    folders.sort();
+    folders.dedup();
+    plan.actions.extend(folders.into_iter().map(InitAction::CreateFolder));
+
+    let mut config = config::Config::default();
+    for (path, content) in files {
+        if path == Path::new(CONFIG_FILE) {
+            config = toml::from_str(&content)
+                .map_err(|e| ConfigError::toml_parse_error(CONFIG_FILE, &content, e))?;
+            plan_config(&mut plan, working_dir.join(config_file(args)), content, args.force)?;
+        } else {
+            plan_example_file(&mut plan, working_dir.join(&path), content);
+        }
+    }
+
+    if !args.no_gitignore {
+        plan_gitignore(&mut plan, &config, working_dir)?;
+    }
+    if !args.no_readme {
+        let readme_path = working_dir.join(&config.paths.components_dir).join("README.md");
+        plan_example_file(&mut plan, readme_path, components_readme(&config));
+    }
+
+    Ok(plan)
+}
+
+/// Components, environments and extensions found in an existing tree by --adopt
+#[derive(Debug, PartialEq)]
+struct AdoptedLayout {
+    components_dir: String,
+    base_dir: Option<String>,
+    environments_dir: Option<String>,
+    environments: Vec<String>,
+    extensions_dirs: Vec<String>,
+    extensions: Vec<String>,
+}
+
+impl AdoptedLayout {
+    fn print(&self) {
+        println!("Inferred from the existing component tree:");
+        println!("  components_dir: {}", self.components_dir);
+        match self.base_dir {
+            Some(ref base_dir) => println!("  base_dir: {}", base_dir),
+            None => println!("  base_dir: none found, require_base = false"),
+        }
+        if let Some(ref environments_dir) = self.environments_dir {
+            println!("  environments_dir: {}", environments_dir);
+            println!("  environments: {}", self.environments.join(", "));
+        }
+        println!("  extensions_dirs: {}", self.extensions_dirs.join(", "));
+        println!("  extensions: {}", self.extensions.join(", "));
+    }
+
+    fn config(&self, name: String) -> config::Config {
+        let mut config = config::Config::default();
+        config.project.name = Some(name);
+        config.paths.components_dir = self.components_dir.clone();
+        match self.base_dir {
+            Some(ref base_dir) => config.paths.base_dir = base_dir.clone(),
+            None => config.build.require_base = false,
+        }
+        if let Some(ref environments_dir) = self.environments_dir {
+            config.paths.environments_dir = environments_dir.clone();
+        }
+        config.paths.extensions_dirs = self.extensions_dirs.clone();
+        if !self.environments.is_empty() {
+            config.build.environments = Some(self.environments.clone());
+        }
+        if !self.extensions.is_empty() {
+            config.build.extensions = Some(self.extensions.clone());
+        }
+        config
+    }
+}
+
+/// Plan a configuration describing an existing component tree; nothing inside the tree is created
+fn plan_adopted(args: &InitArgs, working_dir: &Path) -> Result<InitPlan> {
+    let layout = detect_layout(args, working_dir)?;
+    layout.print();
+
+    let project_name = match args.name {
+        Some(ref name) => name.clone(),
+        None => default_project_name(working_dir)?,
+    };
+    let config = layout.config(project_name);
+
+    let mut plan = InitPlan::default();
+    let content = toml::to_string(&config)
+        .map_err(ConfigError::toml_serialize_error)?;
+    plan_config(&mut plan, working_dir.join(config_file(args)), content, args.force)?;
+    if !args.no_gitignore {
+        plan_gitignore(&mut plan, &config, working_dir)?;
+    }
+    if !args.no_readme {
+        let readme_path = working_dir.join(&config.paths.components_dir).join("README.md");
+        plan_example_file(&mut plan, readme_path, components_readme(&config));
+    }
+    Ok(plan)
+}
+
+/// Infer the configuration paths and component lists from the directories under the components directory.
+/// Folders holding a docker-compose.yml are base candidates, folders of such folders are environments or extensions directories.
+fn detect_layout(args: &InitArgs, working_dir: &Path) -> Result<AdoptedLayout> {
+    let components_name = detect_components_dir(args, working_dir)?;
+    let components_path = working_dir.join(&components_name);
+
+    let mut base_candidates = Vec::new();
+    let mut environment_dirs = Vec::new();
+    let mut extensions_dirs = Vec::new();
+    for name in sub_dirs(&components_path)? {
+        let dir = components_path.join(&name);
+        if is_component(&dir) {
+            base_candidates.push(name);
+        } else if ENVIRONMENT_DIR_NAMES.contains(&name.as_str()) {
+            environment_dirs.push(name);
+        } else if holds_components(&dir)? {
+            extensions_dirs.push(name);
+        }
+    }
+
+    let base_dir = match args.base_dir {
+        Some(ref base_dir) => Some(base_dir.clone()),
+        None => choose_candidate(base_candidates.clone(), "base", "base", "base-dir")?,
+    };
+    for ignored in base_candidates.iter().filter(|name| Some(*name) != base_dir.as_ref()) {
+        diagnostics::warn(WarningCategory::Config, format!("Ignoring component '{}' outside the environments and extensions directories", ignored));
+    }
+
+    let environments_dir = match args.environments_dir {
+        Some(ref environments_dir) => Some(environments_dir.clone()),
+        None => choose_candidate(environment_dirs, "environments", "environments", "environments-dir")?,
+    };
+    extensions_dirs.retain(|name| Some(name) != environments_dir.as_ref());
+
+    let environments = match environments_dir {
+        Some(ref dir) => sub_dirs(&components_path.join(dir))?,
+        None => Vec::new(),
+    };
+
+    let mut extensions = Vec::new();
+    for ext_dir in &extensions_dirs {
+        for name in sub_dirs(&components_path.join(ext_dir))? {
+            if is_component(&components_path.join(ext_dir).join(&name)) && !extensions.contains(&name) {
+                extensions.push(name);
+            }
+        }
+    }
+
+    Ok(AdoptedLayout {
+        components_dir: format!("./{}", components_name),
+        base_dir,
+        environments_dir,
+        environments,
+        extensions_dirs,
+        extensions,
+    })
+}
+
+/// The components directory: --components-dir, `components`, or the only top-level folder holding components
+fn detect_components_dir(args: &InitArgs, working_dir: &Path) -> Result<String> {
+    if let Some(ref components_dir) = args.components_dir {
+        let path = working_dir.join(components_dir);
+        if !path.is_dir() {
+            return Err(InitError::ComponentTreeNotFound { path }.into());
+        }
+        return Ok(components_dir.trim_start_matches("./").trim_end_matches('/').to_string());
     }
 
-    // Step 2: Read the config
-    let config_content = fs::read_to_string(CONFIG_FILE)
+    let build_dir = Path::new(&config::Paths::default().build_dir).components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .next_back();
+    let mut candidates = Vec::new();
+    for name in sub_dirs(working_dir)? {
+        if Some(&name) == build_dir.as_ref() {
+            continue;
+        }
+        let dir = working_dir.join(&name);
+        let mut holds_tree = false;
+        for child in sub_dirs(&dir)? {
+            if is_component(&dir.join(&child)) || holds_components(&dir.join(&child))? {
+                holds_tree = true;
+                break;
+            }
+        }
+        if holds_tree {
+            candidates.push(name);
+        }
+    }
+
+    choose_candidate(candidates, "components", "components", "components-dir")?
+        .ok_or_else(|| InitError::ComponentTreeNotFound { path: working_dir.to_path_buf() }.into())
+}
+
+/// Pick the single candidate or the conventional name; several candidates are ambiguous
+fn choose_candidate(candidates: Vec<String>, preferred: &str, kind: &'static str, flag: &'static str) -> Result<Option<String>> {
+    if candidates.iter().any(|name| name == preferred) {
+        return Ok(Some(preferred.to_string()));
+    }
+    match candidates.len() {
+        0 => Ok(None),
+        1 => Ok(candidates.into_iter().next()),
+        _ => Err(InitError::AmbiguousLayout { kind, candidates, flag }.into()),
+    }
+}
+
+/// Names of the non-hidden folders in `dir`, sorted
+fn sub_dirs(dir: &Path) -> Result<Vec<String>> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: dir.to_path_buf(),
+            source: e,
+        })?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+fn is_component(dir: &Path) -> bool {
+    dir.join("docker-compose.yml").is_file()
+}
+
+/// Whether any folder directly inside `dir` is a component
+fn holds_components(dir: &Path) -> Result<bool> {
+    Ok(sub_dirs(dir)?.iter().any(|name| is_component(&dir.join(name))))
+}
+
+/// Create the configuration file, replace it with --force, or keep it
+fn plan_config(plan: &mut InitPlan, path: PathBuf, content: String, force: bool) -> Result<()> {
+    if !path.exists() {
+        plan.push(InitAction::WriteConfig { path, content, previous: None });
+        return Ok(());
+    }
+
+    let previous = fs::read_to_string(&path)
         .map_err(|e| FileSystemError::FileReadFailed {
-            path: config_path.to_path_buf(),
+            path: path.clone(),
             source: e,
         })?;
-    let config: config::Config = toml::from_str(&config_content)
-        .map_err(|e| ConfigError::toml_parse_error(CONFIG_FILE, e))?;
-    println!("Loaded configuration from: {}", CONFIG_FILE);
-
-    // Step 3: Create folders if not skipping
-    if !args.skip_folders {
-        create_folders(&config)?;
-        // Step 4: Create example docker-compose.yml in base/
-        create_example_compose(&config)?;
+    if force && previous != content {
+        plan.push(InitAction::WriteConfig { path, content, previous: Some(previous) });
     } else {
-        println!("Skipping folder creation due to --skip-folders flag");
+        plan.push(InitAction::Keep { path, kind: "Configuration file" });
     }
+    Ok(())
+}
 
+/// Project .gitignore entries for generated output and stackbuilder state
+fn gitignore_entries(config: &config::Config) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut add = |path: &str| {
+        let path = Path::new(path);
+        // Only directories inside the project can be ignored
+        if path.is_absolute() || path.components().any(|c| matches!(c, std::path::Component::ParentDir)) {
+            return;
+        }
+        let relative: Vec<String> = path.components()
+            .filter_map(|c| match c {
+                std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        if relative.is_empty() {
+            return;
+        }
+        let entry = format!("/{}/", relative.join("/"));
+        // A directory that is already ignored covers everything below it
+        if !entries.iter().any(|existing: &String| entry.starts_with(existing.as_str())) {
+            entries.push(entry);
+        }
+    };
+
+    add(&config.paths.build_dir);
+    // Lock file, object store and default backups
+    add(STATE_DIR);
+    add(&config.build.backup_dir);
+    entries
+}
+
+/// Create the project .gitignore, or append the entries it does not list yet
+fn plan_gitignore(plan: &mut InitPlan, config: &config::Config, working_dir: &Path) -> Result<()> {
+    let path = working_dir.join(".gitignore");
+    let entries = gitignore_entries(config);
+
+    if !path.exists() {
+        let content = format!("# Generated by stackbuilder\n{}\n", entries.join("\n"));
+        plan.push(InitAction::CreateFile { path, content });
+        return Ok(());
+    }
+
+    let existing = fs::read_to_string(&path)
+        .map_err(|e| FileSystemError::FileReadFailed {
+            path: path.clone(),
+            source: e,
+        })?;
+    let normalize = |line: &str| line.trim().trim_matches('/').to_string();
+    let present: Vec<String> = existing.lines().map(normalize).collect();
+    let missing: Vec<String> = entries.into_iter()
+        .filter(|entry| !present.contains(&normalize(entry)))
+        .collect();
+
+    if missing.is_empty() {
+        plan.push(InitAction::Keep { path, kind: "Ignore file" });
+    } else {
+        plan.push(InitAction::AppendLines { path, lines: missing });
+    }
     Ok(())
 }
 
-fn create_folders(config: &config::Config) -> Result<()> {
-    // Always create components_dir + base_dir
-    let components_dir_path = Path::new(&config.paths.components_dir);
-    let base_dir_path = components_dir_path.join(&config.paths.base_dir);
-    if !base_dir_path.exists() {
-        fs::create_dir_all(&base_dir_path)
-            .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
-        println!("Created folder: {}", base_dir_path.display());
+/// components/README.md describing the layout with the configured directory names
+fn components_readme(config: &config::Config) -> String {
+    let paths = &config.paths;
+    let extensions_dirs = paths.extensions_dirs.iter()
+        .map(|dir| format!("`{}/`", dir))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(r#"# Components
+
+stackbuilder merges the Docker Compose files in this directory into `{build_dir}`.
+Run `stackbuilder build` after changing anything here; never edit the generated files.
+
+- `{base}/` - services shared by every build. Always merged first
+- `{environments}/<env>/` - per-environment overlays (e.g. ports, replicas), merged on top of base
+- {extensions_dirs} - optional features in `<extension>/` folders, merged last when an environment or combo enables them
+
+Each component folder holds a `docker-compose.yml` with only the settings it adds or changes,
+an optional `.env.example` that is concatenated into the generated `.env.example`, and any
+additional files (configs, scripts) copied next to the generated compose file.
+
+Environments, extensions and combos are configured in `stackbuilder.toml`.
+"#,
+        build_dir = paths.build_dir,
+        base = paths.base_dir,
+        environments = paths.environments_dir,
+        extensions_dirs = extensions_dirs,
+    )
+}
+
+/// Create an example file unless it already exists
+fn plan_example_file(plan: &mut InitPlan, path: PathBuf, content: String) {
+    if path.exists() {
+        plan.push(InitAction::Keep { path, kind: "Example file" });
     } else {
-        println!("Folder already exists: {}", base_dir_path.display());
+        plan.push(InitAction::CreateFile { path, content });
     }
+}
 
-    // If build has environments, create components_dir + environments_dir
-    if let Some(ref envs) = config.build.environments {
-        if !envs.is_empty() {
-            let env_dir_path = components_dir_path.join(&config.paths.environments_dir);
-            if !env_dir_path.exists() {
-                fs::create_dir_all(&env_dir_path)
-                    .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
-                println!("Created folder: {}", env_dir_path.display());
+/// Ask on the terminal before replacing an existing configuration file
+fn confirm_overwrite(path: &Path) -> Result<()> {
+    let declined = || InitError::OverwriteNotConfirmed { path: path.to_path_buf() };
+
+    if !std::io::stdin().is_terminal() {
+        return Err(declined().into());
+    }
+
+    print!("Overwrite {}? [y/N] ", path.display());
+    std::io::stdout().flush().ok();
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).map_err(|_| declined())?;
+    if matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        Ok(())
+    } else {
+        Err(declined().into())
+    }
+}
+
+/// Line diff of two texts: unchanged lines prefixed with "  ", removed with "- ", added with "+ "
+fn line_diff(old: &str, new: &str) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence lengths of the suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
             } else {
-                println!("Folder already exists: {}", env_dir_path.display());
-            }
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
         }
     }
 
-    // Create folders for each extensions_dirs
-    for ext_dir in &config.paths.extensions_dirs {
-        let ext_dir_path = components_dir_path.join(ext_dir);
-        if !ext_dir_path.exists() {
-            fs::create_dir_all(&ext_dir_path)
-                .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
-            println!("Created folder: {}", ext_dir_path.display());
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(format!("- {}", old[i]));
+            i += 1;
         } else {
-            println!("Folder already exists: {}", ext_dir_path.display());
+            diff.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+fn list_templates() {
+    println!("Available templates:");
+    let width = TEMPLATES.iter().map(|template| template.name.len()).max().unwrap_or(0);
+    for template in TEMPLATES {
+        println!("  {:width$}  {}", template.name, template.description, width = width);
+    }
+}
+
+/// Default configuration, named after --name or the project directory
+fn default_config(args: &InitArgs, working_dir: &Path) -> Result<config::Config> {
+    let mut default_config = config::Config::default();
+    default_config.project.name = Some(match args.name {
+        Some(ref name) => name.clone(),
+        None => default_project_name(working_dir)?,
+    });
+    let mut environments = args.environments.clone();
+    if args.override_compose.is_some() && !environments.iter().any(|env| env == ADOPTED_OVERRIDE_ENV) {
+        environments.insert(0, ADOPTED_OVERRIDE_ENV.to_string());
+    }
+    let mut extensions = args.extensions.clone();
+    if args.with_examples {
+        if !environments.iter().any(|env| env == EXAMPLE_ENVIRONMENT) {
+            environments.push(EXAMPLE_ENVIRONMENT.to_string());
         }
+        if !extensions.iter().any(|ext| ext == EXAMPLE_EXTENSION) {
+            extensions.push(EXAMPLE_EXTENSION.to_string());
+        }
+    }
+    if !environments.is_empty() {
+        default_config.build.environments = Some(environments);
     }
+    if !extensions.is_empty() {
+        default_config.build.extensions = Some(extensions);
+    }
+    Ok(default_config)
+}
 
+fn write_file(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content)
+        .map_err(|e| FileSystemError::FileWriteFailed {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
     Ok(())
 }
 
-fn create_example_compose(config: &config::Config) -> Result<()> {
-    let base_dir_path = Path::new(&config.paths.components_dir).join(&config.paths.base_dir);
-    let compose_file = base_dir_path.join("docker-compose.yml");
+/// Name of the project directory
+fn default_project_name(working_dir: &Path) -> Result<String> {
+    let dir = std::path::absolute(working_dir.join("."))
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: working_dir.to_path_buf(),
+            source: e,
+        })?;
 
-    if compose_file.exists() {
-        println!("docker-compose.yml already exists in: {}", compose_file.display());
-        return Ok(());
-    }
+    Ok(dir.components()
+        .filter_map(|c| match c {
+            std::path::Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .next_back()
+        .unwrap_or_else(|| "stackbuilder".to_string()))
+}
 
-    let example_content = r#"version: '3.8'
+/// Component directory scaffolded by init with its example files
+struct ComponentScaffold {
+    dir: PathBuf,
+    compose: String,
+    env_example: Option<String>,
+}
+
+/// Base, environment and extension components described by the configuration.
+/// With `with_examples` the example extension gets a real service instead of a placeholder.
+fn component_scaffolds(config: &config::Config, working_dir: &Path, with_examples: bool) -> Vec<ComponentScaffold> {
+    let components_dir_path = working_dir.join(&config.paths.components_dir);
+
+    let header = match config.project.name {
+        Some(ref name) => format!("# Base services for project: {}\n", name),
+        None => String::new(),
+    };
+    let mut scaffolds = vec![ComponentScaffold {
+        dir: components_dir_path.join(&config.paths.base_dir),
+        compose: header + r#"version: '3.8'
 services:
   example-service:
     image: nginx:latest
@@ -118,26 +775,348 @@ services:
       - "8080:80"
     environment:
       - EXAMPLE_VAR=hello
-"#;
+"#,
+        env_example: None,
+    }];
 
-    fs::create_dir_all(&base_dir_path)
-        .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
-    fs::write(&compose_file, example_content)
-        .map_err(|e| InitError::ExampleFileCreationFailed {
-            details: format!("Failed to write docker-compose.yml to {}: {}", compose_file.display(), e),
+    for env in config::get_environments_list(config) {
+        scaffolds.push(ComponentScaffold {
+            dir: components_dir_path.join(&config.paths.environments_dir).join(&env),
+            compose: format!(r#"# Overlay for the '{env}' environment, merged on top of base
+# List only the settings that differ from base
+services:
+  example-service:
+    environment:
+      - APP_ENV={env}
+"#),
+            env_example: Some(format!("# Variables for the '{env}' environment\nAPP_ENV={env}\n")),
+        });
+    }
+
+    if let Some(first_ext_dir) = config.paths.extensions_dirs.first() {
+        for ext in config::get_referenced_extensions(config) {
+            // An extension that already exists in any extensions directory is kept there
+            let dir = config.paths.extensions_dirs.iter()
+                .map(|ext_dir| components_dir_path.join(ext_dir).join(&ext))
+                .find(|dir| dir.exists())
+                .unwrap_or_else(|| components_dir_path.join(first_ext_dir).join(&ext));
+            let scaffold = match ext.as_str() {
+                EXAMPLE_EXTENSION if with_examples => ComponentScaffold {
+                    dir,
+                    compose: r#"# Extension 'monitoring', merged on top of base and the environment when enabled
+# Adds a Prometheus service next to the base services
+services:
+  prometheus:
+    image: prom/prometheus:latest
+    ports:
+      - "${PROMETHEUS_PORT:-9090}:9090"
+"#.to_string(),
+                    env_example: Some("# Variables for the 'monitoring' extension\nPROMETHEUS_PORT=9090\n".to_string()),
+                },
+                _ => ComponentScaffold {
+                    dir,
+                    compose: format!(r#"# Extension '{ext}', merged on top of base and the environment when enabled
+# Add services or override settings of existing ones
+services:
+  {ext}:
+    image: alpine:latest
+    command: ["sleep", "infinity"]
+"#),
+                    env_example: Some(format!("# Variables for the '{ext}' extension\n")),
+                },
+            };
+            scaffolds.push(scaffold);
+        }
+    }
+
+    scaffolds
+}
+
+/// Folders of the components directory, sorted
+fn component_folders(config: &config::Config, working_dir: &Path) -> Vec<PathBuf> {
+    let components_dir_path = working_dir.join(&config.paths.components_dir);
+    let mut folders = Vec::new();
+
+    // If build has environments, create components_dir + environments_dir
+    if !config::get_environments_list(config).is_empty() {
+        folders.push(components_dir_path.join(&config.paths.environments_dir));
+    }
+
+    // Create folders for each extensions_dirs
+    for ext_dir in &config.paths.extensions_dirs {
+        folders.push(components_dir_path.join(ext_dir));
+    }
+
+    // Base, environment and extension component folders
+    folders.extend(component_scaffolds(config, working_dir, false).into_iter().map(|scaffold| scaffold.dir));
+    folders.sort();
+    folders.dedup();
+    folders
+}
+
+/// Copy (or with --move, move) --from-compose into base and --override into the dev environment
+fn plan_adoption(args: &InitArgs, config: &config::Config, working_dir: &Path) -> Result<Vec<InitAction>> {
+    let Some(ref from_compose) = args.from_compose else {
+        return Ok(Vec::new());
+    };
+
+    let components_dir_path = working_dir.join(&config.paths.components_dir);
+    let mut adoptions = vec![(from_compose, components_dir_path.join(&config.paths.base_dir))];
+    if let Some(ref override_compose) = args.override_compose {
+        adoptions.push((
+            override_compose,
+            components_dir_path.join(&config.paths.environments_dir).join(ADOPTED_OVERRIDE_ENV),
+        ));
+    }
+
+    let mut actions = Vec::new();
+    for (source, target_dir) in adoptions {
+        // Relative sources are given relative to the project directory
+        let source = working_dir.join(source);
+        if !source.is_file() {
+            return Err(FileSystemError::FileReadFailed {
+                path: source,
+                source: std::io::Error::new(std::io::ErrorKind::NotFound, "compose file not found"),
+            }.into());
+        }
+        actions.push(InitAction::AdoptFile {
+            source,
+            target: target_dir.join("docker-compose.yml"),
+            move_file: args.move_files,
+        });
+    }
+
+    Ok(actions)
+}
+
+fn adopt_file(source: &Path, target: &Path, move_file: bool) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| InitError::ProjectStructureCreationFailed { source: e })?;
+    }
+    fs::copy(source, target)
+        .map_err(|e| FileSystemError::FileWriteFailed {
+            path: target.to_path_buf(),
+            source: e,
         })?;
-    println!("Created example docker-compose.yml in: {}", compose_file.display());
 
+    if move_file {
+        fs::remove_file(source)
+            .map_err(|e| FileSystemError::FileWriteFailed {
+                path: source.to_path_buf(),
+                source: e,
+            })?;
+        println!("Moved {} to {}", source.display(), target.display());
+    } else {
+        println!("Copied {} to {}", source.display(), target.display());
+    }
     Ok(())
 }
 
-#[derive(Parser)]
+#[derive(Parser, Debug, Default)]
 pub struct InitArgs {
+    /// Project name stored in the configuration (defaults to the current directory name)
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Environments to configure and scaffold (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub environments: Vec<String>,
+
+    /// Extensions to configure and scaffold (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub extensions: Vec<String>,
+
+    /// Add a 'dev' environment and a 'monitoring' extension with example content to the new configuration
+    #[arg(long, conflicts_with_all = ["template", "adopt", "skip_folders"])]
+    pub with_examples: bool,
+
+    /// Lay down a complete example project from an embedded template
+    #[arg(long, conflicts_with_all = ["from_compose", "environments", "extensions", "skip_folders"])]
+    pub template: Option<String>,
+
+    /// List the embedded templates and exit
+    #[arg(long, exclusive = true)]
+    pub list_templates: bool,
+
+    /// Write a configuration describing an existing components tree instead of scaffolding one
+    #[arg(long, conflicts_with_all = ["template", "from_compose", "environments", "extensions", "skip_folders"])]
+    pub adopt: bool,
+
+    /// Components directory to adopt when several folders qualify
+    #[arg(long, value_name = "DIR", requires = "adopt")]
+    pub components_dir: Option<String>,
+
+    /// Base component folder to adopt when several folders qualify
+    #[arg(long, value_name = "DIR", requires = "adopt")]
+    pub base_dir: Option<String>,
+
+    /// Environments folder to adopt when several folders qualify
+    #[arg(long, value_name = "DIR", requires = "adopt")]
+    pub environments_dir: Option<String>,
+
+    /// Existing docker-compose.yml to adopt as the base component
+    #[arg(long, value_name = "FILE")]
+    pub from_compose: Option<PathBuf>,
+
+    /// Compose override file to adopt as the 'dev' environment
+    #[arg(long = "override", value_name = "FILE", requires = "from_compose")]
+    pub override_compose: Option<PathBuf>,
+
+    /// Move the adopted compose files instead of copying them
+    #[arg(long = "move", requires = "from_compose")]
+    pub move_files: bool,
+
     /// Skip creating folders, only create config
     #[arg(long)]
     pub skip_folders: bool,
-    
-    /// Force overwrite existing configuration file
+
+    /// Force overwrite existing configuration file (shows a diff and asks for confirmation)
     #[arg(long)]
     pub force: bool,
-}
\ No newline at end of file
+
+    /// Overwrite the configuration with --force without asking for confirmation
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+
+    /// Do not create or extend the project .gitignore
+    #[arg(long)]
+    pub no_gitignore: bool,
+
+    /// Do not create components/README.md
+    #[arg(long)]
+    pub no_readme: bool,
+
+    /// Print the files and folders that would be created or overwritten without writing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Configuration file to write instead of stackbuilder.toml, for use with `build --config`
+    #[arg(long, value_name = "PATH", conflicts_with = "list_templates")]
+    pub config: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_diff() {
+        let diff = line_diff("[build]\nyaml_merger = \"rust\"\nextensions = [\"a\"]\n", "[build]\nyaml_merger = \"yq\"\nextensions = [\"a\"]\n");
+        assert_eq!(diff, vec![
+            "  [build]",
+            "- yaml_merger = \"rust\"",
+            "+ yaml_merger = \"yq\"",
+            "  extensions = [\"a\"]",
+        ]);
+    }
+
+    #[test]
+    fn test_rerun_plans_no_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let args = InitArgs {
+            environments: vec!["dev".to_string()],
+            ..Default::default()
+        };
+        init_project(&args, temp.path()).unwrap();
+
+        let plan = plan_project(&args, temp.path()).unwrap();
+        assert!(plan.actions.iter().all(|action| matches!(action, InitAction::Keep { .. })), "{:?}", plan.actions);
+        assert!(plan.summary(true).starts_with("Summary: 0 to create, 0 to overwrite"));
+    }
+
+    fn write_component(dir: &Path) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("docker-compose.yml"), "services: {}\n").unwrap();
+    }
+
+    #[test]
+    fn test_detect_layout_with_custom_names() {
+        let temp = tempfile::tempdir().unwrap();
+        let stack = temp.path().join("stack");
+        write_component(&stack.join("common"));
+        write_component(&stack.join("envs/dev"));
+        fs::create_dir_all(stack.join("envs/prod")).unwrap();
+        write_component(&stack.join("addons/redis"));
+        write_component(&stack.join("addons/auth"));
+        write_component(&stack.join("plugins/metrics"));
+        fs::create_dir_all(temp.path().join("docs")).unwrap();
+
+        let layout = detect_layout(&InitArgs::default(), temp.path()).unwrap();
+        assert_eq!(layout, AdoptedLayout {
+            components_dir: "./stack".to_string(),
+            base_dir: Some("common".to_string()),
+            environments_dir: Some("envs".to_string()),
+            environments: vec!["dev".to_string(), "prod".to_string()],
+            extensions_dirs: vec!["addons".to_string(), "plugins".to_string()],
+            extensions: vec!["auth".to_string(), "redis".to_string(), "metrics".to_string()],
+        });
+    }
+
+    #[test]
+    fn test_detect_layout_reports_ambiguous_base() {
+        let temp = tempfile::tempdir().unwrap();
+        write_component(&temp.path().join("components/core"));
+        write_component(&temp.path().join("components/shared"));
+
+        match detect_layout(&InitArgs::default(), temp.path()) {
+            Err(crate::error::StackBuilderError::Init(InitError::AmbiguousLayout { kind, candidates, flag })) => {
+                assert_eq!(kind, "base");
+                assert_eq!(candidates, vec!["core", "shared"]);
+                assert_eq!(flag, "base-dir");
+            }
+            other => panic!("Expected an ambiguous base, got {:?}", other),
+        }
+
+        let args = InitArgs {
+            adopt: true,
+            base_dir: Some("core".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(detect_layout(&args, temp.path()).unwrap().base_dir.as_deref(), Some("core"));
+    }
+
+    #[test]
+    fn test_detect_layout_without_tree() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("docs")).unwrap();
+
+        let result = detect_layout(&InitArgs::default(), temp.path());
+        assert!(matches!(result, Err(crate::error::StackBuilderError::Init(InitError::ComponentTreeNotFound { .. }))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_gitignore_entries() {
+        let mut config = config::Config::default();
+        assert_eq!(gitignore_entries(&config), vec!["/build/", "/.stackbuilder/"]);
+
+        config.paths.build_dir = "./out/compose".to_string();
+        config.build.backup_dir = "./backups".to_string();
+        assert_eq!(gitignore_entries(&config), vec!["/out/compose/", "/.stackbuilder/", "/backups/"]);
+
+        config.build.backup_dir = "../shared-backups".to_string();
+        assert_eq!(gitignore_entries(&config), vec!["/out/compose/", "/.stackbuilder/"]);
+    }
+
+    #[test]
+    fn test_force_plans_config_overwrite_with_previous_content() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join(CONFIG_FILE), "[build]\nyaml_merger = \"rust\"\n").unwrap();
+
+        let args = InitArgs {
+            force: true,
+            skip_folders: true,
+            no_gitignore: true,
+            ..Default::default()
+        };
+        let plan = plan_project(&args, temp.path()).unwrap();
+        match &plan.actions[..] {
+            [InitAction::WriteConfig { previous: Some(previous), content, .. }] => {
+                assert_eq!(previous, "[build]\nyaml_merger = \"rust\"\n");
+                assert!(line_diff(previous, content).contains(&"- yaml_merger = \"rust\"".to_string()));
+            }
+            actions => panic!("Expected a config overwrite, got {:?}", actions),
+        }
+        assert_eq!(plan.summary(true), "Summary: 0 to create, 1 to overwrite, 0 already exist");
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::error::{Result, BuildError, FileSystemError};
+
+/// Directory below the build directory holding the Kubernetes manifests of `kubernetes_output`, one subdirectory per combination
+pub const KUBERNETES_OUTPUT_DIR: &str = "k8s";
+
+/// Check if kompose is available in the system and get its version
+pub fn check_kompose_availability() -> Result<String> {
+    let output = Command::new("kompose")
+        .arg("version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("kompose command not found, install it from https://kompose.io/installation/ ({})", e),
+        })?;
+
+    if !output.status.success() {
+        return Err(BuildError::BuildProcessFailed {
+            details: format!("kompose command failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+        }.into());
+    }
+
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    status!("✓ kompose version: {}", version);
+    Ok(version)
+}
+
+/// Convert `compose_file` to Kubernetes manifests in `output_dir`. kompose runs in the directory of the
+/// compose file, so relative `env_file` and volume paths resolve as they do for docker compose.
+pub fn convert(compose_file: &Path, output_dir: &Path) -> Result<()> {
+    fs::create_dir_all(output_dir)
+        .map_err(|e| FileSystemError::DirectoryCreationFailed {
+            path: output_dir.to_path_buf(),
+            source: e,
+        })?;
+    // kompose runs elsewhere, so a relative output directory would land next to the compose file
+    let output_dir = std::path::absolute(output_dir).unwrap_or_else(|_| output_dir.to_path_buf());
+    let compose_dir = compose_file.parent().unwrap_or(Path::new("."));
+    let file_name = compose_file.file_name().unwrap_or(compose_file.as_os_str());
+    let output = Command::new("kompose")
+        .arg("convert")
+        .arg("--file").arg(file_name)
+        // A trailing separator makes kompose write one file per resource into the directory
+        .arg("--out").arg(format!("{}{}", output_dir.display(), std::path::MAIN_SEPARATOR))
+        .current_dir(compose_dir)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to run kompose: {}", e),
+        })?;
+
+    if !output.status.success() {
+        return Err(BuildError::BuildProcessFailed {
+            details: format!("kompose convert failed for {}: {}", compose_file.display(), String::from_utf8_lossy(&output.stderr).trim()),
+        }.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+//! Build docker-compose files from modular components.
+//!
+//! The `stackbuilder` binary is a thin CLI over this crate. [`build`] runs a complete build
+//! against an explicit working directory, with the configuration loaded from its
+//! `stackbuilder.toml` or passed in memory, and returns a [`BuildReport`]:
+//!
+//! ```
+//! use std::fs;
+//! use stackbuilder::{BuildOptions, Config, YamlMergerType};
+//!
+//! let project = tempfile::tempdir()?;
+//! fs::create_dir_all(project.path().join("components/base"))?;
+//! fs::write(
+//!     project.path().join("components/base/docker-compose.yml"),
+//!     "services:\n  web:\n    image: nginx:alpine\n",
+//! )?;
+//!
+//! let config = Config::builder().with_yaml_merger(YamlMergerType::Rust).build();
+//!
+//! let report = stackbuilder::build(BuildOptions::new(project.path()).with_config(config))?;
+//! assert_eq!(report.combinations.len(), 1);
+//! assert!(project.path().join("build/docker-compose.yml").exists());
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Library builds print nothing; warnings are returned in [`BuildReport::warnings`].
+
+/// `println!` for build progress, silenced while a quiet library build runs on this thread
+/// and printed above the progress bars while an observer draws them
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::diagnostics::is_quiet() {
+            $crate::diagnostics::print_status(format_args!($($arg)*));
+        }
+    };
+}
+
+#[doc(hidden)]
+pub mod cli;
+mod error;
+mod config;
+mod diagnostics;
+mod merger;
+mod yq_merger;
+mod env_merger;
+mod file_copier;
+mod build;
+mod build_cleaner;
+mod build_report;
+mod checksums;
+mod archive;
+#[cfg(feature = "argocd")]
+mod export;
+mod observer;
+mod init;
+mod backups;
+mod validate;
+mod manifest;
+mod paths;
+mod upgrade_config;
+mod watch;
+mod build_cache;
+mod build_lock;
+mod helper_scripts;
+mod image_check;
+mod kompose;
+mod variables;
+mod output_writer;
+mod ports;
+mod templates;
+mod yaml_emitter;
+
+#[cfg(test)]
+mod tests;
+
+pub use build::{build, BuildArgs, BuildCombination, BuildExecutor, BuildOptions};
+pub use build_report::{BuildReport, CombinationReport};
+pub use observer::{BuildObserver, ConsoleObserver, GithubObserver};
+pub use paths::ResolvedPaths;
+pub use config::{user_config_path, Config, YamlMergerType};
+pub use env_merger::merge_env_files;
+pub use error::{Result, StackBuilderError};
+pub use merger::{merge_compose_files, ComposeMerger};
+pub use yq_merger::{yq_merge_compose_files, YqMerger};
@@ -0,0 +1,206 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use clap::Parser;
+use serde::Serialize;
+use crate::config::{self, Config};
+use crate::error::{Result, FileSystemError, ValidationError, YamlError};
+
+const COMPOSE_FILE: &str = "docker-compose.yml";
+const ENV_EXAMPLE_FILE: &str = ".env.example";
+
+/// Inventory of the components tree
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentsManifest {
+    /// Components directory as configured
+    pub components_dir: PathBuf,
+    /// Base first, then environments and extensions, each sorted by name
+    pub components: Vec<ComponentEntry>,
+}
+
+/// Role of a component directory in the merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentKind {
+    Base,
+    Environment,
+    Extension,
+}
+
+/// A component directory and the artifacts it contains
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ComponentKind,
+    /// Directory relative to the components directory
+    pub path: PathBuf,
+    /// Whether a docker-compose.yml is present
+    pub compose: bool,
+    /// Whether a .env.example is present
+    pub env_example: bool,
+    /// Every other file, relative to the component directory and sorted
+    pub files: Vec<PathBuf>,
+}
+
+/// Runs the manifest command logic
+pub fn run_manifest(args: &ManifestArgs) -> Result<()> {
+    let config = config::load_config()?;
+    let manifest = build_manifest(&config)?;
+
+    let json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| YamlError::SerializationError {
+            details: format!("Failed to serialize components manifest: {}", e),
+        })?;
+
+    match args.output {
+        Some(ref path) => {
+            fs::write(path, json + "\n")
+                .map_err(|e| FileSystemError::FileWriteFailed {
+                    path: path.clone(),
+                    source: e,
+                })?;
+            eprintln!("✓ Wrote components manifest: {}", path.display());
+        }
+        None => println!("{}", json),
+    }
+
+    Ok(())
+}
+
+/// Walk the base, environment and extension directories of the configured components tree.
+/// Read-only: nothing is merged and yq is not needed.
+pub fn build_manifest(config: &Config) -> Result<ComponentsManifest> {
+    let components_dir = Path::new(&config.paths.components_dir);
+    if !components_dir.is_dir() {
+        return Err(ValidationError::ComponentsDirectoryNotFound {
+            path: components_dir.to_path_buf(),
+        }.into());
+    }
+
+    let mut components = Vec::new();
+
+    let base_path = PathBuf::from(&config.paths.base_dir);
+    if components_dir.join(&base_path).is_dir() {
+        components.push(component_entry(components_dir, config.paths.base_dir.clone(), ComponentKind::Base, base_path)?);
+    }
+
+    let environments_dir = PathBuf::from(&config.paths.environments_dir);
+    for name in config::discover_component_dirs(&components_dir.join(&environments_dir))? {
+        let path = environments_dir.join(&name);
+        components.push(component_entry(components_dir, name, ComponentKind::Environment, path)?);
+    }
+
+    for ext_dir in &config.paths.extensions_dirs {
+        for name in config::discover_component_dirs(&components_dir.join(ext_dir))? {
+            let path = Path::new(ext_dir).join(&name);
+            components.push(component_entry(components_dir, name, ComponentKind::Extension, path)?);
+        }
+    }
+
+    Ok(ComponentsManifest {
+        components_dir: components_dir.to_path_buf(),
+        components,
+    })
+}
+
+fn component_entry(components_dir: &Path, name: String, kind: ComponentKind, path: PathBuf) -> Result<ComponentEntry> {
+    let dir = components_dir.join(&path);
+    let mut files = Vec::new();
+    collect_files(&dir, &dir, &mut files)?;
+    files.sort();
+
+    let compose = files.iter().any(|file| file == Path::new(COMPOSE_FILE));
+    let env_example = files.iter().any(|file| file == Path::new(ENV_EXAMPLE_FILE));
+    files.retain(|file| file != Path::new(COMPOSE_FILE) && file != Path::new(ENV_EXAMPLE_FILE));
+
+    Ok(ComponentEntry { name, kind, path, compose, env_example, files })
+}
+
+/// Collect the files below `dir`, relative to `root`; symlinks to files count as files, symlinks to directories are skipped
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: dir.to_path_buf(),
+            source: e,
+        })?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: dir.to_path_buf(),
+            source: e,
+        })?;
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|e| FileSystemError::DirectoryReadFailed {
+            path: path.clone(),
+            source: e,
+        })?;
+        // Symlinked directories are not followed, so a link to a parent cannot recurse forever
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if !(file_type.is_symlink() && path.is_dir()) {
+            files.push(path.strip_prefix(root).map(Path::to_path_buf).unwrap_or(path));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Parser, Debug, Default)]
+pub struct ManifestArgs {
+    /// Write the JSON manifest to this file instead of stdout
+    #[arg(long, short = 'o', value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_manifest() {
+        let temp = tempfile::tempdir().unwrap();
+        let components = temp.path().join("components");
+        fs::create_dir_all(components.join("base")).unwrap();
+        fs::write(components.join("base/docker-compose.yml"), "services: {}\n").unwrap();
+        fs::write(components.join("base/.env.example"), "PORT=80\n").unwrap();
+        fs::create_dir_all(components.join("environments/prod/config")).unwrap();
+        fs::write(components.join("environments/prod/config/app.conf"), "").unwrap();
+        fs::create_dir_all(components.join("environments/dev")).unwrap();
+        fs::write(components.join("environments/dev/docker-compose.yml"), "services: {}\n").unwrap();
+        fs::create_dir_all(components.join("extensions/monitoring")).unwrap();
+        fs::write(components.join("extensions/monitoring/docker-compose.yml"), "services: {}\n").unwrap();
+        fs::write(components.join("extensions/monitoring/prometheus.yml"), "").unwrap();
+
+        let mut config = Config::default();
+        config.paths.components_dir = components.to_string_lossy().to_string();
+        let manifest = build_manifest(&config).unwrap();
+
+        let summary: Vec<(&str, ComponentKind, bool, bool)> = manifest.components.iter()
+            .map(|c| (c.name.as_str(), c.kind, c.compose, c.env_example))
+            .collect();
+        assert_eq!(summary, vec![
+            ("base", ComponentKind::Base, true, true),
+            ("dev", ComponentKind::Environment, true, false),
+            ("prod", ComponentKind::Environment, false, false),
+            ("monitoring", ComponentKind::Extension, true, false),
+        ]);
+        assert!(manifest.components[0].files.is_empty());
+        assert_eq!(manifest.components[2].path, Path::new("environments/prod"));
+        assert_eq!(manifest.components[2].files, vec![PathBuf::from("config/app.conf")]);
+        assert_eq!(manifest.components[3].files, vec![PathBuf::from("prometheus.yml")]);
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["components"][3]["type"], "extension");
+        assert_eq!(json["components"][3]["path"], "extensions/monitoring");
+    }
+
+    #[test]
+    fn test_build_manifest_missing_components_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.paths.components_dir = temp.path().join("components").to_string_lossy().to_string();
+
+        let result = build_manifest(&config);
+        assert!(matches!(result, Err(crate::error::StackBuilderError::Validation(ValidationError::ComponentsDirectoryNotFound { .. }))));
+    }
+}
@@ -1,14 +1,38 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use serde_yaml_ng::Value;
+use crate::config::{LabelStyle, MergeStrategy, PortStyle};
+use crate::diagnostics::{self, WarningCategory};
+use crate::env_merger::parse_env_file;
 use crate::error::{Result, YamlError, FileSystemError};
+use crate::paths::ResolvedPaths;
+use crate::ports;
 
 /// Structure for managing docker-compose file merging process
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ComposeMerger {
     pub base_path: String,
     pub environments_path: String,
     pub extensions_paths: Vec<String>,
+    /// Fail on a missing base `docker-compose.yml`; when false, the environment or the first extension starts the merge
+    pub require_base: bool,
+    /// Merge strategies for top-level sections; unlisted sections are deep-merged
+    pub merge_strategy: BTreeMap<String, MergeStrategy>,
+    /// Inline `env_file` variables into each service's `environment`
+    pub inline_env_files: bool,
+    /// Make relative `build` contexts absolute against the component they come from
+    pub rewrite_build_context: bool,
+    /// Remove keys left with an empty mapping or sequence after merging
+    pub prune_empty_collections: bool,
+    /// Form the merged `labels` are written in
+    pub label_style: LabelStyle,
+    /// Syntax the merged `ports` are written in
+    pub port_style: PortStyle,
+    /// Components roots, to name the one each merged file comes from
+    pub component_roots: Vec<PathBuf>,
+    /// Absolute directory the merged file is written to; resolved `build` contexts are made relative to it
+    pub output_dir: Option<PathBuf>,
 }
 
 impl ComposeMerger {
@@ -18,8 +42,90 @@ impl ComposeMerger {
             base_path,
             environments_path,
             extensions_paths,
+            require_base: true,
+            merge_strategy: BTreeMap::new(),
+            inline_env_files: false,
+            rewrite_build_context: false,
+            prune_empty_collections: false,
+            label_style: LabelStyle::default(),
+            port_style: PortStyle::default(),
+            component_roots: Vec::new(),
+            output_dir: None,
         }
     }
+
+    /// Create a ComposeMerger reading the components of `paths`
+    pub fn from_paths(paths: &ResolvedPaths) -> Self {
+        let (base_path, environments_path, extensions_paths) = paths.merger_paths();
+        Self::new(base_path, environments_path, extensions_paths)
+            .with_component_roots(paths.components_roots.clone())
+    }
+
+    /// Merge combinations whose base `docker-compose.yml` is missing, starting from their next layer
+    pub fn with_require_base(mut self, require_base: bool) -> Self {
+        self.require_base = require_base;
+        self
+    }
+
+    /// Use the given merge strategies for top-level sections
+    pub fn with_merge_strategy(mut self, merge_strategy: BTreeMap<String, MergeStrategy>) -> Self {
+        self.merge_strategy = merge_strategy;
+        self
+    }
+
+    /// Inline `env_file` contents into service `environment` blocks after merging
+    pub fn with_inline_env_files(mut self, inline_env_files: bool) -> Self {
+        self.inline_env_files = inline_env_files;
+        self
+    }
+
+    /// Resolve relative `build` contexts against their component directory before merging
+    pub fn with_rewrite_build_context(mut self, rewrite_build_context: bool) -> Self {
+        self.rewrite_build_context = rewrite_build_context;
+        self
+    }
+
+    /// Make resolved `build` contexts relative to `output_dir`, where the merged file will be written,
+    /// instead of leaving them absolute
+    pub fn with_output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(output_dir.into());
+        self
+    }
+
+    /// Drop empty mappings and sequences from the merged document
+    pub fn with_prune_empty_collections(mut self, prune_empty_collections: bool) -> Self {
+        self.prune_empty_collections = prune_empty_collections;
+        self
+    }
+
+    /// Write the merged `labels` as a map or as a list
+    pub fn with_label_style(mut self, label_style: LabelStyle) -> Self {
+        self.label_style = label_style;
+        self
+    }
+
+    /// Write the merged `ports` in the short or long syntax, or as declared
+    pub fn with_port_style(mut self, port_style: PortStyle) -> Self {
+        self.port_style = port_style;
+        self
+    }
+
+    /// Name the components root of every merged file in the merge log
+    pub fn with_component_roots(mut self, component_roots: Vec<PathBuf>) -> Self {
+        self.component_roots = component_roots;
+        self
+    }
+}
+
+/// `file_path` for the merge log, followed by its components root when there is more than one root
+pub fn describe_component_file(file_path: &str, component_roots: &[PathBuf]) -> String {
+    if component_roots.len() < 2 {
+        return file_path.to_string();
+    }
+    match crate::config::component_root_of(component_roots, Path::new(file_path)) {
+        Some(root) => format!("{} (root: {})", file_path, root.display()),
+        None => file_path.to_string(),
+    }
 }
 
 /// Load and parse docker-compose.yml file from given path
@@ -31,7 +137,7 @@ pub fn load_compose_file(file_path: &str) -> Result<Value> {
         })?;
 
     let yaml_value: Value = serde_yaml_ng::from_str(&content)
-        .map_err(|e| YamlError::serde_error(file_path, e))?;
+        .map_err(|e| YamlError::serde_error(file_path, &content, e))?;
 
     // Validate basic docker-compose structure
     if let Value::Mapping(ref map) = yaml_value {
@@ -51,17 +157,59 @@ pub fn load_compose_file(file_path: &str) -> Result<Value> {
     Ok(yaml_value)
 }
 
+/// Compose merge tags recognized by the Rust merger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeTag {
+    /// `!reset` - remove the key from the merged result
+    Reset,
+    /// `!override` - replace the value instead of deep-merging it
+    Override,
+}
+
+/// Get the Compose merge tag attached to a value, if any
+pub fn merge_tag(value: &Value) -> Option<MergeTag> {
+    match value {
+        Value::Tagged(tagged) if tagged.tag == "reset" => Some(MergeTag::Reset),
+        Value::Tagged(tagged) if tagged.tag == "override" => Some(MergeTag::Override),
+        _ => None,
+    }
+}
+
+/// Statistics collected while merging compose files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MergeStats {
+    /// Number of existing leaf values replaced by a later file
+    pub overridden_keys: usize,
+    /// Number of files merged
+    pub files: usize,
+}
+
 /// Recursively merge YAML values with priority logic
-/// Later values overwrite earlier ones for objects, primitives, and append for arrays
-pub fn merge_yaml_values(base: Value, override_: Value) -> Value {
+/// Later values overwrite earlier ones for objects, primitives, and append for arrays.
+/// Values tagged `!override` replace the earlier value, keys tagged `!reset` are removed.
+/// Every replaced existing leaf value is counted in `stats`.
+pub fn merge_yaml_values(base: Value, override_: Value, stats: &mut MergeStats) -> Value {
     match (base, override_) {
         (Value::Mapping(mut base_map), Value::Mapping(override_map)) => {
             // For objects, merge recursively and allow overrides
             for (key, value) in override_map {
-                if let Some(base_value) = base_map.get(&key) {
-                    base_map.insert(key, merge_yaml_values(base_value.clone(), value));
-                } else {
-                    base_map.insert(key, value);
+                match merge_tag(&value) {
+                    Some(MergeTag::Reset) => {
+                        base_map.remove(&key);
+                    }
+                    Some(MergeTag::Override) => {
+                        if base_map.contains_key(&key) {
+                            stats.overridden_keys += 1;
+                        }
+                        base_map.insert(key, resolve_merge_tags(value));
+                    }
+                    None => {
+                        if let Some(base_value) = base_map.get(&key) {
+                            base_map.insert(key, merge_yaml_values(base_value.clone(), value, stats));
+                        } else {
+                            base_map.insert(key, value);
+                        }
+                    }
                 }
             }
             Value::Mapping(base_map)
@@ -72,7 +220,123 @@ pub fn merge_yaml_values(base: Value, override_: Value) -> Value {
             Value::Sequence(base_seq)
         }
         // For primitives or other types, override completely
-        (_, override_val) => override_val,
+        (base_val, override_val) => {
+            if base_val != override_val {
+                stats.overridden_keys += 1;
+            }
+            override_val
+        }
+    }
+}
+
+/// Merge two compose documents, applying the configured strategy to each top-level section.
+/// Merge tags on top-level keys take precedence over the strategy.
+pub fn merge_compose_documents(
+    base: Value,
+    override_: Value,
+    strategies: &BTreeMap<String, MergeStrategy>,
+    stats: &mut MergeStats,
+) -> Value {
+    let (mut base_map, override_map) = match (base, override_) {
+        (Value::Mapping(base_map), Value::Mapping(override_map)) => (base_map, override_map),
+        (base, override_) => return merge_yaml_values(base, override_, stats),
+    };
+
+    for (key, value) in override_map {
+        match merge_tag(&value) {
+            Some(MergeTag::Reset) => {
+                base_map.remove(&key);
+            }
+            Some(MergeTag::Override) => {
+                if base_map.contains_key(&key) {
+                    stats.overridden_keys += 1;
+                }
+                base_map.insert(key, resolve_merge_tags(value));
+            }
+            None => {
+                let strategy = key.as_str()
+                    .and_then(|section| strategies.get(section))
+                    .copied()
+                    .unwrap_or_default();
+                if let Some(base_value) = base_map.get(&key) {
+                    base_map.insert(key, merge_with_strategy(base_value.clone(), value, strategy, stats));
+                } else {
+                    base_map.insert(key, value);
+                }
+            }
+        }
+    }
+
+    Value::Mapping(base_map)
+}
+
+/// Merge two values of a top-level section with the given strategy.
+/// Merge tags in the override are resolved in every strategy, `!reset` entries remove the earlier entry.
+pub fn merge_with_strategy(base: Value, override_: Value, strategy: MergeStrategy, stats: &mut MergeStats) -> Value {
+    match strategy {
+        MergeStrategy::Deep => merge_yaml_values(base, override_, stats),
+        MergeStrategy::Replace => {
+            let override_ = resolve_merge_tags(override_);
+            if base != override_ {
+                stats.overridden_keys += 1;
+            }
+            override_
+        }
+        MergeStrategy::Append | MergeStrategy::UniqueAppend => {
+            let unique = strategy == MergeStrategy::UniqueAppend;
+            match (base, override_) {
+                (Value::Mapping(mut base_map), Value::Mapping(override_map)) => {
+                    for (key, value) in override_map {
+                        // `!reset` removes the entry whatever the strategy
+                        if merge_tag(&value) == Some(MergeTag::Reset) {
+                            base_map.remove(&key);
+                            continue;
+                        }
+                        let value = resolve_merge_tags(value);
+                        match base_map.get(&key) {
+                            // unique-append keeps the first definition of a name
+                            Some(_) if unique => {}
+                            Some(existing) => {
+                                if *existing != value {
+                                    stats.overridden_keys += 1;
+                                }
+                                base_map.insert(key, value);
+                            }
+                            None => {
+                                base_map.insert(key, value);
+                            }
+                        }
+                    }
+                    Value::Mapping(base_map)
+                }
+                (Value::Sequence(mut base_seq), Value::Sequence(override_seq)) => {
+                    for item in override_seq.into_iter().map(resolve_merge_tags) {
+                        if !unique || !base_seq.contains(&item) {
+                            base_seq.push(item);
+                        }
+                    }
+                    Value::Sequence(base_seq)
+                }
+                (base_val, override_val) => merge_yaml_values(base_val, override_val, stats),
+            }
+        }
+    }
+}
+
+/// Strip Compose merge tags from a merged value so they don't leak into the output
+pub fn resolve_merge_tags(value: Value) -> Value {
+    match value {
+        Value::Tagged(tagged) if tagged.tag == "reset" || tagged.tag == "override" => {
+            resolve_merge_tags(tagged.value)
+        }
+        Value::Mapping(map) => Value::Mapping(
+            map.into_iter()
+                .filter(|(_, v)| merge_tag(v) != Some(MergeTag::Reset))
+                .map(|(k, v)| (k, resolve_merge_tags(v)))
+                .collect(),
+        ),
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(resolve_merge_tags).collect()),
+        other => other,
     }
 }
 
@@ -81,16 +345,17 @@ pub fn merge_compose_files(
     merger: &ComposeMerger,
     environment: Option<&str>,
     extensions: &[String],
-) -> Result<Value> {
+) -> Result<(Value, MergeStats)> {
     let file_paths = resolve_merge_order(merger, environment, extensions)?;
 
     let mut merged: Option<Value> = None;
     let mut processed_files = 0;
+    let mut stats = MergeStats::default();
 
     for file_path in file_paths {
-        let yaml_value = match load_compose_file(&file_path) {
+        let mut yaml_value = match load_compose_file(&file_path) {
             Ok(val) => {
-                println!("Loaded and merging: {}", file_path);
+                status!("Loaded and merging: {}", describe_component_file(&file_path, &merger.component_roots));
                 processed_files += 1;
                 val
             }
@@ -100,13 +365,26 @@ pub fn merge_compose_files(
                     return Err(e);
                 }
                 // For other files, skip with warning
-                println!("Warning: Skipping missing or invalid file '{}': {}", file_path, e);
+                diagnostics::warn_at(WarningCategory::MissingFile, &file_path, format!("Skipping missing or invalid file '{}': {}", file_path, e));
                 continue;
             }
         };
 
+        // Merged by key below, whether a file lists `- key=value` or maps `key: value`
+        normalize_labels(&mut yaml_value);
+
+        // env_file paths and build contexts are relative to the component they come from
+        if let Some(component_dir) = Path::new(&file_path).parent() {
+            if merger.inline_env_files {
+                resolve_env_file_paths(&mut yaml_value, component_dir);
+            }
+            if merger.rewrite_build_context {
+                resolve_build_contexts(&mut yaml_value, component_dir, merger.output_dir.as_deref());
+            }
+        }
+
         if let Some(current) = merged {
-            merged = Some(merge_yaml_values(current, yaml_value));
+            merged = Some(merge_compose_documents(current, yaml_value, &merger.merge_strategy, &mut stats));
         } else {
             merged = Some(yaml_value);
         }
@@ -118,11 +396,460 @@ pub fn merge_compose_files(
         }.into());
     }
 
-    merged.ok_or_else(|| YamlError::MergeError {
+    stats.files = processed_files;
+
+    let mut merged = merged.map(resolve_merge_tags).ok_or_else(|| YamlError::MergeError {
         details: "Failed to merge docker-compose files".to_string(),
-    }.into())
+    })?;
+
+    if merger.inline_env_files {
+        inline_env_files(&mut merged)?;
+    }
+
+    if merger.prune_empty_collections {
+        prune_empty_collections(&mut merged);
+    }
+
+    if merger.label_style == LabelStyle::List {
+        labels_as_lists(&mut merged);
+    }
+
+    // Appending lists repeats a port declared by several files, possibly in different syntaxes
+    ports::normalize_ports(&mut merged, merger.port_style);
+
+    Ok((merged, stats))
+}
+
+/// Sections whose entries can carry `labels`
+const LABELED_SECTIONS: &[&str] = &["services", "volumes", "networks", "configs", "secrets"];
+
+/// `labels` of every service and top-level volume, network, config and secret, with merge tags still attached
+fn labels_mut(document: &mut Value) -> impl Iterator<Item = &mut Value> {
+    document.as_mapping_mut()
+        .into_iter()
+        .flat_map(|sections| sections.iter_mut())
+        .filter(|(section, _)| section.as_str().is_some_and(|section| LABELED_SECTIONS.contains(&section)))
+        .filter_map(|(_, entries)| entries.as_mapping_mut())
+        .flat_map(|entries| entries.values_mut())
+        .filter_map(|entry| entry.get_mut("labels"))
+}
+
+/// The value under a `!reset` or `!override` tag, or `value` itself
+fn untagged_mut(value: &mut Value) -> &mut Value {
+    match value {
+        Value::Tagged(tagged) => &mut tagged.value,
+        value => value,
+    }
 }
 
+/// Rewrite `labels` given as a `key=value` list into the map form, so later files override by key.
+/// A list entry without `=` is a label with an empty value.
+pub fn normalize_labels(document: &mut Value) {
+    for labels in labels_mut(document) {
+        let labels = untagged_mut(labels);
+        let Value::Sequence(entries) = labels else { continue };
+        // Compose only accepts strings here; a list with anything else is left for it to reject
+        let Some(entries) = entries.iter().map(Value::as_str).collect::<Option<Vec<_>>>() else { continue };
+        let map = entries.into_iter()
+            .map(|entry| entry.split_once('=').unwrap_or((entry, "")))
+            .map(|(key, value)| (Value::from(key), Value::from(value)))
+            .collect();
+        *labels = Value::Mapping(map);
+    }
+}
+
+/// Write merged `labels` maps as `key=value` lists, in merge order
+pub fn labels_as_lists(document: &mut Value) {
+    for labels in labels_mut(document) {
+        let Value::Mapping(map) = labels else { continue };
+        let entries = map.iter()
+            .map(|(key, value)| Value::from(format!("{}={}", scalar_string(key), scalar_string(value))))
+            .collect();
+        *labels = Value::Sequence(entries);
+    }
+}
+
+/// A scalar as compose reads it in a label, with null as an empty value
+fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Number(number) => number.to_string(),
+        other => serde_yaml_ng::to_string(other).unwrap_or_default().trim_end().to_string(),
+    }
+}
+
+/// Service mappings of a compose document
+fn services_mut(document: &mut Value) -> impl Iterator<Item = &mut serde_yaml_ng::Mapping> {
+    document.get_mut("services")
+        .and_then(Value::as_mapping_mut)
+        .into_iter()
+        .flat_map(|services| services.values_mut())
+        .filter_map(Value::as_mapping_mut)
+}
+
+/// Make every `env_file` path of the document's services absolute against the component directory
+fn resolve_env_file_paths(document: &mut Value, component_dir: &Path) {
+    let resolve = |path: &mut Value| {
+        if let Value::String(relative) = path {
+            *relative = component_dir.join(&*relative).to_string_lossy().to_string();
+        }
+    };
+
+    for service in services_mut(document) {
+        match service.get_mut("env_file") {
+            Some(Value::Sequence(entries)) => {
+                for entry in entries {
+                    match entry {
+                        Value::Mapping(long_form) => {
+                            if let Some(path) = long_form.get_mut("path") {
+                                resolve(path);
+                            }
+                        }
+                        path => resolve(path),
+                    }
+                }
+            }
+            Some(path) => resolve(path),
+            None => {}
+        }
+    }
+}
+
+/// The `build` context of a service: the shorthand string or `build.context`
+fn build_context_mut(service: &mut serde_yaml_ng::Mapping) -> Option<&mut String> {
+    match service.get_mut("build")? {
+        Value::String(context) => Some(context),
+        Value::Mapping(build) => match build.get_mut("context")? {
+            Value::String(context) => Some(context),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether a build context is a path on disk rather than a Git repository or URL
+fn is_local_context(context: &str) -> bool {
+    !context.contains("://") && !context.starts_with("git@")
+}
+
+/// Resolve every relative local `build` context of the document's services against the component directory,
+/// relative to `output_dir` when given and absolute otherwise. Absolute paths and remote contexts are kept.
+fn resolve_build_contexts(document: &mut Value, component_dir: &Path, output_dir: Option<&Path>) {
+    for service in services_mut(document) {
+        if let Some(context) = build_context_mut(service) {
+            if is_local_context(context) && Path::new(context.as_str()).is_relative() {
+                let resolved = normalize_path(&component_dir.join(&*context));
+                *context = match output_dir {
+                    Some(output_dir) => relative_path(&resolved, output_dir),
+                    None => resolved,
+                }.to_string_lossy().to_string();
+            }
+        }
+    }
+}
+
+/// Services whose local `build` context is relative, with that context.
+/// Such contexts were written for the component directory and may not resolve from the output directory.
+pub fn relative_build_contexts(document: &Value) -> Vec<(String, String)> {
+    let Some(services) = document.get("services").and_then(Value::as_mapping) else {
+        return Vec::new();
+    };
+    services.iter()
+        .filter_map(|(name, service)| {
+            let context = match service.get("build")? {
+                Value::String(context) => context,
+                build => build.get("context")?.as_str()?,
+            };
+            (is_local_context(context) && Path::new(context).is_relative())
+                .then(|| (name.as_str().unwrap_or_default().to_string(), context.to_string()))
+        })
+        .collect()
+}
+
+/// Rewrite the relative paths of a component's compose file so they resolve from `output_dir`, for a file
+/// that is used as it is instead of being merged. `env_file` paths and bind-mount sources in `volumes` are
+/// rewritten when they leave `component_dir`, or always when `copied` is false; with `copied` the files below
+/// the component are copied next to the output, where the unchanged path finds them. Local `build` contexts
+/// are rewritten with `build_contexts`. Both directories are absolute. Returns the number of rewritten paths.
+pub fn rebase_relative_paths(document: &mut Value, component_dir: &Path, output_dir: &Path, copied: bool, build_contexts: bool) -> usize {
+    let mut rewritten = 0;
+    let mut rebase = |path: &mut String, always: bool| {
+        let target = normalize_path(&component_dir.join(&*path));
+        if always || !copied || !target.starts_with(component_dir) {
+            *path = relative_path(&target, output_dir).to_string_lossy().to_string();
+            rewritten += 1;
+        }
+    };
+
+    for service in services_mut(document) {
+        if build_contexts {
+            if let Some(context) = build_context_mut(service) {
+                if is_local_context(context) && Path::new(context.as_str()).is_relative() {
+                    rebase(context, true);
+                }
+            }
+        }
+
+        let env_files: Vec<&mut Value> = match service.get_mut("env_file") {
+            Some(Value::Sequence(entries)) => entries.iter_mut()
+                .filter_map(|entry| match entry {
+                    Value::Mapping(long_form) => long_form.get_mut("path"),
+                    path => Some(path),
+                })
+                .collect(),
+            Some(path) => vec![path],
+            None => Vec::new(),
+        };
+        for path in env_files {
+            if let Value::String(path) = path {
+                if Path::new(path.as_str()).is_relative() {
+                    rebase(path, false);
+                }
+            }
+        }
+
+        if let Some(Value::Sequence(volumes)) = service.get_mut("volumes") {
+            for volume in volumes {
+                match volume {
+                    // `./data:/data:ro`; named volumes and absolute paths don't start with a dot
+                    Value::String(volume) if volume.starts_with('.') => {
+                        let (source, target) = volume.split_once(':').unwrap_or((volume.as_str(), ""));
+                        let mut source = source.to_string();
+                        let target = target.to_string();
+                        rebase(&mut source, false);
+                        *volume = if target.is_empty() { source } else { format!("{}:{}", source, target) };
+                    }
+                    Value::Mapping(long_form) if long_form.get("type").and_then(Value::as_str) == Some("bind") => {
+                        if let Some(Value::String(source)) = long_form.get_mut("source") {
+                            if Path::new(source.as_str()).is_relative() {
+                                rebase(source, false);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    rewritten
+}
+
+/// `path` with `.` and `..` components removed, without touching the filesystem
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Path of `path` relative to the directory `base`; both are absolute
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    relative.extend(&path[common..]);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+    relative
+}
+
+/// Merge variables from each service's `env_file` entries into its `environment` and drop `env_file`.
+/// Explicit `environment` entries win; later env files override earlier ones.
+fn inline_env_files(document: &mut Value) -> Result<()> {
+    for service in services_mut(document) {
+        let env_files = match service.remove("env_file") {
+            Some(Value::Sequence(entries)) => entries,
+            Some(entry) => vec![entry],
+            None => continue,
+        };
+
+        let mut variables: Vec<(String, String)> = Vec::new();
+        for entry in env_files {
+            let (path, required) = match entry {
+                Value::String(path) => (path, true),
+                Value::Mapping(long_form) => {
+                    let path = long_form.get("path").and_then(Value::as_str).unwrap_or_default().to_string();
+                    let required = long_form.get("required").and_then(Value::as_bool).unwrap_or(true);
+                    (path, required)
+                }
+                _ => continue,
+            };
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(FileSystemError::FileReadFailed {
+                        path: path.into(),
+                        source: e,
+                    }.into());
+                }
+            };
+
+            for (key, value) in parse_env_file(&content) {
+                variables.retain(|(existing, _)| *existing != key);
+                variables.push((key, value));
+            }
+        }
+
+        let environment = service.entry(Value::from("environment"))
+            .or_insert_with(|| Value::Mapping(serde_yaml_ng::Mapping::new()));
+        match environment {
+            Value::Mapping(entries) => {
+                for (key, value) in variables {
+                    if !entries.contains_key(key.as_str()) {
+                        entries.insert(Value::from(key), Value::from(value));
+                    }
+                }
+            }
+            Value::Sequence(entries) => {
+                let defined: Vec<String> = entries.iter()
+                    .filter_map(Value::as_str)
+                    .map(|entry| entry.split('=').next().unwrap_or(entry).to_string())
+                    .collect();
+                for (key, value) in variables {
+                    if !defined.contains(&key) {
+                        entries.push(Value::from(format!("{}={}", key, value)));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Service keys whose entries are names, so `networks: { backend: {} }` attaches a network and
+/// `depends_on: { db: {} }` declares a dependency even though the entry itself is empty
+const NAMED_ENTRY_KEYS: &[&str] = &["networks", "depends_on", "models"];
+
+/// Remove keys whose value is an empty mapping or sequence, including ones emptied by the removal
+/// of their own children. Top-level sections and the entries directly in them (services,
+/// named volumes, networks) are kept, since `volumes: { data: {} }` declares a volume, and so are
+/// the entries of [`NAMED_ENTRY_KEYS`] inside a service.
+pub fn prune_empty_collections(document: &mut Value) {
+    let Some(sections) = document.as_mapping_mut() else { return };
+    for section in sections.values_mut() {
+        if let Some(entries) = section.as_mapping_mut() {
+            for entry in entries.values_mut() {
+                prune_empty_children(entry);
+            }
+        }
+    }
+}
+
+/// Prune empty collections below `value`; returns whether `value` itself is now empty
+fn prune_empty_children(value: &mut Value) -> bool {
+    match value {
+        Value::Mapping(map) => {
+            map.retain(|key, child| match child.as_mapping_mut() {
+                Some(entries) if key.as_str().is_some_and(|key| NAMED_ENTRY_KEYS.contains(&key)) => {
+                    for entry in entries.values_mut() {
+                        prune_empty_children(entry);
+                    }
+                    !entries.is_empty()
+                }
+                _ => !prune_empty_children(child),
+            });
+            map.is_empty()
+        }
+        Value::Sequence(seq) => {
+            seq.retain_mut(|item| !prune_empty_children(item));
+            seq.is_empty()
+        }
+        Value::Tagged(tagged) => prune_empty_children(&mut tagged.value),
+        _ => false,
+    }
+}
+
+/// A service that two extension compose files define with different values at the same paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceConflict {
+    pub service: String,
+    /// The earlier of the two files in merge order
+    pub first: String,
+    pub second: String,
+    /// Paths inside the service whose values differ, e.g. `image` or `environment.POSTGRES_DB`
+    pub paths: Vec<String>,
+}
+
+/// Services that two of the compose `files` both define with different scalar values, or different `ports`, at the
+/// same path. A later file that only adds keys, or marks a value `!override` or `!reset`, does not conflict.
+/// Files that cannot be loaded are skipped; the merge reports them.
+pub fn find_service_conflicts(files: &[String]) -> Vec<ServiceConflict> {
+    let documents: Vec<(&String, Value)> = files.iter()
+        .filter_map(|file| load_compose_file(file).ok().map(|document| (file, document)))
+        .collect();
+    let mut conflicts = Vec::new();
+    for (index, (first, earlier)) in documents.iter().enumerate() {
+        for (second, later) in &documents[index + 1..] {
+            let Some(earlier_services) = earlier.get("services").and_then(Value::as_mapping) else { continue };
+            let Some(later_services) = later.get("services").and_then(Value::as_mapping) else { continue };
+            for (name, later_service) in later_services {
+                let Some(earlier_service) = earlier_services.get(name) else { continue };
+                let mut paths = Vec::new();
+                conflicting_paths(earlier_service, later_service, "", &mut paths);
+                if !paths.is_empty() {
+                    conflicts.push(ServiceConflict {
+                        service: scalar_string(name),
+                        first: first.to_string(),
+                        second: second.to_string(),
+                        paths,
+                    });
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+/// Collect the paths below `path` where `later` would replace a different value of `earlier`
+fn conflicting_paths(earlier: &Value, later: &Value, path: &str, paths: &mut Vec<String>) {
+    if merge_tag(later).is_some() {
+        return;
+    }
+    match (earlier, later) {
+        (Value::Mapping(earlier), Value::Mapping(later)) => {
+            for (key, value) in later {
+                if let Some(earlier_value) = earlier.get(key) {
+                    let key = scalar_string(key);
+                    let path = if path.is_empty() { key } else { format!("{}.{}", path, key) };
+                    conflicting_paths(earlier_value, value, &path, paths);
+                }
+            }
+        }
+        (Value::Sequence(earlier), Value::Sequence(later)) if path == "ports" && ports::ports_collide(earlier, later) => {
+            paths.push(path.to_string());
+        }
+        // Other lists, such as volumes or environment lists, are combined rather than replaced
+        (Value::Sequence(_), Value::Sequence(_)) => {}
+        (earlier, later) if earlier != later => paths.push(path.to_string()),
+        _ => {}
+    }
+}
+
+/// Compose file of `extension` in the first extensions directory that has one
+pub fn extension_compose_file(merger: &ComposeMerger, extension: &str) -> Option<PathBuf> {
+    merger.extensions_paths.iter()
+        .map(|ext_dir| Path::new(ext_dir).join(extension).join("docker-compose.yml"))
+        .find(|ext_file| ext_file.exists())
+}
 
 /// Resolve the order of files to merge based on environment and extensions
 pub fn resolve_merge_order(
@@ -132,9 +859,11 @@ pub fn resolve_merge_order(
 ) -> Result<Vec<String>> {
     let mut file_paths = Vec::new();
 
-    // Always start with base
+    // Start with base unless it is optional and missing
     let base_file = Path::new(&merger.base_path).join("docker-compose.yml");
-    file_paths.push(base_file.to_string_lossy().to_string());
+    if merger.require_base || base_file.exists() {
+        file_paths.push(base_file.to_string_lossy().to_string());
+    }
 
     // Add environment file if specified
     if let Some(env) = environment {
@@ -144,20 +873,11 @@ pub fn resolve_merge_order(
         file_paths.push(env_file.to_string_lossy().to_string());
     }
 
-    // Add extension files in order
+    // Add extension files in order, each from the first extensions directory that has it
     for ext in extensions {
-        let mut found = false;
-        for ext_dir in &merger.extensions_paths {
-            let ext_file = Path::new(ext_dir).join(ext).join("docker-compose.yml");
-            if ext_file.exists() {
-                file_paths.push(ext_file.to_string_lossy().to_string());
-                found = true;
-                break; // Found in first matching directory
-            }
-        }
-        
-        if !found {
-            println!("Warning: Extension '{}' not found in any extensions directory", ext);
+        match extension_compose_file(merger, ext) {
+            Some(ext_file) => file_paths.push(ext_file.to_string_lossy().to_string()),
+            None => diagnostics::warn(WarningCategory::MissingFile, format!("Extension '{}' not found in any extensions directory", ext)),
         }
     }
 
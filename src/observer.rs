@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use crate::build::BuildCombination;
+use crate::build_report::{BuildTimings, CombinationTimings};
+use crate::error::{combination_label, StackBuilderError};
+use crate::diagnostics::{self, Warning};
+
+/// Stage of a build. The stages of generating a combination are reported by [`BuildObserver::on_phase`],
+/// the others by [`BuildObserver::on_build_phase`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildPhase {
+    /// Loading the configuration
+    Config,
+    /// Running `pre_build_command`
+    PreBuild,
+    /// Resolving paths, validating the configuration and determining the combinations
+    Validation,
+    /// Preparing the staging directory, and replacing the build directory with it
+    Cleanup,
+    /// Merging the compose files of a combination
+    Merging,
+    /// Merging the .env.example files of a combination and writing the variables
+    Env,
+    /// Copying the additional files of a combination's components
+    Copying,
+    /// Restoring preserved .env files into the new build
+    Restore,
+    /// Writing the manifests, checksums, build cache and summary
+    Finish,
+}
+
+impl BuildPhase {
+    /// The stages of generating a single combination
+    pub const COMBINATION: [BuildPhase; 3] = [BuildPhase::Merging, BuildPhase::Env, BuildPhase::Copying];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BuildPhase::Config => "config",
+            BuildPhase::PreBuild => "pre_build",
+            BuildPhase::Validation => "validation",
+            BuildPhase::Cleanup => "cleanup",
+            BuildPhase::Merging => "merging",
+            BuildPhase::Env => "env",
+            BuildPhase::Copying => "copying",
+            BuildPhase::Restore => "restore",
+            BuildPhase::Finish => "finish",
+        }
+    }
+}
+
+/// Receives build progress as events. Every callback does nothing by default.
+/// `on_file_written` may be called from IO threads when `--parallel-io` is used.
+pub trait BuildObserver: Send + Sync {
+    /// The build entered a stage that does not belong to a single combination
+    fn on_build_phase(&self, _phase: BuildPhase) {}
+
+    /// The combinations of the build are known and about to be generated
+    fn on_build_start(&self, _combinations: &[BuildCombination]) {}
+
+    /// A combination is about to be merged and written
+    fn on_combination_start(&self, _combination: &BuildCombination) {}
+
+    /// A combination that is generated rather than reused from the build cache entered `phase`
+    fn on_phase(&self, _combination: &BuildCombination, _phase: BuildPhase) {}
+
+    /// A generated file (compose file, .env.example or additional file) was written into the staging directory
+    fn on_file_written(&self, _path: &Path) {}
+
+    /// A warning was emitted
+    fn on_warning(&self, _warning: &Warning) {}
+
+    /// All outputs of a combination were generated
+    fn on_combination_done(&self, _combination: &BuildCombination, _duration: Duration) {}
+
+    /// A combination failed and the build goes on with the next one (`keep_going`); its outputs are discarded.
+    /// Without `keep_going` a failure ends the build, which is reported by `on_build_done` alone.
+    fn on_combination_failed(&self, _combination: &BuildCombination, _error: &StackBuilderError, _duration: Duration) {}
+
+    /// The build finished, whether or not it succeeded
+    fn on_build_done(&self) {}
+
+    /// Whether the observer renders progress itself; the line-based output of the combinations is held back
+    /// while they are generated, and the rest of the build output goes to `print_line`
+    fn draws_progress(&self) -> bool {
+        false
+    }
+
+    /// Print a line of build output, for observers that draw progress
+    fn print_line(&self, line: &str) {
+        println!("{}", line);
+    }
+}
+
+/// Prints progress the way the CLI always has, unless a quiet library build is running
+pub struct ConsoleObserver;
+
+impl BuildObserver for ConsoleObserver {
+    fn on_combination_start(&self, combination: &BuildCombination) {
+        status!("Processing combination: {:?}", combination.output_dir);
+    }
+
+    fn on_warning(&self, warning: &Warning) {
+        status!("Warning: {}", warning.message);
+    }
+}
+
+/// Draws a bar over the combinations and a spinner with the phase of the current one, for terminals.
+/// Warnings are printed above the bars.
+pub struct ProgressObserver {
+    progress: MultiProgress,
+    combinations: ProgressBar,
+    current: ProgressBar,
+}
+
+impl ProgressObserver {
+    pub fn new() -> Self {
+        let progress = MultiProgress::with_draw_target(ProgressDrawTarget::stdout());
+        let combinations = progress.add(ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("[{elapsed_precise}] {bar:40} {pos}/{len} combinations")
+                .expect("valid progress template"),
+        ));
+        let current = progress.add(ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {msg}").expect("valid progress template"),
+        ));
+        current.enable_steady_tick(Duration::from_millis(100));
+        Self { progress, combinations, current }
+    }
+}
+
+impl Default for ProgressObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildObserver for ProgressObserver {
+    fn on_build_start(&self, combinations: &[BuildCombination]) {
+        self.combinations.set_length(combinations.len() as u64);
+    }
+
+    fn on_build_phase(&self, phase: BuildPhase) {
+        self.current.set_message(phase.name());
+    }
+
+    fn on_combination_start(&self, combination: &BuildCombination) {
+        self.current.set_message(combination_label(&combination.output_dir).to_string());
+    }
+
+    fn on_phase(&self, combination: &BuildCombination, phase: BuildPhase) {
+        self.current.set_message(format!("{}: {}", combination_label(&combination.output_dir), phase.name()));
+    }
+
+    fn on_warning(&self, warning: &Warning) {
+        // Nothing to do when the bars cannot be drawn, as the line-based output has no other place for it
+        let _ = self.progress.println(format!("Warning: {}", warning.message));
+    }
+
+    fn on_combination_done(&self, _combination: &BuildCombination, _duration: Duration) {
+        self.combinations.inc(1);
+    }
+
+    fn on_combination_failed(&self, _combination: &BuildCombination, error: &StackBuilderError, _duration: Duration) {
+        // The line-based output is held back while the bars are drawn, so the failure goes above them
+        let _ = self.progress.println(format!("✗ {}", error));
+        self.combinations.inc(1);
+    }
+
+    fn on_build_done(&self) {
+        self.current.finish_and_clear();
+        self.combinations.finish_and_clear();
+    }
+
+    fn draws_progress(&self) -> bool {
+        true
+    }
+
+    fn print_line(&self, line: &str) {
+        // Printed above the bars, so they are not torn apart
+        let _ = self.progress.println(line);
+    }
+}
+
+/// Writes GitHub Actions workflow commands: warnings become `::warning` annotations on their file,
+/// and the output of every combination is folded into a `::group::`
+#[derive(Default)]
+pub struct GithubObserver {
+    group_open: AtomicBool,
+    /// Where the commands go instead of stdout
+    output: Option<Mutex<Box<dyn std::io::Write + Send>>>,
+}
+
+impl GithubObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the workflow commands to `output` instead of stdout
+    pub fn with_output(mut self, output: impl std::io::Write + Send + 'static) -> Self {
+        self.output = Some(Mutex::new(Box::new(output)));
+        self
+    }
+
+    fn command(&self, line: String) {
+        match self.output {
+            Some(ref output) => {
+                let _ = writeln!(output.lock().unwrap(), "{}", line);
+            }
+            None => status!("{}", line),
+        }
+    }
+
+    /// Groups do not nest, so a failed combination's group is closed by the next one or the end of the build
+    fn end_group(&self) {
+        if self.group_open.swap(false, Ordering::SeqCst) {
+            self.command(diagnostics::github_command("endgroup", &[], ""));
+        }
+    }
+}
+
+impl BuildObserver for GithubObserver {
+    fn on_combination_start(&self, combination: &BuildCombination) {
+        self.end_group();
+        self.command(diagnostics::github_command("group", &[], &format!("Combination {}", combination_label(&combination.output_dir))));
+        self.group_open.store(true, Ordering::SeqCst);
+    }
+
+    fn on_warning(&self, warning: &Warning) {
+        let mut properties = vec![("title", format!("stackbuilder {}", warning.category))];
+        if let Some(ref path) = warning.path {
+            properties.insert(0, ("file", diagnostics::annotation_path(path)));
+        }
+        self.command(diagnostics::github_command("warning", &properties, &warning.message));
+    }
+
+    fn on_combination_done(&self, _combination: &BuildCombination, _duration: Duration) {
+        self.end_group();
+    }
+
+    // The failure is annotated with `::error` once the build fails with all of them
+    fn on_combination_failed(&self, _combination: &BuildCombination, _error: &StackBuilderError, _duration: Duration) {
+        self.end_group();
+    }
+
+    fn on_build_done(&self) {
+        self.end_group();
+    }
+}
+
+/// Where the time went while recording, see [`TimingObserver`]
+struct TimingState {
+    started: Instant,
+    build_phase: Option<(BuildPhase, Instant)>,
+    combination: Option<(CombinationTimings, Option<(BuildPhase, Instant)>)>,
+    phases: BTreeMap<&'static str, Duration>,
+    combinations: Vec<CombinationTimings>,
+}
+
+/// Measures how long the phases of a build and of every combination take (`--timings`) and passes
+/// every event on to the observer it wraps. A phase lasts until the next one of its kind starts.
+pub struct TimingObserver {
+    inner: Arc<dyn BuildObserver>,
+    state: Mutex<TimingState>,
+}
+
+impl TimingObserver {
+    pub fn new(inner: Arc<dyn BuildObserver>) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(TimingState {
+                started: Instant::now(),
+                build_phase: None,
+                combination: None,
+                phases: BTreeMap::new(),
+                combinations: Vec::new(),
+            }),
+        }
+    }
+
+    /// The timings recorded so far; the total is the time since the observer was created, and `other` the part of
+    /// it outside every phase, such as writing the outputs queued by `--parallel-io` or kompose conversions
+    pub fn timings(&self) -> BuildTimings {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let total = state.started.elapsed();
+        let other = total.saturating_sub(state.phases.values().sum());
+        BuildTimings {
+            total_ms: total.as_millis() as u64,
+            phases: state.phases.iter()
+                .map(|(phase, duration)| (phase.to_string(), duration.as_millis() as u64))
+                .chain(std::iter::once(("other".to_string(), other.as_millis() as u64)))
+                .collect(),
+            combinations: state.combinations.clone(),
+        }
+    }
+
+    fn record(&self, update: impl FnOnce(&mut TimingState)) {
+        update(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+impl TimingState {
+    fn end_build_phase(&mut self, next: Option<BuildPhase>) {
+        let now = Instant::now();
+        if let Some((phase, started)) = self.build_phase.take() {
+            *self.phases.entry(phase.name()).or_default() += now - started;
+        }
+        self.build_phase = next.map(|phase| (phase, now));
+    }
+
+    fn end_combination_phase(&mut self, next: Option<BuildPhase>) {
+        let now = Instant::now();
+        let Some((ref mut combination, ref mut current)) = self.combination else { return };
+        if let Some((phase, started)) = current.take() {
+            let duration = now - started;
+            *combination.phases.entry(phase.name().to_string()).or_default() += duration.as_millis() as u64;
+            *self.phases.entry(phase.name()).or_default() += duration;
+        }
+        *current = next.map(|phase| (phase, now));
+    }
+
+    fn end_combination(&mut self, duration: Duration, failed: bool) {
+        self.end_combination_phase(None);
+        if let Some((mut timings, _)) = self.combination.take() {
+            timings.duration_ms = duration.as_millis() as u64;
+            timings.failed = failed;
+            self.combinations.push(timings);
+        }
+    }
+}
+
+impl BuildObserver for TimingObserver {
+    fn on_build_phase(&self, phase: BuildPhase) {
+        self.record(|state| state.end_build_phase(Some(phase)));
+        self.inner.on_build_phase(phase);
+    }
+
+    fn on_build_start(&self, combinations: &[BuildCombination]) {
+        self.record(|state| state.end_build_phase(None));
+        self.inner.on_build_start(combinations);
+    }
+
+    fn on_combination_start(&self, combination: &BuildCombination) {
+        self.record(|state| {
+            state.combination = Some((CombinationTimings {
+                output_dir: combination.output_dir.clone(),
+                ..Default::default()
+            }, None));
+        });
+        self.inner.on_combination_start(combination);
+    }
+
+    fn on_phase(&self, combination: &BuildCombination, phase: BuildPhase) {
+        self.record(|state| state.end_combination_phase(Some(phase)));
+        self.inner.on_phase(combination, phase);
+    }
+
+    fn on_file_written(&self, path: &Path) {
+        self.inner.on_file_written(path);
+    }
+
+    fn on_warning(&self, warning: &Warning) {
+        self.inner.on_warning(warning);
+    }
+
+    fn on_combination_done(&self, combination: &BuildCombination, duration: Duration) {
+        self.record(|state| state.end_combination(duration, false));
+        self.inner.on_combination_done(combination, duration);
+    }
+
+    fn on_combination_failed(&self, combination: &BuildCombination, error: &StackBuilderError, duration: Duration) {
+        self.record(|state| state.end_combination(duration, true));
+        self.inner.on_combination_failed(combination, error, duration);
+    }
+
+    fn on_build_done(&self) {
+        self.record(|state| state.end_build_phase(None));
+        self.inner.on_build_done();
+    }
+
+    fn draws_progress(&self) -> bool {
+        self.inner.draws_progress()
+    }
+
+    fn print_line(&self, line: &str) {
+        self.inner.print_line(line);
+    }
+}
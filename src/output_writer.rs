@@ -0,0 +1,313 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use crate::diagnostics;
+use crate::error::{Result, StackBuilderError, BuildError, FileSystemError};
+use crate::observer::BuildObserver;
+
+/// Upper bound for IO threads; the write phase is limited by the filesystem, not the CPU
+const MAX_IO_THREADS: usize = 4;
+
+/// A single file operation of the write phase
+enum WriteJob {
+    Write { path: PathBuf, content: Vec<u8>, executable: bool },
+    Copy { source: PathBuf, dest: PathBuf },
+}
+
+impl WriteJob {
+    /// Perform the operation and return the written path. When `previous`, the file this one replaces,
+    /// has the same content, the written file keeps its modification time.
+    fn run(self, previous: Option<&Path>) -> Result<PathBuf> {
+        let unchanged = previous.filter(|previous| match &self {
+            WriteJob::Write { content, .. } => fs::read(previous).is_ok_and(|existing| existing == *content),
+            WriteJob::Copy { source, .. } => same_content(source, previous),
+        });
+        let path = self.write()?;
+        if let Some(previous) = unchanged {
+            keep_modified_time(previous, &path)
+                .map_err(|e| FileSystemError::FileWriteFailed { path: path.clone(), source: e })?;
+            status!("  unchanged: {}", previous.display());
+        }
+        Ok(path)
+    }
+
+    fn write(self) -> Result<PathBuf> {
+        match self {
+            WriteJob::Write { path, content, executable } => {
+                if let Err(e) = fs::write(&path, content) {
+                    return Err(BuildError::OutputFileWriteError { path, source: e }.into());
+                }
+                #[cfg(unix)]
+                if executable {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                        .map_err(|e| FileSystemError::FileWriteFailed { path: path.clone(), source: e })?;
+                }
+                #[cfg(not(unix))]
+                let _ = executable;
+                Ok(path)
+            }
+            WriteJob::Copy { source, dest } => {
+                fs::copy(&source, &dest)
+                    .map_err(|e| FileSystemError::FileWriteFailed { path: dest.clone(), source: e })?;
+
+                // Preserve permissions on Unix systems
+                #[cfg(unix)]
+                {
+                    if let Ok(metadata) = fs::metadata(&source) {
+                        let _ = fs::set_permissions(&dest, metadata.permissions());
+                    }
+                }
+                Ok(dest)
+            }
+        }
+    }
+
+    /// Run the job and report the written file to the observer
+    fn run_observed(self, previous: Option<&Path>, observer: Option<&Arc<dyn BuildObserver>>) -> Result<()> {
+        let path = self.run(previous)?;
+        if let Some(observer) = observer {
+            observer.on_file_written(&path);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the files at `a` and `b` can both be read and have the same bytes
+fn same_content(a: &Path, b: &Path) -> bool {
+    let (Ok(a_metadata), Ok(b_metadata)) = (fs::metadata(a), fs::metadata(b)) else { return false };
+    a_metadata.len() == b_metadata.len() && matches!((fs::read(a), fs::read(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Give `path` the modification time of `previous`, for a file carried over into a new build unchanged,
+/// so watchers and tools comparing mtimes do not see it as modified
+pub fn keep_modified_time(previous: &Path, path: &Path) -> io::Result<()> {
+    let modified = fs::metadata(previous)?.modified()?;
+    // Setting times needs write access to the handle on Windows, owning the file is enough elsewhere
+    let file = match cfg!(windows) {
+        true => fs::File::options().write(true).open(path)?,
+        false => fs::File::open(path)?,
+    };
+    file.set_modified(modified)
+}
+
+/// Writes generated files either immediately or through a small pool of IO threads (`--parallel-io`).
+/// Contents are handed over as complete buffers, so every file is written with a single call.
+/// Parent directories must exist before a file is queued.
+pub struct OutputWriter {
+    sender: Option<Sender<(WriteJob, Option<PathBuf>)>>,
+    workers: Vec<JoinHandle<Option<StackBuilderError>>>,
+    observer: Option<Arc<dyn BuildObserver>>,
+    /// Staging directory and the build directory it replaces, see `with_previous_build`
+    previous_build: Option<(PathBuf, PathBuf)>,
+    /// Files queued, written or reported since the last `take_written`
+    written_paths: Mutex<Vec<PathBuf>>,
+}
+
+impl OutputWriter {
+    /// Writer for the build: threaded with `parallel_io`, synchronous otherwise.
+    /// Every written file is reported to `observer`.
+    pub fn new(parallel_io: bool, observer: Option<Arc<dyn BuildObserver>>) -> Self {
+        if parallel_io {
+            let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_IO_THREADS);
+            Self::spawn(threads, observer)
+        } else {
+            Self { sender: None, workers: Vec::new(), observer, previous_build: None, written_paths: Mutex::default() }
+        }
+    }
+
+    /// Write every file before returning
+    #[cfg(test)]
+    pub fn sync() -> Self {
+        Self { sender: None, workers: Vec::new(), observer: None, previous_build: None, written_paths: Mutex::default() }
+    }
+
+    /// Compare files written below `staging_dir` with their counterparts in `build_dir`, which the staging
+    /// directory is going to replace: a file with the same content is logged as unchanged and keeps its
+    /// modification time
+    pub fn with_previous_build(mut self, staging_dir: &Path, build_dir: &Path) -> Self {
+        self.previous_build = Some((staging_dir.to_path_buf(), build_dir.to_path_buf()));
+        self
+    }
+
+    /// Queue files to `threads` IO threads; failures are reported by `finish`
+    #[cfg(test)]
+    pub fn parallel(threads: usize) -> Self {
+        Self::spawn(threads, None)
+    }
+
+    fn spawn(threads: usize, observer: Option<Arc<dyn BuildObserver>>) -> Self {
+        let (sender, receiver) = mpsc::channel::<(WriteJob, Option<PathBuf>)>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        // Quiet builds stay quiet in the IO threads
+        let quiet = diagnostics::is_quiet();
+
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let observer = observer.clone();
+                thread::spawn(move || {
+                    let _quiet = quiet.then(diagnostics::quiet);
+                    let mut first_error = None;
+                    loop {
+                        let job = match receiver.lock() {
+                            Ok(receiver) => receiver.recv(),
+                            Err(_) => break,
+                        };
+                        let Ok((job, previous)) = job else { break };
+                        if let Err(e) = job.run_observed(previous.as_deref(), observer.as_ref()) {
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                    first_error
+                })
+            })
+            .collect();
+
+        Self { sender: Some(sender), workers, observer, previous_build: None, written_paths: Mutex::default() }
+    }
+
+    /// Write `content` to `path`
+    pub fn write(&self, path: &Path, content: impl Into<Vec<u8>>) -> Result<()> {
+        self.submit(WriteJob::Write { path: path.to_path_buf(), content: content.into(), executable: false })
+    }
+
+    /// Write `content` to `path` and make it executable on Unix
+    pub fn write_executable(&self, path: &Path, content: impl Into<Vec<u8>>) -> Result<()> {
+        self.submit(WriteJob::Write { path: path.to_path_buf(), content: content.into(), executable: true })
+    }
+
+    /// Copy `source` to `dest`, keeping its permissions
+    pub fn copy(&self, source: &Path, dest: &Path) -> Result<()> {
+        self.submit(WriteJob::Copy { source: source.to_path_buf(), dest: dest.to_path_buf() })
+    }
+
+    fn submit(&self, job: WriteJob) -> Result<()> {
+        let path = match &job {
+            WriteJob::Write { path, .. } => path,
+            WriteJob::Copy { dest, .. } => dest,
+        };
+        let previous = self.previous_build.as_ref()
+            .and_then(|(staging_dir, build_dir)| Some(build_dir.join(path.strip_prefix(staging_dir).ok()?)));
+        self.record(path);
+        match self.sender {
+            Some(ref sender) => sender.send((job, previous)).map_err(|_| BuildError::BuildProcessFailed {
+                details: "IO threads stopped before all files were written".to_string(),
+            }.into()),
+            None => job.run_observed(previous.as_deref(), self.observer.as_ref()),
+        }
+    }
+
+    /// Report a file written without this writer, e.g. a hardlink, to the observer
+    pub fn written(&self, path: &Path) {
+        self.record(path);
+        if let Some(ref observer) = self.observer {
+            observer.on_file_written(path);
+        }
+    }
+
+    /// The files queued, written or reported since the previous call, e.g. those of a combination that
+    /// failed and must be removed again once `finish` returned
+    pub fn take_written(&self) -> Vec<PathBuf> {
+        self.written_paths.lock().map(|mut paths| std::mem::take(&mut *paths)).unwrap_or_default()
+    }
+
+    fn record(&self, path: &Path) {
+        if let Ok(mut paths) = self.written_paths.lock() {
+            paths.push(path.to_path_buf());
+        }
+    }
+
+    /// Wait until every queued file is written, returning the first failure
+    pub fn finish(mut self) -> Result<()> {
+        match self.join() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn join(&mut self) -> Option<StackBuilderError> {
+        // Closing the channel lets the workers drain the queue and exit
+        self.sender = None;
+        let mut first_error = None;
+        for worker in self.workers.drain(..) {
+            let error = worker.join().unwrap_or_else(|_| Some(BuildError::BuildProcessFailed {
+                details: "IO thread panicked".to_string(),
+            }.into()));
+            if let Some(e) = error {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        // Never leave threads writing into a staging directory that is about to be discarded
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_writer_writes_and_copies_everything() {
+        let temp = tempfile::tempdir().unwrap();
+        let source = temp.path().join("source.txt");
+        fs::write(&source, "copied").unwrap();
+
+        let writer = OutputWriter::parallel(3);
+        for i in 0..50 {
+            writer.write(&temp.path().join(format!("file_{}.txt", i)), format!("content {}", i)).unwrap();
+        }
+        writer.copy(&source, &temp.path().join("copy.txt")).unwrap();
+        writer.finish().unwrap();
+
+        for i in 0..50 {
+            assert_eq!(fs::read_to_string(temp.path().join(format!("file_{}.txt", i))).unwrap(), format!("content {}", i));
+        }
+        assert_eq!(fs::read_to_string(temp.path().join("copy.txt")).unwrap(), "copied");
+    }
+
+    #[test]
+    fn test_unchanged_files_keep_their_modification_time() {
+        let temp = tempfile::tempdir().unwrap();
+        let (staging, build) = (temp.path().join("staging"), temp.path().join("build"));
+        fs::create_dir_all(&staging).unwrap();
+        fs::create_dir_all(&build).unwrap();
+        fs::write(build.join("same.yml"), "services: {}\n").unwrap();
+        fs::write(build.join("changed.yml"), "services: {}\n").unwrap();
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        for name in ["same.yml", "changed.yml"] {
+            fs::File::options().write(true).open(build.join(name)).unwrap().set_modified(old).unwrap();
+        }
+
+        let writer = OutputWriter::parallel(2).with_previous_build(&staging, &build);
+        writer.write(&staging.join("same.yml"), "services: {}\n").unwrap();
+        writer.write(&staging.join("changed.yml"), "services:\n  app: {}\n").unwrap();
+        writer.write(&staging.join("new.yml"), "services: {}\n").unwrap();
+        writer.finish().unwrap();
+
+        let modified = |name: &str| fs::metadata(staging.join(name)).unwrap().modified().unwrap();
+        assert_eq!(modified("same.yml"), old);
+        assert_ne!(modified("changed.yml"), old);
+        assert_eq!(fs::read_to_string(staging.join("changed.yml")).unwrap(), "services:\n  app: {}\n");
+        assert!(staging.join("new.yml").exists());
+    }
+
+    #[test]
+    fn test_parallel_writer_reports_failures_on_finish() {
+        let temp = tempfile::tempdir().unwrap();
+
+        let writer = OutputWriter::parallel(2);
+        writer.write(&temp.path().join("missing/file.txt"), "content").unwrap();
+        let result = writer.finish();
+        assert!(matches!(result, Err(StackBuilderError::Build(BuildError::OutputFileWriteError { .. }))), "{:?}", result);
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{absolute_path, Config};
+
+/// Absolute locations of everything a build reads and writes.
+///
+/// Computed once from a configuration and the project root it belongs to, without consulting the
+/// current directory. Nothing is required to exist: missing directories are reported by validation
+/// and skipped or rejected by the mergers when they are used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedPaths {
+    /// Directory relative configured paths are resolved against
+    pub project_root: PathBuf,
+    /// Components roots in search order: `components_dir`, then `components_dirs`
+    pub components_roots: Vec<PathBuf>,
+    /// Base component, taken from the first components root that has it
+    pub base_dir: PathBuf,
+    /// Environments directory, taken from the first components root that has it
+    pub environments_dir: PathBuf,
+    /// Extension directories of `components_dir`, followed by the existing ones of further roots
+    pub extensions_dirs: Vec<PathBuf>,
+    pub build_dir: PathBuf,
+    pub backup_dir: PathBuf,
+}
+
+impl ResolvedPaths {
+    /// Resolve the paths of `config` against `project_root`
+    pub fn new(config: &Config, project_root: &Path) -> Self {
+        let project_root = absolute_path(project_root);
+        let absolute = |path: &str| absolute_path(&project_root.join(path));
+
+        let components_roots: Vec<PathBuf> = std::iter::once(&config.paths.components_dir)
+            .chain(&config.paths.components_dirs)
+            .map(|dir| absolute(dir))
+            .collect();
+        let components_dir = &components_roots[0];
+
+        // Base and environments come from the first components root that has them
+        let first_existing = |relative: &str| components_roots.iter()
+            .map(|root| root.join(relative))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| components_dir.join(relative));
+
+        // Extensions of further roots are searched after those of components_dir; the first match wins
+        let mut extensions_dirs: Vec<PathBuf> = config.paths.extensions_dirs.iter()
+            .map(|dir| absolute_path(&components_dir.join(dir)))
+            .collect();
+        for root in &components_roots[1..] {
+            // Absolute entries are already resolved, from a configuration these paths were applied to
+            extensions_dirs.extend(config.paths.extensions_dirs.iter()
+                .filter(|dir| Path::new(dir).is_relative())
+                .map(|dir| root.join(dir))
+                .filter(|path| path.exists())
+                .map(|path| absolute_path(&path)));
+        }
+
+        Self {
+            base_dir: absolute_path(&first_existing(&config.paths.base_dir)),
+            environments_dir: absolute_path(&first_existing(&config.paths.environments_dir)),
+            extensions_dirs,
+            build_dir: absolute(&config.paths.build_dir),
+            backup_dir: absolute(&config.build.backup_dir),
+            components_roots,
+            project_root,
+        }
+    }
+
+    /// Directories no build writes into or wipes: the further components roots as a whole, and the base,
+    /// environments and extension directories, so `components_dir` itself may hold the outputs, e.g. with
+    /// `components_dir = "."`
+    pub fn read_only_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = self.components_roots[1..].to_vec();
+        dirs.extend([self.base_dir.clone(), self.environments_dir.clone()]);
+        dirs.extend(self.extensions_dirs.iter().cloned());
+        dirs
+    }
+
+    /// The primary components root, `components_dir`
+    pub fn components_dir(&self) -> &Path {
+        &self.components_roots[0]
+    }
+
+    /// Base, environments and extension directories in the form the merger constructors take
+    pub fn merger_paths(&self) -> (String, String, Vec<String>) {
+        (
+            self.base_dir.to_string_lossy().to_string(),
+            self.environments_dir.to_string_lossy().to_string(),
+            self.extensions_dirs.iter().map(|dir| dir.to_string_lossy().to_string()).collect(),
+        )
+    }
+
+    /// Write the resolved paths back into `config`, for code that reads them from the configuration
+    pub fn apply_to(&self, config: &mut Config) {
+        let string = |path: &Path| path.to_string_lossy().to_string();
+        config.paths.components_dir = string(self.components_dir());
+        config.paths.components_dirs = self.components_roots[1..].iter().map(|root| string(root)).collect();
+        config.paths.base_dir = string(&self.base_dir);
+        config.paths.environments_dir = string(&self.environments_dir);
+        config.paths.extensions_dirs = self.extensions_dirs.iter().map(|dir| string(dir)).collect();
+        config.paths.build_dir = string(&self.build_dir);
+        config.build.backup_dir = string(&self.backup_dir);
+    }
+}
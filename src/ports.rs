@@ -0,0 +1,183 @@
+use serde_yaml_ng::{Mapping, Value};
+use crate::config::PortStyle;
+
+/// Protocol of a port mapping that names none
+const DEFAULT_PROTOCOL: &str = "tcp";
+
+/// A service `ports` entry in either syntax, reduced to what identifies the mapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortMapping {
+    pub host_ip: Option<String>,
+    /// Host port or range; `None` lets the engine pick one
+    pub published: Option<String>,
+    /// Container port or range
+    pub target: String,
+    pub protocol: String,
+    /// Further keys of the long syntax, such as `mode`, `name` or `app_protocol`, which the short syntax cannot express
+    pub options: Mapping,
+}
+
+impl PortMapping {
+    /// Parse a `ports` entry: `[[HOST_IP:]PUBLISHED:]TARGET[/PROTOCOL]` with IPv6 addresses in brackets,
+    /// a bare port number, or a mapping with `target`, `published`, `host_ip`, `protocol` and other keys
+    pub fn parse(port: &Value) -> Option<Self> {
+        match port {
+            Value::Number(target) => Some(Self::new(None, None, target.to_string(), DEFAULT_PROTOCOL)),
+            Value::String(port) => Self::parse_short(port),
+            Value::Mapping(port) => Self::parse_long(port),
+            _ => None,
+        }
+    }
+
+    fn new(host_ip: Option<&str>, published: Option<&str>, target: String, protocol: &str) -> Self {
+        Self {
+            host_ip: host_ip.filter(|host_ip| !host_ip.is_empty()).map(|host_ip| host_ip.trim_start_matches('[').trim_end_matches(']').to_string()),
+            published: published.filter(|published| !published.is_empty()).map(str::to_string),
+            target,
+            protocol: protocol.to_ascii_lowercase(),
+            options: Mapping::new(),
+        }
+    }
+
+    fn parse_short(port: &str) -> Option<Self> {
+        // `${PORT:-80}` contains separators of its own, so interpolated entries are left as written
+        if port.contains('$') {
+            return None;
+        }
+        let (port, protocol) = port.rsplit_once('/').unwrap_or((port, DEFAULT_PROTOCOL));
+        let mut parts = port.rsplitn(3, ':');
+        let target = parts.next().filter(|target| !target.is_empty())?;
+        let published = parts.next();
+        let host_ip = parts.next();
+        Some(Self::new(host_ip, published, target.to_string(), protocol))
+    }
+
+    fn parse_long(port: &Mapping) -> Option<Self> {
+        let scalar = |key: &str| match port.get(key)? {
+            Value::String(value) => Some(value.clone()),
+            Value::Number(value) => Some(value.to_string()),
+            _ => None,
+        };
+        let mut mapping = Self::new(
+            scalar("host_ip").as_deref(),
+            scalar("published").as_deref(),
+            scalar("target")?,
+            scalar("protocol").as_deref().unwrap_or(DEFAULT_PROTOCOL),
+        );
+        mapping.options = port.iter()
+            .filter(|(key, _)| !matches!(key.as_str(), Some("target" | "published" | "host_ip" | "protocol")))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Some(mapping)
+    }
+
+    /// Whether `self` and `other` bind the same host address and port to the same container port
+    pub fn same_binding(&self, other: &Self) -> bool {
+        (&self.host_ip, &self.published, &self.target, &self.protocol) == (&other.host_ip, &other.published, &other.target, &other.protocol)
+    }
+
+    /// Whether `self` and `other` publish the same host port on overlapping addresses for different container ports
+    fn collides_with(&self, other: &Self) -> bool {
+        let same_address = match (&self.host_ip, &other.host_ip) {
+            (Some(host_ip), Some(other_ip)) => host_ip == other_ip,
+            _ => true,
+        };
+        self.published.is_some() && self.published == other.published && self.protocol == other.protocol
+            && same_address && self.target != other.target
+    }
+
+    /// The short syntax, e.g. `127.0.0.1:8080:80/udp`; `None` when long-syntax options would be lost
+    pub fn to_short(&self) -> Option<String> {
+        if !self.options.is_empty() {
+            return None;
+        }
+        let mut port = self.target.clone();
+        if self.published.is_some() || self.host_ip.is_some() {
+            port = format!("{}:{}", self.published.as_deref().unwrap_or(""), port);
+        }
+        if let Some(ref host_ip) = self.host_ip {
+            let host_ip = if host_ip.contains(':') { format!("[{}]", host_ip) } else { host_ip.clone() };
+            port = format!("{}:{}", host_ip, port);
+        }
+        if self.protocol != DEFAULT_PROTOCOL {
+            port = format!("{}/{}", port, self.protocol);
+        }
+        Some(port)
+    }
+
+    /// The long syntax, with ports as numbers and the default protocol left out
+    pub fn to_long(&self) -> Value {
+        let number_or_string = |value: &str| value.parse::<u64>().map_or_else(|_| Value::from(value), Value::from);
+        let mut port = Mapping::new();
+        port.insert("target".into(), number_or_string(&self.target));
+        if let Some(ref published) = self.published {
+            port.insert("published".into(), Value::from(published.as_str()));
+        }
+        if let Some(ref host_ip) = self.host_ip {
+            port.insert("host_ip".into(), Value::from(host_ip.as_str()));
+        }
+        if self.protocol != DEFAULT_PROTOCOL {
+            port.insert("protocol".into(), Value::from(self.protocol.as_str()));
+        }
+        port.extend(self.options.clone());
+        Value::Mapping(port)
+    }
+}
+
+/// Whether a binding of `later` publishes a host address and port that one of `earlier` binds to a different container port.
+/// Repeated and added bindings are intended layering; an address without `host_ip` collides with every other address.
+pub fn ports_collide(earlier: &[Value], later: &[Value]) -> bool {
+    let earlier: Vec<PortMapping> = earlier.iter().filter_map(PortMapping::parse).collect();
+    later.iter().filter_map(PortMapping::parse).any(|mapping| earlier.iter().any(|other| mapping.collides_with(other)))
+}
+
+/// Remove `ports` entries of every service that repeat the binding of an earlier entry, in either syntax,
+/// then write them in `style`. The last declaration of a binding wins and keeps the position of the first.
+/// Entries that cannot be parsed, e.g. with interpolated parts in a mapping, are only de-duplicated when identical.
+pub fn normalize_ports(document: &mut Value, style: PortStyle) {
+    let services = document.get_mut("services").and_then(Value::as_mapping_mut);
+    for service in services.into_iter().flat_map(|services| services.values_mut()) {
+        let Some(Value::Sequence(ports)) = service.get_mut("ports") else { continue };
+
+        let mut unique: Vec<(Option<PortMapping>, Value)> = Vec::new();
+        for port in ports.drain(..) {
+            let mapping = PortMapping::parse(&port);
+            let existing = unique.iter_mut().find(|(existing, value)| match (existing, &mapping) {
+                (Some(existing), Some(mapping)) => existing.same_binding(mapping),
+                _ => *value == port,
+            });
+            match existing {
+                Some(existing) => *existing = (mapping, port),
+                None => unique.push((mapping, port)),
+            }
+        }
+
+        ports.extend(unique.into_iter().map(|(mapping, port)| match (mapping, style) {
+            (Some(mapping), PortStyle::Short) => mapping.to_short().map_or(port, Value::from),
+            (Some(mapping), PortStyle::Long) => mapping.to_long(),
+            _ => port,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(yaml: &str) -> PortMapping {
+        PortMapping::parse(&serde_yaml_ng::from_str(yaml).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_short_and_long_syntax() {
+        assert!(port("\"8080:80\"").same_binding(&port("{target: 80, published: 8080, protocol: TCP}")));
+        assert!(port("\"127.0.0.1:5353:53/udp\"").same_binding(&port("{target: 53, published: \"5353\", host_ip: 127.0.0.1, protocol: udp}")));
+        assert!(!port("\"8080:80\"").same_binding(&port("\"8080:80/udp\"")));
+        assert_eq!(port("\"[::1]:6001:6001\"").host_ip.as_deref(), Some("::1"));
+        assert_eq!(port("\"127.0.0.1::80\"").published, None);
+        assert_eq!(port("80").to_short().as_deref(), Some("80"));
+        assert_eq!(port("{target: 80, host_ip: \"::1\", published: 8000-8010}").to_short().as_deref(), Some("[::1]:8000-8010:80"));
+        assert_eq!(port("{target: 80, published: 8080, mode: host}").to_short(), None);
+        assert_eq!(PortMapping::parse(&Value::from("8080:${PORT:-80}")), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+use include_dir::{include_dir, Dir};
+
+/// Template files, one directory per template laid out like a project root
+static TEMPLATES_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/templates");
+
+/// Placeholder replaced with the project name in every template file
+const PROJECT_NAME_PLACEHOLDER: &str = "{{project_name}}";
+
+/// Project template embedded in the binary
+#[derive(Debug)]
+pub struct ProjectTemplate {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+/// Templates available to `init --template`
+pub const TEMPLATES: &[ProjectTemplate] = &[
+    ProjectTemplate {
+        name: "minimal",
+        description: "Single base service, no environments or extensions",
+    },
+    ProjectTemplate {
+        name: "web-app",
+        description: "Nginx, app and Postgres with dev/prod environments, Redis cache and Adminer extensions",
+    },
+    ProjectTemplate {
+        name: "monitoring-stack",
+        description: "Prometheus and Grafana with dev/prod environments, node-exporter and Alertmanager extensions",
+    },
+];
+
+impl ProjectTemplate {
+    pub fn find(name: &str) -> Option<&'static ProjectTemplate> {
+        TEMPLATES.iter().find(|template| template.name == name)
+    }
+
+    pub fn names() -> Vec<String> {
+        TEMPLATES.iter().map(|template| template.name.to_string()).collect()
+    }
+
+    /// Files of the template relative to the project root, with the project name filled in
+    pub fn files(&self, project_name: &str) -> Vec<(PathBuf, String)> {
+        let mut files = Vec::new();
+        if let Some(dir) = TEMPLATES_DIR.get_dir(self.name) {
+            collect_files(dir, Path::new(self.name), project_name, &mut files);
+        }
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        files
+    }
+}
+
+fn collect_files(dir: &Dir<'_>, root: &Path, project_name: &str, files: &mut Vec<(PathBuf, String)>) {
+    for file in dir.files() {
+        let path = file.path().strip_prefix(root).unwrap_or(file.path()).to_path_buf();
+        let content = file.contents_utf8().unwrap_or_default().replace(PROJECT_NAME_PLACEHOLDER, project_name);
+        files.push((path, content));
+    }
+    for subdir in dir.dirs() {
+        collect_files(subdir, root, project_name, files);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_template_directory_is_listed() {
+        let dirs: Vec<&str> = TEMPLATES_DIR.dirs()
+            .filter_map(|dir| dir.path().to_str())
+            .collect();
+        assert_eq!(dirs.len(), TEMPLATES.len());
+        for dir in dirs {
+            assert!(ProjectTemplate::find(dir).is_some(), "Template directory '{}' is not listed", dir);
+        }
+    }
+
+    #[test]
+    fn test_template_files() {
+        let template = ProjectTemplate::find("web-app").unwrap();
+        let files = template.files("shop");
+
+        let config = files.iter().find(|(path, _)| path == Path::new("stackbuilder.toml")).unwrap();
+        assert!(config.1.contains("name = \"shop\""));
+        assert!(files.iter().any(|(path, _)| path == Path::new("components/base/docker-compose.yml")));
+        assert!(files.iter().all(|(_, content)| !content.contains(PROJECT_NAME_PLACEHOLDER)));
+    }
+}
@@ -3,6 +3,7 @@
 #[cfg(test)]
 mod tests {
     use crate::tests::*;
+    use crate::config::load_config_from;
     use std::fs;
 
     #[test]
@@ -29,6 +30,65 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_build_executor_paths_resolved_against_project_dir() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            let components = temp_path.join("components").canonicalize().unwrap();
+
+            let executor = create_build_executor_in_dir(temp_path).expect("BuildExecutor creation should succeed");
+
+            assert_eq!(executor.paths.components_dir(), components);
+            assert_eq!(executor.paths.base_dir, components.join("base"));
+            assert_eq!(executor.paths.build_dir, temp_path.canonicalize().unwrap().join("build"));
+            assert_eq!(executor.rust_merger.base_path, components.join("base").to_string_lossy());
+            assert_eq!(executor.env_merger.extensions_paths, vec![components.join("extensions").to_string_lossy().to_string()]);
+        });
+    }
+
+    #[test]
+    fn test_build_executor_missing_base_reports_validation_error() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            fs::remove_dir_all(temp_path.join("components/base")).unwrap();
+
+            // Resolving never fails on missing directories, so validation names the actual problem
+            let paths = crate::paths::ResolvedPaths::new(&load_config_from(&temp_path.join("stackbuilder.toml")).unwrap(), temp_path);
+            assert!(paths.base_dir.ends_with("components/base"));
+
+            let error = create_build_executor_in_dir(temp_path).unwrap_err();
+            assert_eq!(error.code(), "validation.base_directory_not_found", "{}", error);
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_real_build_from_non_canonical_working_dir() {
+        run_in_temp_dir(|temp_path| {
+            let project = temp_path.join("project");
+            fs::create_dir_all(&project).unwrap();
+            create_test_project_with(&project, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            fs::write(project.join("components/base/.env.example"), "APP=1\n").unwrap();
+            fs::write(project.join("components/base/app.conf"), "base").unwrap();
+            fs::write(project.join("components/extensions/monitoring/prometheus.yml"), "scrape_configs: []").unwrap();
+            fs::create_dir_all(temp_path.join("elsewhere")).unwrap();
+            std::os::unix::fs::symlink(&project, temp_path.join("link")).unwrap();
+
+            // A `..` detour and a symlink both have to resolve to the components of the real project
+            for working_dir in [temp_path.join("elsewhere/../project"), temp_path.join("link")] {
+                let report = execute_real_build_in_dir(&working_dir).expect("Build should succeed");
+
+                let build_dir = project.join("build/dev/monitoring");
+                let compose = fs::read_to_string(build_dir.join("docker-compose.yml")).unwrap();
+                assert!(compose.contains("prom/prometheus") && compose.contains("ENV=development"), "{}", compose);
+                assert!(fs::read_to_string(build_dir.join(".env.example")).unwrap().contains("APP=1"));
+                assert!(build_dir.join("app.conf").exists() && build_dir.join("prometheus.yml").exists());
+                assert!(report.combinations.iter().all(|combination| combination.compose_file.starts_with(project.canonicalize().unwrap())));
+                assert!(!temp_path.join("elsewhere/build").exists() && !temp_path.join("build").exists());
+            }
+        });
+    }
+
     #[test]
     fn test_execute_build_integration() {
         run_in_temp_dir(|temp_path| {
@@ -179,6 +239,21 @@ services:
         });
     }
 
+    #[test]
+    fn test_extension_only_combos_have_stable_order() {
+        let config_content = r#"
+[build]
+combos = { zeta = ["oidc"], alpha = ["guard"], mid = ["oidc", "guard"] }
+"#;
+        // Every parsed configuration gets its own HashMap seed, so repeated runs would expose map order
+        for _ in 0..10 {
+            let config = crate::config::Config::from_toml_str(config_content).unwrap();
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            let output_dirs: Vec<&str> = combinations.iter().map(|combo| combo.output_dir.as_str()).collect();
+            assert_eq!(output_dirs, vec!["alpha", "mid", "zeta"]);
+        }
+    }
+
     #[test]
     fn test_combo_only_with_skip_base() {
         run_in_temp_dir(|temp_path| {
@@ -284,4 +359,2646 @@ services:
             assert!(prod_monitoring.exists(), "prod should have monitoring subfolder");
         });
     }
+
+    #[test]
+    fn test_build_components_only() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.dev]
+extensions = ["monitoring"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create monitoring compose");
+            fs::create_dir_all(temp_path.join("components/extensions/auth")).expect("Failed to create auth dir");
+            fs::write(temp_path.join("components/extensions/auth/docker-compose.yml"), "services:\n  auth:\n    image: keycloak\n").expect("Failed to write auth compose");
+
+            let args = crate::build::BuildArgs {
+                components_only: Some(vec!["auth".to_string()]),
+                env: Some("prod".to_string()),
+                ..Default::default()
+            };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Components-only build should succeed: {:?}", result);
+
+            // Single combination written to the build root, configured environments ignored
+            let build_dir = temp_path.join("build");
+            let compose = fs::read_to_string(build_dir.join("docker-compose.yml")).expect("Compose file should be in build root");
+            assert!(compose.contains("auth"));
+            assert!(!build_dir.join("dev").exists());
+        });
+    }
+
+    #[test]
+    fn test_build_components_only_unknown_extension() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+
+            let args = crate::build::BuildArgs {
+                components_only: Some(vec!["missing".to_string()]),
+                ..Default::default()
+            };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(matches!(result, Err(crate::error::StackBuilderError::Validation(crate::error::ValidationError::ExtensionNotFound { .. }))),
+                    "Unknown extension should be rejected: {:?}", result);
+        });
+    }
+
+    #[test]
+    fn test_build_components_only_suggests_close_names() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create monitoring compose");
+
+            let args = crate::build::BuildArgs {
+                components_only: Some(vec!["monitorng".to_string()]),
+                ..Default::default()
+            };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("Misspelled extension should be rejected");
+            assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'monitoring'?"));
+
+            let args = crate::build::BuildArgs {
+                components_only: Some(vec!["monitoring".to_string()]),
+                env: Some("prd".to_string()),
+                ..Default::default()
+            };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("Unknown environment should be rejected");
+            assert!(matches!(error, crate::error::StackBuilderError::Validation(crate::error::ValidationError::EnvironmentNotFound { .. })), "{:?}", error);
+            assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'prod'?"));
+        });
+    }
+
+    #[test]
+    fn test_environment_specific_exclude_patterns() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.prod]
+exclude_patterns = ["dev-only.conf"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/dev-only.conf"), "debug=true").expect("Failed to write dev-only.conf");
+            fs::write(temp_path.join("components/base/app.conf"), "app=true").expect("Failed to write app.conf");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+            for env in ["dev", "prod"] {
+                create_test_compose(&temp_path.join(format!("components/environments/{}/docker-compose.yml", env))).expect("Failed to create env compose");
+            }
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let build_dir = temp_path.join("build");
+            assert!(build_dir.join("dev/dev-only.conf").exists(), "Dev build should copy dev-only.conf");
+            assert!(!build_dir.join("prod/dev-only.conf").exists(), "Prod build should exclude dev-only.conf");
+            assert!(build_dir.join("prod/app.conf").exists(), "Prod build should still copy other files");
+        });
+    }
+
+    #[test]
+    fn test_build_writes_gitignore() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+env_file_patterns = [".env", ".env.local"]
+backup_dir = "./build/.backup"
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let gitignore = fs::read_to_string(temp_path.join("build/.gitignore")).expect(".gitignore should be generated");
+            assert!(gitignore.starts_with("# Generated by stackbuilder"));
+            assert!(gitignore.lines().any(|line| line == ".env"));
+            assert!(gitignore.lines().any(|line| line == ".env.local"));
+            assert!(gitignore.lines().any(|line| line == "/.backup/"));
+        });
+    }
+
+    #[test]
+    fn test_build_gitignore_can_be_disabled() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nwrite_gitignore = false\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+            assert!(!temp_path.join("build/.gitignore").exists());
+        });
+    }
+
+    #[test]
+    fn test_build_skips_env_example_without_variables() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+
+            // No component has a .env.example
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            for combination in &report.combinations {
+                let dir = combination.compose_file.parent().unwrap();
+                assert!(!dir.join(".env.example").exists(), "{} should have no .env.example", combination.output_dir);
+            }
+
+            // Sources with only comments and blank lines give no file either
+            fs::write(temp_path.join("components/base/.env.example"), "# Base settings\n\n").unwrap();
+            fs::write(temp_path.join("components/extensions/monitoring/.env.example"), "# PROMETHEUS_PORT=9090\n").unwrap();
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(!temp_path.join("build/dev/monitoring/.env.example").exists());
+
+            fs::write(temp_path.join("components/extensions/monitoring/.env.example"), "PROMETHEUS_PORT=9090\n").unwrap();
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(temp_path.join("build/dev/monitoring/.env.example").exists());
+            assert!(!temp_path.join("build/dev/base/.env.example").exists());
+        });
+    }
+
+    #[test]
+    fn test_build_reuses_unchanged_combinations_from_cache() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            let cached = |report: &crate::build_report::BuildReport| -> Vec<String> {
+                report.combinations.iter().filter(|c| c.cached).map(|c| c.output_dir.clone()).collect()
+            };
+
+            let first = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(cached(&first).is_empty());
+            assert!(temp_path.join(".stackbuilder/cache.json").exists());
+
+            // Nothing changed: every combination is carried over with its outputs and warnings
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert_eq!(cached(&report).len(), report.combinations.len());
+            assert_eq!(report.warning_count, first.warning_count);
+            let monitoring = temp_path.join("build/dev/monitoring/docker-compose.yml");
+            assert!(fs::read_to_string(&monitoring).unwrap().contains("prometheus"));
+
+            // Changing an extension only regenerates the combinations that use it
+            let ext_compose = temp_path.join("components/extensions/monitoring/docker-compose.yml");
+            let content = fs::read_to_string(&ext_compose).unwrap().replace("prometheus", "grafana");
+            fs::write(&ext_compose, content).unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert!(!cached(&report).iter().any(|dir| dir.ends_with("monitoring")), "{:?}", cached(&report));
+            assert!(cached(&report).contains(&"dev/base".to_string()), "{:?}", cached(&report));
+            assert!(fs::read_to_string(&monitoring).unwrap().contains("grafana"));
+
+            // An output that no longer matches the cache is regenerated
+            fs::remove_file(&monitoring).unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert!(!cached(&report).contains(&"dev/monitoring".to_string()));
+            assert!(monitoring.exists());
+
+            // --force bypasses the cache
+            let args = crate::build::BuildArgs { force: true, ..Default::default() };
+            let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Forced build should succeed");
+            assert!(cached(&report).is_empty());
+        });
+    }
+
+    #[test]
+    fn test_build_cache_skips_timestamp_headers() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\ngenerated_header = \"built at {timestamp}\"").expect("Failed to create test project");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            // The header would keep the time of the first build if the outputs were reused
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert!(report.combinations.iter().all(|c| !c.cached), "{:?}", report.combinations);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_cache_reuse_keeps_object_store_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\ndedup_copies = \"hardlink\"").expect("Failed to create test project");
+            fs::write(temp_path.join("components/base/static.conf"), "shared=true").unwrap();
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let linked = fs::metadata(temp_path.join("build/dev/base/static.conf")).unwrap();
+
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert!(report.combinations.iter().all(|c| c.cached));
+            // Still the object's inode, so the object store keeps it
+            let reused = fs::metadata(temp_path.join("build/dev/base/static.conf")).unwrap();
+            assert_eq!(reused.ino(), linked.ino());
+            assert!(reused.nlink() > 1, "The reused copy should still be linked to its object");
+        });
+    }
+
+    #[test]
+    fn test_rebuild_keeps_modification_time_of_unchanged_outputs() {
+        use std::time::{Duration, SystemTime};
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            let base = temp_path.join("build/dev/base/docker-compose.yml");
+            let monitoring = temp_path.join("build/dev/monitoring/docker-compose.yml");
+            let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+            let set_old = |path: &std::path::Path| fs::File::options().write(true).open(path).unwrap().set_modified(old).unwrap();
+            let modified = |path: &std::path::Path| fs::metadata(path).unwrap().modified().unwrap();
+
+            // Outputs reused from the build cache are carried over with their times
+            set_old(&base);
+            execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert_eq!(modified(&base), old);
+
+            // Regenerated outputs with the same content keep their times, changed ones are written anew
+            set_old(&base);
+            set_old(&monitoring);
+            let ext_compose = temp_path.join("components/extensions/monitoring/docker-compose.yml");
+            let content = fs::read_to_string(&ext_compose).unwrap().replace("prometheus", "grafana");
+            fs::write(&ext_compose, content).unwrap();
+            let args = crate::build::BuildArgs { force: true, ..Default::default() };
+            execute_real_build_with_args_in_dir(temp_path, &args).expect("Forced build should succeed");
+            assert_eq!(modified(&base), old);
+            assert_ne!(modified(&monitoring), old);
+            assert!(fs::read_to_string(&monitoring).unwrap().contains("grafana"));
+        });
+    }
+
+    #[test]
+    fn test_build_writes_checksums() {
+        use crate::checksums::{parse_checksums, verify_checksums, ChecksumScope, CHECKSUMS_FILE};
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            fs::write(temp_path.join("components/base/app.conf"), "base").unwrap();
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            // A real .env next to the outputs is kept by the next build but never listed
+            fs::write(temp_path.join("build/dev/monitoring/.env"), "SECRET=1\n").unwrap();
+            execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert!(temp_path.join("build/dev/monitoring/.env").exists());
+
+            let build_dir = temp_path.join("build");
+            let root = parse_checksums(&fs::read_to_string(build_dir.join(CHECKSUMS_FILE)).unwrap());
+            assert!(root.contains_key("dev/monitoring/docker-compose.yml"));
+            assert!(root.contains_key("prod/base/app.conf"));
+            assert!(root.contains_key(".gitignore"));
+            assert!(!root.keys().any(|path| path.ends_with(".env") || path.ends_with(CHECKSUMS_FILE)), "{:?}", root);
+            let combination = parse_checksums(&fs::read_to_string(build_dir.join("dev/monitoring").join(CHECKSUMS_FILE)).unwrap());
+            assert_eq!(combination["docker-compose.yml"], root["dev/monitoring/docker-compose.yml"]);
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            crate::config::resolve_paths_in(&mut config, temp_path);
+            let scope = ChecksumScope::from_config(&config);
+            assert!(verify_checksums(&build_dir, &scope).unwrap().is_clean());
+
+            fs::write(build_dir.join("dev/monitoring/.env"), "SECRET=2\n").unwrap();
+            fs::write(build_dir.join("prod/base/app.conf"), "tampered").unwrap();
+            let report = verify_checksums(&build_dir, &scope).unwrap();
+            assert_eq!(report.modified, vec!["prod/base/app.conf"]);
+            assert!(report.missing.is_empty() && report.extra.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_build_reports_compose_counts() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            fs::write(temp_path.join("components/extensions/monitoring/docker-compose.yml"), "\
+services:
+  prometheus:
+    image: prom/prometheus
+    volumes:
+      - prometheus-data:/prometheus
+  grafana:
+    image: grafana/grafana
+volumes:
+  prometheus-data:
+  grafana-data:
+networks:
+  monitoring:
+").unwrap();
+
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let counts = |output_dir: &str| report.combinations.iter()
+                .find(|combination| combination.output_dir == output_dir)
+                .and_then(|combination| combination.counts)
+                .unwrap();
+            let monitoring = counts("dev/monitoring");
+            assert_eq!((monitoring.services, monitoring.volumes, monitoring.networks), (3, 2, 1));
+            assert_eq!(monitoring.to_string(), "3 services, 2 volumes, 1 network");
+            assert_eq!(counts("prod/base").to_string(), "1 service, 0 volumes, 0 networks");
+
+            // Reused combinations keep their counts
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            let cached = report.combinations.iter().find(|combination| combination.output_dir == "dev/monitoring").unwrap();
+            assert!(cached.cached);
+            assert_eq!(cached.counts, Some(monitoring));
+        });
+    }
+
+    #[test]
+    fn test_build_writes_combinations_manifest() {
+        use crate::build_report::COMBINATIONS_MANIFEST_FILE;
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            fs::write(temp_path.join("components/base/app.conf"), "base").unwrap();
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            let manifest_path = temp_path.join("build").join(COMBINATIONS_MANIFEST_FILE);
+            let first = fs::read_to_string(&manifest_path).unwrap();
+            let manifest: serde_json::Value = serde_json::from_str(&first).unwrap();
+            assert_eq!(manifest["version"], env!("CARGO_PKG_VERSION"));
+            assert_eq!(manifest["config_hash"].as_str().unwrap().len(), 64);
+            let output_dirs: Vec<_> = manifest["combinations"].as_array().unwrap().iter()
+                .map(|combination| combination["output_dir"].as_str().unwrap())
+                .collect();
+            assert_eq!(output_dirs, ["dev/base", "dev/monitoring", "prod/base", "prod/monitoring"]);
+            let monitoring = &manifest["combinations"][1];
+            assert_eq!(monitoring["environment"], "dev");
+            assert_eq!(monitoring["extensions"], serde_json::json!(["monitoring"]));
+            assert_eq!(monitoring["files"], serde_json::json!(["app.conf", "docker-compose.yml"]));
+
+            // Deterministic, and never preserved as a modified generated file
+            fs::write(&manifest_path, "edited").unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert_eq!(fs::read_to_string(&manifest_path).unwrap(), first);
+            assert!(report.env_files_backed_up.is_empty(), "{:?}", report.env_files_backed_up);
+        });
+    }
+
+    #[test]
+    fn test_config_hash_is_the_same_in_every_checkout() {
+        use crate::build_report::COMBINATIONS_MANIFEST_FILE;
+
+        run_in_temp_dir(|temp_path| {
+            let hashes: Vec<String> = ["a", "b/nested"].iter()
+                .map(|checkout| {
+                    let dir = temp_path.join(checkout);
+                    fs::create_dir_all(&dir).unwrap();
+                    create_test_project_with(&dir, "yaml_merger = \"rust\"").expect("Failed to create test project");
+                    execute_real_build_in_dir(&dir).expect("Build should succeed");
+                    let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("build").join(COMBINATIONS_MANIFEST_FILE)).unwrap()).unwrap();
+                    manifest["config_hash"].as_str().unwrap().to_string()
+                })
+                .collect();
+            assert_eq!(hashes[0], hashes[1]);
+        });
+    }
+
+    #[test]
+    fn test_build_reports_service_changes() {
+        use crate::build_report::{ServiceChange, COMBINATIONS_MANIFEST_FILE};
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            let monitoring = temp_path.join("components/extensions/monitoring/docker-compose.yml");
+            fs::write(&monitoring, "services:\n  prometheus:\n    image: prom/prometheus:v2.50\n    ports:\n      - \"9090:9090\"\n  grafana:\n    image: grafana/grafana\n").unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(report.service_changes.is_empty());
+            let manifest_path = temp_path.join("build").join(COMBINATIONS_MANIFEST_FILE);
+            let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+            assert_eq!(manifest["schema"], 2);
+            assert_eq!(manifest["combinations"][1]["services"]["prometheus"], serde_json::json!({"image": "prom/prometheus:v2.50", "ports": ["9090:9090"]}));
+
+            // Ports are listed like the short syntax the build writes
+            let compose = serde_yaml_ng::from_str("services:\n  dns:\n    ports:\n      - target: 53\n        published: 53\n        host_ip: \"::1\"\n        protocol: UDP\n      - target: 80\n        host_ip: 127.0.0.1\n      - \"${PORT:-8080}:8080\"\n").unwrap();
+            let inventory = crate::build_report::ServiceInventory::of(&compose);
+            assert_eq!(inventory["dns"].ports, ["${PORT:-8080}:8080", "127.0.0.1::80", "[::1]:53:53/udp"]);
+
+            fs::write(&monitoring, "services:\n  prometheus:\n    image: prom/prometheus:v2.51\n    ports:\n      - target: 9090\n        published: 9091\n  alertmanager:\n    image: prom/alertmanager\n").unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            let output_dirs: Vec<&str> = report.service_changes.iter().map(|changes| changes.output_dir.as_str()).collect();
+            assert_eq!(output_dirs, ["dev/monitoring", "prod/monitoring"]);
+            let changes = &report.service_changes[0];
+            assert_eq!(changes.added, ["alertmanager"]);
+            assert_eq!(changes.removed, ["grafana"]);
+            assert_eq!(changes.images, [ServiceChange { service: "prometheus".into(), from: "prom/prometheus:v2.50".into(), to: "prom/prometheus:v2.51".into() }]);
+            assert_eq!(changes.ports, [ServiceChange { service: "prometheus".into(), from: "9090:9090".into(), to: "9091:9090".into() }]);
+            assert!(report.format_service_changes().unwrap().contains("  dev/monitoring:\n    + alertmanager\n    - grafana\n    ~ prometheus: image prom/prometheus:v2.50 → prom/prometheus:v2.51\n    ~ prometheus: ports 9090:9090 → 9091:9090\n"));
+
+            // A manifest from before the inventory is not compared
+            let mut manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+            manifest.as_object_mut().unwrap().remove("schema");
+            for combination in manifest["combinations"].as_array_mut().unwrap() {
+                combination.as_object_mut().unwrap().remove("services");
+            }
+            fs::write(&manifest_path, manifest.to_string()).unwrap();
+            fs::write(&monitoring, "services:\n  prometheus:\n    image: prom/prometheus:v2.52\n").unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            assert!(report.service_changes.is_empty(), "{:?}", report.service_changes);
+        });
+    }
+
+    #[test]
+    fn test_build_writes_archive() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\narchive_path = \"dist/stacks.tar.gz\"").expect("Failed to create test project");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            fs::write(temp_path.join("build/dev/monitoring/.env"), "SECRET=1\n").unwrap();
+            let first = fs::read(temp_path.join("dist/stacks.tar.gz")).unwrap();
+
+            let args = crate::build::BuildArgs { archive: Some("other.tar.gz".into()), ..Default::default() };
+            execute_real_build_with_args_in_dir(temp_path, &args).expect("Rebuild should succeed");
+            let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(temp_path.join("other.tar.gz")).unwrap()));
+            let names: Vec<String> = archive.entries().unwrap()
+                .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+                .collect();
+            assert!(names.contains(&"dev/monitoring/docker-compose.yml".to_string()), "{:?}", names);
+            assert!(names.contains(&"CHECKSUMS.sha256".to_string()), "{:?}", names);
+            assert!(!names.iter().any(|name| name.ends_with(".env") || name.ends_with(".stackbuilder-build")), "{:?}", names);
+            // The preserved .env is not packed, so unchanged outputs give the same archive
+            assert_eq!(fs::read(temp_path.join("other.tar.gz")).unwrap(), first);
+        });
+    }
+
+    #[test]
+    fn test_build_checksums_can_be_disabled() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nwrite_checksums = false\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(!temp_path.join("build").join(crate::checksums::CHECKSUMS_FILE).exists());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedup_copies_hardlink_shares_inode() {
+        use std::os::unix::fs::MetadataExt;
+
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+environments = ["dev", "prod"]
+dedup_copies = "hardlink"
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/static.conf"), "shared=true").expect("Failed to write static.conf");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+            for env in ["dev", "prod"] {
+                create_test_compose(&temp_path.join(format!("components/environments/{}/docker-compose.yml", env))).expect("Failed to create env compose");
+            }
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let dev = fs::metadata(temp_path.join("build/dev/static.conf")).expect("dev copy should exist");
+            let prod = fs::metadata(temp_path.join("build/prod/static.conf")).expect("prod copy should exist");
+            assert_eq!(dev.ino(), prod.ino(), "Identical copies should share an inode");
+            assert_eq!(fs::read_to_string(temp_path.join("build/prod/static.conf")).unwrap(), "shared=true");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dedup_copies_object_store_is_verified_and_pruned() {
+        use std::os::unix::fs::PermissionsExt;
+
+        fn objects(temp_path: &std::path::Path) -> Vec<std::path::PathBuf> {
+            let store = temp_path.join(crate::file_copier::OBJECT_STORE_DIR);
+            let mut objects = Vec::new();
+            for prefix in fs::read_dir(store).expect("Object store should exist") {
+                for object in fs::read_dir(prefix.unwrap().path()).unwrap() {
+                    objects.push(object.unwrap().path());
+                }
+            }
+            objects
+        }
+
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+environments = ["dev", "prod"]
+dedup_copies = "hardlink"
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/static.conf"), "shared=true").expect("Failed to write static.conf");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+            for env in ["dev", "prod"] {
+                create_test_compose(&temp_path.join(format!("components/environments/{}/docker-compose.yml", env))).expect("Failed to create env compose");
+            }
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            let stored = objects(temp_path);
+            assert_eq!(stored.len(), 1, "Both copies should share one object: {:?}", stored);
+            let mode = fs::metadata(&stored[0]).unwrap().permissions().mode();
+            assert_eq!(mode & 0o222, 0, "Objects should be read-only, got {:o}", mode);
+
+            // Tamper with the object; the next build must not link the modified content again
+            fs::set_permissions(&stored[0], fs::Permissions::from_mode(0o644)).unwrap();
+            fs::write(&stored[0], "shared=tampered").unwrap();
+            let args = crate::build::BuildArgs { force: true, ..Default::default() };
+            execute_real_build_with_args_in_dir(temp_path, &args).expect("Rebuild should succeed");
+            assert_eq!(fs::read_to_string(temp_path.join("build/dev/static.conf")).unwrap(), "shared=true");
+            assert_eq!(fs::read_to_string(&stored[0]).unwrap(), "shared=true");
+            assert_eq!(fs::metadata(&stored[0]).unwrap().permissions().mode() & 0o222, 0);
+
+            // The object of the old content is no longer linked by any output and is pruned
+            fs::write(temp_path.join("components/base/static.conf"), "shared=false").expect("Failed to write static.conf");
+            execute_real_build_in_dir(temp_path).expect("Rebuild should succeed");
+            let stored_after = objects(temp_path);
+            assert_eq!(stored_after.len(), 1, "Only the current object should remain: {:?}", stored_after);
+            assert_eq!(fs::read_to_string(&stored_after[0]).unwrap(), "shared=false");
+        });
+    }
+
+    #[test]
+    fn test_build_summary_file() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+environments = ["dev", "prod"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+            for env in ["dev", "prod"] {
+                create_test_compose(&temp_path.join(format!("components/environments/{}/docker-compose.yml", env))).expect("Failed to create env compose");
+                fs::write(temp_path.join(format!("components/environments/{}/.env.example", env)), "").expect("Failed to write .env.example");
+            }
+
+            fs::write(temp_path.join("components/base/.env.example"), "APP_PORT=8080\n").expect("Failed to write .env.example");
+
+            let summary_path = temp_path.join("artifacts/reports/build.json");
+            let args = crate::build::BuildArgs {
+                summary_file: Some(summary_path.clone()),
+                ..Default::default()
+            };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary_path).expect("Summary file should be written"))
+                .expect("Summary should be valid JSON");
+            assert_eq!(report["merger"], "rust");
+            let combinations = report["combinations"].as_array().expect("combinations should be an array");
+            assert_eq!(combinations.len(), 2);
+            assert_eq!(combinations[0]["environment"], "dev");
+            assert!(combinations[0]["compose_file"].as_str().unwrap().ends_with("dev/docker-compose.yml"));
+            assert!(report["warnings"].as_array().unwrap().is_empty(), "{:?}", report["warnings"]);
+            assert_eq!(report["warning_count"], 0);
+        });
+    }
+
+    #[test]
+    fn test_build_returns_structured_report() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/.env.example"), "APP_PORT=8080\n").unwrap();
+            fs::write(temp_path.join("components/base/app.conf"), "port=8080\n").unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+            for env in ["dev", "prod"] {
+                create_test_compose(&temp_path.join(format!("components/environments/{}/docker-compose.yml", env))).expect("Failed to create env compose");
+            }
+
+            execute_real_build_in_dir(temp_path).expect("First build should succeed");
+            fs::write(temp_path.join("build/dev/.env"), "SECRET=1\n").unwrap();
+
+            let report = execute_real_build_in_dir(temp_path).expect("Second build should succeed");
+            let relative = |paths: &[std::path::PathBuf]| -> Vec<String> {
+                paths.iter().map(|path| path.strip_prefix(&report.build_dir).unwrap().to_string_lossy().to_string()).collect()
+            };
+
+            assert_eq!(report.combinations.len(), 2);
+            let dev = &report.combinations[0];
+            assert_eq!(dev.environment.as_deref(), Some("dev"));
+            assert_eq!(dev.merger, crate::config::YamlMergerType::Rust);
+            assert_eq!(relative(&dev.files_written), ["dev/docker-compose.yml", "dev/.env.example"]);
+            assert_eq!(relative(&dev.files_copied), ["dev/app.conf"]);
+            assert_eq!(relative(&dev.env_files_restored), ["dev/.env"]);
+            // The missing environment .env.example is reported for the combination it belongs to
+            assert_eq!(dev.warnings.len(), 1, "{:?}", dev.warnings);
+            assert_eq!(dev.warnings[0].category, crate::diagnostics::WarningCategory::MissingFile);
+            assert_eq!(dev.warnings[0].combination.as_deref(), Some("dev"));
+
+            let prod = &report.combinations[1];
+            assert!(prod.env_files_restored.is_empty());
+            assert_eq!(prod.warnings.len(), 1);
+            assert_eq!(report.warning_count, 2);
+            assert!(report.env_files_backed_up.is_empty());
+
+            let table = report.format_table();
+            assert!(table.starts_with("COMBINATION"), "{}", table);
+            assert!(table.lines().nth(1).unwrap().starts_with("dev "), "{}", table);
+        });
+    }
+
+    #[test]
+    fn test_build_timings() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(report.timings.is_none(), "Timings are only measured on request");
+
+            let args = crate::build::BuildArgs { force: true, timings_file: Some("reports/timings.json".into()), ..Default::default() };
+            let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Build should succeed");
+            let timings = report.timings.as_ref().expect("--timings-file should measure the build");
+            for phase in ["config", "validation", "cleanup", "merging", "env", "copying", "restore", "finish", "other"] {
+                assert!(timings.phases.contains_key(phase), "Missing phase {}: {:?}", phase, timings.phases);
+            }
+            assert!(timings.phases.values().sum::<u64>() <= timings.total_ms + timings.phases.len() as u64, "{:?}", timings);
+            let generated: Vec<&str> = timings.combinations.iter().map(|c| c.output_dir.as_str()).collect();
+            let built: Vec<&str> = report.combinations.iter().map(|c| c.output_dir.as_str()).collect();
+            assert_eq!(generated, built);
+            assert!(timings.combinations.iter().all(|c| c.phases.contains_key("merging")), "{:?}", timings.combinations);
+
+            let written: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_path.join("reports/timings.json")).unwrap()).unwrap();
+            assert_eq!(written["total_ms"], timings.total_ms);
+            assert_eq!(written["combinations"].as_array().unwrap().len(), built.len());
+
+            // Outputs reused from the build cache have no phases of their own
+            let args = crate::build::BuildArgs { timings: true, ..Default::default() };
+            let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Rebuild should succeed");
+            let timings = report.timings.as_ref().unwrap();
+            assert!(timings.combinations.iter().all(|c| c.phases.is_empty()), "{:?}", timings.combinations);
+            let table = timings.format_table();
+            assert!(table.starts_with("PHASE"), "{}", table);
+            assert!(table.contains("\ntotal "), "{}", table);
+            let header = table.lines().find(|line| line.starts_with("COMBINATION")).expect("Combinations should follow the phases");
+            assert_eq!(header.split_whitespace().collect::<Vec<_>>(), ["COMBINATION", "MERGING", "ENV", "COPYING", "TOTAL"]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_with_read_only_components_root() {
+        use std::os::unix::fs::PermissionsExt;
+        use crate::build::{build, BuildArgs, BuildOptions};
+
+        /// Every file below `dir` with its content and modification time
+        fn snapshot(dir: &std::path::Path) -> Vec<(std::path::PathBuf, Vec<u8>, std::time::SystemTime)> {
+            let mut files = Vec::new();
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    files.extend(snapshot(&path));
+                } else {
+                    files.push((path.clone(), fs::read(&path).unwrap(), fs::metadata(&path).unwrap().modified().unwrap()));
+                }
+            }
+            files.sort();
+            files
+        }
+
+        fn set_read_only(dir: &std::path::Path, read_only: bool) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    set_read_only(&path, read_only);
+                } else {
+                    fs::set_permissions(&path, fs::Permissions::from_mode(if read_only { 0o444 } else { 0o644 })).unwrap();
+                }
+            }
+            fs::set_permissions(dir, fs::Permissions::from_mode(if read_only { 0o555 } else { 0o755 })).unwrap();
+        }
+
+        run_in_temp_dir(|temp_path| {
+            // Vendored tree with the base and a shared extension
+            let vendor = temp_path.join("vendor/components");
+            create_test_compose(&vendor.join("base/docker-compose.yml")).unwrap();
+            fs::write(vendor.join("base/.env.example"), "APP_PORT=8080\n").unwrap();
+            fs::write(vendor.join("base/app.conf"), "port=8080\n").unwrap();
+            fs::create_dir_all(vendor.join("extensions/monitoring")).unwrap();
+            fs::write(vendor.join("extensions/monitoring/docker-compose.yml"), "services:\n  prometheus:\n    image: prom/prometheus\n").unwrap();
+
+            // The project only adds environments and its own extension
+            fs::write(temp_path.join("stackbuilder.toml"), "[paths]\ncomponents_dirs = [\"vendor/components\"]\n\n[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\"]\nextensions = [\"monitoring\", \"cache\"]\n").unwrap();
+            create_test_compose(&temp_path.join("components/environments/dev/docker-compose.yml")).unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions/cache")).unwrap();
+            fs::write(temp_path.join("components/extensions/cache/docker-compose.yml"), "services:\n  redis:\n    image: redis\n").unwrap();
+
+            set_read_only(&temp_path.join("vendor"), true);
+            let before = snapshot(&vendor);
+
+            let report = build(BuildOptions::new(temp_path)).expect("Build from a read-only components root should succeed");
+            let after = snapshot(&vendor);
+            set_read_only(&temp_path.join("vendor"), false);
+
+            assert_eq!(before, after, "The components root must not be written to");
+            let monitoring = report.combinations.iter().find(|c| c.output_dir == "monitoring").expect("monitoring should be built");
+            let compose = fs::read_to_string(&monitoring.compose_file).unwrap();
+            assert!(compose.contains("prom/prometheus") && compose.contains("nginx:alpine"), "{}", compose);
+            assert!(report.combinations.iter().any(|c| c.output_dir == "cache"));
+            assert!(temp_path.join("build/monitoring/app.conf").exists());
+
+            // Discovery records the root each extension comes from
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            crate::config::resolve_paths_in(&mut config, temp_path);
+            let sources = crate::config::discover_extension_sources(&config).unwrap();
+            let root_of = |name: &str| sources.iter().find(|c| c.name == name).map(|c| c.root.clone()).unwrap();
+            assert_eq!(root_of("cache"), temp_path.join("components").canonicalize().unwrap());
+            assert_eq!(root_of("monitoring"), vendor.canonicalize().unwrap());
+
+            // Outputs inside a components root are refused, also when the root is only given on the command line
+            fs::write(temp_path.join("stackbuilder.toml"), "[paths]\nbuild_dir = \"vendor/components/build\"\n\n[build]\nyaml_merger = \"rust\"\n").unwrap();
+            let args = BuildArgs { components_dirs: vec!["vendor/components".into()], force_clean: true, ..Default::default() };
+            let error = build(BuildOptions::new(temp_path).with_args(args)).unwrap_err();
+            assert_eq!(error.code(), "validation.output_inside_components_root");
+            assert!(!vendor.join("build").exists());
+        });
+    }
+
+    #[test]
+    fn test_build_with_components_in_project_root() {
+        run_in_temp_dir(|temp_path| {
+            create_test_compose(&temp_path.join("base/docker-compose.yml")).unwrap();
+            create_test_compose(&temp_path.join("environments/dev/docker-compose.yml")).unwrap();
+            fs::write(temp_path.join("stackbuilder.toml"), "[paths]\ncomponents_dir = \".\"\n\n[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\"]\n").unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("The build directory may lie in components_dir");
+            assert!(temp_path.join("build/docker-compose.yml").exists());
+
+            // The component directories themselves stay read-only
+            let config = fs::read_to_string(temp_path.join("stackbuilder.toml")).unwrap();
+            fs::write(temp_path.join("stackbuilder.toml"), config.replace("components_dir = \".\"", "components_dir = \".\"\nbuild_dir = \"environments/build\"")).unwrap();
+            let error = execute_real_build_in_dir(temp_path).unwrap_err();
+            assert_eq!(error.code(), "validation.output_inside_components_root", "{}", error);
+            assert!(!temp_path.join("environments/build").exists());
+        });
+    }
+
+    #[test]
+    fn test_failed_build_keeps_previous_output() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "First build should succeed: {:?}", result);
+            let build_dir = temp_path.join("build");
+            fs::write(build_dir.join(".env"), "SECRET=1").expect("Failed to write .env");
+            let previous = fs::read_to_string(build_dir.join("docker-compose.yml")).expect("Compose file should exist");
+
+            // Break the base file so merging fails
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services: [unclosed").expect("Failed to break base compose");
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_err(), "Build with invalid base should fail");
+
+            assert_eq!(fs::read_to_string(build_dir.join("docker-compose.yml")).unwrap(), previous);
+            assert_eq!(fs::read_to_string(build_dir.join(".env")).unwrap(), "SECRET=1");
+            let leftovers: Vec<_> = fs::read_dir(temp_path).unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+                .filter(|name| name.starts_with("build."))
+                .collect();
+            assert!(leftovers.is_empty(), "Staging directories should be removed: {:?}", leftovers);
+        });
+    }
+
+    #[test]
+    fn test_modified_generated_files_are_backed_up() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "First build should succeed: {:?}", result);
+            let compose_path = temp_path.join("build/docker-compose.yml");
+            let generated = fs::read_to_string(&compose_path).expect("Compose file should exist");
+            assert!(temp_path.join("build/.stackbuilder-manifest.json").exists());
+
+            // Operator edits the generated file on the host
+            fs::write(&compose_path, "services:\n  hotfix:\n    image: patched\n").expect("Failed to edit compose");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Second build should succeed: {:?}", result);
+            assert_eq!(fs::read_to_string(&compose_path).unwrap(), generated);
+
+            let backup_dir = temp_path.join(".stackbuilder/backup");
+            let backups = crate::backups::list_backups(&backup_dir).expect("Backups should be listed");
+            assert_eq!(backups.len(), 1, "Modified file should be backed up");
+            assert_eq!(backups[0].metadata.files[0].original_path, std::path::PathBuf::from("docker-compose.yml"));
+
+            // With --force the edit is discarded without a backup
+            fs::write(&compose_path, "services:\n  hotfix:\n    image: patched-again\n").expect("Failed to edit compose");
+            let args = crate::build::BuildArgs { force: true, ..Default::default() };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Forced build should succeed: {:?}", result);
+            assert_eq!(crate::backups::list_backups(&backup_dir).unwrap().len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_generated_env_example_wins_over_preserved_copy() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(
+                temp_path.join("stackbuilder.toml"),
+                "[build]\nyaml_merger = \"rust\"\nenv_file_patterns = [\".env\", \".env.example\"]\n",
+            ).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/.env.example"), "PORT=80\n").expect("Failed to write env example");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "First build should succeed: {:?}", result);
+            fs::write(temp_path.join("build/.env"), "PORT=8080\n").expect("Failed to write .env");
+
+            fs::write(temp_path.join("components/base/.env.example"), "PORT=80\nHOST=localhost\n").expect("Failed to update env example");
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Second build should succeed: {:?}", result);
+
+            let env_example = fs::read_to_string(temp_path.join("build/.env.example")).expect(".env.example should exist");
+            assert!(env_example.contains("HOST=localhost"), "Generated .env.example should replace the previous one: {}", env_example);
+            assert_eq!(fs::read_to_string(temp_path.join("build/.env")).unwrap(), "PORT=8080\n");
+            let backups = crate::backups::list_backups(&temp_path.join(".stackbuilder/backup")).unwrap_or_default();
+            assert!(backups.is_empty(), "Previous .env.example should not be treated as a preserved file");
+        });
+    }
+
+    #[test]
+    fn test_parallel_io_build_matches_sequential_build() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(
+                temp_path.join("stackbuilder.toml"),
+                "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\nextensions = [\"monitoring\"]\n",
+            ).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/.env.example"), "PORT=80\n").expect("Failed to write env example");
+            for i in 0..20 {
+                let path = temp_path.join(format!("components/base/config/file_{}.conf", i));
+                fs::create_dir_all(path.parent().unwrap()).expect("Failed to create config dir");
+                fs::write(path, format!("value = {}\n", i)).expect("Failed to write config file");
+            }
+            for env in ["dev", "prod"] {
+                create_test_compose(&temp_path.join(format!("components/environments/{}/docker-compose.yml", env))).expect("Failed to create env compose");
+            }
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+
+            fn collect_files(dir: &std::path::Path, files: &mut Vec<std::path::PathBuf>) {
+                for entry in fs::read_dir(dir).unwrap() {
+                    let path = entry.unwrap().path();
+                    if path.is_dir() { collect_files(&path, files) } else { files.push(path) }
+                }
+            }
+            let snapshot = |root: &std::path::Path| {
+                let mut paths = Vec::new();
+                collect_files(root, &mut paths);
+                let mut files: Vec<(String, String)> = paths.into_iter()
+                    .filter(|path| !path.ends_with(".stackbuilder-manifest.json"))
+                    .map(|path| (path.strip_prefix(root).unwrap().display().to_string(), fs::read_to_string(&path).unwrap()))
+                    .collect();
+                files.sort();
+                files
+            };
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Sequential build should succeed: {:?}", result);
+            let sequential = snapshot(&temp_path.join("build"));
+
+            let args = crate::build::BuildArgs { parallel_io: true, ..Default::default() };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Parallel IO build should succeed: {:?}", result);
+            let parallel = snapshot(&temp_path.join("build"));
+
+            assert!(sequential.iter().any(|(path, _)| path.ends_with("file_19.conf")));
+            assert_eq!(sequential, parallel);
+        });
+    }
+
+    #[test]
+    fn test_generated_header_lines() {
+        use crate::build::generated_header_lines;
+
+        let lines = generated_header_lines("DO NOT EDIT - generated, see stacks/\n\n# built {combination} at {timestamp}\n", "dev/monitoring", "2024-01-01 00:00:00 UTC");
+        assert_eq!(lines, vec![
+            "# DO NOT EDIT - generated, see stacks/",
+            "#",
+            "# built dev/monitoring at 2024-01-01 00:00:00 UTC",
+        ]);
+        assert_eq!(generated_header_lines("{combination}", "", "t"), vec!["# ."]);
+    }
+
+    #[test]
+    fn test_combination_project_name() {
+        use crate::build::{combination_project_name, BuildCombination};
+
+        let combo = |environment: Option<&str>, output_dir: &str| BuildCombination {
+            environment: environment.map(str::to_string),
+            extensions: Vec::new(),
+            combo_names: Vec::new(),
+            output_dir: output_dir.to_string(),
+        };
+
+        let template = "{env}-{combo}";
+        assert_eq!(combination_project_name(template, None, &combo(Some("dev"), "dev/monitoring")).as_deref(), Some("dev-monitoring"));
+        assert_eq!(combination_project_name(template, None, &combo(Some("dev"), "dev")).as_deref(), Some("dev-base"));
+        assert_eq!(combination_project_name(template, None, &combo(Some("prod"), "prod/Web+Cache")).as_deref(), Some("prod-web-cache"));
+        // An environment name that only prefixes the output directory is not stripped
+        assert_eq!(combination_project_name(template, None, &combo(Some("dev"), "devtools")).as_deref(), Some("dev-devtools"));
+        // Without an environment the separator would lead the name
+        assert_eq!(combination_project_name(template, None, &combo(None, "")).as_deref(), Some("base"));
+        assert_eq!(combination_project_name("{project}_{env}", Some("My Shop"), &combo(Some("Staging.EU"), "Staging.EU")).as_deref(), Some("my-shop_staging-eu"));
+        assert_eq!(combination_project_name("{env}", None, &combo(None, "monitoring")), None);
+    }
+
+    #[test]
+    fn test_project_name_template_sets_compose_name() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\nextensions = [\"monitoring\"]\nproject_name_template = \"{env}-{combo}\"\noutput_formats = [\"yaml\", \"json\"]\n").unwrap();
+            fs::create_dir_all(temp_path.join("components/base")).unwrap();
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "name: upstream\nservices:\n  web:\n    image: nginx\n").unwrap();
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).unwrap();
+
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            let names: Vec<(String, String)> = report.combinations.iter()
+                .map(|combination| {
+                    let compose: serde_yaml_ng::Value = serde_yaml_ng::from_str(&fs::read_to_string(&combination.compose_file).unwrap()).unwrap();
+                    (combination.output_dir.clone(), compose["name"].as_str().unwrap().to_string())
+                })
+                .collect();
+            assert_eq!(names, [
+                ("dev/base".to_string(), "dev-base".to_string()),
+                ("dev/monitoring".to_string(), "dev-monitoring".to_string()),
+                ("prod/base".to_string(), "prod-base".to_string()),
+                ("prod/monitoring".to_string(), "prod-monitoring".to_string()),
+            ]);
+
+            // The name is the first key and replaces the one from the components, in every output format
+            let compose = fs::read_to_string(temp_path.join("build/dev/monitoring/docker-compose.yml")).unwrap();
+            assert!(compose.trim_start_matches("---\n").starts_with("name: dev-monitoring\n"), "{}", compose);
+            assert!(!compose.contains("upstream"));
+            let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_path.join("build/prod/base/docker-compose.json")).unwrap()).unwrap();
+            assert_eq!(json["name"], "prod-base");
+        });
+    }
+
+    #[test]
+    fn test_generated_header_in_compose_and_env_example() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+environments = ["dev"]
+extensions = ["monitoring"]
+generated_header = """
+DO NOT EDIT - generated, see stacks/
+Combination: {combination} ({timestamp})
+"""
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/.env.example"), "PORT=80\n").expect("Failed to write env example");
+            create_test_compose(&temp_path.join("components/environments/dev/docker-compose.yml")).expect("Failed to create env compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            // A single environment is built into the build root, one folder per variant
+            let compose = fs::read_to_string(temp_path.join("build/monitoring/docker-compose.yml")).expect("Compose file should exist");
+            assert!(compose.starts_with("# DO NOT EDIT - generated, see stacks/\n# Combination: monitoring ("), "{}", compose);
+            assert!(compose.contains(" UTC)\n---\n"), "{}", compose);
+            let yaml: serde_yaml_ng::Value = serde_yaml_ng::from_str(&compose).expect("Header must keep the file valid YAML");
+            assert!(yaml.get("services").is_some());
+
+            let env_example = fs::read_to_string(temp_path.join("build/monitoring/.env.example")).expect(".env.example should exist");
+            assert!(env_example.starts_with("# DO NOT EDIT - generated, see stacks/\n# Combination: monitoring ("), "{}", env_example);
+            assert!(!env_example.contains("Generated by stackbuilder"));
+            assert!(env_example.contains("# Source files: base/.env.example"));
+        });
+    }
+
+    #[test]
+    fn test_provenance_header_in_compose_and_env_example() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+environments = ["dev"]
+extensions = ["monitoring"]
+generated_header = "DO NOT EDIT"
+provenance_header = true
+include_git_info = false
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/.env.example"), "PORT=80\n").expect("Failed to write env example");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            // The environment has no component of its own, so it is not listed as a source
+            let version = env!("CARGO_PKG_VERSION");
+            let compose = fs::read_to_string(temp_path.join("build/monitoring/docker-compose.yml")).expect("Compose file should exist");
+            let expected = format!("# DO NOT EDIT\n# Generated by stackbuilder {}\n# Environment: dev\n# Extensions: monitoring\n# Sources in merge order:\n#   components/base/docker-compose.yml\n#   components/extensions/monitoring/docker-compose.yml\n---\n", version);
+            assert!(compose.starts_with(&expected), "{}", compose);
+            assert!(!compose.contains("Components commit"));
+
+            let env_example = fs::read_to_string(temp_path.join("build/monitoring/.env.example")).expect(".env.example should exist");
+            assert!(env_example.contains("#   components/base/.env.example\n"), "{}", env_example);
+            assert!(!env_example.contains("# Source files:"), "{}", env_example);
+
+            // No timestamp: rebuilding from scratch gives identical files
+            execute_real_build_with_args_in_dir(temp_path, &crate::build::BuildArgs { force: true, ..Default::default() }).expect("Rebuild should succeed");
+            assert_eq!(fs::read_to_string(temp_path.join("build/monitoring/docker-compose.yml")).unwrap(), compose);
+        });
+    }
+
+    #[test]
+    fn test_provenance_header_lines() {
+        use crate::build::{provenance_header_lines, BuildCombination};
+
+        let combo = BuildCombination {
+            environment: None,
+            extensions: Vec::new(),
+            combo_names: vec!["security".to_string()],
+            output_dir: "security".to_string(),
+        };
+        let lines = provenance_header_lines(&combo, &["auth-guard".to_string()], &["base/docker-compose.yml".to_string()], Some("abc123"));
+        assert_eq!(lines[1..], [
+            "# Extensions: auth-guard",
+            "# Combos: security",
+            "# Components commit: abc123",
+            "# Sources in merge order:",
+            "#   base/docker-compose.yml",
+        ]);
+    }
+
+    #[test]
+    fn test_default_headers() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/.env.example"), "PORT=80\n").expect("Failed to write env example");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let compose = fs::read_to_string(temp_path.join("build/docker-compose.yml")).unwrap();
+            assert!(!compose.starts_with('#'), "{}", compose);
+            let env_example = fs::read_to_string(temp_path.join("build/.env.example")).unwrap();
+            assert!(env_example.starts_with("# Generated by stackbuilder from concatenated .env.example files\n"), "{}", env_example);
+        });
+    }
+
+    #[test]
+    fn test_merge_error_names_combination() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\nextensions = [\"monitoring\"]\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services:\n  web:\n    image: [nginx\n").unwrap();
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+
+            let error = execute_real_build_in_dir(temp_path).expect_err("Invalid base compose should fail the build");
+            assert_eq!(error.code(), "build.combination_failed");
+            assert_eq!(error.combination(), Some(("dev/base", Some("dev"), &[][..])));
+            assert!(error.to_string().starts_with("Failed to build combination 'dev/base' (environment: dev, no extensions): Failed to parse YAML file"), "{}", error);
+
+            let json = error.to_json();
+            assert_eq!(json["combination"]["output_dir"], "dev/base");
+            assert!(json["path"].as_str().unwrap().ends_with("components/base/docker-compose.yml"));
+            assert_eq!(json["line"], 4);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keep_going_builds_remaining_combinations() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\"]\nextensions = [\"broken\", \"monitoring\"]\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+            create_test_compose(&temp_path.join("components/extensions/broken/docker-compose.yml")).expect("Failed to create extension compose");
+            // An unreadable file fails only the combinations that use the extension
+            std::os::unix::fs::symlink("missing", temp_path.join("components/extensions/broken/dangling")).unwrap();
+
+            let error = execute_real_build_in_dir(temp_path).expect_err("Invalid extension should fail the build");
+            assert_eq!(error.code(), "build.combination_failed");
+            assert!(!temp_path.join("build").exists(), "A failing build without --keep-going should not write anything");
+
+            let args = crate::build::BuildArgs {
+                keep_going: true,
+                summary_file: Some("summary.json".into()),
+                ..Default::default()
+            };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("Failed combination should still fail the build");
+            assert_eq!(error.code(), "build.combinations_failed");
+            assert!(error.to_string().starts_with("1 of 3 combinations failed:"), "{}", error);
+            assert_eq!(error.to_json()["errors"][0]["combination"]["output_dir"], "broken");
+
+            let build_dir = temp_path.join("build");
+            assert!(build_dir.join("base/docker-compose.yml").exists());
+            assert!(build_dir.join("monitoring/docker-compose.yml").exists());
+            assert!(!build_dir.join("broken").exists(), "Failed combination should leave no output");
+
+            let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(temp_path.join("summary.json")).unwrap()).unwrap();
+            assert_eq!(summary["combinations"].as_array().unwrap().len(), 2);
+            assert_eq!(summary["failed"][0]["output_dir"], "broken");
+            assert_eq!(summary["failed"][0]["code"], "filesystem.file_read_failed");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keep_going_reports_failed_combinations_to_observers() {
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+        use crate::build::{build, BuildCombination, BuildOptions};
+        use crate::error::StackBuilderError;
+        use crate::observer::{BuildObserver, TimingObserver};
+
+        #[derive(Default)]
+        struct RecordingObserver {
+            events: Mutex<Vec<String>>,
+        }
+
+        impl BuildObserver for RecordingObserver {
+            fn on_combination_done(&self, combination: &BuildCombination, _duration: Duration) {
+                self.events.lock().unwrap().push(format!("done {}", combination.output_dir));
+            }
+
+            fn on_combination_failed(&self, combination: &BuildCombination, error: &StackBuilderError, _duration: Duration) {
+                self.events.lock().unwrap().push(format!("failed {} {}", combination.output_dir, error.code()));
+            }
+        }
+
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nextensions = [\"broken\", \"monitoring\"]\nfail_fast = false\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+            create_test_compose(&temp_path.join("components/extensions/broken/docker-compose.yml")).expect("Failed to create extension compose");
+            std::os::unix::fs::symlink("missing", temp_path.join("components/extensions/broken/dangling")).unwrap();
+
+            let recording = Arc::new(RecordingObserver::default());
+            let timings = Arc::new(TimingObserver::new(recording.clone()));
+            build(BuildOptions::new(temp_path).with_observer(timings.clone())).expect_err("Failed combination should still fail the build");
+
+            // Every combination ends with exactly one of the two events
+            let events = recording.events.lock().unwrap().clone();
+            assert!(events.contains(&"failed broken build.combination_failed".to_string()), "{:?}", events);
+            assert!(events.contains(&"done monitoring".to_string()), "{:?}", events);
+            assert!(!events.contains(&"done broken".to_string()), "{:?}", events);
+
+            let recorded = timings.timings();
+            let failed: Vec<&str> = recorded.combinations.iter().filter(|c| c.failed).map(|c| c.output_dir.as_str()).collect();
+            assert_eq!(failed, ["broken"]);
+            assert_eq!(recorded.combinations.len(), events.len());
+            assert!(recorded.format_table().contains("broken (failed)"), "{}", recorded.format_table());
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_keep_going_removes_partial_output_from_shared_directory() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = "[build]\nyaml_merger = \"rust\"\nextensions = [\"broken\", \"monitoring\"]\nkeep_going = true\n\n[build.aliases]\nmonitoring = \"broken/monitoring\"\n";
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+            create_test_compose(&temp_path.join("components/extensions/broken/docker-compose.yml")).expect("Failed to create extension compose");
+            std::os::unix::fs::symlink("missing", temp_path.join("components/extensions/broken/dangling")).unwrap();
+
+            // The directory of the failed combination also holds another one
+            let error = execute_real_build_in_dir(temp_path).expect_err("Failed combination should still fail the build");
+            assert_eq!(error.code(), "build.combinations_failed");
+
+            let build_dir = temp_path.join("build");
+            assert!(!build_dir.join("broken/docker-compose.yml").exists(), "Files of the failed combination should be rolled back");
+            assert!(build_dir.join("broken/monitoring/docker-compose.yml").exists());
+        });
+    }
+
+    #[test]
+    fn test_use_default_env_builds_only_default_environment() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\nextensions = [\"monitoring\"]\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create extension compose");
+
+            let args = crate::build::BuildArgs { use_default_env: true, ..Default::default() };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("--use-default-env needs default_environment");
+            assert_eq!(error.code(), "build.default_environment_not_set");
+
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\nextensions = [\"monitoring\"]\ndefault_environment = \"dev\"\n").expect("Failed to write config");
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Default environment build should succeed: {:?}", result);
+
+            // Output directories match those of a full build
+            assert!(temp_path.join("build/dev/base/docker-compose.yml").exists());
+            assert!(temp_path.join("build/dev/monitoring/docker-compose.yml").exists());
+            assert!(!temp_path.join("build/prod").exists(), "Other environments should not be built");
+
+            // Without the flag the configured default does not limit the build
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Full build should succeed: {:?}", result);
+            assert!(temp_path.join("build/prod/monitoring/docker-compose.yml").exists());
+        });
+    }
+
+    #[test]
+    fn test_extension_weight_overrides_list_order() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+
+# Both extensions in one combination so their merge order matters
+[build.combos]
+all = ["overrides", "app"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+
+            let overrides_dir = temp_path.join("components/extensions/overrides");
+            fs::create_dir_all(&overrides_dir).expect("Failed to create overrides dir");
+            fs::write(overrides_dir.join("docker-compose.yml"), "services:\n  test-service:\n    image: nginx:pinned\n").unwrap();
+            fs::write(overrides_dir.join("stackbuilder.meta.toml"), "weight = 100\n").unwrap();
+
+            let app_dir = temp_path.join("components/extensions/app");
+            fs::create_dir_all(&app_dir).expect("Failed to create app dir");
+            fs::write(app_dir.join("docker-compose.yml"), "services:\n  test-service:\n    image: nginx:latest\n").unwrap();
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let compose = fs::read_to_string(temp_path.join("build/docker-compose.yml")).expect("Combo output should exist");
+            assert!(compose.contains("image: \"nginx:pinned\""), "High-weight extension should win:\n{}", compose);
+            assert!(!temp_path.join("build/stackbuilder.meta.toml").exists(), "Metadata should not be copied");
+        });
+    }
+
+    #[test]
+    fn test_build_without_base_when_not_required() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+require_base = false
+
+[build.combos]
+stack = ["db", "app"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            let db_dir = temp_path.join("components/extensions/db");
+            fs::create_dir_all(&db_dir).expect("Failed to create db dir");
+            fs::write(db_dir.join("docker-compose.yml"), "services:\n  db:\n    image: postgres\n").unwrap();
+            fs::write(db_dir.join(".env.example"), "DB_PASSWORD=secret\n").unwrap();
+            create_test_compose(&temp_path.join("components/extensions/app/docker-compose.yml")).expect("Failed to create app compose");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build without base should succeed: {:?}", result);
+
+            let compose = fs::read_to_string(temp_path.join("build/docker-compose.yml")).expect("Combo output should exist");
+            assert!(compose.contains("db:"), "First extension should start the merge:\n{}", compose);
+            assert!(compose.contains("test-service:"), "Second extension should be merged:\n{}", compose);
+            let env = fs::read_to_string(temp_path.join("build/.env.example")).expect("Env file should exist");
+            assert!(env.contains("DB_PASSWORD=secret"));
+        });
+    }
+
+    #[test]
+    fn test_build_without_base_environment_layer_first() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[build]
+yaml_merger = "rust"
+require_base = false
+
+[build.environments]
+available = ["dev"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/environments/dev/docker-compose.yml")).expect("Failed to create dev compose");
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build without base should succeed: {:?}", result);
+
+            let compose = fs::read_to_string(temp_path.join("build/docker-compose.yml")).expect("Environment output should exist");
+            assert!(compose.contains("test-service:"));
+        });
+    }
+
+    #[test]
+    fn test_build_missing_base_fails_by_default() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n\n[build.combos]\nstack = [\"app\"]\n").expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/extensions/app/docker-compose.yml")).expect("Failed to create app compose");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_err(), "Missing base should fail when require_base is left at its default");
+        });
+    }
+
+    #[test]
+    fn test_relative_build_context_warning() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\", \"prod\"]\n").expect("Failed to write config");
+            fs::create_dir_all(temp_path.join("components/base")).expect("Failed to create base dir");
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services:\n  api:\n    build:\n      context: ./api\n").unwrap();
+            fs::write(temp_path.join("components/base/.env.example"), "API_PORT=8080\n").unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let summary_path = temp_path.join("build-report.json");
+            let args = crate::build::BuildArgs {
+                summary_file: Some(summary_path.clone()),
+                ..Default::default()
+            };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary_path).unwrap()).unwrap();
+            let warnings: Vec<&serde_json::Value> = report["warnings"].as_array().unwrap().iter()
+                .filter(|warning| warning["category"] == "build_context")
+                .collect();
+            assert_eq!(warnings.len(), 2, "One warning per combination: {:?}", warnings);
+            assert!(warnings[0]["message"].as_str().unwrap().contains("'./api'"));
+            assert_eq!(warnings[0]["combination"], "dev");
+
+            let compose = fs::read_to_string(temp_path.join("build/dev/docker-compose.yml")).unwrap();
+            assert!(compose.contains("context: \"./api\""), "Context should be left as written:\n{}", compose);
+        });
+    }
+
+    #[test]
+    fn test_strict_fails_on_configured_warning_categories() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").expect("Failed to write config");
+            fs::create_dir_all(temp_path.join("components/base")).expect("Failed to create base dir");
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services:\n  api:\n    build: ./api\n").unwrap();
+            fs::write(temp_path.join("components/base/.env.example"), "API_PORT=8080\n").unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build without --strict should succeed: {:?}", result);
+            let previous = fs::read_to_string(temp_path.join("build/docker-compose.yml")).unwrap();
+
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services:\n  api:\n    build: ./api\n    image: api\n").unwrap();
+            let args = crate::build::BuildArgs { strict: true, ..Default::default() };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            match result {
+                Err(crate::error::StackBuilderError::Build(crate::error::BuildError::StrictWarnings { count, ref categories })) => {
+                    assert_eq!(count, 1);
+                    assert_eq!(categories, &vec!["build_context".to_string()]);
+                }
+                other => panic!("Expected strict failure, got {:?}", other),
+            }
+            assert_eq!(fs::read_to_string(temp_path.join("build/docker-compose.yml")).unwrap(), previous, "Output should be left unchanged");
+
+            // Categories outside strict_categories do not fail the build
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nstrict_categories = [\"missing_file\"]\n").expect("Failed to write config");
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+        });
+    }
+
+    #[test]
+    fn test_build_exit_codes_distinguish_outcomes() {
+        run_in_temp_dir(|temp_path| {
+            fs::create_dir_all(temp_path.join("components/base")).expect("Failed to create base dir");
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services:\n  api:\n    build: ./api\n").unwrap();
+            fs::write(temp_path.join("components/base/.env.example"), "API_PORT=8080\n").unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            // Warnings alone do not fail a build without --strict
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").expect("Failed to write config");
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build with warnings should succeed: {:?}", result);
+
+            let args = crate::build::BuildArgs { strict: true, ..Default::default() };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).unwrap_err();
+            assert_eq!(error.exit_code(), 9, "{}", error);
+
+            // A broken configuration keeps its own exit code instead of a generic build failure
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = rust\n").expect("Failed to write config");
+            let error = execute_real_build_in_dir(temp_path).unwrap_err();
+            assert_eq!(error.code(), "config.invalid_toml_syntax");
+            assert_eq!(error.exit_code(), 1);
+        });
+    }
+
+    #[test]
+    fn test_library_build_resolves_paths_against_working_dir() {
+        use crate::build::{build, BuildArgs, BuildOptions};
+
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\ndedup_copies = \"hardlink\"\n").expect("Failed to write config");
+            fs::create_dir_all(temp_path.join("components/base")).expect("Failed to create base dir");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).unwrap();
+            fs::write(temp_path.join("components/base/app.conf"), "port=8080\n").unwrap();
+
+            // Runs without changing the process working directory
+            let args = BuildArgs { summary_file: Some("reports/build.json".into()), ..Default::default() };
+            let report = build(BuildOptions::new(temp_path).with_args(args)).expect("Library build should succeed");
+
+            assert_eq!(report.combinations.len(), 1);
+            assert_eq!(report.build_dir, temp_path.canonicalize().unwrap().join("build"));
+            assert!(temp_path.join("build/docker-compose.yml").exists());
+            assert!(temp_path.join("build/app.conf").exists());
+            assert!(temp_path.join("reports/build.json").exists());
+            assert!(temp_path.join(".stackbuilder/objects").is_dir(), "Object store should live in the project directory");
+
+            // Warnings are returned instead of only being printed
+            assert!(report.warnings.iter().any(|warning| warning.category == crate::diagnostics::WarningCategory::EnvExample));
+        });
+    }
+
+    #[test]
+    fn test_library_build_reports_events_to_observer() {
+        use std::path::Path;
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+        use crate::build::{build, BuildCombination, BuildOptions};
+        use crate::diagnostics::Warning;
+        use crate::observer::{BuildObserver, BuildPhase};
+
+        #[derive(Default)]
+        struct RecordingObserver {
+            events: Mutex<Vec<String>>,
+        }
+
+        impl BuildObserver for RecordingObserver {
+            fn on_build_start(&self, combinations: &[BuildCombination]) {
+                self.events.lock().unwrap().push(format!("build {}", combinations.len()));
+            }
+
+            fn on_combination_start(&self, combination: &BuildCombination) {
+                self.events.lock().unwrap().push(format!("start {}", combination.output_dir));
+            }
+
+            fn on_phase(&self, combination: &BuildCombination, phase: BuildPhase) {
+                self.events.lock().unwrap().push(format!("{} {}", phase.name(), combination.output_dir));
+            }
+
+            fn on_file_written(&self, path: &Path) {
+                let name = path.file_name().unwrap().to_string_lossy();
+                self.events.lock().unwrap().push(format!("file {}", name));
+            }
+
+            fn on_warning(&self, warning: &Warning) {
+                self.events.lock().unwrap().push(format!("warning {:?}", warning.category));
+            }
+
+            fn on_combination_done(&self, combination: &BuildCombination, _duration: Duration) {
+                self.events.lock().unwrap().push(format!("done {}", combination.output_dir));
+            }
+
+            fn on_build_done(&self) {
+                self.events.lock().unwrap().push("finished".to_string());
+            }
+        }
+
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\"]\n").expect("Failed to write config");
+            fs::create_dir_all(temp_path.join("components/base")).expect("Failed to create base dir");
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).unwrap();
+            fs::create_dir_all(temp_path.join("components/environments/dev")).expect("Failed to create dev dir");
+            create_test_compose(&temp_path.join("components/environments/dev/docker-compose.yml")).unwrap();
+
+            let observer = Arc::new(RecordingObserver::default());
+            build(BuildOptions::new(temp_path).with_observer(observer.clone())).expect("Library build should succeed");
+
+            let events = observer.events.lock().unwrap().clone();
+            let start = events.iter().position(|e| e == "start dev").expect("Combination start should be reported");
+            let file = events.iter().position(|e| e == "file docker-compose.yml").expect("Written compose file should be reported");
+            let done = events.iter().position(|e| e == "done dev").expect("Combination completion should be reported");
+            assert!(start < file && file < done, "Events out of order: {:?}", events);
+            let phases: Vec<&str> = events.iter()
+                .filter(|e| e.starts_with("build ") || (e.ends_with(" dev") && !e.starts_with("file ")))
+                .map(String::as_str)
+                .collect();
+            assert_eq!(phases, ["build 1", "start dev", "merging dev", "env dev", "copying dev", "done dev"]);
+            assert_eq!(events.last().map(String::as_str), Some("finished"), "The end of the build should be reported last: {:?}", events);
+            assert!(events.iter().any(|e| e == "warning EnvExample"), "Warnings should go to the observer: {:?}", events);
+        });
+    }
+
+
+    #[test]
+    fn test_progress_observer_keeps_build_output() {
+        use std::sync::{Arc, Mutex};
+        use crate::build::{build, BuildArgs, BuildOptions};
+        use crate::observer::BuildObserver;
+
+        /// Draws no bars, but takes the output the way a progress-drawing observer does
+        #[derive(Default)]
+        struct LineObserver {
+            lines: Mutex<Vec<String>>,
+        }
+
+        impl BuildObserver for LineObserver {
+            fn draws_progress(&self) -> bool {
+                true
+            }
+
+            fn print_line(&self, line: &str) {
+                self.lines.lock().unwrap().push(line.to_string());
+            }
+        }
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            fs::write(temp_path.join("build/dev/monitoring/.env"), "SECRET=1\n").unwrap();
+
+            let build_with = |args: BuildArgs| {
+                let observer = Arc::new(LineObserver::default());
+                build(BuildOptions::new(temp_path).with_args(args).with_print_output(true).with_observer(observer.clone()))
+                    .expect("Build should succeed");
+                let lines = observer.lines.lock().unwrap().clone();
+                lines
+            };
+
+            // The plan of a dry run is all it prints
+            let lines = build_with(BuildArgs { dry_run: true, ..Default::default() });
+            assert!(lines.iter().any(|line| line.starts_with("Would delete")), "{:?}", lines);
+            assert!(lines.iter().any(|line| line.starts_with("Would restore:") && line.contains(".env")), "{:?}", lines);
+
+            // Only the output of the combinations is held back while the bars are drawn
+            let lines = build_with(BuildArgs::default());
+            assert!(lines.iter().any(|line| line == "Starting build process..."), "{:?}", lines);
+            assert!(lines.iter().any(|line| line == "Build process completed successfully"), "{:?}", lines);
+            assert!(!lines.iter().any(|line| line.starts_with("Loaded and merging")), "{:?}", lines);
+        });
+    }
+    /// Output shared with the observer that writes it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_output_format_github() {
+        use clap::Parser;
+        use std::sync::Arc;
+        use crate::build::{build, BuildArgs, BuildOptions, LogFormat};
+        use crate::observer::GithubObserver;
+
+        let args = BuildArgs::try_parse_from(["build", "--output-format", "github"]).expect("--output-format github should parse");
+        assert_eq!(args.log_format(), LogFormat::Github);
+        // An explicit format wins over the GITHUB_ACTIONS detection
+        let args = BuildArgs::try_parse_from(["build", "--output-format", "text"]).unwrap();
+        assert_eq!(args.log_format(), LogFormat::Text);
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+
+            fs::remove_file(temp_path.join("components/environments/prod/docker-compose.yml")).unwrap();
+
+            let output = SharedBuffer::default();
+            let observer = GithubObserver::new().with_output(output.clone());
+            let options = BuildOptions::new(temp_path).with_observer(Arc::new(observer));
+            assert!(format!("{:?}", options).starts_with("BuildOptions { working_dir: "), "{:?}", options);
+            let report = build(options).expect("Build with workflow commands should succeed");
+            assert!(temp_path.join("build/dev/monitoring/docker-compose.yml").exists());
+
+            // Every combination gets a group of its own, closed before the next one opens
+            let output = output.contents();
+            let commands: Vec<&str> = output.lines().filter(|line| line.starts_with("::group::") || *line == "::endgroup::").collect();
+            assert_eq!(commands.len(), report.combinations.len() * 2, "{}", output);
+            for pair in commands.chunks(2) {
+                assert!(pair[0].starts_with("::group::Combination ") && pair[1] == "::endgroup::", "{}", output);
+            }
+            assert!(output.lines().any(|line| line.starts_with("::warning file=") && line.contains("components/environments/prod/docker-compose.yml,title=stackbuilder missing_file::")), "{}", output);
+        });
+    }
+
+    #[test]
+    fn test_rewrite_build_context() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nrewrite_build_context = true\nenvironments = [\"dev\", \"prod\"]\n").expect("Failed to write config");
+            fs::create_dir_all(temp_path.join("components/base")).expect("Failed to create base dir");
+            fs::write(temp_path.join("components/base/docker-compose.yml"), "services:\n  api:\n    build: ./api\n").unwrap();
+            fs::write(temp_path.join("components/base/.env.example"), "API_PORT=8080\n").unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions")).expect("Failed to create extensions dir");
+
+            let summary_path = temp_path.join("build-report.json");
+            let args = crate::build::BuildArgs {
+                summary_file: Some(summary_path.clone()),
+                ..Default::default()
+            };
+            let result = execute_real_build_with_args_in_dir(temp_path, &args);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary_path).unwrap()).unwrap();
+            assert!(report["warnings"].as_array().unwrap().iter().all(|warning| warning["category"] != "build_context"));
+
+            let compose = fs::read_to_string(temp_path.join("build/dev/docker-compose.yml")).unwrap();
+            assert!(compose.contains("build: \"../../components/base/api\""), "Context should point back to the component:\n{}", compose);
+        });
+    }
+
+    fn create_inline_combination_project(temp_path: &std::path::Path, separator: Option<&str>) {
+        let separator_line = separator.map(|sep| format!("combo_name_separator = \"{}\"\n", sep)).unwrap_or_default();
+        let config_content = format!("[build]\nyaml_merger = \"rust\"\n{}environments = [\"dev\", \"prod\"]\nextensions = [\"monitoring+auth\", \"monitoring\"]\n", separator_line);
+        fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+        create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+        fs::write(temp_path.join("components/base/.env.example"), "PORT=80\n").unwrap();
+        fs::create_dir_all(temp_path.join("components/extensions/monitoring")).unwrap();
+        fs::write(temp_path.join("components/extensions/monitoring/docker-compose.yml"), "services:\n  prometheus:\n    image: prom/prometheus\n").unwrap();
+        fs::create_dir_all(temp_path.join("components/extensions/auth")).unwrap();
+        fs::write(temp_path.join("components/extensions/auth/docker-compose.yml"), "services:\n  auth:\n    image: keycloak\n").unwrap();
+    }
+
+    #[test]
+    fn test_inline_extension_combination_default_separator() {
+        run_in_temp_dir(|temp_path| {
+            create_inline_combination_project(temp_path, None);
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Build should succeed: {:?}", result);
+
+            let compose = fs::read_to_string(temp_path.join("build/dev/monitoring+auth/docker-compose.yml")).expect("Inline combination output should exist");
+            assert!(compose.contains("prometheus:") && compose.contains("auth:"), "Both extensions should be merged:\n{}", compose);
+            let single = fs::read_to_string(temp_path.join("build/prod/monitoring/docker-compose.yml")).expect("Single extension output should exist");
+            assert!(!single.contains("auth:"));
+        });
+    }
+
+    #[test]
+    fn test_inline_extension_combination_custom_separators() {
+        for separator in ["_", "-"] {
+            run_in_temp_dir(|temp_path| {
+                create_inline_combination_project(temp_path, Some(separator));
+
+                let result = execute_real_build_in_dir(temp_path);
+                assert!(result.is_ok(), "Build with separator '{}' should succeed: {:?}", separator, result);
+
+                let output_dir = temp_path.join(format!("build/dev/monitoring{}auth", separator));
+                assert!(output_dir.join("docker-compose.yml").exists(), "Expected output in {:?}", output_dir);
+                assert!(!temp_path.join("build/dev/monitoring+auth").exists());
+            });
+        }
+    }
+
+    #[test]
+    fn test_combo_name_separator_rejects_path_separator() {
+        run_in_temp_dir(|temp_path| {
+            create_inline_combination_project(temp_path, Some("/"));
+
+            let result = execute_real_build_in_dir(temp_path);
+            let error = result.expect_err("A path separator must not be accepted as combo_name_separator");
+            assert!(error.to_string().contains("combo_name_separator"), "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_list_files_matches_build_output() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            fs::write(temp_path.join("components/base/.env.example"), "APP=1\n").unwrap();
+            fs::write(temp_path.join("components/base/app.conf"), "base").unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions/monitoring/config")).unwrap();
+            fs::write(temp_path.join("components/extensions/monitoring/config/prometheus.yml"), "scrape_configs: []").unwrap();
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            let resolved = crate::config::resolve_paths_in(&mut config, temp_path);
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+
+            let files = crate::build::list_combination_files(&config, &resolved, &combinations, "dev/monitoring").unwrap();
+            assert!(!temp_path.join("build").exists(), "Listing files must not write anything");
+
+            let build_dir = temp_path.join("build/dev/monitoring");
+            let paths: Vec<_> = files.iter().map(|file| file.path.clone()).collect();
+            assert_eq!(paths, vec![
+                build_dir.join("docker-compose.yml"),
+                build_dir.join(".env.example"),
+                build_dir.join("app.conf"),
+                build_dir.join("config/prometheus.yml"),
+            ]);
+            assert_eq!(files[3].source, "extension:monitoring");
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            for path in &paths {
+                assert!(path.exists(), "Listed file should be produced by the build: {}", path.display());
+            }
+
+            let error = crate::build::list_combination_files(&config, &resolved, &combinations, "staging").unwrap_err().to_string();
+            assert!(error.contains("Unknown build combination 'staging'"), "{}", error);
+            assert!(error.contains("dev/monitoring"), "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_env_source_and_output_filenames() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\nenv_source_filename = \".env.template\"").expect("Failed to create test project");
+            fs::write(temp_path.join("components/base/.env.template"), "APP_PORT=8080\n").unwrap();
+            fs::write(temp_path.join("components/environments/dev/.env.template"), "APP_ENV=dev\n").unwrap();
+            // Only the configured source file is read
+            fs::write(temp_path.join("components/base/.env.example"), "IGNORED=1\n").unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            let dev_dir = temp_path.join("build/dev/base");
+            let merged = fs::read_to_string(dev_dir.join(".env.example")).expect(".env.example should be written");
+            assert!(merged.contains("APP_PORT=8080") && merged.contains("APP_ENV=dev"), "{}", merged);
+            assert!(merged.contains("base/.env.template"), "{}", merged);
+            assert!(!merged.contains("IGNORED"), "{}", merged);
+            assert!(!dev_dir.join(".env.template").exists(), "The source file must not be copied as an additional file");
+
+            // The output name is configurable independently
+            add_build_options(temp_path, "env_output_filename = \".env.defaults\"").expect("Failed to update config");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(fs::read_to_string(dev_dir.join(".env.defaults")).unwrap().contains("APP_ENV=dev"));
+            assert!(!dev_dir.join(".env.example").exists());
+        });
+    }
+
+    #[test]
+    fn test_layers_output_mode_copies_compose_files() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            // No merger runs in layers mode, so the default yq merger needs no yq
+            add_build_options(temp_path, "output_mode = \"layers\"").expect("Failed to update config");
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let dir = temp_path.join("build/dev/monitoring");
+            assert!(!dir.join("docker-compose.yml").exists(), "Nothing should be merged");
+            assert_eq!(fs::read_to_string(dir.join("00-base.yml")).unwrap(), fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap());
+            assert_eq!(fs::read_to_string(dir.join("10-env-dev.yml")).unwrap(), fs::read_to_string(temp_path.join("components/environments/dev/docker-compose.yml")).unwrap());
+            assert_eq!(fs::read_to_string(dir.join("20-ext-monitoring.yml")).unwrap(), fs::read_to_string(temp_path.join("components/extensions/monitoring/docker-compose.yml")).unwrap());
+
+            let script = fs::read_to_string(dir.join("compose.sh")).unwrap();
+            assert!(script.starts_with("#!/bin/sh\n"), "{}", script);
+            assert!(script.contains("export COMPOSE_FILE=\"00-base.yml:10-env-dev.yml:20-ext-monitoring.yml\"\n"), "{}", script);
+            assert!(script.ends_with("exec docker-compose \"$@\"\n"), "{}", script);
+            assert!(script.contains("    exec docker compose \"$@\"\n"), "{}", script);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                assert_eq!(fs::metadata(dir.join("compose.sh")).unwrap().permissions().mode() & 0o111, 0o111);
+            }
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            let resolved = crate::config::resolve_paths_in(&mut config, temp_path);
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            let files = crate::build::list_combination_files(&config, &resolved, &combinations, "prod/base").unwrap();
+            let names: Vec<_> = files.iter().map(|file| file.path.file_name().unwrap().to_string_lossy().to_string()).collect();
+            assert_eq!(names, ["compose.sh", "00-base.yml", "10-env-prod.yml"]);
+
+            // Layers are not merged, but they are still validated
+            fs::write(temp_path.join("components/extensions/monitoring/docker-compose.yml"), "services: [unclosed").unwrap();
+            let error = execute_real_build_in_dir(temp_path).expect_err("Broken layer should fail the build");
+            assert_eq!(error.combination().map(|(output_dir, _, _)| output_dir), Some("dev/monitoring"));
+            assert!(error.to_string().contains("Failed to parse YAML file"), "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_layers_output_mode_rewrites_relative_paths() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "output_mode = \"layers\"\nrewrite_build_context = true").expect("Failed to create test project");
+            let monitoring = "services:\n  monitoring:\n    build: ./exporter\n    env_file: monitoring.env\n    volumes:\n      - ./prometheus.yml:/etc/prometheus/prometheus.yml:ro\n      - ../../shared:/shared\n";
+            fs::write(temp_path.join("components/extensions/monitoring/docker-compose.yml"), monitoring).unwrap();
+            fs::write(temp_path.join("components/extensions/monitoring/monitoring.env"), "LEVEL=info\n").unwrap();
+            fs::write(temp_path.join("components/extensions/monitoring/prometheus.yml"), "global: {}\n").unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let dir = temp_path.join("build/dev/monitoring");
+            let layer: serde_yaml_ng::Value = serde_yaml_ng::from_str(&fs::read_to_string(dir.join("20-ext-monitoring.yml")).unwrap()).unwrap();
+            let service = &layer["services"]["monitoring"];
+            assert_eq!(service["build"].as_str(), Some("../../../components/extensions/monitoring/exporter"));
+            // Copied next to the layers
+            assert_eq!(service["env_file"].as_str(), Some("monitoring.env"));
+            assert!(dir.join("monitoring.env").exists());
+            assert_eq!(service["volumes"][0].as_str(), Some("./prometheus.yml:/etc/prometheus/prometheus.yml:ro"));
+            assert_eq!(service["volumes"][1].as_str(), Some("../../../components/shared:/shared"));
+
+            // Layers without such paths are still copied verbatim
+            assert_eq!(fs::read_to_string(dir.join("00-base.yml")).unwrap(), fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_output_dir_collisions_fail_before_cleaning() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            let config_path = temp_path.join("stackbuilder.toml");
+            let original = fs::read_to_string(&config_path).unwrap();
+            fs::create_dir_all(temp_path.join("components/extensions/base")).unwrap();
+            fs::write(temp_path.join("components/extensions/base/docker-compose.yml"), "services: {}\n").unwrap();
+            fs::create_dir_all(temp_path.join("build/dev/base")).unwrap();
+            fs::write(temp_path.join("build/dev/base/docker-compose.yml"), "previous build\n").unwrap();
+
+            let collisions = [
+                // A combo named like an extension
+                ("extensions = [\"monitoring\"]\n[build.combos]\nmonitoring = [\"monitoring\"]\n", "dev/monitoring", "extension 'monitoring'", "combo 'monitoring'"),
+                // An extension named base next to the environment's base variant
+                ("extensions = [\"base\"]\n", "dev/base", "base (environment: dev, no extensions)", "extension 'base' (environment: dev, extensions: base)"),
+                // The same extension listed twice
+                ("extensions = [\"monitoring\", \"monitoring\"]\n", "dev/monitoring", "extension 'monitoring'", "extension 'monitoring'"),
+            ];
+            for (build_options, output_dir, first, second) in collisions {
+                let content = original.replace("extensions = [\"monitoring\"]\n", &format!("yaml_merger = \"rust\"\n{}", build_options));
+                fs::write(&config_path, content).unwrap();
+
+                let error = execute_real_build_in_dir(temp_path).expect_err("Colliding output directories must fail the build");
+                assert_eq!(error.code(), "validation.output_dir_collision", "{}", error);
+                let message = error.to_string();
+                assert!(message.contains(&format!("'{}'", output_dir)), "{}", message);
+                assert!(message.contains(first) && message.contains(second), "{}", message);
+                assert_eq!(fs::read_to_string(temp_path.join("build/dev/base/docker-compose.yml")).unwrap(), "previous build\n");
+            }
+        });
+    }
+
+    #[test]
+    fn test_generate_helper_scripts() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\ngenerate_helper_scripts = true").expect("Failed to create test project");
+            fs::write(
+                temp_path.join("components/base/docker-compose.yml"),
+                "services:\n  test-service:\n    image: \"nginx:${TAG:-latest}\"\n    environment:\n      - DB_PASSWORD=${DB_PASSWORD:?required}\n",
+            ).unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let dir = temp_path.join("build/dev/monitoring");
+            let up = fs::read_to_string(dir.join("up.sh")).expect("up.sh should be written");
+            assert!(up.contains("for var in DB_PASSWORD; do"), "{}", up);
+            assert!(up.contains("set -- up -d \"$@\""), "{}", up);
+            assert!(up.contains("set -- -f docker-compose.yml --project-name \"dev-monitoring\" \"$@\""), "{}", up);
+            assert!(up.contains("    exec docker compose \"$@\""), "{}", up);
+            let down = fs::read_to_string(dir.join("down.sh")).expect("down.sh should be written");
+            assert!(down.contains("set -- down \"$@\""), "{}", down);
+
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                use std::process::Command;
+                assert_eq!(fs::metadata(dir.join("up.sh")).unwrap().permissions().mode() & 0o111, 0o111);
+
+                // A stand-in docker prints its arguments
+                let bin = temp_path.join("bin");
+                fs::create_dir_all(&bin).unwrap();
+                fs::write(bin.join("docker"), "#!/bin/sh\necho \"$@\"\n").unwrap();
+                fs::set_permissions(bin.join("docker"), fs::Permissions::from_mode(0o755)).unwrap();
+                let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default());
+
+                let refused = Command::new("sh").arg(dir.join("up.sh")).env("PATH", &path).env_remove("DB_PASSWORD").output().unwrap();
+                assert!(!refused.status.success());
+                assert!(String::from_utf8_lossy(&refused.stderr).contains("required variables are not set: DB_PASSWORD"));
+
+                fs::write(dir.join(".env"), "DB_PASSWORD=secret\n").unwrap();
+                let ran = Command::new("sh").arg(dir.join("up.sh")).arg("app").env("PATH", &path).env_remove("DB_PASSWORD").output().unwrap();
+                assert!(ran.status.success(), "{}", String::from_utf8_lossy(&ran.stderr));
+                assert_eq!(String::from_utf8_lossy(&ran.stdout), "compose -f docker-compose.yml --project-name dev-monitoring --env-file .env up -d app\n");
+                fs::remove_file(dir.join(".env")).unwrap();
+            }
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            let resolved = crate::config::resolve_paths_in(&mut config, temp_path);
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            let files = crate::build::list_combination_files(&config, &resolved, &combinations, "prod/base").unwrap();
+            let names: Vec<_> = files.iter().map(|file| file.path.file_name().unwrap().to_string_lossy().to_string()).collect();
+            assert_eq!(names[..3], ["docker-compose.yml", "up.sh", "down.sh"]);
+
+            // Teams can replace the script template
+            fs::write(temp_path.join("helper.sh.tmpl"), "#!/bin/sh\n# {{combination}}\ndocker compose {{compose_files}} -p {{project_name}} {{command}}\n").unwrap();
+            add_build_options(temp_path, "helper_script_template = \"helper.sh.tmpl\"").expect("Failed to update config");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert_eq!(
+                fs::read_to_string(temp_path.join("build/prod/base/down.sh")).unwrap(),
+                "#!/bin/sh\n# prod/base\ndocker compose -f docker-compose.yml -p prod-base down\n",
+            );
+        });
+    }
+
+    #[test]
+    fn test_watch_fingerprint_and_serve_command() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            let args = crate::build::BuildArgs::default();
+            let fingerprint = |only| crate::build::input_fingerprint(&args, temp_path, only).unwrap();
+
+            let before = fingerprint(Some("dev/monitoring"));
+            assert_eq!(before.combination.as_deref(), Some("dev/monitoring"));
+            assert_eq!(fingerprint(Some("dev/monitoring")), before);
+            let prod = fingerprint(Some("prod/base"));
+
+            // Only the inputs of the watched combination count
+            fs::write(temp_path.join("components/environments/dev/docker-compose.yml"), "services:\n  test-service:\n    environment:\n      - ENV=changed\n").unwrap();
+            assert_ne!(fingerprint(Some("dev/monitoring")), before);
+            assert_eq!(fingerprint(Some("prod/base")), prod);
+
+            let error = crate::build::input_fingerprint(&args, temp_path, Some("staging/base")).unwrap_err();
+            assert_eq!(error.code(), "build.unknown_combination", "{}", error);
+
+            let command = crate::watch::serve_command(&["docker", "compose"], &temp_path.join("build/dev/monitoring/docker-compose.yml"));
+            assert_eq!(command.get_program(), "docker");
+            let command_args: Vec<_> = command.get_args().map(|arg| arg.to_string_lossy().to_string()).collect();
+            assert_eq!(command_args, [
+                "compose", "-f", &temp_path.join("build/dev/monitoring/docker-compose.yml").to_string_lossy(),
+                "up", "-d", "--remove-orphans",
+            ]);
+            assert_eq!(command.get_current_dir(), Some(temp_path.join("build/dev/monitoring").as_path()));
+
+            // output_mode = "layers" brings the stack up through its compose.sh
+            let command = crate::watch::serve_command(&["docker-compose"], &temp_path.join("build/dev/monitoring/compose.sh"));
+            assert_eq!(command.get_program(), "sh");
+            let command_args: Vec<_> = command.get_args().map(|arg| arg.to_string_lossy().to_string()).collect();
+            assert_eq!(command_args, [&*temp_path.join("build/dev/monitoring/compose.sh").to_string_lossy(), "up", "-d", "--remove-orphans"]);
+
+            // watch reloads the configuration on every check, which stdin can only give once
+            let watch = <crate::watch::WatchArgs as clap::Parser>::try_parse_from(["watch", "--config", "-"]).unwrap();
+            let error = crate::watch::run_watch(&watch).unwrap_err();
+            assert_eq!(error.code(), "build.watch_config_from_stdin", "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_combination_variables() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            let config_path = temp_path.join("stackbuilder.toml");
+            fs::write(&config_path, r#"
+[build]
+yaml_merger = "rust"
+provenance_header = true
+combos = { full = ["monitoring"] }
+
+[build.variables]
+REPLICAS = "1"
+TAG = "stable"
+
+[build.combo_variables.full]
+REPLICAS = "5"
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.prod]
+combos = ["full"]
+
+[build.environments.prod.variables]
+REPLICAS = "3"
+GREETING = "hello world"
+"#).unwrap();
+            fs::write(
+                temp_path.join("components/base/docker-compose.yml"),
+                "services:\n  test-service:\n    image: \"nginx:${TAG:-latest}\"\n    command: [\"echo\", \"$${TAG}\", \"${GREETING}\"]\n    hostname: web-${REPLICAS}\n",
+            ).unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            // The combo overrides the environment, which overrides [build.variables]
+            let env = fs::read_to_string(temp_path.join("build/prod/full/.env")).expect(".env should be written");
+            assert!(env.starts_with(crate::variables::VARIABLES_ENV_MARKER), "{}", env);
+            assert!(env.contains("\nGREETING='hello world'\nREPLICAS=5\nTAG=stable\n"), "{}", env);
+            assert!(env.contains("#   REPLICAS from combo 'full'\n"), "{}", env);
+            assert!(env.contains("#   GREETING from environment 'prod'\n"), "{}", env);
+            let env = fs::read_to_string(temp_path.join("build/prod/base/.env")).unwrap();
+            assert!(env.contains("\nREPLICAS=3\n"), "{}", env);
+            let env = fs::read_to_string(temp_path.join("build/dev/base/.env")).unwrap();
+            assert!(env.contains("\nREPLICAS=1\nTAG=stable\n") && !env.contains("GREETING"), "{}", env);
+
+            // A hand-written .env keeps its values and takes in the variables it does not set
+            fs::write(temp_path.join("build/prod/base/.env"), "# operator\nREPLICAS=7\nSECRET=x\n").unwrap();
+            for _ in 0..2 {
+                let report = crate::build::build(crate::build::BuildOptions::new(temp_path)).expect("Build should succeed");
+                assert!(report.env_files_backed_up.is_empty(), "{:?}", report.env_files_backed_up);
+                let env = fs::read_to_string(temp_path.join("build/prod/base/.env")).unwrap();
+                assert!(env.starts_with(&format!("# operator\nREPLICAS=7\nSECRET=x\n{}\n", crate::variables::VARIABLES_ENV_MARKER)), "{}", env);
+                assert!(env.contains("\nGREETING='hello world'\nTAG=stable\n") && !env.contains("REPLICAS=3"), "{}", env);
+            }
+
+            // Without --resolve-env the references are left to docker compose, the header documents the variables
+            let compose = fs::read_to_string(temp_path.join("build/prod/full/docker-compose.yml")).unwrap();
+            assert!(compose.contains("nginx:${TAG:-latest}"), "{}", compose);
+            assert!(compose.contains("# Variables:\n#   GREETING from environment 'prod'\n#   REPLICAS from combo 'full'\n#   TAG from [build.variables]\n"), "{}", compose);
+
+            let args = crate::build::BuildArgs { resolve_env: true, ..Default::default() };
+            execute_real_build_with_args_in_dir(temp_path, &args).expect("Build should succeed");
+            let compose: serde_yaml_ng::Value = serde_yaml_ng::from_str(&fs::read_to_string(temp_path.join("build/prod/full/docker-compose.yml")).unwrap()).unwrap();
+            let service = &compose["services"]["test-service"];
+            assert_eq!(service["image"].as_str(), Some("nginx:stable"));
+            assert_eq!(service["hostname"].as_str(), Some("web-5"));
+            // $$ escapes a literal dollar
+            assert_eq!(service["command"][1].as_str(), Some("$${TAG}"));
+            assert_eq!(service["command"][2].as_str(), Some("hello world"));
+
+            // The generated .env is written again rather than preserved or backed up
+            fs::write(&config_path, fs::read_to_string(&config_path).unwrap().replace("REPLICAS = \"5\"", "REPLICAS = \"6\"")).unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(!report.warnings.iter().any(|warning| warning.category == crate::diagnostics::WarningCategory::Cleanup), "{:?}", report.warnings);
+            assert!(fs::read_to_string(temp_path.join("build/prod/full/.env")).unwrap().contains("\nREPLICAS=6\n"));
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            let resolved = crate::config::resolve_paths_in(&mut config, temp_path);
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            let files = crate::build::list_combination_files(&config, &resolved, &combinations, "prod/full").unwrap();
+            assert!(files.iter().any(|file| file.path.ends_with("prod/full/.env") && file.source == "combination variables"), "{:?}", files);
+        });
+    }
+
+    /// Build a project with a `logging` extension and a preserved `.env` in `build/dev/logging`, then drop the extension from the config
+    fn create_project_with_dropped_extension(temp_path: &std::path::Path) {
+        create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+        let config_path = temp_path.join("stackbuilder.toml");
+        fs::write(&config_path, fs::read_to_string(&config_path).unwrap().replace("[\"monitoring\"]", "[\"monitoring\", \"logging\"]")).unwrap();
+        fs::create_dir_all(temp_path.join("components/extensions/logging")).unwrap();
+        fs::write(temp_path.join("components/extensions/logging/docker-compose.yml"), "services:\n  logging:\n    image: fluentd\n").unwrap();
+        execute_real_build_in_dir(temp_path).expect("Build should succeed");
+        fs::write(temp_path.join("build/dev/logging/.env"), "SECRET=1\n").unwrap();
+        fs::write(&config_path, fs::read_to_string(&config_path).unwrap().replace(", \"logging\"", "")).unwrap();
+    }
+
+    #[test]
+    fn test_stale_combinations_are_reported_or_kept() {
+        run_in_temp_dir(|temp_path| {
+            // Dropping an extension prunes its combinations and reports the preserved .env
+            create_project_with_dropped_extension(temp_path);
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let pruned: Vec<&str> = report.pruned.iter().map(|pruned| pruned.output_dir.as_str()).collect();
+            assert_eq!(pruned, ["dev/logging", "prod/logging"]);
+            assert!(report.pruned.iter().all(|pruned| !pruned.kept));
+            let dev = &report.pruned[0];
+            assert_eq!((dev.environment.as_deref(), dev.extensions.as_slice()), (Some("dev"), ["logging".to_string()].as_slice()));
+            assert_eq!(dev.env_files_backed_up.len() + dev.env_files_relocated.len(), 1, "{:?}", dev);
+            assert!(!temp_path.join("build/dev/logging").exists());
+            assert!(report.format_pruned().unwrap().contains("dev/logging"));
+
+            // Pruned combinations are gone from the manifest, so the next build reports nothing
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(report.pruned.is_empty() && report.format_pruned().is_none());
+        });
+    }
+
+    #[test]
+    fn test_keep_stale_carries_outputs_over() {
+        run_in_temp_dir(|temp_path| {
+            create_project_with_dropped_extension(temp_path);
+
+            let args = crate::build::BuildArgs { keep_stale: true, ..Default::default() };
+            for _ in 0..2 {
+                let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Build should succeed");
+                let kept: Vec<&str> = report.pruned.iter().filter(|pruned| pruned.kept).map(|pruned| pruned.output_dir.as_str()).collect();
+                assert_eq!(kept, ["dev/logging", "prod/logging"]);
+                assert!(temp_path.join("build/dev/logging/docker-compose.yml").exists());
+                assert_eq!(fs::read_to_string(temp_path.join("build/dev/logging/.env")).unwrap(), "SECRET=1\n");
+                assert!(report.format_pruned().unwrap().contains("kept by --keep-stale"));
+            }
+
+            // The manifest still knows the kept directories, so a later build without the flag prunes them
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert_eq!(report.pruned.len(), 2);
+            assert!(!temp_path.join("build/prod/logging").exists());
+        });
+    }
+
+    #[test]
+    fn test_stale_combinations_outside_the_build_directory_are_ignored() {
+        run_in_temp_dir(|temp_path| {
+            create_project_with_dropped_extension(temp_path);
+            fs::create_dir_all(temp_path.join("outside")).unwrap();
+            fs::write(temp_path.join("outside/keep.txt"), "keep\n").unwrap();
+
+            // A hand-edited manifest must not make a build prune or move directories outside the build directory
+            let manifest_path = temp_path.join("build").join(crate::build_report::COMBINATIONS_MANIFEST_FILE);
+            let mut manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+            let combinations = manifest["combinations"].as_array_mut().unwrap();
+            for output_dir in ["../outside", temp_path.join("outside").to_str().unwrap()] {
+                let mut escaping = combinations[0].clone();
+                escaping["output_dir"] = output_dir.into();
+                combinations.push(escaping);
+            }
+            fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+
+            for keep_stale in [true, false] {
+                let args = crate::build::BuildArgs { keep_stale, ..Default::default() };
+                let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Build should succeed");
+                let pruned: Vec<&str> = report.pruned.iter().map(|pruned| pruned.output_dir.as_str()).collect();
+                assert_eq!(pruned, ["dev/logging", "prod/logging"]);
+                assert_eq!(report.warnings.iter().filter(|warning| warning.message.contains("not inside the build directory")).count(), 2, "{:?}", report.warnings);
+                assert_eq!(fs::read_to_string(temp_path.join("outside/keep.txt")).unwrap(), "keep\n");
+                assert!(!temp_path.join("build/outside").exists());
+                fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+            }
+        });
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_pre_build_command_generates_components() {
+        run_in_temp_dir(|temp_path| {
+            let config_path = temp_path.join("stackbuilder.toml");
+            // Fails unless the build lock is already held
+            let generate = "test -f .stackbuilder/build.lock && mkdir -p components/extensions/generated && printf 'services:\\n  generated:\\n    image: busybox\\n' > components/extensions/generated/docker-compose.yml && echo generated";
+            create_test_project_with(temp_path, &format!("yaml_merger = \"rust\"\npre_build_command = \"{}\"", generate)).expect("Failed to create test project");
+            fs::write(&config_path, fs::read_to_string(&config_path).unwrap().replace("[\"monitoring\"]", "[\"monitoring\", \"generated\"]")).unwrap();
+
+            // The extension only exists once the command ran, validation included
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let compose = fs::read_to_string(temp_path.join("build/dev/generated/docker-compose.yml")).unwrap();
+            assert!(compose.contains("image: busybox"), "{}", compose);
+
+            // --skip-pre-build builds from the components as they are
+            fs::remove_dir_all(temp_path.join("components/extensions/generated")).unwrap();
+            let args = crate::build::BuildArgs { dry_run: true, ..Default::default() };
+            execute_real_build_with_args_in_dir(temp_path, &args).expect("Dry run should succeed");
+            assert!(!temp_path.join("components/extensions/generated").exists(), "A dry run should not run the command");
+
+            let args = crate::build::BuildArgs { skip_pre_build: true, ..Default::default() };
+            let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Build should succeed");
+            assert!(report.warnings.iter().any(|warning| warning.message == "Extension 'generated' not found in any extensions directory"), "{:?}", report.warnings);
+
+            fs::write(&config_path, fs::read_to_string(&config_path).unwrap().replace(generate, "echo broken >&2; exit 3")).unwrap();
+            let error = execute_real_build_in_dir(temp_path).expect_err("The pre-build command fails");
+            assert_eq!(error.code(), "build.pre_build_command_failed");
+            assert_eq!(error.to_string(), "Pre-build command 'echo broken >&2; exit 3' exited with code 3\n  broken");
+        });
+    }
+
+    #[test]
+    fn test_aliases_rename_output_directories() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            let config_path = temp_path.join("stackbuilder.toml");
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            fs::write(temp_path.join("build/prod/monitoring/.env"), "SECRET=1\n").unwrap();
+
+            fs::write(&config_path, fs::read_to_string(&config_path).unwrap() + "\n[build.aliases]\n\"prod/monitoring\" = \"prod-monitoring-stack\"\n").unwrap();
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert!(temp_path.join("build/prod-monitoring-stack/docker-compose.yml").exists());
+            assert!(!temp_path.join("build/prod/monitoring").exists());
+            // The .env of the planned directory follows the combination to its alias
+            assert_eq!(fs::read_to_string(temp_path.join("build/prod-monitoring-stack/.env")).unwrap(), "SECRET=1\n");
+            assert_eq!(report.pruned.len(), 1);
+            assert_eq!(report.pruned[0].env_files_relocated, [temp_path.join("build/prod-monitoring-stack/.env")]);
+
+            let manifest = crate::build_report::CombinationsManifest::load(&temp_path.join("build")).unwrap();
+            let aliased = manifest.combinations.iter().find(|combo| combo.output_dir == "prod-monitoring-stack").unwrap();
+            assert_eq!(aliased.alias_of.as_deref(), Some("prod/monitoring"));
+
+            // Either name selects the combination
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            let resolved = crate::config::resolve_paths_in(&mut config, temp_path);
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            for name in ["prod/monitoring", "prod-monitoring-stack"] {
+                let files = crate::build::list_combination_files(&config, &resolved, &combinations, name).unwrap();
+                assert!(files[0].path.ends_with("build/prod-monitoring-stack/docker-compose.yml"), "{:?}", files);
+            }
+
+            // An alias onto another combination's directory collides like any duplicate output directory
+            config.build.aliases.insert("dev/monitoring".to_string(), "dev/base".to_string());
+            let error = crate::build::determine_build_combinations(&config).unwrap_err();
+            assert_eq!(error.code(), "validation.output_dir_collision");
+        });
+    }
+
+    #[test]
+    fn test_kubernetes_output_converts_with_kompose() {
+        use crate::diagnostics::WarningCategory;
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\nkubernetes_output = true").expect("Failed to create test project");
+            let config_path = temp_path.join("stackbuilder.toml");
+
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let kubernetes_warnings: Vec<_> = report.warnings.iter().filter(|warning| warning.category == WarningCategory::Kubernetes).collect();
+            if crate::kompose::check_kompose_availability().is_ok() {
+                assert!(kubernetes_warnings.is_empty(), "{:?}", kubernetes_warnings);
+                let manifests: Vec<String> = fs::read_dir(temp_path.join("build/k8s/prod/monitoring")).unwrap()
+                    .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                    .collect();
+                assert!(manifests.iter().any(|name| name.ends_with("-deployment.yaml")), "{:?}", manifests);
+            } else {
+                // Without kompose the compose outputs are still written, and --strict rejects the build
+                assert_eq!(kubernetes_warnings.len(), 1, "{:?}", report.warnings);
+                assert!(kubernetes_warnings[0].message.contains("kompose is not available"), "{}", kubernetes_warnings[0].message);
+                assert!(temp_path.join("build/prod/monitoring/docker-compose.yml").exists());
+                assert!(!temp_path.join("build/k8s").exists());
+                let args = crate::build::BuildArgs { strict: true, ..Default::default() };
+                let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("--strict fails without kompose");
+                assert_eq!(error.code(), "build.strict_warnings");
+            }
+
+            // Combinations cannot write into the Kubernetes tree
+            fs::write(&config_path, fs::read_to_string(&config_path).unwrap() + "\n[build.aliases]\n\"dev/base\" = \"k8s/dev\"\n").unwrap();
+            let error = execute_real_build_in_dir(temp_path).expect_err("The alias collides with the Kubernetes manifests");
+            assert_eq!(error.code(), "validation.output_dir_collision");
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_images_reports_missing_images() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\nimage_check_command = \"test {image} != prom/prometheus:latest\"").expect("Failed to create test project");
+            let config_path = temp_path.join("stackbuilder.toml");
+            execute_real_build_in_dir(temp_path).expect("Build without --check-images should succeed");
+
+            let args = crate::build::BuildArgs { check_images: true, ..Default::default() };
+            fs::write(temp_path.join("build/dev/base/.marker"), "previous").unwrap();
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("prom/prometheus:latest is missing");
+            assert_eq!(error.code(), "build.missing_images");
+            assert_eq!(error.to_string(), "1 image(s) not found by the image check: prom/prometheus:latest");
+            // The previous build is kept
+            assert!(temp_path.join("build/dev/base/.marker").exists());
+
+            // Without the tool the images are not checked, which only --strict rejects
+            fs::write(&config_path, fs::read_to_string(&config_path).unwrap().replace("test {image} != prom/prometheus:latest", "stackbuilder-missing-tool {image}")).unwrap();
+            let report = execute_real_build_with_args_in_dir(temp_path, &args).expect("Build should succeed");
+            let image_warnings: Vec<_> = report.warnings.iter().filter(|warning| warning.category == crate::diagnostics::WarningCategory::ImageCheck).collect();
+            assert_eq!(image_warnings.len(), 1, "{:?}", report.warnings);
+            assert!(image_warnings[0].message.starts_with("Image check command not found"), "{}", image_warnings[0].message);
+            let strict = crate::build::BuildArgs { check_images: true, strict: true, ..Default::default() };
+            let error = execute_real_build_with_args_in_dir(temp_path, &strict).expect_err("--strict fails when images were not checked");
+            assert_eq!(error.code(), "build.strict_warnings");
+        });
+    }
+
+    #[test]
+    fn test_matrix_expands_to_every_combination() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\ncombo_name_separator = \"-\"\nmatrix = { database = [\"postgres\", \"mysql\"], kv = [\"redis\", \"none\"] }")
+                .expect("Failed to create test project");
+            for extension in ["postgres", "mysql", "redis"] {
+                fs::create_dir_all(temp_path.join("components/extensions").join(extension)).unwrap();
+                fs::write(temp_path.join("components/extensions").join(extension).join("docker-compose.yml"), format!("services:\n  {}:\n    image: {}\n", extension, extension)).unwrap();
+            }
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            crate::config::resolve_paths_in(&mut config, temp_path);
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            let dev: Vec<&str> = combinations.iter()
+                .filter(|combo| combo.environment.as_deref() == Some("dev"))
+                .map(|combo| combo.output_dir.as_str())
+                .collect();
+            assert_eq!(dev, ["dev/base", "dev/monitoring", "dev/postgres-redis", "dev/postgres", "dev/mysql-redis", "dev/mysql"]);
+
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            assert_eq!(report.combinations.len(), 12);
+            let compose = fs::read_to_string(temp_path.join("build/prod/mysql-redis/docker-compose.yml")).unwrap();
+            assert!(compose.contains("image: mysql") && compose.contains("image: redis") && !compose.contains("postgres"), "{}", compose);
+
+            // 2 environments x 4 elements
+            config.build.max_combinations = 7;
+            let error = crate::build::determine_build_combinations(&config).unwrap_err();
+            assert_eq!(error.code(), "validation.too_many_combinations");
+            assert_eq!(error.to_string(), "[build.matrix] expands to 8 combinations, more than max_combinations = 7");
+
+            config.build.max_combinations = crate::config::DEFAULT_MAX_COMBINATIONS;
+            config.build.matrix.insert("search".to_string(), vec!["elastic".to_string()]);
+            let error = crate::config::validate_config(&config, crate::config::ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.extension_not_found", "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_image_tags_override_tags_per_environment() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            fs::write(temp_path.join("components/extensions/monitoring/docker-compose.yml"),
+                      "services:\n  prometheus:\n    image: registry:5000/prom/prometheus:v2.50@sha256:0123\n  grafana:\n    image: grafana/grafana:10.4\n").unwrap();
+            let config = r#"
+[build]
+yaml_merger = "rust"
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.dev]
+extensions = ["monitoring"]
+
+[build.environments.prod]
+extensions = ["monitoring"]
+
+[build.environments.prod.image_tags]
+test-service = "sha-3f9a2c1"
+"registry:5000/prom/prometheus" = "v2.51"
+grafana = "11.0"
+"grafana/grafana" = "ignored"
+missing = "1.0"
+"#;
+            let config_path = temp_path.join("stackbuilder.toml");
+            fs::write(&config_path, config).unwrap();
+
+            let report = execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let images = |output_dir: &str| report.combinations.iter()
+                .find(|combination| combination.output_dir == output_dir).unwrap()
+                .services.as_ref().unwrap().iter()
+                .map(|(name, service)| (name.clone(), service.image.clone().unwrap()))
+                .collect::<Vec<_>>();
+            let pair = |name: &str, image: &str| (name.to_string(), image.to_string());
+            assert_eq!(images("prod/base"), [pair("test-service", "nginx:sha-3f9a2c1")]);
+            // The service name wins over the repository, and a digest is replaced with the tag
+            assert_eq!(images("prod/monitoring"), [
+                pair("grafana", "grafana/grafana:11.0"),
+                pair("prometheus", "registry:5000/prom/prometheus:v2.51"),
+                pair("test-service", "nginx:sha-3f9a2c1"),
+            ]);
+            assert_eq!(images("dev/monitoring"), [
+                pair("grafana", "grafana/grafana:10.4"),
+                pair("prometheus", "registry:5000/prom/prometheus:v2.50@sha256:0123"),
+                pair("test-service", "nginx:alpine"),
+            ]);
+            let compose = fs::read_to_string(temp_path.join("build/prod/monitoring/docker-compose.yml")).unwrap();
+            assert!(compose.contains("image: \"registry:5000/prom/prometheus:v2.51\""), "{}", compose);
+
+            let messages: Vec<&str> = report.warnings.iter()
+                .filter(|warning| warning.category == crate::diagnostics::WarningCategory::Config)
+                .map(|warning| warning.message.as_str())
+                .collect();
+            assert_eq!(messages, ["image_tags of environment 'prod' names 'missing', which is neither a service nor an image repository of its combinations"]);
+
+            fs::write(&config_path, config.replace("\"1.0\"", "\"1.0:latest\"")).unwrap();
+            let error = execute_real_build_in_dir(temp_path).expect_err("The tag is invalid");
+            assert_eq!(error.code(), "validation.invalid_image_tag");
+            assert_eq!(error.to_string(), "'1.0:latest' for 'missing' in the image_tags of environment 'prod' is not a valid image tag");
+        });
+    }
+
+    #[test]
+    fn test_service_conflicts_between_extensions() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\nenvironments = [\"dev\"]\nextensions = [\"postgres+timescaledb\"]\n").unwrap();
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            for (extension, image) in [("postgres", "postgres:16"), ("timescaledb", "timescale/timescaledb:latest-pg16")] {
+                fs::create_dir_all(temp_path.join("components/extensions").join(extension)).unwrap();
+                fs::write(temp_path.join("components/extensions").join(extension).join("docker-compose.yml"), format!("services:\n  db:\n    image: {}\n", image)).unwrap();
+            }
+
+            let report = execute_real_build_in_dir(temp_path).expect("A conflict is a warning by default");
+            let conflicts: Vec<_> = report.warnings.iter()
+                .filter(|warning| warning.category == crate::diagnostics::WarningCategory::ServiceConflict)
+                .collect();
+            assert_eq!(conflicts.len(), 1, "{:?}", report.warnings);
+            assert!(conflicts[0].message.starts_with("Service 'db' is defined differently by "), "{}", conflicts[0].message);
+            assert!(conflicts[0].message.ends_with("timescaledb/docker-compose.yml: image"), "{}", conflicts[0].message);
+
+            let args = crate::build::BuildArgs { strict: true, ..Default::default() };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).expect_err("--strict fails on the conflict");
+            assert_eq!(error.code(), "build.strict_warnings");
+
+            add_build_options(temp_path, "fail_on_service_conflicts = true").expect("Failed to update config");
+            let error = execute_real_build_in_dir(temp_path).expect_err("fail_on_service_conflicts fails the combination");
+            assert_eq!(error.code(), "build.combination_failed");
+            let crate::error::StackBuilderError::Build(crate::error::BuildError::CombinationFailed { source, .. }) = &error else { panic!("{:?}", error) };
+            assert_eq!(source.code(), "build.service_conflict");
+        });
+    }
+
+    #[test]
+    fn test_exclusive_groups_reject_conflicting_combinations() {
+        run_in_temp_dir(|temp_path| {
+            let build_options = "yaml_merger = \"rust\"\ncombo_name_separator = \"-\"\nexclusive_groups = { database = [\"postgres\", \"mysql\"] }\nmatrix = { database = [\"postgres\", \"mysql\"] }";
+            create_test_project_with(temp_path, build_options).expect("Failed to create test project");
+            for extension in ["postgres", "mysql"] {
+                fs::create_dir_all(temp_path.join("components/extensions").join(extension)).unwrap();
+                fs::write(temp_path.join("components/extensions").join(extension).join("docker-compose.yml"), format!("services:\n  {}:\n    image: {}\n", extension, extension)).unwrap();
+            }
+
+            // The matrix picks one database per combination
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap();
+            crate::config::resolve_paths_in(&mut config, temp_path);
+            crate::build::determine_build_combinations(&config).expect("One database per combination is allowed");
+
+            config.build.matrix.insert("cache".to_string(), vec!["mysql".to_string()]);
+            let error = crate::build::determine_build_combinations(&config).unwrap_err();
+            assert_eq!(error.code(), "validation.exclusive_group_conflict");
+            assert_eq!(error.to_string(), "Combination 'dev/mysql-postgres' has more than one extension of exclusive group 'database': \
+                postgres ([build.matrix] entry 'mysql+postgres'), mysql ([build.matrix] entry 'mysql+postgres')");
+
+            config.build.matrix.clear();
+            config.build.extensions = Some(vec!["monitoring".to_string(), "postgres+mysql".to_string()]);
+            let error = crate::build::determine_build_combinations(&config).unwrap_err();
+            assert_eq!(error.to_string(), "Combination 'dev/postgres-mysql' has more than one extension of exclusive group 'database': \
+                postgres (entry 'postgres+mysql' in the [build] extensions), mysql (entry 'postgres+mysql' in the [build] extensions)");
+
+            config.build.extensions = Some(vec!["monitoring".to_string()]);
+            config.build.combos.insert("data".to_string(), vec!["mysql".to_string(), "postgres".to_string()]);
+            let error = crate::build::determine_build_combinations(&config).unwrap_err();
+            assert_eq!(error.to_string(), "Combination 'dev/data' has more than one extension of exclusive group 'database': \
+                postgres (combo 'data'), mysql (combo 'data')");
+
+            let args = crate::build::BuildArgs { components_only: Some(vec!["postgres".to_string(), "mysql".to_string()]), ..Default::default() };
+            let error = execute_real_build_with_args_in_dir(temp_path, &args).unwrap_err();
+            assert_eq!(error.code(), "validation.exclusive_group_conflict");
+            assert!(error.to_string().ends_with("postgres (--components-only), mysql (--components-only)"), "{}", error);
+
+            // Members must be extensions
+            config.build.combos.clear();
+            config.build.exclusive_groups.insert("queue".to_string(), vec!["rabbitmq".to_string()]);
+            let error = crate::config::validate_config(&config, crate::config::ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.extension_not_found", "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_multi_document_writes_a_document_per_layer() {
+        use serde::Deserialize;
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "multi_document = true\nproject_name_template = \"{env}-{combo}\"").expect("Failed to create test project");
+            // A leading separator in a source must not add an empty document; a quoted name is replaced
+            let base = fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap();
+            fs::write(temp_path.join("components/base/docker-compose.yml"), format!("---\n\"name\": legacy # old name\n{}", base)).unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+            let documents = |combination: &str| -> Vec<serde_yaml_ng::Value> {
+                let content = fs::read_to_string(temp_path.join("build").join(combination).join("docker-compose.yml")).unwrap();
+                serde_yaml_ng::Deserializer::from_str(&content)
+                    .map(|document| serde_yaml_ng::Value::deserialize(document).unwrap())
+                    .collect()
+            };
+
+            let monitoring = documents("dev/monitoring");
+            assert_eq!(monitoring.len(), 3, "base, environment and extension: {:?}", monitoring);
+            assert_eq!(monitoring[0]["name"], "dev-monitoring");
+            assert!(monitoring[1]["services"]["test-service"]["environment"].is_sequence());
+            assert!(monitoring[2]["services"]["prometheus"].is_mapping());
+            assert_eq!(documents("prod/base").len(), 2);
+        });
+    }
+
+    /// Assert docker-compose.yml and docker-compose.json in `dir` hold the same document
+    fn assert_yaml_and_json_equal(dir: &std::path::Path) {
+        let yaml: serde_yaml_ng::Value = serde_yaml_ng::from_str(&fs::read_to_string(dir.join("docker-compose.yml")).unwrap()).unwrap();
+        let json: serde_yaml_ng::Value = serde_json::from_str(&fs::read_to_string(dir.join("docker-compose.json")).unwrap()).unwrap();
+        assert_eq!(yaml, json);
+    }
+
+    #[test]
+    fn test_build_writes_yaml_and_json() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"\noutput_formats = [\"yaml\", \"json\"]").expect("Failed to create test project");
+
+            execute_real_build_in_dir(temp_path).expect("Build should succeed");
+
+            for combination in ["dev/base", "dev/monitoring", "prod/base", "prod/monitoring"] {
+                let dir = temp_path.join("build").join(combination);
+                assert!(dir.join("docker-compose.yml").exists(), "Missing YAML in {}", combination);
+                assert!(dir.join("docker-compose.json").exists(), "Missing JSON in {}", combination);
+                assert_yaml_and_json_equal(&dir);
+            }
+        });
+    }
+
+    #[test]
+    fn test_yq_output_is_parsed_once_for_json() {
+        run_in_temp_dir(|temp_path| {
+            // yq hands over serialized YAML instead of a merged value
+            let yaml = "services:\n  web:\n    image: nginx:alpine\n    ports:\n    - 80:80\nvolumes:\n  data:\n";
+            let formats = [crate::config::OutputFormat::Yaml, crate::config::OutputFormat::Json];
+
+            let written = crate::build::write_compose_outputs(&formats, temp_path, yaml, None, &crate::output_writer::OutputWriter::sync()).unwrap();
+
+            assert_eq!(written, vec![temp_path.join("docker-compose.yml"), temp_path.join("docker-compose.json")]);
+            assert_eq!(fs::read_to_string(temp_path.join("docker-compose.yml")).unwrap(), yaml);
+            assert_yaml_and_json_equal(temp_path);
+        });
+    }
+
+    #[test]
+    fn test_effective_config_applies_flags_and_redacts_variables() {
+        let config: crate::config::Config = toml::from_str(r#"
+[build]
+combos = { full = ["monitoring"] }
+
+[build.variables]
+DB_PASSWORD = "hunter2"
+
+[build.combo_variables.full]
+TOKEN = "secret"
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.prod.variables]
+REPLICAS = "3"
+"#).unwrap();
+        let args = crate::build::BuildArgs {
+            keep_going: true,
+            resolve_env: true,
+            compose_project_name: Some("ci-{env}".to_string()),
+            ..Default::default()
+        };
+
+        let effective = crate::build::effective_config(config, &args, std::path::Path::new("/project"));
+        let toml_text = toml::to_string_pretty(&effective).unwrap();
+        assert!(!toml_text.contains("hunter2") && !toml_text.contains("secret") && !toml_text.contains("\"3\""), "{}", toml_text);
+
+        // The printed configuration loads again as the one the build used
+        let reloaded: crate::config::Config = toml::from_str(&toml_text).unwrap();
+        assert!(reloaded.build.keep_going && reloaded.build.resolve_env);
+        assert_eq!(reloaded.build.project_name_template.as_deref(), Some("ci-{env}"));
+        assert_eq!(reloaded.paths.build_dir, "/project/build");
+        assert_eq!(crate::config::get_environments_list(&reloaded), ["dev", "prod"]);
+        assert_eq!(reloaded.build.variables["DB_PASSWORD"], crate::config::REDACTED_VALUE);
+        assert_eq!(reloaded.build.combo_variables["full"]["TOKEN"], crate::config::REDACTED_VALUE);
+        let prod = crate::config::get_environment_config(&reloaded, "prod").unwrap();
+        assert_eq!(prod.variables.unwrap()["REPLICAS"], crate::config::REDACTED_VALUE);
+        assert!(reloaded.build.unknown_keys.is_empty(), "{:?}", reloaded.build.unknown_keys);
+
+        let json: serde_json::Value = serde_json::to_value(&effective).unwrap();
+        assert_eq!(json["build"]["environments"]["available"], serde_json::json!(["dev", "prod"]));
+    }
+
+    #[test]
+    fn test_config_show_applies_the_same_flags_as_build() {
+        let show = crate::build::ConfigShowArgs {
+            components_dirs: vec![std::path::PathBuf::from("vendor/components")],
+            keep_going: true,
+            compose_project_name: Some("ci-{env}".to_string()),
+            ..Default::default()
+        };
+
+        let effective = crate::build::effective_config(crate::config::Config::default(), &show.build_args(), std::path::Path::new("/project"));
+        assert_eq!(
+            crate::config::component_roots(&effective),
+            [std::path::PathBuf::from("/project/components"), std::path::PathBuf::from("/project/vendor/components")]
+        );
+        assert_eq!(effective.build.project_name_template.as_deref(), Some("ci-{env}"));
+        assert!(effective.build.keep_going);
+    }
+
+    #[test]
+    fn test_build_reads_config_from_stdin() {
+        use crate::build::{build, BuildOptions};
+        use crate::config::{load_config_reader, ConfigFormat};
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "include_files = [\"shared.toml\"]\nyaml_merger = \"rust\"").expect("Failed to create test project");
+            let content = fs::read_to_string(temp_path.join("stackbuilder.toml")).unwrap();
+            fs::remove_file(temp_path.join("stackbuilder.toml")).unwrap();
+            // Included files of a piped configuration are relative to the project directory
+            fs::write(temp_path.join("shared.toml"), "[build]\nwrite_checksums = false\n").unwrap();
+
+            let config = load_config_reader(content.as_bytes(), ConfigFormat::Toml, temp_path, None)
+                .expect("Configuration from stdin should load");
+            assert!(!config.build.write_checksums);
+            let report = build(BuildOptions::new(temp_path).with_config(config)).expect("Build from a piped configuration should succeed");
+            assert!(report.combinations.iter().any(|c| c.output_dir == "dev/monitoring"));
+            assert!(temp_path.join("build/dev/monitoring/docker-compose.yml").exists());
+
+            let json = serde_json::to_string(&toml::from_str::<toml::Value>(&content).unwrap()).unwrap();
+            let config = load_config_reader(json.as_bytes(), ConfigFormat::Json, temp_path, None)
+                .expect("JSON configuration from stdin should load");
+            assert_eq!(crate::config::get_environments_list(&config), ["dev", "prod"]);
+            assert!(!config.build.write_checksums);
+
+            let error = load_config_reader("{\"build\": [".as_bytes(), ConfigFormat::Json, temp_path, None).unwrap_err();
+            assert_eq!(error.code(), "config.invalid_json_syntax");
+            assert_eq!(error.path(), Some("<stdin>".into()));
+        });
+    }
+
+    #[test]
+    fn test_config_arg_reads_stdin() {
+        use crate::config::{load_config_arg, ConfigFormat};
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        // Stdin is shared by the whole test process, so the reading half runs alone in a child with the configuration piped in
+        if std::env::var_os("STACKBUILDER_TEST_STDIN").is_some() {
+            let config = load_config_arg(std::path::Path::new("-"), ConfigFormat::Toml, &std::env::current_dir().unwrap(), None)
+                .expect("Configuration from stdin should load");
+            assert_eq!(crate::config::get_environments_list(&config), ["ci"]);
+            assert!(!config.build.write_checksums);
+            return;
+        }
+
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["tests::build_tests::tests::test_config_arg_reads_stdin", "--exact", "--test-threads=1"])
+            .env("STACKBUILDER_TEST_STDIN", "1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"[build]\nenvironments = [\"ci\"]\nwrite_checksums = false\n").unwrap();
+        let output = child.wait_with_output().unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+        assert!(stdout.contains("1 passed"), "{}", stdout);
+    }
 }
\ No newline at end of file
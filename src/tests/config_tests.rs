@@ -17,6 +17,15 @@ mod tests {
         assert_eq!(config.paths.build_dir, "./build");
     }
 
+    #[test]
+    fn test_empty_build_section_matches_build_config_default() {
+        // The deserializer and Default fill in the same values
+        let config: Config = toml::from_str("[build]\n").unwrap();
+        assert_eq!(serde_json::to_value(&config.build).unwrap(), serde_json::to_value(BuildConfig::default()).unwrap());
+        assert!(config.build.copy_env_example && config.build.copy_additional_files && config.build.preserve_env_files);
+        assert_eq!(config.build.backup_dir, "./.stackbuilder/backup");
+    }
+
     #[test]
     fn test_config_load_valid() {
         run_in_temp_dir(|temp_path| {
@@ -35,7 +44,7 @@ extensions = ["monitoring", "auth"]
             
             fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
             
-            let result = load_config_from_dir(temp_path);
+            let result = load_config_from(&temp_path.join("stackbuilder.toml"));
             
             assert!(result.is_ok());
             let config = result.unwrap();
@@ -54,7 +63,7 @@ extensions = ["monitoring", "auth"]
     #[test]
     fn test_config_load_missing() {
         run_in_temp_dir(|temp_path| {
-            let result = load_config_from_dir(temp_path);
+            let result = load_config_from(&temp_path.join("stackbuilder.toml"));
             
             assert!(result.is_err());
             let error = result.unwrap_err();
@@ -72,7 +81,7 @@ components_dir = "./components"
             
             fs::write(temp_path.join("stackbuilder.toml"), invalid_config).expect("Failed to write config");
             
-            let result = load_config_from_dir(temp_path);
+            let result = load_config_from(&temp_path.join("stackbuilder.toml"));
             
             assert!(result.is_err());
             let error = result.unwrap_err();
@@ -85,8 +94,8 @@ components_dir = "./components"
         run_in_temp_dir(|temp_path| {
             create_test_project(temp_path).expect("Failed to create test project");
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
-            let result = validate_config_in_dir(&config, temp_path);
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
+            let result = config.validate_against(temp_path);
             
             assert!(result.is_ok(), "Validation should succeed: {:?}", result);
         });
@@ -110,8 +119,8 @@ extensions = ["monitoring"]
 "#;
             fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
-            let result = validate_config_in_dir(&config, temp_path);
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
+            let result = config.validate_against(temp_path);
             
             assert!(result.is_err());
             let error = result.unwrap_err();
@@ -129,8 +138,8 @@ extensions = ["monitoring"]
             fs::create_dir_all(temp_path.join("components/extensions/monitoring")).expect("Failed to create extensions dir");
             // Explicitly don't create components/base directory
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
-            let result = validate_config_in_dir(&config, temp_path);
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
+            let result = config.validate_against(temp_path);
             
             assert!(result.is_err(), "Validation should fail when base directory is missing");
             let error = result.unwrap_err();
@@ -157,8 +166,8 @@ extensions = []
             fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
             create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
-            let result = validate_config_in_dir(&config, temp_path);
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
+            let result = config.validate_against(temp_path);
             
             // Now this should succeed - no targets means base-only build
             assert!(result.is_ok(), "Validation should succeed for base-only configuration: {:?}", result);
@@ -175,7 +184,7 @@ environments = ["dev"]
             
             fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
             
-            let result = load_config_from_dir(temp_path);
+            let result = load_config_from(&temp_path.join("stackbuilder.toml"));
             
             assert!(result.is_ok(), "Loading config without [paths] section should succeed");
             let config = result.unwrap();
@@ -195,7 +204,7 @@ environments = ["dev"]
             
             fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
             
-            let result = load_config_from_dir(temp_path);
+            let result = load_config_from(&temp_path.join("stackbuilder.toml"));
             
             assert!(result.is_ok(), "Loading config without any sections should succeed");
             let config = result.unwrap();
@@ -218,8 +227,8 @@ environments = ["prod"]
             create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
             // Don't create environments directory - it should be optional
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
-            let result = validate_config_in_dir(&config, temp_path);
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
+            let result = config.validate_against(temp_path);
             
             assert!(result.is_ok(), "Validation should succeed even without environments directory: {:?}", result);
         });
@@ -230,7 +239,7 @@ environments = ["prod"]
         run_in_temp_dir(|temp_path| {
             create_test_project(temp_path).expect("Failed to create test project");
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
             let environments = discover_environments_in_dir(&config, temp_path).expect("Failed to discover environments");
             
             assert_eq!(environments.len(), 2);
@@ -244,7 +253,7 @@ environments = ["prod"]
         run_in_temp_dir(|temp_path| {
             create_test_project(temp_path).expect("Failed to create test project");
             
-            let config = load_config_from_dir(temp_path).expect("Failed to load config");
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
             let extensions = discover_extensions_in_dir(&config, temp_path).expect("Failed to discover extensions");
             
             // Extensions should be found since create_test_project creates monitoring extension
@@ -252,4 +261,536 @@ environments = ["prod"]
             assert!(extensions.contains(&"monitoring".to_string()));
         });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_output_formats() {
+        use crate::config::{Config, OutputFormat};
+
+        let config: Config = toml::from_str("[build]\n").unwrap();
+        assert_eq!(config.build.output_formats, vec![OutputFormat::Yaml]);
+
+        let config: Config = toml::from_str("[build]\noutput_format = \"json\"\n").unwrap();
+        assert_eq!(config.build.output_formats, vec![OutputFormat::Json]);
+
+        let config: Config = toml::from_str("[build]\noutput_formats = [\"yaml\", \"json\", \"yaml\"]\n").unwrap();
+        assert_eq!(config.build.output_formats, vec![OutputFormat::Yaml, OutputFormat::Json]);
+
+        assert!(toml::from_str::<Config>("[build]\noutput_format = \"xml\"\n").is_err());
+    }
+
+    #[test]
+    fn test_referenced_extensions_from_both_apis() {
+        use crate::config::{Config, get_referenced_extensions};
+
+        let config: Config = toml::from_str(r#"
+[build]
+extensions = ["logging"]
+
+[build.combos]
+observability = ["metrics", "logging"]
+auth = ["keycloak"]
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.dev]
+extensions = ["redis", "logging"]
+"#).unwrap();
+        assert_eq!(get_referenced_extensions(&config), vec!["logging", "redis", "keycloak", "metrics"]);
+
+        let config: Config = toml::from_str(r#"
+[build]
+environments = ["dev"]
+
+[build.targets.dev]
+extensions = ["debug"]
+"#).unwrap();
+        assert_eq!(get_referenced_extensions(&config), vec!["debug"]);
+    }
+
+    #[test]
+    fn test_components_manifest_for_test_project() {
+        use crate::manifest::{build_manifest, ComponentKind};
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+
+            let mut config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Failed to load config");
+            config.paths.components_dir = temp_path.join(&config.paths.components_dir).to_string_lossy().to_string();
+            let manifest = build_manifest(&config).expect("Manifest should be built");
+
+            let components: Vec<(&str, ComponentKind, String)> = manifest.components.iter()
+                .map(|c| (c.name.as_str(), c.kind, c.path.to_string_lossy().to_string()))
+                .collect();
+            assert_eq!(components, vec![
+                ("base", ComponentKind::Base, "base".to_string()),
+                ("dev", ComponentKind::Environment, "environments/dev".to_string()),
+                ("prod", ComponentKind::Environment, "environments/prod".to_string()),
+                ("monitoring", ComponentKind::Extension, "extensions/monitoring".to_string()),
+            ]);
+            assert!(manifest.components.iter().all(|c| c.compose));
+        });
+    }
+
+    #[test]
+    fn test_parse_extension_combination() {
+        use crate::config::{Config, parse_extension_combination, extension_combination_name};
+
+        assert_eq!(parse_extension_combination("monitoring"), vec!["monitoring"]);
+        assert_eq!(parse_extension_combination("monitoring+auth"), vec!["monitoring", "auth"]);
+        assert_eq!(parse_extension_combination(" monitoring + auth +"), vec!["monitoring", "auth"]);
+
+        let mut config = Config::default();
+        assert_eq!(extension_combination_name(&config, "monitoring+auth"), "monitoring+auth");
+        config.build.combo_name_separator = "_".to_string();
+        assert_eq!(extension_combination_name(&config, "monitoring+auth"), "monitoring_auth");
+        assert_eq!(extension_combination_name(&config, "monitoring"), "monitoring");
+    }
+
+    #[test]
+    fn test_preserved_env_file_patterns_exclude_generated_env_example() {
+        use crate::config::{Config, preserved_env_file_patterns};
+
+        let mut config = Config::default();
+        config.build.env_file_patterns = vec![".env".to_string(), ".env.example".to_string()];
+        assert_eq!(preserved_env_file_patterns(&config), vec![".env"]);
+
+        config.build.copy_env_example = false;
+        assert_eq!(preserved_env_file_patterns(&config), vec![".env", ".env.example"]);
+    }
+
+    #[test]
+    fn test_include_files_merge_into_build_config() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            fs::create_dir_all(temp_path.join("stacks")).unwrap();
+            fs::write(temp_path.join("stacks/extensions.toml"), r#"
+[build]
+extensions = ["monitoring"]
+yaml_merger = "rust"
+
+[build.combos]
+observability = ["monitoring"]
+full = ["monitoring"]
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.dev]
+extensions = ["monitoring"]
+"#).unwrap();
+            fs::write(temp_path.join("stackbuilder.toml"), r#"
+[build]
+include_files = ["stacks/extensions.toml"]
+yaml_merger = "yq"
+
+[build.combos]
+full = ["monitoring", "monitoring"]
+
+[build.environments.prod]
+combos = ["observability"]
+"#).unwrap();
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Included config should load");
+
+            // The main file wins on conflicts, tables are merged key by key
+            assert_eq!(config.build.yaml_merger, YamlMergerType::Yq);
+            assert_eq!(config.build.extensions, Some(vec!["monitoring".to_string()]));
+            assert_eq!(config.build.combos["observability"], vec!["monitoring"]);
+            assert_eq!(config.build.combos["full"], vec!["monitoring", "monitoring"]);
+
+            let environments = config.build.environments_config.as_ref().expect("Environments from the included file");
+            assert_eq!(environments.available, Some(vec!["dev".to_string(), "prod".to_string()]));
+            assert_eq!(get_environment_config(&config, "dev").unwrap().extensions, Some(vec!["monitoring".to_string()]));
+            assert_eq!(get_environment_config(&config, "prod").unwrap().combos, Some(vec!["observability".to_string()]));
+
+            // Combos defined in the included file resolve from the main config
+            let mut config = config;
+            config.paths.components_dir = temp_path.join(&config.paths.components_dir).to_string_lossy().to_string();
+            assert_eq!(resolve_combo_extensions(&config, &["observability".to_string()]).unwrap(), vec!["monitoring"]);
+        });
+    }
+
+    #[test]
+    fn test_glob_combo_members_expand_to_discovered_extensions() {
+        run_in_temp_dir(|temp_path| {
+            for dir in ["base", "extensions/auth-oidc", "extensions/auth-guard", "extensions/monitoring"] {
+                fs::create_dir_all(temp_path.join("components").join(dir)).unwrap();
+            }
+            let mut config: Config = toml::from_str(r#"
+[build.combos]
+security = ["monitoring", "auth-*"]
+"#).unwrap();
+            config.paths.components_dir = temp_path.join("components").to_string_lossy().to_string();
+
+            validate_config(&config, ValidationMode::Collect).expect("Glob members should validate");
+            assert_eq!(
+                resolve_combo_extensions(&config, &["security".to_string()]).unwrap(),
+                vec!["monitoring", "auth-guard", "auth-oidc"],
+            );
+            let combinations = crate::build::determine_build_combinations(&config).unwrap();
+            assert_eq!(combinations[0].extensions, vec!["monitoring", "auth-guard", "auth-oidc"]);
+            assert!(!get_referenced_extensions(&config).contains(&"auth-*".to_string()));
+
+            // A pattern that matches nothing is an invalid combo, both when validating and when resolving
+            config.build.combos.insert("vault".to_string(), vec!["vault-*".to_string()]);
+            let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.invalid_combo_definition");
+            assert!(error.to_string().contains("Pattern 'vault-*' matches no extension"), "{}", error);
+            let error = resolve_combo_extensions(&config, &["vault".to_string()]).unwrap_err();
+            assert_eq!(error.code(), "validation.invalid_combo_definition");
+        });
+    }
+
+    #[test]
+    fn test_include_files_errors_name_the_included_file() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\ninclude_files = [\"extensions.toml\"]\n").unwrap();
+
+            let error = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap_err();
+            assert!(error.to_string().contains("extensions.toml"), "{}", error);
+
+            fs::write(temp_path.join("extensions.toml"), "[build]\nextensions = \"monitoring\"\n").unwrap();
+            let error = load_config_from(&temp_path.join("stackbuilder.toml")).unwrap_err();
+            assert_eq!(error.location().map(|l| (l.line, l.column)), Some((2, 14)), "{}", error);
+            assert!(error.to_string().contains("extensions.toml:2:14"), "{}", error);
+        });
+    }
+
+    /// Config with an unknown combo extension, an empty combo and an unknown environment combo
+    fn config_with_several_problems(temp_path: &std::path::Path) -> Config {
+        fs::create_dir_all(temp_path.join("components/base")).unwrap();
+        fs::create_dir_all(temp_path.join("components/extensions/monitoring")).unwrap();
+
+        let mut config: Config = toml::from_str(r#"
+[build.combos]
+broken = ["missing"]
+empty = []
+
+[build.environments]
+available = ["dev"]
+
+[build.environments.dev]
+combos = ["unknown"]
+"#).unwrap();
+        config.paths.components_dir = temp_path.join("components").to_string_lossy().to_string();
+        config
+    }
+
+    #[test]
+    fn test_validation_collects_all_problems() {
+        use crate::error::{StackBuilderError, ValidationError};
+
+        run_in_temp_dir(|temp_path| {
+            let config = config_with_several_problems(temp_path);
+            assert_eq!(config.build.validation_mode, ValidationMode::Collect);
+
+            let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+            let StackBuilderError::Validation(ValidationError::Multiple { ref errors }) = error else {
+                panic!("Expected all problems, got {:?}", error);
+            };
+            let codes: Vec<&str> = errors.iter().map(|e| e.code()).collect();
+            assert_eq!(codes, vec![
+                "validation.extension_not_found",
+                "validation.invalid_combo_definition",
+                "validation.combo_not_found",
+            ]);
+            assert_eq!(error.code(), "validation.multiple");
+            assert_eq!(error.exit_code(), 2);
+            assert!(error.to_string().contains("Configuration has 3 problems:\n  1. Extension 'missing'"), "{}", error);
+            assert_eq!(error.to_json()["errors"].as_array().unwrap().len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_validation_fail_fast_stops_at_first_problem() {
+        run_in_temp_dir(|temp_path| {
+            let mut config = config_with_several_problems(temp_path);
+
+            let error = validate_config(&config, ValidationMode::FailFast).unwrap_err();
+            assert_eq!(error.code(), "validation.extension_not_found");
+
+            // validation_mode in the config and --fail-fast select the same behavior
+            assert_eq!(validation_mode(&config, true), ValidationMode::FailFast);
+            config.build.validation_mode = toml::from_str::<BuildConfig>("validation_mode = \"fail-fast\"").unwrap().validation_mode;
+            assert_eq!(validation_mode(&config, false), ValidationMode::FailFast);
+
+            // A single problem is reported as is in both modes
+            config.build.combos.clear();
+            config.build.environments_config = None;
+            for separator in ["/", ""] {
+                config.build.combo_name_separator = separator.to_string();
+                for mode in [ValidationMode::Collect, ValidationMode::FailFast] {
+                    let error = validate_config(&config, mode).unwrap_err();
+                    assert_eq!(error.code(), "validation.invalid_combo_name_separator");
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_env_filenames_must_be_plain_file_names() {
+        run_in_temp_dir(|temp_path| {
+            fs::create_dir_all(temp_path.join("components/base")).unwrap();
+            let mut config: Config = toml::from_str("[build]\nenv_source_filename = \".env.template\"\n").unwrap();
+            config.paths.components_dir = temp_path.join("components").to_string_lossy().to_string();
+            assert_eq!(config.build.env_source_filename, ".env.template");
+            assert_eq!(config.build.env_output_filename, ".env.example");
+            validate_config(&config, ValidationMode::Collect).expect("Plain file names are valid");
+
+            config.build.env_output_filename = "config/.env".to_string();
+            let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.invalid_env_filename");
+            assert!(error.to_string().contains("env_output_filename"), "{}", error);
+        });
+    }
+
+    #[test]
+    fn test_matrix_entries() {
+        let config: Config = toml::from_str("[build.matrix]\ncache = [\"redis\", \"none\"]\ndatabase = [\"postgres\", \"mysql\"]\n").unwrap();
+        // Groups are combined in name order, the first group varies slowest
+        assert_eq!(crate::config::matrix_extension_entries(&config), ["redis+postgres", "redis+mysql", "postgres", "mysql"]);
+        assert_eq!(crate::config::matrix_size(&config), 4);
+
+        // The element made only of "none" is the base combination
+        let config: Config = toml::from_str("[build.matrix]\ncache = [\"redis\", \"none\"]\nqueue = [\"none\", \"rabbitmq\"]\n").unwrap();
+        assert_eq!(crate::config::matrix_extension_entries(&config), ["redis", "redis+rabbitmq", "rabbitmq"]);
+        assert_eq!(crate::config::matrix_size(&config), 3);
+
+        let config: Config = toml::from_str("[build.matrix]\ncache = []\n").unwrap();
+        assert!(crate::config::matrix_extension_entries(&config).is_empty());
+        assert_eq!(crate::config::matrix_size(&config), 0);
+    }
+
+    #[test]
+    fn test_aliases_must_be_unique_relative_directories() {
+        run_in_temp_dir(|temp_path| {
+            fs::create_dir_all(temp_path.join("components/base")).unwrap();
+            let mut config: Config = toml::from_str("[build.aliases]\n\"prod/security\" = \"prod-hardened\"\n\"dev/security\" = \"dev/hardened/\"\n").unwrap();
+            config.paths.components_dir = temp_path.join("components").to_string_lossy().to_string();
+            validate_config(&config, ValidationMode::Collect).expect("Relative aliases are valid");
+
+            config.build.aliases.insert("dev/full".to_string(), "dev/hardened".to_string());
+            let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.duplicate_alias");
+            assert_eq!(error.to_string(), "'dev/full' and 'dev/security' in [build.aliases] have the same alias 'dev/hardened/'");
+
+            config.build.aliases = [("prod/base".to_string(), "prod".to_string()), ("prod/monitoring".to_string(), "prod/monitoring".to_string()), ("dev/base".to_string(), "production".to_string())].into();
+            let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.nested_alias");
+            assert_eq!(error.to_string(), "Alias 'prod/monitoring' of 'prod/monitoring' in [build.aliases] is inside the alias 'prod' of 'prod/base'");
+
+            for alias in ["", "../outside", "/srv/stack", "prod/./x"] {
+                config.build.aliases = [("prod/security".to_string(), alias.to_string())].into();
+                let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+                assert_eq!(error.code(), "validation.invalid_alias", "{}", alias);
+            }
+        });
+    }
+
+    #[test]
+    fn test_default_environment_must_be_configured() {
+        run_in_temp_dir(|temp_path| {
+            fs::create_dir_all(temp_path.join("components/base")).unwrap();
+            let mut config: Config = toml::from_str("[build]\nenvironments = [\"dev\", \"prod\"]\ndefault_environment = \"dve\"\n").unwrap();
+            config.paths.components_dir = temp_path.join("components").to_string_lossy().to_string();
+
+            let error = validate_config(&config, ValidationMode::Collect).unwrap_err();
+            assert_eq!(error.code(), "validation.default_environment_not_found");
+            assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'dev'?"));
+
+            config.build.default_environment = Some("prod".to_string());
+            assert!(validate_config(&config, ValidationMode::Collect).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_config_from_toml_str_and_builder() {
+        let parsed = Config::from_toml_str("[paths]\ncomponents_dir = \"stacks\"\n\n[build]\nenvironments = [\"dev\"]\nyaml_merger = \"rust\"\n\n[build.combos]\nobservability = [\"monitoring\", \"logging\"]\n").expect("Config should parse");
+        let built = Config::builder()
+            .with_components_dir("stacks")
+            .with_environments(["dev"])
+            .with_combo("observability", ["monitoring", "logging"])
+            .with_yaml_merger(YamlMergerType::Rust)
+            .build();
+
+        assert_eq!(parsed.paths.components_dir, built.paths.components_dir);
+        assert_eq!(parsed.paths.base_dir, built.paths.base_dir);
+        assert_eq!(parsed.build.environments, built.build.environments);
+        assert_eq!(parsed.build.combos, built.build.combos);
+        assert_eq!(parsed.build.yaml_merger, built.build.yaml_merger);
+        assert_eq!(parsed.build.copy_env_example, built.build.copy_env_example, "Unset options keep their defaults");
+
+        let error = Config::from_toml_str("[paths\n").unwrap_err();
+        assert_eq!(error.code(), "config.invalid_toml_syntax");
+    }
+
+    #[test]
+    fn test_validate_against_explicit_root() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            let config = Config::builder()
+                .with_environments(["dev", "prod"])
+                .with_combo("full", ["monitoring"])
+                .build();
+
+            // Relative paths resolve against the given root, not the current directory
+            assert!(config.validate_against(temp_path).is_ok());
+            let error = config.validate_against(&temp_path.join("missing")).unwrap_err();
+            assert_eq!(error.code(), "validation.components_directory_not_found");
+
+            let config = Config::builder().with_combo("full", ["tracing"]).build();
+            let error = config.validate_against(temp_path).unwrap_err();
+            assert_eq!(error.code(), "validation.extension_not_found");
+        });
+    }
+
+    #[test]
+    fn test_unknown_keys_are_reported() {
+        use crate::config::UnknownKey;
+
+        let config = Config::from_toml_str(r#"
+[paths]
+biuld_dir = "./out"
+
+[build]
+yaml_merger = "rust"
+extentions = ["monitoring"]
+copy_env_exmaple = false
+favourite_color = "blue"
+"#).unwrap();
+
+        assert_eq!(crate::config::find_unknown_keys(&config), vec![
+            UnknownKey { table: "build", key: "copy_env_exmaple".into(), suggestion: Some("copy_env_example") },
+            UnknownKey { table: "build", key: "extentions".into(), suggestion: Some("extensions") },
+            UnknownKey { table: "build", key: "favourite_color".into(), suggestion: None },
+            UnknownKey { table: "paths", key: "biuld_dir".into(), suggestion: Some("build_dir") },
+        ]);
+        // The misspelled options keep their defaults
+        assert!(config.build.extensions.is_none());
+        assert_eq!(config.paths.build_dir, "./build");
+
+        run_in_temp_dir(|temp_path| {
+            create_test_project(temp_path).expect("Failed to create test project");
+            let diagnostics = crate::diagnostics::Diagnostics::collect();
+            config.validate_against(temp_path).expect("Unknown keys are only warnings");
+            let warnings: Vec<String> = diagnostics.finish().into_iter().map(|warning| warning.message).collect();
+            assert!(warnings.contains(&"Unknown key 'extentions' in [build] is ignored, did you mean 'extensions'?".to_string()), "{:?}", warnings);
+            assert!(warnings.contains(&"Unknown key 'favourite_color' in [build] is ignored".to_string()), "{:?}", warnings);
+        });
+
+        let error = crate::validate::check_known_keys(&config).unwrap_err();
+        assert_eq!(error.code(), "config.unknown_keys");
+        assert!(error.to_string().contains("[build] extentions (did you mean 'extensions'?)"), "{}", error);
+        assert!(crate::validate::check_known_keys(&Config::from_toml_str("[build]\nextensions = []\n").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_fail_fast_is_the_inverse_of_keep_going() {
+        assert!(!Config::from_toml_str("[build]\n").unwrap().build.keep_going);
+        assert!(Config::from_toml_str("[build]\nfail_fast = false\n").unwrap().build.keep_going);
+        assert!(!Config::from_toml_str("[build]\nfail_fast = true\n").unwrap().build.keep_going);
+        assert!(Config::from_toml_str("[build]\nkeep_going = true\n").unwrap().build.keep_going);
+
+        let error = Config::from_toml_str("[build]\nfail_fast = false\nkeep_going = true\n").unwrap_err();
+        assert_eq!(error.code(), "config.invalid_toml_syntax");
+        assert!(error.to_string().contains("fail_fast and keep_going are opposites"), "{}", error);
+    }
+
+    #[test]
+    fn test_known_keys_are_not_reported() {
+        let config = Config::from_toml_str(&crate::config::BUILD_KEYS.iter()
+            .filter(|key| !["environments", "targets", "combos", "merge_strategy", "keep_going"].contains(key))
+            .fold("[build]\n".to_string(), |toml, key| toml + &format!("{} = {}\n", key, known_key_value(key))))
+            .unwrap();
+        assert!(crate::config::find_unknown_keys(&config).is_empty(), "{:?}", crate::config::find_unknown_keys(&config));
+    }
+
+    /// A value of the right type for every scalar and list option of [build]
+    fn known_key_value(key: &str) -> &'static str {
+        match key {
+            "yaml_merger" => "\"rust\"",
+            "backup_format" => "\"dir\"",
+            "dedup_copies" => "\"copy\"",
+            "validation_mode" => "\"collect\"",
+            "output_format" => "\"yaml\"",
+            "output_mode" => "\"merged\"",
+            "label_style" => "\"map\"",
+            "port_style" => "\"short\"",
+            "output_formats" => "[\"yaml\"]",
+            "strict_categories" => "[\"config\"]",
+            "lock_timeout" | "yq_retries" | "yq_retry_backoff_ms" | "max_combinations" => "1",
+            key if key.starts_with("copy_") || key.starts_with("write_") || key.starts_with("preserve_")
+                || key.starts_with("inline_") || key.starts_with("rewrite_") || key.starts_with("prune_")
+                || key.starts_with("require_") || key.starts_with("skip_") || key.starts_with("include_git")
+                || key == "provenance_header" || key == "fail_fast" || key == "keep_going" || key == "multi_document" || key == "generate_helper_scripts"
+                || key == "resolve_env" || key == "kubernetes_output" || key == "fail_on_service_conflicts" => "true",
+            "extensions" | "exclude_patterns" | "env_file_patterns" | "include_files" => "[]",
+            "variables" | "combo_variables" | "aliases" | "matrix" | "exclusive_groups" => "{}",
+            _ => "\"x\"",
+        }
+    }
+
+    #[test]
+    fn test_user_config_defaults() {
+        run_in_temp_dir(|temp_path| {
+            let user_config = temp_path.join("user/config.toml");
+            fs::create_dir_all(temp_path.join("user")).unwrap();
+            fs::write(&user_config, "[paths]\nbuild_dir = \"/tmp/elsewhere\"\n\n[build]\nyaml_merger = \"rust\"\nlock_timeout = 5\npre_build_command = \"make\"\n").unwrap();
+            let project_config = temp_path.join("stackbuilder.toml");
+            fs::write(&project_config, "[build]\nlock_timeout = 60\n").unwrap();
+
+            let diagnostics = crate::diagnostics::Diagnostics::collect();
+            let config = load_config_with_user_config(&project_config, Some(&user_config)).expect("Configuration should load");
+            let warnings: Vec<String> = diagnostics.finish().into_iter().map(|warning| warning.message).collect();
+
+            // Allowed keys fill in what the project leaves unset, paths and other keys are ignored
+            assert_eq!(config.build.yaml_merger, YamlMergerType::Rust);
+            assert_eq!(config.build.lock_timeout, 60);
+            assert_eq!(config.paths.build_dir, Paths::default().build_dir);
+            assert!(config.build.pre_build_command.is_none());
+            assert_eq!(config.user_defaults, Some(UserDefaults { path: user_config.clone(), keys: vec!["yaml_merger".to_string()] }));
+            assert_eq!(warnings.len(), 1, "{:?}", warnings);
+            assert!(warnings[0].starts_with("build.pre_build_command, paths in user configuration"), "{:?}", warnings);
+
+            let text = crate::build::mark_user_defaults(&toml::to_string_pretty(&config).unwrap(), config.user_defaults.as_ref().unwrap());
+            assert!(text.contains("yaml_merger = \"rust\"  # user config\n"), "{}", text);
+            assert!(text.contains("lock_timeout = 60\n"), "{}", text);
+            assert!(toml::from_str::<Config>(&text).is_ok(), "{}", text);
+
+            // A missing user configuration is not an error
+            let config = load_config_with_user_config(&project_config, Some(&temp_path.join("missing.toml"))).unwrap();
+            assert!(config.user_defaults.is_none());
+            assert_eq!(config.build.yaml_merger, BuildConfig::default().yaml_merger);
+
+            // The project's fail_fast overrides the user's keep_going instead of clashing with it
+            fs::write(&user_config, "[build]\nkeep_going = true\n").unwrap();
+            fs::write(&project_config, "[build]\nfail_fast = true\n").unwrap();
+            let config = load_config_with_user_config(&project_config, Some(&user_config)).unwrap();
+            assert!(!config.build.keep_going);
+            assert!(config.user_defaults.is_none());
+
+            fs::write(&user_config, "[build]\nlock_timeout = \"soon\"\n").unwrap();
+            let error = load_config_with_user_config(&project_config, Some(&user_config)).unwrap_err();
+            assert_eq!(error.code(), "config.invalid_toml_syntax");
+            assert_eq!(error.path(), Some(user_config));
+        });
+    }
+
+    #[test]
+    fn test_library_build_reads_user_config_only_when_given() {
+        run_in_temp_dir(|temp_path| {
+            create_test_project_with(temp_path, "yaml_merger = \"rust\"").expect("Failed to create test project");
+            let user_config = temp_path.join("user.toml");
+            fs::write(&user_config, "[build]\ngenerated_header = \"from the user configuration\"\n").unwrap();
+            let compose = temp_path.join("build/dev/base/docker-compose.yml");
+
+            crate::build::build(crate::build::BuildOptions::new(temp_path)).expect("Build should succeed");
+            assert!(!fs::read_to_string(&compose).unwrap().contains("from the user configuration"));
+
+            let options = crate::build::BuildOptions::new(temp_path).with_user_config(Some(user_config));
+            crate::build::build(options).expect("Build should succeed");
+            assert!(fs::read_to_string(&compose).unwrap().contains("# from the user configuration"));
+        });
+    }
+}
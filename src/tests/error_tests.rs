@@ -14,6 +14,7 @@ mod tests {
         let validation_error = StackBuilderError::Validation(ValidationError::ExtensionNotFound {
             name: "test".to_string(),
             available_dirs: vec!["extensions".to_string()],
+            available: vec![],
         });
         assert_eq!(validation_error.exit_code(), 2);
 
@@ -31,6 +32,7 @@ mod tests {
         let yaml_error = StackBuilderError::Yaml(YamlError::ParseError {
             file: "test.yml".to_string(),
             details: "test".to_string(),
+            location: None,
         });
         assert_eq!(yaml_error.exit_code(), 5);
 
@@ -40,6 +42,52 @@ mod tests {
         assert_eq!(init_error.exit_code(), 6);
     }
 
+    #[test]
+    fn test_build_outcome_exit_codes() {
+        // Nothing to build and --strict failures are distinguishable from real build failures
+        let nothing_to_build = StackBuilderError::Build(BuildError::NoCombinations);
+        assert_eq!(nothing_to_build.exit_code(), 8);
+        assert!(nothing_to_build.suggestion().is_some());
+
+        let strict = StackBuilderError::Build(BuildError::StrictWarnings { count: 2, categories: vec!["config".into()] });
+        assert_eq!(strict.exit_code(), 9);
+
+        // A failed combination keeps the exit code of its cause
+        let yaml_error = YamlError::ParseError { file: "f".into(), details: "d".into(), location: None };
+        let combination = StackBuilderError::Build(BuildError::CombinationFailed {
+            output_dir: "dev".into(),
+            environment: Some("dev".into()),
+            extensions: vec![],
+            source: Box::new(yaml_error.into()),
+        });
+        assert_eq!(combination.exit_code(), 5);
+        assert_eq!(combination.to_json()["exit_code"], 5);
+
+        for (error, code) in error_codes() {
+            let expected = match code {
+                "build.no_combinations" => 8,
+                "build.strict_warnings" => 9,
+                "build.checksum_mismatch" => 10,
+                "build.combination_failed" => 3,
+                _ => category_exit_code(code.split('.').next().unwrap()),
+            };
+            assert_eq!(error.exit_code(), expected, "{}", code);
+        }
+    }
+
+    fn category_exit_code(category: &str) -> i32 {
+        match category {
+            "config" => 1,
+            "validation" => 2,
+            "build" => 3,
+            "filesystem" => 4,
+            "yaml" => 5,
+            "init" => 6,
+            "backup" => 7,
+            other => panic!("Unknown error category '{}'", other),
+        }
+    }
+
     #[test]
     fn test_error_suggests_init() {
         let config_error = StackBuilderError::Config(ConfigError::ConfigFileNotFound {
@@ -69,6 +117,7 @@ mod tests {
         let ext_error = StackBuilderError::Validation(ValidationError::ExtensionNotFound {
             name: "monitoring".to_string(),
             available_dirs: vec!["extensions".to_string()],
+            available: vec![],
         });
         assert!(ext_error.suggestion().is_some());
         assert!(ext_error.suggestion().unwrap().contains("monitoring"));
@@ -88,7 +137,7 @@ mod tests {
         // Create a simple TOML parse error by trying to parse invalid content
         let toml_result: std::result::Result<toml::Value, toml::de::Error> = toml::from_str("invalid toml [");
         if let Err(toml_error) = toml_result {
-            let error = ConfigError::toml_parse_error("test.toml", toml_error);
+            let error = ConfigError::toml_parse_error("test.toml", "invalid toml [", toml_error);
             assert!(error.to_string().contains("test.toml"));
             assert!(error.to_string().contains("Invalid TOML syntax"));
         }
@@ -96,11 +145,11 @@ mod tests {
 
     #[test]
     fn test_validation_error_helpers() {
-        let error = ValidationError::environment_not_found("dev", "/test/envs");
+        let error = ValidationError::environment_not_found("dev", "/test/envs", vec![]);
         assert!(error.to_string().contains("dev"));
         assert!(error.to_string().contains("/test/envs"));
 
-        let error = ValidationError::extension_not_found("monitoring", vec!["ext1".to_string(), "ext2".to_string()]);
+        let error = ValidationError::extension_not_found("monitoring", vec!["ext1".to_string(), "ext2".to_string()], vec![]);
         assert!(error.to_string().contains("monitoring"));
         assert!(error.to_string().contains("ext1"));
         assert!(error.to_string().contains("ext2"));
@@ -115,7 +164,7 @@ mod tests {
         // Create a serde_yaml error by trying to parse invalid YAML
         let yaml_result: std::result::Result<serde_yaml_ng::Value, serde_yaml_ng::Error> = serde_yaml_ng::from_str("invalid: yaml: [");
         if let Err(serde_error) = yaml_result {
-            let error = YamlError::serde_error("test.yml", serde_error);
+            let error = YamlError::serde_error("test.yml", "invalid: yaml: [", serde_error);
             assert!(error.to_string().contains("test.yml"));
         }
     }
@@ -131,6 +180,49 @@ mod tests {
         assert!(display.contains("stackbuilder init"));
     }
 
+    #[test]
+    fn test_toml_error_location_snippet() {
+        let content = "[build]\nyaml_merger = \"rust\"\ncopy_env_example = \"yes\"\n";
+        let toml_error = toml::from_str::<crate::config::Config>(content).unwrap_err();
+        let error = ConfigError::toml_parse_error("stackbuilder.toml", content, toml_error);
+
+        let display = error.to_string();
+        assert!(display.starts_with("Invalid TOML syntax in configuration file 'stackbuilder.toml:3:20': invalid type"), "{}", display);
+        assert!(display.ends_with(concat!(
+            "\n  |\n",
+            "2 | yaml_merger = \"rust\"\n",
+            "3 | copy_env_example = \"yes\"\n",
+            "  |                    ^^^^^",
+        )), "{}", display);
+
+        let json = StackBuilderError::from(error).to_json();
+        assert_eq!((json["line"].clone(), json["column"].clone()), (3.into(), 20.into()));
+    }
+
+    #[test]
+    fn test_yaml_error_location_snippet() {
+        let content = "services:\n  web:\n\timage: nginx\n";
+        let yaml_error = serde_yaml_ng::from_str::<serde_yaml_ng::Value>(content).unwrap_err();
+        let error = YamlError::serde_error("docker-compose.yml", content, yaml_error);
+
+        let YamlError::ParseError { location: Some(ref location), .. } = error else {
+            panic!("YAML error should be located: {:?}", error);
+        };
+        assert_eq!((location.line, location.column), (3, 1));
+        assert!(error.to_string().contains("'docker-compose.yml:3:1'"));
+        assert!(location.snippet.ends_with("3 | \timage: nginx\n  | ^"), "{}", location.snippet);
+    }
+
+    #[test]
+    fn test_source_location_caret_follows_tabs_and_clamps_to_line() {
+        let location = SourceLocation::from_span("a = 1\n\tkey = [1,\n2]\n", 11..18);
+        assert_eq!((location.line, location.column), (2, 6));
+        assert_eq!(location.snippet, "  |\n1 | a = 1\n2 | \tkey = [1,\n  | \t    ^^^^^");
+
+        let location = SourceLocation::from_line_column("only line", 1, 4);
+        assert_eq!(location.snippet, "  |\n1 | only line\n  |    ^");
+    }
+
     #[test]
     fn test_error_chain() {
         let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "file not found");
@@ -144,4 +236,133 @@ mod tests {
         assert!(display.contains("/test/file"));
         assert!(display.contains("Failed to read file"));
     }
-}
\ No newline at end of file
+
+    fn io_error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "test")
+    }
+
+    /// One error per variant with the code it must keep; automation depends on these strings
+    fn error_codes() -> Vec<(StackBuilderError, &'static str)> {
+        vec![
+            (ConfigError::ConfigFileNotFound { file: "f".into() }.into(), "config.file_not_found"),
+            (ConfigError::ConfigFileReadError { file: "f".into(), source: io_error() }.into(), "config.file_read_error"),
+            (ConfigError::InvalidTomlSyntax { file: "f".into(), details: "d".into(), location: None }.into(), "config.invalid_toml_syntax"),
+            (ConfigError::InvalidJsonSyntax { file: "f".into(), details: "d".into(), location: None }.into(), "config.invalid_json_syntax"),
+            (ConfigError::TomlSerializationError { details: "d".into() }.into(), "config.toml_serialization_error"),
+            (ConfigError::UnknownKeys { keys: vec!["k".into()] }.into(), "config.unknown_keys"),
+            (ValidationError::ComponentsDirectoryNotFound { path: "p".into() }.into(), "validation.components_directory_not_found"),
+            (ValidationError::BaseDirectoryNotFound { path: "p".into() }.into(), "validation.base_directory_not_found"),
+            (ValidationError::environment_not_found("e", "p", vec![]).into(), "validation.environment_not_found"),
+            (ValidationError::extension_not_found("e", vec![], vec![]).into(), "validation.extension_not_found"),
+            (ValidationError::ComboNotFound { combo_name: "c".into(), available_combos: vec![] }.into(), "validation.combo_not_found"),
+            (ValidationError::InvalidComboDefinition { combo_name: "c".into(), details: "d".into() }.into(), "validation.invalid_combo_definition"),
+            (ValidationError::PathResolutionError { path: "p".into(), details: "d".into() }.into(), "validation.path_resolution_error"),
+            (ValidationError::OrphanExtensions { names: vec![] }.into(), "validation.orphan_extensions"),
+            (ValidationError::InvalidComboNameSeparator { separator: "/".into() }.into(), "validation.invalid_combo_name_separator"),
+            (ValidationError::OutputDirCollision { output_dir: "dev/monitoring".into(), first: "extension 'monitoring'".into(), second: "combo 'monitoring'".into() }.into(), "validation.output_dir_collision"),
+            (ValidationError::InvalidEnvFilename { key: "env_source_filename".into(), name: "a/b".into() }.into(), "validation.invalid_env_filename"),
+            (ValidationError::InvalidAlias { name: "prod/security".into(), alias: "..".into(), reason: "r".into() }.into(), "validation.invalid_alias"),
+            (ValidationError::DuplicateAlias { alias: "a".into(), first: "dev/x".into(), second: "dev/y".into() }.into(), "validation.duplicate_alias"),
+            (ValidationError::NestedAlias { outer: "a".into(), inner: "a/b".into(), first: "dev/x".into(), second: "dev/y".into() }.into(), "validation.nested_alias"),
+            (ValidationError::InvalidMatrixGroup { group: "g".into(), details: "d".into() }.into(), "validation.invalid_matrix_group"),
+            (ValidationError::TooManyCombinations { count: 200, max: 100 }.into(), "validation.too_many_combinations"),
+            (ValidationError::InvalidImageTag { environment: "e".into(), key: "k".into(), tag: "t:".into() }.into(), "validation.invalid_image_tag"),
+            (ValidationError::ExclusiveGroupConflict { group: "g".into(), combination: "c".into(), members: vec![] }.into(), "validation.exclusive_group_conflict"),
+            (ValidationError::DefaultEnvironmentNotFound { name: "n".into(), available: vec![] }.into(), "validation.default_environment_not_found"),
+            (ValidationError::OutputInsideComponentsRoot { path: "p".into(), root: "r".into() }.into(), "validation.output_inside_components_root"),
+            (ValidationError::Multiple { errors: vec![] }.into(), "validation.multiple"),
+            (BuildError::OutputFileWriteError { path: "p".into(), source: io_error() }.into(), "build.output_file_write_error"),
+            (BuildError::BuildProcessFailed { details: "d".into() }.into(), "build.process_failed"),
+            (BuildError::UnsafeBuildDirectory { path: "p".into(), reason: "r".into() }.into(), "build.unsafe_build_directory"),
+            (BuildError::BuildLocked { lock_path: "p".into(), pid: None }.into(), "build.locked"),
+            (BuildError::UnknownCombination { name: "n".into(), available: vec![] }.into(), "build.unknown_combination"),
+            (BuildError::StrictWarnings { count: 1, categories: vec![] }.into(), "build.strict_warnings"),
+            (BuildError::CombinationFailed { output_dir: "o".into(), environment: None, extensions: vec![], source: Box::new(BuildError::BuildProcessFailed { details: "d".into() }.into()) }.into(), "build.combination_failed"),
+            (BuildError::CombinationsFailed { total: 1, errors: vec![] }.into(), "build.combinations_failed"),
+            (BuildError::NoCombinations.into(), "build.no_combinations"),
+            (BuildError::DefaultEnvironmentNotSet.into(), "build.default_environment_not_set"),
+            (BuildError::ChecksumsNotFound { path: "p".into() }.into(), "build.checksums_not_found"),
+            (BuildError::ChecksumMismatch { modified: 1, missing: 0, extra: 0 }.into(), "build.checksum_mismatch"),
+            (BuildError::ArchiveInBuildDir { path: "p".into() }.into(), "build.archive_in_build_dir"),
+            (BuildError::ArchivePathNotSet.into(), "build.archive_path_not_set"),
+            (BuildError::MissingImages { images: vec!["app:1".into()] }.into(), "build.missing_images"),
+            (BuildError::ServiceConflict { service: "db".into(), first: "a".into(), second: "b".into(), paths: vec![] }.into(), "build.service_conflict"),
+            (BuildError::WatchConfigFromStdin.into(), "build.watch_config_from_stdin"),
+            (BuildError::PreBuildCommandFailed { command: "c".into(), status: "s".into(), output: String::new() }.into(), "build.pre_build_command_failed"),
+            (FileSystemError::DirectoryCreationFailed { path: "p".into(), source: io_error() }.into(), "filesystem.directory_creation_failed"),
+            (FileSystemError::DirectoryReadFailed { path: "p".into(), source: io_error() }.into(), "filesystem.directory_read_failed"),
+            (FileSystemError::FileReadFailed { path: "p".into(), source: io_error() }.into(), "filesystem.file_read_failed"),
+            (FileSystemError::FileWriteFailed { path: "p".into(), source: io_error() }.into(), "filesystem.file_write_failed"),
+            (YamlError::parse_error("f", "d").into(), "yaml.parse_error"),
+            (YamlError::SerializationError { details: "d".into() }.into(), "yaml.serialization_error"),
+            (YamlError::MergeError { details: "d".into() }.into(), "yaml.merge_error"),
+            (YamlError::InvalidComposeFormat { file: "f".into(), details: "d".into() }.into(), "yaml.invalid_compose_format"),
+            (InitError::ProjectStructureCreationFailed { source: io_error() }.into(), "init.project_structure_creation_failed"),
+            (InitError::ExampleFileCreationFailed { details: "d".into() }.into(), "init.example_file_creation_failed"),
+            (InitError::ComponentsDirectoryExists { path: "p".into() }.into(), "init.components_directory_exists"),
+            (InitError::UnknownTemplate { name: "n".into(), available: vec![] }.into(), "init.unknown_template"),
+            (InitError::OverwriteNotConfirmed { path: "p".into() }.into(), "init.overwrite_not_confirmed"),
+            (InitError::ComponentTreeNotFound { path: "p".into() }.into(), "init.component_tree_not_found"),
+            (InitError::AmbiguousLayout { kind: "base", candidates: vec![], flag: "base-dir" }.into(), "init.ambiguous_layout"),
+            (InitError::ConfigToStdin.into(), "init.config_to_stdin"),
+            (BackupError::BackupNotFound { backup_id: "b".into(), backup_dir: "p".into(), available: vec![] }.into(), "backup.not_found"),
+            (BackupError::InvalidBackup { backup_id: "b".into(), details: "d".into() }.into(), "backup.invalid"),
+            (BackupError::UnsafePath { backup_id: "b".into(), path: "../.env".into() }.into(), "backup.unsafe_path"),
+            (BackupError::PathNotInBackup { backup_id: "b".into(), path: "p".into() }.into(), "backup.path_not_in_backup"),
+            (BackupError::RestoreConflict { paths: vec![] }.into(), "backup.restore_conflict"),
+            (BackupError::NoBackups { backup_dir: "p".into() }.into(), "backup.no_backups"),
+        ]
+    }
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        let mut seen = std::collections::HashSet::new();
+        for (error, code) in error_codes() {
+            assert_eq!(error.code(), code, "Error code changed for: {}", error);
+            assert!(seen.insert(code), "Duplicate error code: {}", code);
+        }
+    }
+
+    #[test]
+    fn test_error_json() {
+        let error = StackBuilderError::Validation(ValidationError::BaseDirectoryNotFound {
+            path: "components/base".into(),
+        });
+        let json = error.to_json();
+        assert_eq!(json["code"], "validation.base_directory_not_found");
+        assert_eq!(json["message"], error.to_string());
+        assert_eq!(json["suggestion"], "Create a base/docker-compose.yml file in your components directory");
+        assert_eq!(json["path"], "components/base");
+        assert_eq!(json["exit_code"], 2);
+
+        let error = StackBuilderError::Build(BuildError::BuildProcessFailed { details: "boom".into() });
+        let json = error.to_json();
+        assert!(json["suggestion"].is_null());
+        assert!(json["path"].is_null());
+        assert!(!json.to_string().contains('\n'), "JSON errors must fit on one line");
+    }
+
+    #[test]
+    fn test_did_you_mean_suggestions() {
+        let names = |list: &[&str]| list.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        let error: StackBuilderError = ValidationError::extension_not_found("monitorng", vec!["extensions".to_string()], names(&["auth", "monitoring"])).into();
+        assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'monitoring'?"));
+
+        // Nothing close enough falls back to the generic hint
+        let error: StackBuilderError = ValidationError::extension_not_found("metrics", vec!["extensions".to_string()], names(&["auth", "monitoring"])).into();
+        assert!(error.suggestion().unwrap().contains("Create an extension directory"));
+
+        let error: StackBuilderError = ValidationError::ComboNotFound { combo_name: "scure".into(), available_combos: names(&["secure", "observability"]) }.into();
+        assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'secure'?"));
+
+        let error: StackBuilderError = ValidationError::environment_not_found("prd", "components/environments", names(&["dev", "prod"])).into();
+        assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'prod'?"));
+
+        let error: StackBuilderError = BuildError::UnknownCombination { name: "dev/monitorng".into(), available: names(&["dev/base", "dev/monitoring"]) }.into();
+        assert_eq!(error.suggestion().as_deref(), Some("Did you mean 'dev/monitoring'?"));
+
+        let error: StackBuilderError = ValidationError::environment_not_found("staging", "components/environments", names(&["dev", "prod"])).into();
+        assert!(error.suggestion().is_none());
+    }
+}
@@ -4,6 +4,7 @@
 mod tests {
     use crate::init::*;
     use crate::tests::*;
+    use crate::config::load_config_from;
     use std::fs;
 
     #[test]
@@ -12,6 +13,7 @@ mod tests {
             let args = InitArgs {
                 skip_folders: false,
                 force: false,
+                ..Default::default()
             };
             
             let result = run_init_in_dir(&args, temp_path);
@@ -44,6 +46,7 @@ mod tests {
             let args = InitArgs {
                 skip_folders: true,
                 force: false,
+                ..Default::default()
             };
             
             let result = run_init_in_dir(&args, temp_path);
@@ -82,6 +85,7 @@ environments = ["existing-env"]
             let args = InitArgs {
                 skip_folders: false,
                 force: false,
+                ..Default::default()
             };
             
             let result = run_init_in_dir(&args, temp_path);
@@ -99,6 +103,20 @@ environments = ["existing-env"]
         });
     }
 
+    #[test]
+    fn test_init_existing_config_reads_include_files() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\ninclude_files = [\"paths.toml\"]\n").unwrap();
+            fs::write(temp_path.join("paths.toml"), "[paths]\ncomponents_dir = \"./stacks\"\n").unwrap();
+
+            run_init_in_dir(&InitArgs::default(), temp_path).expect("Init should succeed with an existing config");
+
+            // Folders follow the configuration with its included files, like a build sees it
+            assert!(temp_path.join("stacks/base").exists());
+            assert!(!temp_path.join("components").exists());
+        });
+    }
+
     #[test]
     fn test_init_existing_config_with_force() {
         run_in_temp_dir(|temp_path| {
@@ -121,6 +139,8 @@ environments = ["existing-env"]
             let args = InitArgs {
                 skip_folders: false,
                 force: true,
+                yes: true,
+                ..Default::default()
             };
             
             let result = run_init_in_dir(&args, temp_path);
@@ -133,4 +153,423 @@ environments = ["existing-env"]
             assert!(current_content.contains("[paths]"));
         });
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_init_stores_project_name() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs {
+                name: Some("my-project".to_string()),
+                ..Default::default()
+            };
+
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Generated config should load");
+            assert_eq!(config.project.name.as_deref(), Some("my-project"));
+
+            let compose = fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap();
+            assert!(compose.starts_with("# Base services for project: my-project\n"));
+        });
+    }
+
+    #[test]
+    fn test_init_defaults_project_name_to_directory() {
+        run_in_temp_dir(|temp_path| {
+            let project_dir = temp_path.join("shop-stack");
+            fs::create_dir_all(&project_dir).unwrap();
+
+            run_init_in_dir(&InitArgs::default(), &project_dir).expect("Init should succeed");
+
+            let content = fs::read_to_string(project_dir.join("stackbuilder.toml")).unwrap();
+            assert!(content.starts_with("[project]\nname = \"shop-stack\"\n"), "{}", content);
+        });
+    }
+
+    #[test]
+    fn test_init_dry_run_writes_nothing() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs {
+                dry_run: true,
+                ..Default::default()
+            };
+
+            let result = run_init_in_dir(&args, temp_path);
+            assert!(result.is_ok(), "Dry run should succeed: {:?}", result);
+
+            let entries: Vec<_> = fs::read_dir(temp_path).unwrap().collect();
+            assert!(entries.is_empty(), "Dry run should not create anything: {:?}", entries);
+        });
+    }
+
+    #[test]
+    fn test_init_dry_run_with_existing_config() {
+        run_in_temp_dir(|temp_path| {
+            let config_file = temp_path.join("stackbuilder.toml");
+            fs::write(&config_file, "[paths]\ncomponents_dir = \"./existing-components\"\n").unwrap();
+
+            let args = InitArgs {
+                force: true,
+                dry_run: true,
+                ..Default::default()
+            };
+
+            run_init_in_dir(&args, temp_path).expect("Dry run should succeed");
+
+            assert_eq!(fs::read_to_string(&config_file).unwrap(), "[paths]\ncomponents_dir = \"./existing-components\"\n");
+            assert!(!temp_path.join("components").exists());
+            assert!(!temp_path.join("existing-components").exists());
+        });
+    }
+
+    #[test]
+    fn test_init_scaffolds_environments_and_extensions() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs {
+                environments: vec!["dev".to_string(), "prod".to_string()],
+                extensions: vec!["monitoring".to_string()],
+                ..Default::default()
+            };
+
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Generated config should load");
+            assert_eq!(config.build.environments, Some(vec!["dev".to_string(), "prod".to_string()]));
+            assert_eq!(config.build.extensions, Some(vec!["monitoring".to_string()]));
+
+            for component in ["environments/dev", "environments/prod", "extensions/monitoring"] {
+                let dir = temp_path.join("components").join(component);
+                assert!(dir.join("docker-compose.yml").exists(), "Missing compose in {}", component);
+                assert!(dir.join(".env.example").exists(), "Missing .env.example in {}", component);
+            }
+
+            // The scaffolded project builds as is
+            let config_path = temp_path.join("stackbuilder.toml");
+            let content = fs::read_to_string(&config_path).unwrap().replace("yaml_merger = \"yq\"", "yaml_merger = \"rust\"");
+            fs::write(&config_path, content).unwrap();
+
+            let result = execute_real_build_in_dir(temp_path);
+            assert!(result.is_ok(), "Scaffolded project should build: {:?}", result);
+
+            let compose = fs::read_to_string(temp_path.join("build/prod/monitoring/docker-compose.yml")).unwrap();
+            assert!(compose.contains("APP_ENV=prod"));
+            assert!(compose.contains("monitoring:"));
+        });
+    }
+
+    #[test]
+    fn test_init_with_examples_builds_multiple_combinations() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs {
+                with_examples: true,
+                environments: vec!["prod".to_string()],
+                ..Default::default()
+            };
+
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Generated config should load");
+            assert_eq!(config.build.environments, Some(vec!["prod".to_string(), "dev".to_string()]));
+            assert_eq!(config.build.extensions, Some(vec!["monitoring".to_string()]));
+            let dev = fs::read_to_string(temp_path.join("components/environments/dev/docker-compose.yml")).unwrap();
+            assert!(dev.contains("APP_ENV=dev"), "{}", dev);
+            let monitoring = fs::read_to_string(temp_path.join("components/extensions/monitoring/docker-compose.yml")).unwrap();
+            assert!(monitoring.contains("image: prom/prometheus"), "{}", monitoring);
+
+            let config_path = temp_path.join("stackbuilder.toml");
+            let content = fs::read_to_string(&config_path).unwrap().replace("yaml_merger = \"yq\"", "yaml_merger = \"rust\"");
+            fs::write(&config_path, content).unwrap();
+
+            let report = execute_real_build_in_dir(temp_path).expect("Example project should build");
+            let output_dirs: Vec<_> = report.combinations.iter().map(|combination| combination.output_dir.as_str()).collect();
+            assert_eq!(output_dirs, ["prod/base", "prod/monitoring", "dev/base", "dev/monitoring"]);
+            let compose = fs::read_to_string(temp_path.join("build/dev/monitoring/docker-compose.yml")).unwrap();
+            assert!(compose.contains("APP_ENV=dev"), "{}", compose);
+            assert!(compose.contains("prometheus:"), "{}", compose);
+        });
+    }
+
+    const MONOLITH_COMPOSE: &str = "services:\n  web:\n    image: nginx\n    ports:\n      - \"80:80\"\n";
+    const MONOLITH_OVERRIDE: &str = "services:\n  web:\n    environment:\n      - DEBUG=1\n";
+
+    #[test]
+    fn test_init_from_compose() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("docker-compose.yml"), MONOLITH_COMPOSE).unwrap();
+            fs::write(temp_path.join("docker-compose.override.yml"), MONOLITH_OVERRIDE).unwrap();
+
+            let args = InitArgs {
+                from_compose: Some("docker-compose.yml".into()),
+                override_compose: Some("docker-compose.override.yml".into()),
+                ..Default::default()
+            };
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            assert_eq!(fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap(), MONOLITH_COMPOSE);
+            assert_eq!(fs::read_to_string(temp_path.join("components/environments/dev/docker-compose.yml")).unwrap(), MONOLITH_OVERRIDE);
+            // Originals are left untouched without --move
+            assert!(temp_path.join("docker-compose.yml").exists());
+            assert!(temp_path.join("docker-compose.override.yml").exists());
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Generated config should load");
+            assert_eq!(config.build.environments, Some(vec!["dev".to_string()]));
+
+            let config_path = temp_path.join("stackbuilder.toml");
+            let content = fs::read_to_string(&config_path).unwrap().replace("yaml_merger = \"yq\"", "yaml_merger = \"rust\"");
+            fs::write(&config_path, content).unwrap();
+
+            execute_real_build_in_dir(temp_path).expect("Adopted project should build");
+            let compose = fs::read_to_string(temp_path.join("build/docker-compose.yml")).unwrap();
+            assert!(compose.contains("image: nginx"));
+            assert!(compose.contains("DEBUG=1"));
+        });
+    }
+
+    #[test]
+    fn test_init_from_compose_move() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("docker-compose.yml"), MONOLITH_COMPOSE).unwrap();
+
+            let args = InitArgs {
+                from_compose: Some("docker-compose.yml".into()),
+                move_files: true,
+                ..Default::default()
+            };
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            assert!(!temp_path.join("docker-compose.yml").exists());
+            assert_eq!(fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap(), MONOLITH_COMPOSE);
+            assert!(!temp_path.join("components/environments/dev").exists());
+        });
+    }
+
+    #[test]
+    fn test_init_from_compose_with_skip_folders() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("docker-compose.yml"), MONOLITH_COMPOSE).unwrap();
+
+            let args = InitArgs {
+                from_compose: Some("docker-compose.yml".into()),
+                skip_folders: true,
+                ..Default::default()
+            };
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            // Only the scaffolding is skipped
+            assert_eq!(fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap(), MONOLITH_COMPOSE);
+            assert!(!temp_path.join("components/extensions").exists());
+            assert!(!temp_path.join("components/README.md").exists());
+        });
+    }
+
+    #[test]
+    fn test_init_from_compose_refuses_existing_components() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("docker-compose.yml"), MONOLITH_COMPOSE).unwrap();
+            fs::create_dir_all(temp_path.join("components/base")).unwrap();
+
+            let args = InitArgs {
+                from_compose: Some("docker-compose.yml".into()),
+                ..Default::default()
+            };
+            let result = run_init_in_dir(&args, temp_path);
+            assert!(matches!(
+                result,
+                Err(crate::error::StackBuilderError::Init(crate::error::InitError::ComponentsDirectoryExists { .. }))
+            ), "{:?}", result);
+            assert!(!temp_path.join("stackbuilder.toml").exists());
+            assert!(!temp_path.join("components/base/docker-compose.yml").exists());
+
+            let args = InitArgs { force: true, ..args };
+            run_init_in_dir(&args, temp_path).expect("Init with --force should succeed");
+            assert_eq!(fs::read_to_string(temp_path.join("components/base/docker-compose.yml")).unwrap(), MONOLITH_COMPOSE);
+        });
+    }
+
+    #[test]
+    fn test_init_templates_build() {
+        for template in crate::templates::TEMPLATES {
+            run_in_temp_dir(|temp_path| {
+                let args = InitArgs {
+                    template: Some(template.name.to_string()),
+                    name: Some("demo".to_string()),
+                    ..Default::default()
+                };
+                run_init_in_dir(&args, temp_path).expect("Init from template should succeed");
+
+                let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Template config should load");
+                assert_eq!(config.project.name.as_deref(), Some("demo"));
+                assert!(temp_path.join("components/base/.env.example").exists());
+
+                let result = execute_real_build_in_dir(temp_path);
+                assert!(result.is_ok(), "Template '{}' should build: {:?}", template.name, result);
+            });
+        }
+    }
+
+    #[test]
+    fn test_init_template_keeps_existing_files() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join("stackbuilder.toml"), "[build]\nyaml_merger = \"rust\"\n").unwrap();
+
+            let args = InitArgs {
+                template: Some("minimal".to_string()),
+                ..Default::default()
+            };
+            run_init_in_dir(&args, temp_path).expect("Init from template should succeed");
+
+            assert_eq!(fs::read_to_string(temp_path.join("stackbuilder.toml")).unwrap(), "[build]\nyaml_merger = \"rust\"\n");
+            assert!(temp_path.join("components/base/docker-compose.yml").exists());
+        });
+    }
+
+    #[test]
+    fn test_init_unknown_template_lists_available() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs {
+                template: Some("kubernetes".to_string()),
+                ..Default::default()
+            };
+            let error = run_init_in_dir(&args, temp_path).unwrap_err().to_string();
+            assert!(error.contains("Unknown template 'kubernetes'"), "{}", error);
+            assert!(error.contains("minimal, web-app, monitoring-stack"), "{}", error);
+            assert!(fs::read_dir(temp_path).unwrap().next().is_none());
+        });
+    }
+
+    #[test]
+    fn test_init_extends_gitignore_and_writes_readme() {
+        run_in_temp_dir(|temp_path| {
+            fs::write(temp_path.join(".gitignore"), "target\nbuild/").unwrap();
+
+            run_init_in_dir(&InitArgs::default(), temp_path).expect("Init should succeed");
+
+            // Existing entries are not duplicated, missing ones are appended
+            assert_eq!(fs::read_to_string(temp_path.join(".gitignore")).unwrap(), "target\nbuild/\n/.stackbuilder/\n");
+
+            let readme = fs::read_to_string(temp_path.join("components/README.md")).unwrap();
+            assert!(readme.contains("`base/`"));
+            assert!(readme.contains("`extensions/`"));
+
+            // A second run finds nothing to add
+            run_init_in_dir(&InitArgs::default(), temp_path).expect("Re-run should succeed");
+            assert_eq!(fs::read_to_string(temp_path.join(".gitignore")).unwrap(), "target\nbuild/\n/.stackbuilder/\n");
+        });
+    }
+
+    #[test]
+    fn test_init_no_gitignore_no_readme() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs {
+                no_gitignore: true,
+                no_readme: true,
+                ..Default::default()
+            };
+            run_init_in_dir(&args, temp_path).expect("Init should succeed");
+
+            assert!(!temp_path.join(".gitignore").exists());
+            assert!(!temp_path.join("components/README.md").exists());
+            assert!(temp_path.join("components/base/docker-compose.yml").exists());
+        });
+    }
+
+    #[test]
+    fn test_init_adopt_existing_tree() {
+        run_in_temp_dir(|temp_path| {
+            create_test_compose(&temp_path.join("components/base/docker-compose.yml")).expect("Failed to create base compose");
+            create_test_compose(&temp_path.join("components/environments/dev/docker-compose.yml")).expect("Failed to create dev compose");
+            create_test_compose(&temp_path.join("components/environments/prod/docker-compose.yml")).expect("Failed to create prod compose");
+            create_test_compose(&temp_path.join("components/extensions/monitoring/docker-compose.yml")).expect("Failed to create monitoring compose");
+
+            let args = InitArgs {
+                adopt: true,
+                no_readme: true,
+                ..Default::default()
+            };
+            let result = run_init_in_dir(&args, temp_path);
+            assert!(result.is_ok(), "Adopt should succeed: {:?}", result);
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Adopted config should load");
+            assert_eq!(config.build.environments, Some(vec!["dev".to_string(), "prod".to_string()]));
+            assert_eq!(config.build.extensions, Some(vec!["monitoring".to_string()]));
+            assert!(config.build.require_base);
+            assert!(config.validate_against(temp_path).is_ok());
+            assert!(!temp_path.join("components/base/.env.example").exists(), "Adopt should not scaffold components");
+        });
+    }
+
+    #[test]
+    fn test_init_adopt_extension_library_without_base() {
+        run_in_temp_dir(|temp_path| {
+            create_test_compose(&temp_path.join("components/extensions/db/docker-compose.yml")).expect("Failed to create db compose");
+            create_test_compose(&temp_path.join("components/extensions/cache/docker-compose.yml")).expect("Failed to create cache compose");
+
+            let args = InitArgs {
+                adopt: true,
+                ..Default::default()
+            };
+            let result = run_init_in_dir(&args, temp_path);
+            assert!(result.is_ok(), "Adopt should succeed: {:?}", result);
+
+            let config = load_config_from(&temp_path.join("stackbuilder.toml")).expect("Adopted config should load");
+            assert!(!config.build.require_base, "A tree without base should not require one");
+            assert_eq!(config.build.extensions, Some(vec!["cache".to_string(), "db".to_string()]));
+            assert!(config.validate_against(temp_path).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_init_rerun_follows_customized_config() {
+        run_in_temp_dir(|temp_path| {
+            let config_content = r#"
+[paths]
+base_dir = "core"
+extensions_dirs = ["addons", "shared"]
+
+[build.environments]
+available = ["dev", "staging"]
+
+[build.environments.dev]
+extensions = ["redis"]
+
+[build.combos]
+observability = ["metrics"]
+"#;
+            fs::write(temp_path.join("stackbuilder.toml"), config_content).expect("Failed to write config");
+            create_test_compose(&temp_path.join("components/shared/metrics/docker-compose.yml")).expect("Failed to create metrics compose");
+
+            let args = InitArgs {
+                no_gitignore: true,
+                ..Default::default()
+            };
+            let result = run_init_in_dir(&args, temp_path);
+            assert!(result.is_ok(), "Init should succeed with an existing config: {:?}", result);
+
+            let components = temp_path.join("components");
+            assert!(components.join("core/docker-compose.yml").exists());
+            assert!(!components.join("base").exists(), "Default base dir should not be created");
+            assert!(!components.join("extensions").exists(), "Default extensions dir should not be created");
+            assert!(components.join("environments/dev/docker-compose.yml").exists());
+            assert!(components.join("environments/staging/docker-compose.yml").exists());
+            assert!(components.join("addons/redis/docker-compose.yml").exists(), "New-API extension should be scaffolded");
+            assert!(!components.join("addons/metrics").exists(), "Existing extension should be kept where it is");
+            assert_eq!(fs::read_to_string(temp_path.join("stackbuilder.toml")).unwrap(), config_content);
+        });
+    }
+
+    #[test]
+    fn test_init_config_path() {
+        run_in_temp_dir(|temp_path| {
+            let args = InitArgs { config: Some("-".into()), ..Default::default() };
+            let error = run_init_in_dir(&args, temp_path).unwrap_err();
+            assert_eq!(error.code(), "init.config_to_stdin");
+            assert!(error.suggestion().is_some());
+            assert!(fs::read_dir(temp_path).unwrap().next().is_none(), "Nothing should be written");
+
+            let args = InitArgs { config: Some("stacks/dev.toml".into()), skip_folders: true, ..Default::default() };
+            fs::create_dir_all(temp_path.join("stacks")).unwrap();
+            run_init_in_dir(&args, temp_path).expect("Init with --config should succeed");
+            assert!(load_config_from(&temp_path.join("stacks/dev.toml")).is_ok());
+            assert!(!temp_path.join("stackbuilder.toml").exists());
+        });
+    }
+}
@@ -1,6 +1,7 @@
 //! Tests for YAML merging functionality
 
 #[cfg(test)]
+#[allow(clippy::needless_borrows_for_generic_args)]
 mod tests {
     use crate::merger::*;
     use crate::tests::*;
@@ -117,7 +118,7 @@ services:
     image: postgres:13
 "#).unwrap();
         
-        let result = merge_yaml_values(base, override_yaml);
+        let result = merge_yaml_values(base, override_yaml, &mut MergeStats::default());
         
         if let Value::Mapping(map) = result {
             if let Some(Value::Mapping(services)) = map.get(&Value::String("services".to_string())) {
@@ -155,7 +156,7 @@ services:
       - "8080:80"
 "#).unwrap();
         
-        let result = merge_yaml_values(base, override_yaml);
+        let result = merge_yaml_values(base, override_yaml, &mut MergeStats::default());
         
         if let Value::Mapping(map) = result {
             if let Some(Value::Mapping(services)) = map.get(&Value::String("services".to_string())) {
@@ -195,7 +196,7 @@ services:
     restart: always
 "#).unwrap();
         
-        let result = merge_yaml_values(base, override_yaml);
+        let result = merge_yaml_values(base, override_yaml, &mut MergeStats::default());
         
         if let Value::Mapping(map) = result {
             // Version should be overridden
@@ -216,6 +217,101 @@ services:
         }
     }
 
+    #[test]
+    fn test_merge_yaml_values_override_tag() {
+        let base = serde_yaml_ng::from_str(r#"
+services:
+  web:
+    image: nginx:alpine
+    ports:
+      - "80:80"
+      - "443:443"
+"#).unwrap();
+        
+        let override_yaml = serde_yaml_ng::from_str(r#"
+services:
+  web:
+    ports: !override
+      - "8080:80"
+"#).unwrap();
+        
+        let result = resolve_merge_tags(merge_yaml_values(base, override_yaml, &mut MergeStats::default()));
+        
+        let ports = &result["services"]["web"]["ports"];
+        assert_eq!(ports, &Value::Sequence(vec![Value::String("8080:80".to_string())]));
+        assert_eq!(result["services"]["web"]["image"], Value::String("nginx:alpine".to_string()));
+        
+        // Tags must not leak into the serialized output
+        let output = serde_yaml_ng::to_string(&result).unwrap();
+        assert!(!output.contains("!override"));
+    }
+
+    #[test]
+    fn test_merge_yaml_values_counts_overridden_keys() {
+        let base = serde_yaml_ng::from_str(r#"
+services:
+  web:
+    image: nginx:alpine
+    restart: always
+    environment:
+      LOG_LEVEL: info
+      MODE: prod
+    ports:
+      - "80:80"
+"#).unwrap();
+        
+        let override_yaml = serde_yaml_ng::from_str(r#"
+services:
+  web:
+    image: nginx:latest
+    restart: always
+    environment:
+      LOG_LEVEL: debug
+      DEBUG: "true"
+    ports:
+      - "8080:80"
+  db:
+    image: postgres
+"#).unwrap();
+        
+        let mut stats = MergeStats::default();
+        merge_yaml_values(base, override_yaml, &mut stats);
+        
+        // image and LOG_LEVEL are replaced; identical values, new keys and appended ports are not overrides
+        assert_eq!(stats.overridden_keys, 2);
+    }
+
+    #[test]
+    fn test_merge_yaml_values_reset_tag() {
+        let base = serde_yaml_ng::from_str(r#"
+services:
+  web:
+    image: nginx:alpine
+    command: nginx -g 'daemon off;'
+"#).unwrap();
+        
+        let override_yaml = serde_yaml_ng::from_str(r#"
+services:
+  web:
+    command: !reset null
+  debug: !reset null
+"#).unwrap();
+        
+        let result = resolve_merge_tags(merge_yaml_values(base, override_yaml, &mut MergeStats::default()));
+        
+        if let Value::Mapping(services) = &result["services"] {
+            assert!(!services.contains_key("debug"));
+            if let Value::Mapping(web) = &services["web"] {
+                assert!(!web.contains_key("command"));
+                assert!(web.contains_key("image"));
+            } else {
+                panic!("Expected web service");
+            }
+        } else {
+            panic!("Expected services mapping");
+        }
+    }
+
     #[test]
     fn test_resolve_merge_order_base_only() {
         let merger = ComposeMerger::new(
@@ -299,7 +395,8 @@ services:
         let result = merge_compose_files(&merger, Some("dev"), &["monitoring".to_string()]);
         assert!(result.is_ok());
         
-        let merged = result.unwrap();
+        let (merged, stats) = result.unwrap();
+        assert_eq!(stats.files, 3);
         if let Value::Mapping(map) = merged {
             assert!(map.contains_key(&Value::String("version".to_string())));
             
@@ -324,4 +421,542 @@ services:
         }
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_merge_warns_about_missing_extension_and_skipped_file() {
+        use crate::diagnostics::{Diagnostics, WarningCategory};
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path()).expect("Failed to create test project");
+        let env_file = temp_dir.path().join("components/environments/dev/docker-compose.yml");
+        fs::write(&env_file, "services: [unclosed\n").expect("Failed to write invalid compose file");
+        let merger = ComposeMerger::new(
+            temp_dir.path().join("components/base").to_string_lossy().to_string(),
+            temp_dir.path().join("components/environments").to_string_lossy().to_string(),
+            vec![temp_dir.path().join("components/extensions").to_string_lossy().to_string()],
+        );
+
+        let diagnostics = Diagnostics::collect();
+        let (_, stats) = merge_compose_files(&merger, Some("dev"), &["monitoring".to_string(), "missing".to_string()])
+            .expect("Skipped files are not fatal");
+        let warnings = diagnostics.finish();
+
+        assert_eq!(stats.files, 2);
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert_eq!(warnings[0].category, WarningCategory::MissingFile);
+        assert_eq!(warnings[0].message, "Extension 'missing' not found in any extensions directory");
+        assert_eq!(warnings[0].path, None);
+        assert_eq!(warnings[1].category, WarningCategory::MissingFile);
+        assert!(warnings[1].message.starts_with("Skipping missing or invalid file"), "{}", warnings[1].message);
+        assert_eq!(warnings[1].path.as_deref(), Some(env_file.as_path()));
+    }
+
+    #[test]
+    fn test_env_merge_warns_about_skipped_env_example() {
+        use crate::diagnostics::{Diagnostics, WarningCategory};
+        use crate::env_merger::{merge_env_files, EnvMerger};
+
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        create_test_project(temp_dir.path()).expect("Failed to create test project");
+        fs::write(temp_dir.path().join("components/base/.env.example"), "PORT=80\n").unwrap();
+        let merger = EnvMerger::new(
+            temp_dir.path().join("components/base").to_string_lossy().to_string(),
+            temp_dir.path().join("components/environments").to_string_lossy().to_string(),
+            vec![temp_dir.path().join("components/extensions").to_string_lossy().to_string()],
+        );
+
+        let diagnostics = Diagnostics::collect();
+        let merged = merge_env_files(&merger, Some("dev"), &[]).expect("A missing environment file is not fatal");
+        let warnings = diagnostics.finish();
+
+        assert!(merged.has_variables());
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert_eq!(warnings[0].category, WarningCategory::MissingFile);
+        assert_eq!(warnings[0].path, Some(temp_dir.path().join("components/environments/dev/.env.example")));
+    }
+
+    /// Merge two documents with a single configured section strategy
+    fn merge_with_section_strategy(section: &str, strategy: crate::config::MergeStrategy, base: &str, override_yaml: &str) -> Value {
+        let strategies = std::collections::BTreeMap::from([(section.to_string(), strategy)]);
+        let mut stats = MergeStats::default();
+        merge_compose_documents(
+            serde_yaml_ng::from_str(base).unwrap(),
+            serde_yaml_ng::from_str(override_yaml).unwrap(),
+            &strategies,
+            &mut stats,
+        )
+    }
+
+    const STRATEGY_BASE: &str = r#"
+version: "3.8"
+services:
+  web:
+    image: nginx
+    ports: ["80:80"]
+networks:
+  frontend:
+    driver: bridge
+    labels: [a]
+  backend:
+    driver: bridge
+"#;
+
+    const STRATEGY_OVERRIDE: &str = r#"
+version: "3.9"
+services:
+  web:
+    ports: ["80:80", "443:443"]
+networks:
+  frontend:
+    labels: [b]
+  monitoring:
+    driver: overlay
+"#;
+
+    #[test]
+    fn test_merge_strategy_deep_is_default() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::Deep, STRATEGY_BASE, STRATEGY_OVERRIDE);
+        let mut stats = MergeStats::default();
+        let expected = merge_yaml_values(
+            serde_yaml_ng::from_str(STRATEGY_BASE).unwrap(),
+            serde_yaml_ng::from_str(STRATEGY_OVERRIDE).unwrap(),
+            &mut stats,
+        );
+        assert_eq!(merged, expected);
+        assert_eq!(merged["networks"]["frontend"]["driver"], Value::from("bridge"));
+        assert_eq!(merged["networks"]["frontend"]["labels"], serde_yaml_ng::from_str::<Value>("[a, b]").unwrap());
+    }
+
+    #[test]
+    fn test_merge_strategy_replace() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::Replace, STRATEGY_BASE, STRATEGY_OVERRIDE);
+        let networks = merged["networks"].as_mapping().unwrap();
+        assert_eq!(networks.len(), 2);
+        assert!(networks.get("backend").is_none());
+        assert!(merged["networks"]["frontend"].get("driver").is_none());
+        // Other sections keep the deep merge
+        assert_eq!(merged["services"]["web"]["image"], Value::from("nginx"));
+    }
+
+    #[test]
+    fn test_merge_strategy_append() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::Append, STRATEGY_BASE, STRATEGY_OVERRIDE);
+        // Same-named entries are replaced as a whole, new entries are added
+        assert!(merged["networks"]["frontend"].get("driver").is_none());
+        assert_eq!(merged["networks"]["frontend"]["labels"], serde_yaml_ng::from_str::<Value>("[b]").unwrap());
+        assert_eq!(merged["networks"]["backend"]["driver"], Value::from("bridge"));
+        assert_eq!(merged["networks"]["monitoring"]["driver"], Value::from("overlay"));
+
+        let merged = merge_with_section_strategy("x-ports", crate::config::MergeStrategy::Append, "services: {}\nx-ports: [80]\n", "services: {}\nx-ports: [80, 443]\n");
+        assert_eq!(merged["x-ports"], serde_yaml_ng::from_str::<Value>("[80, 80, 443]").unwrap());
+    }
+
+    #[test]
+    fn test_merge_strategy_unique_append() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::UniqueAppend, STRATEGY_BASE, STRATEGY_OVERRIDE);
+        // The first definition of a name wins, new names are added
+        assert_eq!(merged["networks"]["frontend"]["driver"], Value::from("bridge"));
+        assert_eq!(merged["networks"]["frontend"]["labels"], serde_yaml_ng::from_str::<Value>("[a]").unwrap());
+        assert_eq!(merged["networks"]["monitoring"]["driver"], Value::from("overlay"));
+
+        let merged = merge_with_section_strategy("x-ports", crate::config::MergeStrategy::UniqueAppend, "services: {}\nx-ports: [80]\n", "services: {}\nx-ports: [80, 443]\n");
+        assert_eq!(merged["x-ports"], serde_yaml_ng::from_str::<Value>("[80, 443]").unwrap());
+    }
+
+    const TAGGED_OVERRIDE: &str = r#"
+services: {}
+networks:
+  frontend:
+    driver: !override overlay
+    labels: !reset null
+  backend: !reset null
+"#;
+
+    fn assert_no_merge_tags(merged: &Value) {
+        let output = serde_yaml_ng::to_string(merged).unwrap();
+        assert!(!output.contains("!override") && !output.contains("!reset"), "{}", output);
+    }
+
+    #[test]
+    fn test_merge_strategy_replace_resolves_tags() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::Replace, STRATEGY_BASE, TAGGED_OVERRIDE);
+        assert_no_merge_tags(&merged);
+        let networks = merged["networks"].as_mapping().unwrap();
+        assert_eq!(networks.len(), 1);
+        assert_eq!(merged["networks"]["frontend"]["driver"], Value::from("overlay"));
+        assert!(merged["networks"]["frontend"].get("labels").is_none());
+    }
+
+    #[test]
+    fn test_merge_strategy_append_resolves_tags() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::Append, STRATEGY_BASE, TAGGED_OVERRIDE);
+        assert_no_merge_tags(&merged);
+        assert!(merged["networks"].get("backend").is_none());
+        assert_eq!(merged["networks"]["frontend"]["driver"], Value::from("overlay"));
+        assert!(merged["networks"]["frontend"].get("labels").is_none());
+
+        let merged = merge_with_section_strategy("x-ports", crate::config::MergeStrategy::Append, "services: {}\nx-ports: [80]\n", "services: {}\nx-ports: [!override 443]\n");
+        assert_eq!(merged["x-ports"], serde_yaml_ng::from_str::<Value>("[80, 443]").unwrap());
+    }
+
+    #[test]
+    fn test_merge_strategy_unique_append_resolves_tags() {
+        let merged = merge_with_section_strategy("networks", crate::config::MergeStrategy::UniqueAppend, STRATEGY_BASE, TAGGED_OVERRIDE);
+        assert_no_merge_tags(&merged);
+        assert!(merged["networks"].get("backend").is_none());
+        // The first definition still wins for names that are not reset
+        assert_eq!(merged["networks"]["frontend"]["driver"], Value::from("bridge"));
+
+        let merged = merge_with_section_strategy("x-ports", crate::config::MergeStrategy::UniqueAppend, "services: {}\nx-ports: [443]\n", "services: {}\nx-ports: [!override 443]\n");
+        assert_eq!(merged["x-ports"], serde_yaml_ng::from_str::<Value>("[443]").unwrap());
+    }
+
+    #[test]
+    fn test_merge_strategy_deep_resolves_tags() {
+        let merged = resolve_merge_tags(merge_with_section_strategy("networks", crate::config::MergeStrategy::Deep, STRATEGY_BASE, TAGGED_OVERRIDE));
+        assert_no_merge_tags(&merged);
+        assert!(merged["networks"].get("backend").is_none());
+        assert_eq!(merged["networks"]["frontend"]["driver"], Value::from("overlay"));
+        assert!(merged["networks"]["frontend"].get("labels").is_none());
+    }
+
+    #[test]
+    fn test_merge_strategy_config() {
+        let config: crate::config::Config = toml::from_str(r#"
+[build.merge_strategy]
+volumes = "append"
+networks = "unique-append"
+version = "replace"
+"#).unwrap();
+        assert_eq!(config.build.merge_strategy.get("volumes"), Some(&crate::config::MergeStrategy::Append));
+        assert_eq!(config.build.merge_strategy.get("networks"), Some(&crate::config::MergeStrategy::UniqueAppend));
+        assert_eq!(config.build.merge_strategy.get("version"), Some(&crate::config::MergeStrategy::Replace));
+        assert!(!config.build.merge_strategy.contains_key("services"));
+    }
+
+    /// Project whose base and extension services use env_file next to explicit environment
+    fn create_env_file_project(root: &std::path::Path) -> ComposeMerger {
+        let base = root.join("components/base");
+        let ext = root.join("components/extensions/cache");
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&ext).unwrap();
+
+        fs::write(base.join("docker-compose.yml"), r#"
+services:
+  web:
+    image: nginx
+    env_file: [app.env]
+    environment:
+      LOG_LEVEL: debug
+  worker:
+    image: worker
+    env_file:
+      - app.env
+      - path: missing.env
+        required: false
+    environment:
+      - LOG_LEVEL=warn
+"#).unwrap();
+        fs::write(base.join("app.env"), "# shared\nexport LOG_LEVEL=info\nDB_HOST=db\nGREETING=\"hello world\"\n").unwrap();
+
+        fs::write(ext.join("docker-compose.yml"), "services:\n  web:\n    env_file: [cache.env]\n").unwrap();
+        fs::write(ext.join("cache.env"), "CACHE_URL=redis://cache\nDB_HOST=cache-db\n").unwrap();
+
+        ComposeMerger::new(
+            base.to_string_lossy().to_string(),
+            root.join("components/environments").to_string_lossy().to_string(),
+            vec![root.join("components/extensions").to_string_lossy().to_string()],
+        )
+    }
+
+    #[test]
+    fn test_inline_env_files() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let merger = create_env_file_project(temp_dir.path()).with_inline_env_files(true);
+
+        let (merged, _) = merge_compose_files(&merger, None, &["cache".to_string()]).unwrap();
+
+        let web = &merged["services"]["web"];
+        assert!(web.get("env_file").is_none());
+        // Explicit environment entries win over env_file variables
+        assert_eq!(web["environment"]["LOG_LEVEL"], Value::from("debug"));
+        // Each env_file is read relative to its own component, later files override earlier ones
+        assert_eq!(web["environment"]["DB_HOST"], Value::from("cache-db"));
+        assert_eq!(web["environment"]["CACHE_URL"], Value::from("redis://cache"));
+        assert_eq!(web["environment"]["GREETING"], Value::from("hello world"));
+
+        let worker = &merged["services"]["worker"];
+        assert!(worker.get("env_file").is_none());
+        let environment: Vec<&str> = worker["environment"].as_sequence().unwrap()
+            .iter().filter_map(Value::as_str).collect();
+        assert_eq!(environment, vec!["LOG_LEVEL=warn", "DB_HOST=db", "GREETING=hello world"]);
+    }
+
+    #[test]
+    fn test_inline_env_files_disabled_keeps_env_file() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let merger = create_env_file_project(temp_dir.path());
+
+        let (merged, _) = merge_compose_files(&merger, None, &[]).unwrap();
+        assert_eq!(merged["services"]["web"]["env_file"], serde_yaml_ng::from_str::<Value>("[app.env]").unwrap());
+        assert!(merged["services"]["web"]["environment"].get("DB_HOST").is_none());
+    }
+
+    #[test]
+    fn test_inline_env_files_missing_required_file() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let merger = create_env_file_project(temp_dir.path()).with_inline_env_files(true);
+        fs::remove_file(temp_dir.path().join("components/base/app.env")).unwrap();
+
+        let result = merge_compose_files(&merger, None, &[]);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("app.env"));
+    }
+
+    #[test]
+    fn test_parse_env_file() {
+        let parsed = crate::env_merger::parse_env_file("# comment\n\nA=1\nexport B = two\nC='x=y'\nINVALID\nD=\n");
+        assert_eq!(parsed, vec![
+            ("A".to_string(), "1".to_string()),
+            ("B".to_string(), "two".to_string()),
+            ("C".to_string(), "x=y".to_string()),
+            ("D".to_string(), String::new()),
+        ]);
+    }
+
+    #[test]
+    fn test_relative_build_contexts_string_and_object_forms() {
+        let document: Value = serde_yaml_ng::from_str(r#"
+services:
+  api:
+    build: ./api
+  web:
+    build:
+      context: ../web
+      dockerfile: Dockerfile.prod
+  remote:
+    build: https://github.com/example/app.git
+  absolute:
+    build:
+      context: /srv/app
+  image-only:
+    image: nginx
+"#).unwrap();
+
+        assert_eq!(relative_build_contexts(&document), vec![
+            ("api".to_string(), "./api".to_string()),
+            ("web".to_string(), "../web".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_rewrite_build_context_against_component() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let base = root.join("components/base");
+        let ext = root.join("components/extensions/worker");
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(base.join("docker-compose.yml"), "services:\n  api:\n    build: ./api\n  vendored:\n    build: /opt/vendored\n  remote:\n    build:\n      context: https://github.com/acme/remote.git#main\n").unwrap();
+        fs::write(ext.join("docker-compose.yml"), "services:\n  worker:\n    build:\n      context: ./src\n      dockerfile: Dockerfile\n").unwrap();
+
+        let merger = ComposeMerger::new(
+            base.to_string_lossy().to_string(),
+            root.join("components/environments").to_string_lossy().to_string(),
+            vec![root.join("components/extensions").to_string_lossy().to_string()],
+        ).with_rewrite_build_context(true);
+
+        let (merged, _) = merge_compose_files(&merger, None, &["worker".to_string()]).expect("Merge should succeed");
+        assert!(relative_build_contexts(&merged).is_empty(), "Contexts should be resolved against their component");
+        assert_eq!(merged["services"]["api"]["build"].as_str(), Some(&*base.join("api").to_string_lossy()));
+
+        let merger = merger.with_output_dir(root.join("build/dev"));
+        let (merged, _) = merge_compose_files(&merger, None, &["worker".to_string()]).expect("Merge should succeed");
+        assert_eq!(merged["services"]["api"]["build"].as_str(), Some("../../components/base/api"));
+        assert_eq!(merged["services"]["worker"]["build"]["context"].as_str(), Some("../../components/extensions/worker/src"));
+        assert_eq!(merged["services"]["worker"]["build"]["dockerfile"].as_str(), Some("Dockerfile"));
+        // Only relative paths on disk are rewritten
+        assert_eq!(merged["services"]["vendored"]["build"].as_str(), Some("/opt/vendored"));
+        assert_eq!(merged["services"]["remote"]["build"]["context"].as_str(), Some("https://github.com/acme/remote.git#main"));
+    }
+
+    #[test]
+    fn test_rebase_relative_paths_for_layers() {
+        let component = std::path::Path::new("/project/components/extensions/worker");
+        let output = std::path::Path::new("/project/build/dev/worker");
+        let layer = "services:\n  worker:\n    build: ./src\n    env_file:\n      - worker.env\n      - path: ../../shared.env\n    volumes:\n      - ./config:/etc/worker:ro\n      - ../shared:/shared\n      - data:/data\n      - /var/run/docker.sock:/var/run/docker.sock\n      - type: bind\n        source: ../certs\n        target: /certs\n";
+
+        // Files below the component are copied next to the output, so only the paths leaving it change
+        let mut document: Value = serde_yaml_ng::from_str(layer).unwrap();
+        assert_eq!(rebase_relative_paths(&mut document, component, output, true, false), 3);
+        let worker = &document["services"]["worker"];
+        assert_eq!(worker["build"].as_str(), Some("./src"));
+        assert_eq!(worker["env_file"][0].as_str(), Some("worker.env"));
+        assert_eq!(worker["env_file"][1]["path"].as_str(), Some("../../../components/shared.env"));
+        assert_eq!(worker["volumes"][0].as_str(), Some("./config:/etc/worker:ro"));
+        assert_eq!(worker["volumes"][1].as_str(), Some("../../../components/extensions/shared:/shared"));
+        assert_eq!(worker["volumes"][2].as_str(), Some("data:/data"));
+        assert_eq!(worker["volumes"][3].as_str(), Some("/var/run/docker.sock:/var/run/docker.sock"));
+        assert_eq!(worker["volumes"][4]["source"].as_str(), Some("../../../components/extensions/certs"));
+
+        // Without copied files every relative path points at the component
+        let mut document: Value = serde_yaml_ng::from_str(layer).unwrap();
+        assert_eq!(rebase_relative_paths(&mut document, component, output, false, true), 6);
+        let worker = &document["services"]["worker"];
+        assert_eq!(worker["build"].as_str(), Some("../../../components/extensions/worker/src"));
+        assert_eq!(worker["env_file"][0].as_str(), Some("../../../components/extensions/worker/worker.env"));
+        assert_eq!(worker["volumes"][0].as_str(), Some("../../../components/extensions/worker/config:/etc/worker:ro"));
+    }
+
+    #[test]
+    fn test_prune_empty_collections_after_merge() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let base = root.join("components/base");
+        let ext = root.join("components/extensions/metrics");
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(base.join("docker-compose.yml"), "services:\n  api:\n    image: api\n    environment: {}\n    ports: []\n  web:\n    image: web\n    environment: {}\n    deploy:\n      resources: {}\n    networks:\n      backend: {}\n      frontend:\n        aliases: []\n    depends_on:\n      db: {}\n  db:\n    image: db\n    networks: {}\nvolumes:\n  data: {}\nnetworks: {}\n").unwrap();
+        fs::write(ext.join("docker-compose.yml"), "services:\n  api:\n    environment:\n      METRICS: \"on\"\n").unwrap();
+
+        let merger = ComposeMerger::new(
+            base.to_string_lossy().to_string(),
+            root.join("components/environments").to_string_lossy().to_string(),
+            vec![root.join("components/extensions").to_string_lossy().to_string()],
+        ).with_prune_empty_collections(true);
+
+        let (merged, _) = merge_compose_files(&merger, None, &["metrics".to_string()]).expect("Merge should succeed");
+        let api = &merged["services"]["api"];
+        assert_eq!(api["environment"]["METRICS"].as_str(), Some("on"), "Values from another layer must be kept");
+        assert!(api.get("ports").is_none(), "Empty sequence should be removed");
+
+        let web = &merged["services"]["web"];
+        assert!(web.get("environment").is_none(), "Empty mapping should be removed");
+        assert!(web.get("deploy").is_none(), "Mappings emptied by pruning should be removed");
+        assert_eq!(web["image"].as_str(), Some("web"));
+
+        // Named entries attach networks and declare dependencies even when empty
+        assert!(web["networks"]["backend"].as_mapping().is_some_and(|backend| backend.is_empty()));
+        assert!(web["networks"]["frontend"].as_mapping().is_some_and(|frontend| frontend.is_empty()));
+        assert!(web["depends_on"]["db"].as_mapping().is_some_and(|db| db.is_empty()));
+        assert!(merged["services"]["db"].get("networks").is_none(), "A mapping without entries names nothing");
+
+        // Top-level sections and named resources are declarations, not noise
+        assert!(merged["volumes"]["data"].as_mapping().is_some_and(|data| data.is_empty()));
+        assert!(merged.get("networks").is_some());
+    }
+
+    #[test]
+    fn test_prune_empty_collections_is_opt_in() {
+        let mut document: Value = serde_yaml_ng::from_str("services:\n  api:\n    environment: {}\n").unwrap();
+        let merger = ComposeMerger::new(String::new(), String::new(), Vec::new());
+        assert!(!merger.prune_empty_collections);
+        assert!(!crate::config::BuildConfig::default().prune_empty_collections);
+        assert!(toml::from_str::<crate::config::BuildConfig>("prune_empty_collections = true").unwrap().prune_empty_collections);
+
+        prune_empty_collections(&mut document);
+        assert!(document["services"]["api"].get("environment").is_none());
+    }
+
+    #[test]
+    fn test_labels_merge_by_key_across_list_and_map_forms() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let base = root.join("components/base");
+        let ext = root.join("components/extensions/traefik");
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(base.join("docker-compose.yml"), "services:\n  web:\n    image: nginx\n    labels:\n      - \"traefik.enable=false\"\n      - \"team=web\"\n      - \"debug\"\n  db:\n    image: postgres\n    labels:\n      backup: daily\nvolumes:\n  data:\n    labels:\n      tier: ssd\n").unwrap();
+        fs::write(ext.join("docker-compose.yml"), "services:\n  web:\n    labels:\n      traefik.enable: \"true\"\n      traefik.http.routers.web.rule: \"Host(`example.com`)\"\n  db:\n    labels:\n      - \"backup=hourly\"\nvolumes:\n  data:\n    labels:\n      - \"tier=nvme\"\n").unwrap();
+
+        let merger = ComposeMerger::new(
+            base.to_string_lossy().to_string(),
+            root.join("components/environments").to_string_lossy().to_string(),
+            vec![root.join("components/extensions").to_string_lossy().to_string()],
+        );
+        let (merged, _) = merge_compose_files(&merger, None, &["traefik".to_string()]).expect("Merge should succeed");
+        let expected: Value = serde_yaml_ng::from_str("traefik.enable: \"true\"\nteam: web\ndebug: \"\"\ntraefik.http.routers.web.rule: \"Host(`example.com`)\"\n").unwrap();
+        assert_eq!(merged["services"]["web"]["labels"], expected, "A map label overrides a list entry with the same key");
+        assert_eq!(merged["services"]["db"]["labels"]["backup"].as_str(), Some("hourly"), "A list entry overrides a map label");
+        assert_eq!(merged["volumes"]["data"]["labels"]["tier"].as_str(), Some("nvme"), "Top-level resources are merged by key too");
+
+        let merger = merger.with_label_style(crate::config::LabelStyle::List);
+        let (merged, _) = merge_compose_files(&merger, None, &["traefik".to_string()]).expect("Merge should succeed");
+        let expected: Value = serde_yaml_ng::from_str("[\"traefik.enable=true\", \"team=web\", \"debug=\", \"traefik.http.routers.web.rule=Host(`example.com`)\"]").unwrap();
+        assert_eq!(merged["services"]["web"]["labels"], expected);
+        assert_eq!(merged["volumes"]["data"]["labels"][0].as_str(), Some("tier=nvme"));
+    }
+
+    #[test]
+    fn test_normalize_labels_keeps_merge_tags_and_non_string_lists() {
+        let mut document: Value = serde_yaml_ng::from_str("services:\n  web:\n    labels: !override\n      - \"a=1=2\"\n  api:\n    labels:\n      - 1\n").unwrap();
+
+        normalize_labels(&mut document);
+        let Value::Tagged(tagged) = &document["services"]["web"]["labels"] else { panic!("The !override tag should be kept") };
+        assert_eq!(tagged.value["a"].as_str(), Some("1=2"), "Only the first = separates key and value");
+        assert!(document["services"]["api"]["labels"].is_sequence(), "Lists compose would reject are left alone");
+    }
+
+    #[test]
+    fn test_find_service_conflicts() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let write = |name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let postgres = write("postgres.yml", "services:\n  db:\n    image: postgres:16\n    ports:\n      - \"5432:5432\"\n    environment:\n      POSTGRES_DB: app\n    volumes:\n      - pg:/var/lib/postgresql/data\n");
+        let timescale = write("timescale.yml", "services:\n  db:\n    image: timescale/timescaledb:latest-pg16\n    ports:\n      - \"5433:5432\"\n    environment:\n      POSTGRES_DB: app\n    volumes:\n      - ts:/var/lib/postgresql/data\n");
+        let tuning = write("tuning.yml", "services:\n  db:\n    command: postgres -c shared_buffers=1GB\n    environment:\n      POSTGRES_DB: app\n      PGTZ: UTC\n");
+        let replacing = write("replacing.yml", "services:\n  db:\n    image: !override postgres:17\n    ports: !reset []\n");
+
+        let conflicts = find_service_conflicts(&[postgres.clone(), timescale.clone()]);
+        assert_eq!(conflicts, [ServiceConflict {
+            service: "db".to_string(),
+            first: postgres.clone(),
+            second: timescale,
+            paths: vec!["image".to_string()],
+        }]);
+        // Adding keys, repeating equal values and explicit merge tags are intended layering
+        assert!(find_service_conflicts(&[postgres.clone(), tuning]).is_empty());
+        assert!(find_service_conflicts(&[postgres.clone(), replacing]).is_empty());
+        // The same binding in the long syntax is not a conflict
+        let long_form = write("long.yml", "services:\n  db:\n    ports:\n      - target: 5432\n        published: \"5432\"\n");
+        assert!(find_service_conflicts(&[postgres.clone(), long_form]).is_empty());
+        // Adding a port next to a repeated one is layering, publishing a host port for another container port is not
+        let exporter = write("exporter.yml", "services:\n  db:\n    ports:\n      - \"5432:5432\"\n      - \"9187:9187\"\n");
+        assert!(find_service_conflicts(&[postgres.clone(), exporter]).is_empty());
+        let pooler = write("pooler.yml", "services:\n  db:\n    ports:\n      - \"127.0.0.1:5432:6432\"\n");
+        assert_eq!(find_service_conflicts(&[postgres, pooler])[0].paths, ["ports"]);
+    }
+
+    #[test]
+    fn test_ports_deduplicated_across_short_and_long_syntax() {
+        let temp_dir = tempdir().expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let base = root.join("components/base");
+        let ext = root.join("components/extensions/proxy");
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(base.join("docker-compose.yml"), "services:\n  web:\n    image: nginx\n    ports:\n      - \"8080:80\"\n      - \"127.0.0.1:5353:53/udp\"\n      - \"${ADMIN_PORT:-9000}:9000\"\n").unwrap();
+        fs::write(ext.join("docker-compose.yml"), "services:\n  web:\n    ports:\n      - target: 80\n        published: 8080\n        protocol: tcp\n      - target: 443\n        published: \"8443\"\n        mode: host\n      - \"${ADMIN_PORT:-9000}:9000\"\n      - \"127.0.0.1:5353:53\"\n").unwrap();
+
+        let merger = ComposeMerger::new(
+            base.to_string_lossy().to_string(),
+            root.join("components/environments").to_string_lossy().to_string(),
+            vec![root.join("components/extensions").to_string_lossy().to_string()],
+        );
+        let merge = |merger: &ComposeMerger| merge_compose_files(merger, None, &["proxy".to_string()]).expect("Merge should succeed").0["services"]["web"]["ports"].clone();
+
+        // The later declaration of a binding wins and keeps the position of the first; udp and tcp are different bindings
+        let expected: Value = serde_yaml_ng::from_str("[{target: 80, published: 8080, protocol: tcp}, \"127.0.0.1:5353:53/udp\", \"${ADMIN_PORT:-9000}:9000\", {target: 443, published: \"8443\", mode: host}, \"127.0.0.1:5353:53\"]").unwrap();
+        assert_eq!(merge(&merger), expected);
+
+        let expected: Value = serde_yaml_ng::from_str("[\"8080:80\", \"127.0.0.1:5353:53/udp\", \"${ADMIN_PORT:-9000}:9000\", {target: 443, published: \"8443\", mode: host}, \"127.0.0.1:5353:53\"]").unwrap();
+        let merger = merger.with_port_style(crate::config::PortStyle::Short);
+        assert_eq!(merge(&merger), expected, "Options only the long syntax has keep it");
+
+        let merger = merger.with_port_style(crate::config::PortStyle::Long);
+        let expected: Value = serde_yaml_ng::from_str("[{target: 80, published: \"8080\"}, {target: 53, published: \"5353\", host_ip: 127.0.0.1, protocol: udp}, \"${ADMIN_PORT:-9000}:9000\", {target: 443, published: \"8443\", mode: host}, {target: 53, published: \"5353\", host_ip: 127.0.0.1}]").unwrap();
+        assert_eq!(merge(&merger), expected);
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs;
+use std::path::Path;
+use clap::Parser;
+use toml::{Table, Value};
+use crate::diagnostics::{self, WarningCategory};
+use crate::error::{Result, ConfigError, FileSystemError};
+
+/// Outcome of converting a configuration to the new environments API
+#[derive(Debug)]
+pub struct UpgradeResult {
+    /// Upgraded TOML content
+    pub content: String,
+    /// Human-readable list of applied changes
+    pub changes: Vec<String>,
+    /// Whether the original file contained comments that will be lost
+    pub has_comments: bool,
+}
+
+/// Runs the upgrade-config command logic
+pub fn run_upgrade_config(args: &UpgradeConfigArgs) -> Result<()> {
+    const CONFIG_FILE: &str = "stackbuilder.toml";
+    let config_path = Path::new(CONFIG_FILE);
+
+    let content = fs::read_to_string(config_path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ConfigError::config_not_found(CONFIG_FILE),
+            _ => ConfigError::ConfigFileReadError {
+                file: CONFIG_FILE.to_string(),
+                source: e,
+            }
+        })?;
+
+    let result = upgrade_config_content(CONFIG_FILE, &content)?;
+
+    if result.changes.is_empty() {
+        println!("Configuration already uses the new environments API, nothing to upgrade");
+        return Ok(());
+    }
+
+    println!("Planned changes:");
+    for change in &result.changes {
+        println!("  - {}", change);
+    }
+
+    if result.has_comments {
+        diagnostics::warn(WarningCategory::Config, format!("Comments in {} will be lost when the file is rewritten", CONFIG_FILE));
+    }
+
+    if args.dry_run {
+        println!("\nUpgraded configuration (dry run, nothing written):\n");
+        println!("{}", result.content);
+        return Ok(());
+    }
+
+    fs::write(config_path, &result.content)
+        .map_err(|e| FileSystemError::FileWriteFailed {
+            path: config_path.to_path_buf(),
+            source: e,
+        })?;
+    println!("✓ Upgraded configuration file: {}", CONFIG_FILE);
+
+    Ok(())
+}
+
+/// Convert legacy `targets` and array-form `environments` into the `[build.environments]` object form
+pub fn upgrade_config_content(file: &str, content: &str) -> Result<UpgradeResult> {
+    let mut table: Table = toml::from_str(content)
+        .map_err(|e| ConfigError::toml_parse_error(file, content, e))?;
+
+    let has_comments = contains_comment(content);
+    let mut changes = Vec::new();
+
+    if let Some(Value::Table(build)) = table.get_mut("build") {
+        let targets = build.remove("targets");
+
+        match build.remove("environments") {
+            Some(Value::Table(environments)) => {
+                // Already on the new API - legacy targets are ignored by the build, drop them
+                if targets.is_some() {
+                    changes.push("Removed [build.targets] (ignored when [build.environments] is an object)".to_string());
+                }
+                build.insert("environments".to_string(), Value::Table(environments));
+            }
+            legacy => {
+                let mut environments = Table::new();
+
+                if let Some(Value::Array(available)) = legacy {
+                    changes.push("Moved build.environments array to build.environments.available".to_string());
+                    environments.insert("available".to_string(), Value::Array(available));
+                }
+
+                if let Some(Value::Table(targets)) = targets {
+                    for (env_name, env_target) in targets {
+                        changes.push(format!("Moved [build.targets.{}] to [build.environments.{}]", env_name, env_name));
+                        environments.insert(env_name, env_target);
+                    }
+                }
+
+                if !environments.is_empty() {
+                    build.insert("environments".to_string(), Value::Table(environments));
+                }
+            }
+        }
+    }
+
+    let content = if changes.is_empty() {
+        content.to_string()
+    } else {
+        toml::to_string(&table).map_err(ConfigError::toml_serialize_error)?
+    };
+
+    Ok(UpgradeResult {
+        content,
+        changes,
+        has_comments,
+    })
+}
+
+/// Whether TOML `content` has a comment anywhere, including after a value (`key = 1 # note`).
+/// A `#` inside a basic, literal or multi-line string is not a comment.
+fn contains_comment(content: &str) -> bool {
+    let mut rest = content;
+    while let Some(index) = rest.find(['#', '"', '\'']) {
+        let (delimiter, after) = (&rest[index..index + 1], &rest[index + 1..]);
+        if delimiter == "#" {
+            return true;
+        }
+        let closing = match after.starts_with(&delimiter.repeat(2)) {
+            true => delimiter.repeat(3),
+            false => delimiter.to_string(),
+        };
+        let after = &after[closing.len() - 1..];
+        // Only basic strings have escapes; a `\"` does not end them
+        let mut search = 0;
+        let end = loop {
+            match after[search..].find(closing.as_str()) {
+                Some(found) if delimiter == "\"" && after[..search + found].chars().rev().take_while(|&c| c == '\\').count() % 2 == 1 => {
+                    search += found + 1;
+                }
+                Some(found) => break Some(search + found + closing.len()),
+                None => break None,
+            }
+        };
+        match end {
+            Some(end) => rest = &after[end..],
+            // An unterminated string does not parse, so there is nothing left to check
+            None => return false,
+        }
+    }
+    false
+}
+
+#[derive(Parser)]
+pub struct UpgradeConfigArgs {
+    /// Preview the upgraded configuration without writing it
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, Config};
+
+    #[test]
+    fn test_upgrade_legacy_targets() {
+        let legacy = r#"
+[paths]
+build_dir = "./out"
+
+[build]
+environments = ["dev", "prod"]
+extensions = ["monitoring"]
+combos = { security = ["auth", "guard"] }
+
+[build.targets.dev]
+extensions = ["debug"]
+skip_base_generation = true
+
+[build.targets.prod]
+combos = ["security"]
+"#;
+        let result = upgrade_config_content("stackbuilder.toml", legacy).unwrap();
+        assert_eq!(result.changes.len(), 3);
+        assert!(!result.content.contains("targets"));
+
+        let legacy_config: Config = toml::from_str(legacy).unwrap();
+        let upgraded: Config = toml::from_str(&result.content).unwrap();
+
+        assert!(config::is_using_new_environments_api(&upgraded));
+        assert!(upgraded.build.targets.is_none());
+        assert_eq!(config::get_environments_list(&upgraded), config::get_environments_list(&legacy_config));
+        assert_eq!(upgraded.build.extensions, legacy_config.build.extensions);
+        assert_eq!(upgraded.build.combos, legacy_config.build.combos);
+        assert_eq!(upgraded.paths.build_dir, "./out");
+
+        for env in ["dev", "prod"] {
+            let old = config::get_environment_config(&legacy_config, env).unwrap();
+            let new = config::get_environment_config(&upgraded, env).unwrap();
+            assert_eq!(new.extensions, old.extensions);
+            assert_eq!(new.combos, old.combos);
+            assert_eq!(new.skip_base_generation, old.skip_base_generation);
+        }
+    }
+
+    #[test]
+    fn test_upgrade_environments_array_only() {
+        let legacy = "[build]\nenvironments = [\"dev\"]\n";
+        let result = upgrade_config_content("stackbuilder.toml", legacy).unwrap();
+
+        let upgraded: Config = toml::from_str(&result.content).unwrap();
+        assert!(config::is_using_new_environments_api(&upgraded));
+        assert_eq!(config::get_environments_list(&upgraded), vec!["dev".to_string()]);
+    }
+
+    #[test]
+    fn test_upgrade_new_api_is_noop() {
+        let current = "# comment\n[build.environments]\navailable = [\"dev\"]\n";
+        let result = upgrade_config_content("stackbuilder.toml", current).unwrap();
+
+        assert!(result.changes.is_empty());
+        assert_eq!(result.content, current);
+    }
+
+    #[test]
+    fn test_upgrade_detects_comments() {
+        let legacy = "# Project config\n[build]\nenvironments = [\"dev\"]\n";
+        let result = upgrade_config_content("stackbuilder.toml", legacy).unwrap();
+        assert!(result.has_comments);
+    }
+
+    #[test]
+    fn test_upgrade_detects_inline_comments() {
+        let legacy = "[build]\nenvironments = [\"dev\"] # the only one\n";
+        assert!(upgrade_config_content("stackbuilder.toml", legacy).unwrap().has_comments);
+
+        // A # inside any kind of string is not a comment
+        for value in [r##""#dev""##, r##""a \" # b""##, "'#dev'", "\"\"\"\n# dev\n\"\"\"", "'''\n# dev\n'''"] {
+            let legacy = format!("[build]\nenvironments = [\"dev\"]\ngenerated_header = {}\n", value);
+            let result = upgrade_config_content("stackbuilder.toml", &legacy).unwrap();
+            assert!(!result.has_comments, "{}", legacy);
+        }
+    }
+}
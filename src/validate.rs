@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use clap::Parser;
+use crate::build::{determine_build_combinations, resolve_all_extensions};
+use crate::config::{self, Config};
+use crate::error::{Result, ConfigError, ValidationError};
+
+/// Extension directory that no build combination uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanExtension {
+    /// Extension name
+    pub name: String,
+    /// Extension directory, relative to the components directory
+    pub path: PathBuf,
+}
+
+/// Fail on keys of `[build]` and `[paths]` that are not options
+pub fn check_known_keys(config: &Config) -> Result<()> {
+    let unknown = config::find_unknown_keys(config);
+    if unknown.is_empty() {
+        return Ok(());
+    }
+    Err(ConfigError::UnknownKeys {
+        keys: unknown.iter().map(ToString::to_string).collect(),
+    }.into())
+}
+
+/// Runs the validate command logic
+pub fn run_validate(args: &ValidateArgs) -> Result<()> {
+    let mut config = config::load_config()?;
+    if args.config_check {
+        check_known_keys(&config)?;
+    }
+    config::resolve_paths(&mut config)?;
+    config::validate_config(&config, config::validation_mode(&config, args.fail_fast))?;
+
+    if !args.orphans {
+        return Ok(());
+    }
+
+    let orphans = find_orphan_extensions(&config)?;
+    if orphans.is_empty() {
+        println!("✓ Every extension is used by at least one build combination");
+        return Ok(());
+    }
+
+    println!("Extensions not used by any build combination:");
+    for orphan in &orphans {
+        println!("  - {} ({})", orphan.name, orphan.path.display());
+    }
+
+    if args.strict {
+        return Err(ValidationError::OrphanExtensions {
+            names: orphans.into_iter().map(|o| o.name).collect(),
+        }.into());
+    }
+
+    Ok(())
+}
+
+/// Find discovered extensions that none of the resolved build combinations apply.
+/// Extensions referenced only by combos that no environment uses are reported as well.
+pub fn find_orphan_extensions(config: &Config) -> Result<Vec<OrphanExtension>> {
+    let mut used = HashSet::new();
+    for combo in determine_build_combinations(config)? {
+        used.extend(resolve_all_extensions(config, &combo.extensions, &combo.combo_names)?);
+    }
+
+    let mut discovered = config::discover_extensions(config)?;
+    discovered.sort();
+    discovered.dedup();
+
+    let components_dir = Path::new(&config.paths.components_dir);
+    let orphans = discovered.into_iter()
+        .filter(|name| !used.contains(name))
+        .map(|name| {
+            let path = config.paths.extensions_dirs.iter()
+                .map(|ext_dir| components_dir.join(ext_dir).join(&name))
+                .find(|path| path.is_dir())
+                .map(|path| path.strip_prefix(components_dir).map(Path::to_path_buf).unwrap_or(path))
+                .unwrap_or_else(|| PathBuf::from(&name));
+            OrphanExtension { name, path }
+        })
+        .collect();
+
+    Ok(orphans)
+}
+
+#[derive(Parser, Debug, Default)]
+pub struct ValidateArgs {
+    /// Report extension directories that no build combination uses
+    #[arg(long)]
+    pub orphans: bool,
+
+    /// Fail when orphan extensions are found
+    #[arg(long, requires = "orphans")]
+    pub strict: bool,
+
+    /// Stop at the first configuration problem instead of reporting all of them
+    #[arg(long)]
+    pub fail_fast: bool,
+
+    /// Fail when [build] or [paths] contains keys that are not options, e.g. misspelled ones
+    #[arg(long)]
+    pub config_check: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn create_project(root: &Path, build_toml: &str) -> Config {
+        for ext in ["monitoring", "auth", "legacy", "unused"] {
+            fs::create_dir_all(root.join("components/extensions").join(ext)).unwrap();
+        }
+        fs::create_dir_all(root.join("components/base")).unwrap();
+
+        let mut config: Config = toml::from_str(build_toml).unwrap();
+        config.paths.components_dir = root.join("components").to_string_lossy().to_string();
+        config
+    }
+
+    #[test]
+    fn test_find_orphan_extensions() {
+        let temp = tempfile::tempdir().unwrap();
+        let config = create_project(temp.path(), r#"
+[build]
+extensions = ["monitoring"]
+
+[build.combos]
+secure = ["auth"]
+
+[build.environments]
+available = ["dev", "prod"]
+
+[build.environments.dev]
+combos = ["secure"]
+
+[build.environments.prod]
+extensions = ["monitoring"]
+"#);
+
+        let orphans = find_orphan_extensions(&config).unwrap();
+        let names: Vec<&str> = orphans.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["legacy", "unused"]);
+        assert_eq!(orphans[0].path, Path::new("extensions/legacy"));
+    }
+
+    #[test]
+    fn test_combo_without_combination_is_orphaned() {
+        let temp = tempfile::tempdir().unwrap();
+        let config = create_project(temp.path(), r#"
+[build.combos]
+secure = ["auth"]
+old = ["legacy", "unused"]
+
+[build.environments]
+available = ["dev"]
+
+[build.environments.dev]
+extensions = ["monitoring"]
+combos = ["secure"]
+"#);
+
+        let names: Vec<String> = find_orphan_extensions(&config).unwrap()
+            .into_iter().map(|o| o.name).collect();
+        assert_eq!(names, vec!["legacy", "unused"]);
+    }
+}
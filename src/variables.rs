@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use regex::{Captures, Regex};
+use serde_yaml_ng::Value;
+
+use crate::config::CombinationVariable;
+use crate::env_merger::parse_env_file;
+
+/// Name of the file the combination variables are written to, read by docker compose from the project directory
+pub const VARIABLES_ENV_FILE: &str = ".env";
+
+/// First line of a generated variables file. The build cleaner does not preserve files starting with it,
+/// and only the lines before it of a hand-written `.env` the variables were merged into,
+/// since the next build writes them again from the configuration.
+pub const VARIABLES_ENV_MARKER: &str = "# Generated by stackbuilder from the combination variables, edit stackbuilder.toml instead";
+
+/// Replace `$NAME`, `${NAME}` and the default and error forms `${NAME:-x}`, `${NAME-x}`, `${NAME:?x}` and `${NAME?x}`
+/// of every name in `variables` with its value. Undefined names, `${NAME:+x}` and the `$$` escape are left to docker compose.
+pub fn substitute(text: &str, variables: &BTreeMap<String, CombinationVariable>) -> String {
+    if variables.is_empty() {
+        return text.to_string();
+    }
+    let re = Regex::new(r"(\$+)(?:\{([A-Za-z_][A-Za-z0-9_]*)(?::?[-?][^}]*)?\}|([A-Za-z_][A-Za-z0-9_]*))").unwrap();
+    re.replace_all(text, |captures: &Captures| {
+        let dollars = &captures[1];
+        let name = captures.get(2).or_else(|| captures.get(3)).map_or("", |name| name.as_str());
+        match variables.get(name) {
+            // An even number of dollars is a run of escapes followed by a literal name
+            Some(variable) if dollars.len() % 2 == 1 => format!("{}{}", &dollars[1..], variable.value),
+            _ => captures[0].to_string(),
+        }
+    })
+    .into_owned()
+}
+
+/// [`substitute`] in every string of a compose document, keys included
+pub fn substitute_value(value: &mut Value, variables: &BTreeMap<String, CombinationVariable>) {
+    match value {
+        Value::String(text) => *text = substitute(text, variables),
+        Value::Sequence(items) => items.iter_mut().for_each(|item| substitute_value(item, variables)),
+        Value::Mapping(map) => {
+            let entries = std::mem::take(map);
+            for (mut key, mut item) in entries {
+                substitute_value(&mut key, variables);
+                substitute_value(&mut item, variables);
+                map.insert(key, item);
+            }
+        }
+        Value::Tagged(tagged) => substitute_value(&mut tagged.value, variables),
+        _ => {}
+    }
+}
+
+/// Comment lines naming each variable with the level that set it, for the combination header
+pub fn variable_header_lines(variables: &BTreeMap<String, CombinationVariable>) -> Vec<String> {
+    let mut lines = vec!["# Variables:".to_string()];
+    lines.extend(variables.iter().map(|(name, variable)| format!("#   {} from {}", name, variable.source)));
+    lines
+}
+
+/// The lines of a `.env` the operator wrote, before the variables a build merged into it
+pub fn hand_written_part(content: &str) -> &str {
+    if content.starts_with(VARIABLES_ENV_MARKER) {
+        return "";
+    }
+    match content.find(&format!("\n{}", VARIABLES_ENV_MARKER)) {
+        Some(index) => &content[..index + 1],
+        None => content,
+    }
+}
+
+/// Merge the generated variables file `generated` into the operator's `hand_written` `.env`: their lines come first
+/// and unchanged, followed by the generated ones without the variables they already set, so the operator's values win
+pub fn merge_into_hand_written(hand_written: &str, generated: &str) -> String {
+    let names: Vec<String> = parse_env_file(hand_written).into_iter().map(|(name, _)| name).collect();
+    let mut content = hand_written.to_string();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for line in generated.lines() {
+        let name = line.split_once('=').filter(|_| !line.starts_with('#')).map(|(name, _)| name);
+        if !name.is_some_and(|name| names.iter().any(|set| set == name)) {
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+    content
+}
+
+/// Content of the generated `.env`: the marker, `header` and one `NAME=value` line per variable.
+/// Values compose would otherwise interpolate or cut short are single-quoted, or double-quoted with `\$` escapes when they hold a `'`.
+pub fn variables_env_content(variables: &BTreeMap<String, CombinationVariable>, header: &[String]) -> String {
+    let mut lines = vec![VARIABLES_ENV_MARKER.to_string()];
+    lines.extend(header.iter().cloned());
+    lines.extend(variables.iter().map(|(name, variable)| env_line(name, &variable.value)));
+    lines.join("\n") + "\n"
+}
+
+/// `NAME=value` with the value quoted as described for [`variables_env_content`]
+pub fn env_line(name: &str, value: &str) -> String {
+    format!("{}={}", name, quote_env_value(value))
+}
+
+fn quote_env_value(value: &str) -> String {
+    let plain = value.chars().all(|c| c.is_ascii_alphanumeric() || "_-.,:/@+=%".contains(c));
+    if plain {
+        value.to_string()
+    } else if !value.contains('\'') {
+        format!("'{}'", value)
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> BTreeMap<String, CombinationVariable> {
+        pairs.iter()
+            .map(|(name, value)| (name.to_string(), CombinationVariable { value: value.to_string(), source: "[build.variables]".to_string(), overridden: Vec::new() }))
+            .collect()
+    }
+
+    #[test]
+    fn test_substitute_forms() {
+        let variables = variables(&[("REPLICAS", "3"), ("TAG", "1.2")]);
+
+        assert_eq!(substitute("${REPLICAS}", &variables), "3");
+        assert_eq!(substitute("app:${TAG:-latest}", &variables), "app:1.2");
+        assert_eq!(substitute("${TAG-x} ${TAG:?set it} ${TAG?}", &variables), "1.2 1.2 1.2");
+        assert_eq!(substitute("$TAG/$OTHER", &variables), "1.2/$OTHER");
+        assert_eq!(substitute("${UNSET:-5} ${TAG:+yes}", &variables), "${UNSET:-5} ${TAG:+yes}");
+        assert_eq!(substitute("$${TAG} $$$TAG", &variables), "$${TAG} $$1.2");
+    }
+
+    #[test]
+    fn test_env_values_are_quoted_when_needed() {
+        let content = variables_env_content(&variables(&[("A", "plain-1.2"), ("B", "two words"), ("C", "it's $HOME")]), &[]);
+
+        assert_eq!(content, format!("{}\nA=plain-1.2\nB='two words'\nC=\"it's \\$HOME\"\n", VARIABLES_ENV_MARKER));
+    }
+
+    #[test]
+    fn test_merge_into_hand_written_keeps_operator_values() {
+        let generated = variables_env_content(&variables(&[("REPLICAS", "3"), ("TAG", "1.2")]), &["# Variables:".to_string()]);
+        let merged = merge_into_hand_written("# secrets\nexport TAG=dev\nPASSWORD=x", &generated);
+
+        assert_eq!(merged, format!("# secrets\nexport TAG=dev\nPASSWORD=x\n{}\n# Variables:\nREPLICAS=3\n", VARIABLES_ENV_MARKER));
+        // The next build preserves only the operator's lines and merges the variables in again
+        assert_eq!(hand_written_part(&merged), "# secrets\nexport TAG=dev\nPASSWORD=x\n");
+        assert_eq!(merge_into_hand_written(hand_written_part(&merged), &generated), merged);
+        assert_eq!(hand_written_part(&generated), "");
+    }
+}
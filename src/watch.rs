@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use clap::Parser;
+use crate::build::{self, BuildArgs, LogFormat, COMPOSE_SCRIPT_FILE};
+use crate::build_report::BuildReport;
+use crate::config::STDIN_CONFIG;
+use crate::error::{Result, BuildError, StackBuilderError};
+use crate::diagnostics;
+use crate::helper_scripts;
+
+#[derive(Parser, Debug)]
+pub struct WatchArgs {
+    /// Rebuild only when the inputs of this combination change, e.g. dev/monitoring
+    #[arg(long, value_name = "COMBINATION")]
+    pub only: Option<String>,
+
+    /// After each successful rebuild, run `docker compose up -d --remove-orphans` on the combination of --only
+    #[arg(long, requires = "only")]
+    pub serve: bool,
+
+    /// Milliseconds between two checks of the project for changes
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    pub interval: u64,
+
+    #[command(flatten)]
+    pub build: BuildArgs,
+}
+
+/// Runs the watch command logic: build, then rebuild whenever the inputs change, until interrupted
+pub fn run_watch(args: &WatchArgs) -> Result<()> {
+    if args.build.config.as_deref() == Some(Path::new(STDIN_CONFIG)) {
+        return Err(BuildError::WatchConfigFromStdin.into());
+    }
+    let project_dir = std::env::current_dir()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to determine current directory: {}", e),
+        })?;
+    let compose = match args.serve {
+        true => Some(helper_scripts::compose_command().ok_or_else(|| BuildError::BuildProcessFailed {
+            details: "--serve needs docker with the compose plugin or docker-compose".to_string(),
+        })?),
+        false => None,
+    };
+
+    let github = args.build.log_format() == LogFormat::Github;
+    let report_error = |error: &StackBuilderError| {
+        eprintln!("Error: {}", error);
+        if github {
+            diagnostics::print_github_annotations(error);
+        }
+    };
+
+    let mut last_fingerprint = None;
+    loop {
+        // Loading the configuration on every check must not repeat its progress output
+        let loaded = {
+            let _quiet = diagnostics::quiet();
+            build::input_fingerprint(&args.build, &project_dir, args.only.as_deref())
+        };
+        let (fingerprint, error) = match loaded {
+            Ok(fingerprint) => (Ok(fingerprint), None),
+            Err(e) => (Err(e.to_string()), Some(e)),
+        };
+        if last_fingerprint.as_ref() != Some(&fingerprint) {
+            match &fingerprint {
+                // A configuration that does not load is reported once, until it changes again
+                Err(_) => error.iter().for_each(report_error),
+                Ok(current) => {
+                    if last_fingerprint.is_some() {
+                        status!("\nChange detected, rebuilding...");
+                    }
+                    match build::execute_build(&args.build) {
+                        Ok(report) => {
+                            if let (Some(compose), Some(combination)) = (&compose, current.combination.as_deref()) {
+                                if let Err(e) = serve(compose, &report, combination) {
+                                    report_error(&e);
+                                }
+                            }
+                        }
+                        // The stack of the previous build keeps running
+                        Err(e) => report_error(&e),
+                    }
+                }
+            }
+            status!("Watching for changes (Ctrl+C to stop)...");
+            last_fingerprint = Some(fingerprint);
+        }
+        thread::sleep(Duration::from_millis(args.interval));
+    }
+}
+
+/// Bring the stack of `combination` up from the outputs of `report`, streaming the compose output.
+/// docker compose recreates the services whose definition changed and removes those no longer defined.
+fn serve(compose: &[&str], report: &BuildReport, combination: &str) -> Result<()> {
+    let Some(generated) = report.combinations.iter().find(|combo| combo.output_dir == combination) else {
+        return Err(BuildError::UnknownCombination {
+            name: combination.to_string(),
+            available: report.combinations.iter().map(|combo| combo.output_dir.clone()).collect(),
+        }.into());
+    };
+    let mut command = serve_command(compose, &generated.compose_file);
+    status!("Bringing up {}...", combination);
+    let status = command.status()
+        .map_err(|e| BuildError::BuildProcessFailed {
+            details: format!("Failed to run {}: {}", compose.join(" "), e),
+        })?;
+    if !status.success() {
+        return Err(BuildError::BuildProcessFailed {
+            details: format!("{} up for {} {}", compose.join(" "), combination, status),
+        }.into());
+    }
+    status!("✓ {} is up", combination);
+    Ok(())
+}
+
+/// `docker compose -f <generated> up -d --remove-orphans`, or the `compose.sh` of `output_mode = "layers"`,
+/// which passes every layer to compose itself
+pub fn serve_command(compose: &[&str], compose_file: &Path) -> Command {
+    // compose runs in the combination directory, so the file must not be relative to ours
+    let compose_file = std::path::absolute(compose_file).unwrap_or_else(|_| compose_file.to_path_buf());
+    let directory = compose_file.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    let mut command = match compose_file.file_name().is_some_and(|name| name == COMPOSE_SCRIPT_FILE) {
+        true => {
+            let mut command = Command::new("sh");
+            command.arg(&compose_file);
+            command
+        }
+        false => {
+            let mut command = Command::new(compose[0]);
+            command.args(&compose[1..]).arg("-f").arg(&compose_file);
+            command
+        }
+    };
+    command.args(["up", "-d", "--remove-orphans"]).current_dir(directory);
+    command
+}
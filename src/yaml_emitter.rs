@@ -0,0 +1,280 @@
+use std::fmt::Write;
+use serde_yaml_ng::{Mapping, Value};
+
+/// Emit a YAML document directly from a `serde_yaml_ng::Value`.
+///
+/// The layout follows the block style stackbuilder has always produced: a `---` document
+/// start, two-space indentation, sequences indented under their key, `~` for null and
+/// double-quoted strings only where a plain scalar would be ambiguous. No trailing newline.
+pub fn emit_yaml(value: &Value) -> String {
+    let mut emitter = YamlEmitter {
+        out: String::new(),
+        level: -1,
+    };
+    emitter.out.push_str("---\n");
+    emitter.emit_node(value);
+    emitter.out
+}
+
+/// Block-style YAML writer over a `serde_yaml_ng::Value` tree
+struct YamlEmitter {
+    out: String,
+    level: isize,
+}
+
+impl YamlEmitter {
+    fn write_indent(&mut self) {
+        for _ in 0..self.level.max(0) {
+            self.out.push_str("  ");
+        }
+    }
+
+    fn emit_node(&mut self, node: &Value) {
+        match node {
+            Value::Sequence(seq) => self.emit_sequence(seq),
+            Value::Mapping(map) => self.emit_mapping(map),
+            Value::String(s) => {
+                if need_quotes(s) {
+                    write_escaped(&mut self.out, s);
+                } else {
+                    self.out.push_str(s);
+                }
+            }
+            Value::Bool(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Value::Number(n) => {
+                let _ = write!(self.out, "{}", n);
+            }
+            Value::Null => self.out.push('~'),
+            // Tags carry no meaning in generated compose files, emit the tagged value
+            Value::Tagged(tagged) => self.emit_node(&tagged.value),
+        }
+    }
+
+    fn emit_sequence(&mut self, seq: &[Value]) {
+        if seq.is_empty() {
+            self.out.push_str("[]");
+            return;
+        }
+
+        self.level += 1;
+        for (index, item) in seq.iter().enumerate() {
+            if index > 0 {
+                self.out.push('\n');
+                self.write_indent();
+            }
+            self.out.push('-');
+            self.emit_value(true, item);
+        }
+        self.level -= 1;
+    }
+
+    fn emit_mapping(&mut self, map: &Mapping) {
+        if map.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.level += 1;
+        for (index, (key, value)) in map.iter().enumerate() {
+            if index > 0 {
+                self.out.push('\n');
+                self.write_indent();
+            }
+            if matches!(key, Value::Mapping(_) | Value::Sequence(_)) {
+                // Complex keys use the explicit "? key : value" form
+                self.out.push('?');
+                self.emit_value(true, key);
+                self.out.push('\n');
+                self.write_indent();
+                self.out.push(':');
+                self.emit_value(true, value);
+            } else {
+                self.emit_node(key);
+                self.out.push(':');
+                self.emit_value(false, value);
+            }
+        }
+        self.level -= 1;
+    }
+
+    /// Emit a value following ":" or "-". Inline collections after "-" stay on the same line.
+    fn emit_value(&mut self, inline: bool, value: &Value) {
+        let value = match value {
+            Value::Tagged(tagged) => &tagged.value,
+            other => other,
+        };
+
+        let is_empty_collection = match value {
+            Value::Sequence(seq) => seq.is_empty(),
+            Value::Mapping(map) => map.is_empty(),
+            _ => true,
+        };
+
+        if inline || is_empty_collection {
+            self.out.push(' ');
+        } else {
+            self.out.push('\n');
+            self.level += 1;
+            self.write_indent();
+            self.level -= 1;
+        }
+        self.emit_node(value);
+    }
+}
+
+/// Write a double-quoted, escaped string
+fn write_escaped(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\x08' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\x0c' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            // YAML line breaks outside ASCII, and the byte order mark
+            '\u{85}' => out.push_str("\\N"),
+            '\u{2028}' => out.push_str("\\L"),
+            '\u{2029}' => out.push_str("\\P"),
+            '\x00'..='\x1f' | '\x7f' | '\u{feff}' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Check whether a string must be quoted to be read back as the same string
+fn need_quotes(s: &str) -> bool {
+    s.is_empty()
+        || s.starts_with(' ')
+        || s.ends_with(' ')
+        || s.starts_with(['&', '*', '?', '|', '-', '<', '>', '=', '!', '%', '@', '.'])
+        || ["0x", "0o", "0b"].iter().any(|prefix| s.starts_with(prefix))
+        || s.contains([':', '{', '}', '[', ']', ',', '#', '`', '"', '\'', '\\'])
+        || s.contains(|c: char| c.is_ascii_control() && c != '\x7f' && c != '\x1b')
+        || s.contains(['\u{85}', '\u{2028}', '\u{2029}', '\u{feff}'])
+        || [
+            "true", "false", "True", "False", "TRUE", "FALSE",
+            "null", "Null", "NULL", "~",
+            // Quoted so YAML 1.1 parsers do not read them as booleans
+            "y", "Y", "n", "N", "yes", "Yes", "YES", "no", "No", "NO",
+            "on", "On", "ON", "off", "Off", "OFF",
+        ].contains(&s)
+        || s.parse::<i64>().is_ok()
+        || s.parse::<f64>().is_ok()
+        // YAML 1.1 reads digits grouped with underscores as a number
+        || (s.contains('_') && s.replace('_', "").parse::<f64>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The former formatting path: serialize, re-parse with yaml-rust2 and re-emit
+    fn emit_via_yaml_rust2(value: &Value) -> String {
+        let yaml_str = serde_yaml_ng::to_string(value).unwrap();
+        let docs = yaml_rust2::YamlLoader::load_from_str(&yaml_str).unwrap();
+        let mut out = String::new();
+        yaml_rust2::YamlEmitter::new(&mut out).dump(&docs[0]).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_emit_matches_previous_formatting() {
+        let value: Value = serde_yaml_ng::from_str(r#"
+version: "3.8"
+services:
+  web:
+    image: nginx:alpine
+    command: ["nginx", "-g", "daemon off;"]
+    ports:
+      - "80:80"
+      - 443
+    environment:
+      DEBUG: "true"
+      EMPTY: ""
+      RATIO: 1.5
+      MULTILINE: "line one\nline two"
+      QUOTE: 'say "hi"'
+    healthcheck:
+      test: [CMD, curl, -f, http://localhost]
+    labels: {}
+    depends_on: []
+  db:
+    image: postgres
+    deploy:
+      resources:
+        limits:
+          cpus: "0.5"
+volumes:
+  data:
+  logs: null
+networks:
+  default:
+    external: true
+"#).unwrap();
+
+        assert_eq!(emit_yaml(&value), emit_via_yaml_rust2(&value));
+    }
+
+    #[test]
+    fn test_emit_nested_sequences() {
+        let value: Value = serde_yaml_ng::from_str("a:\n  - - 1\n    - 2\n  - x: 1\n    y: [3]\n").unwrap();
+        assert_eq!(emit_yaml(&value), emit_via_yaml_rust2(&value));
+    }
+
+    #[test]
+    fn test_emit_round_trips_ambiguous_scalars() {
+        let scalars = [
+            "yes", "No", "on", "OFF", "y", "n", "true", "False", "null", "~", "",
+            "*alias", "&anchor", "!tag", "%directive", "@reserved", "`reserved", "|block", ">folded", "? key", "- item", "#comment",
+            "key: value", "http://localhost:8080", "a # b", "trailing:", "[1, 2]", "{a: 1}", "a,b", "'single'", "\"double\"",
+            "123", "-1", "+1", "1.5", "1e3", ".5", ".inf", "-.inf", ".nan", "NaN", "0x1f", "0o17", "0b101", "1_000", "08", "1:30",
+            " leading", "trailing ", "tab\there", "line\nbreak", "back\\slash", "bell\x07", "nel\u{85}", "sep\u{2028}", "bom\u{feff}",
+            "plain text", "nginx:alpine", "daemon off;", "café",
+        ];
+        for scalar in scalars {
+            let mut mapping = Mapping::new();
+            mapping.insert(Value::from(scalar), Value::from(scalar));
+            mapping.insert(Value::from("list"), Value::Sequence(vec![Value::from(scalar)]));
+            let value = Value::Mapping(mapping);
+
+            let emitted = emit_yaml(&value);
+            let parsed: Value = serde_yaml_ng::from_str(&emitted).unwrap_or_else(|e| panic!("{:?} emitted unparsable YAML {:?}: {}", scalar, emitted, e));
+            assert_eq!(parsed, value, "{:?} did not round-trip through {:?}", scalar, emitted);
+        }
+    }
+
+    /// Benchmark for large merged files: cargo test --release bench_emit_large_compose -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_emit_large_compose() {
+        let mut services = Mapping::new();
+        for i in 0..5_000 {
+            let service: Value = serde_yaml_ng::from_str(&format!(
+                "image: registry.example.com/service-{i}:1.0.{i}\nports:\n  - \"{port}:80\"\nenvironment:\n  SERVICE_ID: \"{i}\"\n  LOG_LEVEL: info\nvolumes:\n  - data-{i}:/var/lib/data\nlabels:\n  traefik.enable: \"true\"\n",
+                i = i,
+                port = 10_000 + i
+            )).unwrap();
+            services.insert(Value::String(format!("service-{}", i)), service);
+        }
+        let mut root = Mapping::new();
+        root.insert(Value::String("services".to_string()), Value::Mapping(services));
+        let value = Value::Mapping(root);
+
+        let started = std::time::Instant::now();
+        let direct = emit_yaml(&value);
+        let direct_time = started.elapsed();
+
+        let started = std::time::Instant::now();
+        let round_trip = emit_via_yaml_rust2(&value);
+        let round_trip_time = started.elapsed();
+
+        assert_eq!(direct, round_trip);
+        println!("{} bytes: direct {:?}, yaml-rust2 round trip {:?}", direct.len(), direct_time, round_trip_time);
+    }
+}
@@ -1,6 +1,10 @@
-use std::process::{Command, Stdio};
-use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use crate::error::{Result, YamlError, BuildError};
+use crate::diagnostics::{self, WarningCategory};
+use crate::merger::describe_component_file;
+use crate::paths::ResolvedPaths;
 
 /// Structure for managing docker-compose file merging process using yq
 #[derive(Debug)]
@@ -8,6 +12,11 @@ pub struct YqMerger {
     pub base_path: String,
     pub environments_path: String,
     pub extensions_paths: Vec<String>,
+    /// Fail on a missing base `docker-compose.yml`; when false, a missing base is not passed to yq
+    pub require_base: bool,
+    pub retry: YqRetry,
+    /// Components roots, to name the one each merged file comes from
+    pub component_roots: Vec<PathBuf>,
 }
 
 impl YqMerger {
@@ -17,8 +26,87 @@ impl YqMerger {
             base_path,
             environments_path,
             extensions_paths,
+            require_base: true,
+            retry: YqRetry::default(),
+            component_roots: Vec::new(),
         }
     }
+
+    /// Create a YqMerger reading the components of `paths`
+    pub fn from_paths(paths: &ResolvedPaths) -> Self {
+        let (base_path, environments_path, extensions_paths) = paths.merger_paths();
+        Self::new(base_path, environments_path, extensions_paths)
+            .with_component_roots(paths.components_roots.clone())
+    }
+
+    /// Leave a missing base `docker-compose.yml` out of the files handed to yq instead of failing
+    pub fn with_require_base(mut self, require_base: bool) -> Self {
+        self.require_base = require_base;
+        self
+    }
+
+    /// Retry failed yq spawns
+    pub fn with_retry(mut self, retry: YqRetry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Name the components root of every merged file in the merge log
+    pub fn with_component_roots(mut self, component_roots: Vec<PathBuf>) -> Self {
+        self.component_roots = component_roots;
+        self
+    }
+}
+
+/// Retry policy for yq subprocess spawns
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YqRetry {
+    /// Additional attempts after the first failed spawn
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub backoff: Duration,
+}
+
+impl YqRetry {
+    pub fn new(retries: u32, backoff_ms: u64) -> Self {
+        Self {
+            retries,
+            backoff: Duration::from_millis(backoff_ms),
+        }
+    }
+
+    /// Run `spawn`, retrying when it fails with a transient IO error (e.g. fork failure).
+    /// A missing or non-executable yq fails at once, and a process that ran and exited with an error
+    /// is returned as is; neither is retried.
+    pub fn run<T>(&self, mut spawn: impl FnMut() -> std::io::Result<T>) -> std::io::Result<T> {
+        let mut delay = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match spawn() {
+                Err(e) if attempt < self.retries && is_transient(&e) => {
+                    attempt += 1;
+                    diagnostics::warn(WarningCategory::Yq, format!("Failed to run yq ({}), retrying in {:?} ({}/{})", e, delay, attempt, self.retries));
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn output(&self, cmd: &mut Command) -> std::io::Result<Output> {
+        self.run(|| cmd.output())
+    }
+}
+
+/// Whether spawning may succeed when tried again: resource exhaustion, interruption or a busy executable
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        error.kind(),
+        ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::OutOfMemory | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy | ErrorKind::ExecutableFileBusy
+    )
 }
 
 /// Check if yq is available in the system and get its version
@@ -65,31 +153,32 @@ pub fn check_yq_availability() -> Result<String> {
         }.into());
     }
 
-    println!("✓ yq version: {}", version_output.trim());
+    status!("✓ yq version: {}", version_output.trim());
     Ok(version_output.trim().to_string())
 }
 
 /// Load and validate docker-compose.yml file using yq
-pub fn yq_load_compose_file(file_path: &str) -> Result<()> {
+pub fn yq_load_compose_file(file_path: &str, retry: YqRetry) -> Result<()> {
     // First check if file exists
     if !Path::new(file_path).exists() {
         return Err(YamlError::ParseError {
             file: file_path.to_string(),
             details: "File does not exist".to_string(),
+            location: None,
         }.into());
     }
 
     // Validate YAML syntax using yq
-    let output = Command::new("yq")
+    let output = retry.output(Command::new("yq")
         .arg("eval")
         .arg(".")
         .arg(file_path)
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+        .stderr(Stdio::piped()))
         .map_err(|e| YamlError::ParseError {
             file: file_path.to_string(),
             details: format!("Failed to execute yq: {}", e),
+            location: None,
         })?;
 
     if !output.status.success() {
@@ -97,6 +186,7 @@ pub fn yq_load_compose_file(file_path: &str) -> Result<()> {
         return Err(YamlError::ParseError {
             file: file_path.to_string(),
             details: format!("YAML syntax error: {}", stderr),
+            location: None,
         }.into());
     }
 
@@ -104,15 +194,14 @@ pub fn yq_load_compose_file(file_path: &str) -> Result<()> {
 }
 
 /// Validate docker-compose structure using yq
-pub fn yq_validate_compose_structure(file_path: &str) -> Result<()> {
+pub fn yq_validate_compose_structure(file_path: &str, retry: YqRetry) -> Result<()> {
     // Check if services section exists
-    let output = Command::new("yq")
+    let output = retry.output(Command::new("yq")
         .arg("eval")
         .arg(".services")
         .arg(file_path)
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+        .stderr(Stdio::piped()))
         .map_err(|e| YamlError::InvalidComposeFormat {
             file: file_path.to_string(),
             details: format!("Failed to validate structure: {}", e),
@@ -136,7 +225,7 @@ pub fn yq_validate_compose_structure(file_path: &str) -> Result<()> {
         }.into());
     }
 
-    println!("✓ Validated structure for: {}", file_path);
+    status!("✓ Validated structure for: {}", file_path);
     Ok(())
 }
 
@@ -159,11 +248,11 @@ pub fn yq_merge_compose_files(
     let mut processed_files = 0;
 
     for file_path in file_paths {
-        match yq_load_compose_file(&file_path) {
+        match yq_load_compose_file(&file_path, merger.retry) {
             Ok(_) => {
-                match yq_validate_compose_structure(&file_path) {
+                match yq_validate_compose_structure(&file_path, merger.retry) {
                     Ok(_) => {
-                        println!("✓ Loaded and validated: {}", file_path);
+                        status!("✓ Loaded and validated: {}", describe_component_file(&file_path, &merger.component_roots));
                         valid_files.push(file_path);
                         processed_files += 1;
                     }
@@ -173,7 +262,7 @@ pub fn yq_merge_compose_files(
                             return Err(e);
                         }
                         // For other files, skip with warning
-                        println!("Warning: Skipping invalid file '{}': {}", file_path, e);
+                        diagnostics::warn_at(WarningCategory::MissingFile, &file_path, format!("Skipping invalid file '{}': {}", file_path, e));
                         continue;
                     }
                 }
@@ -184,7 +273,7 @@ pub fn yq_merge_compose_files(
                     return Err(e);
                 }
                 // For other files, skip with warning
-                println!("Warning: Skipping missing or invalid file '{}': {}", file_path, e);
+                diagnostics::warn_at(WarningCategory::MissingFile, &file_path, format!("Skipping missing or invalid file '{}': {}", file_path, e));
                 continue;
             }
         }
@@ -198,7 +287,7 @@ pub fn yq_merge_compose_files(
 
     // If only one file, just return its content
     if valid_files.len() == 1 {
-        return yq_format_file(&valid_files[0]);
+        return yq_format_file(&valid_files[0], merger.retry);
     }
 
     // Merge multiple files using yq eval-all
@@ -213,7 +302,7 @@ pub fn yq_merge_compose_files(
         cmd.arg(file_path);
     }
 
-    let output = cmd.output()
+    let output = merger.retry.output(&mut cmd)
         .map_err(|e| YamlError::MergeError {
             details: format!("Failed to execute yq merge: {}", e),
         })?;
@@ -234,8 +323,8 @@ pub fn yq_merge_compose_files(
 }
 
 /// Format YAML file using yq
-pub fn yq_format_file(file_path: &str) -> Result<String> {
-    let output = Command::new("yq")
+pub fn yq_format_file(file_path: &str, retry: YqRetry) -> Result<String> {
+    let output = retry.output(Command::new("yq")
         .arg("eval")
         .arg(".")
         .arg(file_path)
@@ -244,8 +333,7 @@ pub fn yq_format_file(file_path: &str) -> Result<String> {
         .arg("--indent")
         .arg("2")
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .output()
+        .stderr(Stdio::piped()))
         .map_err(|e| YamlError::SerializationError {
             details: format!("Failed to format YAML: {}", e),
         })?;
@@ -271,9 +359,11 @@ pub fn resolve_merge_order(
 ) -> Result<Vec<String>> {
     let mut file_paths = Vec::new();
 
-    // Always start with base
+    // Start with base unless it is optional and missing
     let base_file = Path::new(&merger.base_path).join("docker-compose.yml");
-    file_paths.push(base_file.to_string_lossy().to_string());
+    if merger.require_base || base_file.exists() {
+        file_paths.push(base_file.to_string_lossy().to_string());
+    }
 
     // Add environment file if specified
     if let Some(env) = environment {
@@ -296,7 +386,7 @@ pub fn resolve_merge_order(
         }
         
         if !found {
-            println!("Warning: Extension '{}' not found in any extensions directory", ext);
+            diagnostics::warn(WarningCategory::MissingFile, format!("Extension '{}' not found in any extensions directory", ext));
         }
     }
 
@@ -344,4 +434,56 @@ mod tests {
         assert!(result[0].contains("base/docker-compose.yml"));
         assert!(result[1].contains("environments/dev/docker-compose.yml"));
     }
+
+    #[test]
+    fn test_retry_spawn_failure_then_success() {
+        let retry = YqRetry::new(2, 1);
+        let mut attempts = 0;
+        let result = retry.run(|| {
+            attempts += 1;
+            if attempts == 1 {
+                Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, "fork failed"))
+            } else {
+                Ok("merged")
+            }
+        });
+        assert_eq!(result.unwrap(), "merged");
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn test_retry_disabled_by_default() {
+        let mut attempts = 0;
+        let result: std::io::Result<()> = YqRetry::default().run(|| {
+            attempts += 1;
+            Err(std::io::Error::other("fork failed"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_retry_does_not_retry_missing_yq() {
+        let retry = YqRetry::new(3, 1);
+        let mut attempts = 0;
+        let result = retry.run(|| {
+            attempts += 1;
+            Command::new("stackbuilder-test-missing-yq").output()
+        });
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_retry_does_not_retry_failed_process() {
+        // A process that ran and reported an error is not a spawn failure
+        let retry = YqRetry::new(3, 1);
+        let mut attempts = 0;
+        let output = retry.run(|| {
+            attempts += 1;
+            Command::new("sh").arg("-c").arg("echo 'Error: bad yaml' >&2; exit 1").stderr(Stdio::piped()).output()
+        });
+        assert!(!output.unwrap().status.success());
+        assert_eq!(attempts, 1);
+    }
 }
\ No newline at end of file